@@ -5,8 +5,11 @@ extern crate criterion;
 use av_metrics::video::ciede::{calculate_frame_ciede, calculate_frame_ciede_nosimd};
 use av_metrics::video::decode::convert_chroma_data;
 use av_metrics::video::psnr::calculate_frame_psnr;
-use av_metrics::video::psnr_hvs::calculate_frame_psnr_hvs;
-use av_metrics::video::ssim::{calculate_frame_msssim, calculate_frame_ssim};
+use av_metrics::video::psnr_hvs::{calculate_frame_psnr_hvs, calculate_frame_psnr_hvs_nosimd};
+use av_metrics::video::ssim::{
+    calculate_frame_msssim, calculate_frame_msssim_with_config, calculate_frame_ssim, MsSsimConfig,
+    MsSsimWindow,
+};
 use av_metrics::video::Frame;
 use av_metrics::video::{ChromaSamplePosition, ChromaSampling, Pixel};
 use criterion::Criterion;
@@ -96,6 +99,22 @@ pub fn psnrhvs_benchmark(c: &mut Criterion) {
     });
 }
 
+pub fn psnrhvs_nosimd_benchmark(c: &mut Criterion) {
+    let frame1 = get_video_frame::<u8>(&format!(
+        "{}/../testfiles/yuv420p8_input.y4m",
+        env!("CARGO_MANIFEST_DIR")
+    ));
+    let frame2 = get_video_frame::<u8>(&format!(
+        "{}/../testfiles/yuv420p8_output.y4m",
+        env!("CARGO_MANIFEST_DIR")
+    ));
+    c.bench_function("PSNR-HVS yuv420p8 nosimd", |b| {
+        b.iter(|| {
+            calculate_frame_psnr_hvs_nosimd(&frame1, &frame2, 8, ChromaSampling::Cs420).unwrap();
+        })
+    });
+}
+
 pub fn ssim_benchmark(c: &mut Criterion) {
     let frame1 = get_video_frame::<u8>(&format!(
         "{}/../testfiles/yuv420p8_input.y4m",
@@ -128,6 +147,28 @@ pub fn msssim_benchmark(c: &mut Criterion) {
     });
 }
 
+pub fn msssim_box_benchmark(c: &mut Criterion) {
+    let frame1 = get_video_frame::<u8>(&format!(
+        "{}/../testfiles/yuv420p8_input.y4m",
+        env!("CARGO_MANIFEST_DIR")
+    ));
+    let frame2 = get_video_frame::<u8>(&format!(
+        "{}/../testfiles/yuv420p8_output.y4m",
+        env!("CARGO_MANIFEST_DIR")
+    ));
+    let config = MsSsimConfig {
+        window: MsSsimWindow::Box,
+        luma_only: false,
+        ..Default::default()
+    };
+    c.bench_function("MSSSIM yuv420p8 box window", |b| {
+        b.iter(|| {
+            calculate_frame_msssim_with_config(&frame1, &frame2, 8, ChromaSampling::Cs420, config)
+                .unwrap();
+        })
+    });
+}
+
 pub fn ciede2000_nosimd_benchmark(c: &mut Criterion) {
     let frame1 = get_video_frame::<u8>(&format!(
         "{}/../testfiles/yuv420p8_input.y4m",
@@ -260,8 +301,10 @@ criterion_group!(
     benches,
     psnr_benchmark,
     psnrhvs_benchmark,
+    psnrhvs_nosimd_benchmark,
     ssim_benchmark,
     msssim_benchmark,
+    msssim_box_benchmark,
     ciede2000_nosimd_benchmark,
     ciede2000_simd_benchmark,
     psnr_10bit_benchmark,
@@ -0,0 +1,163 @@
+//! Contains a trait and utilities for implementing audio decoders.
+//! Prebuilt decoders are included in the `av-metrics-decoders` crate.
+
+use std::fmt;
+
+/// A trait for allowing metrics to decode generic audio formats.
+///
+/// Decoders convert the samples of the input to `f32` in the range
+/// `-1.0..=1.0`, whatever their format in the input.
+pub trait AudioDecoder: Send {
+    /// Read the next block of samples from the input audio.
+    ///
+    /// The number of samples in a frame depends on the decoder and may vary
+    /// from one frame to the next. Returns `None` if the end of the audio is
+    /// reached.
+    fn read_audio_frame(&mut self) -> Option<AudioFrame>;
+    /// Get the Audio Details
+    fn get_audio_details(&self) -> AudioDetails;
+    /// Get the number of samples per second of each channel.
+    fn get_sample_rate(&self) -> u32 {
+        self.get_audio_details().sample_rate
+    }
+    /// Get the number of channels.
+    fn get_channel_count(&self) -> usize {
+        self.get_audio_details().channels
+    }
+    /// Get the format of the samples in the input.
+    fn get_sample_format(&self) -> SampleFormat {
+        self.get_audio_details().sample_format
+    }
+}
+
+/// The format of the samples of an audio input, before they are converted
+/// to `f32` by the decoder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SampleFormat {
+    /// Unsigned 8-bit integers.
+    U8,
+    /// Signed 16-bit integers.
+    I16,
+    /// Signed 24-bit integers.
+    I24,
+    /// Signed 32-bit integers.
+    I32,
+    /// 32-bit floating point numbers.
+    F32,
+    /// 64-bit floating point numbers.
+    F64,
+}
+
+impl SampleFormat {
+    /// The number of bits of each sample.
+    pub fn bits(self) -> usize {
+        match self {
+            SampleFormat::U8 => 8,
+            SampleFormat::I16 => 16,
+            SampleFormat::I24 => 24,
+            SampleFormat::I32 | SampleFormat::F32 => 32,
+            SampleFormat::F64 => 64,
+        }
+    }
+
+    /// Whether the samples are floating point numbers.
+    pub fn is_float(self) -> bool {
+        matches!(self, SampleFormat::F32 | SampleFormat::F64)
+    }
+}
+
+impl fmt::Display for SampleFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SampleFormat::U8 => "u8",
+            SampleFormat::I16 => "s16",
+            SampleFormat::I24 => "s24",
+            SampleFormat::I32 => "s32",
+            SampleFormat::F32 => "flt",
+            SampleFormat::F64 => "dbl",
+        })
+    }
+}
+
+/// A Structure containing the details of an audio input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AudioDetails {
+    /// The number of samples per second of each channel.
+    pub sample_rate: u32,
+    /// The number of channels.
+    pub channels: usize,
+    /// The format of the samples in the input.
+    pub sample_format: SampleFormat,
+}
+
+impl Default for AudioDetails {
+    fn default() -> Self {
+        AudioDetails {
+            sample_rate: 48000,
+            channels: 2,
+            sample_format: SampleFormat::I16,
+        }
+    }
+}
+
+/// A block of decoded samples.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AudioFrame {
+    /// The number of channels.
+    pub channels: usize,
+    /// The samples of all channels, interleaved, in the range `-1.0..=1.0`.
+    pub samples: Vec<f32>,
+}
+
+impl AudioFrame {
+    /// Creates a frame from interleaved samples.
+    ///
+    /// Trailing samples that do not fill a sample for every channel are
+    /// dropped.
+    pub fn from_interleaved(channels: usize, mut samples: Vec<f32>) -> Self {
+        let len = samples.len() - samples.len() % channels.max(1);
+        samples.truncate(len);
+        AudioFrame { channels, samples }
+    }
+
+    /// Creates a frame from one buffer of samples per channel.
+    ///
+    /// The frame is as long as the shortest buffer.
+    pub fn from_planar(planes: &[Vec<f32>]) -> Self {
+        let len = planes.iter().map(Vec::len).min().unwrap_or(0);
+        let samples = (0..len)
+            .flat_map(|i| planes.iter().map(move |plane| plane[i]))
+            .collect();
+        AudioFrame {
+            channels: planes.len(),
+            samples,
+        }
+    }
+
+    /// The number of samples of each channel.
+    pub fn len(&self) -> usize {
+        self.samples.len().checked_div(self.channels).unwrap_or(0)
+    }
+
+    /// Whether the frame holds no samples.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The samples of one channel.
+    pub fn channel(&self, channel: usize) -> impl Iterator<Item = f32> + '_ {
+        self.samples
+            .iter()
+            .copied()
+            .skip(channel)
+            .step_by(self.channels.max(1))
+            .take(if channel < self.channels {
+                self.len()
+            } else {
+                0
+            })
+    }
+}
@@ -0,0 +1,80 @@
+//! A radix-2 FFT for the power-of-two frame sizes used by the audio metrics.
+
+use std::f64::consts::PI;
+
+pub(crate) struct Fft {
+    len: usize,
+    /// `exp(-2πik/len)` for `k` in `0..len / 2`.
+    twiddles: Vec<(f64, f64)>,
+    bit_reversed: Vec<usize>,
+}
+
+impl Fft {
+    /// Plans a transform of `len` samples, which must be a power of two.
+    pub(crate) fn new(len: usize) -> Self {
+        assert!(len.is_power_of_two());
+        let bits = len.trailing_zeros();
+        let twiddles = (0..len / 2)
+            .map(|k| {
+                let angle = -2. * PI * k as f64 / len as f64;
+                (angle.cos(), angle.sin())
+            })
+            .collect();
+        let bit_reversed = (0..len)
+            .map(|i| {
+                if bits == 0 {
+                    0
+                } else {
+                    i.reverse_bits() >> (usize::BITS - bits)
+                }
+            })
+            .collect();
+        Fft {
+            len,
+            twiddles,
+            bit_reversed,
+        }
+    }
+
+    /// Transforms `re` and `im` in place.
+    pub(crate) fn process(&self, re: &mut [f64], im: &mut [f64]) {
+        debug_assert!(re.len() == self.len && im.len() == self.len);
+        for (i, &j) in self.bit_reversed.iter().enumerate() {
+            if i < j {
+                re.swap(i, j);
+                im.swap(i, j);
+            }
+        }
+        let mut size = 2;
+        while size <= self.len {
+            let half = size / 2;
+            let step = self.len / size;
+            for start in (0..self.len).step_by(size) {
+                for k in 0..half {
+                    let (wr, wi) = self.twiddles[k * step];
+                    let (a, b) = (start + k, start + k + half);
+                    let tr = re[b] * wr - im[b] * wi;
+                    let ti = re[b] * wi + im[b] * wr;
+                    re[b] = re[a] - tr;
+                    im[b] = im[a] - ti;
+                    re[a] += tr;
+                    im[a] += ti;
+                }
+            }
+            size *= 2;
+        }
+    }
+
+    /// The squared magnitudes of the first `len / 2 + 1` bins of the transform
+    /// of a real signal.
+    pub(crate) fn power_spectrum(&self, samples: &[f64]) -> Vec<f64> {
+        let mut re = samples.to_vec();
+        let mut im = vec![0.; self.len];
+        self.process(&mut re, &mut im);
+        re.iter()
+            .zip(im.iter())
+            .take(self.len / 2 + 1)
+            .map(|(re, im)| re * re + im * im)
+            .collect()
+    }
+}
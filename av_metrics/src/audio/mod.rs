@@ -0,0 +1,213 @@
+//! Contains metrics related to audio quality.
+
+pub mod decode;
+mod fft;
+pub mod peaq;
+pub mod spectral;
+pub mod window;
+
+use crate::{MetricsError, PROGRESS_DONE};
+use decode::*;
+use std::error::Error;
+
+/// Splits the samples of a decoder into overlapping windows of each channel.
+struct Windows<'a, D: AudioDecoder> {
+    decoder: &'a mut D,
+    buffers: Vec<Vec<f32>>,
+    len: usize,
+    hop: usize,
+    /// The number of samples at the start of the buffers that were part of
+    /// the previous window.
+    seen: usize,
+    ended: bool,
+}
+
+impl<'a, D: AudioDecoder> Windows<'a, D> {
+    fn new(decoder: &'a mut D, len: usize, hop: usize) -> Self {
+        let channels = decoder.get_channel_count();
+        Windows {
+            decoder,
+            buffers: vec![Vec::new(); channels],
+            len,
+            hop,
+            seen: 0,
+            ended: false,
+        }
+    }
+
+    /// Returns the next window of each channel. The last window is padded
+    /// with silence.
+    fn next_window(&mut self) -> Option<Vec<Vec<f32>>> {
+        while !self.ended && self.buffers.first()?.len() < self.len {
+            match self.decoder.read_audio_frame() {
+                Some(frame) => {
+                    for (channel, buffer) in self.buffers.iter_mut().enumerate() {
+                        buffer.extend(frame.channel(channel));
+                    }
+                }
+                None => self.ended = true,
+            }
+        }
+        let buffered = self.buffers.first()?.len();
+        if buffered >= self.len {
+            let windows = self
+                .buffers
+                .iter_mut()
+                .map(|buffer| {
+                    let window = buffer[..self.len].to_vec();
+                    buffer.drain(..self.hop);
+                    window
+                })
+                .collect();
+            self.seen = self.len - self.hop;
+            Some(windows)
+        } else if buffered > self.seen {
+            let windows = self
+                .buffers
+                .iter_mut()
+                .map(|buffer| {
+                    let mut window = std::mem::take(buffer);
+                    window.resize(self.len, 0.);
+                    window
+                })
+                .collect();
+            self.seen = 0;
+            Some(windows)
+        } else {
+            None
+        }
+    }
+}
+
+/// An audio metric computed on overlapping windows of each channel.
+///
+/// The windows are analyzed in parallel by [`process_window`], and the
+/// results are then passed in order to [`accumulate`], which keeps the state
+/// that depends on the previous windows.
+///
+/// [`process_window`]: AudioMetric::process_window
+/// [`accumulate`]: AudioMetric::accumulate
+trait AudioMetric: Send + Sync {
+    type WindowResult: Send;
+    type State: Send;
+    type AudioResult;
+
+    /// The number of samples of a window.
+    fn window_len(&self) -> usize;
+    /// The number of samples between the starts of two windows.
+    fn hop(&self) -> usize;
+
+    /// Checks that the metric supports the inputs, which have the same
+    /// format.
+    fn validate(&self, _details: &AudioDetails) -> Result<(), MetricsError> {
+        Ok(())
+    }
+
+    fn new_state(&self) -> Self::State;
+
+    fn process_window(&self, reference: &[f32], test: &[f32]) -> Self::WindowResult;
+
+    fn accumulate(&self, state: &mut Self::State, result: Self::WindowResult);
+
+    /// Combines the state of each channel into the result for the inputs.
+    fn aggregate(&self, states: Vec<Self::State>) -> Result<Self::AudioResult, Box<dyn Error>>;
+
+    fn process_audio<D: AudioDecoder, F: Fn(usize) + Send>(
+        &self,
+        decoder1: &mut D,
+        decoder2: &mut D,
+        progress_callback: F,
+    ) -> Result<Self::AudioResult, Box<dyn Error>> {
+        let details = decoder1.get_audio_details();
+        let details2 = decoder2.get_audio_details();
+        if details.sample_rate != details2.sample_rate {
+            return Err(Box::new(MetricsError::InputMismatch {
+                reason: "Sample rates do not match",
+            }));
+        }
+        if details.channels != details2.channels {
+            return Err(Box::new(MetricsError::InputMismatch {
+                reason: "Channel counts do not match",
+            }));
+        }
+        if details.channels == 0 {
+            return Err(Box::new(MetricsError::UnsupportedInput {
+                reason: "Audio has no channels",
+            }));
+        }
+        self.validate(&details)?;
+
+        let num_threads = (rayon::current_num_threads() - 1).max(1);
+        let mut states: Vec<_> = (0..details.channels).map(|_| self.new_state()).collect();
+        let mut windows_read = 0;
+
+        let (window_len, hop) = (self.window_len(), self.hop());
+        let (send, recv) = crossbeam::channel::bounded(num_threads * 2);
+        let scope_result = crossbeam::scope(|s| {
+            let send_result = s.spawn(move |_| {
+                let mut windows1 = Windows::new(decoder1, window_len, hop);
+                let mut windows2 = Windows::new(decoder2, window_len, hop);
+                let mut decoded = 0;
+                while let (Some(window1), Some(window2)) =
+                    (windows1.next_window(), windows2.next_window())
+                {
+                    decoded += 1;
+                    progress_callback(decoded);
+                    if send.send((window1, window2)).is_err() {
+                        return Err(format!("Error sending window {}", decoded - 1));
+                    }
+                }
+                progress_callback(PROGRESS_DONE);
+                Ok(())
+            });
+
+            use rayon::prelude::*;
+            while let Ok(first) = recv.recv() {
+                let mut batch = vec![first];
+                while batch.len() < num_threads {
+                    match recv.recv() {
+                        Ok(windows) => batch.push(windows),
+                        Err(_) => break,
+                    }
+                }
+                let results: Vec<Vec<_>> = batch
+                    .par_iter()
+                    .map(|(window1, window2)| {
+                        window1
+                            .iter()
+                            .zip(window2.iter())
+                            .map(|(reference, test)| self.process_window(reference, test))
+                            .collect()
+                    })
+                    .collect();
+                windows_read += results.len();
+                for channels in results {
+                    for (state, result) in states.iter_mut().zip(channels) {
+                        self.accumulate(state, result);
+                    }
+                }
+            }
+
+            send_result
+                .join()
+                .unwrap_or_else(|_| Err("Failed joining the sender thread".to_owned()))
+        });
+
+        match scope_result {
+            Ok(Ok(())) => {
+                if windows_read == 0 {
+                    return Err(MetricsError::UnsupportedInput {
+                        reason: "No readable samples found in one or more input files",
+                    }
+                    .into());
+                }
+                self.aggregate(states)
+            }
+            Ok(Err(error)) => Err(MetricsError::SendError { reason: error }.into()),
+            Err(e) => Err(MetricsError::ProcessError {
+                reason: format!("\n\nError {e:?} processing the two inputs"),
+            }
+            .into()),
+        }
+    }
+}
@@ -0,0 +1,855 @@
+//! Perceptual Evaluation of Audio Quality (PEAQ).
+//!
+//! This is the basic version of ITU-R BS.1387. Both signals go through an
+//! FFT-based model of the ear, and the differences between them are
+//! summarized by eleven model output variables (MOVs). A neural network maps
+//! the MOVs to an Objective Difference Grade, from 0 (imperceptible
+//! impairment) down to about -4 (very annoying).
+//!
+//! See "An Examination and Interpretation of ITU-R BS.1387: Perceptual
+//! Evaluation of Audio Quality" by P. Kabal (2002) for a description of the
+//! model.
+
+use crate::audio::decode::{AudioDecoder, AudioDetails};
+use crate::audio::fft::Fft;
+use crate::audio::window::WindowFunction;
+use crate::audio::AudioMetric;
+use crate::MetricsError;
+use std::error::Error;
+use std::f64::consts::PI;
+
+/// The only sample rate defined for the model.
+const SAMPLE_RATE: u32 = 48000;
+const FRAME_LEN: usize = 2048;
+const HOP: usize = FRAME_LEN / 2;
+/// The number of auditory bands between 80 Hz and 18 kHz.
+const BANDS: usize = 109;
+/// The width of an auditory band, in Bark.
+const BAND_WIDTH: f64 = 0.25;
+/// The sound pressure level, in dB, of a full scale sine.
+const LISTENING_LEVEL: f64 = 92.;
+/// The averages of the modulation and noise loudness variables ignore the
+/// first half second, where the filters of the model settle.
+const DELAY_FRAMES: usize = 24;
+/// Frames whose energy is below this in both signals are not used for the
+/// error harmonic structure. This is 8000 for 16-bit samples.
+const ENERGY_THRESHOLD: f64 = 8000. / (32768. * 32768.);
+/// The number of lags of the autocorrelation of the error spectrum.
+const EHS_LAGS: usize = 256;
+
+/// Calculates PEAQ for a reference and a test signal. Higher is better.
+///
+/// `decoder1` is the reference and `decoder2` the signal under test. Both
+/// must have the same number of channels and a sample rate of 48 kHz, and
+/// full scale samples are taken to be played at 92 dB SPL. The model output
+/// variables of each channel are averaged.
+pub fn calculate_audio_peaq<D: AudioDecoder, F: Fn(usize) + Send>(
+    decoder1: &mut D,
+    decoder2: &mut D,
+    progress_callback: F,
+) -> Result<PeaqResult, Box<dyn Error>> {
+    Peaq::new().process_audio(decoder1, decoder2, progress_callback)
+}
+
+/// The result of PEAQ.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PeaqResult {
+    /// The Objective Difference Grade, between -3.98 and 0.22. A grade of 0
+    /// means the impairment is imperceptible, -1 perceptible but not
+    /// annoying, -2 slightly annoying, -3 annoying and -4 very annoying.
+    pub odg: f64,
+    /// The Distortion Index, the output of the neural network before it is
+    /// mapped to the ODG scale.
+    pub distortion_index: f64,
+    /// The model output variables the grade is computed from.
+    pub movs: PeaqMovs,
+}
+
+/// The model output variables of the basic version of PEAQ.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PeaqMovs {
+    /// The bandwidth of the reference, in FFT bins.
+    pub bandwidth_ref: f64,
+    /// The bandwidth of the test signal, in FFT bins.
+    pub bandwidth_test: f64,
+    /// The ratio of the noise to the masking threshold, in dB.
+    pub total_nmr: f64,
+    /// The windowed average of the changes in modulation.
+    pub win_mod_diff1: f64,
+    /// The average distorted block, from the probability of detecting the
+    /// distortion.
+    pub adb: f64,
+    /// The error harmonic structure, which is high for errors with a
+    /// harmonic spectrum.
+    pub ehs: f64,
+    /// The average of the changes in modulation.
+    pub avg_mod_diff1: f64,
+    /// The average of the changes in modulation, weighing increases more
+    /// than decreases.
+    pub avg_mod_diff2: f64,
+    /// The root mean square of the partial loudness of the noise.
+    pub rms_noise_loud: f64,
+    /// The maximum filtered probability of detecting the distortion.
+    pub mfpd: f64,
+    /// The fraction of frames where the noise exceeds the masking threshold
+    /// by 1.5 dB in some band.
+    pub rel_dist_frames: f64,
+}
+
+impl PeaqMovs {
+    fn as_array(&self) -> [f64; 11] {
+        [
+            self.bandwidth_ref,
+            self.bandwidth_test,
+            self.total_nmr,
+            self.win_mod_diff1,
+            self.adb,
+            self.ehs,
+            self.avg_mod_diff1,
+            self.avg_mod_diff2,
+            self.rms_noise_loud,
+            self.mfpd,
+            self.rel_dist_frames,
+        ]
+    }
+
+    /// Maps the variables to the Distortion Index with the neural network of
+    /// the basic version.
+    pub fn distortion_index(&self) -> f64 {
+        const MOV_MIN: [f64; 11] = [
+            393.916656, 361.965332, -24.045116, 1.110661, -0.206623, 0.074318, 1.113683, 0.950345,
+            0.029985, 0.000101, 0.,
+        ];
+        const MOV_MAX: [f64; 11] = [
+            921.,
+            881.131226,
+            16.212030,
+            107.137772,
+            2.886017,
+            13.933351,
+            63.257874,
+            1145.018555,
+            14.819740,
+            1.,
+            1.,
+        ];
+        const INPUT_WEIGHTS: [[f64; 3]; 11] = [
+            [-0.502657, 0.436333, 1.219602],
+            [4.307481, 3.246017, 1.123743],
+            [4.984241, -2.211189, -0.192096],
+            [0.051056, -1.762424, 4.331315],
+            [2.321580, 1.789971, -0.754560],
+            [-5.303901, -3.452257, -10.814982],
+            [2.730991, -6.111805, 1.519223],
+            [0.624950, -1.331523, -5.955151],
+            [3.102889, 0.871260, -5.922878],
+            [-1.051468, -0.939882, -0.142913],
+            [-1.804679, -0.503610, -0.620456],
+        ];
+        const INPUT_BIAS: [f64; 3] = [-2.518254, 0.654841, -2.207228];
+        const OUTPUT_WEIGHTS: [f64; 3] = [-3.817048, 4.107138, 4.629582];
+        const OUTPUT_BIAS: f64 = -0.307594;
+
+        let movs = self.as_array();
+        let mut di = OUTPUT_BIAS;
+        for node in 0..3 {
+            let mut sum = INPUT_BIAS[node];
+            for i in 0..11 {
+                sum += INPUT_WEIGHTS[i][node] * (movs[i] - MOV_MIN[i]) / (MOV_MAX[i] - MOV_MIN[i]);
+            }
+            di += OUTPUT_WEIGHTS[node] * sigmoid(sum);
+        }
+        di
+    }
+}
+
+/// Maps a Distortion Index to an Objective Difference Grade.
+fn objective_difference_grade(di: f64) -> f64 {
+    const ODG_MIN: f64 = -3.98;
+    const ODG_MAX: f64 = 0.22;
+    ODG_MIN + (ODG_MAX - ODG_MIN) * sigmoid(di)
+}
+
+fn sigmoid(x: f64) -> f64 {
+    1. / (1. + (-x).exp())
+}
+
+/// The coefficient of a first order low-pass filter, run once per frame,
+/// with a time constant that goes from `tau_min` at high frequencies to
+/// `tau_100` at 100 Hz.
+fn smoothing(center: f64, tau_100: f64, tau_min: f64) -> f64 {
+    let tau = tau_min + 100. / center * (tau_100 - tau_min);
+    (-(HOP as f64) / (SAMPLE_RATE as f64 * tau)).exp()
+}
+
+fn bark(frequency: f64) -> f64 {
+    7. * (frequency / 650.).asinh()
+}
+
+fn hertz(bark: f64) -> f64 {
+    650. * (bark / 7.).sinh()
+}
+
+/// Sums the energy of the FFT bins that fall in each band.
+fn group_bands(bands: &[Vec<(usize, f64)>], power: &[f64]) -> Vec<f64> {
+    bands
+        .iter()
+        .map(|bins| {
+            bins.iter()
+                .map(|&(bin, share)| share * power[bin])
+                .sum::<f64>()
+                .max(1e-12)
+        })
+        .collect()
+}
+
+/// The analysis of one frame of a channel, which does not depend on the
+/// previous frames.
+struct FrameAnalysis {
+    /// The excitation of each band after spreading in frequency, for the
+    /// reference and the test signal.
+    excitation: [Vec<f64>; 2],
+    /// The energy of the difference between the spectra in each band.
+    noise: Vec<f64>,
+    /// The bandwidths of the reference and the test signal, for frames where
+    /// the reference is wide enough.
+    bandwidth: Option<(f64, f64)>,
+    /// The error harmonic structure, for frames that are loud enough.
+    ehs: Option<f64>,
+}
+
+/// The filters of the model and the values collected from each frame of a
+/// channel.
+struct ChannelState {
+    /// The excitation after spreading in time.
+    smeared: [Vec<f64>; 2],
+    /// The excitation of the previous frame raised to 0.3.
+    previous_loudness: [Vec<f64>; 2],
+    average_loudness: [Vec<f64>; 2],
+    loudness_change: [Vec<f64>; 2],
+    levels: [Vec<f64>; 2],
+    correlation: Vec<f64>,
+    reference_energy: Vec<f64>,
+    pattern_correction: [Vec<f64>; 2],
+    filtered_detection: f64,
+
+    frames: usize,
+    bandwidth: (f64, f64, usize),
+    nmr_sum: f64,
+    distorted_frames: usize,
+    /// The modulation differences 1 and 2, their temporal weight and the
+    /// noise loudness of each frame.
+    modulation: Vec<[f64; 4]>,
+    mfpd: f64,
+    detected: (f64, usize),
+    ehs: (f64, usize),
+}
+
+impl ChannelState {
+    fn new() -> Self {
+        let zeros = || vec![0.; BANDS];
+        ChannelState {
+            smeared: [zeros(), zeros()],
+            previous_loudness: [zeros(), zeros()],
+            average_loudness: [zeros(), zeros()],
+            loudness_change: [zeros(), zeros()],
+            levels: [zeros(), zeros()],
+            correlation: zeros(),
+            reference_energy: zeros(),
+            pattern_correction: [zeros(), zeros()],
+            filtered_detection: 0.,
+            frames: 0,
+            bandwidth: (0., 0., 0),
+            nmr_sum: 0.,
+            distorted_frames: 0,
+            modulation: Vec::new(),
+            mfpd: 0.,
+            detected: (0., 0),
+            ehs: (0., 0),
+        }
+    }
+}
+
+struct Peaq {
+    fft: Fft,
+    ehs_fft: Fft,
+    window: Vec<f64>,
+    ehs_window: Vec<f64>,
+    /// Scales the power spectrum so that a full scale sine peaks at the
+    /// listening level.
+    scale: f64,
+    /// The power gain of the outer and middle ear at each FFT bin.
+    ear_weights: Vec<f64>,
+    /// The FFT bins of each band, with the share of each bin that falls in it.
+    bands: Vec<Vec<(usize, f64)>>,
+    centers: Vec<f64>,
+    internal_noise: Vec<f64>,
+    thresholds: Vec<f64>,
+    mask_offsets: Vec<f64>,
+    /// The spreading of a signal at 0 dB in each band, which normalizes the
+    /// spreading.
+    spread_norm: Vec<f64>,
+    time_spreading: Vec<f64>,
+    adaptation: Vec<f64>,
+}
+
+impl Peaq {
+    fn new() -> Self {
+        let fft = Fft::new(FRAME_LEN);
+        // A Hann window scaled to preserve the energy of the signal.
+        let window: Vec<f64> = WindowFunction::Hann
+            .coefficients(FRAME_LEN)
+            .into_iter()
+            .map(|w| w * (8f64 / 3.).sqrt())
+            .collect();
+        let sine: Vec<f64> = window
+            .iter()
+            .enumerate()
+            .map(|(n, w)| w * (2. * PI * 1019.5 * n as f64 / SAMPLE_RATE as f64).sin())
+            .collect();
+        let peak = fft.power_spectrum(&sine).into_iter().fold(0., f64::max);
+        let scale = 10f64.powf(LISTENING_LEVEL / 10.) / peak;
+
+        let bin_width = SAMPLE_RATE as f64 / FRAME_LEN as f64;
+        let ear_weights = (0..=FRAME_LEN / 2)
+            .map(|bin| {
+                if bin == 0 {
+                    return 0.;
+                }
+                let khz = bin as f64 * bin_width / 1000.;
+                let db = -0.6 * 3.64 * khz.powf(-0.8) + 6.5 * (-0.6 * (khz - 3.3).powi(2)).exp()
+                    - 1e-3 * khz.powf(3.6);
+                10f64.powf(db / 10.)
+            })
+            .collect();
+
+        let (low, high) = (bark(80.), bark(18000.));
+        let mut bands = Vec::with_capacity(BANDS);
+        let mut centers = Vec::with_capacity(BANDS);
+        for band in 0..BANDS {
+            let lower = low + band as f64 * BAND_WIDTH;
+            let upper = (lower + BAND_WIDTH).min(high);
+            let (lower_hz, upper_hz) = (hertz(lower), hertz(upper));
+            centers.push(hertz((lower + upper) / 2.));
+            bands.push(
+                (0..=FRAME_LEN / 2)
+                    .filter_map(|bin| {
+                        let start = (bin as f64 - 0.5) * bin_width;
+                        let end = (bin as f64 + 0.5) * bin_width;
+                        let overlap = end.min(upper_hz) - start.max(lower_hz);
+                        (overlap > 0.).then_some((bin, overlap / bin_width))
+                    })
+                    .collect(),
+            );
+        }
+
+        let internal_noise = centers
+            .iter()
+            .map(|fc| 10f64.powf(0.4 * 0.364 * (fc / 1000.).powf(-0.8)))
+            .collect();
+        let thresholds = centers
+            .iter()
+            .map(|fc| 10f64.powf(0.364 * (fc / 1000.).powf(-0.8)))
+            .collect();
+        let mask_offsets = (0..BANDS)
+            .map(|band| {
+                let z = band as f64 * BAND_WIDTH;
+                let db = if z <= 12. { 3. } else { 0.25 * z };
+                10f64.powf(-db / 10.)
+            })
+            .collect();
+        let time_spreading = centers
+            .iter()
+            .map(|&fc| smoothing(fc, 0.030, 0.008))
+            .collect();
+        let adaptation = centers
+            .iter()
+            .map(|&fc| smoothing(fc, 0.050, 0.008))
+            .collect();
+
+        let mut peaq = Peaq {
+            fft,
+            ehs_fft: Fft::new(EHS_LAGS),
+            window,
+            ehs_window: WindowFunction::Hann
+                .coefficients(EHS_LAGS)
+                .into_iter()
+                .map(|w| w * (8f64 / 3.).sqrt() / EHS_LAGS as f64)
+                .collect(),
+            scale,
+            ear_weights,
+            bands,
+            centers,
+            internal_noise,
+            thresholds,
+            mask_offsets,
+            spread_norm: vec![1.; BANDS],
+            time_spreading,
+            adaptation,
+        };
+        peaq.spread_norm = peaq.spread(&[1.; BANDS]);
+        peaq
+    }
+
+    /// The scaled power spectrum of a frame.
+    fn spectrum(&self, samples: &[f32]) -> Vec<f64> {
+        let windowed: Vec<f64> = samples
+            .iter()
+            .zip(self.window.iter())
+            .map(|(&sample, w)| sample as f64 * w)
+            .collect();
+        let mut power = self.fft.power_spectrum(&windowed);
+        for p in power.iter_mut() {
+            *p *= self.scale;
+        }
+        power
+    }
+
+    /// Spreads the energy of each band to the neighbouring bands, with a
+    /// slope towards higher bands that gets shallower at higher levels.
+    fn spread(&self, energy: &[f64]) -> Vec<f64> {
+        let lower = 10f64.powf(-27. * BAND_WIDTH / 10.);
+        let lower_root = lower.powf(0.4);
+        let mut sums = vec![0.; BANDS];
+        for (band, &e) in energy.iter().enumerate() {
+            let level = 10. * e.log10();
+            let upper =
+                10f64.powf(-(24. + 230. / self.centers[band] - 0.2 * level) * BAND_WIDTH / 10.);
+            let lower_sum = lower * (1. - lower.powi(band as i32)) / (1. - lower);
+            let upper_terms = (BANDS - band) as i32;
+            let upper_sum = if (1. - upper).abs() < 1e-12 {
+                upper_terms as f64
+            } else {
+                (1. - upper.powi(upper_terms)) / (1. - upper)
+            };
+            let base = (e / (lower_sum + upper_sum)).powf(0.4);
+            let upper_root = upper.powf(0.4);
+            let mut term = base;
+            for sum in sums[band..].iter_mut() {
+                *sum += term;
+                term *= upper_root;
+            }
+            term = base * lower_root;
+            for sum in sums[..band].iter_mut().rev() {
+                *sum += term;
+                term *= lower_root;
+            }
+        }
+        sums.iter()
+            .zip(self.spread_norm.iter())
+            .map(|(sum, norm)| sum.powf(1. / 0.4) / norm)
+            .collect()
+    }
+
+    /// The bandwidths of the reference and the test signal, in FFT bins.
+    fn bandwidth(reference: &[f64], test: &[f64]) -> Option<(f64, f64)> {
+        let db = |p: f64| 10. * p.max(1e-30).log10();
+        let zero_threshold = test[921..1024]
+            .iter()
+            .map(|&p| db(p))
+            .fold(f64::MIN, f64::max);
+        let bw_ref = (0..921)
+            .rev()
+            .find(|&bin| db(reference[bin]) > zero_threshold + 10.)
+            .map_or(0, |bin| bin + 1);
+        if bw_ref <= 346 {
+            return None;
+        }
+        let bw_test = (0..bw_ref)
+            .rev()
+            .find(|&bin| db(test[bin]) >= zero_threshold + 5.)
+            .map_or(0, |bin| bin + 1);
+        Some((bw_ref as f64, bw_test as f64))
+    }
+
+    /// The peak of the spectrum of the autocorrelation of the difference of
+    /// the log spectra, which is high when the error has harmonics.
+    fn error_harmonic_structure(&self, reference: &[f64], test: &[f64]) -> f64 {
+        let diff: Vec<f64> = (1..=2 * EHS_LAGS)
+            .map(|bin| {
+                let (r, t) = (reference[bin], test[bin]);
+                if r > 0. && t > 0. {
+                    (t / r).ln()
+                } else {
+                    0.
+                }
+            })
+            .collect();
+        let energy0: f64 = diff[..EHS_LAGS].iter().map(|d| d * d).sum();
+        let mut correlation = Vec::with_capacity(EHS_LAGS);
+        for lag in 0..EHS_LAGS {
+            let energy: f64 = diff[lag..lag + EHS_LAGS].iter().map(|d| d * d).sum();
+            let product: f64 = diff[..EHS_LAGS]
+                .iter()
+                .zip(diff[lag..].iter())
+                .map(|(a, b)| a * b)
+                .sum();
+            let norm = (energy0 * energy).sqrt();
+            correlation.push(if norm > 0. { product / norm } else { 0. });
+        }
+        let mean = correlation.iter().sum::<f64>() / EHS_LAGS as f64;
+        let windowed: Vec<f64> = correlation
+            .iter()
+            .zip(self.ehs_window.iter())
+            .map(|(c, w)| (c - mean) * w)
+            .collect();
+        let power = self.ehs_fft.power_spectrum(&windowed);
+        // Skip the falling part of the spectrum around 0, then take the peak.
+        let valley = (1..power.len())
+            .find(|&bin| power[bin] > power[bin - 1])
+            .unwrap_or(power.len());
+        power[valley..].iter().copied().fold(0., f64::max)
+    }
+
+    fn process_frame(&self, state: &mut ChannelState, frame: FrameAnalysis) {
+        let FrameAnalysis {
+            excitation,
+            noise,
+            bandwidth,
+            ehs,
+        } = frame;
+        state.frames += 1;
+
+        if let Some((bw_ref, bw_test)) = bandwidth {
+            state.bandwidth.0 += bw_ref;
+            state.bandwidth.1 += bw_test;
+            state.bandwidth.2 += 1;
+        }
+        if let Some(ehs) = ehs {
+            state.ehs.0 += ehs;
+            state.ehs.1 += 1;
+        }
+
+        // Spreading in time, and the modulation of the unsmeared excitation.
+        let mut modulation = [vec![0.; BANDS], vec![0.; BANDS]];
+        for signal in 0..2 {
+            for band in 0..BANDS {
+                let e = excitation[signal][band];
+                let a = self.time_spreading[band];
+                let smeared = &mut state.smeared[signal][band];
+                *smeared = a * *smeared + (1. - a) * e;
+                *smeared = smeared.max(e);
+
+                let a = self.adaptation[band];
+                let loudness = e.powf(0.3);
+                let change = (loudness - state.previous_loudness[signal][band]).abs()
+                    * (SAMPLE_RATE as f64 / HOP as f64);
+                state.previous_loudness[signal][band] = loudness;
+                let average = &mut state.average_loudness[signal][band];
+                *average = a * *average + (1. - a) * loudness;
+                let derivative = &mut state.loudness_change[signal][band];
+                *derivative = a * *derivative + (1. - a) * change;
+                modulation[signal][band] = *derivative / (1. + *average / 0.3);
+            }
+        }
+        let [reference, test] = &state.smeared;
+
+        // Noise to mask ratio.
+        let mut nmr = 0.;
+        let mut max_nmr: f64 = 0.;
+        for band in 0..BANDS {
+            let ratio = noise[band] / (reference[band] * self.mask_offsets[band]);
+            nmr += ratio;
+            max_nmr = max_nmr.max(ratio);
+        }
+        state.nmr_sum += nmr / BANDS as f64;
+        if 10. * max_nmr.log10() >= 1.5 {
+            state.distorted_frames += 1;
+        }
+
+        // Probability of detection.
+        let mut undetected = 1.;
+        let mut steps = 0.;
+        for band in 0..BANDS {
+            let level_ref = 10. * reference[band].log10();
+            let level_test = 10. * test[band].log10();
+            let level = 0.3 * level_ref.max(level_test) + 0.7 * level_test;
+            let slope = if level > 0. {
+                5.95072 * (6.39468 / level).powf(1.71332)
+                    + 9.01033e-11 * level.powi(4)
+                    + 5.05622e-6 * level.powi(3)
+                    - 0.00102438 * level.powi(2)
+                    + 0.0550197 * level
+                    - 0.198719
+            } else {
+                1e30
+            };
+            let error = level_test - level_ref;
+            let b = if level_ref > level_test { 4. } else { 6. };
+            let a = 10f64.powf(2f64.log10().log10() / b) / slope;
+            let detection = 1. - 10f64.powf(-(a * error).powf(b));
+            undetected *= 1. - detection;
+            steps += error.trunc().abs() / slope;
+        }
+        let detection = 1. - undetected;
+        state.filtered_detection = 0.9 * state.filtered_detection + 0.1 * detection;
+        state.mfpd = state.mfpd.max(state.filtered_detection);
+        if detection > 0.5 {
+            state.detected.0 += steps;
+            state.detected.1 += 1;
+        }
+
+        // Level and pattern adaptation.
+        let mut shared = 0.;
+        let mut test_level = 0.;
+        for band in 0..BANDS {
+            let a = self.adaptation[band];
+            for signal in 0..2 {
+                let level = &mut state.levels[signal][band];
+                *level = a * *level + (1. - a) * state.smeared[signal][band];
+            }
+            shared += (state.levels[0][band] * state.levels[1][band]).sqrt();
+            test_level += state.levels[1][band];
+        }
+        let correction = if test_level > 0. {
+            (shared / test_level).powi(2)
+        } else {
+            1.
+        };
+        let (reference_gain, test_gain) = if correction > 1. {
+            (1. / correction, 1.)
+        } else {
+            (1., correction)
+        };
+        let level_adapted: [Vec<f64>; 2] = [
+            reference.iter().map(|e| e * reference_gain).collect(),
+            test.iter().map(|e| e * test_gain).collect(),
+        ];
+        let mut ratios = [vec![1.; BANDS], vec![1.; BANDS]];
+        for band in 0..BANDS {
+            let a = self.adaptation[band];
+            let (r, t) = (level_adapted[0][band], level_adapted[1][band]);
+            state.correlation[band] = a * state.correlation[band] + t * r;
+            state.reference_energy[band] = a * state.reference_energy[band] + r * r;
+            let (num, den) = (state.correlation[band], state.reference_energy[band]);
+            if num >= den && num > 0. {
+                ratios[1][band] = den / num;
+            } else if den > 0. {
+                ratios[0][band] = num / den;
+            }
+        }
+        let mut pattern_adapted = [vec![0.; BANDS], vec![0.; BANDS]];
+        for signal in 0..2 {
+            for band in 0..BANDS {
+                let neighbours = &ratios[signal][band.saturating_sub(3)..(band + 5).min(BANDS)];
+                let average = neighbours.iter().sum::<f64>() / neighbours.len() as f64;
+                let a = self.adaptation[band];
+                let correction = &mut state.pattern_correction[signal][band];
+                *correction = a * *correction + (1. - a) * average;
+                pattern_adapted[signal][band] = level_adapted[signal][band] * *correction;
+            }
+        }
+
+        // Modulation differences and noise loudness.
+        let mut mod_diff1 = 0.;
+        let mut mod_diff2 = 0.;
+        let mut weight = 0.;
+        let mut noise_loudness = 0.;
+        for band in 0..BANDS {
+            let (mod_ref, mod_test) = (modulation[0][band], modulation[1][band]);
+            let diff = (mod_test - mod_ref).abs();
+            mod_diff1 += diff / (1. + mod_ref);
+            let w = if mod_test > mod_ref { 1. } else { 0.1 };
+            mod_diff2 += w * diff / (0.01 + mod_ref);
+            let average = state.average_loudness[0][band];
+            weight += average / (average + 100. * self.thresholds[band].powf(0.3));
+
+            let (ep_ref, ep_test) = (pattern_adapted[0][band], pattern_adapted[1][band]);
+            let s_ref = 0.15 * mod_ref + 0.5;
+            let s_test = 0.15 * mod_test + 0.5;
+            let beta = if ep_ref > 0. {
+                (-1.5 * (ep_test - ep_ref) / ep_ref).exp()
+            } else {
+                0.
+            };
+            let threshold = self.thresholds[band];
+            noise_loudness += (threshold / s_test).powf(0.23)
+                * ((1.
+                    + (s_test * ep_test - s_ref * ep_ref).max(0.)
+                        / (threshold + s_ref * ep_ref * beta))
+                    .powf(0.23)
+                    - 1.);
+        }
+        state.modulation.push([
+            100. * mod_diff1 / BANDS as f64,
+            100. * mod_diff2 / BANDS as f64,
+            weight,
+            (24. * noise_loudness / BANDS as f64).max(0.),
+        ]);
+    }
+
+    fn channel_movs(&self, state: &ChannelState) -> PeaqMovs {
+        let (bw_ref, bw_test, bw_frames) = state.bandwidth;
+        let (bandwidth_ref, bandwidth_test) = if bw_frames > 0 {
+            (bw_ref / bw_frames as f64, bw_test / bw_frames as f64)
+        } else {
+            (0., 0.)
+        };
+        let frames = state.frames.max(1) as f64;
+
+        let delayed = if state.modulation.len() > DELAY_FRAMES {
+            &state.modulation[DELAY_FRAMES..]
+        } else {
+            &state.modulation[..]
+        };
+        let weights: f64 = delayed.iter().map(|m| m[2]).sum();
+        let weighted_average = |index: usize| {
+            if weights > 0. {
+                delayed.iter().map(|m| m[2] * m[index]).sum::<f64>() / weights
+            } else {
+                0.
+            }
+        };
+        let roots: Vec<f64> = delayed.iter().map(|m| m[0].sqrt()).collect();
+        let win_mod_diff1 = if roots.len() >= 4 {
+            let windows = roots.windows(4);
+            let count = windows.len() as f64;
+            (windows
+                .map(|w| (w.iter().sum::<f64>() / 4.).powi(4))
+                .sum::<f64>()
+                / count)
+                .sqrt()
+        } else {
+            0.
+        };
+        let rms_noise_loud = if delayed.is_empty() {
+            0.
+        } else {
+            (delayed.iter().map(|m| m[3] * m[3]).sum::<f64>() / delayed.len() as f64).sqrt()
+        };
+
+        let (steps, detected) = state.detected;
+        let adb = if detected == 0 {
+            0.
+        } else if steps > 0. {
+            (steps / detected as f64).log10()
+        } else {
+            -0.5
+        };
+        let (ehs, ehs_frames) = state.ehs;
+
+        PeaqMovs {
+            bandwidth_ref,
+            bandwidth_test,
+            total_nmr: 10. * (state.nmr_sum / frames).log10(),
+            win_mod_diff1,
+            adb,
+            ehs: if ehs_frames > 0 {
+                1000. * ehs / ehs_frames as f64
+            } else {
+                0.
+            },
+            avg_mod_diff1: weighted_average(0),
+            avg_mod_diff2: weighted_average(1),
+            rms_noise_loud,
+            mfpd: state.mfpd,
+            rel_dist_frames: state.distorted_frames as f64 / frames,
+        }
+    }
+}
+
+impl AudioMetric for Peaq {
+    type WindowResult = FrameAnalysis;
+    type State = ChannelState;
+    type AudioResult = PeaqResult;
+
+    fn window_len(&self) -> usize {
+        FRAME_LEN
+    }
+
+    fn hop(&self) -> usize {
+        HOP
+    }
+
+    fn validate(&self, details: &AudioDetails) -> Result<(), MetricsError> {
+        if details.sample_rate != SAMPLE_RATE {
+            return Err(MetricsError::UnsupportedInput {
+                reason: "PEAQ requires a sample rate of 48 kHz",
+            });
+        }
+        Ok(())
+    }
+
+    fn new_state(&self) -> ChannelState {
+        ChannelState::new()
+    }
+
+    fn process_window(&self, reference: &[f32], test: &[f32]) -> FrameAnalysis {
+        let power = [self.spectrum(reference), self.spectrum(test)];
+        let weighted: [Vec<f64>; 2] = [
+            power[0]
+                .iter()
+                .zip(self.ear_weights.iter())
+                .map(|(p, w)| p * w)
+                .collect(),
+            power[1]
+                .iter()
+                .zip(self.ear_weights.iter())
+                .map(|(p, w)| p * w)
+                .collect(),
+        ];
+        let noise: Vec<f64> = weighted[0]
+            .iter()
+            .zip(weighted[1].iter())
+            .map(|(r, t)| (r.sqrt() - t.sqrt()).powi(2))
+            .collect();
+        let excitation = [0, 1].map(|signal| {
+            let mut energy = group_bands(&self.bands, &weighted[signal]);
+            for (e, noise) in energy.iter_mut().zip(self.internal_noise.iter()) {
+                *e += noise;
+            }
+            self.spread(&energy)
+        });
+
+        let energy = |samples: &[f32]| {
+            samples[HOP..]
+                .iter()
+                .map(|&s| s as f64 * s as f64)
+                .sum::<f64>()
+        };
+        let ehs = (energy(reference) >= ENERGY_THRESHOLD || energy(test) >= ENERGY_THRESHOLD)
+            .then(|| self.error_harmonic_structure(&weighted[0], &weighted[1]));
+
+        FrameAnalysis {
+            excitation,
+            noise: group_bands(&self.bands, &noise),
+            bandwidth: Self::bandwidth(&power[0], &power[1]),
+            ehs,
+        }
+    }
+
+    fn accumulate(&self, state: &mut ChannelState, result: FrameAnalysis) {
+        self.process_frame(state, result);
+    }
+
+    fn aggregate(&self, states: Vec<ChannelState>) -> Result<PeaqResult, Box<dyn Error>> {
+        let channels = states.len() as f64;
+        let mut movs = [0.; 11];
+        for state in &states {
+            for (sum, mov) in movs.iter_mut().zip(self.channel_movs(state).as_array()) {
+                *sum += mov / channels;
+            }
+        }
+        let movs = PeaqMovs {
+            bandwidth_ref: movs[0],
+            bandwidth_test: movs[1],
+            total_nmr: movs[2],
+            win_mod_diff1: movs[3],
+            adb: movs[4],
+            ehs: movs[5],
+            avg_mod_diff1: movs[6],
+            avg_mod_diff2: movs[7],
+            rms_noise_loud: movs[8],
+            mfpd: movs[9],
+            rel_dist_frames: movs[10],
+        };
+        let distortion_index = movs.distortion_index();
+        Ok(PeaqResult {
+            odg: objective_difference_grade(distortion_index),
+            distortion_index,
+            movs,
+        })
+    }
+}
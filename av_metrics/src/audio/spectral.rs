@@ -0,0 +1,222 @@
+//! Spectral distance and segmental SNR metrics.
+//!
+//! These compare the short-time spectra or the samples of two signals
+//! directly, without a model of hearing. They are quick to compute and
+//! useful to track regressions, much like PSNR for video.
+//!
+//! - The log-spectral distance is the root mean square difference between
+//!   the log power spectra of each frame, in dB, averaged over the frames.
+//! - The spectral convergence is the norm of the difference between the
+//!   magnitude spectrograms, relative to the norm of the reference
+//!   spectrogram.
+//! - The segmental SNR is the signal-to-noise ratio of each frame, limited
+//!   to between -10 and 35 dB, averaged over the frames.
+
+use crate::audio::decode::AudioDecoder;
+use crate::audio::fft::Fft;
+use crate::audio::window::WindowFunction;
+use crate::audio::AudioMetric;
+use crate::MetricsError;
+use std::error::Error;
+
+/// The power added to every bin, so that silent bins do not have an infinite
+/// log-spectral distance.
+const POWER_FLOOR: f64 = 1e-10;
+const MIN_SEGMENT_SNR: f64 = -10.;
+const MAX_SEGMENT_SNR: f64 = 35.;
+
+/// Calculates the spectral metrics for a reference and a test signal.
+///
+/// `decoder1` is the reference and `decoder2` the signal under test. The
+/// metrics of each channel are averaged.
+#[inline]
+pub fn calculate_audio_spectral<D: AudioDecoder, F: Fn(usize) + Send>(
+    decoder1: &mut D,
+    decoder2: &mut D,
+    progress_callback: F,
+) -> Result<SpectralMetrics, Box<dyn Error>> {
+    calculate_audio_spectral_with_config(
+        decoder1,
+        decoder2,
+        progress_callback,
+        SpectralConfig::default(),
+    )
+}
+
+/// Calculates the spectral metrics for a reference and a test signal using
+/// the given configuration.
+///
+/// See [`SpectralConfig`] for the available options.
+#[inline]
+pub fn calculate_audio_spectral_with_config<D: AudioDecoder, F: Fn(usize) + Send>(
+    decoder1: &mut D,
+    decoder2: &mut D,
+    progress_callback: F,
+    config: SpectralConfig,
+) -> Result<SpectralMetrics, Box<dyn Error>> {
+    Spectral::new(config)?.process_audio(decoder1, decoder2, progress_callback)
+}
+
+/// Options for the spectral metrics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SpectralConfig {
+    /// The number of samples of each frame, which must be a power of two.
+    pub frame_len: usize,
+    /// The number of samples between the starts of two frames.
+    pub hop: usize,
+    /// The window applied to each frame before its spectrum is computed.
+    /// The segmental SNR is computed on the samples without a window.
+    pub window: WindowFunction,
+}
+
+impl Default for SpectralConfig {
+    fn default() -> Self {
+        SpectralConfig {
+            frame_len: 1024,
+            hop: 512,
+            window: WindowFunction::Hann,
+        }
+    }
+}
+
+/// The results of the spectral metrics.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SpectralMetrics {
+    /// The log-spectral distance, in dB. Lower is better.
+    pub log_spectral_distance: f64,
+    /// The spectral convergence, from 0 for identical spectra. Lower is better.
+    pub spectral_convergence: f64,
+    /// The segmental SNR, in dB. Higher is better.
+    pub segmental_snr: f64,
+}
+
+struct Spectral {
+    config: SpectralConfig,
+    fft: Fft,
+    window: Vec<f64>,
+}
+
+impl Spectral {
+    fn new(config: SpectralConfig) -> Result<Self, MetricsError> {
+        if !config.frame_len.is_power_of_two() || config.frame_len < 2 {
+            return Err(MetricsError::UnsupportedInput {
+                reason: "Frame length must be a power of two",
+            });
+        }
+        if config.hop == 0 || config.hop > config.frame_len {
+            return Err(MetricsError::UnsupportedInput {
+                reason: "Hop must be between 1 and the frame length",
+            });
+        }
+        Ok(Spectral {
+            config,
+            fft: Fft::new(config.frame_len),
+            window: config.window.coefficients(config.frame_len),
+        })
+    }
+
+    fn power_spectrum(&self, samples: &[f32]) -> Vec<f64> {
+        let windowed: Vec<f64> = samples
+            .iter()
+            .zip(self.window.iter())
+            .map(|(&sample, w)| sample as f64 * w)
+            .collect();
+        self.fft.power_spectrum(&windowed)
+    }
+}
+
+struct FrameResult {
+    log_spectral_distance: f64,
+    squared_error: f64,
+    squared_reference: f64,
+    segment_snr: f64,
+}
+
+#[derive(Default)]
+struct SpectralState {
+    frames: usize,
+    log_spectral_distance: f64,
+    squared_error: f64,
+    squared_reference: f64,
+    segmental_snr: f64,
+}
+
+impl AudioMetric for Spectral {
+    type WindowResult = FrameResult;
+    type State = SpectralState;
+    type AudioResult = SpectralMetrics;
+
+    fn window_len(&self) -> usize {
+        self.config.frame_len
+    }
+
+    fn hop(&self) -> usize {
+        self.config.hop
+    }
+
+    fn new_state(&self) -> SpectralState {
+        SpectralState::default()
+    }
+
+    fn process_window(&self, reference: &[f32], test: &[f32]) -> FrameResult {
+        let power_ref = self.power_spectrum(reference);
+        let power_test = self.power_spectrum(test);
+        let mut log_diff = 0.;
+        let mut squared_error = 0.;
+        let mut squared_reference = 0.;
+        for (&r, &t) in power_ref.iter().zip(power_test.iter()) {
+            log_diff += (10. * ((r + POWER_FLOOR) / (t + POWER_FLOOR)).log10()).powi(2);
+            squared_error += (r.sqrt() - t.sqrt()).powi(2);
+            squared_reference += r;
+        }
+
+        let (signal, noise) =
+            reference
+                .iter()
+                .zip(test.iter())
+                .fold((0., 0.), |(signal, noise), (&r, &t)| {
+                    let (r, t) = (r as f64, t as f64);
+                    (signal + r * r, noise + (r - t) * (r - t))
+                });
+        let segment_snr = if noise > 0. {
+            (10. * (signal / noise).log10()).clamp(MIN_SEGMENT_SNR, MAX_SEGMENT_SNR)
+        } else {
+            MAX_SEGMENT_SNR
+        };
+
+        FrameResult {
+            log_spectral_distance: (log_diff / power_ref.len() as f64).sqrt(),
+            squared_error,
+            squared_reference,
+            segment_snr,
+        }
+    }
+
+    fn accumulate(&self, state: &mut SpectralState, result: FrameResult) {
+        state.frames += 1;
+        state.log_spectral_distance += result.log_spectral_distance;
+        state.squared_error += result.squared_error;
+        state.squared_reference += result.squared_reference;
+        state.segmental_snr += result.segment_snr;
+    }
+
+    fn aggregate(&self, states: Vec<SpectralState>) -> Result<SpectralMetrics, Box<dyn Error>> {
+        let channels = states.len() as f64;
+        let mut metrics = SpectralMetrics::default();
+        for state in &states {
+            let frames = state.frames.max(1) as f64;
+            metrics.log_spectral_distance += state.log_spectral_distance / frames / channels;
+            metrics.spectral_convergence += if state.squared_reference > 0. {
+                (state.squared_error / state.squared_reference).sqrt()
+            } else if state.squared_error > 0. {
+                f64::INFINITY
+            } else {
+                0.
+            } / channels;
+            metrics.segmental_snr += state.segmental_snr / frames / channels;
+        }
+        Ok(metrics)
+    }
+}
@@ -0,0 +1,34 @@
+//! Window functions applied to frames of audio before they are transformed.
+
+use std::f64::consts::PI;
+
+/// The shape of the window applied to each frame.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum WindowFunction {
+    /// The raised cosine window, which falls to 0 at both ends.
+    #[default]
+    Hann,
+    /// A raised cosine window that falls to 0.08 at both ends, trading a
+    /// wider main lobe for lower side lobes than Hann.
+    Hamming,
+    /// No window. Every sample of the frame has the same weight.
+    Rectangular,
+}
+
+impl WindowFunction {
+    /// The coefficients of a symmetric window of `len` samples.
+    pub fn coefficients(self, len: usize) -> Vec<f64> {
+        let denominator = len.saturating_sub(1).max(1) as f64;
+        (0..len)
+            .map(|n| {
+                let phase = (2. * PI * n as f64 / denominator).cos();
+                match self {
+                    WindowFunction::Hann => 0.5 * (1. - phase),
+                    WindowFunction::Hamming => 0.54 - 0.46 * phase,
+                    WindowFunction::Rectangular => 1.,
+                }
+            })
+            .collect()
+    }
+}
@@ -1,6 +1,6 @@
 //! `av_metrics` is a collection of quality metrics for audio and video files.
-//! Currently only includes video metrics. Audio metrics will likely be added
-//! in the future.
+//! Currently only includes video metrics. The [`audio`] module has the
+//! groundwork for audio metrics, which will likely be added in the future.
 
 #![allow(clippy::cast_lossless)]
 #![allow(clippy::needless_range_loop)]
@@ -13,8 +13,16 @@ extern crate itertools;
 #[macro_use]
 extern crate thiserror;
 
+pub mod audio;
 pub mod video;
 
+/// The version of this crate, for recording alongside computed scores.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Passed to progress callbacks once every frame or window has been
+/// processed, after the last count.
+pub const PROGRESS_DONE: usize = usize::MAX;
+
 /// Possible errors that may occur during processing of a metric.
 ///
 /// This enum may be added to in the future and should not be assumed to be exhaustive.
@@ -39,6 +47,14 @@ pub enum MetricsError {
         #[doc(hidden)]
         reason: &'static str,
     },
+    /// Indicates a plane of a frame did not match the other frame or the expected format.
+    #[error("Plane {plane} does not match: {reason}")]
+    PlaneMismatch {
+        #[doc(hidden)]
+        plane: usize,
+        #[doc(hidden)]
+        reason: &'static str,
+    },
     /// Indicates the impossibility to process the two videos.
     #[error("Could not process the two videos: {reason}")]
     VideoError {
@@ -57,4 +73,18 @@ pub enum MetricsError {
         #[doc(hidden)]
         reason: String,
     },
+    /// Indicates an input could not be opened or decoded by its decoder.
+    #[error("{reason}")]
+    DecodeError {
+        #[doc(hidden)]
+        reason: String,
+    },
+    /// Indicates two videos did not have the same number of frames.
+    #[error("Input videos have different lengths: {frames1} and {frames2} frames")]
+    LengthMismatch {
+        #[doc(hidden)]
+        frames1: usize,
+        #[doc(hidden)]
+        frames2: usize,
+    },
 }
@@ -0,0 +1,626 @@
+//! Temporal and spatial alignment of two videos.
+//!
+//! Encoders and capture pipelines sometimes drop or insert frames at the
+//! start of a clip, so that frame `n` of the distorted video no longer shows
+//! the same picture as frame `n` of the reference. Every metric then compares
+//! unrelated frames and the scores are meaningless.
+//!
+//! [`detect_frame_offset`] finds a constant offset between two videos by
+//! cross-correlating their luma planes, and [`Skipped`] drops the leading
+//! frames of a decoder so that the two line up again.
+//!
+//! Capture pipelines may also shift the picture by a pixel or two, which is
+//! enough to ruin SSIM. [`detect_shift`] estimates a global translation by
+//! phase correlation, and [`Shifted`] moves the frames of a decoder back.
+
+use crate::video::decode::{
+    Decoder, DecoderCapabilities, FrameMeta, PlaneRef, StreamInfo, VideoDetails,
+};
+use crate::video::pixel::{CastFromPrimitive, Pixel};
+use crate::video::side_data::SideData;
+use crate::MetricsError;
+use std::error::Error;
+use std::f64::consts::PI;
+use std::fmt;
+use v_frame::frame::Frame;
+use v_frame::plane::Plane;
+
+/// The largest width or height of the luma thumbnails that are correlated.
+/// Downsampling makes the search fast and insensitive to coding noise.
+const THUMBNAIL_SIZE: usize = 64;
+
+/// The largest size of the square window of the luma plane used for phase
+/// correlation.
+const PHASE_WINDOW_SIZE: usize = 256;
+
+/// Detects a constant frame offset between two videos.
+///
+/// The luma planes of up to `frames` frames of each video are compared at
+/// every offset from `-max_offset` to `max_offset`, and the offset with the
+/// highest mean normalized cross-correlation is returned. Ties are resolved
+/// in favor of the smallest offset.
+///
+/// A positive offset means the distorted video is delayed: frame `n` of
+/// `decoder1` matches frame `n + offset` of `decoder2`, and the first
+/// `offset` frames of `decoder2` should be skipped. A negative offset means
+/// the first `-offset` frames of `decoder1` should be skipped instead.
+///
+/// Both decoders are consumed by the search, so new decoders need to be
+/// opened to compute metrics on the aligned videos.
+pub fn detect_frame_offset<D: Decoder>(
+    decoder1: &mut D,
+    decoder2: &mut D,
+    frames: usize,
+    max_offset: usize,
+) -> Result<isize, Box<dyn Error>> {
+    if decoder1.get_bit_depth() != decoder2.get_bit_depth() {
+        return Err(Box::new(MetricsError::InputMismatch {
+            reason: "Bit depths do not match",
+        }));
+    }
+
+    let count = frames + max_offset;
+    let (thumbs1, thumbs2) = if decoder1.get_bit_depth() > 8 {
+        (
+            read_thumbnails::<_, u16>(decoder1, count),
+            read_thumbnails::<_, u16>(decoder2, count),
+        )
+    } else {
+        (
+            read_thumbnails::<_, u8>(decoder1, count),
+            read_thumbnails::<_, u8>(decoder2, count),
+        )
+    };
+    if thumbs1.first().map(Vec::len) != thumbs2.first().map(Vec::len) {
+        return Err(Box::new(MetricsError::InputMismatch {
+            reason: "Resolutions do not match",
+        }));
+    }
+
+    let mut best: Option<(isize, f64)> = None;
+    let candidates = (0..=max_offset as isize).flat_map(|offset| {
+        if offset == 0 {
+            vec![0]
+        } else {
+            vec![offset, -offset]
+        }
+    });
+    for offset in candidates {
+        let (skip1, skip2) = if offset >= 0 {
+            (0, offset as usize)
+        } else {
+            (offset.unsigned_abs(), 0)
+        };
+        let pairs = thumbs1
+            .iter()
+            .skip(skip1)
+            .zip(thumbs2.iter().skip(skip2))
+            .take(frames);
+        let (sum, n) = pairs.fold((0.0, 0), |(sum, n), (a, b)| {
+            (sum + normalized_correlation(a, b), n + 1)
+        });
+        if n == 0 {
+            continue;
+        }
+        let score = sum / n as f64;
+        if best.map(|(_, best)| score > best).unwrap_or(true) {
+            best = Some((offset, score));
+        }
+    }
+
+    best.map(|(offset, _)| offset).ok_or_else(|| {
+        Box::new(MetricsError::UnsupportedInput {
+            reason: "Not enough frames to detect an offset",
+        }) as Box<dyn Error>
+    })
+}
+
+/// Decodes up to `count` frames and returns a downsampled copy of the luma
+/// plane of each.
+fn read_thumbnails<D: Decoder, T: Pixel>(decoder: &mut D, count: usize) -> Vec<Vec<f64>> {
+    let mut thumbs = Vec::with_capacity(count);
+    while thumbs.len() < count {
+        let Some(frame) = decoder.read_video_frame::<T>() else {
+            break;
+        };
+        thumbs.push(luma_thumbnail(&frame));
+    }
+    thumbs
+}
+
+/// Averages blocks of the luma plane so that neither dimension exceeds
+/// `THUMBNAIL_SIZE`.
+fn luma_thumbnail<T: Pixel>(frame: &Frame<T>) -> Vec<f64> {
+    let plane = PlaneRef::from(&frame.planes[0]);
+    let (width, height) = (plane.width(), plane.height());
+    let block = width.max(height).div_ceil(THUMBNAIL_SIZE).max(1);
+    let (thumb_width, thumb_height) = (width.div_ceil(block), height.div_ceil(block));
+    let mut sums = vec![0.0; thumb_width * thumb_height];
+    let mut counts = vec![0u32; thumb_width * thumb_height];
+    for (y, row) in plane.rows().enumerate() {
+        let thumb_row = (y / block) * thumb_width;
+        for (x, &pixel) in row.iter().enumerate() {
+            sums[thumb_row + x / block] += f64::from(i32::cast_from(pixel));
+            counts[thumb_row + x / block] += 1;
+        }
+    }
+    sums.iter()
+        .zip(counts)
+        .map(|(&sum, count)| sum / f64::from(count.max(1)))
+        .collect()
+}
+
+/// The Pearson correlation coefficient of two thumbnails, in `[-1, 1]`.
+///
+/// Two flat thumbnails correlate perfectly if they have the same brightness,
+/// and a flat thumbnail does not correlate with one that has any detail.
+fn normalized_correlation(a: &[f64], b: &[f64]) -> f64 {
+    let n = a.len().min(b.len()) as f64;
+    if n == 0.0 {
+        return 0.0;
+    }
+    let mean_a = a.iter().sum::<f64>() / n;
+    let mean_b = b.iter().sum::<f64>() / n;
+    let (mut cov, mut var_a, mut var_b) = (0.0, 0.0, 0.0);
+    for (&a, &b) in a.iter().zip(b) {
+        let (da, db) = (a - mean_a, b - mean_b);
+        cov += da * db;
+        var_a += da * da;
+        var_b += db * db;
+    }
+    if var_a == 0.0 || var_b == 0.0 {
+        return if var_a == var_b && (mean_a - mean_b).abs() < 0.5 {
+            1.0
+        } else {
+            0.0
+        };
+    }
+    cov / (var_a * var_b).sqrt()
+}
+
+/// A decoder that drops the first frames of another decoder, for example to
+/// apply an offset found by [`detect_frame_offset`].
+///
+/// The frames are skipped on the first read, so wrapping a decoder is cheap.
+pub struct Skipped<D: Decoder> {
+    inner: D,
+    remaining: usize,
+}
+
+impl<D: Decoder> Skipped<D> {
+    /// Wraps `inner`, skipping its first `frames` frames.
+    pub fn new(inner: D, frames: usize) -> Self {
+        Skipped {
+            inner,
+            remaining: frames,
+        }
+    }
+
+    /// Wraps the decoders of a reference and a distorted video, skipping the
+    /// leading frames of one of them according to an offset returned by
+    /// [`detect_frame_offset`].
+    pub fn pair(decoder1: D, decoder2: D, offset: isize) -> (Self, Self) {
+        if offset >= 0 {
+            (
+                Skipped::new(decoder1, 0),
+                Skipped::new(decoder2, offset as usize),
+            )
+        } else {
+            (
+                Skipped::new(decoder1, offset.unsigned_abs()),
+                Skipped::new(decoder2, 0),
+            )
+        }
+    }
+
+    /// Returns the wrapped decoder.
+    pub fn into_inner(self) -> D {
+        self.inner
+    }
+
+    fn skip<T: Pixel>(&mut self) {
+        while self.remaining > 0 {
+            self.remaining -= 1;
+            if self.inner.read_video_frame::<T>().is_none() {
+                self.remaining = 0;
+            }
+        }
+    }
+}
+
+impl<D: Decoder> Decoder for Skipped<D> {
+    fn read_video_frame<T: Pixel>(&mut self) -> Option<Frame<T>> {
+        self.skip::<T>();
+        self.inner.read_video_frame()
+    }
+
+    fn read_video_frame_into<T: Pixel>(&mut self, frame: &mut Frame<T>) -> bool {
+        self.skip::<T>();
+        self.inner.read_video_frame_into(frame)
+    }
+
+    fn read_video_frame_with_alpha<T: Pixel>(&mut self) -> Option<(Frame<T>, Option<Plane<T>>)> {
+        self.skip::<T>();
+        self.inner.read_video_frame_with_alpha()
+    }
+
+    fn get_bit_depth(&self) -> usize {
+        self.inner.get_bit_depth()
+    }
+
+    fn get_stream_info(&self) -> StreamInfo {
+        self.inner.get_stream_info()
+    }
+
+    fn last_frame_meta(&self) -> Option<FrameMeta> {
+        self.inner.last_frame_meta()
+    }
+
+    fn last_side_data(&self) -> Option<SideData> {
+        self.inner.last_side_data()
+    }
+
+    fn capabilities(&self) -> DecoderCapabilities {
+        DecoderCapabilities {
+            metadata: self.inner.capabilities().metadata,
+            ..Default::default()
+        }
+    }
+
+    fn get_video_details(&self) -> VideoDetails {
+        self.inner.get_video_details()
+    }
+}
+
+/// A global translation of a video, in luma pixels.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Shift {
+    /// The horizontal shift. Positive if the picture moved to the right.
+    pub x: isize,
+    /// The vertical shift. Positive if the picture moved down.
+    pub y: isize,
+}
+
+impl fmt::Display for Shift {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:+}x{:+}", self.x, self.y)
+    }
+}
+
+/// Estimates the global translation of `distorted` relative to `reference`
+/// by phase correlation of their luma planes.
+///
+/// The correlation is computed on a centered square window of up to 256x256
+/// pixels, and only shifts of up to `max_shift` pixels in each direction are
+/// considered. The result can be compensated with [`Shifted`] or
+/// [`shift_frame`].
+pub fn detect_shift<T: Pixel>(
+    reference: &Frame<T>,
+    distorted: &Frame<T>,
+    max_shift: usize,
+) -> Result<Shift, Box<dyn Error>> {
+    let plane1 = PlaneRef::from(&reference.planes[0]);
+    let plane2 = PlaneRef::from(&distorted.planes[0]);
+    if plane1.width() != plane2.width() || plane1.height() != plane2.height() {
+        return Err(Box::new(MetricsError::InputMismatch {
+            reason: "Resolutions do not match",
+        }));
+    }
+    let smallest = plane1.width().min(plane1.height()).min(PHASE_WINDOW_SIZE);
+    if smallest < 8 {
+        return Err(Box::new(MetricsError::UnsupportedInput {
+            reason: "Frames are too small to detect a shift",
+        }));
+    }
+    let size = 1 << smallest.ilog2();
+    let max_shift = max_shift.min(size / 2 - 1) as isize;
+
+    let mut spectrum1 = windowed_luma(plane1, size);
+    let mut spectrum2 = windowed_luma(plane2, size);
+    fft_2d(&mut spectrum1, size, false);
+    fft_2d(&mut spectrum2, size, false);
+    // The normalized cross-power spectrum, whose inverse transform peaks at
+    // the translation between the two windows.
+    let mut cross: Vec<Complex> = spectrum1
+        .iter()
+        .zip(spectrum2.iter())
+        .map(|(a, b)| {
+            let product = a.conj().mul(*b);
+            let norm = product.norm();
+            if norm > f64::EPSILON {
+                product.scale(1.0 / norm)
+            } else {
+                Complex::default()
+            }
+        })
+        .collect();
+    fft_2d(&mut cross, size, true);
+
+    let mut best = (Shift::default(), f64::MIN);
+    for y in -max_shift..=max_shift {
+        for x in -max_shift..=max_shift {
+            let row = y.rem_euclid(size as isize) as usize;
+            let col = x.rem_euclid(size as isize) as usize;
+            let value = cross[row * size + col].re;
+            let closer = x.abs() + y.abs() < best.0.x.abs() + best.0.y.abs();
+            if value > best.1 || (value == best.1 && closer) {
+                best = (Shift { x, y }, value);
+            }
+        }
+    }
+    Ok(best.0)
+}
+
+/// Estimates the global translation of a distorted video relative to a
+/// reference on their first frames. See [`detect_shift`].
+///
+/// The first frame of both decoders is consumed.
+pub fn detect_video_shift<D: Decoder>(
+    decoder1: &mut D,
+    decoder2: &mut D,
+    max_shift: usize,
+) -> Result<Shift, Box<dyn Error>> {
+    if decoder1.get_bit_depth() != decoder2.get_bit_depth() {
+        return Err(Box::new(MetricsError::InputMismatch {
+            reason: "Bit depths do not match",
+        }));
+    }
+    if decoder1.get_bit_depth() > 8 {
+        detect_first_frame_shift::<_, u16>(decoder1, decoder2, max_shift)
+    } else {
+        detect_first_frame_shift::<_, u8>(decoder1, decoder2, max_shift)
+    }
+}
+
+fn detect_first_frame_shift<D: Decoder, T: Pixel>(
+    decoder1: &mut D,
+    decoder2: &mut D,
+    max_shift: usize,
+) -> Result<Shift, Box<dyn Error>> {
+    match (
+        decoder1.read_video_frame::<T>(),
+        decoder2.read_video_frame::<T>(),
+    ) {
+        (Some(frame1), Some(frame2)) => detect_shift(&frame1, &frame2, max_shift),
+        _ => Err(Box::new(MetricsError::UnsupportedInput {
+            reason: "Not enough frames to detect a shift",
+        })),
+    }
+}
+
+/// Returns a copy of `frame` translated by `-shift`, undoing a shift found
+/// by [`detect_shift`]. Pixels moved in from outside the frame repeat the
+/// nearest edge.
+///
+/// The shift of subsampled chroma planes is rounded to the nearest pixel.
+pub fn shift_frame<T: Pixel>(frame: &Frame<T>, shift: Shift) -> Frame<T> {
+    let mut out = frame.clone();
+    for (src, dst) in frame.planes.iter().zip(out.planes.iter_mut()) {
+        let x = shift.x as f64 / (1 << src.cfg.xdec) as f64;
+        let y = shift.y as f64 / (1 << src.cfg.ydec) as f64;
+        shift_plane(src, dst, x.round() as isize, y.round() as isize);
+    }
+    out
+}
+
+fn shift_plane<T: Pixel>(src: &Plane<T>, dst: &mut Plane<T>, x: isize, y: isize) {
+    let src = PlaneRef::from(src);
+    let (width, height) = (src.width() as isize, src.height() as isize);
+    if width == 0 || height == 0 {
+        return;
+    }
+    for (row, out_row) in dst.rows_iter_mut().enumerate() {
+        let src_row = src.row((row as isize + y).clamp(0, height - 1) as usize);
+        for (col, out) in out_row[..width as usize].iter_mut().enumerate() {
+            *out = src_row[(col as isize + x).clamp(0, width - 1) as usize];
+        }
+    }
+}
+
+/// A decoder that translates every frame of another decoder to undo a
+/// global shift found by [`detect_shift`].
+pub struct Shifted<D: Decoder> {
+    inner: D,
+    shift: Shift,
+}
+
+impl<D: Decoder> Shifted<D> {
+    /// Wraps `inner`, translating its frames by `-shift`.
+    pub fn new(inner: D, shift: Shift) -> Self {
+        Shifted { inner, shift }
+    }
+
+    /// The shift that is undone.
+    pub fn shift(&self) -> Shift {
+        self.shift
+    }
+
+    /// Returns the wrapped decoder.
+    pub fn into_inner(self) -> D {
+        self.inner
+    }
+}
+
+impl<D: Decoder> Decoder for Shifted<D> {
+    fn read_video_frame<T: Pixel>(&mut self) -> Option<Frame<T>> {
+        let frame = self.inner.read_video_frame()?;
+        if self.shift == Shift::default() {
+            return Some(frame);
+        }
+        Some(shift_frame(&frame, self.shift))
+    }
+
+    fn read_video_frame_with_alpha<T: Pixel>(&mut self) -> Option<(Frame<T>, Option<Plane<T>>)> {
+        let (frame, alpha) = self.inner.read_video_frame_with_alpha()?;
+        if self.shift == Shift::default() {
+            return Some((frame, alpha));
+        }
+        let alpha = alpha.map(|alpha| {
+            let mut out = alpha.clone();
+            shift_plane(&alpha, &mut out, self.shift.x, self.shift.y);
+            out
+        });
+        Some((shift_frame(&frame, self.shift), alpha))
+    }
+
+    fn get_bit_depth(&self) -> usize {
+        self.inner.get_bit_depth()
+    }
+
+    fn get_stream_info(&self) -> StreamInfo {
+        self.inner.get_stream_info()
+    }
+
+    fn last_frame_meta(&self) -> Option<FrameMeta> {
+        self.inner.last_frame_meta()
+    }
+
+    fn last_side_data(&self) -> Option<SideData> {
+        self.inner.last_side_data()
+    }
+
+    fn capabilities(&self) -> DecoderCapabilities {
+        DecoderCapabilities {
+            seek: false,
+            ..self.inner.capabilities()
+        }
+    }
+
+    fn frame_count(&self) -> Option<usize> {
+        self.inner.frame_count()
+    }
+
+    fn build_index(&mut self) -> Option<usize> {
+        self.inner.build_index()
+    }
+
+    fn get_video_details(&self) -> VideoDetails {
+        self.inner.get_video_details()
+    }
+}
+
+/// Copies the centered `size` x `size` window of a luma plane, with its mean
+/// removed and a Hann window applied to reduce the effect of the edges.
+fn windowed_luma<T: Pixel>(plane: PlaneRef<'_, T>, size: usize) -> Vec<Complex> {
+    let left = (plane.width() - size) / 2;
+    let top = (plane.height() - size) / 2;
+    let mut values: Vec<f64> = (top..top + size)
+        .flat_map(|y| plane.row(y)[left..left + size].iter())
+        .map(|&pixel| f64::from(i32::cast_from(pixel)))
+        .collect();
+    let mean = values.iter().sum::<f64>() / values.len() as f64;
+    let hann: Vec<f64> = (0..size)
+        .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f64 / size as f64).cos())
+        .collect();
+    for (i, value) in values.iter_mut().enumerate() {
+        *value = (*value - mean) * hann[i / size] * hann[i % size];
+    }
+    values
+        .into_iter()
+        .map(|re| Complex { re, im: 0.0 })
+        .collect()
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Complex {
+    re: f64,
+    im: f64,
+}
+
+impl Complex {
+    fn add(self, other: Self) -> Self {
+        Complex {
+            re: self.re + other.re,
+            im: self.im + other.im,
+        }
+    }
+
+    fn sub(self, other: Self) -> Self {
+        Complex {
+            re: self.re - other.re,
+            im: self.im - other.im,
+        }
+    }
+
+    fn mul(self, other: Self) -> Self {
+        Complex {
+            re: self.re * other.re - self.im * other.im,
+            im: self.re * other.im + self.im * other.re,
+        }
+    }
+
+    fn scale(self, factor: f64) -> Self {
+        Complex {
+            re: self.re * factor,
+            im: self.im * factor,
+        }
+    }
+
+    fn conj(self) -> Self {
+        Complex {
+            re: self.re,
+            im: -self.im,
+        }
+    }
+
+    fn norm(self) -> f64 {
+        self.re.hypot(self.im)
+    }
+}
+
+/// An in-place two dimensional FFT of a `size` x `size` row-major array.
+/// The inverse transform is not normalized, since only the location of its
+/// peak is used.
+fn fft_2d(data: &mut [Complex], size: usize, inverse: bool) {
+    for row in data.chunks_exact_mut(size) {
+        fft(row, inverse);
+    }
+    let mut column = vec![Complex::default(); size];
+    for x in 0..size {
+        for y in 0..size {
+            column[y] = data[y * size + x];
+        }
+        fft(&mut column, inverse);
+        for y in 0..size {
+            data[y * size + x] = column[y];
+        }
+    }
+}
+
+/// An in-place iterative radix-2 FFT. The length must be a power of two.
+fn fft(data: &mut [Complex], inverse: bool) {
+    let n = data.len();
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            data.swap(i, j);
+        }
+    }
+    let sign = if inverse { 1.0 } else { -1.0 };
+    let mut len = 2;
+    while len <= n {
+        let angle = sign * 2.0 * PI / len as f64;
+        let step = Complex {
+            re: angle.cos(),
+            im: angle.sin(),
+        };
+        for chunk in data.chunks_exact_mut(len) {
+            let mut twiddle = Complex { re: 1.0, im: 0.0 };
+            let (low, high) = chunk.split_at_mut(len / 2);
+            for (a, b) in low.iter_mut().zip(high.iter_mut()) {
+                let t = b.mul(twiddle);
+                *b = a.sub(t);
+                *a = a.add(t);
+                twiddle = twiddle.mul(step);
+            }
+        }
+        len <<= 1;
+    }
+}
@@ -0,0 +1,52 @@
+//! All frame metrics at once.
+//!
+//! For a quick quality snapshot of a single pair of frames,
+//! [`calculate_all_frame_metrics`] computes every full-reference metric
+//! that works on a single frame, checking the frames only once.
+
+use crate::video::ciede::calculate_frame_ciede;
+use crate::video::pixel::Pixel;
+use crate::video::psnr::calculate_frame_psnr;
+use crate::video::psnr_hvs::calculate_frame_psnr_hvs;
+use crate::video::ssim::{calculate_frame_msssim, calculate_frame_ssim};
+use crate::video::{FrameContext, PlanarMetrics};
+use std::error::Error;
+use v_frame::frame::Frame;
+
+/// The scores of every metric computed by [`calculate_all_frame_metrics`].
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AllMetrics {
+    /// Peak Signal-to-Noise Ratio.
+    pub psnr: PlanarMetrics,
+    /// PSNR weighted by the Human Visual System.
+    pub psnr_hvs: PlanarMetrics,
+    /// Structural Similarity.
+    pub ssim: PlanarMetrics,
+    /// Multi-Scale Structural Similarity.
+    pub msssim: PlanarMetrics,
+    /// CIEDE2000 color difference.
+    pub ciede: f64,
+}
+
+/// Calculates PSNR, PSNR-HVS, SSIM, MS-SSIM and CIEDE2000 between two video
+/// frames. Higher is better for all of them.
+///
+/// The frames are validated against `ctx` once, before any metric is computed.
+pub fn calculate_all_frame_metrics<T: Pixel>(
+    frame1: &Frame<T>,
+    frame2: &Frame<T>,
+    ctx: FrameContext,
+) -> Result<AllMetrics, Box<dyn Error>> {
+    ctx.validate(frame1, frame2)?;
+    let bit_depth = ctx.bit_depth();
+    let chroma_sampling = ctx.chroma_sampling();
+
+    Ok(AllMetrics {
+        psnr: calculate_frame_psnr(frame1, frame2, bit_depth, chroma_sampling)?,
+        psnr_hvs: calculate_frame_psnr_hvs(frame1, frame2, bit_depth, chroma_sampling)?,
+        ssim: calculate_frame_ssim(frame1, frame2, bit_depth, chroma_sampling)?,
+        msssim: calculate_frame_msssim(frame1, frame2, bit_depth, chroma_sampling)?,
+        ciede: calculate_frame_ciede(frame1, frame2, bit_depth, chroma_sampling)?,
+    })
+}
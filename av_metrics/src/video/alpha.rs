@@ -0,0 +1,98 @@
+//! Comparison of alpha planes.
+//!
+//! Videos with transparency, such as screen content, carry an alpha plane
+//! next to the three color planes. Decoders that support it return the alpha
+//! plane from [`Decoder::read_video_frame_with_alpha`].
+//!
+//! [`AlphaPlane`] wraps a decoder so that its alpha plane is read as the luma
+//! plane of each frame. Any metric computed on two wrapped decoders then
+//! scores the alpha planes in its `y` value, which can be stored in the
+//! `alpha` field of the results for the color planes:
+//!
+//! ```no_run
+//! # use av_metrics::video::alpha::AlphaPlane;
+//! # use av_metrics::video::decode::Decoder;
+//! # use av_metrics::video::psnr::calculate_video_psnr;
+//! # use std::error::Error;
+//! # fn psnr_with_alpha<D: Decoder>(
+//! #     open: impl Fn() -> (D, D),
+//! # ) -> Result<(), Box<dyn Error>> {
+//! let (mut dec1, mut dec2) = open();
+//! let mut psnr = calculate_video_psnr(&mut dec1, &mut dec2, None, |_| ())?;
+//! let (dec1, dec2) = open();
+//! let alpha = calculate_video_psnr(
+//!     &mut AlphaPlane::new(dec1),
+//!     &mut AlphaPlane::new(dec2),
+//!     None,
+//!     |_| (),
+//! )?;
+//! psnr.alpha = Some(alpha.y);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::video::decode::{Decoder, DecoderCapabilities, FrameMeta, StreamInfo, VideoDetails};
+use crate::video::pixel::Pixel;
+use crate::video::side_data::SideData;
+use v_frame::frame::Frame;
+
+/// A decoder that returns the alpha plane of another decoder as the luma
+/// plane of its frames.
+///
+/// The chroma planes of the frames are cleared, so only the `y` value of a
+/// metric computed on them is meaningful. The video ends at the first frame
+/// without an alpha plane, so a video without an alpha channel has no frames
+/// at all.
+pub struct AlphaPlane<D: Decoder> {
+    inner: D,
+}
+
+impl<D: Decoder> AlphaPlane<D> {
+    /// Wraps `inner`, reading its alpha plane instead of its color planes.
+    pub fn new(inner: D) -> Self {
+        AlphaPlane { inner }
+    }
+
+    /// Returns the wrapped decoder.
+    pub fn into_inner(self) -> D {
+        self.inner
+    }
+}
+
+impl<D: Decoder> Decoder for AlphaPlane<D> {
+    fn read_video_frame<T: Pixel>(&mut self) -> Option<Frame<T>> {
+        let (mut frame, alpha) = self.inner.read_video_frame_with_alpha::<T>()?;
+        frame.planes[0] = alpha?;
+        for plane in frame.planes[1..].iter_mut() {
+            plane.data.fill(T::cast_from(0u8));
+        }
+        Some(frame)
+    }
+
+    fn get_bit_depth(&self) -> usize {
+        self.inner.get_bit_depth()
+    }
+
+    fn get_stream_info(&self) -> StreamInfo {
+        self.inner.get_stream_info()
+    }
+
+    fn last_frame_meta(&self) -> Option<FrameMeta> {
+        self.inner.last_frame_meta()
+    }
+
+    fn last_side_data(&self) -> Option<SideData> {
+        self.inner.last_side_data()
+    }
+
+    fn capabilities(&self) -> DecoderCapabilities {
+        DecoderCapabilities {
+            metadata: self.inner.capabilities().metadata,
+            ..Default::default()
+        }
+    }
+
+    fn get_video_details(&self) -> VideoDetails {
+        self.inner.get_video_details()
+    }
+}
@@ -0,0 +1,214 @@
+//! No-reference indicators of coding artifacts.
+//!
+//! These look for the two most common artifacts of block-based codecs in a
+//! single video, without a base to compare it to:
+//!
+//! - Blockiness compares the luma differences across the boundaries of 8x8
+//!   blocks to those inside the blocks. It is about 1 when the boundaries are
+//!   invisible and grows as blocking artifacts appear.
+//! - Blurriness is the mean width of the vertical edges of the luma plane, in
+//!   pixels, measured between the extrema on either side of each edge as
+//!   described by Marziliano et al. It grows as edges are smeared by strong
+//!   quantization or deblocking.
+//!
+//! Both are cheap to compute and only meant to triage encodes; their values
+//! depend on the content and should only be compared between encodes of the
+//! same source.
+
+use crate::video::decode::{Decoder, PlaneRef};
+use crate::video::pixel::CastFromPrimitive;
+use crate::video::pixel::Pixel;
+use crate::video::FrameContext;
+use crate::{MetricsError, PROGRESS_DONE};
+use std::error::Error;
+use v_frame::frame::Frame;
+use v_frame::prelude::ChromaSampling;
+
+/// The size of the blocks whose boundaries are checked for blocking artifacts.
+const BLOCK_SIZE: usize = 8;
+
+/// The smallest luma difference between the two neighbours of a pixel, on a
+/// scale of 8-bit samples, for it to be part of an edge.
+const EDGE_THRESHOLD: f64 = 16.0;
+
+/// The artifact indicators of one frame.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ArtifactScores {
+    /// The ratio of the mean luma difference across block boundaries to that
+    /// inside the blocks. Differences under one 8-bit step count as one step,
+    /// so flat frames score 1. Lower is better.
+    pub blockiness: f64,
+    /// The mean width of the vertical edges, in pixels, or 0 if the frame has
+    /// no edges. Lower is better.
+    pub blurriness: f64,
+}
+
+/// The artifact indicators of a video.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ArtifactResults {
+    /// The mean indicators of all frames.
+    pub mean: ArtifactScores,
+    /// The highest value of each indicator over all frames.
+    pub max: ArtifactScores,
+    /// The indicators of each frame.
+    pub frames: Vec<ArtifactScores>,
+}
+
+impl ArtifactResults {
+    fn new(frames: Vec<ArtifactScores>) -> Self {
+        let n = frames.len() as f64;
+        let mut mean = ArtifactScores::default();
+        let mut max = ArtifactScores::default();
+        for scores in frames.iter() {
+            mean.blockiness += scores.blockiness / n;
+            mean.blurriness += scores.blurriness / n;
+            max.blockiness = max.blockiness.max(scores.blockiness);
+            max.blurriness = max.blurriness.max(scores.blurriness);
+        }
+        ArtifactResults { mean, max, frames }
+    }
+}
+
+/// Calculates the artifact indicators of every frame of a video.
+pub fn calculate_video_artifacts<D: Decoder, F: Fn(usize) + Send>(
+    decoder: &mut D,
+    frame_limit: Option<usize>,
+    progress_callback: F,
+) -> Result<ArtifactResults, Box<dyn Error>> {
+    if decoder.get_bit_depth() > 8 {
+        process_video::<D, u16, F>(decoder, frame_limit, progress_callback)
+    } else {
+        process_video::<D, u8, F>(decoder, frame_limit, progress_callback)
+    }
+}
+
+/// Calculates the artifact indicators of one frame, on a scale of 8-bit
+/// samples regardless of the bit depth.
+pub fn calculate_frame_artifacts<T: Pixel>(
+    frame: &Frame<T>,
+    bit_depth: usize,
+) -> Result<ArtifactScores, Box<dyn Error>> {
+    // Only the luma plane is used
+    FrameContext::new(bit_depth, ChromaSampling::Cs400)?;
+    let plane = PlaneRef::from(&frame.planes[0]);
+    let scale = (1 << (bit_depth - 8)) as f64;
+    Ok(ArtifactScores {
+        blockiness: blockiness(plane, scale),
+        blurriness: blurriness(plane, scale),
+    })
+}
+
+fn process_video<D: Decoder, P: Pixel, F: Fn(usize) + Send>(
+    decoder: &mut D,
+    frame_limit: Option<usize>,
+    progress_callback: F,
+) -> Result<ArtifactResults, Box<dyn Error>> {
+    let bit_depth = decoder.get_bit_depth();
+    let mut frames = Vec::new();
+    while frame_limit
+        .map(|limit| limit > frames.len())
+        .unwrap_or(true)
+    {
+        let Some(frame) = decoder.read_video_frame::<P>() else {
+            break;
+        };
+        frames.push(calculate_frame_artifacts(&frame, bit_depth)?);
+        progress_callback(frames.len());
+    }
+    progress_callback(PROGRESS_DONE);
+
+    if frames.is_empty() {
+        return Err(MetricsError::UnsupportedInput {
+            reason: "No readable frames found in the input",
+        }
+        .into());
+    }
+    Ok(ArtifactResults::new(frames))
+}
+
+fn sample<T: Pixel>(row: &[T], x: usize) -> f64 {
+    f64::from(i32::cast_from(row[x]))
+}
+
+/// The ratio of the mean absolute differences between horizontal and
+/// vertical neighbours across block boundaries to those inside the blocks.
+fn blockiness<T: Pixel>(plane: PlaneRef<'_, T>, scale: f64) -> f64 {
+    let (width, height) = (plane.width(), plane.height());
+    // (sum, count) of the differences across and inside block boundaries
+    let mut boundary = (0.0, 0usize);
+    let mut inner = (0.0, 0usize);
+    let mut add = |x_or_y: usize, diff: f64| {
+        let sums = if x_or_y.is_multiple_of(BLOCK_SIZE) {
+            &mut boundary
+        } else {
+            &mut inner
+        };
+        sums.0 += diff.abs();
+        sums.1 += 1;
+    };
+    for y in 0..height {
+        let row = plane.row(y);
+        for x in 1..width {
+            add(x, sample(row, x) - sample(row, x - 1));
+        }
+        if y > 0 {
+            let above = plane.row(y - 1);
+            for x in 0..width {
+                add(y, sample(row, x) - sample(above, x));
+            }
+        }
+    }
+
+    let mean = |(sum, count): (f64, usize)| {
+        if count > 0 {
+            (sum / count as f64 / scale).max(1.0)
+        } else {
+            1.0
+        }
+    };
+    mean(boundary) / mean(inner)
+}
+
+/// The mean width of the vertical edges, from the extremum before each edge
+/// pixel to the extremum after it along its row.
+fn blurriness<T: Pixel>(plane: PlaneRef<'_, T>, scale: f64) -> f64 {
+    let width = plane.width();
+    if width < 3 {
+        return 0.0;
+    }
+    let threshold = EDGE_THRESHOLD * scale;
+    let mut total = 0usize;
+    let mut edges = 0usize;
+    for row in plane.rows() {
+        let gradient = |x: usize| sample(row, x + 1) - sample(row, x - 1);
+        for x in 1..width - 1 {
+            let g = gradient(x);
+            // Only the steepest pixel of each edge is measured
+            let steepest = (x < 2 || gradient(x - 1).abs() < g.abs())
+                && (x + 2 >= width || gradient(x + 1).abs() <= g.abs());
+            if g.abs() < threshold || !steepest {
+                continue;
+            }
+            let rising = g > 0.0;
+            let towards_extremum = |a: f64, b: f64| if rising { b < a } else { b > a };
+            let mut left = x;
+            while left > 0 && towards_extremum(sample(row, left), sample(row, left - 1)) {
+                left -= 1;
+            }
+            let mut right = x;
+            while right + 1 < width && towards_extremum(sample(row, right + 1), sample(row, right))
+            {
+                right += 1;
+            }
+            total += right - left;
+            edges += 1;
+        }
+    }
+    if edges > 0 {
+        total as f64 / edges as f64
+    } else {
+        0.0
+    }
+}
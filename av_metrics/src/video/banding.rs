@@ -0,0 +1,354 @@
+//! Coverage of the regions prone to banding.
+//!
+//! Banding appears in smooth gradients, such as skies or shaded walls, where
+//! a coarse quantizer replaces the gradient with visible steps. Metrics such
+//! as CAMBI estimate how visible the steps are; this module reports how much
+//! of each frame is at risk in the first place, and scores the compressed
+//! frames in those regions alone, where the averages over the whole frame
+//! would dilute the errors.
+//!
+//! The base frames are divided into square blocks, and a block is prone to
+//! banding when no two neighbouring luma samples in it differ by more than
+//! [`BandingConfig::max_gradient`]. Flat blocks count as well, since a
+//! gradient too slow to change between neighbours is flat over a block.
+
+use crate::video::decode::Decoder;
+use crate::video::pixel::CastFromPrimitive;
+use crate::video::pixel::Pixel;
+use crate::video::psnr::{calculate_plane_psnr_metrics, planar_psnr, PsnrMetrics};
+use crate::video::tiles::region;
+use crate::video::{
+    CompatMode, FrameContext, PlanarMetrics, PlaneSelect, PlaneWeights, VideoMetric, VideoOptions,
+};
+use crate::MetricsError;
+use std::error::Error;
+use std::ops::Range;
+use std::sync::Mutex;
+use v_frame::frame::Frame;
+use v_frame::plane::Plane;
+use v_frame::prelude::ChromaSampling;
+
+/// How the regions prone to banding are found.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BandingConfig {
+    /// The width and height of the blocks, in luma pixels. Must be at least 2.
+    pub block_size: usize,
+    /// The largest difference between neighbouring luma samples of a block
+    /// prone to banding, on a scale of 8-bit samples regardless of the bit
+    /// depth.
+    pub max_gradient: f64,
+}
+
+impl Default for BandingConfig {
+    /// Blocks of 16x16 pixels whose neighbours differ by at most one 8-bit
+    /// step.
+    fn default() -> Self {
+        BandingConfig {
+            block_size: 16,
+            max_gradient: 1.0,
+        }
+    }
+}
+
+/// The regions prone to banding of one frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BandingFrame {
+    /// The percentage of the luma pixels of the frame in blocks prone to
+    /// banding.
+    pub coverage: f64,
+    /// The PSNR of the blocks prone to banding, or `None` if the frame has
+    /// none. Higher is better.
+    pub psnr: Option<PlanarMetrics>,
+}
+
+/// The regions prone to banding of a video.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BandingResults {
+    /// The mean percentage of the luma pixels of each frame in blocks prone
+    /// to banding.
+    pub coverage: f64,
+    /// The PSNR of the blocks prone to banding of all frames, or `None` if no
+    /// frame has any. Higher is better.
+    pub psnr: Option<PlanarMetrics>,
+    /// The regions of each frame.
+    pub frames: Vec<BandingFrame>,
+}
+
+/// Finds the regions prone to banding in every frame of the base video
+/// `decoder1`, and calculates the PSNR of `decoder2` in those regions.
+///
+/// Like [`calculate_video_psnr`], PSNR is capped at 100.
+///
+/// [`calculate_video_psnr`]: crate::video::psnr::calculate_video_psnr
+#[inline]
+pub fn calculate_video_banding<D: Decoder, F: Fn(usize) + Send>(
+    decoder1: &mut D,
+    decoder2: &mut D,
+    frame_limit: Option<usize>,
+    progress_callback: F,
+) -> Result<BandingResults, Box<dyn Error>> {
+    calculate_video_banding_with(
+        decoder1,
+        decoder2,
+        BandingOptions {
+            video: VideoOptions::limited(frame_limit, progress_callback),
+            config: BandingConfig::default(),
+        },
+    )
+}
+
+/// Finds the regions prone to banding in every frame of the base video and
+/// scores the other video in those regions, with the given options.
+///
+/// See [`BandingOptions`] for the available options.
+#[inline]
+pub fn calculate_video_banding_with<D: Decoder, F: Fn(usize) + Send>(
+    decoder1: &mut D,
+    decoder2: &mut D,
+    options: BandingOptions<F>,
+) -> Result<BandingResults, Box<dyn Error>> {
+    let config = options.config;
+    options.video.run(|limits, progress_callback| {
+        Banding { config }.process_video(decoder1, decoder2, limits, progress_callback)
+    })
+}
+
+/// Finds the regions prone to banding in the base frame `frame1`, and
+/// calculates the PSNR of `frame2` in those regions.
+#[inline]
+pub fn calculate_frame_banding<T: Pixel>(
+    frame1: &Frame<T>,
+    frame2: &Frame<T>,
+    bit_depth: usize,
+    chroma_sampling: ChromaSampling,
+    config: BandingConfig,
+) -> Result<BandingFrame, Box<dyn Error>> {
+    let metrics = Banding { config }.process_frame(frame1, frame2, bit_depth, chroma_sampling)?;
+    Ok(metrics.frame())
+}
+
+/// Finds the regions prone to banding in frame pairs given one at a time, such
+/// as those given to a [`FrameSink`], so that they are found in the same pass
+/// as another metric instead of decoding the videos again.
+///
+/// The frame pairs may be pushed from several threads and in any order.
+///
+/// [`FrameSink`]: crate::video::FrameSink
+#[derive(Debug)]
+pub struct BandingAggregator {
+    metric: Banding,
+    frames: Mutex<Vec<(usize, FrameBanding)>>,
+}
+
+impl BandingAggregator {
+    /// Creates an aggregator with no frames, finding the regions with
+    /// `config`.
+    pub fn new(config: BandingConfig) -> Self {
+        BandingAggregator {
+            metric: Banding { config },
+            frames: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Finds the regions prone to banding of frame pair number `frame`, where
+    /// `frame1` is the base frame.
+    pub fn push<T: Pixel>(
+        &self,
+        frame: usize,
+        frame1: &Frame<T>,
+        frame2: &Frame<T>,
+        context: FrameContext,
+    ) -> Result<(), Box<dyn Error>> {
+        let banding = self.metric.process_frame(
+            frame1,
+            frame2,
+            context.bit_depth(),
+            context.chroma_sampling(),
+        )?;
+        self.frames.lock().unwrap().push((frame, banding));
+        Ok(())
+    }
+
+    /// The regions prone to banding of the frames pushed so far.
+    pub fn finish(self) -> Result<BandingResults, Box<dyn Error>> {
+        let mut frames = self.frames.into_inner().unwrap();
+        frames.sort_by_key(|&(frame, _)| frame);
+        let frames: Vec<_> = frames.into_iter().map(|(_, banding)| banding).collect();
+        self.metric.aggregate_frame_results(&frames)
+    }
+}
+
+/// Options for finding the regions prone to banding over whole videos, built
+/// with chained calls:
+///
+/// ```
+/// use av_metrics::video::banding::BandingOptions;
+///
+/// let options = BandingOptions::new().frame_limit(100).block_size(32);
+/// ```
+#[derive(Debug, Clone)]
+pub struct BandingOptions<F = fn(usize)> {
+    /// The frame limit, thread count and progress callback.
+    pub video: VideoOptions<F>,
+    /// How the regions prone to banding are found.
+    pub config: BandingConfig,
+}
+
+video_options!(BandingOptions);
+
+impl<F> BandingOptions<F> {
+    /// Divides the frames into blocks of `block_size` by `block_size` pixels.
+    pub fn block_size(mut self, block_size: usize) -> Self {
+        self.config.block_size = block_size;
+        self
+    }
+
+    /// Sets the largest difference between neighbouring luma samples, on a
+    /// scale of 8-bit samples, of a block prone to banding.
+    pub fn max_gradient(mut self, max_gradient: f64) -> Self {
+        self.config.max_gradient = max_gradient;
+        self
+    }
+}
+
+#[derive(Debug)]
+struct Banding {
+    config: BandingConfig,
+}
+
+/// The squared errors in the regions prone to banding of one frame.
+#[derive(Debug, Clone, Copy)]
+struct FrameBanding {
+    covered: usize,
+    pixels: usize,
+    psnr: [PsnrMetrics; 3],
+}
+
+impl FrameBanding {
+    fn frame(&self) -> BandingFrame {
+        BandingFrame {
+            coverage: 100.0 * self.covered as f64 / self.pixels as f64,
+            psnr: psnr(&self.psnr),
+        }
+    }
+}
+
+/// The PSNR of the squared errors, if there are any samples.
+fn psnr(metrics: &[PsnrMetrics; 3]) -> Option<PlanarMetrics> {
+    (metrics[0].n_pixels > 0).then(|| {
+        planar_psnr(
+            metrics,
+            PlaneSelect::ALL,
+            PlaneWeights::Default,
+            CompatMode::Native,
+        )
+    })
+}
+
+impl VideoMetric for Banding {
+    type FrameResult = FrameBanding;
+    type VideoResult = BandingResults;
+
+    fn process_frame<T: Pixel>(
+        &self,
+        frame1: &Frame<T>,
+        frame2: &Frame<T>,
+        bit_depth: usize,
+        chroma_sampling: ChromaSampling,
+    ) -> Result<Self::FrameResult, Box<dyn Error>> {
+        FrameContext::new(bit_depth, chroma_sampling)?.validate(frame1, frame2)?;
+        let BandingConfig {
+            block_size,
+            max_gradient,
+        } = self.config;
+        if block_size < 2 {
+            return Err(Box::new(MetricsError::UnsupportedInput {
+                reason: "The banding block size must be at least 2 pixels",
+            }));
+        }
+        let max_gradient = max_gradient * (1 << (bit_depth - 8)) as f64;
+        let luma = &frame1.planes[0];
+        let (width, height) = (luma.cfg.width, luma.cfg.height);
+
+        let mut covered = 0;
+        let mut errors = [PsnrMetrics::default(); 3];
+        for y in (0..height).step_by(block_size) {
+            for x in (0..width).step_by(block_size) {
+                let (x, y) = (
+                    x..(x + block_size).min(width),
+                    y..(y + block_size).min(height),
+                );
+                if !is_low_gradient(luma, x.clone(), y.clone(), max_gradient) {
+                    continue;
+                }
+                covered += x.len() * y.len();
+                for (p, sums) in errors.iter_mut().enumerate() {
+                    let (plane1, plane2) = (&frame1.planes[p], &frame2.planes[p]);
+                    let x = subsampled(x.clone(), plane1.cfg.xdec, plane1.cfg.width);
+                    let y = subsampled(y.clone(), plane1.cfg.ydec, plane1.cfg.height);
+                    let block = calculate_plane_psnr_metrics(
+                        &region(plane1, x.clone(), y.clone()),
+                        &region(plane2, x, y),
+                        bit_depth,
+                    );
+                    *sums = sums.add(block);
+                }
+            }
+        }
+        Ok(FrameBanding {
+            covered,
+            pixels: width * height,
+            psnr: errors,
+        })
+    }
+
+    fn aggregate_frame_results(
+        &self,
+        metrics: &[Self::FrameResult],
+    ) -> Result<Self::VideoResult, Box<dyn Error>> {
+        let frames: Vec<BandingFrame> = metrics.iter().map(FrameBanding::frame).collect();
+        let coverage = if frames.is_empty() {
+            0.0
+        } else {
+            frames.iter().map(|frame| frame.coverage).sum::<f64>() / frames.len() as f64
+        };
+        let summed = metrics
+            .iter()
+            .fold([PsnrMetrics::default(); 3], |acc, frame| {
+                [0, 1, 2].map(|p| acc[p].add(frame.psnr[p]))
+            });
+        Ok(BandingResults {
+            coverage,
+            psnr: psnr(&summed),
+            frames,
+        })
+    }
+}
+
+/// Whether no two neighbouring samples of `plane` in the given ranges differ
+/// by more than `max_gradient`.
+fn is_low_gradient<T: Pixel>(
+    plane: &Plane<T>,
+    x: Range<usize>,
+    y: Range<usize>,
+    max_gradient: f64,
+) -> bool {
+    let block = region(plane, x, y);
+    let close =
+        |a: &T, b: &T| f64::from((i32::cast_from(*a) - i32::cast_from(*b)).abs()) <= max_gradient;
+    (0..block.height()).all(|y| {
+        let row = block.row(y);
+        row.windows(2).all(|pair| close(&pair[0], &pair[1]))
+            && (y == 0 || row.iter().zip(block.row(y - 1)).all(|(a, b)| close(a, b)))
+    })
+}
+
+/// The samples of a plane of `len` samples decimated by `dec` whose first
+/// luma sample is in `range`.
+fn subsampled(range: Range<usize>, dec: usize, len: usize) -> Range<usize> {
+    let end = range.end.div_ceil(1 << dec).min(len);
+    range.start.div_ceil(1 << dec).min(end)..end
+}
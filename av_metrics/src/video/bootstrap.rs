@@ -0,0 +1,87 @@
+//! Confidence intervals of mean scores, by bootstrap resampling.
+//!
+//! When two encoders score within a fraction of a dB of each other, the
+//! difference may be down to which frames happen to be in the clip. Resampling
+//! the per-frame scores with replacement many times and looking at how much
+//! the mean moves gives an interval that the mean of similar content would
+//! likely fall in. If the intervals of two encoders overlap, their difference
+//! is not meaningful.
+
+use crate::video::synth::XorShift;
+
+/// The mean of per-frame scores, with the bounds of its confidence interval.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ConfidenceInterval {
+    /// The mean of the scores.
+    pub mean: f64,
+    /// The lower bound of the interval.
+    pub lower: f64,
+    /// The upper bound of the interval.
+    pub upper: f64,
+    /// The probability that the interval is meant to cover, such as 0.95.
+    pub confidence: f64,
+}
+
+/// Options for bootstrap resampling.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BootstrapConfig {
+    /// The number of times the scores are resampled. More resamples give more
+    /// stable bounds.
+    pub resamples: usize,
+    /// The probability that the interval is meant to cover, between 0 and 1.
+    pub confidence: f64,
+    /// The seed of the resampling, so that the same scores always give the
+    /// same interval.
+    pub seed: u64,
+}
+
+impl Default for BootstrapConfig {
+    /// 1000 resamples for a 95% confidence interval.
+    fn default() -> Self {
+        BootstrapConfig {
+            resamples: 1000,
+            confidence: 0.95,
+            seed: 0,
+        }
+    }
+}
+
+/// Computes the confidence interval of the mean of the scores of consecutive
+/// frames, by the percentiles of the means of bootstrap resamples.
+///
+/// Frames are resampled independently, so the interval is too narrow for
+/// scores that depend on their neighbours, as in long static scenes.
+/// Returns `None` if there are no scores or no resamples.
+pub fn bootstrap_mean<I: IntoIterator<Item = f64>>(
+    scores: I,
+    config: BootstrapConfig,
+) -> Option<ConfidenceInterval> {
+    let scores: Vec<f64> = scores.into_iter().collect();
+    if scores.is_empty() || config.resamples == 0 {
+        return None;
+    }
+
+    let n = scores.len();
+    let mut rng = XorShift::new(config.seed);
+    let mut means: Vec<f64> = (0..config.resamples)
+        .map(|_| {
+            (0..n)
+                .map(|_| scores[(rng.next_u64() % n as u64) as usize])
+                .sum::<f64>()
+                / n as f64
+        })
+        .collect();
+    means.sort_by(f64::total_cmp);
+
+    let confidence = config.confidence.clamp(0.0, 1.0);
+    let percentile = |p: f64| means[(p * (means.len() - 1) as f64).round() as usize];
+    let tail = (1.0 - confidence) / 2.0;
+    Some(ConfidenceInterval {
+        mean: scores.iter().sum::<f64>() / n as f64,
+        lower: percentile(tail),
+        upper: percentile(1.0 - tail),
+        confidence,
+    })
+}
@@ -7,13 +7,14 @@
 
 use crate::video::decode::Decoder;
 use crate::video::pixel::{CastFromPrimitive, Pixel};
-use crate::video::VideoMetric;
+use crate::video::{
+    ColorRange, FrameContext, MatrixCoefficients, Sampling, Score, VideoMetric, VideoOptions,
+};
 use crate::MetricsError;
 use std::f64;
-use std::mem::size_of;
 
-mod rgbtolab;
-use rgbtolab::*;
+use crate::video::color::rgbtolab::*;
+use crate::video::color::YuvToRgb;
 
 mod delta_e;
 use delta_e::*;
@@ -25,6 +26,9 @@ use delta_e::*;
 ///
 /// Optionally, `frame_limit` can be set to only compare the first
 /// `frame_limit` frames in each video.
+///
+/// The frames are converted to RGB with the color range and matrix
+/// coefficients reported by `decoder1`.
 #[inline]
 pub fn calculate_video_ciede<D: Decoder, F: Fn(usize) + Send>(
     decoder1: &mut D,
@@ -32,7 +36,97 @@ pub fn calculate_video_ciede<D: Decoder, F: Fn(usize) + Send>(
     frame_limit: Option<usize>,
     progress_callback: F,
 ) -> Result<f64, Box<dyn Error>> {
-    Ciede2000::default().process_video(decoder1, decoder2, frame_limit, progress_callback)
+    calculate_video_ciede_with(
+        decoder1,
+        decoder2,
+        CiedeOptions {
+            video: VideoOptions::limited(frame_limit, progress_callback),
+            config: CiedeConfig::default(),
+        },
+    )
+}
+
+/// Options for computing CIEDE2000.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CiedeConfig {
+    /// The samples the color difference is computed on. See [`Sampling`] for
+    /// the expected deviation of a subsample.
+    pub sampling: Sampling,
+}
+
+/// Options for computing CIEDE2000 over whole videos, built with chained
+/// calls:
+///
+/// ```
+/// use av_metrics::video::ciede::CiedeOptions;
+/// use av_metrics::video::Sampling;
+///
+/// let options = CiedeOptions::new()
+///     .frame_limit(100)
+///     .sampling(Sampling::Quincunx);
+/// ```
+#[derive(Debug, Clone)]
+pub struct CiedeOptions<F = fn(usize)> {
+    /// The frame limit, thread count and progress callback.
+    pub video: VideoOptions<F>,
+    /// The options of CIEDE2000 itself.
+    pub config: CiedeConfig,
+}
+
+video_options!(CiedeOptions);
+
+impl<F> CiedeOptions<F> {
+    /// Only computes the color difference on the samples selected by
+    /// `sampling`.
+    pub fn sampling(mut self, sampling: Sampling) -> Self {
+        self.config.sampling = sampling;
+        self
+    }
+}
+
+/// Calculates the CIEDE2000 metric between two video clips with the given
+/// options. Higher is better.
+///
+/// See [`CiedeOptions`] for the available options.
+#[inline]
+pub fn calculate_video_ciede_with<D: Decoder, F: Fn(usize) + Send>(
+    decoder1: &mut D,
+    decoder2: &mut D,
+    options: CiedeOptions<F>,
+) -> Result<f64, Box<dyn Error>> {
+    let details = decoder1.get_video_details();
+    let sampling = options.config.sampling;
+    let deterministic = options.video.deterministic;
+    options.video.run(|limits, progress_callback| {
+        Ciede2000 {
+            color_range: details.color_range,
+            matrix_coefficients: details.matrix_coefficients,
+            sampling,
+            deterministic,
+            ..Default::default()
+        }
+        .process_video(decoder1, decoder2, limits, progress_callback)
+    })
+}
+
+/// Calculates the CIEDE2000 color difference for pairs of frames from any source, such as frames
+/// received over a network or generated in memory. Lower is better.
+///
+/// The frames are scored with the same multithreaded pipeline as
+/// [`calculate_video_ciede`].
+#[inline]
+pub fn calculate_video_ciede_from_frames<T: Pixel, I, F: Fn(usize) + Send>(
+    frames: I,
+    bit_depth: usize,
+    chroma_sampling: ChromaSampling,
+    progress_callback: F,
+) -> Result<f64, Box<dyn Error>>
+where
+    I: IntoIterator<Item = (Frame<T>, Frame<T>)>,
+    I::IntoIter: Send,
+{
+    Ciede2000::default().process_frames(frames, bit_depth, chroma_sampling, progress_callback)
 }
 
 /// Calculate the CIEDE2000 metric between two video clips. Higher is better.
@@ -47,12 +141,14 @@ pub fn calculate_video_ciede_nosimd<D: Decoder, F: Fn(usize) + Send>(
     frame_limit: Option<usize>,
     progress_callback: F,
 ) -> Result<f64, Box<dyn Error>> {
-    (Ciede2000 { use_simd: false }).process_video(
-        decoder1,
-        decoder2,
-        frame_limit,
-        progress_callback,
-    )
+    let details = decoder1.get_video_details();
+    Ciede2000 {
+        use_simd: false,
+        color_range: details.color_range,
+        matrix_coefficients: details.matrix_coefficients,
+        ..Default::default()
+    }
+    .process_video(decoder1, decoder2, frame_limit.into(), progress_callback)
 }
 
 /// Calculate the CIEDE2000 metric between two video frames. Higher is better.
@@ -78,16 +174,32 @@ pub fn calculate_frame_ciede_nosimd<T: Pixel>(
     bit_depth: usize,
     chroma_sampling: ChromaSampling,
 ) -> Result<f64, Box<dyn Error>> {
-    (Ciede2000 { use_simd: false }).process_frame(frame1, frame2, bit_depth, chroma_sampling)
+    Ciede2000 {
+        use_simd: false,
+        ..Default::default()
+    }
+    .process_frame(frame1, frame2, bit_depth, chroma_sampling)
 }
 
 struct Ciede2000 {
     use_simd: bool,
+    color_range: ColorRange,
+    matrix_coefficients: MatrixCoefficients,
+    sampling: Sampling,
+    /// Whether the color differences of the rows of a frame are added up in
+    /// row order, rather than in the order the threads finish them.
+    deterministic: bool,
 }
 
 impl Default for Ciede2000 {
     fn default() -> Self {
-        Ciede2000 { use_simd: true }
+        Ciede2000 {
+            use_simd: true,
+            color_range: ColorRange::default(),
+            matrix_coefficients: MatrixCoefficients::default(),
+            sampling: Sampling::default(),
+            deterministic: false,
+        }
     }
 }
 
@@ -106,20 +218,18 @@ impl VideoMetric for Ciede2000 {
         bit_depth: usize,
         chroma_sampling: ChromaSampling,
     ) -> Result<Self::FrameResult, Box<dyn Error>> {
-        if (size_of::<T>() == 1 && bit_depth > 8) || (size_of::<T>() == 2 && bit_depth <= 8) {
-            return Err(Box::new(MetricsError::InputMismatch {
-                reason: "Bit depths does not match pixel width",
-            }));
-        }
-
-        frame1.can_compare(frame2)?;
+        FrameContext::new(bit_depth, chroma_sampling)?.validate(frame1, frame2)?;
 
         let dec = chroma_sampling.get_decimation().unwrap_or((1, 1));
         let y_width = frame1.planes[0].cfg.width;
         let y_height = frame1.planes[0].cfg.height;
         let c_width = frame1.planes[1].cfg.width;
-        let delta_e_row_fn = get_delta_e_row_fn(bit_depth, dec.0, self.use_simd);
-        // let mut delta_e_vec: Vec<f32> = vec![0.0; y_width * y_height];
+        let delta_e_row_fn = get_delta_e_row_fn(bit_depth, dec.0, self.use_simd)?;
+        let yuv_to_rgb = YuvToRgb::new(bit_depth, self.color_range, self.matrix_coefficients);
+
+        if self.sampling != Sampling::Full {
+            return Ok(self.process_frame_sampled(frame1, frame2, dec, &yuv_to_rgb));
+        }
 
         let delta_e_per_line = (0..y_height).into_par_iter().map(|i| {
             let y_start = i * y_width;
@@ -144,16 +254,23 @@ impl VideoMetric for Ciede2000 {
                         u: &frame2.planes[1].data[c_range.clone()],
                         v: &frame2.planes[2].data[c_range],
                     },
+                    &yuv_to_rgb,
                     &mut delta_e_vec[..],
                 );
             }
 
-            delta_e_vec.iter().map(|x| *x as f64).sum::<f64>()
+            (
+                delta_e_vec.iter().map(|x| *x as f64).sum::<f64>(),
+                delta_e_vec.len(),
+            )
         });
 
-        let score =
-            45. - 20. * (delta_e_per_line.sum::<f64>() / ((y_width * y_height) as f64)).log10();
-        Ok(score.min(100.))
+        let (sum, count) = self.sum_rows(delta_e_per_line);
+        Ok(ciede_score(sum, count))
+    }
+
+    fn frame_score(&self, result: &Self::FrameResult) -> Option<Score> {
+        Some(Score::Value(*result))
     }
 
     fn aggregate_frame_results(
@@ -164,6 +281,87 @@ impl VideoMetric for Ciede2000 {
     }
 }
 
+impl Ciede2000 {
+    /// Adds up the sums and numbers of the color differences of each row.
+    fn sum_rows(&self, rows: impl IndexedParallelIterator<Item = (f64, usize)>) -> (f64, usize) {
+        let add = |a: (f64, usize), b: (f64, usize)| (a.0 + b.0, a.1 + b.1);
+        if self.deterministic {
+            rows.collect::<Vec<_>>().into_iter().fold((0.0, 0), add)
+        } else {
+            rows.reduce(|| (0.0, 0), add)
+        }
+    }
+
+    /// Scores the samples of each row selected by the sampling of the metric.
+    ///
+    /// The selected samples of a row are gathered along with their chroma,
+    /// which lines them up one to one, and compared with the scalar code.
+    fn process_frame_sampled<T: Pixel>(
+        &self,
+        frame1: &Frame<T>,
+        frame2: &Frame<T>,
+        (xdec, ydec): (usize, usize),
+        yuv_to_rgb: &YuvToRgb,
+    ) -> f64 {
+        let y_width = frame1.planes[0].cfg.width;
+        let y_height = frame1.planes[0].cfg.height;
+        let c_width = frame1.planes[1].cfg.width;
+
+        let rows = (0..y_height).into_par_iter().map(|i| {
+            let (first, step) = self.sampling.columns(i);
+            let y_range = i * y_width..(i + 1) * y_width;
+            let c_range = (i >> ydec) * c_width..((i >> ydec) + 1) * c_width;
+            let gather = |samples: &[T], xdec: usize| -> Vec<T> {
+                samples[first >> xdec..]
+                    .iter()
+                    .step_by(step >> xdec)
+                    .copied()
+                    .collect()
+            };
+            let gather_row = |frame: &Frame<T>| {
+                (
+                    gather(&frame.planes[0].data[y_range.clone()], 0),
+                    gather(&frame.planes[1].data[c_range.clone()], xdec),
+                    gather(&frame.planes[2].data[c_range.clone()], xdec),
+                )
+            };
+            let (y1, u1, v1) = gather_row(frame1);
+            let (y2, u2, v2) = gather_row(frame2);
+
+            let mut delta_e_vec = vec![0.0; y1.len()];
+            unsafe {
+                FullChroma::delta_e_row_scalar(
+                    FrameRow {
+                        y: &y1,
+                        u: &u1,
+                        v: &v1,
+                    },
+                    FrameRow {
+                        y: &y2,
+                        u: &u2,
+                        v: &v2,
+                    },
+                    yuv_to_rgb,
+                    &mut delta_e_vec[..],
+                );
+            }
+            (
+                delta_e_vec.iter().map(|x| *x as f64).sum::<f64>(),
+                delta_e_vec.len(),
+            )
+        });
+
+        let (sum, count) = self.sum_rows(rows);
+        ciede_score(sum, count)
+    }
+}
+
+/// The score of a frame from the sum of the color differences of `count`
+/// samples.
+fn ciede_score(sum: f64, count: usize) -> f64 {
+    (45. - 20. * (sum / count as f64).log10()).min(100.)
+}
+
 // Arguments for delta e
 // "Color Image Quality Assessment Based on CIEDE2000"
 // Yang Yang, Jun Ming and Nenghai Yu, 2012
@@ -180,66 +378,44 @@ pub(crate) struct FrameRow<'a, T: Pixel> {
     v: &'a [T],
 }
 
-type DeltaERowFn<T> = unsafe fn(FrameRow<T>, FrameRow<T>, &mut [f32]);
+type DeltaERowFn<T> = unsafe fn(FrameRow<T>, FrameRow<T>, &YuvToRgb, &mut [f32]);
 
-fn get_delta_e_row_fn<T: Pixel>(bit_depth: usize, xdec: usize, simd: bool) -> DeltaERowFn<T> {
+fn get_delta_e_row_fn<T: Pixel>(
+    bit_depth: usize,
+    xdec: usize,
+    simd: bool,
+) -> Result<DeltaERowFn<T>, MetricsError> {
+    if !matches!(bit_depth, 8 | 10 | 12 | 16) {
+        return Err(MetricsError::UnsupportedInput {
+            reason: "CIEDE2000 supports bit depths of 8, 10, 12 and 16",
+        });
+    }
     #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
     {
         if is_x86_feature_detected!("avx2") && xdec == 1 && simd {
-            return match bit_depth {
-                8 => BD8::delta_e_row_avx2,
-                10 => BD10::delta_e_row_avx2,
-                12 => BD12::delta_e_row_avx2,
-                _ => unreachable!(),
-            };
+            return Ok(SubsampledChroma::delta_e_row_avx2);
         }
     }
-    match (bit_depth, xdec) {
-        (8, 1) => BD8::delta_e_row_scalar,
-        (10, 1) => BD10::delta_e_row_scalar,
-        (12, 1) => BD12::delta_e_row_scalar,
-        (8, 0) => BD8_444::delta_e_row_scalar,
-        (10, 0) => BD10_444::delta_e_row_scalar,
-        (12, 0) => BD12_444::delta_e_row_scalar,
-        _ => unreachable!(),
-    }
+    Ok(if xdec == 1 {
+        SubsampledChroma::delta_e_row_scalar
+    } else {
+        FullChroma::delta_e_row_scalar
+    })
 }
 
 pub(crate) trait Colorspace {
-    const BIT_DEPTH: u32;
     const X_DECIMATION: u32;
 }
 
-struct BD8;
-struct BD10;
-struct BD12;
-
-struct BD8_444;
-struct BD10_444;
-struct BD12_444;
+/// Chroma planes with half the width of the luma plane (4:2:0 and 4:2:2).
+struct SubsampledChroma;
+/// Chroma planes with the width of the luma plane (4:4:4).
+struct FullChroma;
 
-impl Colorspace for BD8 {
-    const BIT_DEPTH: u32 = 8;
-    const X_DECIMATION: u32 = 1;
-}
-impl Colorspace for BD10 {
-    const BIT_DEPTH: u32 = 10;
+impl Colorspace for SubsampledChroma {
     const X_DECIMATION: u32 = 1;
 }
-impl Colorspace for BD12 {
-    const BIT_DEPTH: u32 = 12;
-    const X_DECIMATION: u32 = 1;
-}
-impl Colorspace for BD8_444 {
-    const BIT_DEPTH: u32 = 8;
-    const X_DECIMATION: u32 = 0;
-}
-impl Colorspace for BD10_444 {
-    const BIT_DEPTH: u32 = 10;
-    const X_DECIMATION: u32 = 0;
-}
-impl Colorspace for BD12_444 {
-    const BIT_DEPTH: u32 = 12;
+impl Colorspace for FullChroma {
     const X_DECIMATION: u32 = 0;
 }
 
@@ -253,32 +429,16 @@ where
 }
 
 pub(crate) trait DeltaEScalar: Colorspace {
-    fn delta_e_scalar(yuv1: (u16, u16, u16), yuv2: (u16, u16, u16)) -> f32 {
-        let scale = (1 << (Self::BIT_DEPTH - 8)) as f32;
-        let yuv_to_rgb = |yuv: (u16, u16, u16)| {
-            // Assumes BT.709
-            let y = (yuv.0 as f32 - 16. * scale) * (1. / (219. * scale));
-            let u = (yuv.1 as f32 - 128. * scale) * (1. / (224. * scale));
-            let v = (yuv.2 as f32 - 128. * scale) * (1. / (224. * scale));
-
-            // [-0.804677, 1.81723]
-            let r = y + 1.28033 * v;
-            // [−0.316650, 1.09589]
-            let g = y - 0.21482 * u - 0.38059 * v;
-            // [-1.28905, 2.29781]
-            let b = y + 2.12798 * u;
-
-            (r, g, b)
-        };
-
-        let (r1, g1, b1) = yuv_to_rgb(yuv1);
-        let (r2, g2, b2) = yuv_to_rgb(yuv2);
-        DE2000::new(rgb_to_lab(&[r1, g1, b1]), rgb_to_lab(&[r2, g2, b2]), K_SUB)
+    fn delta_e_scalar(yuv1: (u16, u16, u16), yuv2: (u16, u16, u16), coeffs: &YuvToRgb) -> f32 {
+        let rgb1 = coeffs.convert(yuv1.0, yuv1.1, yuv1.2);
+        let rgb2 = coeffs.convert(yuv2.0, yuv2.1, yuv2.2);
+        DE2000::new(rgb_to_lab(&rgb1), rgb_to_lab(&rgb2), K_SUB)
     }
 
     unsafe fn delta_e_row_scalar<T: Pixel>(
         row1: FrameRow<T>,
         row2: FrameRow<T>,
+        coeffs: &YuvToRgb,
         res_row: &mut [f32],
     ) {
         if Self::X_DECIMATION == 1 {
@@ -302,6 +462,7 @@ pub(crate) trait DeltaEScalar: Colorspace {
                         u16::cast_from(*u2),
                         u16::cast_from(*v2),
                     ),
+                    coeffs,
                 );
             }
         } else {
@@ -319,25 +480,20 @@ pub(crate) trait DeltaEScalar: Colorspace {
                         u16::cast_from(*u2),
                         u16::cast_from(*v2),
                     ),
+                    coeffs,
                 );
             }
         }
     }
 }
 
-impl DeltaEScalar for BD8 {}
-impl DeltaEScalar for BD10 {}
-impl DeltaEScalar for BD12 {}
-impl DeltaEScalar for BD8_444 {}
-impl DeltaEScalar for BD10_444 {}
-impl DeltaEScalar for BD12_444 {}
+impl DeltaEScalar for SubsampledChroma {}
+impl DeltaEScalar for FullChroma {}
 
 #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
 use self::avx2::*;
 use std::error::Error;
 
-use super::FrameCompare;
-
 #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
 mod avx2 {
     use super::*;
@@ -346,34 +502,38 @@ mod avx2 {
     use std::arch::x86::*;
     #[cfg(target_arch = "x86_64")]
     use std::arch::x86_64::*;
+    use std::mem::size_of;
+    use std::ptr;
 
     pub(crate) trait DeltaEAVX2: Colorspace + DeltaEScalar {
         #[target_feature(enable = "avx2")]
-        unsafe fn yuv_to_rgb(yuv: (__m256, __m256, __m256)) -> (__m256, __m256, __m256) {
-            let scale: f32 = (1 << (Self::BIT_DEPTH - 8)) as f32;
+        unsafe fn yuv_to_rgb(
+            yuv: (__m256, __m256, __m256),
+            coeffs: &YuvToRgb,
+        ) -> (__m256, __m256, __m256) {
             #[target_feature(enable = "avx2")]
             unsafe fn set1(val: f32) -> __m256 {
                 _mm256_set1_ps(val)
             }
             let y = _mm256_mul_ps(
-                _mm256_sub_ps(yuv.0, set1(16. * scale)),
-                set1(1. / (219. * scale)),
+                _mm256_sub_ps(yuv.0, set1(coeffs.y_offset)),
+                set1(coeffs.y_scale),
             );
             let u = _mm256_mul_ps(
-                _mm256_sub_ps(yuv.1, set1(128. * scale)),
-                set1(1. / (224. * scale)),
+                _mm256_sub_ps(yuv.1, set1(coeffs.c_offset)),
+                set1(coeffs.c_scale),
             );
             let v = _mm256_mul_ps(
-                _mm256_sub_ps(yuv.2, set1(128. * scale)),
-                set1(1. / (224. * scale)),
+                _mm256_sub_ps(yuv.2, set1(coeffs.c_offset)),
+                set1(coeffs.c_scale),
             );
 
-            let r = _mm256_add_ps(y, _mm256_mul_ps(v, set1(1.28033)));
-            let g = _mm256_add_ps(
-                _mm256_add_ps(y, _mm256_mul_ps(u, set1(-0.21482))),
-                _mm256_mul_ps(v, set1(-0.38059)),
+            let r = _mm256_add_ps(y, _mm256_mul_ps(v, set1(coeffs.r_v)));
+            let g = _mm256_sub_ps(
+                _mm256_sub_ps(y, _mm256_mul_ps(u, set1(coeffs.g_u))),
+                _mm256_mul_ps(v, set1(coeffs.g_v)),
             );
-            let b = _mm256_add_ps(y, _mm256_mul_ps(u, set1(2.12798)));
+            let b = _mm256_add_ps(y, _mm256_mul_ps(u, set1(coeffs.b_u)));
 
             (r, g, b)
         }
@@ -382,10 +542,11 @@ mod avx2 {
         unsafe fn delta_e_avx2(
             yuv1: (__m256, __m256, __m256),
             yuv2: (__m256, __m256, __m256),
+            coeffs: &YuvToRgb,
             res_chunk: &mut [f32],
         ) {
-            let (r1, g1, b1) = Self::yuv_to_rgb(yuv1);
-            let (r2, g2, b2) = Self::yuv_to_rgb(yuv2);
+            let (r1, g1, b1) = Self::yuv_to_rgb(yuv1, coeffs);
+            let (r2, g2, b2) = Self::yuv_to_rgb(yuv2, coeffs);
 
             let lab1 = rgb_to_lab_avx2(&[r1, g1, b1]);
             let lab2 = rgb_to_lab_avx2(&[r2, g2, b2]);
@@ -398,178 +559,85 @@ mod avx2 {
         unsafe fn delta_e_row_avx2<T: Pixel>(
             row1: FrameRow<T>,
             row2: FrameRow<T>,
+            coeffs: &YuvToRgb,
             res_row: &mut [f32],
         ) {
-            // Only one version should be compiled for each trait
-            if Self::BIT_DEPTH == 8 {
-                for (chunk1_y, chunk1_u, chunk1_v, chunk2_y, chunk2_u, chunk2_v, res_chunk) in izip!(
-                    row1.y.chunks(8),
-                    row1.u.chunks(4),
-                    row1.v.chunks(4),
-                    row2.y.chunks(8),
-                    row2.u.chunks(4),
-                    row2.v.chunks(4),
-                    res_row.chunks_mut(8)
-                ) {
-                    if chunk1_y.len() == 8 {
-                        #[inline(always)]
-                        unsafe fn load_luma(chunk: &[u8]) -> __m256 {
-                            let tmp = _mm_loadl_epi64(chunk.as_ptr() as *const _);
-                            _mm256_cvtepi32_ps(_mm256_cvtepu8_epi32(tmp))
-                        }
-
-                        #[inline(always)]
-                        unsafe fn load_chroma(chunk: &[u8]) -> __m256 {
-                            let tmp = _mm_cvtsi32_si128(*(chunk.as_ptr() as *const i32));
-                            _mm256_cvtepi32_ps(_mm256_cvtepu8_epi32(_mm_unpacklo_epi8(tmp, tmp)))
-                        }
-
-                        Self::delta_e_avx2(
-                            (
-                                load_luma(
-                                    &chunk1_y
-                                        .iter()
-                                        .map(|p| u8::cast_from(*p))
-                                        .collect::<Vec<_>>(),
-                                ),
-                                load_chroma(
-                                    &chunk1_u
-                                        .iter()
-                                        .map(|p| u8::cast_from(*p))
-                                        .collect::<Vec<_>>(),
-                                ),
-                                load_chroma(
-                                    &chunk1_v
-                                        .iter()
-                                        .map(|p| u8::cast_from(*p))
-                                        .collect::<Vec<_>>(),
-                                ),
-                            ),
-                            (
-                                load_luma(
-                                    &chunk2_y
-                                        .iter()
-                                        .map(|p| u8::cast_from(*p))
-                                        .collect::<Vec<_>>(),
-                                ),
-                                load_chroma(
-                                    &chunk2_u
-                                        .iter()
-                                        .map(|p| u8::cast_from(*p))
-                                        .collect::<Vec<_>>(),
-                                ),
-                                load_chroma(
-                                    &chunk2_v
-                                        .iter()
-                                        .map(|p| u8::cast_from(*p))
-                                        .collect::<Vec<_>>(),
-                                ),
-                            ),
-                            res_chunk,
-                        );
-                    } else {
-                        Self::delta_e_row_scalar(
-                            FrameRow {
-                                y: chunk1_y,
-                                u: chunk1_u,
-                                v: chunk1_v,
-                            },
-                            FrameRow {
-                                y: chunk2_y,
-                                u: chunk2_u,
-                                v: chunk2_v,
-                            },
-                            res_chunk,
-                        );
-                    }
-                }
-            } else {
-                for (chunk1_y, chunk1_u, chunk1_v, chunk2_y, chunk2_u, chunk2_v, res_chunk) in izip!(
-                    row1.y.chunks(8),
-                    row1.u.chunks(4),
-                    row1.v.chunks(4),
-                    row2.y.chunks(8),
-                    row2.u.chunks(4),
-                    row2.v.chunks(4),
-                    res_row.chunks_mut(8)
-                ) {
-                    if chunk1_y.len() == 8 {
-                        #[inline(always)]
-                        unsafe fn load_luma(chunk: &[u16]) -> __m256 {
-                            let tmp = _mm_loadu_si128(chunk.as_ptr() as *const _);
-                            _mm256_cvtepi32_ps(_mm256_cvtepu16_epi32(tmp))
-                        }
-
-                        #[inline(always)]
-                        unsafe fn load_chroma(chunk: &[u16]) -> __m256 {
-                            let tmp = _mm_loadl_epi64(chunk.as_ptr() as *const _);
-                            _mm256_cvtepi32_ps(_mm256_cvtepu16_epi32(_mm_unpacklo_epi16(tmp, tmp)))
-                        }
-
-                        Self::delta_e_avx2(
-                            (
-                                load_luma(
-                                    &chunk1_y
-                                        .iter()
-                                        .map(|p| u16::cast_from(*p))
-                                        .collect::<Vec<_>>(),
-                                ),
-                                load_chroma(
-                                    &chunk1_u
-                                        .iter()
-                                        .map(|p| u16::cast_from(*p))
-                                        .collect::<Vec<_>>(),
-                                ),
-                                load_chroma(
-                                    &chunk1_v
-                                        .iter()
-                                        .map(|p| u16::cast_from(*p))
-                                        .collect::<Vec<_>>(),
-                                ),
-                            ),
-                            (
-                                load_luma(
-                                    &chunk2_y
-                                        .iter()
-                                        .map(|p| u16::cast_from(*p))
-                                        .collect::<Vec<_>>(),
-                                ),
-                                load_chroma(
-                                    &chunk2_u
-                                        .iter()
-                                        .map(|p| u16::cast_from(*p))
-                                        .collect::<Vec<_>>(),
-                                ),
-                                load_chroma(
-                                    &chunk2_v
-                                        .iter()
-                                        .map(|p| u16::cast_from(*p))
-                                        .collect::<Vec<_>>(),
-                                ),
-                            ),
-                            res_chunk,
-                        );
-                    } else {
-                        Self::delta_e_row_scalar(
-                            FrameRow {
-                                y: chunk1_y,
-                                u: chunk1_u,
-                                v: chunk1_v,
-                            },
-                            FrameRow {
-                                y: chunk2_y,
-                                u: chunk2_u,
-                                v: chunk2_v,
-                            },
-                            res_chunk,
-                        );
-                    }
+            for (chunk1_y, chunk1_u, chunk1_v, chunk2_y, chunk2_u, chunk2_v, res_chunk) in izip!(
+                row1.y.chunks(8),
+                row1.u.chunks(4),
+                row1.v.chunks(4),
+                row2.y.chunks(8),
+                row2.u.chunks(4),
+                row2.v.chunks(4),
+                res_row.chunks_mut(8)
+            ) {
+                if chunk1_y.len() == 8 && chunk1_u.len() == 4 {
+                    Self::delta_e_avx2(
+                        (
+                            load_luma(chunk1_y),
+                            load_chroma(chunk1_u),
+                            load_chroma(chunk1_v),
+                        ),
+                        (
+                            load_luma(chunk2_y),
+                            load_chroma(chunk2_u),
+                            load_chroma(chunk2_v),
+                        ),
+                        coeffs,
+                        res_chunk,
+                    );
+                } else {
+                    Self::delta_e_row_scalar(
+                        FrameRow {
+                            y: chunk1_y,
+                            u: chunk1_u,
+                            v: chunk1_v,
+                        },
+                        FrameRow {
+                            y: chunk2_y,
+                            u: chunk2_u,
+                            v: chunk2_v,
+                        },
+                        coeffs,
+                        res_chunk,
+                    );
                 }
             }
         }
     }
 
-    impl DeltaEAVX2 for BD8 {}
-    impl DeltaEAVX2 for BD10 {}
-    impl DeltaEAVX2 for BD12 {}
+    /// Loads 8 luma samples directly from the row, converted to floats.
+    ///
+    /// The caller must ensure `chunk` holds at least 8 samples.
+    #[target_feature(enable = "avx2")]
+    #[inline]
+    unsafe fn load_luma<T: Pixel>(chunk: &[T]) -> __m256 {
+        debug_assert!(chunk.len() >= 8);
+        let wide = if size_of::<T>() == 1 {
+            _mm256_cvtepu8_epi32(_mm_loadl_epi64(chunk.as_ptr() as *const __m128i))
+        } else {
+            _mm256_cvtepu16_epi32(_mm_loadu_si128(chunk.as_ptr() as *const __m128i))
+        };
+        _mm256_cvtepi32_ps(wide)
+    }
+
+    /// Loads 4 horizontally subsampled chroma samples directly from the row,
+    /// duplicating each one to line up with 8 luma samples, converted to floats.
+    ///
+    /// The caller must ensure `chunk` holds at least 4 samples.
+    #[target_feature(enable = "avx2")]
+    #[inline]
+    unsafe fn load_chroma<T: Pixel>(chunk: &[T]) -> __m256 {
+        debug_assert!(chunk.len() >= 4);
+        let wide = if size_of::<T>() == 1 {
+            let tmp = _mm_cvtsi32_si128(ptr::read_unaligned(chunk.as_ptr() as *const i32));
+            _mm256_cvtepu8_epi32(_mm_unpacklo_epi8(tmp, tmp))
+        } else {
+            let tmp = _mm_loadl_epi64(chunk.as_ptr() as *const __m128i);
+            _mm256_cvtepu16_epi32(_mm_unpacklo_epi16(tmp, tmp))
+        };
+        _mm256_cvtepi32_ps(wide)
+    }
+
+    impl DeltaEAVX2 for SubsampledChroma {}
 }
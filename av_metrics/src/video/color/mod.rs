@@ -0,0 +1,169 @@
+//! Conversions of YUV samples to RGB, linear RGB, CIE XYZ and CIE L*a*b*.
+//!
+//! The same conversions are used by CIEDE2000, so colors converted with this
+//! module match the colors the metric compares.
+
+use crate::video::decode::VideoDetails;
+use crate::video::{ColorRange, MatrixCoefficients};
+
+pub(crate) mod rgbtolab;
+use rgbtolab::*;
+
+/// The transfer function that relates RGB values to linear light.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TransferFunction {
+    /// The sRGB transfer function, which CIEDE2000 assumes.
+    #[default]
+    Srgb,
+    /// The ITU-R BT.709 transfer function, also used by BT.601 and BT.2020.
+    Bt709,
+    /// The values are already linear.
+    Linear,
+}
+
+impl TransferFunction {
+    /// Converts a value between 0 and 1 to linear light.
+    pub fn to_linear(self, value: f32) -> f32 {
+        match self {
+            TransferFunction::Srgb => rgb_to_xyz_map(value),
+            TransferFunction::Bt709 => {
+                if value < 0.081 {
+                    value / 4.5
+                } else {
+                    ((value + 0.099) / 1.099).powf(1. / 0.45)
+                }
+            }
+            TransferFunction::Linear => value,
+        }
+    }
+}
+
+/// How YUV samples are converted to RGB.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ColorConfig {
+    /// The range of the sample values.
+    pub color_range: ColorRange,
+    /// The matrix coefficients converting YUV to RGB.
+    pub matrix_coefficients: MatrixCoefficients,
+    /// The transfer function of the RGB values.
+    pub transfer_function: TransferFunction,
+}
+
+impl From<&VideoDetails> for ColorConfig {
+    /// The color range and matrix coefficients of a video, with the sRGB
+    /// transfer function.
+    fn from(details: &VideoDetails) -> Self {
+        ColorConfig {
+            color_range: details.color_range,
+            matrix_coefficients: details.matrix_coefficients,
+            ..Default::default()
+        }
+    }
+}
+
+/// Coefficients converting YUV samples to RGB in the range 0 to 1.
+#[derive(Debug, Clone, Copy)]
+pub struct YuvToRgb {
+    pub(crate) y_offset: f32,
+    pub(crate) y_scale: f32,
+    pub(crate) c_offset: f32,
+    pub(crate) c_scale: f32,
+    pub(crate) r_v: f32,
+    pub(crate) g_u: f32,
+    pub(crate) g_v: f32,
+    pub(crate) b_u: f32,
+}
+
+impl YuvToRgb {
+    /// The coefficients for samples of the given bit depth, range and matrix.
+    pub fn new(bit_depth: usize, range: ColorRange, matrix: MatrixCoefficients) -> Self {
+        let scale = (1 << (bit_depth - 8)) as f32;
+        let (y_offset, y_scale, c_scale) = match range {
+            ColorRange::Limited => (16. * scale, 1. / (219. * scale), 1. / (224. * scale)),
+            ColorRange::Full => {
+                let max = ((1u32 << bit_depth) - 1) as f32;
+                (0., 1. / max, 1. / max)
+            }
+        };
+        // The coefficients are those of analog YUV, where U and V span
+        // ±0.436 and ±0.615, which is what the BT.709 conversion always used.
+        const U_MAX: f64 = 0.436;
+        const V_MAX: f64 = 0.615;
+        let (kr, kb) = matrix.luma_weights();
+        let kg = 1. - kr - kb;
+        YuvToRgb {
+            y_offset,
+            y_scale,
+            c_offset: 128. * scale,
+            c_scale,
+            r_v: ((1. - kr) / V_MAX) as f32,
+            g_u: (kb * (1. - kb) / (U_MAX * kg)) as f32,
+            g_v: (kr * (1. - kr) / (V_MAX * kg)) as f32,
+            b_u: ((1. - kb) / U_MAX) as f32,
+        }
+    }
+
+    /// Converts a YUV sample to RGB. Colors outside of the RGB gamut are not
+    /// clipped.
+    #[inline]
+    pub fn convert(&self, y: u16, u: u16, v: u16) -> [f32; 3] {
+        let y = (y as f32 - self.y_offset) * self.y_scale;
+        let u = (u as f32 - self.c_offset) * self.c_scale;
+        let v = (v as f32 - self.c_offset) * self.c_scale;
+
+        [
+            y + self.r_v * v,
+            y - self.g_u * u - self.g_v * v,
+            y + self.b_u * u,
+        ]
+    }
+}
+
+/// Converts YUV samples of a given bit depth and color configuration to the
+/// other color spaces.
+///
+/// XYZ and L*a*b* assume the BT.709 primaries, which sRGB shares, and the
+/// D65 white point.
+#[derive(Debug, Clone, Copy)]
+pub struct ColorConverter {
+    yuv_to_rgb: YuvToRgb,
+    transfer_function: TransferFunction,
+}
+
+impl ColorConverter {
+    /// Creates a converter for samples of the given bit depth.
+    pub fn new(bit_depth: usize, config: ColorConfig) -> Self {
+        ColorConverter {
+            yuv_to_rgb: YuvToRgb::new(bit_depth, config.color_range, config.matrix_coefficients),
+            transfer_function: config.transfer_function,
+        }
+    }
+
+    /// Converts a YUV sample to RGB, in the range 0 to 1.
+    pub fn rgb(&self, y: u16, u: u16, v: u16) -> [f32; 3] {
+        self.yuv_to_rgb.convert(y, u, v)
+    }
+
+    /// Converts a YUV sample to linear RGB.
+    pub fn linear_rgb(&self, y: u16, u: u16, v: u16) -> [f32; 3] {
+        self.rgb(y, u, v)
+            .map(|c| self.transfer_function.to_linear(c))
+    }
+
+    /// Converts a YUV sample to CIE XYZ.
+    pub fn xyz(&self, y: u16, u: u16, v: u16) -> [f32; 3] {
+        linear_rgb_to_xyz(&self.linear_rgb(y, u, v))
+    }
+
+    /// Converts a YUV sample to CIE L*a*b*, as `[L, a, b]`.
+    pub fn lab(&self, y: u16, u: u16, v: u16) -> [f32; 3] {
+        let lab = match self.transfer_function {
+            // The same approximations as CIEDE2000
+            TransferFunction::Srgb => rgb_to_lab(&self.rgb(y, u, v)),
+            _ => xyz_to_lab(self.xyz(y, u, v)),
+        };
+        [lab.l, lab.a, lab.b]
+    }
+}
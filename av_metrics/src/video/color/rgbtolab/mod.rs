@@ -12,11 +12,17 @@ pub fn rgb_to_lab(rgb: &[f32; 3]) -> Lab {
     xyz_to_lab(rgb_to_xyz(rgb))
 }
 
-#[allow(clippy::excessive_precision)]
 fn rgb_to_xyz(rgb: &[f32; 3]) -> [f32; 3] {
-    let r = rgb_to_xyz_map(rgb[0]);
-    let g = rgb_to_xyz_map(rgb[1]);
-    let b = rgb_to_xyz_map(rgb[2]);
+    linear_rgb_to_xyz(&[
+        rgb_to_xyz_map(rgb[0]),
+        rgb_to_xyz_map(rgb[1]),
+        rgb_to_xyz_map(rgb[2]),
+    ])
+}
+
+#[allow(clippy::excessive_precision)]
+pub fn linear_rgb_to_xyz(rgb: &[f32; 3]) -> [f32; 3] {
+    let [r, g, b] = *rgb;
 
     [
         r * 0.4124564390896921 + g * 0.357576077643909 + b * 0.18043748326639894,
@@ -26,7 +32,7 @@ fn rgb_to_xyz(rgb: &[f32; 3]) -> [f32; 3] {
 }
 
 #[inline]
-fn rgb_to_xyz_map(c: f32) -> f32 {
+pub fn rgb_to_xyz_map(c: f32) -> f32 {
     if c > 10. / 255. {
         const A: f32 = 0.055;
         const D: f32 = 1.0 / 1.055;
@@ -37,7 +43,7 @@ fn rgb_to_xyz_map(c: f32) -> f32 {
     }
 }
 
-fn xyz_to_lab(xyz: [f32; 3]) -> Lab {
+pub fn xyz_to_lab(xyz: [f32; 3]) -> Lab {
     let x = xyz_to_lab_map(xyz[0] * (1.0 / 0.95047));
     let y = xyz_to_lab_map(xyz[1]);
     let z = xyz_to_lab_map(xyz[2] * (1.0 / 1.08883));
@@ -2,10 +2,18 @@
 //! Prebuilt decoders are included in the `av-metrics-decoders` crate.
 
 use crate::video::pixel::Pixel;
-use crate::video::{ChromaSamplePosition, ChromaSampling};
+use crate::video::side_data::SideData;
+use crate::video::{
+    ChromaSamplePosition, ChromaSampling, ColorRange, Interlacing, MatrixCoefficients,
+};
+use crate::MetricsError;
+use std::any::Any;
 use std::cmp;
+use std::fmt;
+use std::mem::{align_of, size_of};
+use std::slice;
 use v_frame::frame::Frame;
-use v_frame::pixel::CastFromPrimitive;
+use v_frame::pixel::{CastFromPrimitive, PixelType};
 use v_frame::plane::Plane;
 
 /// A trait for allowing metrics to decode generic video formats.
@@ -18,6 +26,22 @@ pub trait Decoder: Send {
     ///
     /// Expected to return `Err` if the end of the video is reached.
     fn read_video_frame<T: Pixel>(&mut self) -> Option<Frame<T>>;
+    /// Read the next frame from the input video into `frame`.
+    ///
+    /// Decoders that can decode in place override this to reuse the buffers of
+    /// `frame` instead of allocating a new one. The default implementation
+    /// replaces `frame` with the result of [`read_video_frame`](Decoder::read_video_frame).
+    ///
+    /// Returns `false` if the end of the video is reached.
+    fn read_video_frame_into<T: Pixel>(&mut self, frame: &mut Frame<T>) -> bool {
+        match self.read_video_frame() {
+            Some(f) => {
+                *frame = f;
+                true
+            }
+            None => false,
+        }
+    }
     /// Read a specific frame from the input video
     ///
     /// Expected to return `Err` if the frame is not found.
@@ -32,14 +56,380 @@ pub trait Decoder: Send {
         }
         None
     }
+    /// Read the next frame from the input video and pass a borrowed view of it to `f`.
+    ///
+    /// Decoders that can expose their own buffers override this to avoid
+    /// copying the frame. The default implementation reads the frame with
+    /// [`read_video_frame`](Decoder::read_video_frame) and borrows from it.
+    ///
+    /// Returns `None` if the end of the video is reached.
+    fn read_video_frame_ref<T: Pixel, R, F: FnOnce(FrameRef<'_, T>) -> R>(
+        &mut self,
+        f: F,
+    ) -> Option<R> {
+        self.read_video_frame::<T>()
+            .map(|frame| f(FrameRef::from(&frame)))
+    }
+    /// Read the next frame from the input video along with its alpha plane.
+    ///
+    /// The alpha plane has the same layout as the luma plane, and is `None`
+    /// if the video has no alpha channel. The default implementation never
+    /// returns one.
+    ///
+    /// Returns `None` if the end of the video is reached.
+    fn read_video_frame_with_alpha<T: Pixel>(&mut self) -> Option<(Frame<T>, Option<Plane<T>>)> {
+        self.read_video_frame().map(|frame| (frame, None))
+    }
     /// Get the bit depth of the video.
     fn get_bit_depth(&self) -> usize;
     /// Get the Video Details
     fn get_video_details(&self) -> VideoDetails;
+    /// Get the codec, container and other metadata of the input.
+    ///
+    /// The default implementation only fills in what can be derived from
+    /// [`get_video_details`](Decoder::get_video_details).
+    fn get_stream_info(&self) -> StreamInfo {
+        StreamInfo::from(&self.get_video_details())
+    }
+    /// Get the position and timestamp of the frame that was read last.
+    ///
+    /// Returns `None` if no frame has been read yet, or if the decoder does
+    /// not track them, which is what the default implementation does.
+    fn last_frame_meta(&self) -> Option<FrameMeta> {
+        None
+    }
+    /// Get the encoder statistics of the frame that was read last, for
+    /// hybrid metrics.
+    ///
+    /// Returns `None` if no frame has been read yet, or if the decoder does
+    /// not know them, which is what the default implementation does. See
+    /// [`WithSideData`](crate::video::side_data::WithSideData) to attach them
+    /// from another source.
+    fn last_side_data(&self) -> Option<SideData> {
+        None
+    }
+    /// Get what the decoder supports beyond reading frames in order.
+    ///
+    /// The default implementation supports nothing, which is always correct
+    /// for the default implementations of the other methods.
+    fn capabilities(&self) -> DecoderCapabilities {
+        DecoderCapabilities::default()
+    }
+    /// Get the number of frames of the video without decoding them.
+    ///
+    /// Returns `None` if the decoder cannot tell, which is what the default
+    /// implementation does.
+    fn frame_count(&self) -> Option<usize> {
+        None
+    }
+    /// Scan the input once to count its frames and record where they are,
+    /// without decoding them.
+    ///
+    /// Afterwards, [`frame_count`](Decoder::frame_count) returns the number of
+    /// frames, and decoders that recorded where the frames are report that
+    /// they can seek. Frames are still read from the current position.
+    ///
+    /// Returns the number of frames, or `None` if the decoder cannot index
+    /// the input, which is what the default implementation does.
+    fn build_index(&mut self) -> Option<usize> {
+        None
+    }
+}
+
+/// What a [`Decoder`] supports beyond reading frames in order, so generic
+/// code can pick the cheapest way to do something.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DecoderCapabilities {
+    /// [`read_specific_frame`](Decoder::read_specific_frame) seeks to the
+    /// frame instead of decoding every frame before it.
+    pub seek: bool,
+    /// [`frame_count`](Decoder::frame_count) returns the number of frames.
+    pub frame_count: bool,
+    /// [`get_stream_info`](Decoder::get_stream_info) and
+    /// [`last_frame_meta`](Decoder::last_frame_meta) report what the input
+    /// says, rather than what can be derived from the video details.
+    pub metadata: bool,
+}
+
+/// An object-safe form of [`Decoder`], for decoders chosen at runtime.
+///
+/// The generic methods of [`Decoder`] are replaced by one method per sample
+/// type. Every [`Decoder`] implements this trait, and `Box<dyn DynDecoder>`
+/// implements [`Decoder`], so a boxed decoder can be passed to any metric:
+///
+/// ```no_run
+/// # use av_metrics::video::decode::DynDecoder;
+/// fn psnr(mut dec1: Box<dyn DynDecoder>, mut dec2: Box<dyn DynDecoder>) {
+///     let psnr = av_metrics::video::psnr::calculate_video_psnr(&mut dec1, &mut dec2, None, |_| ());
+/// }
+/// ```
+pub trait DynDecoder: Send {
+    /// Read the next frame of a video of up to 8 bits.
+    fn read_frame_u8(&mut self) -> Option<Frame<u8>>;
+    /// Read the next frame of a video of more than 8 bits.
+    fn read_frame_u16(&mut self) -> Option<Frame<u16>>;
+    /// Read the next frame of a video of up to 8 bits into `frame`.
+    fn read_frame_into_u8(&mut self, frame: &mut Frame<u8>) -> bool;
+    /// Read the next frame of a video of more than 8 bits into `frame`.
+    fn read_frame_into_u16(&mut self, frame: &mut Frame<u16>) -> bool;
+    /// Read the next frame of a video of up to 8 bits along with its alpha plane.
+    fn read_frame_with_alpha_u8(&mut self) -> Option<(Frame<u8>, Option<Plane<u8>>)>;
+    /// Read the next frame of a video of more than 8 bits along with its alpha plane.
+    fn read_frame_with_alpha_u16(&mut self) -> Option<(Frame<u16>, Option<Plane<u16>>)>;
+    /// Get the bit depth of the video.
+    fn bit_depth(&self) -> usize;
+    /// Get the Video Details
+    fn video_details(&self) -> VideoDetails;
+    /// Get the codec, container and other metadata of the input.
+    fn stream_info(&self) -> StreamInfo;
+    /// Get the position and timestamp of the frame that was read last.
+    fn frame_meta(&self) -> Option<FrameMeta>;
+    /// Get the encoder statistics of the frame that was read last.
+    fn side_data(&self) -> Option<SideData>;
+    /// Get what the decoder supports beyond reading frames in order.
+    fn decoder_capabilities(&self) -> DecoderCapabilities;
+    /// Get the number of frames of the video without decoding them.
+    fn num_frames(&self) -> Option<usize>;
+    /// Scan the input once to count its frames and record where they are.
+    fn index(&mut self) -> Option<usize>;
+}
+
+impl<D: Decoder> DynDecoder for D {
+    fn read_frame_u8(&mut self) -> Option<Frame<u8>> {
+        self.read_video_frame()
+    }
+
+    fn read_frame_u16(&mut self) -> Option<Frame<u16>> {
+        self.read_video_frame()
+    }
+
+    fn read_frame_into_u8(&mut self, frame: &mut Frame<u8>) -> bool {
+        self.read_video_frame_into(frame)
+    }
+
+    fn read_frame_into_u16(&mut self, frame: &mut Frame<u16>) -> bool {
+        self.read_video_frame_into(frame)
+    }
+
+    fn read_frame_with_alpha_u8(&mut self) -> Option<(Frame<u8>, Option<Plane<u8>>)> {
+        self.read_video_frame_with_alpha()
+    }
+
+    fn read_frame_with_alpha_u16(&mut self) -> Option<(Frame<u16>, Option<Plane<u16>>)> {
+        self.read_video_frame_with_alpha()
+    }
+
+    fn bit_depth(&self) -> usize {
+        self.get_bit_depth()
+    }
+
+    fn video_details(&self) -> VideoDetails {
+        self.get_video_details()
+    }
+
+    fn stream_info(&self) -> StreamInfo {
+        self.get_stream_info()
+    }
+
+    fn frame_meta(&self) -> Option<FrameMeta> {
+        self.last_frame_meta()
+    }
+
+    fn side_data(&self) -> Option<SideData> {
+        self.last_side_data()
+    }
+
+    fn decoder_capabilities(&self) -> DecoderCapabilities {
+        self.capabilities()
+    }
+
+    fn num_frames(&self) -> Option<usize> {
+        self.frame_count()
+    }
+
+    fn index(&mut self) -> Option<usize> {
+        self.build_index()
+    }
+}
+
+/// Converts a value of a type known to be `T` at runtime to `T`.
+fn cast_to<U: 'static, T: 'static>(value: U) -> Option<T> {
+    (Box::new(value) as Box<dyn Any>)
+        .downcast::<T>()
+        .ok()
+        .map(|value| *value)
+}
+
+/// Borrows a value of a type known to be `U` at runtime as `U`.
+fn cast_mut<T: 'static, U: 'static>(value: &mut T) -> Option<&mut U> {
+    (value as &mut dyn Any).downcast_mut::<U>()
+}
+
+// The methods are called on the boxed decoder, since the box is a decoder
+// itself.
+impl Decoder for Box<dyn DynDecoder> {
+    fn read_video_frame<T: Pixel>(&mut self) -> Option<Frame<T>> {
+        match T::type_enum() {
+            PixelType::U8 => cast_to((**self).read_frame_u8()?),
+            PixelType::U16 => cast_to((**self).read_frame_u16()?),
+        }
+    }
+
+    fn read_video_frame_into<T: Pixel>(&mut self, frame: &mut Frame<T>) -> bool {
+        match T::type_enum() {
+            PixelType::U8 => {
+                cast_mut(frame).is_some_and(|frame| (**self).read_frame_into_u8(frame))
+            }
+            PixelType::U16 => {
+                cast_mut(frame).is_some_and(|frame| (**self).read_frame_into_u16(frame))
+            }
+        }
+    }
+
+    fn read_video_frame_with_alpha<T: Pixel>(&mut self) -> Option<(Frame<T>, Option<Plane<T>>)> {
+        match T::type_enum() {
+            PixelType::U8 => cast_to((**self).read_frame_with_alpha_u8()?),
+            PixelType::U16 => cast_to((**self).read_frame_with_alpha_u16()?),
+        }
+    }
+
+    fn get_bit_depth(&self) -> usize {
+        (**self).bit_depth()
+    }
+
+    fn get_video_details(&self) -> VideoDetails {
+        (**self).video_details()
+    }
+
+    fn get_stream_info(&self) -> StreamInfo {
+        (**self).stream_info()
+    }
+
+    fn last_frame_meta(&self) -> Option<FrameMeta> {
+        (**self).frame_meta()
+    }
+
+    fn last_side_data(&self) -> Option<SideData> {
+        (**self).side_data()
+    }
+
+    fn capabilities(&self) -> DecoderCapabilities {
+        (**self).decoder_capabilities()
+    }
+
+    fn frame_count(&self) -> Option<usize> {
+        (**self).num_frames()
+    }
+
+    fn build_index(&mut self) -> Option<usize> {
+        (**self).index()
+    }
+}
+
+/// The position and timestamp of a decoded frame.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FrameMeta {
+    /// The position of the frame in the input, starting from 0.
+    pub number: usize,
+    /// The presentation timestamp of the frame, in the time base of the
+    /// container, if it is known.
+    pub pts: Option<i64>,
+    /// The presentation time of the frame in seconds, if it is known.
+    pub time: Option<f64>,
+    /// Whether the frame is a keyframe, if the decoder knows it.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub keyframe: Option<bool>,
+}
+
+impl FrameMeta {
+    /// The metadata of the frame at `number` in a video with a constant
+    /// frame rate, whose timestamps are the frame numbers.
+    pub fn from_number(number: usize, time_base: Rational) -> Self {
+        FrameMeta {
+            number,
+            pts: Some(number as i64),
+            time: Some(number as f64 * time_base.as_f64()),
+            keyframe: None,
+        }
+    }
+}
+
+/// Metadata describing an input, as reported by its decoder.
+/// Fields are `None` when the decoder does not know them.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StreamInfo {
+    /// The name of the codec, such as `h264`.
+    pub codec: Option<String>,
+    /// The name of the container format, such as `matroska,webm`.
+    pub container: Option<String>,
+    /// The duration of the input, in seconds.
+    pub duration: Option<f64>,
+    /// The number of frames per second.
+    pub frame_rate: Option<f64>,
+    /// The pixel format, using FFmpeg's names such as `yuv420p10le`.
+    pub pixel_format: Option<String>,
+}
+
+impl From<&VideoDetails> for StreamInfo {
+    fn from(details: &VideoDetails) -> Self {
+        let frame_rate = 1. / details.time_base.as_f64();
+        StreamInfo {
+            frame_rate: (frame_rate.is_finite() && frame_rate > 0.).then_some(frame_rate),
+            pixel_format: Some(pixel_format_name(
+                details.chroma_sampling,
+                details.bit_depth,
+            )),
+            ..Default::default()
+        }
+    }
+}
+
+impl fmt::Display for StreamInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        match (&self.codec, &self.container) {
+            (Some(codec), Some(container)) => parts.push(format!("{codec} in {container}")),
+            (Some(name), None) | (None, Some(name)) => parts.push(name.clone()),
+            (None, None) => (),
+        }
+        if let Some(pixel_format) = &self.pixel_format {
+            parts.push(pixel_format.clone());
+        }
+        if let Some(frame_rate) = self.frame_rate {
+            parts.push(format!("{frame_rate:.3} fps"));
+        }
+        if let Some(duration) = self.duration {
+            parts.push(format!("{duration:.2} s"));
+        }
+        f.write_str(&parts.join(", "))
+    }
+}
+
+/// The FFmpeg name of the planar YUV pixel format with the given chroma
+/// sampling and bit depth, such as `yuv420p` or `yuv444p10le`.
+pub fn pixel_format_name(chroma_sampling: ChromaSampling, bit_depth: usize) -> String {
+    let base = match chroma_sampling {
+        ChromaSampling::Cs420 => "yuv420p",
+        ChromaSampling::Cs422 => "yuv422p",
+        ChromaSampling::Cs444 => "yuv444p",
+        ChromaSampling::Cs400 => "gray",
+    };
+    if bit_depth > 8 {
+        format!("{base}{bit_depth}le")
+    } else {
+        base.to_owned()
+    }
 }
 
 /// A Structure containing Video Details as per Plane's Config
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct VideoDetails {
     /// Width in pixels.
     pub width: usize,
@@ -55,6 +445,14 @@ pub struct VideoDetails {
     pub time_base: Rational,
     /// Padding Constant
     pub luma_padding: usize,
+    /// The range of sample values.
+    pub color_range: ColorRange,
+    /// The matrix coefficients for converting to RGB.
+    pub matrix_coefficients: MatrixCoefficients,
+    /// How the frames are scanned.
+    pub interlacing: Interlacing,
+    /// The width of a pixel relative to its height, 1:1 for square pixels.
+    pub sample_aspect_ratio: Rational,
 }
 
 impl Default for VideoDetails {
@@ -67,12 +465,174 @@ impl Default for VideoDetails {
             chroma_sample_position: ChromaSamplePosition::Unknown,
             time_base: Rational { num: 30, den: 1 },
             luma_padding: 0,
+            color_range: ColorRange::Limited,
+            matrix_coefficients: MatrixCoefficients::Bt709,
+            interlacing: Interlacing::Progressive,
+            sample_aspect_ratio: Rational { num: 1, den: 1 },
+        }
+    }
+}
+
+/// Video details that replace the values reported by a decoder, for inputs
+/// whose metadata is missing or wrong. Fields left as `None` are not changed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DetailsOverride {
+    /// Replaces the range of sample values.
+    pub color_range: Option<ColorRange>,
+    /// Replaces the matrix coefficients.
+    pub matrix_coefficients: Option<MatrixCoefficients>,
+    /// Replaces the chroma sample position. Decoders reposition the chroma
+    /// of each frame according to this value.
+    pub chroma_sample_position: Option<ChromaSamplePosition>,
+}
+
+impl DetailsOverride {
+    /// Replaces the fields of `details` that are set in this override.
+    pub fn apply(&self, details: &mut VideoDetails) {
+        if let Some(color_range) = self.color_range {
+            details.color_range = color_range;
+        }
+        if let Some(matrix_coefficients) = self.matrix_coefficients {
+            details.matrix_coefficients = matrix_coefficients;
+        }
+        if let Some(chroma_sample_position) = self.chroma_sample_position {
+            details.chroma_sample_position = chroma_sample_position;
+        }
+    }
+}
+
+/// A read-only view of the visible area of one plane.
+///
+/// Unlike a [`Plane`], the pixels may be borrowed from a buffer owned by a decoder.
+#[derive(Debug, Clone, Copy)]
+pub struct PlaneRef<'a, T: Pixel> {
+    data: &'a [T],
+    stride: usize,
+    width: usize,
+    height: usize,
+}
+
+impl<'a, T: Pixel> PlaneRef<'a, T> {
+    /// Creates a view of `height` rows of `width` pixels, where each row starts
+    /// `stride` pixels after the previous one.
+    ///
+    /// Returns `None` if `data` is too short to hold the plane.
+    pub fn new(data: &'a [T], stride: usize, width: usize, height: usize) -> Option<Self> {
+        if width == 0 || height == 0 {
+            return Some(PlaneRef {
+                data: &[],
+                stride,
+                width,
+                height,
+            });
+        }
+        let len = (height - 1).checked_mul(stride)?.checked_add(width)?;
+        if stride < width || data.len() < len {
+            return None;
+        }
+        Some(PlaneRef {
+            data,
+            stride,
+            width,
+            height,
+        })
+    }
+
+    /// Creates a view of raw little-endian samples without copying them.
+    ///
+    /// `source_stride` is in bytes. Returns `None` if the samples cannot be
+    /// reinterpreted as `T` in place, because `source_bytewidth` does not
+    /// match the size of `T` or the buffer is not aligned for `T`.
+    pub fn from_raw_u8(
+        source: &'a [u8],
+        source_stride: usize,
+        source_bytewidth: usize,
+        width: usize,
+        height: usize,
+    ) -> Option<Self> {
+        if source_bytewidth != size_of::<T>() || !source_stride.is_multiple_of(size_of::<T>()) {
+            return None;
+        }
+        if width == 0 || height == 0 {
+            return PlaneRef::new(&[], source_stride / size_of::<T>(), width, height);
+        }
+        if (size_of::<T>() > 1 && cfg!(target_endian = "big"))
+            || source.as_ptr().align_offset(align_of::<T>()) != 0
+        {
+            return None;
+        }
+        // SAFETY: `T` is either `u8` or `u16`, which are valid for any bit pattern,
+        // and the pointer was checked to be aligned for `T`.
+        let data = unsafe {
+            slice::from_raw_parts(source.as_ptr() as *const T, source.len() / size_of::<T>())
+        };
+        PlaneRef::new(data, source_stride / size_of::<T>(), width, height)
+    }
+
+    /// Width of the plane in pixels.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Height of the plane in pixels.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Distance in pixels between the start of two consecutive rows.
+    pub fn stride(&self) -> usize {
+        self.stride
+    }
+
+    /// Returns the visible pixels of row `y`.
+    pub fn row(&self, y: usize) -> &'a [T] {
+        let start = y * self.stride;
+        &self.data[start..start + self.width]
+    }
+
+    /// Iterates over the visible pixels of each row.
+    pub fn rows(&self) -> impl Iterator<Item = &'a [T]> + 'a {
+        let plane = *self;
+        (0..self.height).map(move |y| plane.row(y))
+    }
+}
+
+impl<'a, T: Pixel> From<&'a Plane<T>> for PlaneRef<'a, T> {
+    fn from(plane: &'a Plane<T>) -> Self {
+        PlaneRef {
+            data: plane.data_origin(),
+            stride: plane.cfg.stride,
+            width: plane.cfg.width,
+            height: plane.cfg.height,
+        }
+    }
+}
+
+/// A read-only view of the three planes of a frame.
+///
+/// See [`Decoder::read_video_frame_ref`].
+#[derive(Debug, Clone, Copy)]
+pub struct FrameRef<'a, T: Pixel> {
+    /// The Y, U and V planes.
+    pub planes: [PlaneRef<'a, T>; 3],
+}
+
+impl<'a, T: Pixel> From<&'a Frame<T>> for FrameRef<'a, T> {
+    fn from(frame: &'a Frame<T>) -> Self {
+        FrameRef {
+            planes: [
+                PlaneRef::from(&frame.planes[0]),
+                PlaneRef::from(&frame.planes[1]),
+                PlaneRef::from(&frame.planes[2]),
+            ],
         }
     }
 }
 
 /// A rational number.
 #[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(C)]
 pub struct Rational {
     /// Numerator.
@@ -99,6 +659,84 @@ impl Rational {
     }
 }
 
+/// Returns `true` if a frame previously read from a video with the given details
+/// can be decoded into again, rather than allocating a new one.
+pub fn is_frame_reusable<T: Pixel>(frame: &Frame<T>, details: &VideoDetails) -> bool {
+    let (xdec, ydec) = details.chroma_sampling.get_decimation().unwrap_or((0, 0));
+    let (chroma_width, chroma_height) = details
+        .chroma_sampling
+        .get_chroma_dimensions(details.width, details.height);
+    let luma = &frame.planes[0].cfg;
+    luma.width >= details.width
+        && luma.height >= details.height
+        && frame.planes[1..].iter().all(|plane| {
+            plane.cfg.xdec == xdec
+                && plane.cfg.ydec == ydec
+                && plane.cfg.width >= chroma_width
+                && plane.cfg.height >= chroma_height
+        })
+}
+
+/// Copies the visible samples of `plane` from `source`, where each row starts
+/// `stride` samples after the previous one.
+///
+/// Returns an error instead of panicking if `source` is too short for the
+/// dimensions of the plane or `stride` is narrower than the plane.
+pub fn copy_plane_from_slice<T: Pixel>(
+    plane: &mut Plane<T>,
+    source: &[T],
+    stride: usize,
+) -> Result<(), MetricsError> {
+    let width = plane.cfg.width;
+    let source = PlaneRef::new(source, stride, width, plane.cfg.height).ok_or(
+        MetricsError::MalformedInput {
+            reason: "Plane data is too short for its dimensions and stride",
+        },
+    )?;
+    for (dst, src) in plane.rows_iter_mut().zip(source.rows()) {
+        dst[..width].copy_from_slice(src);
+    }
+    Ok(())
+}
+
+/// Copies the visible samples of `plane` from raw little-endian samples of
+/// `source_bytewidth` bytes, where each row starts `source_stride` bytes after
+/// the previous one.
+///
+/// This is a checked alternative to [`Plane::copy_from_raw_u8`], which panics
+/// on a byte width that does not fit `T` and skips the last rows when
+/// `source` is too short.
+pub fn copy_plane_from_raw_u8<T: Pixel>(
+    plane: &mut Plane<T>,
+    source: &[u8],
+    source_stride: usize,
+    source_bytewidth: usize,
+) -> Result<(), MetricsError> {
+    if !matches!(source_bytewidth, 1 | 2) || source_bytewidth > size_of::<T>() {
+        return Err(MetricsError::MalformedInput {
+            reason: "Sample byte width does not fit the pixel type",
+        });
+    }
+    let row_bytes = plane.cfg.width * source_bytewidth;
+    let source = PlaneRef::new(source, source_stride, row_bytes, plane.cfg.height).ok_or(
+        MetricsError::MalformedInput {
+            reason: "Plane data is too short for its dimensions and stride",
+        },
+    )?;
+    for (dst, src) in plane.rows_iter_mut().zip(source.rows()) {
+        if source_bytewidth == 1 {
+            for (pixel, &sample) in dst.iter_mut().zip(src) {
+                *pixel = T::cast_from(sample);
+            }
+        } else {
+            for (pixel, sample) in dst.iter_mut().zip(src.chunks_exact(2)) {
+                *pixel = T::cast_from(u16::from_le_bytes([sample[0], sample[1]]));
+            }
+        }
+    }
+    Ok(())
+}
+
 /// The algorithms (as ported from daala-tools) expect a colocated or bilaterally located chroma
 /// sample position. This means that a vertical chroma sample position must be realigned
 /// in order to produce a correct result.
@@ -129,13 +767,19 @@ pub fn convert_chroma_data<T: Pixel>(
         convert_u16
     };
 
-    let output_data = &mut plane_data.data;
-    let width = plane_data.cfg.width;
-    let height = plane_data.cfg.height;
+    // The plane may be padded beyond the samples of the video, which are
+    // the only ones converted.
+    let width = cmp::min(plane_data.cfg.width, source_stride / source_bytewidth);
+    let height = cmp::min(
+        plane_data.cfg.height,
+        source.len().checked_div(source_stride).unwrap_or(0),
+    );
+    let stride = plane_data.cfg.stride;
+    let output_data = plane_data.data_origin_mut();
     for y in 0..height {
         // Filter: [4 -17 114 35 -9 1]/128, derived from a 6-tap Lanczos window.
         let in_row = &source[(y * source_stride)..];
-        let out_row = &mut output_data[(y * width)..];
+        let out_row = &mut output_data[(y * stride)..];
         let breakpoint = cmp::min(width, 2);
         for x in 0..breakpoint {
             out_row[x] = T::cast_from(clamp(
@@ -150,7 +794,8 @@ pub fn convert_chroma_data<T: Pixel>(
                 (1 << bit_depth) - 1,
             ));
         }
-        let breakpoint2 = width - 3;
+        // Planes narrower than the filter only use the clamped edge filters.
+        let breakpoint2 = width.saturating_sub(3).max(breakpoint);
         for x in breakpoint..breakpoint2 {
             out_row[x] = T::cast_from(clamp(
                 (4 * get_pixel(in_row, x - 2) - 17 * get_pixel(in_row, x - 1)
@@ -166,7 +811,8 @@ pub fn convert_chroma_data<T: Pixel>(
         }
         for x in breakpoint2..width {
             out_row[x] = T::cast_from(clamp(
-                (4 * get_pixel(in_row, x - 2) - 17 * get_pixel(in_row, x - 1)
+                (4 * get_pixel(in_row, x.saturating_sub(2))
+                    - 17 * get_pixel(in_row, x.saturating_sub(1))
                     + 114 * get_pixel(in_row, x)
                     + 35 * get_pixel(in_row, cmp::min(x + 1, width - 1))
                     - 9 * get_pixel(in_row, cmp::min(x + 2, width - 1))
@@ -0,0 +1,88 @@
+//! Aggregation of per-frame scores by group of pictures.
+//!
+//! Rate control problems in an encoder usually show up as a whole GOP, from
+//! one keyframe to the next, scoring lower than its neighbours, or as quality
+//! dropping towards the end of each GOP. Grouping per-frame scores by GOP
+//! makes both visible, where the average over the video hides them.
+//!
+//! The keyframe flags come from [`FrameMeta::keyframe`], which is only known
+//! for decoders that report it, such as FFmpeg.
+//!
+//! [`FrameMeta::keyframe`]: crate::video::decode::FrameMeta::keyframe
+
+/// The scores of the frames of one GOP.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GopScores {
+    /// The number of the keyframe starting the GOP.
+    pub start_frame: usize,
+    /// The number of frames in the GOP.
+    pub frames: usize,
+    /// The mean score of the frames.
+    pub mean: f64,
+    /// The lowest score of the frames.
+    pub min: f64,
+    /// The number of the frame with the lowest score.
+    pub min_frame: usize,
+}
+
+/// Groups the scores of consecutive frames by GOP.
+///
+/// A new GOP starts at every keyframe. The first frame always starts a GOP,
+/// even if it is not a keyframe, since the video may have been cut.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GopAggregator {
+    gops: Vec<GopScores>,
+    frames: usize,
+    sum: f64,
+}
+
+impl GopAggregator {
+    /// Creates an aggregator with no frames.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the score of the next frame.
+    pub fn push(&mut self, keyframe: bool, score: f64) {
+        let frame = self.frames;
+        self.frames += 1;
+        match self.gops.last_mut() {
+            Some(gop) if !keyframe => {
+                self.sum += score;
+                gop.frames += 1;
+                gop.mean = self.sum / gop.frames as f64;
+                if score < gop.min {
+                    gop.min = score;
+                    gop.min_frame = frame;
+                }
+            }
+            _ => {
+                self.sum = score;
+                self.gops.push(GopScores {
+                    start_frame: frame,
+                    frames: 1,
+                    mean: score,
+                    min: score,
+                    min_frame: frame,
+                });
+            }
+        }
+    }
+
+    /// The scores of each GOP, in order.
+    pub fn finish(self) -> Vec<GopScores> {
+        self.gops
+    }
+}
+
+/// Groups the scores of consecutive frames, given with whether each frame is
+/// a keyframe, by GOP.
+pub fn aggregate_by_gop<I: IntoIterator<Item = (bool, f64)>>(scores: I) -> Vec<GopScores> {
+    let mut aggregator = GopAggregator::new();
+    for (keyframe, score) in scores {
+        aggregator.push(keyframe, score);
+    }
+    aggregator.finish()
+}
@@ -0,0 +1,63 @@
+//! The distribution of per-frame scores.
+//!
+//! Two videos with the same average can have very different scores frame by
+//! frame: one steady, the other alternating between great and poor frames. A
+//! histogram of the per-frame scores tells them apart at a glance.
+
+/// The number of frames whose score falls in each of a number of equally
+/// wide bins, from the lowest score to the highest.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Histogram {
+    /// The lowest score, where the first bin starts.
+    pub min: f64,
+    /// The highest score, where the last bin ends.
+    pub max: f64,
+    /// The number of frames in each bin.
+    pub counts: Vec<usize>,
+}
+
+impl Histogram {
+    /// Counts the scores of consecutive frames into `bins` bins.
+    ///
+    /// Scores that are not finite, such as the infinite PSNR of identical
+    /// frames in FFmpeg compatible mode, are not counted. If every score is
+    /// the same, they are all counted in the first bin.
+    pub fn new<I: IntoIterator<Item = f64>>(scores: I, bins: usize) -> Self {
+        let scores: Vec<f64> = scores.into_iter().filter(|s| s.is_finite()).collect();
+        let mut counts = vec![0; bins];
+        if scores.is_empty() || bins == 0 {
+            return Histogram {
+                counts,
+                ..Default::default()
+            };
+        }
+
+        let min = scores.iter().copied().fold(f64::INFINITY, f64::min);
+        let max = scores.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        let width = (max - min) / bins as f64;
+        for score in scores {
+            let bin = if width > 0.0 {
+                ((score - min) / width) as usize
+            } else {
+                0
+            };
+            // The highest score ends the last bin
+            counts[bin.min(bins - 1)] += 1;
+        }
+        Histogram { min, max, counts }
+    }
+
+    /// The width of each bin.
+    pub fn bin_width(&self) -> f64 {
+        if self.counts.is_empty() {
+            return 0.0;
+        }
+        (self.max - self.min) / self.counts.len() as f64
+    }
+
+    /// The number of frames counted.
+    pub fn frames(&self) -> usize {
+        self.counts.iter().sum()
+    }
+}
@@ -0,0 +1,200 @@
+//! Bit-exact comparison of two videos.
+//!
+//! Checking that an encode is lossless does not need a metric: it is enough
+//! to find whether any sample differs. Rows are compared as slices, which
+//! stops at the first difference, so this is much faster than computing
+//! PSNR and also reports where the first difference is.
+
+use crate::video::decode::{Decoder, PlaneRef};
+use crate::video::pixel::{CastFromPrimitive, Pixel};
+use crate::video::{ChromaSampling, FrameContext};
+use crate::{MetricsError, PROGRESS_DONE};
+use std::error::Error;
+use v_frame::frame::Frame;
+
+/// The result of a bit-exact comparison of two videos.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LosslessResult {
+    /// The number of frames that were compared and found identical.
+    pub frames: usize,
+    /// The first difference between the videos, if any.
+    pub mismatch: Option<Mismatch>,
+}
+
+impl LosslessResult {
+    /// Whether the videos are identical.
+    pub fn is_lossless(&self) -> bool {
+        self.mismatch.is_none()
+    }
+}
+
+/// The first difference found between two videos.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Mismatch {
+    /// A sample differs between the frames of both videos.
+    Sample {
+        /// The number of the frame, starting from 0.
+        frame: usize,
+        /// The plane, 0 for luma and 1 and 2 for chroma.
+        plane: usize,
+        /// The column of the sample in the plane.
+        x: usize,
+        /// The row of the sample in the plane.
+        y: usize,
+        /// The value of the sample in the first video.
+        value1: i32,
+        /// The value of the sample in the second video.
+        value2: i32,
+    },
+    /// One video ended before the other. `frame` is the number of the first
+    /// frame that only one of the videos has.
+    Length {
+        /// The number of the first frame missing from one of the videos.
+        frame: usize,
+    },
+}
+
+/// Checks whether two videos are bit-exact, stopping at the first difference.
+///
+/// When `frame_limit` is given, only that many frames are compared, and the
+/// videos are not required to have the same length beyond it.
+pub fn verify_video_lossless<D: Decoder, F: Fn(usize) + Send>(
+    decoder1: &mut D,
+    decoder2: &mut D,
+    frame_limit: Option<usize>,
+    progress_callback: F,
+) -> Result<LosslessResult, Box<dyn Error>> {
+    if decoder1.get_bit_depth() != decoder2.get_bit_depth() {
+        return Err(Box::new(MetricsError::InputMismatch {
+            reason: "Bit depths do not match",
+        }));
+    }
+    if decoder1.get_video_details().chroma_sampling != decoder2.get_video_details().chroma_sampling
+    {
+        return Err(Box::new(MetricsError::InputMismatch {
+            reason: "Chroma samplings do not match",
+        }));
+    }
+
+    if decoder1.get_bit_depth() > 8 {
+        process_video::<D, u16, F>(decoder1, decoder2, frame_limit, progress_callback)
+    } else {
+        process_video::<D, u8, F>(decoder1, decoder2, frame_limit, progress_callback)
+    }
+}
+
+/// Finds the first sample that differs between two frames, as
+/// `(plane, x, y, value1, value2)`.
+///
+/// The frames must have the same dimensions.
+pub fn find_frame_mismatch<T: Pixel>(
+    frame1: &Frame<T>,
+    frame2: &Frame<T>,
+    chroma_sampling: ChromaSampling,
+) -> Option<(usize, usize, usize, i32, i32)> {
+    let planes = if chroma_sampling == ChromaSampling::Cs400 {
+        1
+    } else {
+        3
+    };
+    for (plane, (plane1, plane2)) in frame1
+        .planes
+        .iter()
+        .zip(frame2.planes.iter())
+        .take(planes)
+        .enumerate()
+    {
+        let rows1 = PlaneRef::from(plane1).rows();
+        let rows2 = PlaneRef::from(plane2).rows();
+        for (y, (row1, row2)) in rows1.zip(rows2).enumerate() {
+            if row1 == row2 {
+                continue;
+            }
+            let x = row1
+                .iter()
+                .zip(row2.iter())
+                .position(|(a, b)| a != b)
+                .unwrap_or_default();
+            return Some((
+                plane,
+                x,
+                y,
+                i32::cast_from(row1[x]),
+                i32::cast_from(row2[x]),
+            ));
+        }
+    }
+    None
+}
+
+/// Reads the next frame into `slot`, reusing its buffers after the first
+/// frame.
+fn read_next<D: Decoder, P: Pixel>(decoder: &mut D, slot: &mut Option<Frame<P>>) -> bool {
+    match slot {
+        Some(frame) => decoder.read_video_frame_into(frame),
+        None => {
+            *slot = decoder.read_video_frame();
+            slot.is_some()
+        }
+    }
+}
+
+fn process_video<D: Decoder, P: Pixel, F: Fn(usize) + Send>(
+    decoder1: &mut D,
+    decoder2: &mut D,
+    frame_limit: Option<usize>,
+    progress_callback: F,
+) -> Result<LosslessResult, Box<dyn Error>> {
+    let details = decoder1.get_video_details();
+    let context = FrameContext::try_from(&details)?;
+
+    let mut result = LosslessResult::default();
+    let mut frame1: Option<Frame<P>> = None;
+    let mut frame2: Option<Frame<P>> = None;
+    while frame_limit
+        .map(|limit| limit > result.frames)
+        .unwrap_or(true)
+    {
+        match (
+            read_next(decoder1, &mut frame1),
+            read_next(decoder2, &mut frame2),
+        ) {
+            (true, true) => (),
+            (false, false) => break,
+            _ => {
+                result.mismatch = Some(Mismatch::Length {
+                    frame: result.frames,
+                });
+                break;
+            }
+        }
+        let (frame1, frame2) = (frame1.as_ref().unwrap(), frame2.as_ref().unwrap());
+        context.validate(frame1, frame2)?;
+        if let Some((plane, x, y, value1, value2)) =
+            find_frame_mismatch(frame1, frame2, details.chroma_sampling)
+        {
+            result.mismatch = Some(Mismatch::Sample {
+                frame: result.frames,
+                plane,
+                x,
+                y,
+                value1,
+                value2,
+            });
+            break;
+        }
+        result.frames += 1;
+        progress_callback(result.frames);
+    }
+    progress_callback(PROGRESS_DONE);
+
+    if result.frames == 0 && result.mismatch.is_none() {
+        return Err(MetricsError::UnsupportedInput {
+            reason: "No readable frames found in one or more input files",
+        }
+        .into());
+    }
+    Ok(result)
+}
@@ -1,16 +1,51 @@
 //! Contains metrics related to video/image quality.
 
+#[macro_use]
+mod options;
+
+pub mod align;
+pub mod all;
+pub mod alpha;
+pub mod artifacts;
+pub mod banding;
+pub mod bootstrap;
 pub mod ciede;
+pub mod color;
 pub mod decode;
+pub mod gop;
+pub mod histogram;
+pub mod lossless;
+pub mod noref;
+pub mod pairing;
+mod pipeline;
 mod pixel;
 pub mod psnr;
+pub mod psnr_b;
 pub mod psnr_hvs;
+pub mod rate_control;
+pub mod scale;
+pub mod side_data;
 pub mod ssim;
+mod svm;
+pub mod synth;
+pub mod temporal;
+pub mod tiles;
+pub mod vmaf;
+pub mod vmaf_features;
+pub mod worst;
 
 use crate::MetricsError;
 use decode::*;
+use options::Limits;
+use side_data::SideData;
 use std::error::Error;
+use std::mem::size_of;
+use std::time::Instant;
 
+pub use options::{LengthMismatch, VideoOptions};
+pub use pipeline::{
+    FrameSink, PipelineControl, PipelineMonitor, PipelineStats, Score, ScoredFrame,
+};
 pub use pixel::*;
 pub use v_frame::frame::Frame;
 pub use v_frame::plane::Plane;
@@ -21,9 +56,14 @@ trait FrameCompare {
 
 impl<T: Pixel> FrameCompare for Frame<T> {
     fn can_compare(&self, other: &Self) -> Result<(), MetricsError> {
-        self.planes[0].can_compare(&other.planes[0])?;
-        self.planes[1].can_compare(&other.planes[1])?;
-        self.planes[2].can_compare(&other.planes[2])?;
+        for (plane, (plane1, plane2)) in self.planes.iter().zip(other.planes.iter()).enumerate() {
+            plane1
+                .can_compare(plane2)
+                .map_err(|_| MetricsError::PlaneMismatch {
+                    plane,
+                    reason: "Plane dimensions do not match",
+                })?;
+        }
 
         Ok(())
     }
@@ -64,6 +104,7 @@ impl ChromaWeight for ChromaSampling {
 
 /// Sample position for subsampled chroma
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ChromaSamplePosition {
     /// The source video transfer function is not signaled. This crate will assume
     /// no transformation needs to be done on this data, but there is a risk of metric
@@ -81,10 +122,250 @@ pub enum ChromaSamplePosition {
     Interpolated,
 }
 
+/// How the frames of a video are scanned.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Interlacing {
+    /// Each frame is scanned as a whole.
+    #[default]
+    Progressive,
+    /// Each frame holds two fields, the top one first.
+    TopFieldFirst,
+    /// Each frame holds two fields, the bottom one first.
+    BottomFieldFirst,
+    /// The scan changes from frame to frame.
+    Mixed,
+}
+
+/// The range of sample values used by a video.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ColorRange {
+    /// Luma uses 16-235 and chroma 16-240, scaled to the bit depth.
+    #[default]
+    Limited,
+    /// Luma and chroma use every value of the bit depth.
+    Full,
+}
+
+/// The matrix coefficients used to convert between YUV and RGB.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MatrixCoefficients {
+    /// ITU-R BT.709, used by most HD content.
+    #[default]
+    Bt709,
+    /// ITU-R BT.601, used by SD content.
+    Bt601,
+    /// ITU-R BT.2020 non-constant luminance, used by UHD and HDR content.
+    Bt2020,
+}
+
+impl MatrixCoefficients {
+    /// The red and blue luma weights `(Kr, Kb)` of the matrix.
+    pub fn luma_weights(self) -> (f64, f64) {
+        match self {
+            MatrixCoefficients::Bt709 => (0.2126, 0.0722),
+            MatrixCoefficients::Bt601 => (0.299, 0.114),
+            MatrixCoefficients::Bt2020 => (0.2627, 0.0593),
+        }
+    }
+}
+
+/// The format of the frames passed to a frame-level metric.
+///
+/// Frame-level metrics check the frames they are given against this,
+/// so a bit depth or chroma sampling that disagrees with the frames
+/// results in an error instead of a meaningless score.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "FrameContextFields"))]
+pub struct FrameContext {
+    bit_depth: usize,
+    chroma_sampling: ChromaSampling,
+}
+
+/// The fields of a deserialized [`FrameContext`], which are checked by
+/// [`FrameContext::new`].
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct FrameContextFields {
+    bit_depth: usize,
+    chroma_sampling: ChromaSampling,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<FrameContextFields> for FrameContext {
+    type Error = MetricsError;
+
+    fn try_from(fields: FrameContextFields) -> Result<Self, MetricsError> {
+        FrameContext::new(fields.bit_depth, fields.chroma_sampling)
+    }
+}
+
+impl FrameContext {
+    /// Creates a new frame context. Bit depths from 8 to 16 are supported.
+    pub fn new(bit_depth: usize, chroma_sampling: ChromaSampling) -> Result<Self, MetricsError> {
+        if !(8..=16).contains(&bit_depth) {
+            return Err(MetricsError::UnsupportedInput {
+                reason: "Bit depth must be between 8 and 16",
+            });
+        }
+        Ok(FrameContext {
+            bit_depth,
+            chroma_sampling,
+        })
+    }
+
+    /// The bit depth of the frames.
+    pub fn bit_depth(self) -> usize {
+        self.bit_depth
+    }
+
+    /// The chroma sampling of the frames.
+    pub fn chroma_sampling(self) -> ChromaSampling {
+        self.chroma_sampling
+    }
+
+    /// Checks that two frames match this context and can be compared to each other.
+    pub fn validate<T: Pixel>(
+        self,
+        frame1: &Frame<T>,
+        frame2: &Frame<T>,
+    ) -> Result<(), MetricsError> {
+        self.validate_pixel::<T>()?;
+        self.validate_planes(frame1)?;
+        frame1.can_compare(frame2)
+    }
+
+    /// Checks that two borrowed frames match this context and can be compared
+    /// to each other.
+    pub fn validate_ref<T: Pixel>(
+        self,
+        frame1: &FrameRef<T>,
+        frame2: &FrameRef<T>,
+    ) -> Result<(), MetricsError> {
+        self.validate_pixel::<T>()?;
+        if let Some((xdec, ydec)) = self.chroma_sampling.get_decimation() {
+            let luma = &frame1.planes[0];
+            for plane in 1..3 {
+                let chroma = &frame1.planes[plane];
+                if chroma.width() != (luma.width() + xdec) >> xdec
+                    || chroma.height() != (luma.height() + ydec) >> ydec
+                {
+                    return Err(MetricsError::PlaneMismatch {
+                        plane,
+                        reason: "Chroma plane does not match chroma sampling",
+                    });
+                }
+            }
+        }
+        for (plane, (plane1, plane2)) in frame1.planes.iter().zip(frame2.planes.iter()).enumerate()
+        {
+            if plane1.width() != plane2.width() || plane1.height() != plane2.height() {
+                return Err(MetricsError::PlaneMismatch {
+                    plane,
+                    reason: "Plane dimensions do not match",
+                });
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn validate_pixel<T: Pixel>(self) -> Result<(), MetricsError> {
+        if (size_of::<T>() == 1) != (self.bit_depth == 8) {
+            return Err(MetricsError::InputMismatch {
+                reason: "Bit depths does not match pixel width",
+            });
+        }
+        Ok(())
+    }
+
+    fn validate_planes<T: Pixel>(self, frame: &Frame<T>) -> Result<(), MetricsError> {
+        // Monochrome frames may carry chroma planes of any size, which are ignored.
+        let (xdec, ydec) = match self.chroma_sampling.get_decimation() {
+            Some(dec) => dec,
+            None => return Ok(()),
+        };
+        let luma = &frame.planes[0].cfg;
+        for plane in 1..3 {
+            let cfg = &frame.planes[plane].cfg;
+            if cfg.xdec != xdec
+                || cfg.ydec != ydec
+                || cfg.width != (luma.width + xdec) >> xdec
+                || cfg.height != (luma.height + ydec) >> ydec
+            {
+                return Err(MetricsError::PlaneMismatch {
+                    plane,
+                    reason: "Chroma plane does not match chroma sampling",
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Builds a frame from the samples of its Y, U and V planes, for frames that
+/// do not come from a [`Decoder`].
+///
+/// Each plane is given as rows of `strides[i]` samples, which start with the
+/// visible samples of the row. The chroma planes have the dimensions implied
+/// by `chroma_sampling`. They are ignored for 4:0:0 and may be empty.
+///
+/// ```
+/// use av_metrics::video::{frame_from_planes, ChromaSampling};
+///
+/// let y = [16u8; 4 * 2];
+/// let uv = [128u8; 2];
+/// let frame = frame_from_planes(4, 2, ChromaSampling::Cs420, [&y, &uv, &uv], [4, 2, 2])?;
+/// assert_eq!(16, frame.planes[0].p(3, 1));
+/// # Ok::<(), av_metrics::MetricsError>(())
+/// ```
+pub fn frame_from_planes<T: Pixel>(
+    width: usize,
+    height: usize,
+    chroma_sampling: ChromaSampling,
+    planes: [&[T]; 3],
+    strides: [usize; 3],
+) -> Result<Frame<T>, MetricsError> {
+    if width == 0 || height == 0 {
+        return Err(MetricsError::UnsupportedInput {
+            reason: "Frame dimensions must be non-zero",
+        });
+    }
+    let (chroma_width, chroma_height) = chroma_sampling.get_chroma_dimensions(width, height);
+    let dimensions = [
+        (width, height),
+        (chroma_width, chroma_height),
+        (chroma_width, chroma_height),
+    ];
+    let mut frame = Frame::new_with_padding(width, height, chroma_sampling, 0);
+    for (plane, (dst, (width, height))) in frame.planes.iter_mut().zip(dimensions).enumerate() {
+        let src = PlaneRef::new(planes[plane], strides[plane], width, height).ok_or(
+            MetricsError::PlaneMismatch {
+                plane,
+                reason: "Plane is too short for its dimensions and stride",
+            },
+        )?;
+        for (dst_row, src_row) in dst.rows_iter_mut().zip(src.rows()) {
+            dst_row[..width].copy_from_slice(src_row);
+        }
+    }
+    Ok(frame)
+}
+
+impl TryFrom<&VideoDetails> for FrameContext {
+    type Error = MetricsError;
+
+    fn try_from(details: &VideoDetails) -> Result<Self, Self::Error> {
+        FrameContext::new(details.bit_depth, details.chroma_sampling)
+    }
+}
+
 /// Certain metrics return a value per plane. This struct contains the output
 /// for those metrics per plane, as well as a weighted average of the planes.
 #[derive(Debug, Default, Clone, Copy, PartialEq)]
-#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct PlanarMetrics {
     /// Metric value for the Y plane.
     pub y: f64,
@@ -94,6 +375,207 @@ pub struct PlanarMetrics {
     pub v: f64,
     /// Weighted average of the three planes.
     pub avg: f64,
+    /// Metric value for the alpha plane, if it was compared.
+    /// It is not included in the average.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub alpha: Option<f64>,
+    /// Structural dissimilarity of the weighted average, `1 / SSIM - 1` on
+    /// the SSIM before it is converted to decibels. It is 0 for identical
+    /// inputs and grows without bound as they differ. Only reported by SSIM
+    /// and MS-SSIM.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub dssim: Option<f64>,
+}
+
+/// A set of planes to compute a metric on.
+///
+/// Planes that are not selected are not computed at all. They are reported
+/// as 0 and left out of the average. Sets can be combined with `|`:
+///
+/// ```
+/// use av_metrics::video::PlaneSelect;
+///
+/// let planes = PlaneSelect::Y | PlaneSelect::V;
+/// assert!(planes.contains(PlaneSelect::V));
+/// assert!(!planes.contains(PlaneSelect::CHROMA));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PlaneSelect(u8);
+
+impl PlaneSelect {
+    /// The Y plane.
+    pub const Y: PlaneSelect = PlaneSelect(1);
+    /// The U/Cb plane.
+    pub const U: PlaneSelect = PlaneSelect(1 << 1);
+    /// The V/Cr plane.
+    pub const V: PlaneSelect = PlaneSelect(1 << 2);
+    /// Both chroma planes.
+    pub const CHROMA: PlaneSelect = PlaneSelect(Self::U.0 | Self::V.0);
+    /// All three planes. This is the default.
+    pub const ALL: PlaneSelect = PlaneSelect(Self::Y.0 | Self::CHROMA.0);
+    /// No planes.
+    pub const NONE: PlaneSelect = PlaneSelect(0);
+
+    /// Whether every plane of `other` is selected.
+    pub const fn contains(self, other: PlaneSelect) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Whether the plane with the given index, 0 for Y to 2 for V, is selected.
+    pub const fn has_plane(self, index: usize) -> bool {
+        index < 3 && self.0 & (1 << index) != 0
+    }
+
+    /// Whether no plane is selected.
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl Default for PlaneSelect {
+    fn default() -> Self {
+        PlaneSelect::ALL
+    }
+}
+
+impl std::ops::BitOr for PlaneSelect {
+    type Output = PlaneSelect;
+
+    fn bitor(self, rhs: PlaneSelect) -> PlaneSelect {
+        PlaneSelect(self.0 | rhs.0)
+    }
+}
+
+impl std::ops::BitOrAssign for PlaneSelect {
+    fn bitor_assign(&mut self, rhs: PlaneSelect) {
+        self.0 |= rhs.0;
+    }
+}
+
+impl std::ops::BitAnd for PlaneSelect {
+    type Output = PlaneSelect;
+
+    fn bitand(self, rhs: PlaneSelect) -> PlaneSelect {
+        PlaneSelect(self.0 & rhs.0)
+    }
+}
+
+/// The conventions followed when computing and aggregating a metric.
+///
+/// Implementations of the same metric differ in small ways, which makes
+/// scores from different tools hard to compare. The default follows the
+/// conventions of this crate; the others match another tool as closely as
+/// possible.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CompatMode {
+    /// The conventions of this crate.
+    #[default]
+    Native,
+    /// The conventions of FFmpeg's `psnr` and `ssim` filters.
+    ///
+    /// - PSNR is not capped, so identical planes score infinity. The
+    ///   aggregation is otherwise the same as in this crate: the mean
+    ///   squared error of each plane is averaged over the frames, and the
+    ///   average is weighted by the size of the planes.
+    /// - SSIM is computed over unweighted 8x8 windows placed every 4 samples,
+    ///   as in x264, instead of a Gaussian window. Like in FFmpeg, each window
+    ///   of an 8-bit input is scored in single precision. The score of a video
+    ///   is the mean of the scores of its frames, which this crate also uses.
+    Ffmpeg,
+}
+
+/// How the planes are weighted in the average of a planar metric.
+///
+/// Tools disagree on how much the chroma planes count. These presets match
+/// the common conventions, so that averages can be compared with the scores
+/// of other tools. The scores of the planes themselves do not change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PlaneWeights {
+    /// Each plane is weighted by its number of samples, so that the chroma
+    /// planes of 4:2:0 video count for a quarter of the luma plane each.
+    /// This is the default.
+    #[default]
+    Default,
+    /// Only the luma plane counts, so the average is the luma score.
+    Luma,
+    /// The three planes count the same, as in the mean of their scores.
+    Equal,
+    /// Weights of 6:1:1 for the luma and chroma planes, regardless of the
+    /// chroma sampling, as in the common test conditions of JVET and AOM.
+    Ctc,
+}
+
+impl PlaneWeights {
+    /// The weights of the Y, U and V planes of video with `chroma_sampling`.
+    pub fn weights(self, chroma_sampling: ChromaSampling) -> [f64; 3] {
+        self.with_chroma_weight(chroma_sampling.get_chroma_weight())
+    }
+
+    /// The weights of the planes, with `cweight` as the weight of each
+    /// chroma plane by default.
+    pub(crate) fn with_chroma_weight(self, cweight: f64) -> [f64; 3] {
+        self.fixed().unwrap_or([1.0, cweight, cweight])
+    }
+
+    /// The weights of the planes, unless they depend on the planes.
+    pub(crate) fn fixed(self) -> Option<[f64; 3]> {
+        match self {
+            PlaneWeights::Default => None,
+            PlaneWeights::Luma => Some([1.0, 0.0, 0.0]),
+            PlaneWeights::Equal => Some([1.0, 1.0, 1.0]),
+            PlaneWeights::Ctc => Some([6.0, 1.0, 1.0]),
+        }
+    }
+}
+
+/// The samples of each plane that a metric is computed on.
+///
+/// Scoring a subsample is a fast approximation of the full computation. It is
+/// supported by PSNR, APSNR and CIEDE2000.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Sampling {
+    /// Every sample. This is the default.
+    #[default]
+    Full,
+    /// Every other sample, in a checkerboard pattern: the samples where the
+    /// sum of the column and the row is even.
+    ///
+    /// This halves the work, and keeps the samples spread evenly over each
+    /// row and column. Since coding errors are spatially correlated, the
+    /// result stays close to the full computation: on natural content,
+    /// PSNR usually deviates by less than 0.05 dB and CIEDE2000 by less
+    /// than 0.1. Errors that follow a one pixel pattern, such as dithering
+    /// or a checkerboard, can be over or under represented, and deviate
+    /// much more. Use full sampling for final results.
+    Quincunx,
+}
+
+impl Sampling {
+    /// The first column scored in row `y`, and the step to the next one.
+    #[inline]
+    pub(crate) const fn columns(self, y: usize) -> (usize, usize) {
+        match self {
+            Sampling::Full => (0, 1),
+            Sampling::Quincunx => (y % 2, 2),
+        }
+    }
+}
+
+/// What the decoders reported about a frame pair besides its samples.
+#[derive(Debug, Default)]
+struct FrameInfo {
+    meta: [Option<FrameMeta>; 2],
+    side_data: [Option<SideData>; 2],
 }
 
 trait VideoMetric: Send + Sync {
@@ -106,10 +588,10 @@ trait VideoMetric: Send + Sync {
     /// `frame_fn` is the function to calculate metrics on one frame of the video.
     /// `acc_fn` is the accumulator function to calculate the aggregate metric.
     fn process_video<D: Decoder, F: Fn(usize) + Send>(
-        &mut self,
+        &self,
         decoder1: &mut D,
         decoder2: &mut D,
-        frame_limit: Option<usize>,
+        limits: Limits,
         progress_callback: F,
     ) -> Result<Self::VideoResult, Box<dyn Error>> {
         if decoder1.get_bit_depth() != decoder2.get_bit_depth() {
@@ -126,9 +608,9 @@ trait VideoMetric: Send + Sync {
         }
 
         if decoder1.get_bit_depth() > 8 {
-            self.process_video_mt::<D, u16, F>(decoder1, decoder2, frame_limit, progress_callback)
+            self.process_video_mt::<D, u16, F>(decoder1, decoder2, limits, progress_callback)
         } else {
-            self.process_video_mt::<D, u8, F>(decoder1, decoder2, frame_limit, progress_callback)
+            self.process_video_mt::<D, u8, F>(decoder1, decoder2, limits, progress_callback)
         }
     }
 
@@ -145,42 +627,220 @@ trait VideoMetric: Send + Sync {
         metrics: &[Self::FrameResult],
     ) -> Result<Self::VideoResult, Box<dyn Error>>;
 
+    /// Scores a pair of frames along with the encoder statistics the decoder
+    /// of each frame reported for it. Hybrid metrics override this, the
+    /// others ignore the side data.
+    fn process_frame_with_side_data<T: Pixel>(
+        &self,
+        frame1: &Frame<T>,
+        frame2: &Frame<T>,
+        _side_data: &[Option<SideData>; 2],
+        bit_depth: usize,
+        chroma_sampling: ChromaSampling,
+    ) -> Result<Self::FrameResult, Box<dyn Error>> {
+        self.process_frame(frame1, frame2, bit_depth, chroma_sampling)
+    }
+
+    /// Called on the decoding thread with each pair of frames read from the
+    /// decoders, in order, before it is scored. Metrics that compare
+    /// consecutive frames keep track of them here.
+    fn frame_decoded<T: Pixel>(&self, _frame1: &Frame<T>, _frame2: &Frame<T>) {}
+
+    /// The score of a single frame pair given to a [`FrameSink`]. Metrics
+    /// with a meaningful score per frame override this.
+    fn frame_score(&self, _result: &Self::FrameResult) -> Option<Score> {
+        None
+    }
+
     fn process_video_mt<D: Decoder, P: Pixel, F: Fn(usize) + Send>(
-        &mut self,
+        &self,
         decoder1: &mut D,
         decoder2: &mut D,
-        frame_limit: Option<usize>,
+        limits: Limits,
         progress_callback: F,
     ) -> Result<Self::VideoResult, Box<dyn Error>> {
+        let vid_info = decoder1.get_video_details();
+        let mut compared = 0;
+        // The index of the input that has frames left when the other ended
+        let mut longer = None;
+        let (dec1, dec2) = (&mut *decoder1, &mut *decoder2);
+        // Scored frame pairs are decoded into again when the decoders support it,
+        // so no frames need to be allocated once the pipeline is full.
+        let source = |recycled: Option<(Frame<P>, Frame<P>)>| {
+            let frames = match recycled {
+                Some((mut frame1, mut frame2)) => (
+                    dec1.read_video_frame_into(&mut frame1).then_some(frame1),
+                    dec2.read_video_frame_into(&mut frame2).then_some(frame2),
+                ),
+                None => (dec1.read_video_frame(), dec2.read_video_frame()),
+            };
+            match frames {
+                (Some(frame1), Some(frame2)) => {
+                    compared += 1;
+                    self.frame_decoded(&frame1, &frame2);
+                    let info = FrameInfo {
+                        meta: [dec1.last_frame_meta(), dec2.last_frame_meta()],
+                        side_data: [dec1.last_side_data(), dec2.last_side_data()],
+                    };
+                    return Some((frame1, frame2, info));
+                }
+                (Some(_), None) => longer = Some(0),
+                (None, Some(_)) => longer = Some(1),
+                (None, None) => (),
+            }
+            None
+        };
+        let result = self.process_source(
+            source,
+            vid_info.bit_depth,
+            vid_info.chroma_sampling,
+            &limits,
+            progress_callback,
+        )?;
+
+        let Some(longer) = longer else {
+            return Ok(result);
+        };
+        // Counting the trailing frames means decoding them, so it is only done
+        // when someone is interested.
+        let monitor = limits.monitor.as_ref();
+        if monitor.is_none() && limits.length_mismatch == LengthMismatch::Truncate {
+            return Ok(result);
+        }
+        let decoder = if longer == 0 { decoder1 } else { decoder2 };
+        let mut ignored = 1;
+        while decoder.read_video_frame::<P>().is_some() {
+            ignored += 1;
+        }
+        if let Some(monitor) = monitor {
+            monitor.frames_ignored(longer, ignored);
+        }
+        if limits.length_mismatch == LengthMismatch::Error {
+            let mut frames = [compared; 2];
+            frames[longer] += ignored;
+            return Err(MetricsError::LengthMismatch {
+                frames1: frames[0],
+                frames2: frames[1],
+            }
+            .into());
+        }
+        Ok(result)
+    }
+
+    /// Scores pairs of frames from an iterator with the same pipeline as videos.
+    fn process_frames<P: Pixel, I, F: Fn(usize) + Send>(
+        &self,
+        frames: I,
+        bit_depth: usize,
+        chroma_sampling: ChromaSampling,
+        progress_callback: F,
+    ) -> Result<Self::VideoResult, Box<dyn Error>>
+    where
+        I: IntoIterator<Item = (Frame<P>, Frame<P>)>,
+        I::IntoIter: Send,
+    {
+        let context = FrameContext::new(bit_depth, chroma_sampling)?;
+        context.validate_pixel::<P>()?;
+        let mut frames = frames.into_iter();
+        self.process_source(
+            move |_| {
+                frames
+                    .next()
+                    .map(|(frame1, frame2)| (frame1, frame2, FrameInfo::default()))
+            },
+            bit_depth,
+            chroma_sampling,
+            &Limits::default(),
+            progress_callback,
+        )
+    }
+
+    /// Runs the decoding and scoring pipeline. `source` is called on the decoding
+    /// thread to get each frame pair and what the decoders reported about it,
+    /// and is given a scored frame pair whose buffers it may reuse, if there is
+    /// one.
+    ///
+    /// `source` is called at most `limits.frame_limit` times, and every frame
+    /// pair it returns is scored. `progress_callback` gets the number of frame
+    /// pairs scored so far, then [`PROGRESS_DONE`](crate::PROGRESS_DONE).
+    fn process_source<P: Pixel, S, F: Fn(usize) + Send>(
+        &self,
+        mut source: S,
+        bit_depth: usize,
+        chroma_sampling: ChromaSampling,
+        limits: &Limits,
+        progress_callback: F,
+    ) -> Result<Self::VideoResult, Box<dyn Error>>
+    where
+        S: FnMut(Option<(Frame<P>, Frame<P>)>) -> Option<(Frame<P>, Frame<P>, FrameInfo)> + Send,
+    {
         let num_threads = (rayon::current_num_threads() - 1).max(1);
 
         let mut out = Vec::new();
 
         let (send, recv) = crossbeam::channel::bounded(num_threads);
-        let vid_info = decoder1.get_video_details();
+        let (recycle, recycled) = crossbeam::channel::unbounded::<(Frame<P>, Frame<P>)>();
+        let monitor = limits.monitor.as_ref();
+        if let Some(monitor) = monitor {
+            monitor.start(num_threads, num_threads);
+        }
+        let frame_limit = limits.frame_limit;
+        let control = limits.control.as_ref();
+        let frame_sink = limits.frame_sink.as_ref();
+        let context = match frame_sink {
+            Some(_) => Some(FrameContext::new(bit_depth, chroma_sampling)?),
+            None => None,
+        };
+        #[cfg(feature = "tracing")]
+        let span = tracing::info_span!(
+            "video_metric",
+            metric = std::any::type_name::<Self>(),
+            bit_depth,
+            threads = num_threads
+        );
+        #[cfg(feature = "tracing")]
+        let _entered = span.enter();
 
         let scope_result = crossbeam::scope(|s| {
+            #[cfg(feature = "tracing")]
+            let span = &span;
+            // Returns the number of frame pairs sent to the scoring threads,
+            // which stops early if they stopped receiving.
             let send_result = s.spawn(move |_| {
                 let mut decoded = 0;
-                while frame_limit.map(|limit| limit > decoded).unwrap_or(true) {
-                    decoded += 1;
-                    let frame1 = decoder1.read_video_frame::<P>();
-                    let frame2 = decoder2.read_video_frame::<P>();
-                    if let (Some(frame1), Some(frame2)) = (frame1, frame2) {
-                        progress_callback(decoded);
-                        if let Err(e) = send.send((frame1, frame2)) {
-                            let (frame1, frame2) = e.into_inner();
-                            return Err(format!(
-                                "Error sending\n\nframe1: {frame1:?}\n\nframe2: {frame2:?}"
-                            ));
-                        }
-                    } else {
+                let mut next_frame = None;
+                while frame_limit.is_none_or(|limit| decoded < limit) {
+                    if let Some(control) = control {
+                        control.wait_turn(&mut next_frame);
+                    }
+                    #[cfg(feature = "tracing")]
+                    let decode_span =
+                        tracing::debug_span!(parent: span, "decode_frame", frame = decoded);
+                    #[cfg(feature = "tracing")]
+                    let decode_start = Instant::now();
+                    #[cfg(feature = "tracing")]
+                    let frames = decode_span.in_scope(|| source(recycled.try_recv().ok()));
+                    #[cfg(not(feature = "tracing"))]
+                    let frames = source(recycled.try_recv().ok());
+                    let Some((frame1, frame2, info)) = frames else {
+                        break;
+                    };
+                    #[cfg(feature = "tracing")]
+                    tracing::trace!(
+                        parent: &decode_span,
+                        elapsed_us = decode_start.elapsed().as_micros() as u64,
+                        "frame decoded"
+                    );
+                    let wait = Instant::now();
+                    if send.send((decoded, frame1, frame2, info)).is_err() {
                         break;
                     }
+                    decoded += 1;
+                    if let Some(monitor) = monitor {
+                        monitor.frame_decoded(wait.elapsed());
+                    }
                 }
-                // Mark the end of the decoding process
-                progress_callback(usize::MAX);
-                Ok(())
+                decoded
             });
 
             use rayon::prelude::*;
@@ -190,17 +850,56 @@ trait VideoMetric: Send + Sync {
                 let working_set: Vec<_> = (0..num_threads)
                     .into_par_iter()
                     .filter_map(|_w| {
+                        let wait = Instant::now();
                         recv.recv()
-                            .map(|(f1, f2)| {
-                                self.process_frame(
+                            .map(|(frameno, f1, f2, info)| {
+                                if let Some(monitor) = monitor {
+                                    monitor.frame_received(wait.elapsed());
+                                }
+                                #[cfg(feature = "tracing")]
+                                let score_span =
+                                    tracing::debug_span!(parent: span, "score_frame", frame = frameno);
+                                #[cfg(feature = "tracing")]
+                                let _entered = score_span.enter();
+                                #[cfg(feature = "tracing")]
+                                let score_start = Instant::now();
+                                let result = self.process_frame_with_side_data(
                                     &f1,
                                     &f2,
-                                    vid_info.bit_depth,
-                                    vid_info.chroma_sampling,
-                                )
-                                .map_err(|e| {
-                                    format!("\n\n{e} on\n\nframe1: {f1:?}\n\nand\n\nframe2: {f2:?}")
-                                })
+                                    &info.side_data,
+                                    bit_depth,
+                                    chroma_sampling,
+                                );
+                                if let (Some(sink), Some(context), Ok(result)) =
+                                    (frame_sink, context, &result)
+                                {
+                                    let mut scored = ScoredFrame::new(
+                                        frameno,
+                                        [&f1, &f2],
+                                        &info.meta,
+                                        &info.side_data,
+                                        context,
+                                    );
+                                    scored.score = self.frame_score(result);
+                                    sink.send(&scored);
+                                }
+                                let result = result
+                                    .map(|result| (frameno, result))
+                                    .map_err(|e| {
+                                        format!(
+                                            "\n\n{e} on frame {frameno}\n\nframe1: {f1:?}\n\nand\n\nframe2: {f2:?}"
+                                        )
+                                    });
+                                #[cfg(feature = "tracing")]
+                                tracing::trace!(
+                                    elapsed_us = score_start.elapsed().as_micros() as u64,
+                                    "frame scored"
+                                );
+                                if let Some(monitor) = monitor {
+                                    monitor.frame_scored();
+                                }
+                                let _ = recycle.send((f1, f2));
+                                result
                             })
                             .ok()
                     })
@@ -211,31 +910,45 @@ trait VideoMetric: Send + Sync {
                     .collect();
                 if work_set.is_empty() || process_error.is_err() {
                     break;
-                } else {
-                    metrics.extend(work_set);
                 }
+                metrics.extend(work_set);
+                progress_callback(metrics.len());
             }
+            // Stops the decoding thread if scoring stopped early
+            drop(recv);
+            progress_callback(crate::PROGRESS_DONE);
 
+            // The scoring threads take frames in any order
+            metrics.sort_unstable_by_key(|&(frameno, _)| frameno);
             out = metrics;
 
-            (
-                send_result
-                    .join()
-                    .unwrap_or_else(|_| Err("Failed joining the sender thread".to_owned())),
-                process_error,
-            )
+            (send_result.join(), process_error)
         });
 
         match scope_result {
-            Ok((send_error, process_error)) => {
-                if let Err(error) = send_error {
-                    return Err(MetricsError::SendError { reason: error }.into());
-                }
-
+            Ok((sent, process_error)) => {
                 if let Err(error) = process_error {
                     return Err(MetricsError::ProcessError { reason: error }.into());
                 }
 
+                let Ok(sent) = sent else {
+                    return Err(MetricsError::SendError {
+                        reason: "Failed joining the sender thread".to_owned(),
+                    }
+                    .into());
+                };
+                if out.len() != sent
+                    || out
+                        .iter()
+                        .enumerate()
+                        .any(|(i, &(frameno, _))| i != frameno)
+                {
+                    return Err(MetricsError::ProcessError {
+                        reason: format!("{} of {} decoded frames were scored", out.len(), sent),
+                    }
+                    .into());
+                }
+
                 if out.is_empty() {
                     return Err(MetricsError::UnsupportedInput {
                         reason: "No readable frames found in one or more input files",
@@ -243,6 +956,7 @@ trait VideoMetric: Send + Sync {
                     .into());
                 }
 
+                let out: Vec<_> = out.into_iter().map(|(_, result)| result).collect();
                 self.aggregate_frame_results(&out)
             }
             Err(e) => Err(MetricsError::VideoError {
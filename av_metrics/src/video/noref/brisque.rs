@@ -0,0 +1,501 @@
+//! Blind/Referenceless Image Spatial Quality Evaluator (BRISQUE).
+//!
+//! BRISQUE fits generalized Gaussian distributions to the mean subtracted
+//! contrast normalized (MSCN) luma samples of a frame and to the products of
+//! neighbouring MSCN samples, at two scales. Distortions change the shape of
+//! these distributions, and a support vector regression trained on subjective
+//! scores maps the 36 fitted parameters to a score from about 0, for pristine
+//! frames, to 100. Lower is better.
+//!
+//! The models are read from the `allmodel` and `allrange` files of the
+//! reference implementation, which hold a libsvm model and the ranges its
+//! features are scaled from. The frames are downscaled with a box filter
+//! instead of bicubic interpolation, so scores differ slightly from the
+//! reference implementation.
+//!
+//! See "No-Reference Image Quality Assessment in the Spatial Domain" by Mittal,
+//! Moorthy and Bovik (IEEE TIP, 2012).
+
+use crate::video::decode::{Decoder, PlaneRef};
+use crate::video::pixel::CastFromPrimitive;
+use crate::video::pixel::Pixel;
+use crate::video::svm::Svm;
+use crate::video::FrameContext;
+use crate::{MetricsError, PROGRESS_DONE};
+use std::error::Error;
+use std::path::Path;
+use std::sync::OnceLock;
+use v_frame::frame::Frame;
+use v_frame::prelude::ChromaSampling;
+
+/// The number of features of a frame.
+pub const FEATURES: usize = 36;
+
+/// The radius of the Gaussian window of the local means and deviations.
+const WINDOW_RADIUS: usize = 3;
+
+/// The shape parameters tried when fitting distributions, from 0.2 to 10.
+const SHAPES: usize = 9801;
+
+/// The BRISQUE scores of a video.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BrisqueResults {
+    /// The mean score of all frames.
+    pub mean: f64,
+    /// The score of the worst frame, which is the highest.
+    pub max: f64,
+    /// The score of each frame.
+    pub frames: Vec<f64>,
+}
+
+impl BrisqueResults {
+    fn new(frames: Vec<f64>) -> Self {
+        BrisqueResults {
+            mean: frames.iter().sum::<f64>() / frames.len() as f64,
+            max: frames.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+            frames,
+        }
+    }
+}
+
+/// The natural scene statistics of a frame that BRISQUE is computed from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BrisqueFeatures {
+    /// For each of the two scales, the shape and variance of the MSCN samples,
+    /// then the shape, mean, left and right variance of the products of
+    /// horizontal, vertical and both diagonal neighbours.
+    #[cfg_attr(feature = "serde", serde(with = "serde_features"))]
+    pub values: [f64; FEATURES],
+}
+
+/// Serializes the features as a sequence, since serde only implements its
+/// traits for arrays of up to 32 elements.
+#[cfg(feature = "serde")]
+mod serde_features {
+    use super::FEATURES;
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        values: &[f64; FEATURES],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(values)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<[f64; FEATURES], D::Error> {
+        let values = Vec::<f64>::deserialize(deserializer)?;
+        let len = values.len();
+        values
+            .try_into()
+            .map_err(|_| D::Error::invalid_length(len, &"36 BRISQUE features"))
+    }
+}
+
+/// A BRISQUE model, as distributed with the reference implementation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BrisqueModel {
+    /// The range each feature is scaled to.
+    target: (f64, f64),
+    /// The lowest and highest value of each feature in the training set.
+    ranges: Vec<(f64, f64)>,
+    svm: Svm,
+}
+
+fn malformed(reason: &'static str) -> MetricsError {
+    MetricsError::MalformedInput { reason }
+}
+
+impl BrisqueModel {
+    /// Reads a model from the `allmodel` and `allrange` files of the reference
+    /// implementation.
+    pub fn load<P: AsRef<Path>, R: AsRef<Path>>(
+        model: P,
+        range: R,
+    ) -> Result<Self, Box<dyn Error>> {
+        let model = std::fs::read_to_string(model)?;
+        let range = std::fs::read_to_string(range)?;
+        Ok(Self::from_libsvm(&model, &range)?)
+    }
+
+    /// Parses a model saved by libsvm, with the feature ranges saved by
+    /// `svm-scale`.
+    pub fn from_libsvm(model: &str, range: &str) -> Result<Self, MetricsError> {
+        let invalid = || malformed("Invalid feature ranges in the BRISQUE model");
+        let mut lines = range.lines().filter(|line| !line.trim().is_empty());
+        if lines.next().map(str::trim) != Some("x") {
+            return Err(invalid());
+        }
+        let bounds = |line: Option<&str>| -> Option<Vec<f64>> {
+            line?
+                .split_whitespace()
+                .map(|value| value.parse().ok())
+                .collect()
+        };
+        let Some(&[lower, upper]) = bounds(lines.next()).as_deref() else {
+            return Err(invalid());
+        };
+        let mut ranges = vec![(0.0, 0.0); FEATURES];
+        let mut seen = 0;
+        for line in lines {
+            let Some(&[index, min, max]) = bounds(Some(line)).as_deref() else {
+                return Err(invalid());
+            };
+            let range = (index as usize)
+                .checked_sub(1)
+                .and_then(|index| ranges.get_mut(index))
+                .ok_or_else(invalid)?;
+            *range = (min, max);
+            seen += 1;
+        }
+        if seen != FEATURES {
+            return Err(invalid());
+        }
+
+        Ok(BrisqueModel {
+            target: (lower, upper),
+            ranges,
+            svm: Svm::parse(model, FEATURES)?,
+        })
+    }
+
+    /// Predicts the BRISQUE score of a frame from its features.
+    pub fn predict(&self, features: &BrisqueFeatures) -> f64 {
+        let (lower, upper) = self.target;
+        let input: Vec<f64> = features
+            .values
+            .iter()
+            .zip(&self.ranges)
+            .map(|(&value, &(min, max))| {
+                if max > min {
+                    lower + (upper - lower) * (value - min) / (max - min)
+                } else {
+                    lower
+                }
+            })
+            .collect();
+        self.svm.predict(&input)
+    }
+}
+
+/// Calculates the BRISQUE score of a video with `model`. Lower is better.
+pub fn calculate_video_brisque<D: Decoder, F: Fn(usize) + Send>(
+    decoder: &mut D,
+    model: &BrisqueModel,
+    frame_limit: Option<usize>,
+    progress_callback: F,
+) -> Result<BrisqueResults, Box<dyn Error>> {
+    if decoder.get_bit_depth() > 8 {
+        process_video::<D, u16, F>(decoder, model, frame_limit, progress_callback)
+    } else {
+        process_video::<D, u8, F>(decoder, model, frame_limit, progress_callback)
+    }
+}
+
+/// Calculates the BRISQUE score of a frame with `model`. Lower is better.
+pub fn calculate_frame_brisque<T: Pixel>(
+    frame: &Frame<T>,
+    bit_depth: usize,
+    model: &BrisqueModel,
+) -> Result<f64, Box<dyn Error>> {
+    let features = calculate_frame_brisque_features(frame, bit_depth)?;
+    Ok(model.predict(&features))
+}
+
+/// Calculates the features of a frame, on a scale of 8-bit samples
+/// regardless of the bit depth.
+pub fn calculate_frame_brisque_features<T: Pixel>(
+    frame: &Frame<T>,
+    bit_depth: usize,
+) -> Result<BrisqueFeatures, Box<dyn Error>> {
+    // Only the luma plane is used
+    FrameContext::new(bit_depth, ChromaSampling::Cs400)?;
+    let plane = PlaneRef::from(&frame.planes[0]);
+    let (width, height) = (plane.width(), plane.height());
+    if width < 2 * (2 * WINDOW_RADIUS + 1) || height < 2 * (2 * WINDOW_RADIUS + 1) {
+        return Err(MetricsError::UnsupportedInput {
+            reason: "Frames are too small for BRISQUE",
+        }
+        .into());
+    }
+
+    let scale = (1 << (bit_depth - 8)) as f64;
+    let mut samples = Samples {
+        width,
+        height,
+        data: plane
+            .rows()
+            .flat_map(|row| row.iter().map(|&px| f64::from(i32::cast_from(px)) / scale))
+            .collect(),
+    };
+    let mut values = [0.0; FEATURES];
+    for features in values.chunks_exact_mut(FEATURES / 2) {
+        scale_features(&samples, features);
+        samples = samples.downscale();
+    }
+    Ok(BrisqueFeatures { values })
+}
+
+fn process_video<D: Decoder, P: Pixel, F: Fn(usize) + Send>(
+    decoder: &mut D,
+    model: &BrisqueModel,
+    frame_limit: Option<usize>,
+    progress_callback: F,
+) -> Result<BrisqueResults, Box<dyn Error>> {
+    let bit_depth = decoder.get_bit_depth();
+    let mut scores = Vec::new();
+    while frame_limit
+        .map(|limit| limit > scores.len())
+        .unwrap_or(true)
+    {
+        let Some(frame) = decoder.read_video_frame::<P>() else {
+            break;
+        };
+        scores.push(calculate_frame_brisque(&frame, bit_depth, model)?);
+        progress_callback(scores.len());
+    }
+    progress_callback(PROGRESS_DONE);
+
+    if scores.is_empty() {
+        return Err(MetricsError::UnsupportedInput {
+            reason: "No readable frames found in the input",
+        }
+        .into());
+    }
+    Ok(BrisqueResults::new(scores))
+}
+
+/// The luma samples of one scale, row by row.
+struct Samples {
+    width: usize,
+    height: usize,
+    data: Vec<f64>,
+}
+
+impl Samples {
+    /// Halves the size with a 2x2 box filter.
+    fn downscale(&self) -> Samples {
+        let (width, height) = (self.width / 2, self.height / 2);
+        let at = |x: usize, y: usize| self.data[y * self.width + x];
+        let data = (0..height)
+            .flat_map(|y| {
+                (0..width).map(move |x| {
+                    (at(2 * x, 2 * y)
+                        + at(2 * x + 1, 2 * y)
+                        + at(2 * x, 2 * y + 1)
+                        + at(2 * x + 1, 2 * y + 1))
+                        / 4.0
+                })
+            })
+            .collect();
+        Samples {
+            width,
+            height,
+            data,
+        }
+    }
+
+    /// Filters the samples with a separable kernel, treating samples outside
+    /// of the frame as 0.
+    fn filter(&self, kernel: &[f64]) -> Vec<f64> {
+        let radius = kernel.len() / 2;
+        let (width, height) = (self.width, self.height);
+        let mut rows = vec![0.0; self.data.len()];
+        for y in 0..height {
+            let row = &self.data[y * width..(y + 1) * width];
+            for x in 0..width {
+                rows[y * width + x] = kernel
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(k, &weight)| {
+                        let x = (x + k).checked_sub(radius).filter(|&x| x < width)?;
+                        Some(weight * row[x])
+                    })
+                    .sum();
+            }
+        }
+        let mut filtered = vec![0.0; self.data.len()];
+        for y in 0..height {
+            for x in 0..width {
+                filtered[y * width + x] = kernel
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(k, &weight)| {
+                        let y = (y + k).checked_sub(radius).filter(|&y| y < height)?;
+                        Some(weight * rows[y * width + x])
+                    })
+                    .sum();
+            }
+        }
+        filtered
+    }
+
+    /// The mean subtracted contrast normalized samples.
+    fn mscn(&self) -> Vec<f64> {
+        let kernel = gaussian_kernel();
+        let mu = self.filter(&kernel);
+        let squares = Samples {
+            width: self.width,
+            height: self.height,
+            data: self.data.iter().map(|v| v * v).collect(),
+        };
+        let mu_sq = squares.filter(&kernel);
+        self.data
+            .iter()
+            .zip(mu.iter().zip(mu_sq))
+            .map(|(&v, (&mu, mu_sq))| {
+                let sigma = (mu_sq - mu * mu).abs().sqrt();
+                (v - mu) / (sigma + 1.0)
+            })
+            .collect()
+    }
+}
+
+/// The normalized 7-tap Gaussian window with a standard deviation of 7/6.
+fn gaussian_kernel() -> Vec<f64> {
+    let sigma = 7.0 / 6.0;
+    let kernel: Vec<f64> = (0..=2 * WINDOW_RADIUS)
+        .map(|i| {
+            let d = i as f64 - WINDOW_RADIUS as f64;
+            (-d * d / (2.0 * sigma * sigma)).exp()
+        })
+        .collect();
+    let sum: f64 = kernel.iter().sum();
+    kernel.into_iter().map(|w| w / sum).collect()
+}
+
+/// Computes the 18 features of one scale.
+fn scale_features(samples: &Samples, features: &mut [f64]) {
+    let mscn = samples.mscn();
+    let (shape, variance) = fit_ggd(&mscn);
+    features[0] = shape;
+    features[1] = variance;
+
+    let (width, height) = (samples.width, samples.height);
+    // The products wrap around the edges of the frame
+    let offsets: [(usize, usize); 4] = [(1, 0), (0, 1), (1, 1), (width - 1, 1)];
+    for (i, (dx, dy)) in offsets.into_iter().enumerate() {
+        let products: Vec<f64> = (0..height)
+            .flat_map(|y| {
+                let mscn = &mscn;
+                (0..width).map(move |x| {
+                    let (nx, ny) = ((x + dx) % width, (y + dy) % height);
+                    mscn[y * width + x] * mscn[ny * width + nx]
+                })
+            })
+            .collect();
+        let (shape, mean, left, right) = fit_aggd(&products);
+        features[2 + 4 * i..6 + 4 * i].copy_from_slice(&[shape, mean, left, right]);
+    }
+}
+
+/// The shape parameters tried when fitting distributions, with the ratios of
+/// gamma functions they give.
+struct ShapeTable {
+    shapes: Vec<f64>,
+    /// Γ(1/a)·Γ(3/a) / Γ(2/a)², for generalized Gaussian fits.
+    ggd: Vec<f64>,
+    /// Γ(2/a)² / (Γ(1/a)·Γ(3/a)), for asymmetric generalized Gaussian fits.
+    aggd: Vec<f64>,
+}
+
+fn shape_table() -> &'static ShapeTable {
+    static TABLE: OnceLock<ShapeTable> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        let shapes: Vec<f64> = (0..SHAPES).map(|i| 0.2 + i as f64 * 0.001).collect();
+        let ggd: Vec<f64> = shapes
+            .iter()
+            .map(|&a| gamma(1.0 / a) * gamma(3.0 / a) / gamma(2.0 / a).powi(2))
+            .collect();
+        let aggd = ggd.iter().map(|ratio| 1.0 / ratio).collect();
+        ShapeTable { shapes, ggd, aggd }
+    })
+}
+
+/// The shape whose ratio in the table is closest to `ratio`.
+fn closest_shape(table: &[f64], ratio: f64) -> f64 {
+    let index = table
+        .iter()
+        .enumerate()
+        .min_by(|(_, a), (_, b)| (*a - ratio).abs().total_cmp(&(*b - ratio).abs()))
+        .map(|(i, _)| i)
+        .unwrap_or(0);
+    shape_table().shapes[index]
+}
+
+/// Fits a zero-mean generalized Gaussian distribution, returning its shape
+/// and variance.
+fn fit_ggd(values: &[f64]) -> (f64, f64) {
+    let n = values.len() as f64;
+    let variance = values.iter().map(|v| v * v).sum::<f64>() / n;
+    let mean_abs = values.iter().map(|v| v.abs()).sum::<f64>() / n;
+    if mean_abs == 0.0 {
+        return (shape_table().shapes[SHAPES - 1], 0.0);
+    }
+    let shape = closest_shape(&shape_table().ggd, variance / (mean_abs * mean_abs));
+    (shape, variance)
+}
+
+/// Fits an asymmetric generalized Gaussian distribution, returning its shape,
+/// mean, and left and right variances.
+fn fit_aggd(values: &[f64]) -> (f64, f64, f64, f64) {
+    let side = |negative: bool| {
+        let (sum, count) = values
+            .iter()
+            .filter(|&&v| if negative { v < 0.0 } else { v > 0.0 })
+            .fold((0.0, 0usize), |(sum, count), v| (sum + v * v, count + 1));
+        if count > 0 {
+            sum / count as f64
+        } else {
+            0.0
+        }
+    };
+    let (left, right) = (side(true), side(false));
+    let n = values.len() as f64;
+    let mean_sq = values.iter().map(|v| v * v).sum::<f64>() / n;
+    let mean_abs = values.iter().map(|v| v.abs()).sum::<f64>() / n;
+    if left == 0.0 || right == 0.0 || mean_sq == 0.0 {
+        return (shape_table().shapes[SHAPES - 1], 0.0, left, right);
+    }
+
+    let (left_std, right_std) = (left.sqrt(), right.sqrt());
+    let g = left_std / right_std;
+    let r = mean_abs * mean_abs / mean_sq;
+    let r_norm = r * (g.powi(3) + 1.0) * (g + 1.0) / (g * g + 1.0).powi(2);
+    let shape = closest_shape(&shape_table().aggd, r_norm);
+    let mean = (right_std - left_std) * gamma(2.0 / shape) / gamma(1.0 / shape)
+        * (gamma(1.0 / shape) / gamma(3.0 / shape)).sqrt();
+    (shape, mean, left, right)
+}
+
+/// The gamma function of positive numbers, by the Lanczos approximation.
+fn gamma(x: f64) -> f64 {
+    const G: f64 = 7.0;
+    const COEFFICIENTS: [f64; 9] = [
+        0.999_999_999_999_809_9,
+        676.520_368_121_885_1,
+        -1_259.139_216_722_402_8,
+        771.323_428_777_653_1,
+        -176.615_029_162_140_6,
+        12.507_343_278_686_905,
+        -0.138_571_095_265_720_12,
+        9.984_369_578_019_572e-6,
+        1.505_632_735_149_311_6e-7,
+    ];
+    if x < 0.5 {
+        return std::f64::consts::PI / ((std::f64::consts::PI * x).sin() * gamma(1.0 - x));
+    }
+    let x = x - 1.0;
+    let t = x + G + 0.5;
+    let sum = COEFFICIENTS[1..]
+        .iter()
+        .enumerate()
+        .fold(COEFFICIENTS[0], |sum, (i, &c)| {
+            sum + c / (x + i as f64 + 1.0)
+        });
+    (2.0 * std::f64::consts::PI).sqrt() * t.powf(x + 0.5) * (-t).exp() * sum
+}
@@ -0,0 +1,10 @@
+//! No-reference metrics, which score a single video without a base to
+//! compare it to.
+//!
+//! They predict how natural a video looks from the statistics of its own
+//! samples, which is useful to check the quality of sources before they are
+//! used as the base of comparisons. Their scores are less precise than those
+//! of full-reference metrics and should only be compared between videos of
+//! similar content.
+
+pub mod brisque;
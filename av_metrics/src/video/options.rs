@@ -0,0 +1,298 @@
+//! Options shared by the metrics computed over whole videos.
+
+use crate::video::{FrameSink, PipelineControl, PipelineMonitor};
+use crate::MetricsError;
+use std::error::Error;
+
+/// Options for computing a metric over whole videos, built with chained
+/// calls:
+///
+/// ```
+/// use av_metrics::video::psnr_hvs::PsnrHvsOptions;
+///
+/// let options = PsnrHvsOptions::new()
+///     .frame_limit(100)
+///     .threads(4)
+///     .progress(|frames| eprintln!("{} frames", frames));
+/// ```
+///
+/// Metrics with options of their own, such as PSNR, wrap these options in a
+/// dedicated type with the same methods.
+#[derive(Debug, Clone)]
+pub struct VideoOptions<F = fn(usize)> {
+    /// Only compare the first `frame_limit` frames of each video.
+    pub frame_limit: Option<usize>,
+    /// The number of threads computing the metric. The global thread pool is
+    /// used if it is not set.
+    pub threads: Option<usize>,
+    /// Called with the number of frames compared so far, which never exceeds
+    /// the frame limit, and with [`PROGRESS_DONE`](crate::PROGRESS_DONE) once
+    /// every frame is compared.
+    pub progress_callback: F,
+    /// What to do when one video has more frames than the other.
+    pub length_mismatch: LengthMismatch,
+    /// Whether CIEDE2000 adds up the rows of each frame in row order, making
+    /// its results bit-identical across runs and thread counts. It is ignored
+    /// by the other metrics.
+    ///
+    /// The frames are always scored in any order and aggregated in frame
+    /// order, so the other metrics are bit-identical without it. CIEDE2000
+    /// alone adds up the rows of each frame on several threads, in the order
+    /// they finish, unless this is set, at the cost of keeping the sum of each
+    /// row until the frame is done.
+    pub deterministic: bool,
+    /// Pauses and throttles the decoding of the videos while the metric is
+    /// running.
+    pub control: Option<PipelineControl>,
+    /// Records the statistics of the decoding and scoring pipeline while the
+    /// metric is running.
+    pub monitor: Option<PipelineMonitor>,
+    /// Receives every frame pair as soon as it is scored.
+    pub frame_sink: Option<FrameSink>,
+}
+
+/// What to do when one of the compared videos has more frames than the other,
+/// and the frame limit is not reached first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum LengthMismatch {
+    /// Compare the frames both videos have, and ignore the trailing frames of
+    /// the longer one. Their number is reported in
+    /// [`PipelineStats::ignored_frames`](crate::video::PipelineStats::ignored_frames).
+    #[default]
+    Truncate,
+    /// Fail with [`MetricsError::LengthMismatch`].
+    Error,
+}
+
+/// Which frames of the videos a metric compares, and how fast they are read.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Limits {
+    pub(crate) frame_limit: Option<usize>,
+    pub(crate) length_mismatch: LengthMismatch,
+    pub(crate) control: Option<PipelineControl>,
+    pub(crate) monitor: Option<PipelineMonitor>,
+    pub(crate) frame_sink: Option<FrameSink>,
+}
+
+impl From<Option<usize>> for Limits {
+    fn from(frame_limit: Option<usize>) -> Self {
+        Limits {
+            frame_limit,
+            ..Default::default()
+        }
+    }
+}
+
+impl VideoOptions {
+    /// Options comparing every frame on the global thread pool, without
+    /// reporting progress.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Default for VideoOptions {
+    fn default() -> Self {
+        VideoOptions {
+            frame_limit: None,
+            threads: None,
+            progress_callback: |_| (),
+            length_mismatch: LengthMismatch::default(),
+            deterministic: false,
+            control: None,
+            monitor: None,
+            frame_sink: None,
+        }
+    }
+}
+
+impl<F> VideoOptions<F> {
+    /// Options comparing the first `frame_limit` frames, if any, with the
+    /// defaults otherwise, for the functions that take these as arguments.
+    pub(crate) fn limited(frame_limit: Option<usize>, progress_callback: F) -> Self {
+        VideoOptions {
+            frame_limit,
+            threads: None,
+            progress_callback,
+            length_mismatch: LengthMismatch::default(),
+            deterministic: false,
+            control: None,
+            monitor: None,
+            frame_sink: None,
+        }
+    }
+
+    /// Only compares the first `frame_limit` frames of each video.
+    pub fn frame_limit(mut self, frame_limit: usize) -> Self {
+        self.frame_limit = Some(frame_limit);
+        self
+    }
+
+    /// Computes the metric on a thread pool of `threads` threads.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = Some(threads);
+        self
+    }
+
+    /// Calls `progress_callback` with the number of frames compared so far.
+    pub fn progress<G: Fn(usize) + Send>(self, progress_callback: G) -> VideoOptions<G> {
+        VideoOptions {
+            frame_limit: self.frame_limit,
+            threads: self.threads,
+            progress_callback,
+            length_mismatch: self.length_mismatch,
+            deterministic: self.deterministic,
+            control: self.control,
+            monitor: self.monitor,
+            frame_sink: self.frame_sink,
+        }
+    }
+
+    /// Sets what to do when one video has more frames than the other.
+    pub fn length_mismatch(mut self, length_mismatch: LengthMismatch) -> Self {
+        self.length_mismatch = length_mismatch;
+        self
+    }
+
+    /// Sets whether CIEDE2000 adds up the rows of each frame in row order, for
+    /// results that are bit-identical across runs and thread counts. Only
+    /// CIEDE2000 uses it.
+    pub fn deterministic(mut self, deterministic: bool) -> Self {
+        self.deterministic = deterministic;
+        self
+    }
+
+    /// Lets `control` pause and throttle the decoding of the videos.
+    pub fn control(mut self, control: &PipelineControl) -> Self {
+        self.control = Some(control.clone());
+        self
+    }
+
+    /// Records the pipeline statistics of the metric in `monitor`.
+    pub fn monitor(mut self, monitor: &PipelineMonitor) -> Self {
+        self.monitor = Some(monitor.clone());
+        self
+    }
+
+    /// Gives every frame pair to `frame_sink` as soon as it is scored.
+    pub fn frame_sink(mut self, frame_sink: &FrameSink) -> Self {
+        self.frame_sink = Some(frame_sink.clone());
+        self
+    }
+}
+
+impl<F: Fn(usize) + Send> VideoOptions<F> {
+    /// Runs `metric` with the frame limits and progress callback, on a thread
+    /// pool of the requested size if there is one.
+    pub(crate) fn run<R: Send>(
+        self,
+        metric: impl FnOnce(Limits, F) -> Result<R, Box<dyn Error>> + Send,
+    ) -> Result<R, Box<dyn Error>> {
+        let limits = Limits {
+            frame_limit: self.frame_limit,
+            length_mismatch: self.length_mismatch,
+            control: self.control,
+            monitor: self.monitor,
+            frame_sink: self.frame_sink,
+        };
+        let Some(threads) = self.threads else {
+            return metric(limits, self.progress_callback);
+        };
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()?;
+        // Errors have to be sent back from the pool
+        pool.install(move || {
+            metric(limits, self.progress_callback).map_err(|err| {
+                err.downcast::<MetricsError>()
+                    .map(|err| *err)
+                    .unwrap_or_else(|err| MetricsError::VideoError {
+                        reason: err.to_string(),
+                    })
+            })
+        })
+        .map_err(Box::from)
+    }
+}
+
+/// Implements the methods of [`VideoOptions`] on the options of a metric,
+/// which hold them in a `video` field next to the `config` of the metric.
+macro_rules! video_options {
+    ($name:ident) => {
+        impl $name {
+            /// Options comparing every frame on the global thread pool, with
+            /// the default configuration and without reporting progress.
+            pub fn new() -> Self {
+                Self::default()
+            }
+        }
+
+        impl Default for $name {
+            fn default() -> Self {
+                $name {
+                    video: $crate::video::VideoOptions::default(),
+                    config: Default::default(),
+                }
+            }
+        }
+
+        impl<F> $name<F> {
+            /// Only compares the first `frame_limit` frames of each video.
+            pub fn frame_limit(mut self, frame_limit: usize) -> Self {
+                self.video.frame_limit = Some(frame_limit);
+                self
+            }
+
+            /// Computes the metric on a thread pool of `threads` threads.
+            pub fn threads(mut self, threads: usize) -> Self {
+                self.video.threads = Some(threads);
+                self
+            }
+
+            /// Calls `progress_callback` with the number of frames compared so
+            /// far.
+            pub fn progress<G: Fn(usize) + Send>(self, progress_callback: G) -> $name<G> {
+                $name {
+                    video: self.video.progress(progress_callback),
+                    config: self.config,
+                }
+            }
+
+            /// Sets what to do when one video has more frames than the other.
+            pub fn length_mismatch(
+                mut self,
+                length_mismatch: $crate::video::LengthMismatch,
+            ) -> Self {
+                self.video.length_mismatch = length_mismatch;
+                self
+            }
+
+            /// Sets whether CIEDE2000 adds up the rows of each frame in row
+            /// order, for results that are bit-identical across runs and
+            /// thread counts. Only CIEDE2000 uses it.
+            pub fn deterministic(mut self, deterministic: bool) -> Self {
+                self.video.deterministic = deterministic;
+                self
+            }
+
+            /// Lets `control` pause and throttle the decoding of the videos.
+            pub fn control(mut self, control: &$crate::video::PipelineControl) -> Self {
+                self.video.control = Some(control.clone());
+                self
+            }
+
+            /// Records the pipeline statistics of the metric in `monitor`.
+            pub fn monitor(mut self, monitor: &$crate::video::PipelineMonitor) -> Self {
+                self.video.monitor = Some(monitor.clone());
+                self
+            }
+
+            /// Gives every frame pair to `frame_sink` as soon as it is scored.
+            pub fn frame_sink(mut self, frame_sink: &$crate::video::FrameSink) -> Self {
+                self.video.frame_sink = Some(frame_sink.clone());
+                self
+            }
+        }
+    };
+}
@@ -0,0 +1,268 @@
+//! Pairing of the frames of two inputs.
+//!
+//! Metrics compare the frames of two decoders in the order they are decoded.
+//! When an input has a variable frame rate, or the inputs have different
+//! frame rates, the frames at the same position can show different content.
+//! [`Paired::by_timestamp`] instead matches each frame of the first input with
+//! the frame of the second input whose timestamp is the nearest.
+
+use crate::video::decode::{
+    Decoder, DecoderCapabilities, FrameMeta, FrameRef, StreamInfo, VideoDetails,
+};
+use crate::video::pixel::Pixel;
+use crate::video::side_data::SideData;
+use std::any::Any;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use v_frame::frame::Frame;
+use v_frame::plane::Plane;
+
+type Decoded<T> = (Frame<T>, Option<Plane<T>>);
+
+/// A frame of the second input that has been decoded ahead of the frame of
+/// the first input it is paired with.
+struct Buffered {
+    meta: FrameMeta,
+    side_data: Option<SideData>,
+    time: f64,
+    /// A `Decoded<T>` for the pixel type that is being read.
+    decoded: Box<dyn Any + Send>,
+}
+
+struct Shared<D: Decoder> {
+    decoders: [D; 2],
+    tolerance: Option<f64>,
+    frames_read: [usize; 2],
+    current: Option<Buffered>,
+    next: Option<Buffered>,
+    second_ended: bool,
+    /// The frame of the second input paired with the frame that was last
+    /// returned for the first input.
+    pending: Option<Box<dyn Any + Send>>,
+    metas: [Option<FrameMeta>; 2],
+    side_data: [Option<SideData>; 2],
+}
+
+impl<D: Decoder> Shared<D> {
+    /// Reads the next frame of an input along with its metadata, its side
+    /// data and its presentation time in seconds.
+    fn read<T: Pixel>(
+        &mut self,
+        index: usize,
+    ) -> Option<(FrameMeta, Option<SideData>, f64, Decoded<T>)> {
+        let decoder = &mut self.decoders[index];
+        let decoded = decoder.read_video_frame_with_alpha::<T>()?;
+        let number = self.frames_read[index];
+        self.frames_read[index] += 1;
+        let time_base = decoder.get_video_details().time_base;
+        let meta = decoder
+            .last_frame_meta()
+            .unwrap_or_else(|| FrameMeta::from_number(number, time_base));
+        let time = meta.time.unwrap_or(meta.number as f64 * time_base.as_f64());
+        Some((meta, decoder.last_side_data(), time, decoded))
+    }
+
+    fn read_second<T: Pixel>(&mut self) -> Option<Buffered> {
+        if self.second_ended {
+            return None;
+        }
+        let buffered = self
+            .read::<T>(1)
+            .map(|(meta, side_data, time, decoded)| Buffered {
+                meta,
+                side_data,
+                time,
+                decoded: Box::new(decoded),
+            });
+        self.second_ended = buffered.is_none();
+        buffered
+    }
+
+    /// Reads frames of the first input until one can be paired with a frame
+    /// of the second input within `tolerance` seconds.
+    fn next_pair<T: Pixel>(&mut self, tolerance: f64) -> Option<Decoded<T>> {
+        loop {
+            let (meta1, side_data1, time1, decoded1) = self.read::<T>(0)?;
+            if self.current.is_none() {
+                self.current = self.read_second::<T>();
+            }
+            loop {
+                if self.next.is_none() {
+                    self.next = self.read_second::<T>();
+                }
+                match (&self.current, &self.next) {
+                    (Some(current), Some(next))
+                        if (next.time - time1).abs() <= (current.time - time1).abs() =>
+                    {
+                        self.current = self.next.take();
+                    }
+                    _ => break,
+                }
+            }
+
+            let current = self.current.as_ref()?;
+            if (current.time - time1).abs() <= tolerance {
+                let decoded2 = current.decoded.downcast_ref::<Decoded<T>>()?.clone();
+                self.metas = [Some(meta1), Some(current.meta)];
+                self.side_data = [side_data1, current.side_data.clone()];
+                self.pending = Some(Box::new(decoded2));
+                return Some(decoded1);
+            }
+            if current.time < time1 && self.next.is_none() {
+                // The second input has ended, so no later frame can be paired.
+                return None;
+            }
+        }
+    }
+
+    fn read_paired<T: Pixel>(&mut self, index: usize, tolerance: f64) -> Option<Decoded<T>> {
+        if index == 0 {
+            self.next_pair(tolerance)
+        } else {
+            self.pending
+                .take()?
+                .downcast::<Decoded<T>>()
+                .ok()
+                .map(|decoded| *decoded)
+        }
+    }
+}
+
+/// One of two decoders whose frames are read in pairs.
+///
+/// The two decoders must be read in turn, first then second, which is what
+/// the metrics of this crate do.
+pub struct Paired<D: Decoder> {
+    shared: Arc<Mutex<Shared<D>>>,
+    index: usize,
+}
+
+impl<D: Decoder> Paired<D> {
+    /// Pairs the frames of two decoders in the order they are decoded, which
+    /// is the same as reading the decoders directly.
+    pub fn lockstep(decoder1: D, decoder2: D) -> (Self, Self) {
+        Self::new(decoder1, decoder2, None)
+    }
+
+    /// Pairs each frame of `decoder1` with the frame of `decoder2` whose
+    /// timestamp is the nearest, if they are at most `tolerance` seconds apart.
+    ///
+    /// Frames of `decoder1` without such a frame are skipped, and a frame of
+    /// `decoder2` is paired with several frames of `decoder1` if it is the
+    /// nearest to all of them. The timestamps are taken from
+    /// [`Decoder::last_frame_meta`], or computed from the frame numbers and the
+    /// time base for decoders that do not report them.
+    ///
+    /// [`Decoder::last_frame_meta`] and [`Decoder::last_side_data`] of the
+    /// paired decoders return the metadata and side data of the frames of the
+    /// last pair.
+    pub fn by_timestamp(decoder1: D, decoder2: D, tolerance: f64) -> (Self, Self) {
+        Self::new(decoder1, decoder2, Some(tolerance))
+    }
+
+    fn new(decoder1: D, decoder2: D, tolerance: Option<f64>) -> (Self, Self) {
+        let shared = Arc::new(Mutex::new(Shared {
+            decoders: [decoder1, decoder2],
+            tolerance,
+            frames_read: [0; 2],
+            current: None,
+            next: None,
+            second_ended: false,
+            pending: None,
+            metas: [None; 2],
+            side_data: Default::default(),
+        }));
+        (
+            Paired {
+                shared: Arc::clone(&shared),
+                index: 0,
+            },
+            Paired { shared, index: 1 },
+        )
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Shared<D>> {
+        self.shared.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<D: Decoder> Decoder for Paired<D> {
+    fn read_video_frame<T: Pixel>(&mut self) -> Option<Frame<T>> {
+        let mut shared = self.lock();
+        match shared.tolerance {
+            None => shared.decoders[self.index].read_video_frame(),
+            Some(tolerance) => shared
+                .read_paired::<T>(self.index, tolerance)
+                .map(|(frame, _)| frame),
+        }
+    }
+
+    fn read_video_frame_into<T: Pixel>(&mut self, frame: &mut Frame<T>) -> bool {
+        let mut shared = self.lock();
+        match shared.tolerance {
+            None => shared.decoders[self.index].read_video_frame_into(frame),
+            Some(tolerance) => match shared.read_paired::<T>(self.index, tolerance) {
+                Some((decoded, _)) => {
+                    *frame = decoded;
+                    true
+                }
+                None => false,
+            },
+        }
+    }
+
+    fn read_video_frame_ref<T: Pixel, R, F: FnOnce(FrameRef<'_, T>) -> R>(
+        &mut self,
+        f: F,
+    ) -> Option<R> {
+        let mut shared = self.lock();
+        match shared.tolerance {
+            None => shared.decoders[self.index].read_video_frame_ref(f),
+            Some(tolerance) => shared
+                .read_paired::<T>(self.index, tolerance)
+                .map(|(frame, _)| f(FrameRef::from(&frame))),
+        }
+    }
+
+    fn read_video_frame_with_alpha<T: Pixel>(&mut self) -> Option<(Frame<T>, Option<Plane<T>>)> {
+        let mut shared = self.lock();
+        match shared.tolerance {
+            None => shared.decoders[self.index].read_video_frame_with_alpha(),
+            Some(tolerance) => shared.read_paired(self.index, tolerance),
+        }
+    }
+
+    fn get_bit_depth(&self) -> usize {
+        self.lock().decoders[self.index].get_bit_depth()
+    }
+
+    fn get_stream_info(&self) -> StreamInfo {
+        self.lock().decoders[self.index].get_stream_info()
+    }
+
+    fn last_frame_meta(&self) -> Option<FrameMeta> {
+        let shared = self.lock();
+        match shared.tolerance {
+            None => shared.decoders[self.index].last_frame_meta(),
+            Some(_) => shared.metas[self.index],
+        }
+    }
+
+    fn last_side_data(&self) -> Option<SideData> {
+        let shared = self.lock();
+        match shared.tolerance {
+            None => shared.decoders[self.index].last_side_data(),
+            Some(_) => shared.side_data[self.index].clone(),
+        }
+    }
+
+    fn capabilities(&self) -> DecoderCapabilities {
+        DecoderCapabilities {
+            metadata: self.lock().decoders[self.index].capabilities().metadata,
+            ..Default::default()
+        }
+    }
+
+    fn get_video_details(&self) -> VideoDetails {
+        self.lock().decoders[self.index].get_video_details()
+    }
+}
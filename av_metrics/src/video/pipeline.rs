@@ -0,0 +1,344 @@
+use crate::video::decode::FrameMeta;
+use crate::video::pixel::Pixel;
+use crate::video::side_data::SideData;
+use crate::video::{FrameContext, PlanarMetrics};
+use std::any::Any;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+use v_frame::frame::Frame;
+
+/// A snapshot of the decode and scoring pipeline of a video metric.
+///
+/// Videos are decoded on one thread and the frames are sent through a
+/// bounded queue to the threads computing the metric. If the decoder spends
+/// a lot of time waiting for room in the queue, the metric is the bottleneck
+/// and more threads will help. If the scoring threads spend most of their time
+/// waiting for frames, the decoder is the bottleneck.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PipelineStats {
+    /// Number of frame pairs decoded so far.
+    pub frames_decoded: usize,
+    /// Number of frame pairs for which the metric has been computed.
+    pub frames_scored: usize,
+    /// Number of decoded frame pairs waiting to be scored.
+    pub queue_depth: usize,
+    /// Maximum number of frame pairs that may wait to be scored.
+    pub queue_capacity: usize,
+    /// Number of threads computing the metric.
+    pub threads: usize,
+    /// Time since the video started processing.
+    pub elapsed: Duration,
+    /// Total time the decoder spent waiting for room in the queue.
+    pub decoder_wait: Duration,
+    /// Total time the scoring threads spent waiting for decoded frames,
+    /// summed over all threads.
+    pub scorer_wait: Duration,
+    /// Number of trailing frames of each video that were not compared because
+    /// the other video ended first.
+    pub ignored_frames: [usize; 2],
+}
+
+impl PipelineStats {
+    /// Frame pairs decoded per second.
+    pub fn decode_fps(&self) -> f64 {
+        per_second(self.frames_decoded, self.elapsed)
+    }
+
+    /// Frame pairs scored per second.
+    pub fn score_fps(&self) -> f64 {
+        per_second(self.frames_scored, self.elapsed)
+    }
+}
+
+fn per_second(frames: usize, elapsed: Duration) -> f64 {
+    let secs = elapsed.as_secs_f64();
+    if secs > 0.0 {
+        frames as f64 / secs
+    } else {
+        0.0
+    }
+}
+
+/// Collects [`PipelineStats`] from the video metrics it is given to with
+/// [`VideoOptions::monitor`](crate::video::VideoOptions::monitor).
+///
+/// The monitor can be cloned and read from another thread, for example
+/// from the progress callback, while the metric is running. The counters are
+/// reset each time a video starts processing, so a monitor given to several
+/// metrics describes the most recent one.
+#[derive(Debug, Clone, Default)]
+pub struct PipelineMonitor {
+    inner: Arc<Counters>,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    start: Mutex<Option<Instant>>,
+    frames_decoded: AtomicUsize,
+    frames_received: AtomicUsize,
+    frames_scored: AtomicUsize,
+    queue_capacity: AtomicUsize,
+    threads: AtomicUsize,
+    decoder_wait_ns: AtomicU64,
+    scorer_wait_ns: AtomicU64,
+    ignored_frames: [AtomicUsize; 2],
+}
+
+impl PipelineMonitor {
+    /// Creates a monitor with all counters at zero.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the current statistics.
+    pub fn stats(&self) -> PipelineStats {
+        let c = &self.inner;
+        let frames_decoded = c.frames_decoded.load(Ordering::Relaxed);
+        let frames_received = c.frames_received.load(Ordering::Relaxed);
+        PipelineStats {
+            frames_decoded,
+            frames_scored: c.frames_scored.load(Ordering::Relaxed),
+            queue_depth: frames_decoded.saturating_sub(frames_received),
+            queue_capacity: c.queue_capacity.load(Ordering::Relaxed),
+            threads: c.threads.load(Ordering::Relaxed),
+            elapsed: c
+                .start
+                .lock()
+                .unwrap()
+                .map(|start| start.elapsed())
+                .unwrap_or_default(),
+            decoder_wait: Duration::from_nanos(c.decoder_wait_ns.load(Ordering::Relaxed)),
+            scorer_wait: Duration::from_nanos(c.scorer_wait_ns.load(Ordering::Relaxed)),
+            ignored_frames: [
+                c.ignored_frames[0].load(Ordering::Relaxed),
+                c.ignored_frames[1].load(Ordering::Relaxed),
+            ],
+        }
+    }
+
+    pub(crate) fn start(&self, queue_capacity: usize, threads: usize) {
+        let c = &self.inner;
+        *c.start.lock().unwrap() = Some(Instant::now());
+        c.frames_decoded.store(0, Ordering::Relaxed);
+        c.frames_received.store(0, Ordering::Relaxed);
+        c.frames_scored.store(0, Ordering::Relaxed);
+        c.queue_capacity.store(queue_capacity, Ordering::Relaxed);
+        c.threads.store(threads, Ordering::Relaxed);
+        c.decoder_wait_ns.store(0, Ordering::Relaxed);
+        c.scorer_wait_ns.store(0, Ordering::Relaxed);
+        for ignored in c.ignored_frames.iter() {
+            ignored.store(0, Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn frame_decoded(&self, wait: Duration) {
+        self.inner.frames_decoded.fetch_add(1, Ordering::Relaxed);
+        add_duration(&self.inner.decoder_wait_ns, wait);
+    }
+
+    pub(crate) fn frame_received(&self, wait: Duration) {
+        self.inner.frames_received.fetch_add(1, Ordering::Relaxed);
+        add_duration(&self.inner.scorer_wait_ns, wait);
+    }
+
+    pub(crate) fn frame_scored(&self) {
+        self.inner.frames_scored.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn frames_ignored(&self, input: usize, frames: usize) {
+        self.inner.ignored_frames[input].store(frames, Ordering::Relaxed);
+    }
+}
+
+fn add_duration(counter: &AtomicU64, duration: Duration) {
+    counter.fetch_add(duration.as_nanos() as u64, Ordering::Relaxed);
+}
+
+/// Pauses and throttles the video metrics it is given to with
+/// [`VideoOptions::control`](crate::video::VideoOptions::control), so that
+/// scoring in the background leaves the machine responsive.
+///
+/// The control can be cloned and used from another thread, for example from
+/// a user interface, while the metric is running. Pausing stops the decoder
+/// from reading more frames; the frames already decoded are still scored.
+#[derive(Debug, Clone, Default)]
+pub struct PipelineControl {
+    inner: Arc<ControlState>,
+}
+
+#[derive(Debug, Default)]
+struct ControlState {
+    paused: Mutex<bool>,
+    resumed: Condvar,
+    /// The bits of the maximum frame rate, or 0 for no limit.
+    max_fps: AtomicU64,
+}
+
+impl PipelineControl {
+    /// Creates a control that is running, without a frame rate limit.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stops reading frames until [`resume`](Self::resume) is called.
+    pub fn pause(&self) {
+        *self.inner.paused.lock().unwrap() = true;
+    }
+
+    /// Continues reading frames after [`pause`](Self::pause).
+    pub fn resume(&self) {
+        *self.inner.paused.lock().unwrap() = false;
+        self.inner.resumed.notify_all();
+    }
+
+    /// Whether the control is paused.
+    pub fn is_paused(&self) -> bool {
+        *self.inner.paused.lock().unwrap()
+    }
+
+    /// Limits the number of frame pairs read per second, or removes the limit
+    /// with `None`. Limits that are not positive are ignored.
+    pub fn set_max_fps(&self, max_fps: Option<f64>) {
+        let bits = max_fps
+            .filter(|fps| *fps > 0.0)
+            .map_or(0, |fps| fps.to_bits());
+        self.inner.max_fps.store(bits, Ordering::Relaxed);
+    }
+
+    /// The maximum number of frame pairs read per second, if limited.
+    pub fn max_fps(&self) -> Option<f64> {
+        match self.inner.max_fps.load(Ordering::Relaxed) {
+            0 => None,
+            bits => Some(f64::from_bits(bits)),
+        }
+    }
+
+    /// Blocks the decoding thread while paused, and until the next frame is
+    /// due under the frame rate limit. `next_frame` is when the next frame is
+    /// due, updated for the frame after it.
+    pub(crate) fn wait_turn(&self, next_frame: &mut Option<Instant>) {
+        let mut paused = self.inner.paused.lock().unwrap();
+        if *paused {
+            while *paused {
+                paused = self.inner.resumed.wait(paused).unwrap();
+            }
+            // Do not catch up on the time spent paused
+            *next_frame = None;
+        }
+        drop(paused);
+
+        let Some(max_fps) = self.max_fps() else {
+            *next_frame = None;
+            return;
+        };
+        let now = Instant::now();
+        let due = next_frame.map_or(now, |due| due.max(now));
+        if due > now {
+            std::thread::sleep(due - now);
+        }
+        *next_frame = Some(due + Duration::from_secs_f64(1.0 / max_fps));
+    }
+}
+
+/// The score of a single frame pair.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Score {
+    /// The scores of each plane, of metrics such as PSNR and SSIM.
+    Planar(PlanarMetrics),
+    /// The single score of metrics such as CIEDE2000.
+    Value(f64),
+}
+
+impl Score {
+    /// The average of the planes, or the single score.
+    pub fn value(&self) -> f64 {
+        match self {
+            Score::Planar(metrics) => metrics.avg,
+            Score::Value(value) => *value,
+        }
+    }
+}
+
+/// A frame pair scored by a video metric, as given to a [`FrameSink`].
+#[derive(Debug)]
+pub struct ScoredFrame<'a> {
+    /// The number of the frame pair, from 0.
+    pub frame: usize,
+    /// The score of the frame pair, for the metrics that have one. Metrics
+    /// that only make sense over several frames, such as the features of
+    /// VMAF, have none.
+    pub score: Option<Score>,
+    /// The position, timestamp and frame type each decoder reported for its
+    /// frame.
+    pub meta: &'a [Option<FrameMeta>; 2],
+    /// The encoder statistics each decoder reported for its frame.
+    pub side_data: &'a [Option<SideData>; 2],
+    /// The bit depth and chroma sampling of the frames.
+    pub context: FrameContext,
+    frames: [&'a dyn Any; 2],
+}
+
+impl<'a> ScoredFrame<'a> {
+    pub(crate) fn new<T: Pixel>(
+        frame: usize,
+        frames: [&'a Frame<T>; 2],
+        meta: &'a [Option<FrameMeta>; 2],
+        side_data: &'a [Option<SideData>; 2],
+        context: FrameContext,
+    ) -> Self {
+        ScoredFrame {
+            frame,
+            score: None,
+            meta,
+            side_data,
+            context,
+            frames: [frames[0], frames[1]],
+        }
+    }
+
+    /// The compared frames, if their samples are of type `T`: `u8` up to 8
+    /// bits, and `u16` above.
+    pub fn frames<T: Pixel>(&self) -> Option<[&'a Frame<T>; 2]> {
+        Some([
+            self.frames[0].downcast_ref()?,
+            self.frames[1].downcast_ref()?,
+        ])
+    }
+}
+
+/// Receives every frame pair scored by the video metrics it is given to with
+/// [`VideoOptions::frame_sink`](crate::video::VideoOptions::frame_sink), as
+/// soon as it is scored.
+///
+/// This gives the per-frame scores of a video metric along with its average,
+/// without computing the metric a second time. The frames are scored on
+/// several threads, so the sink is called from those threads and the frames
+/// may arrive out of order. A scoring thread waits for the sink before it
+/// takes the next frame pair, so the sink should not block for long.
+#[derive(Clone)]
+pub struct FrameSink {
+    callback: Arc<dyn Fn(&ScoredFrame) + Send + Sync>,
+}
+
+impl FrameSink {
+    /// Creates a sink calling `callback` with each scored frame pair.
+    pub fn new<F: Fn(&ScoredFrame) + Send + Sync + 'static>(callback: F) -> Self {
+        FrameSink {
+            callback: Arc::new(callback),
+        }
+    }
+
+    pub(crate) fn send(&self, frame: &ScoredFrame) {
+        (self.callback)(frame)
+    }
+}
+
+impl fmt::Debug for FrameSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FrameSink").finish_non_exhaustive()
+    }
+}
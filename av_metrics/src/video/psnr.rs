@@ -1,22 +1,22 @@
 //! Peak Signal-to-Noise Ratio metric.
 //!
 //! PSNR is most easily defined via the mean squared error between two images.
+//! The mean squared error itself is reported by [`calculate_video_mse`].
 //!
 //! See https://en.wikipedia.org/wiki/Peak_signal-to-noise_ratio for more details.
 
-use crate::video::decode::Decoder;
+use crate::video::decode::{Decoder, FrameRef, PlaneRef};
 use crate::video::pixel::CastFromPrimitive;
 use crate::video::pixel::Pixel;
-use crate::video::{PlanarMetrics, VideoMetric};
+use crate::video::{
+    CompatMode, FrameContext, PlanarMetrics, PlaneSelect, PlaneWeights, Sampling, Score,
+    VideoMetric, VideoOptions,
+};
 use crate::MetricsError;
 use std::error::Error;
-use std::mem::size_of;
 use v_frame::frame::Frame;
-use v_frame::plane::Plane;
 use v_frame::prelude::ChromaSampling;
 
-use super::FrameCompare;
-
 /// Calculates the PSNR for two videos. Higher is better.
 ///
 /// PSNR is capped at 100 in order to avoid skewed statistics
@@ -29,10 +29,76 @@ pub fn calculate_video_psnr<D: Decoder, F: Fn(usize) + Send>(
     frame_limit: Option<usize>,
     progress_callback: F,
 ) -> Result<PlanarMetrics, Box<dyn Error>> {
-    let metrics = Psnr.process_video(decoder1, decoder2, frame_limit, progress_callback)?;
+    calculate_video_psnr_with_config(
+        decoder1,
+        decoder2,
+        frame_limit,
+        progress_callback,
+        PsnrConfig::default(),
+    )
+}
+
+/// Calculates the PSNR for pairs of frames from any source, such as frames
+/// received over a network or generated in memory. Higher is better.
+///
+/// The frames are scored with the same multithreaded pipeline as
+/// [`calculate_video_psnr`].
+#[inline]
+pub fn calculate_video_psnr_from_frames<T: Pixel, I, F: Fn(usize) + Send>(
+    frames: I,
+    bit_depth: usize,
+    chroma_sampling: ChromaSampling,
+    progress_callback: F,
+) -> Result<PlanarMetrics, Box<dyn Error>>
+where
+    I: IntoIterator<Item = (Frame<T>, Frame<T>)>,
+    I::IntoIter: Send,
+{
+    let metrics = Psnr {
+        config: PsnrConfig::default(),
+    }
+    .process_frames(frames, bit_depth, chroma_sampling, progress_callback)?;
     Ok(metrics.psnr)
 }
 
+/// Calculates the PSNR for two videos using the given configuration. Higher is better.
+///
+/// See [`PsnrConfig`] for the available options.
+#[inline]
+pub fn calculate_video_psnr_with_config<D: Decoder, F: Fn(usize) + Send>(
+    decoder1: &mut D,
+    decoder2: &mut D,
+    frame_limit: Option<usize>,
+    progress_callback: F,
+    config: PsnrConfig,
+) -> Result<PlanarMetrics, Box<dyn Error>> {
+    calculate_video_psnr_with(
+        decoder1,
+        decoder2,
+        PsnrOptions {
+            video: VideoOptions::limited(frame_limit, progress_callback),
+            config,
+        },
+    )
+}
+
+/// Calculates the PSNR for two videos with the given options. Higher is better.
+///
+/// See [`PsnrOptions`] for the available options.
+#[inline]
+pub fn calculate_video_psnr_with<D: Decoder, F: Fn(usize) + Send>(
+    decoder1: &mut D,
+    decoder2: &mut D,
+    options: PsnrOptions<F>,
+) -> Result<PlanarMetrics, Box<dyn Error>> {
+    let config = options.config;
+    options.video.run(|limits, progress_callback| {
+        let metrics =
+            Psnr { config }.process_video(decoder1, decoder2, limits, progress_callback)?;
+        Ok(metrics.psnr)
+    })
+}
+
 /// Calculates the APSNR for two videos. Higher is better.
 ///
 /// APSNR is capped at 100 in order to avoid skewed statistics
@@ -45,10 +111,76 @@ pub fn calculate_video_apsnr<D: Decoder, F: Fn(usize) + Send>(
     frame_limit: Option<usize>,
     progress_callback: F,
 ) -> Result<PlanarMetrics, Box<dyn Error>> {
-    let metrics = Psnr.process_video(decoder1, decoder2, frame_limit, progress_callback)?;
+    calculate_video_apsnr_with_config(
+        decoder1,
+        decoder2,
+        frame_limit,
+        progress_callback,
+        PsnrConfig::default(),
+    )
+}
+
+/// Calculates the APSNR for pairs of frames from any source, such as frames
+/// received over a network or generated in memory. Higher is better.
+///
+/// The frames are scored with the same multithreaded pipeline as
+/// [`calculate_video_apsnr`].
+#[inline]
+pub fn calculate_video_apsnr_from_frames<T: Pixel, I, F: Fn(usize) + Send>(
+    frames: I,
+    bit_depth: usize,
+    chroma_sampling: ChromaSampling,
+    progress_callback: F,
+) -> Result<PlanarMetrics, Box<dyn Error>>
+where
+    I: IntoIterator<Item = (Frame<T>, Frame<T>)>,
+    I::IntoIter: Send,
+{
+    let metrics = Psnr {
+        config: PsnrConfig::default(),
+    }
+    .process_frames(frames, bit_depth, chroma_sampling, progress_callback)?;
     Ok(metrics.apsnr)
 }
 
+/// Calculates the APSNR for two videos using the given configuration. Higher is better.
+///
+/// See [`PsnrConfig`] for the available options.
+#[inline]
+pub fn calculate_video_apsnr_with_config<D: Decoder, F: Fn(usize) + Send>(
+    decoder1: &mut D,
+    decoder2: &mut D,
+    frame_limit: Option<usize>,
+    progress_callback: F,
+    config: PsnrConfig,
+) -> Result<PlanarMetrics, Box<dyn Error>> {
+    calculate_video_apsnr_with(
+        decoder1,
+        decoder2,
+        PsnrOptions {
+            video: VideoOptions::limited(frame_limit, progress_callback),
+            config,
+        },
+    )
+}
+
+/// Calculates the APSNR for two videos with the given options. Higher is better.
+///
+/// See [`PsnrOptions`] for the available options.
+#[inline]
+pub fn calculate_video_apsnr_with<D: Decoder, F: Fn(usize) + Send>(
+    decoder1: &mut D,
+    decoder2: &mut D,
+    options: PsnrOptions<F>,
+) -> Result<PlanarMetrics, Box<dyn Error>> {
+    let config = options.config;
+    options.video.run(|limits, progress_callback| {
+        let metrics =
+            Psnr { config }.process_video(decoder1, decoder2, limits, progress_callback)?;
+        Ok(metrics.apsnr)
+    })
+}
+
 /// Calculates the PSNR for two video frames. Higher is better.
 ///
 /// PSNR is capped at 100 in order to avoid skewed statistics
@@ -61,22 +193,377 @@ pub fn calculate_frame_psnr<T: Pixel>(
     bit_depth: usize,
     chroma_sampling: ChromaSampling,
 ) -> Result<PlanarMetrics, Box<dyn Error>> {
-    let metrics = Psnr.process_frame(frame1, frame2, bit_depth, chroma_sampling)?;
-    Ok(PlanarMetrics {
-        y: calculate_psnr(metrics[0]),
-        u: calculate_psnr(metrics[1]),
-        v: calculate_psnr(metrics[2]),
-        avg: calculate_summed_psnr(&metrics),
+    calculate_frame_psnr_with_config(
+        frame1,
+        frame2,
+        bit_depth,
+        chroma_sampling,
+        PsnrConfig::default(),
+    )
+}
+
+/// Calculates the PSNR for two video frames using the given configuration.
+/// Higher is better.
+///
+/// See [`PsnrConfig`] for the available options.
+#[inline]
+pub fn calculate_frame_psnr_with_config<T: Pixel>(
+    frame1: &Frame<T>,
+    frame2: &Frame<T>,
+    bit_depth: usize,
+    chroma_sampling: ChromaSampling,
+    config: PsnrConfig,
+) -> Result<PlanarMetrics, Box<dyn Error>> {
+    let metrics = Psnr { config }.process_frame(frame1, frame2, bit_depth, chroma_sampling)?;
+    Ok(planar_psnr(
+        &metrics,
+        config.planes,
+        config.weights,
+        config.compat,
+    ))
+}
+
+/// Calculates the PSNR for two borrowed video frames, such as the ones passed
+/// by [`Decoder::read_video_frame_ref`]. Higher is better.
+///
+/// PSNR is capped at 100 in order to avoid skewed statistics
+/// from e.g. all black frames, which would
+/// otherwise show a PSNR of infinity.
+#[inline]
+pub fn calculate_frame_psnr_ref<T: Pixel>(
+    frame1: &FrameRef<T>,
+    frame2: &FrameRef<T>,
+    bit_depth: usize,
+    chroma_sampling: ChromaSampling,
+) -> Result<PlanarMetrics, Box<dyn Error>> {
+    FrameContext::new(bit_depth, chroma_sampling)?.validate_ref(frame1, frame2)?;
+    Ok(planar_psnr(
+        &calculate_frame_psnr_metrics(
+            frame1,
+            frame2,
+            bit_depth,
+            None,
+            PlaneSelect::ALL,
+            Sampling::Full,
+        ),
+        PlaneSelect::ALL,
+        PlaneWeights::Default,
+        CompatMode::Native,
+    ))
+}
+
+pub(crate) fn planar_psnr(
+    metrics: &[PsnrMetrics; 3],
+    planes: PlaneSelect,
+    weights: PlaneWeights,
+    compat: CompatMode,
+) -> PlanarMetrics {
+    let plane = |index: usize| {
+        if planes.has_plane(index) {
+            calculate_psnr(metrics[index], compat)
+        } else {
+            0.0
+        }
+    };
+    PlanarMetrics {
+        y: plane(0),
+        u: plane(1),
+        v: plane(2),
+        avg: calculate_weighted_psnr(metrics, planes, weights, compat),
+        alpha: None,
+        dssim: None,
+    }
+}
+
+/// The mean squared error of each plane of two videos, with its square root
+/// and the mean squared error of each frame.
+///
+/// The errors are in squared sample values at the bit depth of the inputs,
+/// before the conversion to decibels of PSNR, so that they can be averaged or
+/// interpolated directly, such as for BD-rates on MSE.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MseResults {
+    /// The mean squared error of each plane over all frames. Planes that are
+    /// not selected are reported as 0 and are not part of the average.
+    pub mse: PlanarMetrics,
+    /// The root mean squared error of each plane over all frames, the square
+    /// root of each value of `mse`.
+    pub rmse: PlanarMetrics,
+    /// The mean squared error of each plane of each frame.
+    pub frames: Vec<PlanarMetrics>,
+}
+
+/// Calculates the mean squared error of each plane of two videos. Lower is
+/// better.
+#[inline]
+pub fn calculate_video_mse<D: Decoder, F: Fn(usize) + Send>(
+    decoder1: &mut D,
+    decoder2: &mut D,
+    frame_limit: Option<usize>,
+    progress_callback: F,
+) -> Result<MseResults, Box<dyn Error>> {
+    calculate_video_mse_with(
+        decoder1,
+        decoder2,
+        PsnrOptions {
+            video: VideoOptions::limited(frame_limit, progress_callback),
+            config: PsnrConfig::default(),
+        },
+    )
+}
+
+/// Calculates the mean squared error of each plane of two videos with the
+/// options of PSNR. Lower is better.
+///
+/// The prefilter, planes, sampling and weights apply as they do to PSNR. The
+/// peak value and the compatibility mode, which only affect the conversion to
+/// decibels, are not used.
+#[inline]
+pub fn calculate_video_mse_with<D: Decoder, F: Fn(usize) + Send>(
+    decoder1: &mut D,
+    decoder2: &mut D,
+    options: PsnrOptions<F>,
+) -> Result<MseResults, Box<dyn Error>> {
+    let config = options.config;
+    options.video.run(|limits, progress_callback| {
+        Mse { config }.process_video(decoder1, decoder2, limits, progress_callback)
     })
 }
 
+/// Calculates the mean squared error of each plane of two video frames. Lower
+/// is better. The root mean squared error is the square root of each value.
+#[inline]
+pub fn calculate_frame_mse<T: Pixel>(
+    frame1: &Frame<T>,
+    frame2: &Frame<T>,
+    bit_depth: usize,
+    chroma_sampling: ChromaSampling,
+) -> Result<PlanarMetrics, Box<dyn Error>> {
+    calculate_frame_mse_with_config(
+        frame1,
+        frame2,
+        bit_depth,
+        chroma_sampling,
+        PsnrConfig::default(),
+    )
+}
+
+/// Calculates the mean squared error of each plane of two video frames using
+/// the configuration of PSNR. Lower is better.
+///
+/// See [`calculate_video_mse_with`] for the options that apply.
+#[inline]
+pub fn calculate_frame_mse_with_config<T: Pixel>(
+    frame1: &Frame<T>,
+    frame2: &Frame<T>,
+    bit_depth: usize,
+    chroma_sampling: ChromaSampling,
+    config: PsnrConfig,
+) -> Result<PlanarMetrics, Box<dyn Error>> {
+    let metrics = Mse { config }.process_frame(frame1, frame2, bit_depth, chroma_sampling)?;
+    Ok(planar_mse(&metrics, config.planes, config.weights))
+}
+
+/// The mean squared error of each selected plane and of the planes together,
+/// with the squared errors summed by default as for PSNR.
+fn planar_mse(
+    metrics: &[PsnrMetrics; 3],
+    planes: PlaneSelect,
+    weights: PlaneWeights,
+) -> PlanarMetrics {
+    let plane = |index: usize| {
+        if planes.has_plane(index) {
+            metrics[index].mse()
+        } else {
+            0.0
+        }
+    };
+    PlanarMetrics {
+        y: plane(0),
+        u: plane(1),
+        v: plane(2),
+        avg: weighted_mean(metrics, planes, weights, PsnrMetrics::mse).unwrap_or_else(|| {
+            metrics
+                .iter()
+                .copied()
+                .fold(PsnrMetrics::default(), PsnrMetrics::add)
+                .mse()
+        }),
+        alpha: None,
+        dssim: None,
+    }
+}
+
+struct Mse {
+    config: PsnrConfig,
+}
+
+impl VideoMetric for Mse {
+    type FrameResult = [PsnrMetrics; 3];
+    type VideoResult = MseResults;
+
+    fn process_frame<T: Pixel>(
+        &self,
+        frame1: &Frame<T>,
+        frame2: &Frame<T>,
+        bit_depth: usize,
+        chroma_sampling: ChromaSampling,
+    ) -> Result<Self::FrameResult, Box<dyn Error>> {
+        Psnr {
+            config: self.config,
+        }
+        .process_frame(frame1, frame2, bit_depth, chroma_sampling)
+    }
+
+    fn aggregate_frame_results(
+        &self,
+        metrics: &[Self::FrameResult],
+    ) -> Result<Self::VideoResult, Box<dyn Error>> {
+        let PsnrConfig {
+            planes, weights, ..
+        } = self.config;
+        let totals = [0, 1, 2].map(|index| {
+            metrics
+                .iter()
+                .map(|m| m[index])
+                .fold(PsnrMetrics::default(), PsnrMetrics::add)
+        });
+        let mse = planar_mse(&totals, planes, weights);
+        Ok(MseResults {
+            mse,
+            rmse: PlanarMetrics {
+                y: mse.y.sqrt(),
+                u: mse.u.sqrt(),
+                v: mse.v.sqrt(),
+                avg: mse.avg.sqrt(),
+                alpha: None,
+                dssim: None,
+            },
+            frames: metrics
+                .iter()
+                .map(|m| planar_mse(m, planes, weights))
+                .collect(),
+        })
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 struct PsnrResults {
     psnr: PlanarMetrics,
     apsnr: PlanarMetrics,
 }
 
-struct Psnr;
+/// A low-pass filter applied to both inputs before computing PSNR.
+///
+/// Dithering adds high frequency noise that is barely visible but inflates the
+/// mean squared error. Filtering both inputs first removes most of it, so that
+/// dithered encodes are not unfairly penalized.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Prefilter {
+    /// A 3x3 binomial filter, with weights 1-2-1 in each direction.
+    Binomial3x3,
+    /// A 3x3 box filter, averaging each sample with its 8 neighbors.
+    Box3x3,
+}
+
+impl Prefilter {
+    fn kernel(self) -> [[u32; 3]; 3] {
+        match self {
+            Prefilter::Binomial3x3 => [[1, 2, 1], [2, 4, 2], [1, 2, 1]],
+            Prefilter::Box3x3 => [[1; 3]; 3],
+        }
+    }
+}
+
+/// Options for computing PSNR and APSNR.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PsnrConfig {
+    /// A filter applied to both inputs before the squared error is computed.
+    pub prefilter: Option<Prefilter>,
+    /// The planes to compute. The others are reported as 0 and are not part
+    /// of the average.
+    pub planes: PlaneSelect,
+    /// The conventions to follow. See [`CompatMode`] for the differences.
+    pub compat: CompatMode,
+    /// The samples the squared error is computed on. See [`Sampling`] for the
+    /// expected deviation of a subsample.
+    pub sampling: Sampling,
+    /// The peak sample value the error is measured against, instead of
+    /// `(1 << bit_depth) - 1`. Useful for limited range content, where the
+    /// nominal peak is lower, or for floating point sources scaled into a
+    /// different integer range.
+    pub peak_value: Option<usize>,
+    /// How the selected planes are weighted in the average.
+    pub weights: PlaneWeights,
+}
+
+/// Options for computing PSNR and APSNR over whole videos, built with
+/// chained calls:
+///
+/// ```
+/// use av_metrics::video::psnr::{Prefilter, PsnrOptions};
+///
+/// let options = PsnrOptions::new()
+///     .frame_limit(100)
+///     .threads(4)
+///     .prefilter(Prefilter::Box3x3);
+/// ```
+#[derive(Debug, Clone)]
+pub struct PsnrOptions<F = fn(usize)> {
+    /// The frame limit, thread count and progress callback.
+    pub video: VideoOptions<F>,
+    /// The options of PSNR itself.
+    pub config: PsnrConfig,
+}
+
+video_options!(PsnrOptions);
+
+impl<F> PsnrOptions<F> {
+    /// Filters both inputs with `prefilter` before the squared error is
+    /// computed.
+    pub fn prefilter(mut self, prefilter: Prefilter) -> Self {
+        self.config.prefilter = Some(prefilter);
+        self
+    }
+
+    /// Only computes the given planes.
+    pub fn planes(mut self, planes: PlaneSelect) -> Self {
+        self.config.planes = planes;
+        self
+    }
+
+    /// Follows the conventions of `compat`.
+    pub fn compat(mut self, compat: CompatMode) -> Self {
+        self.config.compat = compat;
+        self
+    }
+
+    /// Only computes the squared error on the samples selected by
+    /// `sampling`.
+    pub fn sampling(mut self, sampling: Sampling) -> Self {
+        self.config.sampling = sampling;
+        self
+    }
+
+    /// Measures the error against a peak sample value of `peak_value`.
+    pub fn peak_value(mut self, peak_value: usize) -> Self {
+        self.config.peak_value = Some(peak_value);
+        self
+    }
+
+    /// Weights the planes in the average with `weights`.
+    pub fn weights(mut self, weights: PlaneWeights) -> Self {
+        self.config.weights = weights;
+        self
+    }
+}
+
+struct Psnr {
+    config: PsnrConfig,
+}
 
 impl VideoMetric for Psnr {
     type FrameResult = [PsnrMetrics; 3];
@@ -87,110 +574,338 @@ impl VideoMetric for Psnr {
         frame1: &Frame<T>,
         frame2: &Frame<T>,
         bit_depth: usize,
-        _chroma_sampling: ChromaSampling,
+        chroma_sampling: ChromaSampling,
     ) -> Result<Self::FrameResult, Box<dyn Error>> {
-        if (size_of::<T>() == 1 && bit_depth > 8) || (size_of::<T>() == 2 && bit_depth <= 8) {
-            return Err(Box::new(MetricsError::InputMismatch {
-                reason: "Bit depths does not match pixel width",
+        FrameContext::new(bit_depth, chroma_sampling)?.validate(frame1, frame2)?;
+        if self.config.planes.is_empty() {
+            return Err(Box::new(MetricsError::UnsupportedInput {
+                reason: "No planes selected",
+            }));
+        }
+        if self.config.peak_value == Some(0) {
+            return Err(Box::new(MetricsError::UnsupportedInput {
+                reason: "The peak value must be greater than 0",
             }));
         }
 
-        frame1.can_compare(frame2)?;
-
-        let mut y = Default::default();
-        let mut u = Default::default();
-        let mut v = Default::default();
-
-        rayon::scope(|s| {
-            s.spawn(|_| {
-                y = calculate_plane_psnr_metrics(&frame1.planes[0], &frame2.planes[0], bit_depth)
-            });
-            s.spawn(|_| {
-                u = calculate_plane_psnr_metrics(&frame1.planes[1], &frame2.planes[1], bit_depth)
-            });
-            s.spawn(|_| {
-                v = calculate_plane_psnr_metrics(&frame1.planes[2], &frame2.planes[2], bit_depth)
-            });
-        });
+        let mut metrics = calculate_frame_psnr_metrics(
+            &FrameRef::from(frame1),
+            &FrameRef::from(frame2),
+            bit_depth,
+            self.config.prefilter,
+            self.config.planes,
+            self.config.sampling,
+        );
+        if let Some(peak_value) = self.config.peak_value {
+            for plane in metrics.iter_mut() {
+                plane.sample_max = peak_value;
+            }
+        }
+        Ok(metrics)
+    }
 
-        Ok([y, u, v])
+    fn frame_score(&self, result: &Self::FrameResult) -> Option<Score> {
+        let PsnrConfig {
+            planes,
+            weights,
+            compat,
+            ..
+        } = self.config;
+        Some(Score::Planar(planar_psnr(result, planes, weights, compat)))
     }
 
     fn aggregate_frame_results(
         &self,
         metrics: &[Self::FrameResult],
     ) -> Result<Self::VideoResult, Box<dyn Error>> {
+        let planes = self.config.planes;
+        let weights = self.config.weights;
+        let compat = self.config.compat;
+        let summed = |index: usize| {
+            if planes.has_plane(index) {
+                calculate_summed_psnr(
+                    &metrics.iter().map(|m| m[index]).collect::<Vec<_>>(),
+                    compat,
+                )
+            } else {
+                0.0
+            }
+        };
+        let averaged = |index: usize| {
+            if planes.has_plane(index) {
+                metrics
+                    .iter()
+                    .map(|m| calculate_psnr(m[index], compat))
+                    .sum::<f64>()
+                    / metrics.len() as f64
+            } else {
+                0.0
+            }
+        };
         let psnr = PlanarMetrics {
-            y: calculate_summed_psnr(&metrics.iter().map(|m| m[0]).collect::<Vec<_>>()),
-            u: calculate_summed_psnr(&metrics.iter().map(|m| m[1]).collect::<Vec<_>>()),
-            v: calculate_summed_psnr(&metrics.iter().map(|m| m[2]).collect::<Vec<_>>()),
-            avg: calculate_summed_psnr(&metrics.iter().flatten().copied().collect::<Vec<_>>()),
+            y: summed(0),
+            u: summed(1),
+            v: summed(2),
+            avg: calculate_weighted_psnr(
+                &[0, 1, 2].map(|index| {
+                    metrics
+                        .iter()
+                        .map(|m| m[index])
+                        .fold(PsnrMetrics::default(), PsnrMetrics::add)
+                }),
+                planes,
+                weights,
+                compat,
+            ),
+            alpha: None,
+            dssim: None,
         };
         let apsnr = PlanarMetrics {
-            y: metrics.iter().map(|m| calculate_psnr(m[0])).sum::<f64>() / metrics.len() as f64,
-            u: metrics.iter().map(|m| calculate_psnr(m[1])).sum::<f64>() / metrics.len() as f64,
-            v: metrics.iter().map(|m| calculate_psnr(m[2])).sum::<f64>() / metrics.len() as f64,
+            y: averaged(0),
+            u: averaged(1),
+            v: averaged(2),
             avg: metrics
                 .iter()
-                .map(|m| calculate_summed_psnr(m))
+                .map(|m| calculate_weighted_psnr(m, planes, weights, compat))
                 .sum::<f64>()
                 / metrics.len() as f64,
+            alpha: None,
+            dssim: None,
         };
         Ok(PsnrResults { psnr, apsnr })
     }
 }
 
 #[derive(Debug, Clone, Copy, Default)]
-struct PsnrMetrics {
-    sq_err: f64,
-    n_pixels: usize,
-    sample_max: usize,
+pub(crate) struct PsnrMetrics {
+    /// The sum of the squared errors, which is exact at any resolution, bit
+    /// depth and number of frames.
+    pub(crate) sq_err: u128,
+    /// An error added to the squared errors that is not a whole number, such
+    /// as the blocking effect factor of PSNR-B.
+    pub(crate) penalty: f64,
+    pub(crate) n_pixels: usize,
+    pub(crate) sample_max: usize,
 }
 
-fn calculate_summed_psnr(metrics: &[PsnrMetrics]) -> f64 {
+fn calculate_frame_psnr_metrics<T: Pixel>(
+    frame1: &FrameRef<T>,
+    frame2: &FrameRef<T>,
+    bit_depth: usize,
+    prefilter: Option<Prefilter>,
+    planes: PlaneSelect,
+    sampling: Sampling,
+) -> [PsnrMetrics; 3] {
+    let mut y = Default::default();
+    let mut u = Default::default();
+    let mut v = Default::default();
+
+    let plane_metrics = |plane: usize| {
+        let (plane1, plane2) = (&frame1.planes[plane], &frame2.planes[plane]);
+        match prefilter {
+            Some(filter) => {
+                let filtered1 = filter_plane(plane1, filter);
+                let filtered2 = filter_plane(plane2, filter);
+                let width = plane1.width();
+                let height = plane1.height();
+                calculate_plane_psnr_metrics_sampled(
+                    &PlaneRef::new(&filtered1, width, width, height).unwrap(),
+                    &PlaneRef::new(&filtered2, width, width, height).unwrap(),
+                    bit_depth,
+                    sampling,
+                )
+            }
+            None => calculate_plane_psnr_metrics_sampled(plane1, plane2, bit_depth, sampling),
+        }
+    };
+
+    rayon::scope(|s| {
+        if planes.has_plane(0) {
+            s.spawn(|_| y = plane_metrics(0));
+        }
+        if planes.has_plane(1) {
+            s.spawn(|_| u = plane_metrics(1));
+        }
+        if planes.has_plane(2) {
+            s.spawn(|_| v = plane_metrics(2));
+        }
+    });
+
+    [y, u, v]
+}
+
+/// Applies `filter` to a plane, repeating the samples at the edges.
+fn filter_plane<T: Pixel>(plane: &PlaneRef<T>, filter: Prefilter) -> Vec<T> {
+    let (width, height) = (plane.width(), plane.height());
+    let kernel = filter.kernel();
+    let norm: u32 = kernel.iter().flatten().sum();
+    let mut out = Vec::with_capacity(width * height);
+    for y in 0..height {
+        let rows = [
+            plane.row(y.saturating_sub(1)),
+            plane.row(y),
+            plane.row((y + 1).min(height - 1)),
+        ];
+        for x in 0..width {
+            let cols = [x.saturating_sub(1), x, (x + 1).min(width - 1)];
+            let mut sum = 0;
+            for (row, weights) in rows.iter().zip(kernel.iter()) {
+                for (&col, &weight) in cols.iter().zip(weights.iter()) {
+                    sum += weight * u32::cast_from(row[col]);
+                }
+            }
+            out.push(T::cast_from((sum + norm / 2) / norm));
+        }
+    }
+    out
+}
+
+impl PsnrMetrics {
+    /// The sum of the squared errors and the penalty. Only this conversion to
+    /// floating point rounds the sum.
+    pub(crate) fn total_err(self) -> f64 {
+        self.sq_err as f64 + self.penalty
+    }
+
+    /// The mean squared error, or 0 if there are no samples.
+    pub(crate) fn mse(self) -> f64 {
+        if self.n_pixels > 0 {
+            self.total_err() / self.n_pixels as f64
+        } else {
+            0.0
+        }
+    }
+
+    /// The squared errors of two sets of samples together.
+    pub(crate) fn add(self, other: PsnrMetrics) -> PsnrMetrics {
+        PsnrMetrics {
+            sq_err: self.sq_err + other.sq_err,
+            penalty: self.penalty + other.penalty,
+            sample_max: self.sample_max.max(other.sample_max),
+            n_pixels: self.n_pixels + other.n_pixels,
+        }
+    }
+}
+
+pub(crate) fn calculate_summed_psnr(metrics: &[PsnrMetrics], compat: CompatMode) -> f64 {
     calculate_psnr(
         metrics
             .iter()
-            .fold(PsnrMetrics::default(), |acc, plane| PsnrMetrics {
-                sq_err: acc.sq_err + plane.sq_err,
-                sample_max: plane.sample_max,
-                n_pixels: acc.n_pixels + plane.n_pixels,
-            }),
+            .copied()
+            .fold(PsnrMetrics::default(), PsnrMetrics::add),
+        compat,
     )
 }
 
+/// Calculate the PSNR of the selected planes together. By default the squared
+/// errors of the planes are summed, which weights them by their number of
+/// samples. Otherwise the mean squared errors of the planes, relative to their
+/// peak, are averaged with the given weights.
+pub(crate) fn calculate_weighted_psnr(
+    metrics: &[PsnrMetrics; 3],
+    planes: PlaneSelect,
+    weights: PlaneWeights,
+    compat: CompatMode,
+) -> f64 {
+    let relative_mse = |plane: PsnrMetrics| plane.mse() / (plane.sample_max as f64).powi(2);
+    match weighted_mean(metrics, planes, weights, relative_mse) {
+        None => calculate_summed_psnr(metrics, compat),
+        Some(mse) if mse <= 0.0 => calculate_psnr(PsnrMetrics::default(), compat),
+        Some(mse) => -10.0 * mse.log10(),
+    }
+}
+
+/// The mean of `error` over the selected planes with the given weights, or
+/// `None` with the default weights or if no selected plane has a weight.
+fn weighted_mean(
+    metrics: &[PsnrMetrics; 3],
+    planes: PlaneSelect,
+    weights: PlaneWeights,
+    error: impl Fn(PsnrMetrics) -> f64,
+) -> Option<f64> {
+    let weights = weights.fixed()?;
+    let (sum, total_weight) = (0..3)
+        .filter(|&plane| planes.has_plane(plane) && metrics[plane].n_pixels > 0)
+        .fold((0.0, 0.0), |(sum, total), plane| {
+            (
+                sum + weights[plane] * error(metrics[plane]),
+                total + weights[plane],
+            )
+        });
+    (total_weight > 0.0).then(|| sum / total_weight)
+}
+
 /// Calculate the PSNR metrics for a `Plane` by comparing the original (uncompressed) to
 /// the compressed version.
-fn calculate_plane_psnr_metrics<T: Pixel>(
-    plane1: &Plane<T>,
-    plane2: &Plane<T>,
+pub(crate) fn calculate_plane_psnr_metrics<T: Pixel>(
+    plane1: &PlaneRef<T>,
+    plane2: &PlaneRef<T>,
     bit_depth: usize,
 ) -> PsnrMetrics {
-    let sq_err = calculate_plane_total_squared_error(plane1, plane2);
+    calculate_plane_psnr_metrics_sampled(plane1, plane2, bit_depth, Sampling::Full)
+}
+
+/// Calculate the PSNR metrics for a `Plane` on the samples selected by
+/// `sampling`.
+fn calculate_plane_psnr_metrics_sampled<T: Pixel>(
+    plane1: &PlaneRef<T>,
+    plane2: &PlaneRef<T>,
+    bit_depth: usize,
+    sampling: Sampling,
+) -> PsnrMetrics {
+    let (sq_err, n_pixels) = calculate_plane_total_squared_error(plane1, plane2, sampling);
     let max = (1 << bit_depth) - 1;
     PsnrMetrics {
         sq_err,
-        n_pixels: plane1.cfg.width * plane1.cfg.height,
+        penalty: 0.0,
+        n_pixels,
         sample_max: max,
     }
 }
 
-fn calculate_psnr(metrics: PsnrMetrics) -> f64 {
-    if metrics.sq_err <= f64::EPSILON {
-        return 100.0;
+fn calculate_psnr(metrics: PsnrMetrics, compat: CompatMode) -> f64 {
+    let total_err = metrics.total_err();
+    if total_err <= f64::EPSILON {
+        return match compat {
+            CompatMode::Native => 100.0,
+            CompatMode::Ffmpeg => f64::INFINITY,
+        };
     }
-    10.0 * ((metrics.sample_max.pow(2) as f64).log10() + (metrics.n_pixels as f64).log10()
-        - metrics.sq_err.log10())
+    10.0 * ((metrics.sample_max as f64).powi(2).log10() + (metrics.n_pixels as f64).log10()
+        - total_err.log10())
 }
 
 /// Calculate the squared error for a `Plane` by comparing the original (uncompressed)
-/// to the compressed version.
-fn calculate_plane_total_squared_error<T: Pixel>(plane1: &Plane<T>, plane2: &Plane<T>) -> f64 {
-    plane1
-        .data
-        .iter()
-        .zip(plane2.data.iter())
-        .map(|(a, b)| (i32::cast_from(*a) - i32::cast_from(*b)).unsigned_abs() as u64)
-        .map(|err| err * err)
-        .sum::<u64>() as f64
+/// to the compressed version, along with the number of samples compared.
+///
+/// The squared errors of each row are summed in a `u64`, and the rows in a
+/// `u128`. At 16 bits a squared error is below 2^32, so a row overflows
+/// only with 2^32 samples, which is checked; a plane of 16-bit samples would
+/// overflow a `u64` from about 4 billion samples, such as 64K by 64K.
+fn calculate_plane_total_squared_error<T: Pixel>(
+    plane1: &PlaneRef<T>,
+    plane2: &PlaneRef<T>,
+    sampling: Sampling,
+) -> (u128, usize) {
+    assert!(
+        u32::try_from(plane1.width()).is_ok(),
+        "Rows of 2^32 samples or more are not supported"
+    );
+    let mut n_pixels = 0;
+    let sq_err = plane1
+        .rows()
+        .zip(plane2.rows())
+        .enumerate()
+        .map(|(y, (row1, row2))| {
+            let (first, step) = sampling.columns(y);
+            let samples = row1.iter().zip(row2.iter()).skip(first).step_by(step);
+            n_pixels += samples.len();
+            samples
+                .map(|(a, b)| (i32::cast_from(*a) - i32::cast_from(*b)).unsigned_abs() as u64)
+                .map(|err| err * err)
+                .sum::<u64>()
+        })
+        .map(u128::from)
+        .sum::<u128>();
+    (sq_err, n_pixels)
 }
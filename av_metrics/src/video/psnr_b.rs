@@ -0,0 +1,223 @@
+//! PSNR including Blocking Effect Factor (PSNR-B).
+//!
+//! PSNR-B adds a penalty to the mean squared error for visible edges along
+//! the 8x8 block boundaries of the distorted image. Unlike plain PSNR, it
+//! rewards deblocking filters that smooth blocking artifacts.
+//!
+//! See "Quality Assessment of Deblocked Images" by Yim and Bovik (IEEE TIP, 2011).
+
+use crate::video::decode::{Decoder, FrameRef, PlaneRef};
+use crate::video::pixel::CastFromPrimitive;
+use crate::video::pixel::Pixel;
+use crate::video::psnr::{
+    calculate_plane_psnr_metrics, calculate_summed_psnr, planar_psnr, PsnrMetrics,
+};
+use crate::video::{
+    CompatMode, FrameContext, PlanarMetrics, PlaneSelect, PlaneWeights, Score, VideoMetric,
+    VideoOptions,
+};
+use std::error::Error;
+use v_frame::frame::Frame;
+use v_frame::prelude::ChromaSampling;
+
+/// The size of the blocks whose boundaries are checked for blocking artifacts.
+const BLOCK_SIZE: usize = 8;
+
+/// Calculates the PSNR-B for two videos. Higher is better.
+///
+/// PSNR-B is capped at 100 in order to avoid skewed statistics
+/// from e.g. all black frames, which would
+/// otherwise show a PSNR-B of infinity.
+#[inline]
+pub fn calculate_video_psnr_b<D: Decoder, F: Fn(usize) + Send>(
+    decoder1: &mut D,
+    decoder2: &mut D,
+    frame_limit: Option<usize>,
+    progress_callback: F,
+) -> Result<PlanarMetrics, Box<dyn Error>> {
+    calculate_video_psnr_b_with(
+        decoder1,
+        decoder2,
+        VideoOptions::limited(frame_limit, progress_callback),
+    )
+}
+
+/// Options for computing PSNR-B over whole videos, which has no options of
+/// its own.
+pub type PsnrBOptions<F = fn(usize)> = VideoOptions<F>;
+
+/// Calculates the PSNR-B for two videos with the given options. Higher is
+/// better.
+///
+/// See [`PsnrBOptions`] for the available options.
+#[inline]
+pub fn calculate_video_psnr_b_with<D: Decoder, F: Fn(usize) + Send>(
+    decoder1: &mut D,
+    decoder2: &mut D,
+    options: PsnrBOptions<F>,
+) -> Result<PlanarMetrics, Box<dyn Error>> {
+    options.run(|limits, progress_callback| {
+        PsnrB.process_video(decoder1, decoder2, limits, progress_callback)
+    })
+}
+
+/// Calculates the PSNR-B for pairs of frames from any source, such as frames
+/// received over a network or generated in memory. Higher is better.
+///
+/// The frames are scored with the same multithreaded pipeline as
+/// [`calculate_video_psnr_b`].
+#[inline]
+pub fn calculate_video_psnr_b_from_frames<T: Pixel, I, F: Fn(usize) + Send>(
+    frames: I,
+    bit_depth: usize,
+    chroma_sampling: ChromaSampling,
+    progress_callback: F,
+) -> Result<PlanarMetrics, Box<dyn Error>>
+where
+    I: IntoIterator<Item = (Frame<T>, Frame<T>)>,
+    I::IntoIter: Send,
+{
+    PsnrB.process_frames(frames, bit_depth, chroma_sampling, progress_callback)
+}
+
+/// Calculates the PSNR-B for two video frames. Higher is better.
+///
+/// `frame2` is the distorted frame, in which blocking artifacts are measured.
+///
+/// PSNR-B is capped at 100 in order to avoid skewed statistics
+/// from e.g. all black frames, which would
+/// otherwise show a PSNR-B of infinity.
+#[inline]
+pub fn calculate_frame_psnr_b<T: Pixel>(
+    frame1: &Frame<T>,
+    frame2: &Frame<T>,
+    bit_depth: usize,
+    chroma_sampling: ChromaSampling,
+) -> Result<PlanarMetrics, Box<dyn Error>> {
+    let metrics = PsnrB.process_frame(frame1, frame2, bit_depth, chroma_sampling)?;
+    Ok(planar_psnr(
+        &metrics,
+        PlaneSelect::ALL,
+        PlaneWeights::Default,
+        CompatMode::Native,
+    ))
+}
+
+struct PsnrB;
+
+impl VideoMetric for PsnrB {
+    type FrameResult = [PsnrMetrics; 3];
+    type VideoResult = PlanarMetrics;
+
+    fn process_frame<T: Pixel>(
+        &self,
+        frame1: &Frame<T>,
+        frame2: &Frame<T>,
+        bit_depth: usize,
+        chroma_sampling: ChromaSampling,
+    ) -> Result<Self::FrameResult, Box<dyn Error>> {
+        FrameContext::new(bit_depth, chroma_sampling)?.validate(frame1, frame2)?;
+
+        let frame1 = FrameRef::from(frame1);
+        let frame2 = FrameRef::from(frame2);
+        let mut y = Default::default();
+        let mut u = Default::default();
+        let mut v = Default::default();
+
+        rayon::scope(|s| {
+            s.spawn(|_| {
+                y = calculate_plane_psnr_b_metrics(&frame1.planes[0], &frame2.planes[0], bit_depth)
+            });
+            s.spawn(|_| {
+                u = calculate_plane_psnr_b_metrics(&frame1.planes[1], &frame2.planes[1], bit_depth)
+            });
+            s.spawn(|_| {
+                v = calculate_plane_psnr_b_metrics(&frame1.planes[2], &frame2.planes[2], bit_depth)
+            });
+        });
+
+        Ok([y, u, v])
+    }
+
+    fn frame_score(&self, result: &Self::FrameResult) -> Option<Score> {
+        self.aggregate_frame_results(std::slice::from_ref(result))
+            .ok()
+            .map(Score::Planar)
+    }
+
+    fn aggregate_frame_results(
+        &self,
+        metrics: &[Self::FrameResult],
+    ) -> Result<Self::VideoResult, Box<dyn Error>> {
+        let summed =
+            |metrics: Vec<PsnrMetrics>| calculate_summed_psnr(&metrics, CompatMode::Native);
+        Ok(PlanarMetrics {
+            y: summed(metrics.iter().map(|m| m[0]).collect()),
+            u: summed(metrics.iter().map(|m| m[1]).collect()),
+            v: summed(metrics.iter().map(|m| m[2]).collect()),
+            avg: summed(metrics.iter().flatten().copied().collect()),
+            alpha: None,
+            dssim: None,
+        })
+    }
+}
+
+/// Calculate the PSNR metrics for a plane, with the blocking effect factor
+/// of the compressed version added to the mean squared error.
+fn calculate_plane_psnr_b_metrics<T: Pixel>(
+    plane1: &PlaneRef<T>,
+    plane2: &PlaneRef<T>,
+    bit_depth: usize,
+) -> PsnrMetrics {
+    let metrics = calculate_plane_psnr_metrics(plane1, plane2, bit_depth);
+    PsnrMetrics {
+        penalty: blocking_effect_factor(plane2) * metrics.n_pixels as f64,
+        ..metrics
+    }
+}
+
+/// Calculate the blocking effect factor of a plane: how much larger the squared
+/// differences between neighboring pixels are across block boundaries than
+/// inside the blocks, scaled by the relative block size.
+fn blocking_effect_factor<T: Pixel>(plane: &PlaneRef<T>) -> f64 {
+    let (width, height) = (plane.width(), plane.height());
+    if width.min(height) <= BLOCK_SIZE {
+        return 0.0;
+    }
+
+    let sq_diff = |a: T, b: T| {
+        let diff = i64::from(i32::cast_from(a) - i32::cast_from(b));
+        (diff * diff) as u64
+    };
+    let mut boundary = (0u64, 0u64);
+    let mut interior = (0u64, 0u64);
+    let mut add = |at_boundary: bool, err: u64| {
+        let sum = if at_boundary {
+            &mut boundary
+        } else {
+            &mut interior
+        };
+        sum.0 += err;
+        sum.1 += 1;
+    };
+    for y in 0..height {
+        let row = plane.row(y);
+        for x in 0..width - 1 {
+            add((x + 1) % BLOCK_SIZE == 0, sq_diff(row[x], row[x + 1]));
+        }
+        if y + 1 < height {
+            let next = plane.row(y + 1);
+            for x in 0..width {
+                add((y + 1) % BLOCK_SIZE == 0, sq_diff(row[x], next[x]));
+            }
+        }
+    }
+
+    let boundary_mean = boundary.0 as f64 / boundary.1 as f64;
+    let interior_mean = interior.0 as f64 / interior.1 as f64;
+    if boundary_mean <= interior_mean {
+        return 0.0;
+    }
+    let eta = (BLOCK_SIZE as f64).log2() / (width.min(height) as f64).log2();
+    eta * (boundary_mean - interior_mean)
+}
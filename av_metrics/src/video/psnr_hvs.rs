@@ -10,23 +10,43 @@ use crate::video::decode::Decoder;
 use crate::video::pixel::CastFromPrimitive;
 use crate::video::pixel::Pixel;
 use crate::video::ChromaWeight;
-use crate::video::{PlanarMetrics, VideoMetric};
-use crate::MetricsError;
+use crate::video::{FrameContext, PlanarMetrics, Score, VideoMetric, VideoOptions};
 use std::error::Error;
-use std::mem::size_of;
 use v_frame::frame::Frame;
 use v_frame::plane::Plane;
 use v_frame::prelude::ChromaSampling;
 
-use super::FrameCompare;
-
 /// Calculates the PSNR-HVS score between two videos. Higher is better.
+///
+/// Chroma planes are weighted by a contrast sensitivity table matching
+/// the chroma subsampling of the input.
 #[inline]
 pub fn calculate_video_psnr_hvs<D: Decoder, F: Fn(usize) + Send>(
     decoder1: &mut D,
     decoder2: &mut D,
     frame_limit: Option<usize>,
     progress_callback: F,
+) -> Result<PlanarMetrics, Box<dyn Error>> {
+    calculate_video_psnr_hvs_with(
+        decoder1,
+        decoder2,
+        VideoOptions::limited(frame_limit, progress_callback),
+    )
+}
+
+/// Options for computing PSNR-HVS over whole videos, which has no options of
+/// its own.
+pub type PsnrHvsOptions<F = fn(usize)> = VideoOptions<F>;
+
+/// Calculates the PSNR-HVS score between two videos with the given options.
+/// Higher is better.
+///
+/// See [`PsnrHvsOptions`] for the available options.
+#[inline]
+pub fn calculate_video_psnr_hvs_with<D: Decoder, F: Fn(usize) + Send>(
+    decoder1: &mut D,
+    decoder2: &mut D,
+    options: PsnrHvsOptions<F>,
 ) -> Result<PlanarMetrics, Box<dyn Error>> {
     let cweight = Some(
         decoder1
@@ -34,7 +54,36 @@ pub fn calculate_video_psnr_hvs<D: Decoder, F: Fn(usize) + Send>(
             .chroma_sampling
             .get_chroma_weight(),
     );
-    PsnrHvs { cweight }.process_video(decoder1, decoder2, frame_limit, progress_callback)
+    options.run(|limits, progress_callback| {
+        PsnrHvs {
+            cweight,
+            use_simd: true,
+        }
+        .process_video(decoder1, decoder2, limits, progress_callback)
+    })
+}
+
+/// Calculates the PSNR-HVS for pairs of frames from any source, such as frames
+/// received over a network or generated in memory. Higher is better.
+///
+/// The frames are scored with the same multithreaded pipeline as
+/// [`calculate_video_psnr_hvs`].
+#[inline]
+pub fn calculate_video_psnr_hvs_from_frames<T: Pixel, I, F: Fn(usize) + Send>(
+    frames: I,
+    bit_depth: usize,
+    chroma_sampling: ChromaSampling,
+    progress_callback: F,
+) -> Result<PlanarMetrics, Box<dyn Error>>
+where
+    I: IntoIterator<Item = (Frame<T>, Frame<T>)>,
+    I::IntoIter: Send,
+{
+    PsnrHvs {
+        cweight: Some(chroma_sampling.get_chroma_weight()),
+        use_simd: true,
+    }
+    .process_frames(frames, bit_depth, chroma_sampling, progress_callback)
 }
 
 /// Calculates the PSNR-HVS score between two video frames. Higher is better.
@@ -45,7 +94,41 @@ pub fn calculate_frame_psnr_hvs<T: Pixel>(
     bit_depth: usize,
     chroma_sampling: ChromaSampling,
 ) -> Result<PlanarMetrics, Box<dyn Error>> {
-    let processor = PsnrHvs::default();
+    frame_psnr_hvs(
+        PsnrHvs::default(),
+        frame1,
+        frame2,
+        bit_depth,
+        chroma_sampling,
+    )
+}
+
+/// Calculates the PSNR-HVS score between two video frames. Higher is better.
+///
+/// This version disables SIMD. It is intended to only be used
+/// by tests and benchmarks.
+#[inline]
+#[doc(hidden)]
+pub fn calculate_frame_psnr_hvs_nosimd<T: Pixel>(
+    frame1: &Frame<T>,
+    frame2: &Frame<T>,
+    bit_depth: usize,
+    chroma_sampling: ChromaSampling,
+) -> Result<PlanarMetrics, Box<dyn Error>> {
+    let processor = PsnrHvs {
+        cweight: None,
+        use_simd: false,
+    };
+    frame_psnr_hvs(processor, frame1, frame2, bit_depth, chroma_sampling)
+}
+
+fn frame_psnr_hvs<T: Pixel>(
+    processor: PsnrHvs,
+    frame1: &Frame<T>,
+    frame2: &Frame<T>,
+    bit_depth: usize,
+    chroma_sampling: ChromaSampling,
+) -> Result<PlanarMetrics, Box<dyn Error>> {
     let result = processor.process_frame(frame1, frame2, bit_depth, chroma_sampling)?;
     let cweight = chroma_sampling.get_chroma_weight();
     Ok(PlanarMetrics {
@@ -56,12 +139,23 @@ pub fn calculate_frame_psnr_hvs<T: Pixel>(
             result.y + cweight * (result.u + result.v),
             1.0 + 2.0 * cweight,
         ),
+        alpha: None,
+        dssim: None,
     })
 }
 
-#[derive(Default)]
 struct PsnrHvs {
     pub cweight: Option<f64>,
+    use_simd: bool,
+}
+
+impl Default for PsnrHvs {
+    fn default() -> Self {
+        PsnrHvs {
+            cweight: None,
+            use_simd: true,
+        }
+    }
 }
 
 impl VideoMetric for PsnrHvs {
@@ -75,15 +169,9 @@ impl VideoMetric for PsnrHvs {
         frame1: &Frame<T>,
         frame2: &Frame<T>,
         bit_depth: usize,
-        _chroma_sampling: ChromaSampling,
+        chroma_sampling: ChromaSampling,
     ) -> Result<Self::FrameResult, Box<dyn Error>> {
-        if (size_of::<T>() == 1 && bit_depth > 8) || (size_of::<T>() == 2 && bit_depth <= 8) {
-            return Err(Box::new(MetricsError::InputMismatch {
-                reason: "Bit depths does not match pixel width",
-            }));
-        }
-
-        frame1.can_compare(frame2)?;
+        FrameContext::new(bit_depth, chroma_sampling)?.validate(frame1, frame2)?;
 
         let mut y = 0.0;
         let mut u = 0.0;
@@ -91,13 +179,34 @@ impl VideoMetric for PsnrHvs {
 
         rayon::scope(|s| {
             s.spawn(|_| {
-                y = calculate_plane_psnr_hvs(&frame1.planes[0], &frame2.planes[0], 0, bit_depth)
+                y = calculate_plane_psnr_hvs(
+                    &frame1.planes[0],
+                    &frame2.planes[0],
+                    0,
+                    bit_depth,
+                    chroma_sampling,
+                    self.use_simd,
+                )
             });
             s.spawn(|_| {
-                u = calculate_plane_psnr_hvs(&frame1.planes[1], &frame2.planes[1], 1, bit_depth)
+                u = calculate_plane_psnr_hvs(
+                    &frame1.planes[1],
+                    &frame2.planes[1],
+                    1,
+                    bit_depth,
+                    chroma_sampling,
+                    self.use_simd,
+                )
             });
             s.spawn(|_| {
-                v = calculate_plane_psnr_hvs(&frame1.planes[2], &frame2.planes[2], 2, bit_depth)
+                v = calculate_plane_psnr_hvs(
+                    &frame1.planes[2],
+                    &frame2.planes[2],
+                    2,
+                    bit_depth,
+                    chroma_sampling,
+                    self.use_simd,
+                )
             });
         });
 
@@ -107,9 +216,17 @@ impl VideoMetric for PsnrHvs {
             v,
             // field not used here
             avg: 0.,
+            alpha: None,
+            dssim: None,
         })
     }
 
+    fn frame_score(&self, result: &Self::FrameResult) -> Option<Score> {
+        self.aggregate_frame_results(std::slice::from_ref(result))
+            .ok()
+            .map(Score::Planar)
+    }
+
     fn aggregate_frame_results(
         &self,
         metrics: &[Self::FrameResult],
@@ -126,6 +243,8 @@ impl VideoMetric for PsnrHvs {
                 sum_y + cweight * (sum_u + sum_v),
                 (1. + 2. * cweight) * 1. / metrics.len() as f64,
             ),
+            alpha: None,
+            dssim: None,
         })
     }
 }
@@ -169,19 +288,59 @@ const CSF_CR420: [[f64; 8]; 8] = [
     [0.593906509971, 0.802254508198, 0.706020324706, 0.587716619023, 0.478717061273, 0.393021669543, 0.330555063063, 0.285345396658]
 ];
 
+// The chroma tables above were derived for planes decimated by two in both
+// directions, whose samples are twice as far apart as those of a full
+// resolution plane, so a given DCT coefficient covers half the spatial
+// frequency it would in a full resolution plane. For chroma planes that are
+// not decimated along an axis, coefficient `k` on that axis therefore
+// corresponds to coefficient `2k` of the 4:2:0 table.
+//
+// Entries past the end of the table are extrapolated geometrically, using
+// the ratio between its last two entries. Past its peak, the contrast
+// sensitivity falls off about exponentially with the spatial frequency, as
+// in the CSF model of J. L. Mannos and D. J. Sakrison, "The effects of a
+// visual fidelity criterion on the encoding of images", IEEE Transactions on
+// Information Theory, vol. 20, no. 4, pp. 525-536, 1974.
+fn chroma_csf(base: &[[f64; 8]; 8], chroma_sampling: ChromaSampling) -> [[f64; 8]; 8] {
+    let (row_scale, col_scale) = match chroma_sampling {
+        ChromaSampling::Cs420 | ChromaSampling::Cs400 => return *base,
+        ChromaSampling::Cs422 => (2, 1),
+        ChromaSampling::Cs444 => (2, 2),
+    };
+
+    let mut csf = [[0.0; 8]; 8];
+    for i in 0..8 {
+        for j in 0..8 {
+            let (row, col) = (i * row_scale, j * col_scale);
+            let (r, c) = (row.min(7), col.min(7));
+            let mut value = base[r][c];
+            if row > 7 {
+                value *= (base[7][c] / base[6][c]).powi((row - 7) as i32);
+            }
+            if col > 7 {
+                value *= (base[r][7] / base[r][6]).powi((col - 7) as i32);
+            }
+            csf[i][j] = value;
+        }
+    }
+    csf
+}
+
 fn calculate_plane_psnr_hvs<T: Pixel>(
     plane1: &Plane<T>,
     plane2: &Plane<T>,
     plane_idx: usize,
     bit_depth: usize,
+    chroma_sampling: ChromaSampling,
+    simd: bool,
 ) -> f64 {
     const STEP: usize = 7;
     let mut result = 0.0;
     let mut pixels = 0usize;
     let csf = match plane_idx {
-        0 => &CSF_Y,
-        1 => &CSF_CB420,
-        2 => &CSF_CR420,
+        0 => CSF_Y,
+        1 => chroma_csf(&CSF_CB420, chroma_sampling),
+        2 => chroma_csf(&CSF_CR420, chroma_sampling),
         _ => unreachable!(),
     };
 
@@ -209,13 +368,12 @@ fn calculate_plane_psnr_hvs<T: Pixel>(
         }
     }
 
+    let block_error = get_block_error_fn(bit_depth, simd);
     let height = plane1.cfg.height;
     let width = plane1.cfg.width;
     let stride = plane1.cfg.stride;
-    let mut p1 = [0i16; 8 * 8];
-    let mut p2 = [0i16; 8 * 8];
-    let mut dct_p1 = [0i32; 8 * 8];
-    let mut dct_p2 = [0i32; 8 * 8];
+    let mut p1 = [0i32; 8 * 8];
+    let mut p2 = [0i32; 8 * 8];
     assert!(plane1.data.len() >= stride * height);
     assert!(plane2.data.len() >= stride * height);
     for y in (0..(height - STEP)).step_by(STEP) {
@@ -228,13 +386,11 @@ fn calculate_plane_psnr_hvs<T: Pixel>(
             let mut p2_gmean = 0.0;
             let mut p1_gvar = 0.0;
             let mut p2_gvar = 0.0;
-            let mut p1_mask = 0.0;
-            let mut p2_mask = 0.0;
 
             for i in 0..8 {
                 for j in 0..8 {
-                    p1[i * 8 + j] = i16::cast_from(plane1.data[(y + i) * stride + x + j]);
-                    p2[i * 8 + j] = i16::cast_from(plane2.data[(y + i) * stride + x + j]);
+                    p1[i * 8 + j] = i32::cast_from(plane1.data[(y + i) * stride + x + j]);
+                    p2[i * 8 + j] = i32::cast_from(plane2.data[(y + i) * stride + x + j]);
 
                     let sub = ((i & 12) >> 2) + ((j & 12) >> 1);
                     p1_gmean += p1[i * 8 + j] as f64;
@@ -276,36 +432,10 @@ fn calculate_plane_psnr_hvs<T: Pixel>(
                 p2_gvar = p2_vars.iter().sum::<f64>() / p2_gvar;
             }
 
-            p1.iter().copied().enumerate().for_each(|(i, v)| {
-                dct_p1[i] = v as i32;
-            });
-            p2.iter().copied().enumerate().for_each(|(i, v)| {
-                dct_p2[i] = v as i32;
-            });
-            od_bin_fdct8x8(&mut dct_p1);
-            od_bin_fdct8x8(&mut dct_p2);
-            for i in 0..8 {
-                for j in (i == 0) as usize..8 {
-                    p1_mask += dct_p1[i * 8 + j].pow(2) as f64 * mask[i][j];
-                    p2_mask += dct_p2[i * 8 + j].pow(2) as f64 * mask[i][j];
-                }
-            }
-            p1_mask = (p1_mask * p1_gvar).sqrt() / 32.0;
-            p2_mask = (p2_mask * p2_gvar).sqrt() / 32.0;
-            if p2_mask > p1_mask {
-                p1_mask = p2_mask;
-            }
-            for i in 0..8 {
-                for j in 0..8 {
-                    let mut err = (dct_p1[i * 8 + j] - dct_p2[i * 8 + j]).abs() as f64;
-                    if i != 0 || j != 0 {
-                        let err_mask = p1_mask / mask[i][j];
-                        err = if err < err_mask { 0.0 } else { err - err_mask };
-                    }
-                    result += (err * csf[i][j]).powi(2);
-                    pixels += 1;
-                }
-            }
+            // SAFETY: `get_block_error_fn` only returns SIMD versions
+            // when the CPU supports them.
+            result += unsafe { block_error(&p1, &p2, p1_gvar, p2_gvar, &mask, &csf) };
+            pixels += 64;
         }
     }
 
@@ -315,14 +445,81 @@ fn calculate_plane_psnr_hvs<T: Pixel>(
     result
 }
 
+/// Computes the masked and weighted squared error of one 8x8 block, given the
+/// ratio of the sum of the variances of its four 4x4 sub-blocks to the variance
+/// of the whole block for each input.
+type BlockErrorFn = unsafe fn(
+    p1: &[i32; 64],
+    p2: &[i32; 64],
+    p1_gvar: f64,
+    p2_gvar: f64,
+    mask: &[[f64; 8]; 8],
+    csf: &[[f64; 8]; 8],
+) -> f64;
+
+fn get_block_error_fn(bit_depth: usize, simd: bool) -> BlockErrorFn {
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    {
+        // The SIMD DCT works on 32 bit lanes, which overflow for inputs
+        // deeper than 12 bits.
+        if simd && bit_depth <= 12 && is_x86_feature_detected!("avx2") {
+            return avx2::block_error;
+        }
+    }
+    let _ = (bit_depth, simd);
+    block_error_scalar
+}
+
+unsafe fn block_error_scalar(
+    p1: &[i32; 64],
+    p2: &[i32; 64],
+    p1_gvar: f64,
+    p2_gvar: f64,
+    mask: &[[f64; 8]; 8],
+    csf: &[[f64; 8]; 8],
+) -> f64 {
+    // The intermediate products of the DCT overflow 32 bits for inputs
+    // deeper than 12 bits.
+    let mut dct_p1 = p1.map(i64::from);
+    let mut dct_p2 = p2.map(i64::from);
+    let mut p1_mask = 0.0;
+    let mut p2_mask = 0.0;
+    let mut result = 0.0;
+
+    od_bin_fdct8x8(&mut dct_p1);
+    od_bin_fdct8x8(&mut dct_p2);
+    for i in 0..8 {
+        for j in (i == 0) as usize..8 {
+            p1_mask += dct_p1[i * 8 + j].pow(2) as f64 * mask[i][j];
+            p2_mask += dct_p2[i * 8 + j].pow(2) as f64 * mask[i][j];
+        }
+    }
+    p1_mask = (p1_mask * p1_gvar).sqrt() / 32.0;
+    p2_mask = (p2_mask * p2_gvar).sqrt() / 32.0;
+    if p2_mask > p1_mask {
+        p1_mask = p2_mask;
+    }
+    for i in 0..8 {
+        for j in 0..8 {
+            let mut err = (dct_p1[i * 8 + j] - dct_p2[i * 8 + j]).abs() as f64;
+            if i != 0 || j != 0 {
+                let err_mask = p1_mask / mask[i][j];
+                err = if err < err_mask { 0.0 } else { err - err_mask };
+            }
+            result += (err * csf[i][j]).powi(2);
+        }
+    }
+    result
+}
+
 fn log10_convert(score: f64, weight: f64) -> f64 {
-    10.0 * (-1.0 * (weight * score).log10())
+    -10.0 * (weight * score).log10()
 }
 
 const DCT_STRIDE: usize = 8;
 
 // Based on daala's version. It is different from the 8x8 DCT we use during encoding.
-fn od_bin_fdct8x8(data: &mut [i32]) {
+fn od_bin_fdct8x8(data: &mut [i64]) {
     assert!(data.len() >= 64);
     let mut z = [0; 64];
     for i in 0..8 {
@@ -334,7 +531,7 @@ fn od_bin_fdct8x8(data: &mut [i32]) {
 }
 
 #[allow(clippy::identity_op)]
-fn od_bin_fdct8(y: &mut [i32], x: &[i32]) {
+fn od_bin_fdct8(y: &mut [i64], x: &[i64]) {
     assert!(y.len() >= 8);
     assert!(x.len() > 7 * DCT_STRIDE);
     let mut t = [0; 8];
@@ -401,9 +598,208 @@ fn od_bin_fdct8(y: &mut [i32], x: &[i32]) {
 /// This will not work for `b == 0`, however currently this is only used for
 /// `b == 1` anyway.
 #[inline(always)]
-fn od_dct_rshift(a: i32, b: u32) -> i32 {
+fn od_dct_rshift(a: i64, b: u32) -> i64 {
     debug_assert!(b > 0);
-    debug_assert!(b <= 32);
+    debug_assert!(b <= 64);
+
+    ((a as u64 >> (64 - b)) as i64 + a) >> b
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+mod avx2 {
+    #[cfg(target_arch = "x86")]
+    use std::arch::x86::*;
+    #[cfg(target_arch = "x86_64")]
+    use std::arch::x86_64::*;
+
+    /// AVX2 version of `block_error_scalar`, for inputs up to 12 bits.
+    ///
+    /// The DCT is computed on eight columns or rows at a time in 32 bit lanes.
+    /// It gives exactly the same coefficients as the scalar version, while the
+    /// floating point sums are accumulated in a different order.
+    #[target_feature(enable = "avx2")]
+    pub(super) unsafe fn block_error(
+        p1: &[i32; 64],
+        p2: &[i32; 64],
+        p1_gvar: f64,
+        p2_gvar: f64,
+        mask: &[[f64; 8]; 8],
+        csf: &[[f64; 8]; 8],
+    ) -> f64 {
+        let dct_p1 = fdct8x8(p1);
+        let dct_p2 = fdct8x8(p2);
+        let zero = _mm256_setzero_pd();
+
+        let mut p1_mask = zero;
+        let mut p2_mask = zero;
+        for i in 0..8 {
+            let (lo1, hi1) = to_f64(dct_p1[i]);
+            let (lo2, hi2) = to_f64(dct_p2[i]);
+            let mut mask_lo = _mm256_loadu_pd(mask[i].as_ptr());
+            let mask_hi = _mm256_loadu_pd(mask[i][4..].as_ptr());
+            if i == 0 {
+                // The DC coefficient does not contribute to masking.
+                mask_lo = _mm256_blend_pd::<0b0001>(mask_lo, zero);
+            }
+            p1_mask = _mm256_add_pd(p1_mask, _mm256_mul_pd(_mm256_mul_pd(lo1, lo1), mask_lo));
+            p1_mask = _mm256_add_pd(p1_mask, _mm256_mul_pd(_mm256_mul_pd(hi1, hi1), mask_hi));
+            p2_mask = _mm256_add_pd(p2_mask, _mm256_mul_pd(_mm256_mul_pd(lo2, lo2), mask_lo));
+            p2_mask = _mm256_add_pd(p2_mask, _mm256_mul_pd(_mm256_mul_pd(hi2, hi2), mask_hi));
+        }
+        let p1_mask = (hsum(p1_mask) * p1_gvar).sqrt() / 32.0;
+        let p2_mask = (hsum(p2_mask) * p2_gvar).sqrt() / 32.0;
+        let block_mask = _mm256_set1_pd(p1_mask.max(p2_mask));
+
+        let mut result = zero;
+        for i in 0..8 {
+            let (err_lo, err_hi) = to_f64(_mm256_abs_epi32(_mm256_sub_epi32(dct_p1[i], dct_p2[i])));
+            let mut err_mask_lo = _mm256_div_pd(block_mask, _mm256_loadu_pd(mask[i].as_ptr()));
+            let err_mask_hi = _mm256_div_pd(block_mask, _mm256_loadu_pd(mask[i][4..].as_ptr()));
+            if i == 0 {
+                // The DC coefficient is not masked.
+                err_mask_lo = _mm256_blend_pd::<0b0001>(err_mask_lo, zero);
+            }
+            let err_lo = _mm256_max_pd(_mm256_sub_pd(err_lo, err_mask_lo), zero);
+            let err_hi = _mm256_max_pd(_mm256_sub_pd(err_hi, err_mask_hi), zero);
+            let err_lo = _mm256_mul_pd(err_lo, _mm256_loadu_pd(csf[i].as_ptr()));
+            let err_hi = _mm256_mul_pd(err_hi, _mm256_loadu_pd(csf[i][4..].as_ptr()));
+            result = _mm256_add_pd(result, _mm256_mul_pd(err_lo, err_lo));
+            result = _mm256_add_pd(result, _mm256_mul_pd(err_hi, err_hi));
+        }
+        hsum(result)
+    }
+
+    /// Converts eight 32 bit integers to two vectors of four doubles.
+    #[target_feature(enable = "avx2")]
+    #[inline]
+    unsafe fn to_f64(v: __m256i) -> (__m256d, __m256d) {
+        (
+            _mm256_cvtepi32_pd(_mm256_castsi256_si128(v)),
+            _mm256_cvtepi32_pd(_mm256_extracti128_si256::<1>(v)),
+        )
+    }
+
+    #[target_feature(enable = "avx2")]
+    #[inline]
+    unsafe fn hsum(v: __m256d) -> f64 {
+        let sum = _mm_add_pd(_mm256_castpd256_pd128(v), _mm256_extractf128_pd::<1>(v));
+        _mm_cvtsd_f64(_mm_add_sd(sum, _mm_unpackhi_pd(sum, sum)))
+    }
 
-    ((a as u32 >> (32 - b)) as i32 + a) >> b
+    /// Returns the rows of the 2D DCT of an 8x8 block.
+    #[target_feature(enable = "avx2")]
+    #[inline]
+    unsafe fn fdct8x8(block: &[i32; 64]) -> [__m256i; 8] {
+        let mut rows = [_mm256_setzero_si256(); 8];
+        for (i, row) in rows.iter_mut().enumerate() {
+            *row = _mm256_loadu_si256(block[i * 8..].as_ptr() as *const __m256i);
+        }
+        // Each lane holds one column, so this transforms all the columns at once.
+        let cols = fdct8(rows);
+        let rows = fdct8(transpose(cols));
+        transpose(rows)
+    }
+
+    /// `od_dct_rshift(a, 1)`
+    #[target_feature(enable = "avx2")]
+    #[inline]
+    unsafe fn rshift1(a: __m256i) -> __m256i {
+        _mm256_srai_epi32::<1>(_mm256_add_epi32(a, _mm256_srli_epi32::<31>(a)))
+    }
+
+    /// `(a * mul + (1 << (SHIFT - 1))) >> SHIFT`
+    #[target_feature(enable = "avx2")]
+    #[inline]
+    unsafe fn mul_shift<const SHIFT: i32>(a: __m256i, mul: i32) -> __m256i {
+        _mm256_srai_epi32::<SHIFT>(_mm256_add_epi32(
+            _mm256_mullo_epi32(a, _mm256_set1_epi32(mul)),
+            _mm256_set1_epi32(1 << (SHIFT - 1)),
+        ))
+    }
+
+    /// `od_bin_fdct8` applied independently to each lane.
+    #[target_feature(enable = "avx2")]
+    #[inline]
+    unsafe fn fdct8(x: [__m256i; 8]) -> [__m256i; 8] {
+        // Initial permutation
+        let mut t0 = x[0];
+        let mut t4 = x[1];
+        let mut t2 = x[2];
+        let mut t6 = x[3];
+        let mut t7 = x[4];
+        let mut t3 = x[5];
+        let mut t5 = x[6];
+        let mut t1 = x[7];
+        // +1/-1 butterflies
+        t1 = _mm256_sub_epi32(t0, t1);
+        let th1 = rshift1(t1);
+        t0 = _mm256_sub_epi32(t0, th1);
+        t4 = _mm256_add_epi32(t4, t5);
+        let th4 = rshift1(t4);
+        t5 = _mm256_sub_epi32(t5, th4);
+        t3 = _mm256_sub_epi32(t2, t3);
+        t2 = _mm256_sub_epi32(t2, rshift1(t3));
+        t6 = _mm256_add_epi32(t6, t7);
+        let th6 = rshift1(t6);
+        t7 = _mm256_sub_epi32(th6, t7);
+        // + Embedded 4-point type-II DCT
+        t0 = _mm256_add_epi32(t0, th6);
+        t6 = _mm256_sub_epi32(t0, t6);
+        t2 = _mm256_sub_epi32(th4, t2);
+        t4 = _mm256_sub_epi32(t2, t4);
+        // |-+ Embedded 2-point type-II DCT
+        t0 = _mm256_sub_epi32(t0, mul_shift::<15>(t4, 13573));
+        t4 = _mm256_add_epi32(t4, mul_shift::<14>(t0, 11585));
+        t0 = _mm256_sub_epi32(t0, mul_shift::<15>(t4, 13573));
+        // |-+ Embedded 2-point type-IV DST
+        t6 = _mm256_sub_epi32(t6, mul_shift::<15>(t2, 21895));
+        t2 = _mm256_add_epi32(t2, mul_shift::<14>(t6, 15137));
+        t6 = _mm256_sub_epi32(t6, mul_shift::<15>(t2, 21895));
+        // + Embedded 4-point type-IV DST
+        t3 = _mm256_add_epi32(t3, mul_shift::<15>(t5, 19195));
+        t5 = _mm256_add_epi32(t5, mul_shift::<14>(t3, 11585));
+        t3 = _mm256_sub_epi32(t3, mul_shift::<13>(t5, 7489));
+        t7 = _mm256_sub_epi32(rshift1(t5), t7);
+        t5 = _mm256_sub_epi32(t5, t7);
+        t3 = _mm256_sub_epi32(th1, t3);
+        t1 = _mm256_sub_epi32(t1, t3);
+        t7 = _mm256_add_epi32(t7, mul_shift::<15>(t1, 3227));
+        t1 = _mm256_sub_epi32(t1, mul_shift::<15>(t7, 6393));
+        t7 = _mm256_add_epi32(t7, mul_shift::<15>(t1, 3227));
+        t5 = _mm256_add_epi32(t5, mul_shift::<13>(t3, 2485));
+        t3 = _mm256_sub_epi32(t3, mul_shift::<15>(t5, 18205));
+        t5 = _mm256_add_epi32(t5, mul_shift::<13>(t3, 2485));
+        [t0, t1, t2, t3, t4, t5, t6, t7]
+    }
+
+    #[target_feature(enable = "avx2")]
+    #[inline]
+    unsafe fn transpose(r: [__m256i; 8]) -> [__m256i; 8] {
+        let t0 = _mm256_unpacklo_epi32(r[0], r[1]);
+        let t1 = _mm256_unpackhi_epi32(r[0], r[1]);
+        let t2 = _mm256_unpacklo_epi32(r[2], r[3]);
+        let t3 = _mm256_unpackhi_epi32(r[2], r[3]);
+        let t4 = _mm256_unpacklo_epi32(r[4], r[5]);
+        let t5 = _mm256_unpackhi_epi32(r[4], r[5]);
+        let t6 = _mm256_unpacklo_epi32(r[6], r[7]);
+        let t7 = _mm256_unpackhi_epi32(r[6], r[7]);
+        let u0 = _mm256_unpacklo_epi64(t0, t2);
+        let u1 = _mm256_unpackhi_epi64(t0, t2);
+        let u2 = _mm256_unpacklo_epi64(t1, t3);
+        let u3 = _mm256_unpackhi_epi64(t1, t3);
+        let u4 = _mm256_unpacklo_epi64(t4, t6);
+        let u5 = _mm256_unpackhi_epi64(t4, t6);
+        let u6 = _mm256_unpacklo_epi64(t5, t7);
+        let u7 = _mm256_unpackhi_epi64(t5, t7);
+        [
+            _mm256_permute2x128_si256::<0x20>(u0, u4),
+            _mm256_permute2x128_si256::<0x20>(u1, u5),
+            _mm256_permute2x128_si256::<0x20>(u2, u6),
+            _mm256_permute2x128_si256::<0x20>(u3, u7),
+            _mm256_permute2x128_si256::<0x31>(u0, u4),
+            _mm256_permute2x128_si256::<0x31>(u1, u5),
+            _mm256_permute2x128_si256::<0x31>(u2, u6),
+            _mm256_permute2x128_si256::<0x31>(u3, u7),
+        ]
+    }
 }
@@ -0,0 +1,156 @@
+//! Correlation of per-frame scores with encoder statistics.
+//!
+//! A rate control that spends its bits well gives frames with a higher
+//! quantizer a lower score, and frames that took more bits a higher one. How
+//! closely the per-frame scores follow the quantizers and frame sizes, and how
+//! much they move with them, shows whether quality varies because of the rate
+//! control or despite it.
+//!
+//! The statistics come from [`SideData`], reported by a decoder or attached
+//! from an encoder log with [`WithSideData`](crate::video::side_data::WithSideData).
+
+use crate::video::side_data::SideData;
+
+/// A least-squares line through per-frame scores.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LinearFit {
+    /// The change of the score for each unit of the statistic.
+    pub slope: f64,
+    /// The score predicted for a statistic of 0.
+    pub intercept: f64,
+    /// The Pearson correlation of the scores with the statistic, between -1
+    /// and 1, or 0 if every score is the same.
+    pub correlation: f64,
+    /// The number of frames the line was fitted to.
+    pub frames: usize,
+}
+
+impl LinearFit {
+    /// The score predicted for a statistic of `x`.
+    pub fn predict(&self, x: f64) -> f64 {
+        self.slope * x + self.intercept
+    }
+
+    /// The share of the variance of the scores explained by the line.
+    pub fn r_squared(&self) -> f64 {
+        self.correlation * self.correlation
+    }
+}
+
+/// The lines fitted through the per-frame scores of a video.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RateControlFits {
+    /// The scores against the average quantizer of each frame, if enough
+    /// frames had one.
+    pub qp: Option<LinearFit>,
+    /// The scores against the base 2 logarithm of the size of each frame, so
+    /// that the slope is the change of the score when a frame takes twice as
+    /// many bits, if enough frames had a size.
+    pub bits: Option<LinearFit>,
+}
+
+/// Running sums of the points of a least-squares fit.
+#[derive(Debug, Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct Sums {
+    n: usize,
+    x: f64,
+    y: f64,
+    xx: f64,
+    yy: f64,
+    xy: f64,
+}
+
+impl Sums {
+    fn push(&mut self, x: f64, y: f64) {
+        self.n += 1;
+        self.x += x;
+        self.y += y;
+        self.xx += x * x;
+        self.yy += y * y;
+        self.xy += x * y;
+    }
+
+    /// The fitted line, or `None` with fewer than two points or if every
+    /// point has the same statistic.
+    fn fit(&self) -> Option<LinearFit> {
+        if self.n < 2 {
+            return None;
+        }
+        let n = self.n as f64;
+        let var_x = n * self.xx - self.x * self.x;
+        let var_y = n * self.yy - self.y * self.y;
+        let cov = n * self.xy - self.x * self.y;
+        if var_x <= 0.0 {
+            return None;
+        }
+        let slope = cov / var_x;
+        Some(LinearFit {
+            slope,
+            intercept: (self.y - slope * self.x) / n,
+            correlation: if var_y > 0.0 {
+                (cov / (var_x * var_y).sqrt()).clamp(-1.0, 1.0)
+            } else {
+                0.0
+            },
+            frames: self.n,
+        })
+    }
+}
+
+/// Fits lines through the scores of consecutive frames against their encoder
+/// statistics.
+///
+/// Frames without a statistic are left out of its fit, as are infinite
+/// scores, such as the PSNR of identical frames.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RateControlAggregator {
+    qp: Sums,
+    bits: Sums,
+}
+
+impl RateControlAggregator {
+    /// Creates an aggregator with no frames.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the score of the next frame, with its side data if it is known.
+    pub fn push(&mut self, side_data: Option<&SideData>, score: f64) {
+        let Some(side_data) = side_data else {
+            return;
+        };
+        if !score.is_finite() {
+            return;
+        }
+        if let Some(qp) = side_data.mean_qp() {
+            self.qp.push(qp, score);
+        }
+        if let Some(bits) = side_data.bits.filter(|&bits| bits > 0) {
+            self.bits.push((bits as f64).log2(), score);
+        }
+    }
+
+    /// The lines fitted through the scores.
+    pub fn finish(self) -> RateControlFits {
+        RateControlFits {
+            qp: self.qp.fit(),
+            bits: self.bits.fit(),
+        }
+    }
+}
+
+/// Fits lines through the scores of consecutive frames, given with their side
+/// data, against their encoder statistics.
+pub fn fit_rate_control<'a, I: IntoIterator<Item = (Option<&'a SideData>, f64)>>(
+    scores: I,
+) -> RateControlFits {
+    let mut aggregator = RateControlAggregator::new();
+    for (side_data, score) in scores {
+        aggregator.push(side_data, score);
+    }
+    aggregator.finish()
+}
@@ -0,0 +1,183 @@
+//! Downscaling of video frames.
+//!
+//! Computing a metric on downscaled inputs is much faster than at full
+//! resolution, but the scores are only an approximation of the full
+//! resolution scores. This is intended for quick preview passes.
+
+use crate::video::decode::{Decoder, DecoderCapabilities, FrameMeta, StreamInfo, VideoDetails};
+use crate::video::pixel::{CastFromPrimitive, Pixel};
+use crate::video::side_data::SideData;
+use crate::MetricsError;
+use v_frame::frame::Frame;
+use v_frame::plane::Plane;
+
+/// A decoder that downscales every frame of another decoder by an integer
+/// factor, averaging each `factor` x `factor` block of pixels.
+///
+/// Any metric can be computed on the downscaled frames by passing this
+/// decoder in place of the original one. The results are approximate.
+pub struct Downscaled<D: Decoder> {
+    inner: D,
+    factor: usize,
+}
+
+impl<D: Decoder> Downscaled<D> {
+    /// Wraps `inner`, dividing the width and height of its frames by `factor`.
+    /// A factor of 1 passes the frames through unchanged.
+    pub fn new(inner: D, factor: usize) -> Result<Self, MetricsError> {
+        if factor == 0 {
+            return Err(MetricsError::UnsupportedInput {
+                reason: "Downscale factor must be at least 1",
+            });
+        }
+        Ok(Downscaled { inner, factor })
+    }
+
+    /// The factor the frames are downscaled by.
+    pub fn factor(&self) -> usize {
+        self.factor
+    }
+
+    /// Returns the wrapped decoder.
+    pub fn into_inner(self) -> D {
+        self.inner
+    }
+}
+
+impl<D: Decoder> Decoder for Downscaled<D> {
+    fn read_video_frame<T: Pixel>(&mut self) -> Option<Frame<T>> {
+        let frame = self.inner.read_video_frame()?;
+        if self.factor == 1 {
+            return Some(frame);
+        }
+        let details = self.inner.get_video_details();
+        Some(downscale_frame(&frame, &details, self.factor))
+    }
+
+    fn read_video_frame_with_alpha<T: Pixel>(&mut self) -> Option<(Frame<T>, Option<Plane<T>>)> {
+        let (frame, alpha) = self.inner.read_video_frame_with_alpha()?;
+        if self.factor == 1 {
+            return Some((frame, alpha));
+        }
+        let details = self.inner.get_video_details();
+        let frame = downscale_frame(&frame, &details, self.factor);
+        let alpha = alpha.map(|alpha| {
+            let mut out = frame.planes[0].clone();
+            downscale_plane(&alpha, &mut out, details.width, details.height, self.factor);
+            out
+        });
+        Some((frame, alpha))
+    }
+
+    fn get_bit_depth(&self) -> usize {
+        self.inner.get_bit_depth()
+    }
+
+    fn get_stream_info(&self) -> StreamInfo {
+        self.inner.get_stream_info()
+    }
+
+    fn last_frame_meta(&self) -> Option<FrameMeta> {
+        self.inner.last_frame_meta()
+    }
+
+    fn last_side_data(&self) -> Option<SideData> {
+        self.inner.last_side_data()
+    }
+
+    fn capabilities(&self) -> DecoderCapabilities {
+        DecoderCapabilities {
+            seek: false,
+            ..self.inner.capabilities()
+        }
+    }
+
+    fn frame_count(&self) -> Option<usize> {
+        self.inner.frame_count()
+    }
+
+    fn build_index(&mut self) -> Option<usize> {
+        self.inner.build_index()
+    }
+
+    fn get_video_details(&self) -> VideoDetails {
+        let details = self.inner.get_video_details();
+        VideoDetails {
+            width: details.width.div_ceil(self.factor),
+            height: details.height.div_ceil(self.factor),
+            luma_padding: 0,
+            ..details
+        }
+    }
+}
+
+/// Downscales a frame of a video with the given details by `factor` in
+/// both dimensions, averaging each `factor` x `factor` block of pixels.
+///
+/// Blocks at the right and bottom edges are averaged over the pixels
+/// they contain, so the dimensions of the result are rounded up.
+pub fn downscale_frame<T: Pixel>(
+    frame: &Frame<T>,
+    details: &VideoDetails,
+    factor: usize,
+) -> Frame<T> {
+    let mut out: Frame<T> = Frame::new_with_padding(
+        details.width.div_ceil(factor),
+        details.height.div_ceil(factor),
+        details.chroma_sampling,
+        0,
+    );
+    let (chroma_width, chroma_height) = details
+        .chroma_sampling
+        .get_chroma_dimensions(details.width, details.height);
+    downscale_plane(
+        &frame.planes[0],
+        &mut out.planes[0],
+        details.width,
+        details.height,
+        factor,
+    );
+    for plane in 1..3 {
+        downscale_plane(
+            &frame.planes[plane],
+            &mut out.planes[plane],
+            chroma_width,
+            chroma_height,
+            factor,
+        );
+    }
+    out
+}
+
+fn downscale_plane<T: Pixel>(
+    src: &Plane<T>,
+    dst: &mut Plane<T>,
+    width: usize,
+    height: usize,
+    factor: usize,
+) {
+    if width == 0 || height == 0 {
+        return;
+    }
+    let stride = src.cfg.stride;
+    let data = src.data_origin();
+    let out_width = dst.cfg.width;
+    for (y, out_row) in dst.rows_iter_mut().enumerate() {
+        let y0 = (y * factor).min(height - 1);
+        let y1 = ((y + 1) * factor).min(height).max(y0 + 1);
+        for (x, out) in out_row[..out_width].iter_mut().enumerate() {
+            let x0 = (x * factor).min(width - 1);
+            let x1 = ((x + 1) * factor).min(width).max(x0 + 1);
+            let sum: u64 = (y0..y1)
+                .map(|row| {
+                    data[row * stride + x0..row * stride + x1]
+                        .iter()
+                        .map(|&p| u64::from(u16::cast_from(p)))
+                        .sum::<u64>()
+                })
+                .sum();
+            let count = ((y1 - y0) * (x1 - x0)) as u64;
+            *out = T::cast_from(((sum + count / 2) / count) as u16);
+        }
+    }
+}
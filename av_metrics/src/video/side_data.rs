@@ -0,0 +1,220 @@
+//! Encoder statistics of each frame, for hybrid metrics.
+//!
+//! Hybrid metrics, such as ITU-T P.1204.4 or AVQT, combine the decoded frames
+//! with what the encoder knows about them: how many bits each frame took and
+//! the quantizers it used. Decoders report these as [`SideData`] through
+//! [`Decoder::last_side_data`], and [`WithSideData`] attaches statistics from
+//! another source, such as the log of an encoder, to the frames of any
+//! decoder:
+//!
+//! ```no_run
+//! # use av_metrics::video::decode::Decoder;
+//! # use av_metrics::video::side_data::{SideData, WithSideData};
+//! # fn attach<D: Decoder>(decoder: D, frame_sizes: Vec<u64>) {
+//! let side_data = frame_sizes.into_iter().map(|bytes| SideData {
+//!     bits: Some(bytes * 8),
+//!     ..Default::default()
+//! });
+//! let mut decoder = WithSideData::new(decoder, side_data);
+//! # }
+//! ```
+
+use crate::video::decode::{
+    Decoder, DecoderCapabilities, FrameMeta, FrameRef, StreamInfo, VideoDetails,
+};
+use crate::video::pixel::Pixel;
+use v_frame::frame::Frame;
+use v_frame::plane::Plane;
+
+/// Encoder statistics of one frame. Fields are `None` when they are not known.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SideData {
+    /// The size of the coded frame, in bits.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub bits: Option<u64>,
+    /// The average quantizer of the frame, in the scale of the codec.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub qp: Option<f64>,
+    /// The quantizer of each block of the frame.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub qp_map: Option<QpMap>,
+}
+
+impl SideData {
+    /// The average quantizer of the frame, from [`qp`](Self::qp) or else
+    /// from the [`qp_map`](Self::qp_map).
+    pub fn mean_qp(&self) -> Option<f64> {
+        self.qp
+            .or_else(|| self.qp_map.as_ref().map(|map| map.mean()))
+    }
+}
+
+/// The quantizers of the blocks of a frame, row by row.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct QpMap {
+    block_size: usize,
+    columns: usize,
+    rows: usize,
+    values: Vec<i32>,
+}
+
+impl QpMap {
+    /// Creates a map of `rows` rows of `columns` blocks of `block_size` by
+    /// `block_size` luma pixels.
+    ///
+    /// Returns `None` if the block size is 0 or `values` does not hold one
+    /// quantizer for each block.
+    pub fn new(block_size: usize, columns: usize, rows: usize, values: Vec<i32>) -> Option<Self> {
+        if block_size == 0 || values.len() != columns * rows {
+            return None;
+        }
+        Some(QpMap {
+            block_size,
+            columns,
+            rows,
+            values,
+        })
+    }
+
+    /// The width and height of the blocks, in luma pixels.
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    /// The number of blocks in each row.
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    /// The number of rows of blocks.
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    /// The quantizers of all blocks, row by row.
+    pub fn values(&self) -> &[i32] {
+        &self.values
+    }
+
+    /// The quantizer of the block covering the luma pixel at `x`, `y`, if the
+    /// map covers it.
+    pub fn at_pixel(&self, x: usize, y: usize) -> Option<i32> {
+        let (column, row) = (x / self.block_size, y / self.block_size);
+        if column >= self.columns || row >= self.rows {
+            return None;
+        }
+        Some(self.values[row * self.columns + column])
+    }
+
+    /// The average quantizer of all blocks, or 0 for an empty map.
+    pub fn mean(&self) -> f64 {
+        if self.values.is_empty() {
+            return 0.0;
+        }
+        self.values.iter().map(|&qp| f64::from(qp)).sum::<f64>() / self.values.len() as f64
+    }
+}
+
+/// A decoder that attaches the side data of an iterator to the frames of
+/// another decoder, in order.
+///
+/// Frames after the end of the iterator have no side data.
+pub struct WithSideData<D: Decoder, I: Iterator<Item = SideData>> {
+    inner: D,
+    side_data: I,
+    last: Option<SideData>,
+}
+
+impl<D: Decoder, I: Iterator<Item = SideData> + Send> WithSideData<D, I> {
+    /// Wraps `inner`, attaching each item of `side_data` to the next frame.
+    pub fn new<S: IntoIterator<IntoIter = I>>(inner: D, side_data: S) -> Self {
+        WithSideData {
+            inner,
+            side_data: side_data.into_iter(),
+            last: None,
+        }
+    }
+
+    /// Returns the wrapped decoder.
+    pub fn into_inner(self) -> D {
+        self.inner
+    }
+
+    /// Moves to the side data of the next frame if a frame was read.
+    fn advance<R>(&mut self, frame: Option<R>) -> Option<R> {
+        self.last = frame.as_ref().and_then(|_| self.side_data.next());
+        frame
+    }
+}
+
+impl<D: Decoder, I: Iterator<Item = SideData> + Send> Decoder for WithSideData<D, I> {
+    fn read_video_frame<T: Pixel>(&mut self) -> Option<Frame<T>> {
+        let frame = self.inner.read_video_frame();
+        self.advance(frame)
+    }
+
+    fn read_video_frame_into<T: Pixel>(&mut self, frame: &mut Frame<T>) -> bool {
+        let read = self.inner.read_video_frame_into(frame);
+        self.advance(read.then_some(())).is_some()
+    }
+
+    fn read_video_frame_ref<T: Pixel, R, F: FnOnce(FrameRef<'_, T>) -> R>(
+        &mut self,
+        f: F,
+    ) -> Option<R> {
+        let result = self.inner.read_video_frame_ref(f);
+        self.advance(result)
+    }
+
+    fn read_video_frame_with_alpha<T: Pixel>(&mut self) -> Option<(Frame<T>, Option<Plane<T>>)> {
+        let frame = self.inner.read_video_frame_with_alpha();
+        self.advance(frame)
+    }
+
+    fn get_bit_depth(&self) -> usize {
+        self.inner.get_bit_depth()
+    }
+
+    fn get_video_details(&self) -> VideoDetails {
+        self.inner.get_video_details()
+    }
+
+    fn get_stream_info(&self) -> StreamInfo {
+        self.inner.get_stream_info()
+    }
+
+    fn last_frame_meta(&self) -> Option<FrameMeta> {
+        self.inner.last_frame_meta()
+    }
+
+    fn last_side_data(&self) -> Option<SideData> {
+        self.last.clone()
+    }
+
+    fn capabilities(&self) -> DecoderCapabilities {
+        // Seeking would skip the side data of the frames in between
+        DecoderCapabilities {
+            seek: false,
+            ..self.inner.capabilities()
+        }
+    }
+
+    fn frame_count(&self) -> Option<usize> {
+        self.inner.frame_count()
+    }
+
+    fn build_index(&mut self) -> Option<usize> {
+        self.inner.build_index()
+    }
+}
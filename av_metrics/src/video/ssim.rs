@@ -12,18 +12,18 @@ use crate::video::decode::Decoder;
 use crate::video::pixel::CastFromPrimitive;
 use crate::video::pixel::Pixel;
 use crate::video::ChromaWeight;
-use crate::video::{PlanarMetrics, VideoMetric};
+use crate::video::{
+    CompatMode, FrameContext, PlanarMetrics, PlaneSelect, PlaneWeights, Score, VideoMetric,
+    VideoOptions,
+};
 use crate::MetricsError;
 use std::cmp;
 use std::error::Error;
 use std::f64::consts::{E, PI};
-use std::mem::size_of;
 use v_frame::frame::Frame;
 use v_frame::plane::Plane;
 use v_frame::prelude::ChromaSampling;
 
-use super::FrameCompare;
-
 /// Calculates the SSIM score between two videos. Higher is better.
 #[inline]
 pub fn calculate_video_ssim<D: Decoder, F: Fn(usize) + Send>(
@@ -31,6 +31,47 @@ pub fn calculate_video_ssim<D: Decoder, F: Fn(usize) + Send>(
     decoder2: &mut D,
     frame_limit: Option<usize>,
     progress_callback: F,
+) -> Result<PlanarMetrics, Box<dyn Error>> {
+    calculate_video_ssim_with_config(
+        decoder1,
+        decoder2,
+        frame_limit,
+        progress_callback,
+        SsimConfig::default(),
+    )
+}
+
+/// Calculates the SSIM score between two videos using the given configuration.
+/// Higher is better.
+///
+/// See [`SsimConfig`] for the available options.
+#[inline]
+pub fn calculate_video_ssim_with_config<D: Decoder, F: Fn(usize) + Send>(
+    decoder1: &mut D,
+    decoder2: &mut D,
+    frame_limit: Option<usize>,
+    progress_callback: F,
+    config: SsimConfig,
+) -> Result<PlanarMetrics, Box<dyn Error>> {
+    calculate_video_ssim_with(
+        decoder1,
+        decoder2,
+        SsimOptions {
+            video: VideoOptions::limited(frame_limit, progress_callback),
+            config,
+        },
+    )
+}
+
+/// Calculates the SSIM score between two videos with the given options.
+/// Higher is better.
+///
+/// See [`SsimOptions`] for the available options.
+#[inline]
+pub fn calculate_video_ssim_with<D: Decoder, F: Fn(usize) + Send>(
+    decoder1: &mut D,
+    decoder2: &mut D,
+    options: SsimOptions<F>,
 ) -> Result<PlanarMetrics, Box<dyn Error>> {
     let cweight = Some(
         decoder1
@@ -38,7 +79,33 @@ pub fn calculate_video_ssim<D: Decoder, F: Fn(usize) + Send>(
             .chroma_sampling
             .get_chroma_weight(),
     );
-    Ssim { cweight }.process_video(decoder1, decoder2, frame_limit, progress_callback)
+    let config = options.config;
+    options.video.run(|limits, progress_callback| {
+        Ssim { cweight, config }.process_video(decoder1, decoder2, limits, progress_callback)
+    })
+}
+
+/// Calculates the SSIM for pairs of frames from any source, such as frames
+/// received over a network or generated in memory. Higher is better.
+///
+/// The frames are scored with the same multithreaded pipeline as
+/// [`calculate_video_ssim`].
+#[inline]
+pub fn calculate_video_ssim_from_frames<T: Pixel, I, F: Fn(usize) + Send>(
+    frames: I,
+    bit_depth: usize,
+    chroma_sampling: ChromaSampling,
+    progress_callback: F,
+) -> Result<PlanarMetrics, Box<dyn Error>>
+where
+    I: IntoIterator<Item = (Frame<T>, Frame<T>)>,
+    I::IntoIter: Send,
+{
+    Ssim {
+        cweight: Some(chroma_sampling.get_chroma_weight()),
+        config: SsimConfig::default(),
+    }
+    .process_frames(frames, bit_depth, chroma_sampling, progress_callback)
 }
 
 /// Calculates the SSIM score between two video frames. Higher is better.
@@ -49,23 +116,239 @@ pub fn calculate_frame_ssim<T: Pixel>(
     bit_depth: usize,
     chroma_sampling: ChromaSampling,
 ) -> Result<PlanarMetrics, Box<dyn Error>> {
-    let processor = Ssim::default();
+    calculate_frame_ssim_with_config(
+        frame1,
+        frame2,
+        bit_depth,
+        chroma_sampling,
+        SsimConfig::default(),
+    )
+}
+
+/// Calculates the SSIM score between two video frames using the given
+/// configuration. Higher is better.
+///
+/// See [`SsimConfig`] for the available options.
+#[inline]
+pub fn calculate_frame_ssim_with_config<T: Pixel>(
+    frame1: &Frame<T>,
+    frame2: &Frame<T>,
+    bit_depth: usize,
+    chroma_sampling: ChromaSampling,
+    config: SsimConfig,
+) -> Result<PlanarMetrics, Box<dyn Error>> {
+    let processor = Ssim {
+        cweight: None,
+        config,
+    };
     let result = processor.process_frame(frame1, frame2, bit_depth, chroma_sampling)?;
-    let cweight = chroma_sampling.get_chroma_weight();
-    Ok(PlanarMetrics {
-        y: log10_convert(result.y, 1.0),
-        u: log10_convert(result.u, 1.0),
-        v: log10_convert(result.v, 1.0),
-        avg: log10_convert(
-            result.y + cweight * (result.u + result.v),
-            1.0 + 2.0 * cweight,
-        ),
-    })
+    Ok(planar_ssim(
+        [result.y, result.u, result.v],
+        1.0,
+        config.weights.weights(chroma_sampling),
+        config.planes,
+        config.scale,
+    ))
+}
+
+/// Calculates the local SSIM of every sample of two video frames, before the
+/// samples are pooled into a score. Higher is better.
+///
+/// One map is returned for each plane, in the order Y, U, V. The SSIM score of
+/// a plane is the mean of its map, with samples near the edges weighted by how
+/// much of the window lies within the plane.
+#[inline]
+pub fn calculate_frame_ssim_map<T: Pixel>(
+    frame1: &Frame<T>,
+    frame2: &Frame<T>,
+    bit_depth: usize,
+    chroma_sampling: ChromaSampling,
+) -> Result<[SsimMap; 3], Box<dyn Error>> {
+    calculate_frame_ssim_map_with_config(
+        frame1,
+        frame2,
+        bit_depth,
+        chroma_sampling,
+        SsimConfig::default(),
+    )
+}
+
+/// Calculates the local SSIM of every sample of two video frames using the
+/// given configuration.
+///
+/// The maps of the planes that are not selected in `config` are all zeros.
+pub fn calculate_frame_ssim_map_with_config<T: Pixel>(
+    frame1: &Frame<T>,
+    frame2: &Frame<T>,
+    bit_depth: usize,
+    chroma_sampling: ChromaSampling,
+    config: SsimConfig,
+) -> Result<[SsimMap; 3], Box<dyn Error>> {
+    FrameContext::new(bit_depth, chroma_sampling)?.validate(frame1, frame2)?;
+    let sample_max = (1 << bit_depth) - 1;
+    let plane_map = |plane: usize| {
+        let cfg = &frame1.planes[plane].cfg;
+        let mut map = SsimMap {
+            width: cfg.width,
+            height: cfg.height,
+            data: vec![0.0; cfg.width * cfg.height],
+        };
+        if config.planes.has_plane(plane) {
+            calculate_plane_ssim(
+                &frame1.planes[plane],
+                &frame2.planes[plane],
+                sample_max,
+                config.window,
+                Some(&mut map.data),
+            );
+        }
+        map
+    };
+
+    let (y, (u, v)) = rayon::join(
+        || plane_map(0),
+        || rayon::join(|| plane_map(1), || plane_map(2)),
+    );
+    Ok([y, u, v])
+}
+
+/// The local SSIM of every sample of a plane, as returned by
+/// [`calculate_frame_ssim_map`].
+///
+/// The values are not stored in a `Plane`, which only holds integer samples.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SsimMap {
+    /// The width of the plane in samples.
+    pub width: usize,
+    /// The height of the plane in samples.
+    pub height: usize,
+    /// The local SSIM of each sample, in rows of `width` samples.
+    pub data: Vec<f32>,
+}
+
+impl SsimMap {
+    /// Returns the local SSIM at the given position.
+    pub fn get(&self, x: usize, y: usize) -> f32 {
+        self.data[y * self.width + x]
+    }
+
+    /// Returns the rows of the map.
+    pub fn rows(&self) -> impl Iterator<Item = &[f32]> {
+        self.data.chunks_exact(self.width)
+    }
+}
+
+/// The window used to compute the local statistics of SSIM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SsimWindow {
+    /// A Gaussian window whose standard deviation grows with the height of
+    /// the plane, `height * 1.5 / 256` samples, so that larger planes are
+    /// compared at a coarser scale.
+    #[default]
+    Adaptive,
+    /// A Gaussian window of 11x11 samples with a standard deviation of 1.5
+    /// samples, as in the original SSIM paper and most other implementations.
+    Standard,
+}
+
+/// The scale SSIM and MS-SSIM scores are reported on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SsimScale {
+    /// Decibels, `10 * log10(1 / (1 - SSIM))`, which spreads out the scores
+    /// close to 1 where most encodes lie. This is the default.
+    #[default]
+    Decibels,
+    /// The mean SSIM itself, at most 1 for identical inputs, as reported by
+    /// most other implementations.
+    Raw,
+}
+
+impl SsimScale {
+    /// Converts the sum of `count` unscaled scores to this scale.
+    fn convert(self, score: f64, count: f64) -> f64 {
+        match self {
+            SsimScale::Decibels => log10_convert(score, count),
+            SsimScale::Raw => score / count,
+        }
+    }
+}
+
+/// Options for computing SSIM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SsimConfig {
+    /// The window used to compute the local statistics.
+    /// It is not used with [`CompatMode::Ffmpeg`].
+    pub window: SsimWindow,
+    /// The planes to compute. The others are reported as 0 and are not part
+    /// of the average.
+    pub planes: PlaneSelect,
+    /// The conventions to follow. See [`CompatMode`] for the differences.
+    /// The maps returned by [`calculate_frame_ssim_map_with_config`] always
+    /// use this crate's conventions.
+    pub compat: CompatMode,
+    /// The scale the scores are reported on.
+    pub scale: SsimScale,
+    /// How the selected planes are weighted in the average.
+    pub weights: PlaneWeights,
+}
+
+/// Options for computing SSIM over whole videos, built with chained calls:
+///
+/// ```
+/// use av_metrics::video::ssim::{SsimOptions, SsimWindow};
+///
+/// let options = SsimOptions::new().frame_limit(100).window(SsimWindow::Standard);
+/// ```
+#[derive(Debug, Clone)]
+pub struct SsimOptions<F = fn(usize)> {
+    /// The frame limit, thread count and progress callback.
+    pub video: VideoOptions<F>,
+    /// The options of SSIM itself.
+    pub config: SsimConfig,
+}
+
+video_options!(SsimOptions);
+
+impl<F> SsimOptions<F> {
+    /// Computes the local statistics with `window`.
+    pub fn window(mut self, window: SsimWindow) -> Self {
+        self.config.window = window;
+        self
+    }
+
+    /// Only computes the given planes.
+    pub fn planes(mut self, planes: PlaneSelect) -> Self {
+        self.config.planes = planes;
+        self
+    }
+
+    /// Follows the conventions of `compat`.
+    pub fn compat(mut self, compat: CompatMode) -> Self {
+        self.config.compat = compat;
+        self
+    }
+
+    /// Reports the scores on `scale`.
+    pub fn scale(mut self, scale: SsimScale) -> Self {
+        self.config.scale = scale;
+        self
+    }
+
+    /// Weights the planes in the average with `weights`.
+    pub fn weights(mut self, weights: PlaneWeights) -> Self {
+        self.config.weights = weights;
+        self
+    }
 }
 
 #[derive(Default)]
 struct Ssim {
     pub cweight: Option<f64>,
+    pub config: SsimConfig,
 }
 
 impl VideoMetric for Ssim {
@@ -79,69 +362,44 @@ impl VideoMetric for Ssim {
         frame1: &Frame<T>,
         frame2: &Frame<T>,
         bit_depth: usize,
-        _chroma_sampling: ChromaSampling,
+        chroma_sampling: ChromaSampling,
     ) -> Result<Self::FrameResult, Box<dyn Error>> {
-        if (size_of::<T>() == 1 && bit_depth > 8) || (size_of::<T>() == 2 && bit_depth <= 8) {
-            return Err(Box::new(MetricsError::InputMismatch {
-                reason: "Bit depths does not match pixel width",
+        FrameContext::new(bit_depth, chroma_sampling)?.validate(frame1, frame2)?;
+        let planes = self.config.planes;
+        if planes.is_empty() {
+            return Err(Box::new(MetricsError::UnsupportedInput {
+                reason: "No planes selected",
             }));
         }
 
-        frame1.can_compare(frame2)?;
-
-        const KERNEL_SHIFT: usize = 8;
-        const KERNEL_WEIGHT: usize = 1 << KERNEL_SHIFT;
         let sample_max = (1 << bit_depth) - 1;
+        let plane_ssim = |plane: usize| match self.config.compat {
+            CompatMode::Native => calculate_plane_ssim(
+                &frame1.planes[plane],
+                &frame2.planes[plane],
+                sample_max,
+                self.config.window,
+                None,
+            ),
+            CompatMode::Ffmpeg => {
+                calculate_plane_ssim_ffmpeg(&frame1.planes[plane], &frame2.planes[plane], bit_depth)
+            }
+        };
 
         let mut y = 0.0;
         let mut u = 0.0;
         let mut v = 0.0;
 
         rayon::scope(|s| {
-            s.spawn(|_| {
-                let y_kernel = build_gaussian_kernel(
-                    frame1.planes[0].cfg.height as f64 * 1.5 / 256.0,
-                    cmp::min(frame1.planes[0].cfg.width, frame1.planes[0].cfg.height),
-                    KERNEL_WEIGHT,
-                );
-                y = calculate_plane_ssim(
-                    &frame1.planes[0],
-                    &frame2.planes[0],
-                    sample_max,
-                    &y_kernel,
-                    &y_kernel,
-                )
-            });
-
-            s.spawn(|_| {
-                let u_kernel = build_gaussian_kernel(
-                    frame1.planes[1].cfg.height as f64 * 1.5 / 256.0,
-                    cmp::min(frame1.planes[1].cfg.width, frame1.planes[1].cfg.height),
-                    KERNEL_WEIGHT,
-                );
-                u = calculate_plane_ssim(
-                    &frame1.planes[1],
-                    &frame2.planes[1],
-                    sample_max,
-                    &u_kernel,
-                    &u_kernel,
-                )
-            });
-
-            s.spawn(|_| {
-                let v_kernel = build_gaussian_kernel(
-                    frame1.planes[2].cfg.height as f64 * 1.5 / 256.0,
-                    cmp::min(frame1.planes[2].cfg.width, frame1.planes[2].cfg.height),
-                    KERNEL_WEIGHT,
-                );
-                v = calculate_plane_ssim(
-                    &frame1.planes[2],
-                    &frame2.planes[2],
-                    sample_max,
-                    &v_kernel,
-                    &v_kernel,
-                )
-            });
+            if planes.has_plane(0) {
+                s.spawn(|_| y = plane_ssim(0));
+            }
+            if planes.has_plane(1) {
+                s.spawn(|_| u = plane_ssim(1));
+            }
+            if planes.has_plane(2) {
+                s.spawn(|_| v = plane_ssim(2));
+            }
         });
 
         Ok(PlanarMetrics {
@@ -150,29 +408,67 @@ impl VideoMetric for Ssim {
             v,
             // Not used here
             avg: 0.,
+            alpha: None,
+            dssim: None,
         })
     }
 
+    fn frame_score(&self, result: &Self::FrameResult) -> Option<Score> {
+        self.aggregate_frame_results(std::slice::from_ref(result))
+            .ok()
+            .map(Score::Planar)
+    }
+
     fn aggregate_frame_results(
         &self,
         metrics: &[Self::FrameResult],
     ) -> Result<Self::VideoResult, Box<dyn Error>> {
-        let cweight = self.cweight.unwrap_or(1.0);
-        let y_sum = metrics.iter().map(|m| m.y).sum::<f64>();
-        let u_sum = metrics.iter().map(|m| m.u).sum::<f64>();
-        let v_sum = metrics.iter().map(|m| m.v).sum::<f64>();
-        Ok(PlanarMetrics {
-            y: log10_convert(y_sum, metrics.len() as f64),
-            u: log10_convert(u_sum, metrics.len() as f64),
-            v: log10_convert(v_sum, metrics.len() as f64),
-            avg: log10_convert(
-                y_sum + cweight * (u_sum + v_sum),
-                (1. + 2. * cweight) * metrics.len() as f64,
-            ),
-        })
+        Ok(planar_ssim(
+            [
+                metrics.iter().map(|m| m.y).sum::<f64>(),
+                metrics.iter().map(|m| m.u).sum::<f64>(),
+                metrics.iter().map(|m| m.v).sum::<f64>(),
+            ],
+            metrics.len() as f64,
+            self.config
+                .weights
+                .with_chroma_weight(self.cweight.unwrap_or(1.0)),
+            self.config.planes,
+            self.config.scale,
+        ))
     }
 }
 
+/// Converts the sums of the unweighted scores of each plane over `count`
+/// frames to `scale`. The average is taken over the selected planes, with
+/// the given weights, and is also reported as DSSIM.
+fn planar_ssim(
+    sums: [f64; 3],
+    count: f64,
+    weights: [f64; 3],
+    planes: PlaneSelect,
+    scale: SsimScale,
+) -> PlanarMetrics {
+    let (weighted_sum, total_weight) = (0..3)
+        .filter(|&plane| planes.has_plane(plane))
+        .fold((0.0, 0.0), |(sum, total), plane| {
+            (sum + weights[plane] * sums[plane], total + weights[plane])
+        });
+    PlanarMetrics {
+        y: scale.convert(sums[0], count),
+        u: scale.convert(sums[1], count),
+        v: scale.convert(sums[2], count),
+        avg: scale.convert(weighted_sum, total_weight * count),
+        alpha: None,
+        dssim: Some(dssim(weighted_sum / (total_weight * count))),
+    }
+}
+
+/// Converts an unscaled SSIM score to structural dissimilarity.
+fn dssim(ssim: f64) -> f64 {
+    1.0 / ssim - 1.0
+}
+
 /// Calculates the MSSSIM score between two videos. Higher is better.
 ///
 /// MSSSIM is a variant of SSIM computed over subsampled versions
@@ -184,6 +480,70 @@ pub fn calculate_video_msssim<D: Decoder, F: Fn(usize) + Send>(
     decoder2: &mut D,
     frame_limit: Option<usize>,
     progress_callback: F,
+) -> Result<PlanarMetrics, Box<dyn Error>> {
+    calculate_video_msssim_with_config(
+        decoder1,
+        decoder2,
+        frame_limit,
+        progress_callback,
+        MsSsimConfig::default(),
+    )
+}
+
+/// Calculates the MSSSIM for pairs of frames from any source, such as frames
+/// received over a network or generated in memory. Higher is better.
+///
+/// The frames are scored with the same multithreaded pipeline as
+/// [`calculate_video_msssim`].
+#[inline]
+pub fn calculate_video_msssim_from_frames<T: Pixel, I, F: Fn(usize) + Send>(
+    frames: I,
+    bit_depth: usize,
+    chroma_sampling: ChromaSampling,
+    progress_callback: F,
+) -> Result<PlanarMetrics, Box<dyn Error>>
+where
+    I: IntoIterator<Item = (Frame<T>, Frame<T>)>,
+    I::IntoIter: Send,
+{
+    MsSsim {
+        cweight: Some(chroma_sampling.get_chroma_weight()),
+        config: MsSsimConfig::default(),
+    }
+    .process_frames(frames, bit_depth, chroma_sampling, progress_callback)
+}
+
+/// Calculates the MSSSIM score between two videos using the given configuration.
+/// Higher is better.
+///
+/// See [`MsSsimConfig`] for the available trade-offs between speed and accuracy.
+#[inline]
+pub fn calculate_video_msssim_with_config<D: Decoder, F: Fn(usize) + Send>(
+    decoder1: &mut D,
+    decoder2: &mut D,
+    frame_limit: Option<usize>,
+    progress_callback: F,
+    config: MsSsimConfig,
+) -> Result<PlanarMetrics, Box<dyn Error>> {
+    calculate_video_msssim_with(
+        decoder1,
+        decoder2,
+        MsSsimOptions {
+            video: VideoOptions::limited(frame_limit, progress_callback),
+            config,
+        },
+    )
+}
+
+/// Calculates the MSSSIM score between two videos with the given options.
+/// Higher is better.
+///
+/// See [`MsSsimOptions`] for the available options.
+#[inline]
+pub fn calculate_video_msssim_with<D: Decoder, F: Fn(usize) + Send>(
+    decoder1: &mut D,
+    decoder2: &mut D,
+    options: MsSsimOptions<F>,
 ) -> Result<PlanarMetrics, Box<dyn Error>> {
     let cweight = Some(
         decoder1
@@ -191,7 +551,10 @@ pub fn calculate_video_msssim<D: Decoder, F: Fn(usize) + Send>(
             .chroma_sampling
             .get_chroma_weight(),
     );
-    MsSsim { cweight }.process_video(decoder1, decoder2, frame_limit, progress_callback)
+    let config = options.config;
+    options.video.run(|limits, progress_callback| {
+        MsSsim { cweight, config }.process_video(decoder1, decoder2, limits, progress_callback)
+    })
 }
 
 /// Calculates the MSSSIM score between two video frames. Higher is better.
@@ -206,23 +569,141 @@ pub fn calculate_frame_msssim<T: Pixel>(
     bit_depth: usize,
     chroma_sampling: ChromaSampling,
 ) -> Result<PlanarMetrics, Box<dyn Error>> {
-    let processor = MsSsim::default();
+    calculate_frame_msssim_with_config(
+        frame1,
+        frame2,
+        bit_depth,
+        chroma_sampling,
+        MsSsimConfig::default(),
+    )
+}
+
+/// Calculates the MSSSIM score between two video frames using the given
+/// configuration. Higher is better.
+///
+/// See [`MsSsimConfig`] for the available trade-offs between speed and accuracy.
+#[inline]
+pub fn calculate_frame_msssim_with_config<T: Pixel>(
+    frame1: &Frame<T>,
+    frame2: &Frame<T>,
+    bit_depth: usize,
+    chroma_sampling: ChromaSampling,
+    config: MsSsimConfig,
+) -> Result<PlanarMetrics, Box<dyn Error>> {
+    let processor = MsSsim {
+        cweight: None,
+        config,
+    };
     let result = processor.process_frame(frame1, frame2, bit_depth, chroma_sampling)?;
-    let cweight = chroma_sampling.get_chroma_weight();
-    Ok(PlanarMetrics {
-        y: log10_convert(result.y, 1.0),
-        u: log10_convert(result.u, 1.0),
-        v: log10_convert(result.v, 1.0),
-        avg: log10_convert(
-            result.y + cweight * (result.u + result.v),
-            1.0 + 2.0 * cweight,
-        ),
-    })
+    Ok(planar_ssim(
+        [result.y, result.u, result.v],
+        1.0,
+        config.weights.weights(chroma_sampling),
+        config.planes(),
+        config.scale,
+    ))
+}
+
+/// The window used to compute the local statistics of MS-SSIM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MsSsimWindow {
+    /// A Gaussian window with a standard deviation of 1.5 samples,
+    /// as in the original MS-SSIM paper.
+    #[default]
+    Gaussian,
+    /// A square window of 7x7 samples, computed with summed-area tables.
+    ///
+    /// The cost per sample does not depend on the size of the window, which makes
+    /// this considerably faster. Scores are close to, but not the same as,
+    /// the Gaussian window.
+    Box,
+}
+
+/// Options for computing MS-SSIM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MsSsimConfig {
+    /// The window used to compute the local statistics.
+    pub window: MsSsimWindow,
+    /// Only compute the luma plane. The chroma scores are reported as 0
+    /// and the average is the luma score.
+    ///
+    /// This is a shorthand for setting `planes` to [`PlaneSelect::Y`],
+    /// and takes precedence over it.
+    pub luma_only: bool,
+    /// The planes to compute. The others are reported as 0 and are not part
+    /// of the average.
+    pub planes: PlaneSelect,
+    /// The scale the scores are reported on.
+    pub scale: SsimScale,
+    /// How the selected planes are weighted in the average.
+    pub weights: PlaneWeights,
+}
+
+impl MsSsimConfig {
+    fn planes(&self) -> PlaneSelect {
+        if self.luma_only {
+            PlaneSelect::Y
+        } else {
+            self.planes
+        }
+    }
+}
+
+/// Options for computing MS-SSIM over whole videos, built with chained calls:
+///
+/// ```
+/// use av_metrics::video::ssim::MsSsimOptions;
+///
+/// let options = MsSsimOptions::new().threads(2).luma_only(true);
+/// ```
+#[derive(Debug, Clone)]
+pub struct MsSsimOptions<F = fn(usize)> {
+    /// The frame limit, thread count and progress callback.
+    pub video: VideoOptions<F>,
+    /// The options of MS-SSIM itself.
+    pub config: MsSsimConfig,
+}
+
+video_options!(MsSsimOptions);
+
+impl<F> MsSsimOptions<F> {
+    /// Computes the local statistics with `window`.
+    pub fn window(mut self, window: MsSsimWindow) -> Self {
+        self.config.window = window;
+        self
+    }
+
+    /// Only computes the luma plane.
+    pub fn luma_only(mut self, luma_only: bool) -> Self {
+        self.config.luma_only = luma_only;
+        self
+    }
+
+    /// Only computes the given planes.
+    pub fn planes(mut self, planes: PlaneSelect) -> Self {
+        self.config.planes = planes;
+        self
+    }
+
+    /// Reports the scores on `scale`.
+    pub fn scale(mut self, scale: SsimScale) -> Self {
+        self.config.scale = scale;
+        self
+    }
+
+    /// Weights the planes in the average with `weights`.
+    pub fn weights(mut self, weights: PlaneWeights) -> Self {
+        self.config.weights = weights;
+        self
+    }
 }
 
 #[derive(Default)]
 struct MsSsim {
     pub cweight: Option<f64>,
+    pub config: MsSsimConfig,
 }
 
 impl VideoMetric for MsSsim {
@@ -236,30 +717,40 @@ impl VideoMetric for MsSsim {
         frame1: &Frame<T>,
         frame2: &Frame<T>,
         bit_depth: usize,
-        _chroma_sampling: ChromaSampling,
+        chroma_sampling: ChromaSampling,
     ) -> Result<Self::FrameResult, Box<dyn Error>> {
-        if (size_of::<T>() == 1 && bit_depth > 8) || (size_of::<T>() == 2 && bit_depth <= 8) {
-            return Err(Box::new(MetricsError::InputMismatch {
-                reason: "Bit depths does not match pixel width",
+        FrameContext::new(bit_depth, chroma_sampling)?.validate(frame1, frame2)?;
+        let planes = self.config.planes();
+        if planes.is_empty() {
+            return Err(Box::new(MetricsError::UnsupportedInput {
+                reason: "No planes selected",
             }));
         }
 
-        frame1.can_compare(frame2)?;
+        let window = self.config.window;
+        let plane_msssim = |plane: usize| {
+            calculate_plane_msssim(
+                &frame1.planes[plane],
+                &frame2.planes[plane],
+                bit_depth,
+                window,
+            )
+        };
 
         let mut y = 0.0;
         let mut u = 0.0;
         let mut v = 0.0;
 
         rayon::scope(|s| {
-            s.spawn(|_| {
-                y = calculate_plane_msssim(&frame1.planes[0], &frame2.planes[0], bit_depth)
-            });
-            s.spawn(|_| {
-                u = calculate_plane_msssim(&frame1.planes[1], &frame2.planes[1], bit_depth)
-            });
-            s.spawn(|_| {
-                v = calculate_plane_msssim(&frame1.planes[2], &frame2.planes[2], bit_depth)
-            });
+            if planes.has_plane(0) {
+                s.spawn(|_| y = plane_msssim(0));
+            }
+            if planes.has_plane(1) {
+                s.spawn(|_| u = plane_msssim(1));
+            }
+            if planes.has_plane(2) {
+                s.spawn(|_| v = plane_msssim(2));
+            }
         });
 
         Ok(PlanarMetrics {
@@ -268,26 +759,34 @@ impl VideoMetric for MsSsim {
             v,
             // Not used here
             avg: 0.,
+            alpha: None,
+            dssim: None,
         })
     }
 
+    fn frame_score(&self, result: &Self::FrameResult) -> Option<Score> {
+        self.aggregate_frame_results(std::slice::from_ref(result))
+            .ok()
+            .map(Score::Planar)
+    }
+
     fn aggregate_frame_results(
         &self,
         metrics: &[Self::FrameResult],
     ) -> Result<Self::VideoResult, Box<dyn Error>> {
-        let cweight = self.cweight.unwrap();
-        let y_sum = metrics.iter().map(|m| m.y).sum::<f64>();
-        let u_sum = metrics.iter().map(|m| m.u).sum::<f64>();
-        let v_sum = metrics.iter().map(|m| m.v).sum::<f64>();
-        Ok(PlanarMetrics {
-            y: log10_convert(y_sum, metrics.len() as f64),
-            u: log10_convert(u_sum, metrics.len() as f64),
-            v: log10_convert(v_sum, metrics.len() as f64),
-            avg: log10_convert(
-                y_sum + cweight * (u_sum + v_sum),
-                (1. + 2. * cweight) * metrics.len() as f64,
-            ),
-        })
+        Ok(planar_ssim(
+            [
+                metrics.iter().map(|m| m.y).sum::<f64>(),
+                metrics.iter().map(|m| m.u).sum::<f64>(),
+                metrics.iter().map(|m| m.v).sum::<f64>(),
+            ],
+            metrics.len() as f64,
+            self.config
+                .weights
+                .with_chroma_weight(self.cweight.unwrap()),
+            self.config.planes(),
+            self.config.scale,
+        ))
     }
 }
 
@@ -304,13 +803,23 @@ struct SsimMoments {
 const SSIM_K1: f64 = 0.01 * 0.01;
 const SSIM_K2: f64 = 0.03 * 0.03;
 
+/// Computes the SSIM of a plane, optionally writing the local SSIM of each
+/// sample to `map`.
 fn calculate_plane_ssim<T: Pixel>(
     plane1: &Plane<T>,
     plane2: &Plane<T>,
     sample_max: u64,
-    vert_kernel: &[i64],
-    horiz_kernel: &[i64],
+    window: SsimWindow,
+    map: Option<&mut [f32]>,
 ) -> f64 {
+    let max_len = cmp::min(plane1.cfg.width, plane1.cfg.height);
+    let kernel = match window {
+        SsimWindow::Adaptive => {
+            build_gaussian_kernel(plane1.cfg.height as f64 * 1.5 / 256.0, max_len, 1 << 8)
+        }
+        // The extra precision keeps all 11 taps of the window.
+        SsimWindow::Standard => build_gaussian_kernel(1.5, cmp::min(6, max_len), 1 << 10),
+    };
     let vec1 = plane_to_vec(plane1);
     let vec2 = plane_to_vec(plane2);
     calculate_plane_ssim_internal(
@@ -319,8 +828,8 @@ fn calculate_plane_ssim<T: Pixel>(
         plane1.cfg.width,
         plane1.cfg.height,
         sample_max,
-        vert_kernel,
-        horiz_kernel,
+        &kernel,
+        map,
     )
     .0
 }
@@ -331,30 +840,29 @@ fn calculate_plane_ssim_internal(
     width: usize,
     height: usize,
     sample_max: u64,
-    vert_kernel: &[i64],
-    horiz_kernel: &[i64],
+    kernel: &[i64],
+    mut map: Option<&mut [f32]>,
 ) -> (f64, f64) {
-    let vert_offset = vert_kernel.len() >> 1;
-    let line_size = vert_kernel.len().next_power_of_two();
+    let offset = kernel.len() >> 1;
+    let line_size = kernel.len().next_power_of_two();
     let line_mask = line_size - 1;
     let mut lines = vec![vec![SsimMoments::default(); width]; line_size];
-    let horiz_offset = horiz_kernel.len() >> 1;
     let mut ssim = 0.0;
     let mut ssimw = 0.0;
     let mut cs = 0.0;
-    for y in 0..(height + vert_offset) {
+    for y in 0..(height + offset) {
         if y < height {
             let buf = &mut lines[y & line_mask];
             let line1 = &plane1[(y * width)..];
             let line2 = &plane2[(y * width)..];
             for x in 0..width {
                 let mut moments = SsimMoments::default();
-                let k_min = horiz_offset.saturating_sub(x);
-                let tmp_offset = (x + horiz_offset + 1).saturating_sub(width);
-                let k_max = horiz_kernel.len() - tmp_offset;
+                let k_min = offset.saturating_sub(x);
+                let tmp_offset = (x + offset + 1).saturating_sub(width);
+                let k_max = kernel.len() - tmp_offset;
                 for k in k_min..k_max {
-                    let window = horiz_kernel[k];
-                    let target_x = (x + k).saturating_sub(horiz_offset);
+                    let window = kernel[k];
+                    let target_x = (x + k).saturating_sub(offset);
                     let pix1 = line1[target_x] as i64;
                     let pix2 = line2[target_x] as i64;
                     moments.mux += window * pix1;
@@ -367,15 +875,15 @@ fn calculate_plane_ssim_internal(
                 buf[x] = moments;
             }
         }
-        if y >= vert_offset {
-            let k_min = vert_kernel.len().saturating_sub(y + 1);
+        if y >= offset {
+            let k_min = kernel.len().saturating_sub(y + 1);
             let tmp_offset = (y + 1).saturating_sub(height);
-            let k_max = vert_kernel.len() - tmp_offset;
+            let k_max = kernel.len() - tmp_offset;
             for x in 0..width {
                 let mut moments = SsimMoments::default();
                 for k in k_min..k_max {
-                    let buf = lines[(y + 1 + k - vert_kernel.len()) & line_mask][x];
-                    let window = vert_kernel[k];
+                    let buf = lines[(y + 1 + k - kernel.len()) & line_mask][x];
+                    let window = kernel[k];
                     moments.mux += window * buf.mux;
                     moments.muy += window * buf.muy;
                     moments.x2 += window * buf.x2;
@@ -383,44 +891,260 @@ fn calculate_plane_ssim_internal(
                     moments.y2 += window * buf.y2;
                     moments.w += window * buf.w;
                 }
-                let w = moments.w as f64;
-                let c1 = sample_max.pow(2) as f64 * SSIM_K1 * w.powi(2);
-                let c2 = sample_max.pow(2) as f64 * SSIM_K2 * w.powi(2);
-                let mx2 = (moments.mux as f64).powi(2);
-                let mxy = moments.mux as f64 * moments.muy as f64;
-                let my2 = (moments.muy as f64).powi(2);
-                let cs_tmp = w * (c2 + 2.0 * (moments.xy as f64 * w - mxy))
-                    / (moments.x2 as f64 * w - mx2 + moments.y2 as f64 * w - my2 + c2);
+                let (ssim_tmp, cs_tmp) = window_ssim(
+                    moments.mux as f64,
+                    moments.muy as f64,
+                    moments.x2 as f64,
+                    moments.xy as f64,
+                    moments.y2 as f64,
+                    moments.w as f64,
+                    sample_max,
+                );
+                if let Some(map) = map.as_deref_mut() {
+                    map[(y - offset) * width + x] = (ssim_tmp / moments.w as f64) as f32;
+                }
                 cs += cs_tmp;
-                ssim += cs_tmp * (2.0 * mxy + c1) / (mx2 + my2 + c1);
-                ssimw += w;
+                ssim += ssim_tmp;
+                ssimw += moments.w as f64;
+            }
+        }
+    }
+
+    (ssim / ssimw, cs / ssimw)
+}
+
+/// Computes the SSIM of a plane the way FFmpeg's `ssim` filter does, which
+/// comes from x264: the sums of the samples are taken over 4x4 blocks, and
+/// each 2x2 group of blocks forms an unweighted 8x8 window.
+fn calculate_plane_ssim_ffmpeg<T: Pixel>(
+    plane1: &Plane<T>,
+    plane2: &Plane<T>,
+    bit_depth: usize,
+) -> f64 {
+    let width = plane1.cfg.width >> 2;
+    let height = plane1.cfg.height >> 2;
+    if width < 2 || height < 2 {
+        return 0.0;
+    }
+
+    let vec1 = plane_to_vec(plane1);
+    let vec2 = plane_to_vec(plane2);
+    let stride = plane1.cfg.width;
+    let block_sums = |bx: usize, by: usize| {
+        let mut sums = [0i64; 4];
+        for y in (by * 4)..(by * 4 + 4) {
+            for x in (bx * 4)..(bx * 4 + 4) {
+                let a = vec1[y * stride + x] as i64;
+                let b = vec2[y * stride + x] as i64;
+                sums[0] += a;
+                sums[1] += b;
+                sums[2] += a * a + b * b;
+                sums[3] += a * b;
             }
         }
+        sums
+    };
+
+    let max = ((1u64 << bit_depth) - 1) as f64;
+    let c1 = (0.01 * 0.01 * max * max * 64.0 + 0.5) as i64;
+    let c2 = (0.03 * 0.03 * max * max * 64.0 * 63.0 + 0.5) as i64;
+    let window_ssim = |s: [i64; 4]| {
+        let vars = s[2] * 64 - s[0] * s[0] - s[1] * s[1];
+        let covar = s[3] * 64 - s[0] * s[1];
+        let num = (2 * s[0] * s[1] + c1, 2 * covar + c2);
+        let den = (s[0] * s[0] + s[1] * s[1] + c1, vars + c2);
+        (num, den)
+    };
+
+    let mut prev: Vec<[i64; 4]> = (0..width).map(|bx| block_sums(bx, 0)).collect();
+    let mut ssim = 0.0;
+    for by in 1..height {
+        let cur: Vec<[i64; 4]> = (0..width).map(|bx| block_sums(bx, by)).collect();
+        let windows = (0..(width - 1)).map(|x| {
+            let mut s = [0i64; 4];
+            for blocks in [prev[x], prev[x + 1], cur[x], cur[x + 1]] {
+                for (sum, block) in s.iter_mut().zip(blocks) {
+                    *sum += block;
+                }
+            }
+            window_ssim(s)
+        });
+        // FFmpeg sums the windows of a row in single precision for 8-bit
+        // inputs and in double precision otherwise.
+        ssim += if bit_depth == 8 {
+            windows
+                .map(|(num, den)| num.0 as f32 * num.1 as f32 / (den.0 as f32 * den.1 as f32))
+                .fold(0.0f32, |acc, v| acc + v) as f64
+        } else {
+            windows
+                .map(|(num, den)| num.0 as f64 * num.1 as f64 / (den.0 as f64 * den.1 as f64))
+                .sum::<f64>()
+        };
+        prev = cur;
+    }
+
+    ssim / ((width - 1) * (height - 1)) as f64
+}
+
+/// Computes the SSIM and contrast-structure terms of one window from its
+/// weighted moments, both scaled by the total weight `w` of the window.
+#[inline(always)]
+fn window_ssim(
+    mux: f64,
+    muy: f64,
+    x2: f64,
+    xy: f64,
+    y2: f64,
+    w: f64,
+    sample_max: u64,
+) -> (f64, f64) {
+    let c1 = sample_max.pow(2) as f64 * SSIM_K1 * w.powi(2);
+    let c2 = sample_max.pow(2) as f64 * SSIM_K2 * w.powi(2);
+    let mx2 = mux.powi(2);
+    let mxy = mux * muy;
+    let my2 = muy.powi(2);
+    let cs = w * (c2 + 2.0 * (xy * w - mxy)) / (x2 * w - mx2 + y2 * w - my2 + c2);
+    (cs * (2.0 * mxy + c1) / (mx2 + my2 + c1), cs)
+}
+
+/// Running sums of the samples of two planes and their products.
+/// Wrapping arithmetic is used throughout: the sums over a window are
+/// recovered by differences of the table, which are exact even if the
+/// table itself overflows.
+#[derive(Debug, Clone, Copy, Default)]
+struct BoxSums {
+    x: u64,
+    y: u64,
+    x2: u64,
+    xy: u64,
+    y2: u64,
+}
+
+impl BoxSums {
+    #[inline(always)]
+    fn add(self, other: Self) -> Self {
+        BoxSums {
+            x: self.x.wrapping_add(other.x),
+            y: self.y.wrapping_add(other.y),
+            x2: self.x2.wrapping_add(other.x2),
+            xy: self.xy.wrapping_add(other.xy),
+            y2: self.y2.wrapping_add(other.y2),
+        }
+    }
+
+    #[inline(always)]
+    fn sub(self, other: Self) -> Self {
+        BoxSums {
+            x: self.x.wrapping_sub(other.x),
+            y: self.y.wrapping_sub(other.y),
+            x2: self.x2.wrapping_sub(other.x2),
+            xy: self.xy.wrapping_sub(other.xy),
+            y2: self.y2.wrapping_sub(other.y2),
+        }
+    }
+}
+
+/// Equivalent to `calculate_plane_ssim_internal` with a square window
+/// of `2 * radius + 1` samples, using summed-area tables so that the cost
+/// does not depend on the window size.
+fn calculate_plane_ssim_box(
+    plane1: &[u32],
+    plane2: &[u32],
+    width: usize,
+    height: usize,
+    sample_max: u64,
+    radius: usize,
+) -> (f64, f64) {
+    let stride = width + 1;
+    let mut table = vec![BoxSums::default(); stride * (height + 1)];
+    for y in 0..height {
+        let mut row = BoxSums::default();
+        for x in 0..width {
+            let pix1 = plane1[y * width + x] as u64;
+            let pix2 = plane2[y * width + x] as u64;
+            row = row.add(BoxSums {
+                x: pix1,
+                y: pix2,
+                x2: pix1 * pix1,
+                xy: pix1 * pix2,
+                y2: pix2 * pix2,
+            });
+            table[(y + 1) * stride + x + 1] = table[y * stride + x + 1].add(row);
+        }
+    }
+
+    let mut ssim = 0.0;
+    let mut ssimw = 0.0;
+    let mut cs = 0.0;
+    for y in 0..height {
+        let y0 = y.saturating_sub(radius);
+        let y1 = cmp::min(y + radius + 1, height);
+        for x in 0..width {
+            let x0 = x.saturating_sub(radius);
+            let x1 = cmp::min(x + radius + 1, width);
+            let sums = table[y1 * stride + x1]
+                .sub(table[y0 * stride + x1])
+                .sub(table[y1 * stride + x0])
+                .add(table[y0 * stride + x0]);
+            let w = ((y1 - y0) * (x1 - x0)) as f64;
+            let (ssim_tmp, cs_tmp) = window_ssim(
+                sums.x as f64,
+                sums.y as f64,
+                sums.x2 as f64,
+                sums.xy as f64,
+                sums.y2 as f64,
+                w,
+                sample_max,
+            );
+            cs += cs_tmp;
+            ssim += ssim_tmp;
+            ssimw += w;
+        }
     }
 
     (ssim / ssimw, cs / ssimw)
 }
 
-fn calculate_plane_msssim<T: Pixel>(plane1: &Plane<T>, plane2: &Plane<T>, bit_depth: usize) -> f64 {
+fn calculate_plane_msssim<T: Pixel>(
+    plane1: &Plane<T>,
+    plane2: &Plane<T>,
+    bit_depth: usize,
+    window: MsSsimWindow,
+) -> f64 {
     const KERNEL_SHIFT: usize = 10;
+    const BOX_RADIUS: usize = 3;
     const KERNEL_WEIGHT: usize = 1 << KERNEL_SHIFT;
     // These come from the original MS-SSIM implementation paper:
     // https://ece.uwaterloo.ca/~z70wang/publications/msssim.pdf
     // They don't add up to 1 due to rounding done in the paper.
     const MS_WEIGHT: [f64; 5] = [0.0448, 0.2856, 0.3001, 0.2363, 0.1333];
 
-    let mut sample_max = (1 << bit_depth) - 1;
+    // Each downscale gains two bits of precision, so the moments of the
+    // smallest scale would overflow an i64 for inputs deeper than 12 bits.
+    // Those are reduced to 12 bits, which does not meaningfully change the score.
+    let shift = bit_depth.saturating_sub(12);
+    let mut sample_max = (1 << (bit_depth - shift)) - 1;
     let mut ssim = [0.0; 5];
     let mut cs = [0.0; 5];
     let mut width = plane1.cfg.width;
     let mut height = plane1.cfg.height;
     let mut plane1 = plane_to_vec(plane1);
     let mut plane2 = plane_to_vec(plane2);
+    if shift > 0 {
+        plane1.iter_mut().for_each(|pix| *pix >>= shift);
+        plane2.iter_mut().for_each(|pix| *pix >>= shift);
+    }
 
     let kernel = build_gaussian_kernel(1.5, 5, KERNEL_WEIGHT);
-    let res = calculate_plane_ssim_internal(
-        &plane1, &plane2, width, height, sample_max, &kernel, &kernel,
-    );
+    let plane_ssim = |plane1: &[u32], plane2: &[u32], width, height, sample_max| match window {
+        MsSsimWindow::Gaussian => {
+            calculate_plane_ssim_internal(plane1, plane2, width, height, sample_max, &kernel, None)
+        }
+        MsSsimWindow::Box => {
+            calculate_plane_ssim_box(plane1, plane2, width, height, sample_max, BOX_RADIUS)
+        }
+    };
+    let res = plane_ssim(&plane1, &plane2, width, height, sample_max);
     ssim[0] = res.0;
     cs[0] = res.1;
     for i in 1..5 {
@@ -429,9 +1153,7 @@ fn calculate_plane_msssim<T: Pixel>(plane1: &Plane<T>, plane2: &Plane<T>, bit_de
         width /= 2;
         height /= 2;
         sample_max *= 4;
-        let res = calculate_plane_ssim_internal(
-            &plane1, &plane2, width, height, sample_max, &kernel, &kernel,
-        );
+        let res = plane_ssim(&plane1, &plane2, width, height, sample_max);
         ssim[i] = res.0;
         cs[i] = res.1;
     }
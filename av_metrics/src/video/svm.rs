@@ -0,0 +1,82 @@
+//! Support vector regression, for the models of metrics trained on subjective
+//! scores.
+
+use crate::MetricsError;
+
+/// A support vector regression with an RBF kernel, in the format of libsvm.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Svm {
+    gamma: f64,
+    rho: f64,
+    /// The coefficient of each support vector and the vector itself.
+    support_vectors: Vec<(f64, Vec<f64>)>,
+}
+
+impl Svm {
+    /// Parses a model saved by libsvm, with `dimensions` features.
+    pub(crate) fn parse(text: &str, dimensions: usize) -> Result<Self, MetricsError> {
+        let invalid = || MetricsError::MalformedInput {
+            reason: "Invalid support vector model",
+        };
+        let mut lines = text.lines();
+        let mut gamma = None;
+        let mut rho = None;
+        for line in lines.by_ref() {
+            let (key, value) = line.split_once(' ').unwrap_or((line.trim(), ""));
+            match key {
+                "SV" => break,
+                "kernel_type" if value != "rbf" => {
+                    return Err(MetricsError::UnsupportedInput {
+                        reason: "Only RBF kernels are supported in support vector models",
+                    })
+                }
+                "gamma" => gamma = value.parse().ok(),
+                "rho" => rho = value.parse().ok(),
+                _ => {}
+            }
+        }
+
+        let support_vectors = lines
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                let mut fields = line.split_whitespace();
+                let coeff: f64 = fields
+                    .next()
+                    .and_then(|c| c.parse().ok())
+                    .ok_or_else(invalid)?;
+                let mut vector = vec![0.0; dimensions];
+                for field in fields {
+                    let (index, value) = field.split_once(':').ok_or_else(invalid)?;
+                    let index: usize = index.parse().map_err(|_| invalid())?;
+                    let value = value.parse().map_err(|_| invalid())?;
+                    *index
+                        .checked_sub(1)
+                        .and_then(|index| vector.get_mut(index))
+                        .ok_or_else(invalid)? = value;
+                }
+                Ok((coeff, vector))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Svm {
+            gamma: gamma.ok_or_else(invalid)?,
+            rho: rho.ok_or_else(invalid)?,
+            support_vectors,
+        })
+    }
+
+    pub(crate) fn predict(&self, input: &[f64]) -> f64 {
+        self.support_vectors
+            .iter()
+            .map(|(coeff, vector)| {
+                let dist_sq: f64 = vector
+                    .iter()
+                    .zip(input)
+                    .map(|(a, b)| (a - b) * (a - b))
+                    .sum();
+                coeff * (-self.gamma * dist_sq).exp()
+            })
+            .sum::<f64>()
+            - self.rho
+    }
+}
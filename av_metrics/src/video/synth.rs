@@ -0,0 +1,253 @@
+//! Generators for synthetic test frames.
+//!
+//! These produce reference frames programmatically, which is useful for
+//! validating metric implementations without relying on large video fixtures.
+//! All generated frames use limited range BT.709 levels and no padding.
+
+use crate::video::pixel::CastFromPrimitive;
+use crate::video::pixel::Pixel;
+use crate::MetricsError;
+use std::error::Error;
+use std::f64::consts::PI;
+use std::mem::size_of;
+use v_frame::frame::Frame;
+use v_frame::plane::Plane;
+use v_frame::prelude::ChromaSampling;
+
+/// Generates a frame with a horizontal luma ramp from black to white.
+///
+/// The U plane ramps vertically and the V plane ramps diagonally,
+/// so that every plane contains detail.
+pub fn gradient<T: Pixel>(
+    width: usize,
+    height: usize,
+    bit_depth: usize,
+    chroma_sampling: ChromaSampling,
+) -> Result<Frame<T>, Box<dyn Error>> {
+    let mut frame = new_frame(width, height, bit_depth, chroma_sampling)?;
+    let (y_min, y_max) = luma_range(bit_depth);
+    let (c_min, c_max) = chroma_range(bit_depth);
+    fill_plane(&mut frame.planes[0], |x, _, w, _| {
+        lerp(y_min, y_max, x as f64 / (w.max(2) - 1) as f64)
+    });
+    fill_plane(&mut frame.planes[1], |_, y, _, h| {
+        lerp(c_min, c_max, y as f64 / (h.max(2) - 1) as f64)
+    });
+    fill_plane(&mut frame.planes[2], |x, y, w, h| {
+        lerp(
+            c_min,
+            c_max,
+            (x + y) as f64 / (w + h).max(3).saturating_sub(2) as f64,
+        )
+    });
+    Ok(frame)
+}
+
+/// Generates a circular zone plate in the luma plane, with neutral chroma.
+///
+/// The spatial frequency increases from zero at the center of the frame
+/// to the Nyquist limit at the left and right edges, which makes it useful
+/// for checking how a metric responds to detail at every frequency.
+pub fn zone_plate<T: Pixel>(
+    width: usize,
+    height: usize,
+    bit_depth: usize,
+    chroma_sampling: ChromaSampling,
+) -> Result<Frame<T>, Box<dyn Error>> {
+    let mut frame = new_frame(width, height, bit_depth, chroma_sampling)?;
+    let (y_min, y_max) = luma_range(bit_depth);
+    let (c_min, c_max) = chroma_range(bit_depth);
+    fill_plane(&mut frame.planes[0], |x, y, w, h| {
+        let dx = x as f64 - w as f64 / 2.0;
+        let dy = y as f64 - h as f64 / 2.0;
+        let phase = PI * (dx * dx + dy * dy) / w as f64;
+        lerp(y_min, y_max, 0.5 + 0.5 * phase.cos())
+    });
+    let neutral = (c_min + c_max) / 2.0;
+    fill_plane(&mut frame.planes[1], |_, _, _, _| neutral);
+    fill_plane(&mut frame.planes[2], |_, _, _, _| neutral);
+    Ok(frame)
+}
+
+/// Generates 75% color bars: white, yellow, cyan, green, magenta, red, blue and black.
+pub fn color_bars<T: Pixel>(
+    width: usize,
+    height: usize,
+    bit_depth: usize,
+    chroma_sampling: ChromaSampling,
+) -> Result<Frame<T>, Box<dyn Error>> {
+    const BARS: [[f64; 3]; 8] = [
+        [0.75, 0.75, 0.75],
+        [0.75, 0.75, 0.0],
+        [0.0, 0.75, 0.75],
+        [0.0, 0.75, 0.0],
+        [0.75, 0.0, 0.75],
+        [0.75, 0.0, 0.0],
+        [0.0, 0.0, 0.75],
+        [0.0, 0.0, 0.0],
+    ];
+
+    let mut frame = new_frame(width, height, bit_depth, chroma_sampling)?;
+    let scale = (1 << (bit_depth - 8)) as f64;
+    let bars = BARS.map(|[r, g, b]| {
+        // BT.709 coefficients
+        let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+        let u = (b - y) / 1.8556;
+        let v = (r - y) / 1.5748;
+        [
+            (16.0 + 219.0 * y) * scale,
+            (128.0 + 224.0 * u) * scale,
+            (128.0 + 224.0 * v) * scale,
+        ]
+    });
+    for (idx, plane) in frame.planes.iter_mut().enumerate() {
+        fill_plane(plane, |x, _, w, _| bars[x * BARS.len() / w][idx]);
+    }
+    Ok(frame)
+}
+
+/// Returns a copy of `frame` with Gaussian noise added to every plane,
+/// scaled so that the PSNR of each plane against the original is
+/// approximately `target_psnr`.
+///
+/// The noise is generated from `seed`, so the same inputs always
+/// produce the same output.
+pub fn add_noise<T: Pixel>(
+    frame: &Frame<T>,
+    bit_depth: usize,
+    target_psnr: f64,
+    seed: u64,
+) -> Result<Frame<T>, Box<dyn Error>> {
+    check_bit_depth::<T>(bit_depth)?;
+    if !target_psnr.is_finite() || target_psnr <= 0.0 {
+        return Err(Box::new(MetricsError::UnsupportedInput {
+            reason: "Target PSNR must be a positive finite number",
+        }));
+    }
+
+    let sample_max = ((1 << bit_depth) - 1) as f64;
+    let target_mse = sample_max.powi(2) / 10f64.powf(target_psnr / 10.0);
+    let mut rng = XorShift::new(seed);
+    let mut output = frame.clone();
+    for (src, dst) in frame.planes.iter().zip(output.planes.iter_mut()) {
+        let width = src.cfg.width;
+        let height = src.cfg.height;
+        let stride = src.cfg.stride;
+        if width == 0 || height == 0 {
+            continue;
+        }
+        let noise: Vec<f64> = (0..width * height).map(|_| rng.next_gaussian()).collect();
+        let apply = |dst: &mut Plane<T>, sigma: f64| -> f64 {
+            let mut sq_err = 0.0;
+            for y in 0..height {
+                for x in 0..width {
+                    let orig = u32::cast_from(src.data[y * stride + x]) as f64;
+                    let noisy = (orig + sigma * noise[y * width + x])
+                        .round()
+                        .clamp(0.0, sample_max);
+                    dst.data[y * stride + x] = T::cast_from(noisy as u32);
+                    sq_err += (noisy - orig).powi(2);
+                }
+            }
+            sq_err / (width * height) as f64
+        };
+
+        // Rounding and clipping mean the noise strength has to be refined
+        // a few times to land close to the requested error.
+        let mut sigma = target_mse.sqrt();
+        for _ in 0..4 {
+            let mse = apply(dst, sigma);
+            if mse <= f64::EPSILON {
+                break;
+            }
+            sigma *= (target_mse / mse).sqrt();
+        }
+        apply(dst, sigma);
+    }
+    Ok(output)
+}
+
+fn check_bit_depth<T: Pixel>(bit_depth: usize) -> Result<(), MetricsError> {
+    if (size_of::<T>() == 1 && bit_depth != 8)
+        || (size_of::<T>() == 2 && !(9..=16).contains(&bit_depth))
+    {
+        return Err(MetricsError::InputMismatch {
+            reason: "Bit depths does not match pixel width",
+        });
+    }
+    Ok(())
+}
+
+fn new_frame<T: Pixel>(
+    width: usize,
+    height: usize,
+    bit_depth: usize,
+    chroma_sampling: ChromaSampling,
+) -> Result<Frame<T>, MetricsError> {
+    check_bit_depth::<T>(bit_depth)?;
+    if width == 0 || height == 0 {
+        return Err(MetricsError::UnsupportedInput {
+            reason: "Frame dimensions must be non-zero",
+        });
+    }
+    Ok(Frame::new_with_padding(width, height, chroma_sampling, 0))
+}
+
+fn luma_range(bit_depth: usize) -> (f64, f64) {
+    let scale = (1 << (bit_depth - 8)) as f64;
+    (16.0 * scale, 235.0 * scale)
+}
+
+fn chroma_range(bit_depth: usize) -> (f64, f64) {
+    let scale = (1 << (bit_depth - 8)) as f64;
+    (16.0 * scale, 240.0 * scale)
+}
+
+fn lerp(min: f64, max: f64, t: f64) -> f64 {
+    min + (max - min) * t.clamp(0.0, 1.0)
+}
+
+/// Fills the visible area of a plane. The closure receives the sample
+/// position followed by the plane's width and height.
+fn fill_plane<T: Pixel, F: Fn(usize, usize, usize, usize) -> f64>(plane: &mut Plane<T>, f: F) {
+    let width = plane.cfg.width;
+    let height = plane.cfg.height;
+    let stride = plane.cfg.stride;
+    for y in 0..height {
+        for x in 0..width {
+            plane.data[y * stride + x] = T::cast_from(f(x, y, width, height).round() as u32);
+        }
+    }
+}
+
+/// A small deterministic generator, so that synthetic frames are
+/// reproducible without pulling in a dependency.
+pub(crate) struct XorShift(u64);
+
+impl XorShift {
+    pub(crate) fn new(seed: u64) -> Self {
+        // xorshift cannot leave the all zero state
+        XorShift(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.0 = x;
+        x
+    }
+
+    /// Uniformly distributed in `(0, 1]`.
+    fn next_f64(&mut self) -> f64 {
+        ((self.next_u64() >> 11) + 1) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Standard normal distribution, using the Box-Muller transform.
+    fn next_gaussian(&mut self) -> f64 {
+        let u1 = self.next_f64();
+        let u2 = self.next_f64();
+        (-2.0 * u1.ln()).sqrt() * (2.0 * PI * u2).cos()
+    }
+}
@@ -0,0 +1,206 @@
+//! Temporal consistency metrics.
+//!
+//! Unlike the other metrics, these compare each frame to the previous frame of
+//! the same video, so frames are processed in order instead of independently.
+//!
+//! Spatial and temporal information (SI and TI) are computed on the luma plane
+//! as described in ITU-T P.910, on a scale of 8-bit samples regardless of the
+//! bit depth of the input. Flicker compares the temporal information of the
+//! distorted video to that of the reference, frame by frame.
+
+use crate::video::decode::{Decoder, PlaneRef};
+use crate::video::pixel::CastFromPrimitive;
+use crate::video::pixel::Pixel;
+use crate::video::{FrameCompare, FrameContext};
+use crate::{MetricsError, PROGRESS_DONE};
+use std::error::Error;
+use v_frame::frame::Frame;
+use v_frame::plane::Plane;
+
+/// Spatial and temporal information of a video, as defined in ITU-T P.910.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SiTi {
+    /// Spatial information: the maximum over all frames of the standard
+    /// deviation of the Sobel-filtered luma plane.
+    pub si: f64,
+    /// Temporal information: the maximum over all frames of the standard
+    /// deviation of the difference between a luma plane and the previous one.
+    pub ti: f64,
+}
+
+/// The temporal metrics of a distorted video compared to a reference.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TemporalMetrics {
+    /// Spatial and temporal information of the reference video.
+    pub reference: SiTi,
+    /// Spatial and temporal information of the distorted video.
+    pub distorted: SiTi,
+    /// The mean absolute difference between the temporal information of the
+    /// two videos at each frame. Lower is better; flickering or frozen frames
+    /// in the distorted video increase it.
+    pub flicker: f64,
+}
+
+/// Calculates the temporal metrics of two videos.
+///
+/// At least two frames are needed to measure temporal information.
+pub fn calculate_video_temporal<D: Decoder, F: Fn(usize) + Send>(
+    decoder1: &mut D,
+    decoder2: &mut D,
+    frame_limit: Option<usize>,
+    progress_callback: F,
+) -> Result<TemporalMetrics, Box<dyn Error>> {
+    if decoder1.get_bit_depth() != decoder2.get_bit_depth() {
+        return Err(Box::new(MetricsError::InputMismatch {
+            reason: "Bit depths do not match",
+        }));
+    }
+    if decoder1.get_video_details().chroma_sampling != decoder2.get_video_details().chroma_sampling
+    {
+        return Err(Box::new(MetricsError::InputMismatch {
+            reason: "Chroma samplings do not match",
+        }));
+    }
+
+    if decoder1.get_bit_depth() > 8 {
+        process_video::<D, u16, F>(decoder1, decoder2, frame_limit, progress_callback)
+    } else {
+        process_video::<D, u8, F>(decoder1, decoder2, frame_limit, progress_callback)
+    }
+}
+
+/// Calculates the spatial information of one frame: the standard deviation
+/// of its Sobel-filtered luma plane.
+pub fn calculate_frame_si<T: Pixel>(frame: &Frame<T>, bit_depth: usize) -> f64 {
+    let plane = PlaneRef::from(&frame.planes[0]);
+    let (width, height) = (plane.width(), plane.height());
+    if width < 3 || height < 3 {
+        return 0.0;
+    }
+    let px = |x: usize, y: usize| f64::from(i32::cast_from(plane.row(y)[x]));
+    let mut stats = Stats::default();
+    for y in 1..height - 1 {
+        for x in 1..width - 1 {
+            let gx = px(x + 1, y - 1) + 2.0 * px(x + 1, y) + px(x + 1, y + 1)
+                - px(x - 1, y - 1)
+                - 2.0 * px(x - 1, y)
+                - px(x - 1, y + 1);
+            let gy = px(x - 1, y + 1) + 2.0 * px(x, y + 1) + px(x + 1, y + 1)
+                - px(x - 1, y - 1)
+                - 2.0 * px(x, y - 1)
+                - px(x + 1, y - 1);
+            stats.add(gx.hypot(gy));
+        }
+    }
+    stats.std_dev() / sample_scale(bit_depth)
+}
+
+/// Calculates the temporal information between two consecutive frames: the
+/// standard deviation of the difference between their luma planes.
+pub fn calculate_frame_ti<T: Pixel>(
+    previous: &Frame<T>,
+    current: &Frame<T>,
+    bit_depth: usize,
+) -> Result<f64, Box<dyn Error>> {
+    previous.can_compare(current)?;
+    Ok(frame_ti(&previous.planes[0], &current.planes[0], bit_depth))
+}
+
+fn frame_ti<T: Pixel>(previous: &Plane<T>, current: &Plane<T>, bit_depth: usize) -> f64 {
+    let mut stats = Stats::default();
+    let rows1 = PlaneRef::from(previous).rows();
+    let rows2 = PlaneRef::from(current).rows();
+    for (row1, row2) in rows1.zip(rows2) {
+        for (&a, &b) in row1.iter().zip(row2.iter()) {
+            stats.add(f64::from(i32::cast_from(b) - i32::cast_from(a)));
+        }
+    }
+    stats.std_dev() / sample_scale(bit_depth)
+}
+
+/// The size of one 8-bit step in samples of the given bit depth.
+fn sample_scale(bit_depth: usize) -> f64 {
+    (1 << (bit_depth - 8)) as f64
+}
+
+fn process_video<D: Decoder, P: Pixel, F: Fn(usize) + Send>(
+    decoder1: &mut D,
+    decoder2: &mut D,
+    frame_limit: Option<usize>,
+    progress_callback: F,
+) -> Result<TemporalMetrics, Box<dyn Error>> {
+    let details = decoder1.get_video_details();
+    let context = FrameContext::try_from(&details)?;
+    let bit_depth = details.bit_depth;
+
+    let mut result = TemporalMetrics::default();
+    let mut previous: Option<(Frame<P>, Frame<P>)> = None;
+    let mut flicker_sum = 0.0;
+    let mut decoded = 0;
+    while frame_limit.map(|limit| limit > decoded).unwrap_or(true) {
+        let (frame1, frame2) = match (
+            decoder1.read_video_frame::<P>(),
+            decoder2.read_video_frame::<P>(),
+        ) {
+            (Some(frame1), Some(frame2)) => (frame1, frame2),
+            _ => break,
+        };
+        decoded += 1;
+        progress_callback(decoded);
+        context.validate(&frame1, &frame2)?;
+
+        result.reference.si = result
+            .reference
+            .si
+            .max(calculate_frame_si(&frame1, bit_depth));
+        result.distorted.si = result
+            .distorted
+            .si
+            .max(calculate_frame_si(&frame2, bit_depth));
+        if let Some((prev1, prev2)) = previous.as_ref() {
+            let ti1 = frame_ti(&prev1.planes[0], &frame1.planes[0], bit_depth);
+            let ti2 = frame_ti(&prev2.planes[0], &frame2.planes[0], bit_depth);
+            result.reference.ti = result.reference.ti.max(ti1);
+            result.distorted.ti = result.distorted.ti.max(ti2);
+            flicker_sum += (ti2 - ti1).abs();
+        }
+        previous = Some((frame1, frame2));
+    }
+    progress_callback(PROGRESS_DONE);
+
+    if decoded < 2 {
+        return Err(MetricsError::UnsupportedInput {
+            reason: "Temporal metrics need at least two frames",
+        }
+        .into());
+    }
+    result.flicker = flicker_sum / (decoded - 1) as f64;
+    Ok(result)
+}
+
+/// Running mean and variance, using Welford's algorithm.
+#[derive(Default)]
+struct Stats {
+    n: u64,
+    mean: f64,
+    m2: f64,
+}
+
+impl Stats {
+    fn add(&mut self, value: f64) {
+        self.n += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.n as f64;
+        self.m2 += delta * (value - self.mean);
+    }
+
+    fn std_dev(&self) -> f64 {
+        if self.n == 0 {
+            0.0
+        } else {
+            (self.m2 / self.n as f64).sqrt()
+        }
+    }
+}
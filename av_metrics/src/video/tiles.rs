@@ -0,0 +1,364 @@
+//! Scores of each tile of a grid over the frames.
+//!
+//! Some quality problems stay in the same place for the whole video, such as
+//! a corrupted corner or a logo that the encoder smears. The average over the
+//! frames hides them, while a heatmap of every frame is too much to look at.
+//! Dividing the frames into a grid of tiles and scoring each tile over the
+//! whole video shows where such problems are:
+//!
+//! ```no_run
+//! # use av_metrics::video::decode::Decoder;
+//! # use av_metrics::video::tiles::{calculate_video_psnr_tiles, TileGrid};
+//! # use std::error::Error;
+//! # fn worst_tile<D: Decoder>(dec1: &mut D, dec2: &mut D) -> Result<(), Box<dyn Error>> {
+//! let tiles = calculate_video_psnr_tiles(dec1, dec2, TileGrid::new(8, 8), None, |_| ())?;
+//! let worst = tiles
+//!     .iter()
+//!     .min_by(|a, b| a.psnr.avg.total_cmp(&b.psnr.avg))
+//!     .unwrap();
+//! println!("Worst tile at {}x{}: {:.2} dB", worst.x, worst.y, worst.psnr.avg);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::video::decode::{Decoder, PlaneRef};
+use crate::video::pixel::Pixel;
+use crate::video::psnr::{calculate_plane_psnr_metrics, calculate_summed_psnr, PsnrMetrics};
+use crate::video::{CompatMode, FrameContext, PlanarMetrics, VideoMetric, VideoOptions};
+use crate::MetricsError;
+use std::error::Error;
+use std::ops::Range;
+use std::sync::Mutex;
+use v_frame::frame::Frame;
+use v_frame::plane::Plane;
+use v_frame::prelude::ChromaSampling;
+
+/// The number of columns and rows of tiles the frames are divided into.
+///
+/// Tiles in a row have the same width, give or take a pixel, and so do tiles
+/// in a column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TileGrid {
+    /// The number of tiles in each row.
+    pub columns: usize,
+    /// The number of rows of tiles.
+    pub rows: usize,
+}
+
+impl TileGrid {
+    /// A grid of `columns` by `rows` tiles.
+    pub fn new(columns: usize, rows: usize) -> Self {
+        TileGrid { columns, rows }
+    }
+}
+
+impl Default for TileGrid {
+    /// A grid of 8 by 8 tiles.
+    fn default() -> Self {
+        TileGrid::new(8, 8)
+    }
+}
+
+/// The scores of one tile over all frames.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TileScores {
+    /// The column of the tile in the grid, from the left.
+    pub column: usize,
+    /// The row of the tile in the grid, from the top.
+    pub row: usize,
+    /// The horizontal position of the tile, in luma pixels.
+    pub x: usize,
+    /// The vertical position of the tile, in luma pixels.
+    pub y: usize,
+    /// The width of the tile, in luma pixels.
+    pub width: usize,
+    /// The height of the tile, in luma pixels.
+    pub height: usize,
+    /// The PSNR of the tile over all frames.
+    pub psnr: PlanarMetrics,
+    /// The lowest PSNR of the tile in a single frame, averaged over the planes.
+    pub min: f64,
+    /// The number of the frame with the lowest PSNR.
+    pub min_frame: usize,
+}
+
+/// Calculates the PSNR of each tile of `grid` over two videos. Higher is
+/// better.
+///
+/// The tiles are returned row by row. Like [`calculate_video_psnr`], PSNR is
+/// capped at 100.
+///
+/// [`calculate_video_psnr`]: crate::video::psnr::calculate_video_psnr
+#[inline]
+pub fn calculate_video_psnr_tiles<D: Decoder, F: Fn(usize) + Send>(
+    decoder1: &mut D,
+    decoder2: &mut D,
+    grid: TileGrid,
+    frame_limit: Option<usize>,
+    progress_callback: F,
+) -> Result<Vec<TileScores>, Box<dyn Error>> {
+    calculate_video_psnr_tiles_with(
+        decoder1,
+        decoder2,
+        TilesOptions {
+            video: VideoOptions::limited(frame_limit, progress_callback),
+            config: grid,
+        },
+    )
+}
+
+/// Calculates the PSNR of each tile over two videos with the given options.
+/// Higher is better.
+///
+/// See [`TilesOptions`] for the available options.
+#[inline]
+pub fn calculate_video_psnr_tiles_with<D: Decoder, F: Fn(usize) + Send>(
+    decoder1: &mut D,
+    decoder2: &mut D,
+    options: TilesOptions<F>,
+) -> Result<Vec<TileScores>, Box<dyn Error>> {
+    let grid = options.config;
+    options.video.run(|limits, progress_callback| {
+        PsnrTiles { grid }.process_video(decoder1, decoder2, limits, progress_callback)
+    })
+}
+
+/// Calculates the PSNR of each tile of `grid` for two video frames. Higher
+/// is better.
+#[inline]
+pub fn calculate_frame_psnr_tiles<T: Pixel>(
+    frame1: &Frame<T>,
+    frame2: &Frame<T>,
+    bit_depth: usize,
+    chroma_sampling: ChromaSampling,
+    grid: TileGrid,
+) -> Result<Vec<TileScores>, Box<dyn Error>> {
+    let metric = PsnrTiles { grid };
+    let metrics = metric.process_frame(frame1, frame2, bit_depth, chroma_sampling)?;
+    metric.aggregate_frame_results(&[metrics])
+}
+
+/// Scores the tiles of frame pairs given one at a time, such as those given to
+/// a [`FrameSink`], so that the tiles are scored in the same pass as another
+/// metric instead of decoding the videos again.
+///
+/// The frame pairs may be pushed from several threads and in any order.
+///
+/// [`FrameSink`]: crate::video::FrameSink
+#[derive(Debug)]
+pub struct TileAggregator {
+    metric: PsnrTiles,
+    frames: Mutex<Vec<(usize, FrameTiles)>>,
+}
+
+impl TileAggregator {
+    /// Creates an aggregator with no frames, dividing them into `grid`.
+    pub fn new(grid: TileGrid) -> Self {
+        TileAggregator {
+            metric: PsnrTiles { grid },
+            frames: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Scores the tiles of frame pair number `frame`.
+    pub fn push<T: Pixel>(
+        &self,
+        frame: usize,
+        frame1: &Frame<T>,
+        frame2: &Frame<T>,
+        context: FrameContext,
+    ) -> Result<(), Box<dyn Error>> {
+        let tiles = self.metric.process_frame(
+            frame1,
+            frame2,
+            context.bit_depth(),
+            context.chroma_sampling(),
+        )?;
+        self.frames.lock().unwrap().push((frame, tiles));
+        Ok(())
+    }
+
+    /// The scores of each tile over the frames pushed so far, row by row.
+    pub fn finish(self) -> Result<Vec<TileScores>, Box<dyn Error>> {
+        let mut frames = self.frames.into_inner().unwrap();
+        frames.sort_by_key(|&(frame, _)| frame);
+        let frames: Vec<_> = frames.into_iter().map(|(_, tiles)| tiles).collect();
+        self.metric.aggregate_frame_results(&frames)
+    }
+}
+
+/// Options for computing the scores of tiles over whole videos, built with
+/// chained calls:
+///
+/// ```
+/// use av_metrics::video::tiles::TilesOptions;
+///
+/// let options = TilesOptions::new().frame_limit(100).grid(4, 4);
+/// ```
+#[derive(Debug, Clone)]
+pub struct TilesOptions<F = fn(usize)> {
+    /// The frame limit, thread count and progress callback.
+    pub video: VideoOptions<F>,
+    /// The grid the frames are divided into.
+    pub config: TileGrid,
+}
+
+video_options!(TilesOptions);
+
+impl<F> TilesOptions<F> {
+    /// Divides the frames into `columns` by `rows` tiles.
+    pub fn grid(mut self, columns: usize, rows: usize) -> Self {
+        self.config = TileGrid::new(columns, rows);
+        self
+    }
+}
+
+/// The range of samples covered by tile `index` of `count` tiles over `len`
+/// samples.
+fn span(index: usize, count: usize, len: usize) -> Range<usize> {
+    index * len / count..(index + 1) * len / count
+}
+
+/// A view of the samples of `plane` in the given ranges, which may be empty
+/// in subsampled chroma planes.
+pub(crate) fn region<T: Pixel>(
+    plane: &Plane<T>,
+    x: Range<usize>,
+    y: Range<usize>,
+) -> PlaneRef<'_, T> {
+    let stride = plane.cfg.stride;
+    let data = plane
+        .data_origin()
+        .get(y.start * stride + x.start..)
+        .unwrap_or(&[]);
+    PlaneRef::new(data, stride, x.len(), y.len()).unwrap()
+}
+
+#[derive(Debug)]
+struct PsnrTiles {
+    grid: TileGrid,
+}
+
+/// The squared errors of each tile of one frame, row by row.
+#[derive(Debug)]
+struct FrameTiles {
+    width: usize,
+    height: usize,
+    tiles: Vec<[PsnrMetrics; 3]>,
+}
+
+impl VideoMetric for PsnrTiles {
+    type FrameResult = FrameTiles;
+    type VideoResult = Vec<TileScores>;
+
+    fn process_frame<T: Pixel>(
+        &self,
+        frame1: &Frame<T>,
+        frame2: &Frame<T>,
+        bit_depth: usize,
+        chroma_sampling: ChromaSampling,
+    ) -> Result<Self::FrameResult, Box<dyn Error>> {
+        FrameContext::new(bit_depth, chroma_sampling)?.validate(frame1, frame2)?;
+        let TileGrid { columns, rows } = self.grid;
+        let (width, height) = (frame1.planes[0].cfg.width, frame1.planes[0].cfg.height);
+        if columns == 0 || rows == 0 {
+            return Err(Box::new(MetricsError::UnsupportedInput {
+                reason: "The tile grid must have at least one column and one row",
+            }));
+        }
+        if columns > width || rows > height {
+            return Err(Box::new(MetricsError::UnsupportedInput {
+                reason: "The tile grid has more tiles than the frame has pixels",
+            }));
+        }
+
+        let mut tiles = Vec::with_capacity(columns * rows);
+        for row in 0..rows {
+            for column in 0..columns {
+                tiles.push([0, 1, 2].map(|p| {
+                    let (plane1, plane2) = (&frame1.planes[p], &frame2.planes[p]);
+                    let x = span(column, columns, plane1.cfg.width);
+                    let y = span(row, rows, plane1.cfg.height);
+                    calculate_plane_psnr_metrics(
+                        &region(plane1, x.clone(), y.clone()),
+                        &region(plane2, x, y),
+                        bit_depth,
+                    )
+                }));
+            }
+        }
+        Ok(FrameTiles {
+            width,
+            height,
+            tiles,
+        })
+    }
+
+    fn aggregate_frame_results(
+        &self,
+        metrics: &[Self::FrameResult],
+    ) -> Result<Self::VideoResult, Box<dyn Error>> {
+        let Some(first) = metrics.first() else {
+            return Ok(Vec::new());
+        };
+        let TileGrid { columns, rows } = self.grid;
+        let compat = CompatMode::Native;
+        let mut scores = Vec::with_capacity(columns * rows);
+        for row in 0..rows {
+            for column in 0..columns {
+                let index = row * columns + column;
+                let summed = |plane: usize| {
+                    calculate_summed_psnr(
+                        &metrics
+                            .iter()
+                            .map(|frame| frame.tiles[index][plane])
+                            .collect::<Vec<_>>(),
+                        compat,
+                    )
+                };
+                let (min_frame, min) = metrics
+                    .iter()
+                    .map(|frame| calculate_summed_psnr(&frame.tiles[index], compat))
+                    .enumerate()
+                    .fold((0, f64::INFINITY), |min, (frame, score)| {
+                        if score < min.1 {
+                            (frame, score)
+                        } else {
+                            min
+                        }
+                    });
+                let (x, y) = (
+                    span(column, columns, first.width),
+                    span(row, rows, first.height),
+                );
+                scores.push(TileScores {
+                    column,
+                    row,
+                    x: x.start,
+                    y: y.start,
+                    width: x.len(),
+                    height: y.len(),
+                    psnr: PlanarMetrics {
+                        y: summed(0),
+                        u: summed(1),
+                        v: summed(2),
+                        avg: calculate_summed_psnr(
+                            &metrics
+                                .iter()
+                                .flat_map(|frame| frame.tiles[index])
+                                .collect::<Vec<_>>(),
+                            compat,
+                        ),
+                        alpha: None,
+                        dssim: None,
+                    },
+                    min,
+                    min_frame,
+                });
+            }
+        }
+        Ok(scores)
+    }
+}
@@ -0,0 +1,164 @@
+//! Video Multi-Method Assessment Fusion.
+//!
+//! VMAF predicts the subjective quality of a video by fusing the elementary
+//! features of [`vmaf_features`](crate::video::vmaf_features) with a model
+//! trained on subjective scores. The models are read from the JSON files of
+//! libvmaf, such as `vmaf_v0.6.1.json`, and scores range from 0 to 100 for
+//! the usual models.
+//!
+//! See https://github.com/Netflix/vmaf for more details.
+
+mod json;
+mod model;
+
+pub use model::VmafModel;
+
+use crate::video::decode::Decoder;
+use crate::video::pixel::Pixel;
+use crate::video::vmaf_features::{
+    calculate_frame_vmaf_features, calculate_video_vmaf_features_with, VmafFeatures,
+};
+use crate::video::VideoOptions;
+use crate::MetricsError;
+use std::error::Error;
+use std::path::PathBuf;
+use v_frame::frame::Frame;
+use v_frame::prelude::ChromaSampling;
+
+/// The VMAF scores of a video.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct VmafResults {
+    /// The mean score of all frames.
+    pub mean: f64,
+    /// The harmonic mean of the scores, which weighs the worst frames more.
+    pub harmonic_mean: f64,
+    /// The score of the worst frame.
+    pub min: f64,
+    /// The score of each frame.
+    pub frames: Vec<f64>,
+}
+
+impl VmafResults {
+    fn new(frames: Vec<f64>) -> Self {
+        let n = frames.len() as f64;
+        VmafResults {
+            mean: frames.iter().sum::<f64>() / n,
+            // Offset by one like libvmaf, so that scores of 0 are defined
+            harmonic_mean: n / frames.iter().map(|score| 1.0 / (score + 1.0)).sum::<f64>() - 1.0,
+            min: frames.iter().copied().fold(f64::INFINITY, f64::min),
+            frames,
+        }
+    }
+}
+
+/// Calculates the VMAF score of two videos with `model`. Higher is better.
+///
+/// `decoder1` must be the reference video.
+#[inline]
+pub fn calculate_video_vmaf<D: Decoder, F: Fn(usize) + Send>(
+    decoder1: &mut D,
+    decoder2: &mut D,
+    model: &VmafModel,
+    frame_limit: Option<usize>,
+    progress_callback: F,
+) -> Result<VmafResults, Box<dyn Error>> {
+    calculate_video_vmaf_with_model(
+        decoder1,
+        decoder2,
+        model,
+        VideoOptions::limited(frame_limit, progress_callback),
+    )
+}
+
+/// Calculates the VMAF score of two videos with the given options. Higher is
+/// better.
+///
+/// See [`VmafOptions`] for the available options. The model file must be
+/// given with [`VmafOptions::model_path`].
+#[inline]
+pub fn calculate_video_vmaf_with<D: Decoder, F: Fn(usize) + Send>(
+    decoder1: &mut D,
+    decoder2: &mut D,
+    options: VmafOptions<F>,
+) -> Result<VmafResults, Box<dyn Error>> {
+    let path = options
+        .config
+        .model_path
+        .ok_or(MetricsError::UnsupportedInput {
+            reason: "No VMAF model was given",
+        })?;
+    let model = VmafModel::load(path)?;
+    calculate_video_vmaf_with_model(decoder1, decoder2, &model, options.video)
+}
+
+/// Calculates the VMAF score of two videos with a model that is already
+/// loaded and the given options. Higher is better.
+///
+/// `decoder1` must be the reference video.
+#[inline]
+pub fn calculate_video_vmaf_with_model<D: Decoder, F: Fn(usize) + Send>(
+    decoder1: &mut D,
+    decoder2: &mut D,
+    model: &VmafModel,
+    options: VideoOptions<F>,
+) -> Result<VmafResults, Box<dyn Error>> {
+    let features = calculate_video_vmaf_features_with(decoder1, decoder2, options)?;
+    Ok(predict(model, &features))
+}
+
+/// Calculates the VMAF score of two video frames with `model`. Higher is
+/// better.
+///
+/// Motion needs the previous frame, so the frames are scored as if they were
+/// still.
+#[inline]
+pub fn calculate_frame_vmaf<T: Pixel>(
+    frame1: &Frame<T>,
+    frame2: &Frame<T>,
+    bit_depth: usize,
+    chroma_sampling: ChromaSampling,
+    model: &VmafModel,
+) -> Result<f64, Box<dyn Error>> {
+    let features = calculate_frame_vmaf_features(frame1, frame2, bit_depth, chroma_sampling)?;
+    Ok(model.predict(&features))
+}
+
+fn predict(model: &VmafModel, features: &[VmafFeatures]) -> VmafResults {
+    VmafResults::new(features.iter().map(|f| model.predict(f)).collect())
+}
+
+/// Options for computing VMAF.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct VmafConfig {
+    /// The JSON model file of libvmaf to score the features with.
+    pub model_path: Option<PathBuf>,
+}
+
+/// Options for computing VMAF over whole videos, built with chained calls:
+///
+/// ```
+/// use av_metrics::video::vmaf::VmafOptions;
+///
+/// let options = VmafOptions::new()
+///     .frame_limit(100)
+///     .model_path("vmaf_v0.6.1.json");
+/// ```
+#[derive(Debug, Clone)]
+pub struct VmafOptions<F = fn(usize)> {
+    /// The frame limit, thread count and progress callback.
+    pub video: VideoOptions<F>,
+    /// The options of VMAF itself.
+    pub config: VmafConfig,
+}
+
+video_options!(VmafOptions);
+
+impl<F> VmafOptions<F> {
+    /// Scores the features with the libvmaf model stored at `path`.
+    pub fn model_path<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.config.model_path = Some(path.into());
+        self
+    }
+}
@@ -0,0 +1,204 @@
+//! The trained models of libvmaf: a support vector regression over the
+//! normalized features, followed by an optional transform and clipping of the
+//! predicted score.
+
+use super::json::Json;
+use crate::video::svm::Svm;
+use crate::video::vmaf_features::VmafFeatures;
+use crate::MetricsError;
+use std::error::Error;
+use std::path::Path;
+
+/// A VMAF model, as distributed with libvmaf in JSON, such as
+/// `vmaf_v0.6.1.json`.
+///
+/// Only support vector regression models (`LIBSVMNU`) with an RBF kernel are
+/// supported, over the features of [`VmafFeatures`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct VmafModel {
+    features: Vec<Feature>,
+    /// The slope and intercept of the score, then of each feature, if the
+    /// features are rescaled.
+    rescale: Option<Vec<(f64, f64)>>,
+    svm: Svm,
+    transform: Option<ScoreTransform>,
+    clip: Option<(f64, f64)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Feature {
+    Adm2,
+    Motion,
+    Motion2,
+    Vif(usize),
+}
+
+impl Feature {
+    /// Maps a feature name of libvmaf, such as `VMAF_feature_adm2_score`.
+    fn from_name(name: &str) -> Option<Self> {
+        let (_, name) = name.rsplit_once("feature_")?;
+        Some(match name {
+            "adm2_score" => Feature::Adm2,
+            "motion_score" => Feature::Motion,
+            "motion2_score" => Feature::Motion2,
+            "vif_scale0_score" => Feature::Vif(0),
+            "vif_scale1_score" => Feature::Vif(1),
+            "vif_scale2_score" => Feature::Vif(2),
+            "vif_scale3_score" => Feature::Vif(3),
+            _ => return None,
+        })
+    }
+
+    fn value(self, features: &VmafFeatures) -> f64 {
+        match self {
+            Feature::Adm2 => features.adm2,
+            Feature::Motion => features.motion,
+            Feature::Motion2 => features.motion2,
+            Feature::Vif(scale) => features.vif[scale],
+        }
+    }
+}
+
+/// A polynomial applied to the predicted score.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ScoreTransform {
+    p0: f64,
+    p1: f64,
+    p2: f64,
+    /// Never lower the score.
+    out_gte_in: bool,
+    /// Never raise the score.
+    out_lte_in: bool,
+}
+
+fn malformed(reason: &'static str) -> MetricsError {
+    MetricsError::MalformedInput { reason }
+}
+
+impl VmafModel {
+    /// Reads a model from a JSON file of libvmaf.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
+        let json = std::fs::read_to_string(path)?;
+        Ok(Self::from_json(&json)?)
+    }
+
+    /// Parses a model in the JSON format of libvmaf.
+    pub fn from_json(json: &str) -> Result<Self, MetricsError> {
+        let json = Json::parse(json)?;
+        let model = json
+            .get("model_dict")
+            .ok_or_else(|| malformed("Missing model_dict in the VMAF model"))?;
+
+        if model.get("model_type").and_then(Json::as_str) != Some("LIBSVMNU") {
+            return Err(MetricsError::UnsupportedInput {
+                reason: "Only LIBSVMNU VMAF models are supported",
+            });
+        }
+
+        let features = model
+            .get("feature_names")
+            .and_then(Json::as_array)
+            .ok_or_else(|| malformed("Missing feature_names in the VMAF model"))?
+            .iter()
+            .map(|name| {
+                name.as_str()
+                    .and_then(Feature::from_name)
+                    .ok_or(MetricsError::UnsupportedInput {
+                        reason: "The VMAF model uses an unsupported feature",
+                    })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let rescale = match model.get("norm_type").and_then(Json::as_str) {
+            Some("linear_rescale") => {
+                let numbers = |key: &str| {
+                    model
+                        .get(key)
+                        .and_then(Json::as_array)
+                        .and_then(|values| {
+                            values.iter().map(Json::as_f64).collect::<Option<Vec<_>>>()
+                        })
+                        .filter(|values| values.len() == features.len() + 1)
+                        .ok_or_else(|| malformed("Invalid slopes or intercepts in the VMAF model"))
+                };
+                let slopes = numbers("slopes")?;
+                let intercepts = numbers("intercepts")?;
+                Some(slopes.into_iter().zip(intercepts).collect())
+            }
+            Some("none") | None => None,
+            Some(_) => {
+                return Err(MetricsError::UnsupportedInput {
+                    reason: "Unsupported normalization in the VMAF model",
+                })
+            }
+        };
+
+        let transform = model
+            .get("score_transform")
+            .filter(|transform| transform.get("enabled").and_then(Json::as_bool) == Some(true))
+            .map(|transform| {
+                let coeff = |key: &str| transform.get(key).and_then(Json::as_f64).unwrap_or(0.0);
+                let flag = |key: &str| transform.get(key).and_then(Json::as_bool) == Some(true);
+                ScoreTransform {
+                    p0: coeff("p0"),
+                    p1: coeff("p1"),
+                    p2: coeff("p2"),
+                    out_gte_in: flag("out_gte_in"),
+                    out_lte_in: flag("out_lte_in"),
+                }
+            });
+
+        let clip = match model.get("score_clip").and_then(Json::as_array) {
+            Some([min, max]) => Some(
+                min.as_f64()
+                    .zip(max.as_f64())
+                    .ok_or_else(|| malformed("Invalid score_clip in the VMAF model"))?,
+            ),
+            Some(_) => return Err(malformed("Invalid score_clip in the VMAF model")),
+            None => None,
+        };
+
+        let svm = model
+            .get("model")
+            .and_then(Json::as_str)
+            .ok_or_else(|| malformed("Missing model in the VMAF model"))?;
+        let svm = Svm::parse(svm, features.len())?;
+
+        Ok(VmafModel {
+            features,
+            rescale,
+            svm,
+            transform,
+            clip,
+        })
+    }
+
+    /// Predicts the VMAF score of a frame from its features.
+    pub fn predict(&self, features: &VmafFeatures) -> f64 {
+        let mut input: Vec<f64> = self.features.iter().map(|f| f.value(features)).collect();
+        if let Some(rescale) = &self.rescale {
+            for (value, &(slope, intercept)) in input.iter_mut().zip(&rescale[1..]) {
+                *value = slope * *value + intercept;
+            }
+        }
+
+        let mut score = self.svm.predict(&input);
+        if let Some(&(slope, intercept)) = self.rescale.as_ref().and_then(|r| r.first()) {
+            score = (score - intercept) / slope;
+        }
+        if let Some(transform) = self.transform {
+            let transformed = transform.p0 + transform.p1 * score + transform.p2 * score * score;
+            score = if transform.out_gte_in {
+                transformed.max(score)
+            } else if transform.out_lte_in {
+                transformed.min(score)
+            } else {
+                transformed
+            };
+        }
+        if let Some((min, max)) = self.clip {
+            score = score.clamp(min, max);
+        }
+        score
+    }
+}
@@ -0,0 +1,241 @@
+//! The Additive Distortion Measure of VMAF, also known as the Detail Loss
+//! Metric.
+//!
+//! Both luma planes are decomposed into four scales of Daubechies wavelets.
+//! At each scale, the distorted bands are split into the details restored from
+//! the reference and the additive impairments. The restored details are
+//! weighted by the contrast sensitivity of the eye and masked by the
+//! impairments, and ADM2 is their energy relative to that of the reference.
+//!
+//! See "Image Quality Assessment by Separately Evaluating Detail Losses and
+//! Additive Impairments" by Li, Zhang, Ma and Ngan (IEEE TMM, 2011).
+
+use super::{mirror, Luma};
+
+/// The low-pass filter of the Daubechies 2 wavelet.
+const LOW: [f32; 4] = [0.4829629, 0.8365163, 0.22414387, -0.12940952];
+/// The high-pass filter of the Daubechies 2 wavelet.
+const HIGH: [f32; 4] = [-0.12940952, -0.22414387, 0.8365163, -0.4829629];
+
+/// The amplitudes of the basis functions of the wavelet at each scale, for
+/// the approximation, horizontal and vertical, diagonal, and again
+/// horizontal and vertical bands.
+const BASIS_AMPLITUDES: [[f32; 4]; 4] = [
+    [0.62171, 0.67234, 0.72709, 0.67234],
+    [0.34537, 0.41317, 0.49428, 0.41317],
+    [0.18004, 0.22727, 0.28688, 0.22727],
+    [0.091401, 0.11792, 0.15214, 0.11792],
+];
+
+/// The distance to the display, in display heights.
+const VIEW_DISTANCE: f32 = 3.0;
+/// The height of the display, in pixels.
+const DISPLAY_HEIGHT: f32 = 1080.0;
+/// The largest gain of the distorted details over the reference that counts
+/// as restored, so that enhancing an image only improves ADM2 a little.
+const GAIN_LIMIT: f32 = 100.0;
+/// The fraction of each side of a band left out of the sums.
+const BORDER_FACTOR: f32 = 0.1;
+
+/// The bands of one level of the wavelet decomposition.
+struct Bands {
+    approximation: Luma,
+    /// The horizontal, vertical and diagonal details.
+    details: [Luma; 3],
+}
+
+/// Computes ADM2.
+pub(super) fn adm2(reference: &Luma, distorted: &Luma) -> f64 {
+    let (width, height) = (reference.width, reference.height);
+    let mut reference = dwt2(reference);
+    let mut distorted = dwt2(distorted);
+    let (mut num, mut den) = (0.0, 0.0);
+    for scale in 0..4 {
+        if scale > 0 {
+            reference = dwt2(&reference.approximation);
+            distorted = dwt2(&distorted.approximation);
+        }
+        let weights = csf_weights(scale);
+        let (restored, additive) = decouple(&reference.details, &distorted.details);
+        num += masked_energy(&restored, &additive, weights);
+        den += energy(&reference.details, weights);
+    }
+
+    let limit = 1e-10 * (width * height) as f64 / (1920.0 * 1080.0);
+    let num = if num < limit { 0.0 } else { num };
+    let den = if den < limit { 0.0 } else { den };
+    if den == 0.0 {
+        1.0
+    } else {
+        num / den
+    }
+}
+
+/// Decomposes a plane into one level of Daubechies 2 wavelets, with mirrored
+/// edges.
+fn dwt2(plane: &Luma) -> Bands {
+    let (width, height) = (plane.width.div_ceil(2), plane.height.div_ceil(2));
+    let taps =
+        |i: usize, len: usize| [0, 1, 2, 3].map(|k| mirror(2 * i as isize - 1 + k as isize, len));
+
+    // Vertical pass
+    let mut low = Luma::new(plane.width, height);
+    let mut high = Luma::new(plane.width, height);
+    for y in 0..height {
+        for (k, row) in taps(y, plane.height).into_iter().enumerate() {
+            let row = plane.row(row);
+            let out_low = &mut low.data[y * plane.width..][..plane.width];
+            for (out, &value) in out_low.iter_mut().zip(row) {
+                *out += LOW[k] * value;
+            }
+            let out_high = &mut high.data[y * plane.width..][..plane.width];
+            for (out, &value) in out_high.iter_mut().zip(row) {
+                *out += HIGH[k] * value;
+            }
+        }
+    }
+
+    // Horizontal pass
+    let horizontal = |plane: &Luma, filter: &[f32; 4]| {
+        let mut out = Luma::new(width, height);
+        for y in 0..height {
+            let row = plane.row(y);
+            for x in 0..width {
+                out.data[y * width + x] = taps(x, plane.width)
+                    .iter()
+                    .zip(filter)
+                    .map(|(&i, &c)| c * row[i])
+                    .sum();
+            }
+        }
+        out
+    };
+    Bands {
+        approximation: horizontal(&low, &LOW),
+        details: [
+            horizontal(&high, &LOW),
+            horizontal(&low, &HIGH),
+            horizontal(&high, &HIGH),
+        ],
+    }
+}
+
+/// Splits the distorted detail bands into the details restored from the
+/// reference and the additive impairments.
+fn decouple(reference: &[Luma; 3], distorted: &[Luma; 3]) -> ([Luma; 3], [Luma; 3]) {
+    let cos_1deg_sq = 1f32.to_radians().cos().powi(2);
+    let (width, height) = (reference[0].width, reference[0].height);
+    let mut restored = [0, 1, 2].map(|_| Luma::new(width, height));
+    let mut additive = [0, 1, 2].map(|_| Luma::new(width, height));
+    for i in 0..width * height {
+        let o = [0, 1, 2].map(|band| reference[band].data[i]);
+        let t = [0, 1, 2].map(|band| distorted[band].data[i]);
+
+        // Distortions that keep the direction of the horizontal and vertical
+        // details within one degree only change their contrast
+        let ot_dp = o[0] * t[0] + o[1] * t[1];
+        let o_mag_sq = o[0] * o[0] + o[1] * o[1];
+        let t_mag_sq = t[0] * t[0] + t[1] * t[1];
+        let angle_flag = ot_dp >= 0.0 && ot_dp * ot_dp >= cos_1deg_sq * o_mag_sq * t_mag_sq;
+
+        for band in 0..3 {
+            let k = (t[band] / (o[band] + 1e-30)).clamp(0.0, 1.0);
+            let mut rst = k * o[band];
+            if angle_flag {
+                rst = if rst > 0.0 {
+                    (rst * GAIN_LIMIT).min(t[band])
+                } else if rst < 0.0 {
+                    (rst * GAIN_LIMIT).max(t[band])
+                } else {
+                    rst
+                };
+            }
+            restored[band].data[i] = rst;
+            additive[band].data[i] = t[band] - rst;
+        }
+    }
+    (restored, additive)
+}
+
+/// The weights of the horizontal, vertical and diagonal bands of a scale,
+/// from the contrast sensitivity of the eye to their frequencies.
+fn csf_weights(scale: usize) -> [f32; 3] {
+    // The threshold model of Watson et al. for the luma of 9/7 wavelets
+    const A: f32 = 0.495;
+    const K: f32 = 0.466;
+    const F0: f32 = 0.401;
+    const G: [f32; 4] = [1.501, 1.0, 0.534, 1.0];
+    let resolution = VIEW_DISTANCE * DISPLAY_HEIGHT * std::f32::consts::PI / 180.0;
+    let quant_step = |theta: usize| {
+        let frequency = 2f32.powi(scale as i32 + 1) * F0 * G[theta] / resolution;
+        2.0 * A * 10f32.powf(K * frequency.log10().powi(2)) / BASIS_AMPLITUDES[scale][theta]
+    };
+    let (straight, diagonal) = (1.0 / quant_step(1), 1.0 / quant_step(2));
+    [straight, straight, diagonal]
+}
+
+/// The region of a band whose samples are summed, without its borders, as
+/// the first and last columns and rows.
+fn inner_region(band: &Luma) -> (usize, usize, usize, usize) {
+    let border = |len: usize| ((len as f32 * BORDER_FACTOR - 0.5).max(0.0) as usize).min(len / 2);
+    let (left, top) = (border(band.width), border(band.height));
+    (left, band.width - left, top, band.height - top)
+}
+
+/// Sums the cubes of `values` over the inner region of the band, as a
+/// Minkowski norm of order 3 with a constant for the size of the region.
+fn minkowski_sum(band: &Luma, mut value: impl FnMut(usize, usize) -> f32) -> f64 {
+    let (left, right, top, bottom) = inner_region(band);
+    let mut accum = 0.0;
+    for y in top..bottom {
+        for x in left..right {
+            accum += (value(x, y) as f64).powi(3);
+        }
+    }
+    let area = ((right - left) * (bottom - top)) as f64;
+    accum.cbrt() + (area / 32.0).cbrt()
+}
+
+/// The weighted energy of the reference detail bands.
+fn energy(bands: &[Luma; 3], weights: [f32; 3]) -> f64 {
+    bands
+        .iter()
+        .zip(weights)
+        .map(|(band, weight)| minkowski_sum(band, |x, y| (weight * band.at(x, y)).abs()))
+        .sum()
+}
+
+/// The weighted energy of the restored details, masked by the weighted
+/// additive impairments around each sample.
+fn masked_energy(restored: &[Luma; 3], additive: &[Luma; 3], weights: [f32; 3]) -> f64 {
+    let (width, height) = (additive[0].width, additive[0].height);
+    let mut mask = Luma::new(width, height);
+    for (band, weight) in additive.iter().zip(weights) {
+        for y in 0..height {
+            for x in 0..width {
+                let mut sum = 0.0;
+                for dy in -1..=1 {
+                    for dx in -1..=1 {
+                        let value = band.at(
+                            mirror(x as isize + dx, width),
+                            mirror(y as isize + dy, height),
+                        );
+                        // The sample itself counts twice
+                        let factor = if dx == 0 && dy == 0 { 2.0 } else { 1.0 };
+                        sum += factor * (weight * value).abs();
+                    }
+                }
+                mask.data[y * width + x] += sum / 30.0;
+            }
+        }
+    }
+    restored
+        .iter()
+        .zip(weights)
+        .map(|(band, weight)| {
+            minkowski_sum(band, |x, y| {
+                ((weight * band.at(x, y)).abs() - mask.at(x, y)).max(0.0)
+            })
+        })
+        .sum()
+}
@@ -0,0 +1,275 @@
+//! The elementary features of VMAF, computed per frame without a model.
+//!
+//! VMAF predicts subjective quality by fusing a few simple features with a
+//! trained model. This module extracts these features from the luma planes:
+//! the motion of the reference video, the Additive Distortion Measure (ADM2)
+//! and Visual Information Fidelity (VIF) at four scales. They follow the
+//! floating point algorithms of libvmaf, but may differ from its output in the
+//! last digits.
+//!
+//! See https://github.com/Netflix/vmaf for more details.
+
+mod adm;
+mod motion;
+mod vif;
+
+use crate::video::decode::{Decoder, PlaneRef};
+use crate::video::pixel::CastFromPrimitive;
+use crate::video::pixel::Pixel;
+use crate::video::{FrameContext, VideoMetric, VideoOptions};
+use motion::Motion;
+use std::error::Error;
+use std::sync::Mutex;
+use v_frame::frame::Frame;
+use v_frame::plane::Plane;
+use v_frame::prelude::ChromaSampling;
+
+/// The VMAF features of one frame.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct VmafFeatures {
+    /// The mean absolute difference between the blurred luma of this frame of
+    /// the reference video and of the previous one. 0 for the first frame.
+    pub motion: f64,
+    /// The smaller of the motion of this frame and of the next one.
+    pub motion2: f64,
+    /// The Additive Distortion Measure, from 0 to 1 for distorted frames.
+    pub adm2: f64,
+    /// Visual Information Fidelity at scales 0 to 3, from 0 to 1 for
+    /// distorted frames.
+    pub vif: [f64; 4],
+}
+
+/// Calculates the VMAF features of each frame of two videos. `decoder1` must
+/// be the reference video.
+#[inline]
+pub fn calculate_video_vmaf_features<D: Decoder, F: Fn(usize) + Send>(
+    decoder1: &mut D,
+    decoder2: &mut D,
+    frame_limit: Option<usize>,
+    progress_callback: F,
+) -> Result<Vec<VmafFeatures>, Box<dyn Error>> {
+    calculate_video_vmaf_features_with(
+        decoder1,
+        decoder2,
+        VideoOptions::limited(frame_limit, progress_callback),
+    )
+}
+
+/// Options for extracting the VMAF features of whole videos, which have no
+/// options of their own.
+pub type VmafFeaturesOptions<F = fn(usize)> = VideoOptions<F>;
+
+/// Calculates the VMAF features of each frame of two videos with the given
+/// options. `decoder1` must be the reference video.
+///
+/// See [`VmafFeaturesOptions`] for the available options.
+#[inline]
+pub fn calculate_video_vmaf_features_with<D: Decoder, F: Fn(usize) + Send>(
+    decoder1: &mut D,
+    decoder2: &mut D,
+    options: VmafFeaturesOptions<F>,
+) -> Result<Vec<VmafFeatures>, Box<dyn Error>> {
+    let bit_depth = decoder1.get_bit_depth();
+    options.run(|limits, progress_callback| {
+        VmafFeaturesMetric::new(bit_depth).process_video(
+            decoder1,
+            decoder2,
+            limits,
+            progress_callback,
+        )
+    })
+}
+
+/// Calculates the VMAF features of two video frames. `frame1` must be the
+/// reference frame.
+///
+/// Motion needs the previous frame, so it is reported as 0.
+#[inline]
+pub fn calculate_frame_vmaf_features<T: Pixel>(
+    frame1: &Frame<T>,
+    frame2: &Frame<T>,
+    bit_depth: usize,
+    chroma_sampling: ChromaSampling,
+) -> Result<VmafFeatures, Box<dyn Error>> {
+    VmafFeaturesMetric::new(bit_depth).process_frame(frame1, frame2, bit_depth, chroma_sampling)
+}
+
+struct VmafFeaturesMetric {
+    bit_depth: usize,
+    /// The motion of the reference video, tracked in decoding order.
+    motion: Mutex<Motion>,
+}
+
+impl VmafFeaturesMetric {
+    fn new(bit_depth: usize) -> Self {
+        VmafFeaturesMetric {
+            bit_depth,
+            motion: Mutex::default(),
+        }
+    }
+}
+
+impl VideoMetric for VmafFeaturesMetric {
+    type FrameResult = VmafFeatures;
+    type VideoResult = Vec<VmafFeatures>;
+
+    fn frame_decoded<T: Pixel>(&self, frame1: &Frame<T>, _frame2: &Frame<T>) {
+        let luma = Luma::from_plane(&frame1.planes[0], self.bit_depth);
+        self.motion.lock().unwrap().push(&luma);
+    }
+
+    fn process_frame<T: Pixel>(
+        &self,
+        frame1: &Frame<T>,
+        frame2: &Frame<T>,
+        bit_depth: usize,
+        chroma_sampling: ChromaSampling,
+    ) -> Result<Self::FrameResult, Box<dyn Error>> {
+        FrameContext::new(bit_depth, chroma_sampling)?.validate(frame1, frame2)?;
+
+        let reference = Luma::from_plane(&frame1.planes[0], bit_depth);
+        let distorted = Luma::from_plane(&frame2.planes[0], bit_depth);
+        let mut adm2 = 0.0;
+        let mut vif = [0.0; 4];
+        rayon::scope(|s| {
+            s.spawn(|_| adm2 = adm::adm2(&reference, &distorted));
+            s.spawn(|_| vif = vif::vif(&reference, &distorted));
+        });
+        Ok(VmafFeatures {
+            adm2,
+            vif,
+            ..Default::default()
+        })
+    }
+
+    fn aggregate_frame_results(
+        &self,
+        metrics: &[Self::FrameResult],
+    ) -> Result<Self::VideoResult, Box<dyn Error>> {
+        let scores = &self.motion.lock().unwrap().scores;
+        let motion2 = motion::motion2(&scores[..metrics.len().min(scores.len())]);
+        Ok(metrics
+            .iter()
+            .enumerate()
+            .map(|(i, features)| VmafFeatures {
+                motion: scores.get(i).copied().unwrap_or_default(),
+                motion2: motion2.get(i).copied().unwrap_or_default(),
+                ..*features
+            })
+            .collect())
+    }
+}
+
+/// A luma plane in floating point, scaled to the range of 8-bit samples.
+#[derive(Debug, Clone)]
+struct Luma {
+    width: usize,
+    height: usize,
+    data: Vec<f32>,
+}
+
+impl Luma {
+    /// A plane of zeros.
+    fn new(width: usize, height: usize) -> Self {
+        Luma {
+            width,
+            height,
+            data: vec![0.0; width * height],
+        }
+    }
+
+    fn from_plane<T: Pixel>(plane: &Plane<T>, bit_depth: usize) -> Self {
+        let plane = PlaneRef::from(plane);
+        let scale = 1.0 / (1 << (bit_depth - 8)) as f32;
+        Luma {
+            width: plane.width(),
+            height: plane.height(),
+            data: plane
+                .rows()
+                .flatten()
+                .map(|&pixel| u16::cast_from(pixel) as f32 * scale)
+                .collect(),
+        }
+    }
+
+    fn row(&self, y: usize) -> &[f32] {
+        &self.data[y * self.width..][..self.width]
+    }
+
+    fn at(&self, x: usize, y: usize) -> f32 {
+        self.data[y * self.width + x]
+    }
+
+    /// Convolves the plane with a symmetric separable `kernel`, mirroring
+    /// the edges.
+    fn filter(&self, kernel: &[f32]) -> Luma {
+        let radius = (kernel.len() / 2) as isize;
+        let mut vertical = Luma::new(self.width, self.height);
+        for y in 0..self.height {
+            let out = &mut vertical.data[y * self.width..][..self.width];
+            for (k, &coeff) in kernel.iter().enumerate() {
+                let row = self.row(mirror(y as isize + k as isize - radius, self.height));
+                for (out, &value) in out.iter_mut().zip(row) {
+                    *out += coeff * value;
+                }
+            }
+        }
+
+        let mut out = Luma::new(self.width, self.height);
+        for y in 0..self.height {
+            let row = vertical.row(y);
+            for x in 0..self.width {
+                out.data[y * self.width + x] = kernel
+                    .iter()
+                    .enumerate()
+                    .map(|(k, &coeff)| {
+                        coeff * row[mirror(x as isize + k as isize - radius, self.width)]
+                    })
+                    .sum();
+            }
+        }
+        out
+    }
+
+    /// Combines two planes of the same size sample by sample.
+    fn zip_map(&self, other: &Luma, f: impl Fn(f32, f32) -> f32) -> Luma {
+        Luma {
+            width: self.width,
+            height: self.height,
+            data: self
+                .data
+                .iter()
+                .zip(other.data.iter())
+                .map(|(&a, &b)| f(a, b))
+                .collect(),
+        }
+    }
+
+    /// Keeps every other sample in each direction.
+    fn decimate(&self) -> Luma {
+        let (width, height) = ((self.width / 2).max(1), (self.height / 2).max(1));
+        let mut out = Luma::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                out.data[y * width + x] = self.at(2 * x, 2 * y);
+            }
+        }
+        out
+    }
+}
+
+/// Maps an index outside of `0..len` back inside by mirroring around the
+/// first and last samples.
+fn mirror(i: isize, len: usize) -> usize {
+    if len == 1 {
+        return 0;
+    }
+    let period = 2 * len as isize - 2;
+    let i = i.rem_euclid(period);
+    if i < len as isize {
+        i as usize
+    } else {
+        (period - i) as usize
+    }
+}
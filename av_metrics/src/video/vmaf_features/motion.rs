@@ -0,0 +1,43 @@
+//! The motion feature of VMAF: the mean absolute difference between
+//! consecutive blurred luma planes of the reference video.
+
+use super::Luma;
+
+/// The 5-tap Gaussian blur applied before comparing frames.
+const BLUR: [f32; 5] = [0.054488685, 0.24420134, 0.40261995, 0.24420134, 0.054488685];
+
+/// Follows the motion of a video frame by frame.
+#[derive(Default)]
+pub(super) struct Motion {
+    previous: Option<Luma>,
+    /// The motion of each frame since the previous one, 0 for the first frame.
+    pub(super) scores: Vec<f64>,
+}
+
+impl Motion {
+    /// Adds the next luma plane of the reference video.
+    pub(super) fn push(&mut self, luma: &Luma) {
+        let blurred = luma.filter(&BLUR);
+        let score = self.previous.as_ref().map_or(0.0, |previous| {
+            let sum: f64 = previous
+                .data
+                .iter()
+                .zip(blurred.data.iter())
+                .map(|(&a, &b)| (a - b).abs() as f64)
+                .sum();
+            sum / blurred.data.len() as f64
+        });
+        self.scores.push(score);
+        self.previous = Some(blurred);
+    }
+}
+
+/// The motion2 feature of each frame: the smaller of its motion and the
+/// motion of the next frame, or its own motion for the last frame.
+pub(super) fn motion2(scores: &[f64]) -> Vec<f64> {
+    scores
+        .iter()
+        .enumerate()
+        .map(|(i, &score)| scores.get(i + 1).map_or(score, |&next| score.min(next)))
+        .collect()
+}
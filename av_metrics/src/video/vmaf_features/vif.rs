@@ -0,0 +1,99 @@
+//! Visual Information Fidelity, as extracted by libvmaf.
+//!
+//! The reference and distorted luma planes are compared at four scales, each
+//! half the size of the previous one. At each scale, VIF is the information
+//! the distorted plane shares with the reference, relative to the information
+//! in the reference, estimated from local statistics under a Gaussian window.
+//!
+//! See "Image Information and Visual Quality" by Sheikh and Bovik (IEEE TIP,
+//! 2006).
+
+use super::Luma;
+
+/// The variance of the noise of the visual system.
+const SIGMA_NSQ: f32 = 2.0;
+/// The inverse of the largest variance of 8-bit samples.
+const SIGMA_MAX_INV: f32 = 4.0 / (255.0 * 255.0);
+/// Variances below this are treated as zero.
+const EPS: f32 = 1e-10;
+/// The largest gain of the distorted plane over the reference that counts as
+/// information, so that enhancing an image only improves VIF a little.
+const GAIN_LIMIT: f32 = 100.0;
+
+/// Computes VIF at the four scales.
+pub(super) fn vif(reference: &Luma, distorted: &Luma) -> [f64; 4] {
+    let mut reference = reference.clone();
+    let mut distorted = distorted.clone();
+    let mut scores = [0.0; 4];
+    for (scale, score) in scores.iter_mut().enumerate() {
+        let kernel = gaussian_kernel((1 << (4 - scale)) + 1);
+        if scale > 0 {
+            reference = reference.filter(&kernel).decimate();
+            distorted = distorted.filter(&kernel).decimate();
+        }
+
+        let mu1 = reference.filter(&kernel);
+        let mu2 = distorted.filter(&kernel);
+        let xx = reference.zip_map(&reference, |a, b| a * b).filter(&kernel);
+        let yy = distorted.zip_map(&distorted, |a, b| a * b).filter(&kernel);
+        let xy = reference.zip_map(&distorted, |a, b| a * b).filter(&kernel);
+
+        let (mut num, mut den) = (0.0, 0.0);
+        for i in 0..mu1.data.len() {
+            let (mu1, mu2) = (mu1.data[i], mu2.data[i]);
+            let sigma1_sq = (xx.data[i] - mu1 * mu1).max(0.0);
+            let sigma2_sq = (yy.data[i] - mu2 * mu2).max(0.0);
+            let sigma12 = xy.data[i] - mu1 * mu2;
+            let (num_val, den_val) = statistic(sigma1_sq, sigma2_sq, sigma12);
+            num += num_val as f64;
+            den += den_val as f64;
+        }
+        *score = if den > 0.0 { num / den } else { 1.0 };
+    }
+    scores
+}
+
+/// The information shared by both planes and the information in the
+/// reference, at one sample.
+fn statistic(mut sigma1_sq: f32, sigma2_sq: f32, sigma12: f32) -> (f32, f32) {
+    let mut g = sigma12 / (sigma1_sq + EPS);
+    let mut sv_sq = sigma2_sq - g * sigma12;
+    if sigma1_sq < EPS {
+        g = 0.0;
+        sv_sq = sigma2_sq;
+        sigma1_sq = 0.0;
+    }
+    if sigma2_sq < EPS {
+        g = 0.0;
+        sv_sq = 0.0;
+    }
+    if g < 0.0 {
+        sv_sq = sigma2_sq;
+        g = 0.0;
+    }
+    let sv_sq = sv_sq.max(EPS);
+    let g = g.min(GAIN_LIMIT);
+
+    if sigma1_sq < SIGMA_NSQ {
+        // Flat areas carry no information, only the noise of the distorted
+        // plane is penalized
+        (1.0 - sigma2_sq * SIGMA_MAX_INV, 1.0)
+    } else {
+        (
+            (1.0 + g * g * sigma1_sq / (sv_sq + SIGMA_NSQ)).log2(),
+            (1.0 + sigma1_sq / SIGMA_NSQ).log2(),
+        )
+    }
+}
+
+/// A normalized Gaussian window of `size` taps, with a standard deviation of
+/// a fifth of its size.
+fn gaussian_kernel(size: usize) -> Vec<f32> {
+    let sigma = size as f64 / 5.0;
+    let center = (size - 1) as f64 / 2.0;
+    let weights: Vec<f64> = (0..size)
+        .map(|i| (-(i as f64 - center).powi(2) / (2.0 * sigma * sigma)).exp())
+        .collect();
+    let sum: f64 = weights.iter().sum();
+    weights.iter().map(|w| (w / sum) as f32).collect()
+}
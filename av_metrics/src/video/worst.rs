@@ -0,0 +1,98 @@
+//! The lowest-scoring frames of a video.
+//!
+//! The average over a video says how good it is overall, but not where to
+//! look when it is not. Keeping the frames with the lowest scores points
+//! straight at the scenes that need attention.
+
+use std::collections::BinaryHeap;
+
+/// The score of one frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FrameScore {
+    /// The number of the frame.
+    pub frame: usize,
+    /// The score of the frame.
+    pub score: f64,
+}
+
+/// Orders frames by score, then by number, so that the heap keeps the best
+/// of the worst frames at its top.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct Ranked(FrameScore);
+
+impl PartialEq for Ranked {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for Ranked {}
+
+impl PartialOrd for Ranked {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Ranked {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.0
+            .score
+            .total_cmp(&other.0.score)
+            .then(self.0.frame.cmp(&other.0.frame))
+    }
+}
+
+/// Keeps the `n` frames with the lowest scores out of consecutive frames.
+///
+/// Higher scores are assumed to be better, as for every metric of this crate.
+/// Frames with equal scores are ranked by number.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WorstFrames {
+    n: usize,
+    frames: usize,
+    worst: BinaryHeap<Ranked>,
+}
+
+impl WorstFrames {
+    /// Creates an aggregator keeping `n` frames.
+    pub fn new(n: usize) -> Self {
+        WorstFrames {
+            n,
+            frames: 0,
+            worst: BinaryHeap::with_capacity(n + 1),
+        }
+    }
+
+    /// Adds the score of the next frame.
+    pub fn push(&mut self, score: f64) {
+        let frame = self.frames;
+        self.frames += 1;
+        self.worst.push(Ranked(FrameScore { frame, score }));
+        if self.worst.len() > self.n {
+            self.worst.pop();
+        }
+    }
+
+    /// The worst frames, from the lowest score up.
+    pub fn finish(self) -> Vec<FrameScore> {
+        self.worst
+            .into_sorted_vec()
+            .into_iter()
+            .map(|ranked| ranked.0)
+            .collect()
+    }
+}
+
+/// Returns the `n` frames with the lowest scores, from the lowest up, out of
+/// the scores of consecutive frames.
+pub fn worst_frames<I: IntoIterator<Item = f64>>(scores: I, n: usize) -> Vec<FrameScore> {
+    let mut aggregator = WorstFrames::new(n);
+    for score in scores {
+        aggregator.push(score);
+    }
+    aggregator.finish()
+}
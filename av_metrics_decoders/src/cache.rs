@@ -0,0 +1,249 @@
+use av_metrics::video::decode::*;
+use av_metrics::video::side_data::SideData;
+use av_metrics::video::*;
+use std::any::Any;
+use std::collections::VecDeque;
+use std::mem::size_of;
+
+/// A decoded frame kept by a [`CachingDecoder`], along with what the decoder
+/// reported about it.
+struct CachedFrame {
+    number: usize,
+    frame: Box<dyn Any + Send>,
+    meta: Option<FrameMeta>,
+    side_data: Option<SideData>,
+    bytes: usize,
+}
+
+/// A decoder that keeps the most recently used frames in memory, so that
+/// reading the same frames again with
+/// [`read_specific_frame`](Decoder::read_specific_frame) does not decode the
+/// input again.
+///
+/// Frames decoded on the way to a requested frame are cached as well, and the
+/// least recently used frames are dropped once the cache would exceed its
+/// memory budget. A frame before the last decoded one that is no longer cached
+/// can only be read again if the wrapped decoder can seek.
+///
+/// Reading frames in order passes through to the wrapped decoder without
+/// caching them. Frames read from the cache have no alpha plane.
+pub struct CachingDecoder<D: Decoder> {
+    inner: D,
+    budget: usize,
+    used: usize,
+    frames: VecDeque<CachedFrame>,
+    /// The number of the next frame the wrapped decoder returns
+    decoded: usize,
+    /// The number of the next frame returned when reading in order
+    next: usize,
+    last_meta: Option<FrameMeta>,
+    last_side_data: Option<SideData>,
+}
+
+impl<D: Decoder> CachingDecoder<D> {
+    /// Wraps `inner`, caching up to `budget` bytes of decoded samples.
+    pub fn new(inner: D, budget: usize) -> Self {
+        CachingDecoder {
+            inner,
+            budget,
+            used: 0,
+            frames: VecDeque::new(),
+            decoded: 0,
+            next: 0,
+            last_meta: None,
+            last_side_data: None,
+        }
+    }
+
+    /// Returns the wrapped decoder.
+    pub fn into_inner(self) -> D {
+        self.inner
+    }
+
+    /// The number of bytes of decoded samples currently cached.
+    pub fn cached_bytes(&self) -> usize {
+        self.used
+    }
+
+    /// The numbers of the cached frames, from the least to the most recently
+    /// used.
+    pub fn cached_frames(&self) -> impl Iterator<Item = usize> + '_ {
+        self.frames.iter().map(|cached| cached.number)
+    }
+
+    /// Drops every cached frame.
+    pub fn clear(&mut self) {
+        self.frames.clear();
+        self.used = 0;
+    }
+
+    /// Whether the next frame read in order is the next frame of the wrapped
+    /// decoder, so that it can be read from it directly.
+    fn in_sync(&self) -> bool {
+        self.next == self.decoded
+    }
+
+    /// Moves past the frame the wrapped decoder returned, if it returned one.
+    fn advance<R>(&mut self, result: Option<R>) -> Option<R> {
+        if result.is_some() {
+            self.decoded += 1;
+            self.next = self.decoded;
+            self.last_meta = self.inner.last_frame_meta();
+            self.last_side_data = self.inner.last_side_data();
+        }
+        result
+    }
+
+    /// Returns the cached frame at `number`, marking it as the most recently
+    /// used.
+    fn lookup<T: Pixel>(&mut self, number: usize) -> Option<Frame<T>> {
+        let index = self
+            .frames
+            .iter()
+            .position(|cached| cached.number == number)?;
+        let cached = self.frames.remove(index)?;
+        let frame = cached.frame.downcast_ref::<Frame<T>>().cloned();
+        if frame.is_some() {
+            self.last_meta = cached.meta;
+            self.last_side_data = cached.side_data.clone();
+            self.frames.push_back(cached);
+        } else {
+            // The frame was cached with another pixel type
+            self.used -= cached.bytes;
+        }
+        frame
+    }
+
+    /// Caches `frame` as the frame at `number`, with what the wrapped decoder
+    /// reported about it, dropping the least recently used frames to stay
+    /// within the budget.
+    fn insert<T: Pixel>(&mut self, number: usize, frame: &Frame<T>) {
+        let bytes = frame
+            .planes
+            .iter()
+            .map(|plane| plane.data.len() * size_of::<T>())
+            .sum::<usize>();
+        if bytes > self.budget {
+            return;
+        }
+        while self.used + bytes > self.budget {
+            match self.frames.pop_front() {
+                Some(evicted) => self.used -= evicted.bytes,
+                None => break,
+            }
+        }
+        self.used += bytes;
+        self.frames.push_back(CachedFrame {
+            number,
+            frame: Box::new(frame.clone()),
+            meta: self.last_meta,
+            side_data: self.last_side_data.clone(),
+            bytes,
+        });
+    }
+}
+
+impl<D: Decoder> Decoder for CachingDecoder<D> {
+    fn read_video_frame<T: Pixel>(&mut self) -> Option<Frame<T>> {
+        if !self.in_sync() {
+            return self.read_specific_frame(self.next);
+        }
+        let frame = self.inner.read_video_frame();
+        self.advance(frame)
+    }
+
+    fn read_video_frame_into<T: Pixel>(&mut self, frame: &mut Frame<T>) -> bool {
+        if !self.in_sync() {
+            return match self.read_specific_frame(self.next) {
+                Some(f) => {
+                    *frame = f;
+                    true
+                }
+                None => false,
+            };
+        }
+        let read = self.inner.read_video_frame_into(frame);
+        self.advance(read.then_some(())).is_some()
+    }
+
+    fn read_specific_frame<T: Pixel>(&mut self, frame_number: usize) -> Option<Frame<T>> {
+        if let Some(frame) = self.lookup(frame_number) {
+            self.next = frame_number + 1;
+            return Some(frame);
+        }
+        if self.inner.capabilities().seek && frame_number != self.decoded {
+            let frame = self.inner.read_specific_frame(frame_number)?;
+            self.decoded = frame_number;
+            self.advance(Some(()));
+            self.insert(frame_number, &frame);
+            return Some(frame);
+        }
+        if frame_number < self.decoded {
+            return None;
+        }
+        loop {
+            let number = self.decoded;
+            let frame = self.inner.read_video_frame()?;
+            self.advance(Some(()));
+            self.insert(number, &frame);
+            if number == frame_number {
+                return Some(frame);
+            }
+        }
+    }
+
+    fn read_video_frame_ref<T: Pixel, R, F: FnOnce(FrameRef<'_, T>) -> R>(
+        &mut self,
+        f: F,
+    ) -> Option<R> {
+        if !self.in_sync() {
+            return self
+                .read_specific_frame::<T>(self.next)
+                .map(|frame| f(FrameRef::from(&frame)));
+        }
+        let result = self.inner.read_video_frame_ref(f);
+        self.advance(result)
+    }
+
+    fn read_video_frame_with_alpha<T: Pixel>(&mut self) -> Option<(Frame<T>, Option<Plane<T>>)> {
+        if !self.in_sync() {
+            return self
+                .read_specific_frame(self.next)
+                .map(|frame| (frame, None));
+        }
+        let frame = self.inner.read_video_frame_with_alpha();
+        self.advance(frame)
+    }
+
+    fn get_bit_depth(&self) -> usize {
+        self.inner.get_bit_depth()
+    }
+
+    fn get_video_details(&self) -> VideoDetails {
+        self.inner.get_video_details()
+    }
+
+    fn get_stream_info(&self) -> StreamInfo {
+        self.inner.get_stream_info()
+    }
+
+    fn last_frame_meta(&self) -> Option<FrameMeta> {
+        self.last_meta
+    }
+
+    fn last_side_data(&self) -> Option<SideData> {
+        self.last_side_data.clone()
+    }
+
+    fn capabilities(&self) -> DecoderCapabilities {
+        self.inner.capabilities()
+    }
+
+    fn frame_count(&self) -> Option<usize> {
+        self.inner.frame_count()
+    }
+
+    fn build_index(&mut self) -> Option<usize> {
+        self.inner.build_index()
+    }
+}
@@ -0,0 +1,53 @@
+use av_metrics::MetricsError;
+use std::io;
+use thiserror::Error;
+
+/// Possible errors that may occur while opening or decoding an input.
+///
+/// This enum may be added to in the future and should not be assumed to be exhaustive.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum DecoderError {
+    /// Indicates an input could not be opened or read, such as a missing file.
+    #[error("Could not read input: {reason}")]
+    Io {
+        #[doc(hidden)]
+        reason: String,
+    },
+    /// Indicates an input could be read, but is not a valid stream, such as a
+    /// truncated header.
+    #[error("Could not decode input: {reason}")]
+    MalformedInput {
+        #[doc(hidden)]
+        reason: String,
+    },
+    /// Indicates an input is valid, but uses a format the decoder does not
+    /// support.
+    #[error("Input format not supported: {reason}")]
+    UnsupportedInput {
+        #[doc(hidden)]
+        reason: String,
+    },
+    /// Indicates the decoding library or process failed.
+    #[error("Decoder failed: {reason}")]
+    Backend {
+        #[doc(hidden)]
+        reason: String,
+    },
+}
+
+impl From<io::Error> for DecoderError {
+    fn from(err: io::Error) -> Self {
+        DecoderError::Io {
+            reason: err.to_string(),
+        }
+    }
+}
+
+impl From<DecoderError> for MetricsError {
+    fn from(err: DecoderError) -> Self {
+        MetricsError::DecodeError {
+            reason: err.to_string(),
+        }
+    }
+}
@@ -0,0 +1,254 @@
+use crate::DecoderError;
+use av_metrics::video::decode::*;
+use av_metrics::video::*;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+#[cfg(any(
+    feature = "ffmpeg",
+    feature = "ffmpeg_static",
+    feature = "ffmpeg_build"
+))]
+use crate::FfmpegDecoder;
+#[cfg(feature = "vapoursynth")]
+use crate::VapoursynthDecoder;
+#[cfg(feature = "y4m")]
+use crate::Y4MDecoder;
+#[cfg(feature = "y4m")]
+use std::{fs::File, io::BufReader};
+
+/// A decoder backend that can be selected at runtime.
+///
+/// Only the backends enabled via Cargo features are available.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Backend {
+    /// Decodes y4m files
+    #[cfg(feature = "y4m")]
+    Y4m,
+    /// Decodes any format supported by FFmpeg
+    #[cfg(any(
+        feature = "ffmpeg",
+        feature = "ffmpeg_static",
+        feature = "ffmpeg_build"
+    ))]
+    Ffmpeg,
+    /// Decodes any format supported by the default Vapoursynth source plugin
+    #[cfg(feature = "vapoursynth")]
+    Vapoursynth,
+    /// Evaluates a Vapoursynth script and decodes its output
+    #[cfg(feature = "vapoursynth")]
+    VapoursynthScript,
+}
+
+impl Backend {
+    /// All backends enabled in this build, in order of preference.
+    pub const AVAILABLE: &'static [Backend] = &[
+        #[cfg(feature = "y4m")]
+        Backend::Y4m,
+        #[cfg(any(
+            feature = "ffmpeg",
+            feature = "ffmpeg_static",
+            feature = "ffmpeg_build"
+        ))]
+        Backend::Ffmpeg,
+        #[cfg(feature = "vapoursynth")]
+        Backend::Vapoursynth,
+        #[cfg(feature = "vapoursynth")]
+        Backend::VapoursynthScript,
+    ];
+
+    /// The name of the backend, as accepted by `from_str`.
+    pub fn name(self) -> &'static str {
+        match self {
+            #[cfg(feature = "y4m")]
+            Backend::Y4m => "y4m",
+            #[cfg(any(
+                feature = "ffmpeg",
+                feature = "ffmpeg_static",
+                feature = "ffmpeg_build"
+            ))]
+            Backend::Ffmpeg => "ffmpeg",
+            #[cfg(feature = "vapoursynth")]
+            Backend::Vapoursynth => "vapoursynth",
+            #[cfg(feature = "vapoursynth")]
+            Backend::VapoursynthScript => "vapoursynth-script",
+        }
+    }
+
+    /// Picks an available backend for a file based on its extension.
+    ///
+    /// Vapoursynth scripts (`.vpy`) use `VapoursynthScript` and y4m files use
+    /// `Y4m`. Other files use FFmpeg or Vapoursynth, whichever is available.
+    pub fn detect<P: AsRef<Path>>(input: P) -> Option<Backend> {
+        let extension = input
+            .as_ref()
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_ascii_lowercase());
+        match extension.as_deref() {
+            #[cfg(feature = "vapoursynth")]
+            Some("vpy") => return Some(Backend::VapoursynthScript),
+            #[cfg(feature = "y4m")]
+            Some("y4m") => return Some(Backend::Y4m),
+            _ => (),
+        }
+        let fallbacks: &[Backend] = &[
+            #[cfg(any(
+                feature = "ffmpeg",
+                feature = "ffmpeg_static",
+                feature = "ffmpeg_build"
+            ))]
+            Backend::Ffmpeg,
+            #[cfg(feature = "vapoursynth")]
+            Backend::Vapoursynth,
+            #[cfg(feature = "y4m")]
+            Backend::Y4m,
+        ];
+        fallbacks.first().copied()
+    }
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Backend::AVAILABLE
+            .iter()
+            .copied()
+            .find(|backend| backend.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("Unsupported decoder backend {s}"))
+    }
+}
+
+/// A decoder created by [`open_decoder`], wrapping the decoder of the
+/// selected backend.
+pub enum AnyDecoder {
+    /// A y4m decoder
+    #[cfg(feature = "y4m")]
+    Y4m(Y4MDecoder<BufReader<File>>),
+    /// An FFmpeg decoder
+    #[cfg(any(
+        feature = "ffmpeg",
+        feature = "ffmpeg_static",
+        feature = "ffmpeg_build"
+    ))]
+    Ffmpeg(FfmpegDecoder),
+    /// A Vapoursynth decoder, for either a video file or a script
+    #[cfg(feature = "vapoursynth")]
+    Vapoursynth(VapoursynthDecoder),
+}
+
+/// Opens `input` with the given decoder backend.
+pub fn open_decoder<P: AsRef<Path>>(
+    backend: Backend,
+    input: P,
+) -> Result<AnyDecoder, DecoderError> {
+    let input = input.as_ref();
+    match backend {
+        #[cfg(feature = "y4m")]
+        Backend::Y4m => crate::y4m::new_decoder_from_file(input).map(AnyDecoder::Y4m),
+        #[cfg(any(
+            feature = "ffmpeg",
+            feature = "ffmpeg_static",
+            feature = "ffmpeg_build"
+        ))]
+        Backend::Ffmpeg => FfmpegDecoder::new(input).map(AnyDecoder::Ffmpeg),
+        #[cfg(feature = "vapoursynth")]
+        Backend::Vapoursynth => {
+            VapoursynthDecoder::new_from_video(input).map(AnyDecoder::Vapoursynth)
+        }
+        #[cfg(feature = "vapoursynth")]
+        Backend::VapoursynthScript => {
+            VapoursynthDecoder::new_from_script(input).map(AnyDecoder::Vapoursynth)
+        }
+    }
+}
+
+macro_rules! dispatch {
+    ($self:expr, $decoder:ident => $call:expr) => {
+        match $self {
+            #[cfg(feature = "y4m")]
+            AnyDecoder::Y4m($decoder) => $call,
+            #[cfg(any(
+                feature = "ffmpeg",
+                feature = "ffmpeg_static",
+                feature = "ffmpeg_build"
+            ))]
+            AnyDecoder::Ffmpeg($decoder) => $call,
+            #[cfg(feature = "vapoursynth")]
+            AnyDecoder::Vapoursynth($decoder) => $call,
+        }
+    };
+}
+
+impl AnyDecoder {
+    /// Replaces details of the video reported by the backend, such as a
+    /// misdetected color range or chroma sample position.
+    pub fn set_overrides(&mut self, overrides: DetailsOverride) {
+        dispatch!(self, decoder => decoder.set_overrides(overrides))
+    }
+}
+
+impl Decoder for AnyDecoder {
+    fn read_video_frame<T: Pixel>(&mut self) -> Option<Frame<T>> {
+        dispatch!(self, decoder => decoder.read_video_frame())
+    }
+
+    fn read_video_frame_into<T: Pixel>(&mut self, frame: &mut Frame<T>) -> bool {
+        dispatch!(self, decoder => decoder.read_video_frame_into(frame))
+    }
+
+    fn read_specific_frame<T: Pixel>(&mut self, frame_number: usize) -> Option<Frame<T>> {
+        dispatch!(self, decoder => decoder.read_specific_frame(frame_number))
+    }
+
+    fn read_video_frame_ref<T: Pixel, R, F: FnOnce(FrameRef<'_, T>) -> R>(
+        &mut self,
+        f: F,
+    ) -> Option<R> {
+        dispatch!(self, decoder => decoder.read_video_frame_ref(f))
+    }
+
+    fn read_video_frame_with_alpha<T: Pixel>(&mut self) -> Option<(Frame<T>, Option<Plane<T>>)> {
+        dispatch!(self, decoder => decoder.read_video_frame_with_alpha())
+    }
+
+    fn get_bit_depth(&self) -> usize {
+        dispatch!(self, decoder => decoder.get_bit_depth())
+    }
+
+    fn get_video_details(&self) -> VideoDetails {
+        dispatch!(self, decoder => decoder.get_video_details())
+    }
+
+    fn get_stream_info(&self) -> StreamInfo {
+        dispatch!(self, decoder => decoder.get_stream_info())
+    }
+
+    fn last_frame_meta(&self) -> Option<FrameMeta> {
+        dispatch!(self, decoder => decoder.last_frame_meta())
+    }
+
+    fn last_side_data(&self) -> Option<side_data::SideData> {
+        dispatch!(self, decoder => decoder.last_side_data())
+    }
+
+    fn capabilities(&self) -> DecoderCapabilities {
+        dispatch!(self, decoder => decoder.capabilities())
+    }
+
+    fn frame_count(&self) -> Option<usize> {
+        dispatch!(self, decoder => decoder.frame_count())
+    }
+
+    fn build_index(&mut self) -> Option<usize> {
+        dispatch!(self, decoder => decoder.build_index())
+    }
+}
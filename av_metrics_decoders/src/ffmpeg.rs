@@ -1,50 +1,150 @@
 extern crate ffmpeg_the_third as ffmpeg;
 
-use std::path::Path;
+use std::ffi::CString;
+use std::path::{Path, PathBuf};
+use std::ptr;
 
 use ffmpeg::codec::{decoder, packet};
 use ffmpeg::format::context;
 use ffmpeg::media::Type;
 use ffmpeg::{format, frame};
 
+use crate::DecoderError;
 use av_metrics::video::decode::*;
 use av_metrics::video::*;
 
+/// Options of the FFmpeg decoder.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FfmpegOptions {
+    /// The type of the device used to decode the video in hardware, by its
+    /// FFmpeg name, such as `vaapi`, `cuda` or `videotoolbox`.
+    ///
+    /// Decoded frames are downloaded to system memory. If the device does not
+    /// support the codec, FFmpeg falls back to software decoding.
+    pub hwaccel: Option<String>,
+    /// The index of the video stream to decode in the container. The best
+    /// video stream is decoded by default.
+    pub stream_index: Option<usize>,
+    /// The id of the program to decode a video stream of, in containers with
+    /// several programs such as MPEG-TS. With `stream_index`, the stream must
+    /// belong to the program.
+    pub program: Option<i32>,
+}
+
 /// An interface that is used for decoding a video stream using FFMpeg
 ///
-/// There have been desync issue reported with this decoder
-/// on some video files. Use at your own risk!
+/// Frames are returned in presentation order, with the timestamps of the
+/// container in [`Decoder::last_frame_meta`].
 pub struct FfmpegDecoder {
     input_ctx: context::Input,
     decoder: decoder::Video,
     video_details: VideoDetails,
+    stream_info: StreamInfo,
+    has_alpha: bool,
     frameno: usize,
+    last_frame_meta: Option<FrameMeta>,
     stream_index: usize,
-    end_of_stream: bool,
+    stream_time_base: f64,
     eof_sent: bool,
+    path: PathBuf,
+    index: Option<FrameIndex>,
+}
+
+/// The frames of the decoded stream, as recorded by
+/// [`Decoder::build_index`].
+struct FrameIndex {
+    /// The number of frames
+    frames: usize,
+    /// The presentation timestamps of the frames that have one, in
+    /// presentation order
+    pts: Vec<i64>,
+    /// The presentation timestamps of the keyframes, in presentation order
+    keyframes: Vec<i64>,
+}
+
+impl FrameIndex {
+    /// Whether every frame has a timestamp, so that a frame can be found by
+    /// seeking to the keyframe before it.
+    fn seekable(&self) -> bool {
+        self.pts.len() == self.frames && !self.keyframes.is_empty()
+    }
+
+    /// The timestamp of the frame at `number`, and of the last keyframe at or
+    /// before it.
+    fn seek_point(&self, number: usize) -> Option<(i64, i64)> {
+        if !self.seekable() {
+            return None;
+        }
+        let pts = *self.pts.get(number)?;
+        let keyframe = *self
+            .keyframes
+            .iter()
+            .rev()
+            .find(|&&keyframe| keyframe <= pts)?;
+        Some((pts, keyframe))
+    }
 }
 
 impl FfmpegDecoder {
     /// Initialize a new FFMpeg decoder for a given input file
-    pub fn new<P: AsRef<Path>>(input: P) -> Result<Self, String> {
-        ffmpeg::init().map_err(|e| e.to_string())?;
+    pub fn new<P: AsRef<Path>>(input: P) -> Result<Self, DecoderError> {
+        Self::new_with_options(input, &FfmpegOptions::default())
+    }
+
+    /// Initialize a new FFMpeg decoder for a given input file, with the given
+    /// options
+    pub fn new_with_options<P: AsRef<Path>>(
+        input: P,
+        options: &FfmpegOptions,
+    ) -> Result<Self, DecoderError> {
+        ffmpeg::init()?;
 
-        let input_ctx = format::input(&input).map_err(|e| e.to_string())?;
+        let path = input.as_ref().to_path_buf();
+        let input_ctx = format::input(&input).map_err(|e| DecoderError::Io {
+            reason: e.to_string(),
+        })?;
         let input = input_ctx
-            .streams()
-            .best(Type::Video)
-            .ok_or_else(|| "Could not find video stream".to_string())?;
+            .stream(select_stream(&input_ctx, options)?)
+            .ok_or_else(|| DecoderError::MalformedInput {
+                reason: "Could not find video stream".to_owned(),
+            })?;
         let stream_index = input.index();
-        let mut decoder = ffmpeg::codec::context::Context::from_parameters(input.parameters())
-            .map_err(|e| e.to_string())?
-            .decoder()
-            .video()
-            .map_err(|e| e.to_string())?;
-        decoder
-            .set_parameters(input.parameters())
-            .map_err(|e| e.to_string())?;
+        let stream_time_base = f64::from(input.time_base());
+        let mut context = ffmpeg::codec::context::Context::from_parameters(input.parameters())?;
+        if let Some(hwaccel) = options.hwaccel.as_deref() {
+            create_hw_device(&mut context, hwaccel)?;
+        }
+        let mut decoder = context.decoder().video()?;
+        decoder.set_parameters(input.parameters())?;
 
         let frame_rate = input.avg_frame_rate();
+        let duration = input_ctx.duration();
+        let stream_info = StreamInfo {
+            codec: Some(decoder.id().name().to_owned()),
+            container: Some(input_ctx.format().name().to_owned()),
+            duration: (duration > 0)
+                .then(|| duration as f64 / f64::from(ffmpeg::ffi::AV_TIME_BASE)),
+            frame_rate: (frame_rate.numerator() > 0 && frame_rate.denominator() > 0)
+                .then(|| f64::from(frame_rate)),
+            pixel_format: decoder
+                .format()
+                .descriptor()
+                .map(|descriptor| descriptor.name().to_owned()),
+        };
+        let has_alpha = matches!(
+            decoder.format(),
+            format::pixel::Pixel::YUVA420P
+                | format::pixel::Pixel::YUVA422P
+                | format::pixel::Pixel::YUVA444P
+                | format::pixel::Pixel::YUVA420P10LE
+                | format::pixel::Pixel::YUVA422P10LE
+                | format::pixel::Pixel::YUVA444P10LE
+                | format::pixel::Pixel::YUVA422P12LE
+                | format::pixel::Pixel::YUVA444P12LE
+                | format::pixel::Pixel::YUVA420P16LE
+                | format::pixel::Pixel::YUVA422P16LE
+                | format::pixel::Pixel::YUVA444P16LE
+        );
         Ok(Self {
             video_details: VideoDetails {
                 width: decoder.width() as usize,
@@ -55,38 +155,75 @@ impl FfmpegDecoder {
                     | format::pixel::Pixel::YUV444P
                     | format::pixel::Pixel::YUVJ420P
                     | format::pixel::Pixel::YUVJ422P
-                    | format::pixel::Pixel::YUVJ444P => 8,
+                    | format::pixel::Pixel::YUVJ444P
+                    | format::pixel::Pixel::YUVA420P
+                    | format::pixel::Pixel::YUVA422P
+                    | format::pixel::Pixel::YUVA444P => 8,
                     format::pixel::Pixel::YUV420P10LE
                     | format::pixel::Pixel::YUV422P10LE
-                    | format::pixel::Pixel::YUV444P10LE => 10,
+                    | format::pixel::Pixel::YUV444P10LE
+                    | format::pixel::Pixel::YUVA420P10LE
+                    | format::pixel::Pixel::YUVA422P10LE
+                    | format::pixel::Pixel::YUVA444P10LE => 10,
                     format::pixel::Pixel::YUV420P12LE
                     | format::pixel::Pixel::YUV422P12LE
-                    | format::pixel::Pixel::YUV444P12LE => 12,
+                    | format::pixel::Pixel::YUV444P12LE
+                    | format::pixel::Pixel::YUVA422P12LE
+                    | format::pixel::Pixel::YUVA444P12LE => 12,
+                    format::pixel::Pixel::YUV420P16LE
+                    | format::pixel::Pixel::YUV422P16LE
+                    | format::pixel::Pixel::YUV444P16LE
+                    | format::pixel::Pixel::YUVA420P16LE
+                    | format::pixel::Pixel::YUVA422P16LE
+                    | format::pixel::Pixel::YUVA444P16LE => 16,
                     _ => {
-                        return Err(format!("Unsupported pixel format {:?}", decoder.format()));
+                        return Err(DecoderError::UnsupportedInput {
+                            reason: format!("Pixel format {:?}", decoder.format()),
+                        });
                     }
                 },
                 chroma_sampling: match decoder.format() {
                     format::pixel::Pixel::YUV420P
                     | format::pixel::Pixel::YUVJ420P
                     | format::pixel::Pixel::YUV420P10LE
-                    | format::pixel::Pixel::YUV420P12LE => ChromaSampling::Cs420,
+                    | format::pixel::Pixel::YUV420P12LE
+                    | format::pixel::Pixel::YUV420P16LE
+                    | format::pixel::Pixel::YUVA420P
+                    | format::pixel::Pixel::YUVA420P10LE
+                    | format::pixel::Pixel::YUVA420P16LE => ChromaSampling::Cs420,
                     format::pixel::Pixel::YUV422P
                     | format::pixel::Pixel::YUVJ422P
                     | format::pixel::Pixel::YUV422P10LE
-                    | format::pixel::Pixel::YUV422P12LE => ChromaSampling::Cs422,
+                    | format::pixel::Pixel::YUV422P12LE
+                    | format::pixel::Pixel::YUV422P16LE
+                    | format::pixel::Pixel::YUVA422P
+                    | format::pixel::Pixel::YUVA422P10LE
+                    | format::pixel::Pixel::YUVA422P12LE
+                    | format::pixel::Pixel::YUVA422P16LE => ChromaSampling::Cs422,
                     format::pixel::Pixel::YUV444P
                     | format::pixel::Pixel::YUVJ444P
                     | format::pixel::Pixel::YUV444P10LE
-                    | format::pixel::Pixel::YUV444P12LE => ChromaSampling::Cs444,
+                    | format::pixel::Pixel::YUV444P12LE
+                    | format::pixel::Pixel::YUV444P16LE
+                    | format::pixel::Pixel::YUVA444P
+                    | format::pixel::Pixel::YUVA444P10LE
+                    | format::pixel::Pixel::YUVA444P12LE
+                    | format::pixel::Pixel::YUVA444P16LE => ChromaSampling::Cs444,
                     _ => {
-                        return Err(format!("Unsupported pixel format {:?}", decoder.format()));
+                        return Err(DecoderError::UnsupportedInput {
+                            reason: format!("Pixel format {:?}", decoder.format()),
+                        });
                     }
                 },
                 chroma_sample_position: match decoder.format() {
                     format::pixel::Pixel::YUV422P
                     | format::pixel::Pixel::YUV422P10LE
-                    | format::pixel::Pixel::YUV422P12LE => ChromaSamplePosition::Vertical,
+                    | format::pixel::Pixel::YUV422P12LE
+                    | format::pixel::Pixel::YUV422P16LE
+                    | format::pixel::Pixel::YUVA422P
+                    | format::pixel::Pixel::YUVA422P10LE
+                    | format::pixel::Pixel::YUVA422P12LE
+                    | format::pixel::Pixel::YUVA422P16LE => ChromaSamplePosition::Vertical,
                     _ => ChromaSamplePosition::Colocated,
                 },
                 time_base: Rational::new(
@@ -94,16 +231,54 @@ impl FfmpegDecoder {
                     frame_rate.numerator() as u64,
                 ),
                 luma_padding: 0,
+                color_range: match (decoder.color_range(), decoder.format()) {
+                    (ffmpeg::color::Range::JPEG, _)
+                    | (
+                        _,
+                        format::pixel::Pixel::YUVJ420P
+                        | format::pixel::Pixel::YUVJ422P
+                        | format::pixel::Pixel::YUVJ444P,
+                    ) => ColorRange::Full,
+                    _ => ColorRange::Limited,
+                },
+                matrix_coefficients: match decoder.color_space() {
+                    ffmpeg::color::Space::BT470BG | ffmpeg::color::Space::SMPTE170M => {
+                        MatrixCoefficients::Bt601
+                    }
+                    ffmpeg::color::Space::BT2020NCL | ffmpeg::color::Space::BT2020CL => {
+                        MatrixCoefficients::Bt2020
+                    }
+                    _ => MatrixCoefficients::Bt709,
+                },
+                sample_aspect_ratio: match decoder.aspect_ratio() {
+                    sar if sar.numerator() > 0 && sar.denominator() > 0 => {
+                        Rational::new(sar.numerator() as u64, sar.denominator() as u64)
+                    }
+                    // 0:1 means that the aspect ratio is unknown
+                    _ => Rational::new(1, 1),
+                },
+                ..Default::default()
             },
+            stream_info,
+            has_alpha,
             decoder,
             input_ctx,
             frameno: 0,
+            last_frame_meta: None,
             stream_index,
-            end_of_stream: false,
+            stream_time_base,
             eof_sent: false,
+            path,
+            index: None,
         })
     }
 
+    /// Replaces details of the video reported by FFmpeg, such as a misdetected
+    /// color range or chroma sample position.
+    pub fn set_overrides(&mut self, overrides: DetailsOverride) {
+        overrides.apply(&mut self.video_details);
+    }
+
     fn decode_frame<T: Pixel>(&self, decoded: &frame::Video) -> Frame<T> {
         let mut f: Frame<T> = Frame::new_with_padding(
             self.video_details.width,
@@ -111,6 +286,11 @@ impl FfmpegDecoder {
             self.video_details.chroma_sampling,
             0,
         );
+        self.decode_frame_into(decoded, &mut f);
+        f
+    }
+
+    fn decode_frame_into<T: Pixel>(&self, decoded: &frame::Video, f: &mut Frame<T>) {
         let width = self.video_details.width;
         let height = self.video_details.height;
         let bit_depth = self.video_details.bit_depth;
@@ -119,6 +299,10 @@ impl FfmpegDecoder {
             .video_details
             .chroma_sampling
             .get_chroma_dimensions(width, height);
+        if is_semi_planar(decoded.format()) {
+            self.decode_semi_planar_into(decoded, f);
+            return;
+        }
         f.planes[0].copy_from_raw_u8(decoded.data(0), width * bytes, bytes);
         convert_chroma_data(
             &mut f.planes[1],
@@ -136,8 +320,289 @@ impl FfmpegDecoder {
             chroma_width * bytes,
             bytes,
         );
-        f
     }
+
+    /// Copies a frame downloaded from a hardware device, with the chroma
+    /// samples interleaved in one plane as in NV12 or P010.
+    ///
+    /// High bit depth samples are stored in the most significant bits, and are
+    /// shifted down to the bit depth of the video.
+    fn decode_semi_planar_into<T: Pixel>(&self, decoded: &frame::Video, f: &mut Frame<T>) {
+        let width = self.video_details.width;
+        let height = self.video_details.height;
+        let bit_depth = self.video_details.bit_depth;
+        let bytes = if bit_depth > 8 { 2 } else { 1 };
+        let shift = if bytes == 2 { 16 - bit_depth } else { 0 };
+        let (chroma_width, chroma_height) = self
+            .video_details
+            .chroma_sampling
+            .get_chroma_dimensions(width, height);
+
+        let sample = |row: &[u8], index: usize| -> u16 {
+            if bytes == 2 {
+                u16::from_le_bytes([row[index * 2], row[index * 2 + 1]]) >> shift
+            } else {
+                u16::from(row[index])
+            }
+        };
+        let push = |buffer: &mut Vec<u8>, value: u16| {
+            if bytes == 2 {
+                buffer.extend_from_slice(&value.to_le_bytes());
+            } else {
+                buffer.push(value as u8);
+            }
+        };
+
+        let mut luma = Vec::with_capacity(width * height * bytes);
+        for row in decoded.data(0).chunks(decoded.stride(0)).take(height) {
+            for x in 0..width {
+                push(&mut luma, sample(row, x));
+            }
+        }
+        f.planes[0].copy_from_raw_u8(&luma, width * bytes, bytes);
+
+        let mut u = Vec::with_capacity(chroma_width * chroma_height * bytes);
+        let mut v = Vec::with_capacity(chroma_width * chroma_height * bytes);
+        for row in decoded
+            .data(1)
+            .chunks(decoded.stride(1))
+            .take(chroma_height)
+        {
+            for x in 0..chroma_width {
+                push(&mut u, sample(row, x * 2));
+                push(&mut v, sample(row, x * 2 + 1));
+            }
+        }
+        for (plane, data) in f.planes[1..].iter_mut().zip([u, v]) {
+            convert_chroma_data(
+                plane,
+                self.video_details.chroma_sample_position,
+                bit_depth,
+                &data,
+                chroma_width * bytes,
+                bytes,
+            );
+        }
+    }
+
+    /// Copies the alpha plane of a decoded frame, if the pixel format has one,
+    /// into a plane laid out like the luma plane of `frame`.
+    fn decode_alpha<T: Pixel>(&self, decoded: &frame::Video, frame: &Frame<T>) -> Option<Plane<T>> {
+        if !self.has_alpha {
+            return None;
+        }
+        let bytes = if self.video_details.bit_depth > 8 {
+            2
+        } else {
+            1
+        };
+        let mut alpha = frame.planes[0].clone();
+        alpha.copy_from_raw_u8(decoded.data(3), decoded.stride(3), bytes);
+        Some(alpha)
+    }
+
+    /// Returns the next frame in presentation order.
+    ///
+    /// Frames are taken from the decoder before it is sent another packet, so
+    /// that no packet is dropped while the decoder holds frames back to reorder
+    /// them, and the timestamps of the container are kept.
+    fn receive_frame(&mut self) -> Option<frame::Video> {
+        let mut decoded = frame::Video::empty();
+        loop {
+            match self.decoder.receive_frame(&mut decoded) {
+                Ok(()) => {
+                    let pts = decoded.timestamp().or_else(|| decoded.pts());
+                    self.last_frame_meta = Some(FrameMeta {
+                        number: self.frameno,
+                        pts,
+                        time: pts.map(|pts| pts as f64 * self.stream_time_base),
+                        keyframe: Some(decoded.is_key()),
+                    });
+                    self.frameno += 1;
+                    return download_hw_frame(decoded);
+                }
+                Err(ffmpeg::Error::Other { errno }) if errno == ffmpeg::error::EAGAIN => {}
+                // The decoder is drained, or failed beyond recovery
+                Err(_) => return None,
+            }
+            if self.eof_sent {
+                return None;
+            }
+            match self.next_packet() {
+                // A corrupt packet is skipped, like FFmpeg does
+                Some(packet) => {
+                    let _ = self.decoder.send_packet(&packet);
+                }
+                None => {
+                    let _ = self.decoder.send_eof();
+                    self.eof_sent = true;
+                }
+            }
+        }
+    }
+
+    /// Seeks to the keyframe with the presentation timestamp `pts`, so that
+    /// the frames from it on are decoded next.
+    fn seek(&mut self, pts: i64) -> bool {
+        let ret = unsafe {
+            ffmpeg::ffi::avformat_seek_file(
+                self.input_ctx.as_mut_ptr(),
+                self.stream_index as i32,
+                i64::MIN,
+                pts,
+                pts,
+                0,
+            )
+        };
+        if ret < 0 {
+            return false;
+        }
+        self.decoder.flush();
+        self.eof_sent = false;
+        true
+    }
+
+    /// Reads the next packet of the decoded stream.
+    fn next_packet(&mut self) -> Option<packet::Packet> {
+        let stream_index = self.stream_index;
+        self.input_ctx
+            .packets()
+            .filter_map(Result::ok)
+            .map(|(_, packet)| packet)
+            .find(|packet| packet.stream() == stream_index)
+    }
+}
+
+impl From<ffmpeg::Error> for DecoderError {
+    fn from(err: ffmpeg::Error) -> Self {
+        DecoderError::Backend {
+            reason: err.to_string(),
+        }
+    }
+}
+
+/// Finds the index of the video stream to decode.
+fn select_stream(
+    input_ctx: &context::Input,
+    options: &FfmpegOptions,
+) -> Result<usize, DecoderError> {
+    let error = |reason: String| DecoderError::MalformedInput { reason };
+    let is_video = |index: usize| {
+        input_ctx
+            .stream(index)
+            .is_some_and(|stream| stream.parameters().medium() == Type::Video)
+    };
+    let program = match options.program {
+        Some(id) => Some((
+            id,
+            program_streams(input_ctx, id)
+                .ok_or_else(|| error(format!("Could not find program {}", id)))?,
+        )),
+        None => None,
+    };
+
+    match (options.stream_index, program) {
+        (Some(index), _) if !is_video(index) => {
+            Err(error(format!("Stream {} is not a video stream", index)))
+        }
+        (Some(index), Some((id, streams))) if !streams.contains(&index) => {
+            Err(error(format!("Stream {} is not in program {}", index, id)))
+        }
+        (Some(index), _) => Ok(index),
+        (None, Some((id, streams))) => streams
+            .into_iter()
+            .find(|&index| is_video(index))
+            .ok_or_else(|| error(format!("Could not find video stream in program {}", id))),
+        (None, None) => input_ctx
+            .streams()
+            .best(Type::Video)
+            .map(|stream| stream.index())
+            .ok_or_else(|| error("Could not find video stream".to_owned())),
+    }
+}
+
+/// The indices of the streams of the program with the id `id`, if the
+/// container has one.
+fn program_streams(input_ctx: &context::Input, id: i32) -> Option<Vec<usize>> {
+    unsafe {
+        let ctx = input_ctx.as_ptr();
+        (0..(*ctx).nb_programs as usize)
+            .map(|i| *(*ctx).programs.add(i))
+            .find(|program| (**program).id == id)
+            .map(|program| {
+                (0..(*program).nb_stream_indexes as usize)
+                    .map(|i| *(*program).stream_index.add(i) as usize)
+                    .collect()
+            })
+    }
+}
+
+/// Creates a hardware device of the type named `name`, that the decoder
+/// opened from `context` will use.
+fn create_hw_device(
+    context: &mut ffmpeg::codec::context::Context,
+    name: &str,
+) -> Result<(), DecoderError> {
+    let unknown = || DecoderError::Backend {
+        reason: format!("Unknown hardware device type {}", name),
+    };
+    let c_name = CString::new(name).map_err(|_| unknown())?;
+    unsafe {
+        let device_type = ffmpeg::ffi::av_hwdevice_find_type_by_name(c_name.as_ptr());
+        if device_type == ffmpeg::ffi::AVHWDeviceType::AV_HWDEVICE_TYPE_NONE {
+            return Err(unknown());
+        }
+        let mut device = ptr::null_mut();
+        let ret = ffmpeg::ffi::av_hwdevice_ctx_create(
+            &mut device,
+            device_type,
+            ptr::null(),
+            ptr::null_mut(),
+            0,
+        );
+        if ret < 0 {
+            return Err(DecoderError::Backend {
+                reason: format!(
+                    "Failed to create the {} device: {}",
+                    name,
+                    ffmpeg::Error::from(ret)
+                ),
+            });
+        }
+        // The codec context owns the reference from now on
+        (*context.as_mut_ptr()).hw_device_ctx = device;
+    }
+    Ok(())
+}
+
+/// Downloads a frame decoded in hardware to system memory. Frames decoded in
+/// software are returned as they are.
+fn download_hw_frame(decoded: frame::Video) -> Option<frame::Video> {
+    unsafe {
+        if (*decoded.as_ptr()).hw_frames_ctx.is_null() {
+            return Some(decoded);
+        }
+        let mut software = frame::Video::empty();
+        if ffmpeg::ffi::av_hwframe_transfer_data(software.as_mut_ptr(), decoded.as_ptr(), 0) < 0
+            || ffmpeg::ffi::av_frame_copy_props(software.as_mut_ptr(), decoded.as_ptr()) < 0
+        {
+            return None;
+        }
+        Some(software)
+    }
+}
+
+/// Whether the chroma samples of the pixel format are interleaved in one
+/// plane, as in the formats hardware decoders download frames to.
+fn is_semi_planar(format: format::pixel::Pixel) -> bool {
+    matches!(
+        format,
+        format::pixel::Pixel::NV12
+            | format::pixel::Pixel::NV16
+            | format::pixel::Pixel::NV24
+            | format::pixel::Pixel::P010LE
+            | format::pixel::Pixel::P016LE
+    )
 }
 
 impl Decoder for FfmpegDecoder {
@@ -146,61 +611,158 @@ impl Decoder for FfmpegDecoder {
     }
 
     fn read_video_frame<T: Pixel>(&mut self) -> Option<Frame<T>> {
-        // For some reason there's a crap ton of work needed to get ffmpeg to do something simple,
-        // because each codec has it's own stupid way of doing things and they don't all
-        // decode the same way.
-        //
-        // Maybe ffmpeg could have made a simple, singular interface that does this for us,
-        // but noooooo.
-        //
-        // Reference: https://ffmpeg.org/doxygen/trunk/api-h264-test_8c_source.html#l00110
-        loop {
-            // This iterator is actually really stupid... it doesn't reset itself after each `new`.
-            // But that solves our lifetime hell issues, ironically.
-            let packet = self
-                .input_ctx
-                .packets()
-                .filter_map(Result::ok)
-                .next()
-                .map(|(_, packet)| packet);
-
-            let mut packet = if let Some(packet) = packet {
-                packet
+        self.receive_frame()
+            .map(|decoded| self.decode_frame(&decoded))
+    }
+
+    fn read_video_frame_into<T: Pixel>(&mut self, frame: &mut Frame<T>) -> bool {
+        match self.receive_frame() {
+            Some(decoded) => {
+                if is_frame_reusable(frame, &self.video_details) {
+                    self.decode_frame_into(&decoded, frame);
+                } else {
+                    *frame = self.decode_frame(&decoded);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn read_video_frame_with_alpha<T: Pixel>(&mut self) -> Option<(Frame<T>, Option<Plane<T>>)> {
+        let decoded = self.receive_frame()?;
+        let frame = self.decode_frame(&decoded);
+        let alpha = self.decode_alpha(&decoded, &frame);
+        Some((frame, alpha))
+    }
+
+    fn read_video_frame_ref<T: Pixel, R, F: FnOnce(FrameRef<'_, T>) -> R>(
+        &mut self,
+        f: F,
+    ) -> Option<R> {
+        let decoded = self.receive_frame()?;
+        if self.video_details.chroma_sample_position != ChromaSamplePosition::Vertical
+            && !is_semi_planar(decoded.format())
+        {
+            let width = self.video_details.width;
+            let height = self.video_details.height;
+            let bytes = if self.video_details.bit_depth > 8 {
+                2
             } else {
-                self.end_of_stream = true;
-                packet::Packet::empty()
+                1
             };
-
-            if self.end_of_stream && !self.eof_sent {
-                let _ = self.decoder.send_eof();
-                self.eof_sent = true;
+            let (chroma_width, chroma_height) = self
+                .video_details
+                .chroma_sampling
+                .get_chroma_dimensions(width, height);
+            let planes = (
+                PlaneRef::from_raw_u8(decoded.data(0), decoded.stride(0), bytes, width, height),
+                PlaneRef::from_raw_u8(
+                    decoded.data(1),
+                    decoded.stride(1),
+                    bytes,
+                    chroma_width,
+                    chroma_height,
+                ),
+                PlaneRef::from_raw_u8(
+                    decoded.data(2),
+                    decoded.stride(2),
+                    bytes,
+                    chroma_width,
+                    chroma_height,
+                ),
+            );
+            if let (Some(y), Some(u), Some(v)) = planes {
+                return Some(f(FrameRef { planes: [y, u, v] }));
             }
+        }
+        // The chroma needs to be repositioned or deinterleaved, or the samples can't be
+        // borrowed as `T`.
+        let frame: Frame<T> = self.decode_frame(&decoded);
+        Some(f(FrameRef::from(&frame)))
+    }
 
-            if self.end_of_stream || packet.stream() == self.stream_index {
-                let mut decoded = frame::Video::new(
-                    self.decoder.format(),
-                    self.video_details.width as u32,
-                    self.video_details.height as u32,
-                );
-                packet.set_pts(Some(self.frameno as i64));
-                packet.set_dts(Some(self.frameno as i64));
+    fn get_bit_depth(&self) -> usize {
+        self.video_details.bit_depth
+    }
 
-                if !self.end_of_stream {
-                    let _ = self.decoder.send_packet(&packet);
-                }
+    fn get_stream_info(&self) -> StreamInfo {
+        self.stream_info.clone()
+    }
 
-                if self.decoder.receive_frame(&mut decoded).is_ok() {
-                    let f = self.decode_frame(&decoded);
-                    self.frameno += 1;
-                    return Some(f);
-                } else if self.end_of_stream {
-                    return None;
+    fn last_frame_meta(&self) -> Option<FrameMeta> {
+        self.last_frame_meta
+    }
+
+    // The number of frames stored by containers is often missing or wrong,
+    // and seeking is not exact, so neither is reported until the packets of
+    // the stream were indexed.
+    fn capabilities(&self) -> DecoderCapabilities {
+        DecoderCapabilities {
+            metadata: true,
+            frame_count: self.index.is_some(),
+            seek: self.index.as_ref().is_some_and(FrameIndex::seekable),
+        }
+    }
+
+    fn frame_count(&self) -> Option<usize> {
+        self.index.as_ref().map(|index| index.frames)
+    }
+
+    // The packets are read from a second demuxer, so that reading frames is
+    // not disturbed.
+    fn build_index(&mut self) -> Option<usize> {
+        if self.index.is_none() {
+            let mut input_ctx = format::input(&self.path).ok()?;
+            let mut index = FrameIndex {
+                frames: 0,
+                pts: Vec::new(),
+                keyframes: Vec::new(),
+            };
+            for (_, packet) in input_ctx.packets().filter_map(Result::ok) {
+                if packet.stream() != self.stream_index {
+                    continue;
+                }
+                index.frames += 1;
+                if let Some(pts) = packet.pts() {
+                    index.pts.push(pts);
+                    if packet.is_key() {
+                        index.keyframes.push(pts);
+                    }
                 }
             }
+            index.pts.sort_unstable();
+            index.keyframes.sort_unstable();
+            self.index = Some(index);
         }
+        self.frame_count()
     }
 
-    fn get_bit_depth(&self) -> usize {
-        self.video_details.bit_depth
+    fn read_specific_frame<T: Pixel>(&mut self, frame_number: usize) -> Option<Frame<T>> {
+        let Some((pts, keyframe)) = self
+            .index
+            .as_ref()
+            .and_then(|index| index.seek_point(frame_number))
+        else {
+            // Without an index, the frames before it are decoded, as by default
+            for _ in 0..frame_number {
+                self.receive_frame()?;
+            }
+            return self.read_video_frame();
+        };
+        if !self.seek(keyframe) {
+            return None;
+        }
+        loop {
+            let decoded = self.receive_frame()?;
+            let frame_pts = decoded.timestamp().or_else(|| decoded.pts());
+            if frame_pts.is_some_and(|frame_pts| frame_pts >= pts) {
+                self.frameno = frame_number + 1;
+                if let Some(meta) = self.last_frame_meta.as_mut() {
+                    meta.number = frame_number;
+                }
+                return Some(self.decode_frame(&decoded));
+            }
+        }
     }
 }
@@ -0,0 +1,162 @@
+extern crate ffmpeg_the_third as ffmpeg;
+
+use std::path::Path;
+
+use ffmpeg::codec::{decoder, packet};
+use ffmpeg::format::sample::Type as SampleType;
+use ffmpeg::format::{context, Sample};
+use ffmpeg::media::Type;
+use ffmpeg::{format, frame};
+
+use crate::DecoderError;
+use av_metrics::audio::decode::*;
+
+/// An interface that is used for decoding an audio stream using FFMpeg
+pub struct FfmpegAudioDecoder {
+    input_ctx: context::Input,
+    decoder: decoder::Audio,
+    audio_details: AudioDetails,
+    stream_index: usize,
+    end_of_stream: bool,
+    eof_sent: bool,
+}
+
+impl FfmpegAudioDecoder {
+    /// Initialize a new FFMpeg decoder for the audio of a given input file
+    pub fn new<P: AsRef<Path>>(input: P) -> Result<Self, DecoderError> {
+        ffmpeg::init()?;
+
+        let input_ctx = format::input(&input).map_err(|e| DecoderError::Io {
+            reason: e.to_string(),
+        })?;
+        let input =
+            input_ctx
+                .streams()
+                .best(Type::Audio)
+                .ok_or_else(|| DecoderError::MalformedInput {
+                    reason: "Could not find audio stream".to_owned(),
+                })?;
+        let stream_index = input.index();
+        let mut decoder = ffmpeg::codec::context::Context::from_parameters(input.parameters())?
+            .decoder()
+            .audio()?;
+        decoder.set_parameters(input.parameters())?;
+
+        let sample_format = match decoder.format() {
+            Sample::U8(_) => SampleFormat::U8,
+            Sample::I16(_) => SampleFormat::I16,
+            Sample::I32(_) => SampleFormat::I32,
+            Sample::F32(_) => SampleFormat::F32,
+            Sample::F64(_) => SampleFormat::F64,
+            format => {
+                return Err(DecoderError::UnsupportedInput {
+                    reason: format!("Sample format {:?}", format),
+                });
+            }
+        };
+        Ok(Self {
+            audio_details: AudioDetails {
+                sample_rate: decoder.rate(),
+                channels: decoder.ch_layout().channels() as usize,
+                sample_format,
+            },
+            decoder,
+            input_ctx,
+            stream_index,
+            end_of_stream: false,
+            eof_sent: false,
+        })
+    }
+
+    fn receive_frame(&mut self) -> Option<frame::Audio> {
+        // Same dance as for video: feed packets of our stream until the decoder
+        // has a frame for us, then drain it after the end of the input.
+        loop {
+            let packet = self
+                .input_ctx
+                .packets()
+                .filter_map(Result::ok)
+                .next()
+                .map(|(_, packet)| packet);
+
+            let packet = if let Some(packet) = packet {
+                packet
+            } else {
+                self.end_of_stream = true;
+                packet::Packet::empty()
+            };
+
+            if self.end_of_stream && !self.eof_sent {
+                let _ = self.decoder.send_eof();
+                self.eof_sent = true;
+            }
+
+            if self.end_of_stream || packet.stream() == self.stream_index {
+                if !self.end_of_stream {
+                    let _ = self.decoder.send_packet(&packet);
+                }
+
+                let mut decoded = frame::Audio::empty();
+                if self.decoder.receive_frame(&mut decoded).is_ok() {
+                    return Some(decoded);
+                } else if self.end_of_stream {
+                    return None;
+                }
+            }
+        }
+    }
+
+    /// Converts the samples of a decoded frame to interleaved `f32`.
+    fn convert_frame(&self, decoded: &frame::Audio) -> AudioFrame {
+        let channels = self.audio_details.channels;
+        let format = decoded.format();
+        let planar = matches!(
+            format,
+            Sample::U8(SampleType::Planar)
+                | Sample::I16(SampleType::Planar)
+                | Sample::I32(SampleType::Planar)
+                | Sample::F32(SampleType::Planar)
+                | Sample::F64(SampleType::Planar)
+        );
+        let mut samples = Vec::with_capacity(decoded.samples() * channels);
+        for i in 0..decoded.samples() {
+            for c in 0..channels {
+                let (data, index) = if planar {
+                    (decoded.data(c), i)
+                } else {
+                    (decoded.data(0), i * channels + c)
+                };
+                samples.push(read_sample(format, data, index));
+            }
+        }
+        AudioFrame::from_interleaved(channels, samples)
+    }
+}
+
+/// Reads the sample at `index` of a buffer of native-endian samples, scaled
+/// to the range `-1.0..=1.0`.
+fn read_sample(format: Sample, data: &[u8], index: usize) -> f32 {
+    fn bytes<const N: usize>(data: &[u8], index: usize) -> [u8; N] {
+        data[index * N..(index + 1) * N].try_into().unwrap()
+    }
+
+    match format {
+        Sample::U8(_) => (data[index] as f32 - 128.) / 128.,
+        Sample::I16(_) => i16::from_ne_bytes(bytes(data, index)) as f32 / 32768.,
+        Sample::I32(_) => i32::from_ne_bytes(bytes(data, index)) as f32 / 2147483648.,
+        Sample::F32(_) => f32::from_ne_bytes(bytes(data, index)),
+        Sample::F64(_) => f64::from_ne_bytes(bytes(data, index)) as f32,
+        _ => 0.,
+    }
+}
+
+impl AudioDecoder for FfmpegAudioDecoder {
+    fn read_audio_frame(&mut self) -> Option<AudioFrame> {
+        self.receive_frame()
+            .map(|decoded| self.convert_frame(&decoded))
+    }
+
+    fn get_audio_details(&self) -> AudioDetails {
+        self.audio_details
+    }
+}
@@ -0,0 +1,134 @@
+use crate::y4m::Y4MDecoder;
+use crate::DecoderError;
+use av_metrics::video::decode::*;
+use av_metrics::video::*;
+use std::io::BufReader;
+use std::path::Path;
+use std::process::{Child, ChildStdout, Command, Stdio};
+
+/// A decoder that decodes the input in a child process, which writes the
+/// frames to this one as y4m over a pipe.
+///
+/// A crash or an exploit of a bug while decoding an untrusted file then stays
+/// in the child process, which can be further confined by running it in a
+/// sandbox. Any command that writes y4m to its standard output can be used,
+/// such as the `ffmpeg` command line tool run by [`IsolatedDecoder::ffmpeg`]:
+///
+/// ```no_run
+/// # use av_metrics_decoders::IsolatedDecoder;
+/// # use std::process::Command;
+/// // Run ffmpeg without network access and with a read-only file system
+/// let ffmpeg = IsolatedDecoder::ffmpeg_command("untrusted.mkv");
+/// let mut sandboxed = Command::new("bwrap");
+/// sandboxed
+///     .args(["--ro-bind", "/", "/", "--unshare-all", "--die-with-parent"])
+///     .arg(ffmpeg.get_program())
+///     .args(ffmpeg.get_args());
+/// let decoder = IsolatedDecoder::new(sandboxed)?;
+/// # Ok::<(), av_metrics_decoders::DecoderError>(())
+/// ```
+///
+/// The child process is killed when the decoder is dropped.
+pub struct IsolatedDecoder {
+    inner: Y4MDecoder<BufReader<ChildStdout>>,
+    child: Child,
+}
+
+impl IsolatedDecoder {
+    /// Runs `command` and decodes the y4m video it writes to its standard
+    /// output. Its standard input is closed.
+    pub fn new(mut command: Command) -> Result<Self, DecoderError> {
+        let mut child = command
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| DecoderError::Backend {
+                reason: format!("Failed to start the decoder process: {}", e),
+            })?;
+        let inner = match child.stdout.take() {
+            Some(stdout) => {
+                Y4MDecoder::new(BufReader::new(stdout)).map_err(|e| DecoderError::Backend {
+                    reason: format!("The decoder process did not write y4m: {}", e),
+                })
+            }
+            None => Err(DecoderError::Backend {
+                reason: "The decoder process has no standard output".to_owned(),
+            }),
+        };
+        match inner {
+            Ok(inner) => Ok(IsolatedDecoder { inner, child }),
+            Err(e) => {
+                let _ = child.kill();
+                let _ = child.wait();
+                Err(e)
+            }
+        }
+    }
+
+    /// Decodes `input` with the `ffmpeg` command line tool in a child process.
+    pub fn ffmpeg<P: AsRef<Path>>(input: P) -> Result<Self, DecoderError> {
+        Self::new(Self::ffmpeg_command(input))
+    }
+
+    /// The command run by [`IsolatedDecoder::ffmpeg`], which converts `input`
+    /// to y4m on its standard output. It can be wrapped to run in a sandbox.
+    pub fn ffmpeg_command<P: AsRef<Path>>(input: P) -> Command {
+        let mut command = Command::new("ffmpeg");
+        command
+            .args(["-nostdin", "-loglevel", "error", "-i"])
+            .arg(input.as_ref())
+            // High bit depth y4m is an extension that needs -strict
+            .args(["-map", "0:v:0", "-f", "yuv4mpegpipe", "-strict", "-1", "-"]);
+        command
+    }
+
+    /// Replaces details of the video reported by the y4m header, such as a
+    /// wrong chroma sample position.
+    pub fn set_overrides(&mut self, overrides: DetailsOverride) {
+        self.inner.set_overrides(overrides);
+    }
+}
+
+impl Drop for IsolatedDecoder {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+impl Decoder for IsolatedDecoder {
+    fn get_video_details(&self) -> VideoDetails {
+        self.inner.get_video_details()
+    }
+
+    fn read_video_frame<T: Pixel>(&mut self) -> Option<Frame<T>> {
+        self.inner.read_video_frame()
+    }
+
+    fn read_video_frame_into<T: Pixel>(&mut self, frame: &mut Frame<T>) -> bool {
+        self.inner.read_video_frame_into(frame)
+    }
+
+    fn read_video_frame_ref<T: Pixel, U, F: FnOnce(FrameRef<'_, T>) -> U>(
+        &mut self,
+        f: F,
+    ) -> Option<U> {
+        self.inner.read_video_frame_ref(f)
+    }
+
+    fn get_bit_depth(&self) -> usize {
+        self.inner.get_bit_depth()
+    }
+
+    fn get_stream_info(&self) -> StreamInfo {
+        self.inner.get_stream_info()
+    }
+
+    fn last_frame_meta(&self) -> Option<FrameMeta> {
+        self.inner.last_frame_meta()
+    }
+
+    fn capabilities(&self) -> DecoderCapabilities {
+        self.inner.capabilities()
+    }
+}
@@ -2,10 +2,33 @@
 //!
 //! No decoders are enabled by default. They must be enabled via Cargo features.
 //!
-//! Currently supported decoder features: y4m
+//! Currently supported decoder features: y4m, ffmpeg, vapoursynth, wav
+//!
+//! The isolation feature provides [`IsolatedDecoder`], which decodes untrusted
+//! inputs in a child process.
+//!
+//! The shm feature provides [`ShmDecoder`], which reads frames that another
+//! process, possibly not written in Rust, streams into shared memory.
+//!
+//! The ffmpeg and wav features also provide decoders for audio inputs, which
+//! implement [`AudioDecoder`].
+//!
+//! The [`factory`] module opens inputs with a backend chosen at runtime from
+//! the enabled ones.
+//!
+//! [`CachingDecoder`] wraps any decoder to keep recently read frames in
+//! memory, for tools that query the same frames repeatedly.
 
 #![deny(missing_docs)]
 
+mod error;
+
+pub use crate::error::DecoderError;
+
+mod cache;
+
+pub use crate::cache::CachingDecoder;
+
 #[cfg(feature = "y4m")]
 /// Items related to decoding y4m video
 pub mod y4m;
@@ -13,6 +36,18 @@ pub mod y4m;
 #[cfg(feature = "y4m")]
 pub use crate::y4m::Y4MDecoder;
 
+#[cfg(feature = "isolation")]
+mod isolated;
+
+#[cfg(feature = "isolation")]
+pub use crate::isolated::IsolatedDecoder;
+
+#[cfg(feature = "shm")]
+pub mod shm;
+
+#[cfg(feature = "shm")]
+pub use crate::shm::{ShmDecoder, ShmFrameWriter};
+
 #[cfg(any(
     feature = "ffmpeg",
     feature = "ffmpeg_static",
@@ -25,7 +60,21 @@ mod ffmpeg;
     feature = "ffmpeg_static",
     feature = "ffmpeg_build"
 ))]
-pub use crate::ffmpeg::FfmpegDecoder;
+pub use crate::ffmpeg::{FfmpegDecoder, FfmpegOptions};
+
+#[cfg(any(
+    feature = "ffmpeg",
+    feature = "ffmpeg_static",
+    feature = "ffmpeg_build"
+))]
+mod ffmpeg_audio;
+
+#[cfg(any(
+    feature = "ffmpeg",
+    feature = "ffmpeg_static",
+    feature = "ffmpeg_build"
+))]
+pub use crate::ffmpeg_audio::FfmpegAudioDecoder;
 
 #[cfg(feature = "vapoursynth")]
 mod vapoursynth;
@@ -33,5 +82,31 @@ mod vapoursynth;
 #[cfg(feature = "vapoursynth")]
 pub use crate::vapoursynth::{VapoursynthDecoder, VapoursynthDecoderPlugin};
 
+#[cfg(feature = "wav")]
+mod wav;
+
+#[cfg(feature = "wav")]
+pub use crate::wav::WavDecoder;
+
+#[cfg(any(
+    feature = "y4m",
+    feature = "ffmpeg",
+    feature = "ffmpeg_static",
+    feature = "ffmpeg_build",
+    feature = "vapoursynth"
+))]
+/// Selecting a decoder backend at runtime
+pub mod factory;
+
+#[cfg(any(
+    feature = "y4m",
+    feature = "ffmpeg",
+    feature = "ffmpeg_static",
+    feature = "ffmpeg_build",
+    feature = "vapoursynth"
+))]
+pub use crate::factory::{open_decoder, AnyDecoder, Backend};
+
+pub use av_metrics::audio::decode::{AudioDecoder, AudioDetails, AudioFrame, SampleFormat};
 pub use av_metrics::video::decode::{Decoder, VideoDetails};
 pub use av_metrics::video::{CastFromPrimitive, ChromaSampling, Frame, Pixel, Plane};
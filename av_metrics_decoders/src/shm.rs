@@ -0,0 +1,564 @@
+//! Streaming frames into the metrics through shared memory.
+//!
+//! A producer, such as a decoder running in a separate process or an
+//! application written in another language, writes raw frames into a ring
+//! buffer in memory that it shares with this process, for example a file in
+//! `/dev/shm` mapped by both. [`ShmDecoder`] reads the frames where they are,
+//! without copying them, and [`ShmFrameWriter`] writes them from Rust.
+//!
+//! The memory starts with a header of [`HEADER_SIZE`] bytes, in the native
+//! byte order of the machine:
+//!
+//! | Offset | Type | Field |
+//! |--------|------|-------|
+//! | 0  | `u32` | magic, the bytes `AVMF`, written last |
+//! | 4  | `u32` | version, 1 |
+//! | 8  | `u32` | width of the frames |
+//! | 12 | `u32` | height of the frames |
+//! | 16 | `u32` | bit depth, from 8 to 16 |
+//! | 20 | `u32` | chroma sampling: 0 for 4:2:0, 1 for 4:2:2, 2 for 4:4:4, 3 for 4:0:0 |
+//! | 24 | `u32` | number of slots |
+//! | 28 | `u32` | reserved, 0 |
+//! | 32 | `u64` | size of a slot in bytes |
+//! | 40 | `u64` | frames written, atomic |
+//! | 48 | `u64` | frames read, atomic |
+//! | 56 | `u32` | closed flag, atomic |
+//! | 60 | `u32` | reserved, 0 |
+//!
+//! The slots follow the header. Frame `n` is in slot `n % slots`, which holds
+//! the Y, U and V planes one after the other, without padding between rows.
+//! Samples take one byte up to a bit depth of 8 and two little-endian bytes
+//! above. The size of a slot is the size of a frame rounded up to a multiple
+//! of 64 bytes.
+//!
+//! The producer waits until `written - read` is less than the number of
+//! slots, fills the slot of frame `written`, then increments `written` with
+//! release ordering. The consumer waits until `written` is greater than
+//! `read`, with acquire ordering, and increments `read` once it is done with
+//! the frame. When the producer has no more frames, it sets the closed flag.
+
+use crate::DecoderError;
+use av_metrics::video::decode::*;
+use av_metrics::video::*;
+use std::slice;
+use std::sync::atomic::{fence, AtomicU32, AtomicU64, Ordering};
+use std::thread;
+use std::time::Duration;
+
+/// The size of the header, after which the slots start.
+pub const HEADER_SIZE: usize = 64;
+
+const MAGIC: u32 = u32::from_ne_bytes(*b"AVMF");
+const VERSION: u32 = 1;
+const SLOT_ALIGNMENT: usize = 64;
+
+/// The format of the frames in a ring buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ShmFormat {
+    /// Width of the frames in pixels.
+    pub width: usize,
+    /// Height of the frames in pixels.
+    pub height: usize,
+    /// Bit depth of the samples.
+    pub bit_depth: usize,
+    /// Subsampling of the chroma planes.
+    pub chroma_sampling: ChromaSampling,
+}
+
+impl ShmFormat {
+    /// The format of the frames of a video.
+    pub fn from_details(details: &VideoDetails) -> Self {
+        ShmFormat {
+            width: details.width,
+            height: details.height,
+            bit_depth: details.bit_depth,
+            chroma_sampling: details.chroma_sampling,
+        }
+    }
+
+    fn bytes_per_sample(&self) -> usize {
+        if self.bit_depth > 8 {
+            2
+        } else {
+            1
+        }
+    }
+
+    fn plane_sizes(&self) -> Option<[usize; 3]> {
+        let bytes = self.bytes_per_sample();
+        let (chroma_width, chroma_height) = self
+            .chroma_sampling
+            .get_chroma_dimensions(self.width, self.height);
+        let chroma = chroma_width
+            .checked_mul(chroma_height)?
+            .checked_mul(bytes)?;
+        let luma = self.width.checked_mul(self.height)?.checked_mul(bytes)?;
+        Some([luma, chroma, chroma])
+    }
+
+    /// The size of one frame in bytes, or `None` if it overflows `usize`.
+    pub fn frame_size(&self) -> Option<usize> {
+        let [y, u, v] = self.plane_sizes()?;
+        y.checked_add(u)?.checked_add(v)
+    }
+
+    fn slot_size(&self) -> Option<usize> {
+        self.frame_size()?.checked_next_multiple_of(SLOT_ALIGNMENT)
+    }
+
+    /// The size in bytes of a ring buffer of `slots` frames, header included,
+    /// or `None` if it overflows `usize`.
+    pub fn ring_size(&self, slots: usize) -> Option<usize> {
+        slots
+            .checked_mul(self.slot_size()?)?
+            .checked_add(HEADER_SIZE)
+    }
+
+    /// The sizes of the planes and of a slot of a ring buffer of `slots`
+    /// frames that fits in `len` bytes.
+    fn layout(&self, slots: usize, len: usize) -> Result<([usize; 3], usize), DecoderError> {
+        let too_large = || DecoderError::MalformedInput {
+            reason: "Frame ring buffer is too large".to_owned(),
+        };
+        let plane_sizes = self.plane_sizes().ok_or_else(too_large)?;
+        let slot_size = self.slot_size().ok_or_else(too_large)?;
+        if len < self.ring_size(slots).ok_or_else(too_large)? {
+            return Err(DecoderError::MalformedInput {
+                reason: "Shared memory is too small for the frame ring buffer".to_owned(),
+            });
+        }
+        Ok((plane_sizes, slot_size))
+    }
+}
+
+fn chroma_sampling_code(chroma_sampling: ChromaSampling) -> u32 {
+    match chroma_sampling {
+        ChromaSampling::Cs420 => 0,
+        ChromaSampling::Cs422 => 1,
+        ChromaSampling::Cs444 => 2,
+        ChromaSampling::Cs400 => 3,
+    }
+}
+
+fn chroma_sampling_from_code(code: u32) -> Option<ChromaSampling> {
+    match code {
+        0 => Some(ChromaSampling::Cs420),
+        1 => Some(ChromaSampling::Cs422),
+        2 => Some(ChromaSampling::Cs444),
+        3 => Some(ChromaSampling::Cs400),
+        _ => None,
+    }
+}
+
+/// A view of the shared memory.
+struct Ring {
+    base: *mut u8,
+    format: ShmFormat,
+    slots: usize,
+    /// The sizes of the planes of a frame, which were checked to fit in the
+    /// memory along with the other slots.
+    plane_sizes: [usize; 3],
+    slot_size: usize,
+}
+
+// SAFETY: The memory is only accessed through atomics and slots that the
+// protocol hands over to one side at a time.
+unsafe impl Send for Ring {}
+
+impl Ring {
+    fn field(&self, offset: usize) -> *mut u8 {
+        // SAFETY: Every field is within the header, which was checked to be
+        // part of the memory.
+        unsafe { self.base.add(offset) }
+    }
+
+    fn magic(&self) -> &AtomicU32 {
+        // SAFETY: The base is aligned to 8 bytes, and the field is only
+        // accessed atomically.
+        unsafe { &*(self.field(0) as *const AtomicU32) }
+    }
+
+    fn written(&self) -> &AtomicU64 {
+        // SAFETY: As above.
+        unsafe { &*(self.field(40) as *const AtomicU64) }
+    }
+
+    fn read(&self) -> &AtomicU64 {
+        // SAFETY: As above.
+        unsafe { &*(self.field(48) as *const AtomicU64) }
+    }
+
+    fn closed(&self) -> &AtomicU32 {
+        // SAFETY: As above.
+        unsafe { &*(self.field(56) as *const AtomicU32) }
+    }
+
+    fn slot(&self, frame: u64) -> *mut u8 {
+        let index = (frame % self.slots as u64) as usize;
+        self.field(HEADER_SIZE + index * self.slot_size)
+    }
+}
+
+/// Waits a little longer each time it is called.
+fn backoff(attempts: &mut u32) {
+    if *attempts < 64 {
+        thread::yield_now();
+    } else {
+        thread::sleep(Duration::from_micros(100));
+    }
+    *attempts += 1;
+}
+
+fn check_memory(memory: *mut u8, len: usize) -> Result<(), DecoderError> {
+    if memory.is_null() || memory.align_offset(8) != 0 {
+        return Err(DecoderError::MalformedInput {
+            reason: "Shared memory must be aligned to 8 bytes".to_owned(),
+        });
+    }
+    if len < HEADER_SIZE {
+        return Err(DecoderError::MalformedInput {
+            reason: "Shared memory is too small for the header".to_owned(),
+        });
+    }
+    Ok(())
+}
+
+/// Reads frames that another process writes into a ring buffer in shared
+/// memory. See the [module documentation](self) for the protocol.
+///
+/// Reading a frame blocks until the producer has written it, and returns
+/// `None` once the producer has closed the ring buffer and every frame was
+/// read.
+pub struct ShmDecoder {
+    ring: Ring,
+    position: u64,
+}
+
+impl ShmDecoder {
+    /// Reads the frames of a ring buffer set up by a producer.
+    ///
+    /// # Safety
+    ///
+    /// `memory` must point to `len` bytes that stay mapped for the lifetime
+    /// of the decoder, and the producer must follow the protocol. Only one
+    /// decoder may read from a ring buffer.
+    pub unsafe fn open(memory: *mut u8, len: usize) -> Result<Self, DecoderError> {
+        check_memory(memory, len)?;
+        // SAFETY: The header was checked to be part of the memory, and the
+        // memory to be aligned for the magic.
+        let magic = unsafe { &*(memory as *const AtomicU32) };
+        if magic.load(Ordering::Acquire) != MAGIC {
+            return Err(DecoderError::MalformedInput {
+                reason: "Shared memory does not hold a frame ring buffer".to_owned(),
+            });
+        }
+        // SAFETY: As above.
+        let header = unsafe { slice::from_raw_parts(memory, HEADER_SIZE) };
+        let field = |offset: usize| {
+            u32::from_ne_bytes(header[offset..offset + 4].try_into().unwrap()) as usize
+        };
+        if field(4) != VERSION as usize {
+            return Err(DecoderError::UnsupportedInput {
+                reason: format!("Frame ring buffer version {}", field(4)),
+            });
+        }
+        let format = ShmFormat {
+            width: field(8),
+            height: field(12),
+            bit_depth: field(16),
+            chroma_sampling: chroma_sampling_from_code(field(20) as u32).ok_or_else(|| {
+                DecoderError::UnsupportedInput {
+                    reason: format!("Chroma sampling {}", field(20)),
+                }
+            })?,
+        };
+        if !(8..=16).contains(&format.bit_depth) {
+            return Err(DecoderError::UnsupportedInput {
+                reason: format!("Bit depth {}", format.bit_depth),
+            });
+        }
+        let slots = field(24);
+        let slot_size = u64::from_ne_bytes(header[32..40].try_into().unwrap());
+        // The header is untrusted, so the slot size it gives is only compared
+        // with the one of its format, which is computed without overflowing
+        if slots == 0 || format.slot_size().map(|size| size as u64) != Some(slot_size) {
+            return Err(DecoderError::MalformedInput {
+                reason: "Frame ring buffer header is inconsistent".to_owned(),
+            });
+        }
+        let (plane_sizes, slot_size) = format.layout(slots, len)?;
+        let ring = Ring {
+            base: memory,
+            format,
+            slots,
+            plane_sizes,
+            slot_size,
+        };
+        let position = ring.read().load(Ordering::Acquire);
+        Ok(ShmDecoder { ring, position })
+    }
+
+    /// Waits for the next frame, and returns its planes.
+    fn next_frame(&self) -> Option<[&[u8]; 3]> {
+        let mut attempts = 0;
+        loop {
+            if self.ring.written().load(Ordering::Acquire) > self.position {
+                break;
+            }
+            // A frame may have been written just before the ring buffer was closed
+            if self.ring.closed().load(Ordering::Acquire) != 0
+                && self.ring.written().load(Ordering::Acquire) <= self.position
+            {
+                return None;
+            }
+            backoff(&mut attempts);
+        }
+        let [y, u, v] = self.ring.plane_sizes;
+        // SAFETY: The slot is within the memory, and the producer does not
+        // write it again until the frame is released.
+        let data = unsafe { slice::from_raw_parts(self.ring.slot(self.position), y + u + v) };
+        let (y_plane, chroma) = data.split_at(y);
+        let (u_plane, v_plane) = chroma.split_at(u);
+        Some([y_plane, u_plane, v_plane])
+    }
+
+    /// Hands the slot of the last frame back to the producer.
+    fn release_frame(&mut self) {
+        self.position += 1;
+        self.ring.read().store(self.position, Ordering::Release);
+    }
+
+    fn fill_frame<T: Pixel>(&self, frame: &mut Frame<T>, planes: [&[u8]; 3]) {
+        let format = &self.ring.format;
+        let bytes = format.bytes_per_sample();
+        let (chroma_width, _) = format
+            .chroma_sampling
+            .get_chroma_dimensions(format.width, format.height);
+        frame.planes[0].copy_from_raw_u8(planes[0], format.width * bytes, bytes);
+        frame.planes[1].copy_from_raw_u8(planes[1], chroma_width * bytes, bytes);
+        frame.planes[2].copy_from_raw_u8(planes[2], chroma_width * bytes, bytes);
+    }
+}
+
+impl Decoder for ShmDecoder {
+    fn get_video_details(&self) -> VideoDetails {
+        let format = &self.ring.format;
+        VideoDetails {
+            width: format.width,
+            height: format.height,
+            bit_depth: format.bit_depth,
+            chroma_sampling: format.chroma_sampling,
+            ..Default::default()
+        }
+    }
+
+    fn read_video_frame<T: Pixel>(&mut self) -> Option<Frame<T>> {
+        let format = self.ring.format;
+        let mut frame =
+            Frame::new_with_padding(format.width, format.height, format.chroma_sampling, 0);
+        self.read_video_frame_into(&mut frame).then_some(frame)
+    }
+
+    fn read_video_frame_into<T: Pixel>(&mut self, frame: &mut Frame<T>) -> bool {
+        let Some(planes) = self.next_frame() else {
+            return false;
+        };
+        let details = self.get_video_details();
+        if !is_frame_reusable(frame, &details) {
+            *frame =
+                Frame::new_with_padding(details.width, details.height, details.chroma_sampling, 0);
+        }
+        self.fill_frame(frame, planes);
+        self.release_frame();
+        true
+    }
+
+    fn read_video_frame_ref<T: Pixel, U, F: FnOnce(FrameRef<'_, T>) -> U>(
+        &mut self,
+        f: F,
+    ) -> Option<U> {
+        let planes = self.next_frame()?;
+        let format = self.ring.format;
+        let bytes = format.bytes_per_sample();
+        let (chroma_width, chroma_height) = format
+            .chroma_sampling
+            .get_chroma_dimensions(format.width, format.height);
+        let views = (
+            PlaneRef::from_raw_u8(
+                planes[0],
+                format.width * bytes,
+                bytes,
+                format.width,
+                format.height,
+            ),
+            PlaneRef::from_raw_u8(
+                planes[1],
+                chroma_width * bytes,
+                bytes,
+                chroma_width,
+                chroma_height,
+            ),
+            PlaneRef::from_raw_u8(
+                planes[2],
+                chroma_width * bytes,
+                bytes,
+                chroma_width,
+                chroma_height,
+            ),
+        );
+        let result = if let (Some(y), Some(u), Some(v)) = views {
+            f(FrameRef { planes: [y, u, v] })
+        } else {
+            // The samples can't be borrowed as `T`
+            let mut frame =
+                Frame::new_with_padding(format.width, format.height, format.chroma_sampling, 0);
+            self.fill_frame(&mut frame, planes);
+            f(FrameRef::from(&frame))
+        };
+        self.release_frame();
+        Some(result)
+    }
+
+    fn get_bit_depth(&self) -> usize {
+        self.ring.format.bit_depth
+    }
+}
+
+/// Writes frames into a ring buffer in shared memory, for a [`ShmDecoder`]
+/// in this or another process to read.
+///
+/// The ring buffer is closed when the writer is dropped.
+pub struct ShmFrameWriter {
+    ring: Ring,
+    position: u64,
+}
+
+impl ShmFrameWriter {
+    /// Sets up a ring buffer of `slots` frames of `format` in `memory`.
+    ///
+    /// The size needed is given by [`ShmFormat::ring_size`].
+    ///
+    /// # Safety
+    ///
+    /// `memory` must point to `len` bytes that stay mapped for the lifetime
+    /// of the writer, and that nothing else writes to except a
+    /// [`ShmDecoder`].
+    pub unsafe fn create(
+        memory: *mut u8,
+        len: usize,
+        format: ShmFormat,
+        slots: usize,
+    ) -> Result<Self, DecoderError> {
+        check_memory(memory, len)?;
+        if !(8..=16).contains(&format.bit_depth) {
+            return Err(DecoderError::UnsupportedInput {
+                reason: format!("Bit depth {}", format.bit_depth),
+            });
+        }
+        if slots == 0 || slots > u32::MAX as usize {
+            return Err(DecoderError::MalformedInput {
+                reason: "A frame ring buffer needs at least one slot".to_owned(),
+            });
+        }
+        if format.width > u32::MAX as usize || format.height > u32::MAX as usize {
+            return Err(DecoderError::UnsupportedInput {
+                reason: format!("Frame size {}x{}", format.width, format.height),
+            });
+        }
+        let (plane_sizes, slot_size) = format.layout(slots, len)?;
+
+        let fields = [
+            VERSION,
+            format.width as u32,
+            format.height as u32,
+            format.bit_depth as u32,
+            chroma_sampling_code(format.chroma_sampling),
+            slots as u32,
+            0,
+        ];
+        // SAFETY: The header was checked to be part of the memory.
+        let header = unsafe { slice::from_raw_parts_mut(memory, HEADER_SIZE) };
+        header.fill(0);
+        for (i, field) in fields.iter().enumerate() {
+            header[4 + i * 4..8 + i * 4].copy_from_slice(&field.to_ne_bytes());
+        }
+        header[32..40].copy_from_slice(&(slot_size as u64).to_ne_bytes());
+
+        let ring = Ring {
+            base: memory,
+            format,
+            slots,
+            plane_sizes,
+            slot_size,
+        };
+        // The header is complete once a reader sees the magic
+        fence(Ordering::Release);
+        ring.magic().store(MAGIC, Ordering::Release);
+        Ok(ShmFrameWriter { ring, position: 0 })
+    }
+
+    /// Writes the next frame, waiting for a free slot if the reader is
+    /// behind.
+    pub fn write_frame<T: Pixel>(&mut self, frame: &Frame<T>) -> Result<(), DecoderError> {
+        let format = self.ring.format;
+        if frame.planes[0].cfg.width != format.width || frame.planes[0].cfg.height != format.height
+        {
+            return Err(DecoderError::MalformedInput {
+                reason: "Frame does not match the format of the ring buffer".to_owned(),
+            });
+        }
+        if (std::mem::size_of::<T>() == 1) != (format.bytes_per_sample() == 1) {
+            return Err(DecoderError::MalformedInput {
+                reason: "Frame does not match the bit depth of the ring buffer".to_owned(),
+            });
+        }
+
+        let mut attempts = 0;
+        while self.position - self.ring.read().load(Ordering::Acquire) >= self.ring.slots as u64 {
+            backoff(&mut attempts);
+        }
+
+        let sizes = self.ring.plane_sizes;
+        // SAFETY: The slot is within the memory, and the reader has released
+        // it.
+        let mut data =
+            unsafe { slice::from_raw_parts_mut(self.ring.slot(self.position), sizes.iter().sum()) };
+        for (plane, size) in frame.planes.iter().zip(sizes) {
+            let (dst, rest) = data.split_at_mut(size);
+            data = rest;
+            let width = plane.cfg.width;
+            if width == 0 {
+                continue;
+            }
+            let rows = plane.rows_iter().take(plane.cfg.height);
+            if format.bytes_per_sample() == 1 {
+                for (dst, row) in dst.chunks_exact_mut(width).zip(rows) {
+                    for (d, &s) in dst.iter_mut().zip(&row[..width]) {
+                        *d = u8::cast_from(s);
+                    }
+                }
+            } else {
+                for (dst, row) in dst.chunks_exact_mut(width * 2).zip(rows) {
+                    for (d, &s) in dst.chunks_exact_mut(2).zip(&row[..width]) {
+                        d.copy_from_slice(&u16::cast_from(s).to_le_bytes());
+                    }
+                }
+            }
+        }
+
+        self.position += 1;
+        self.ring.written().store(self.position, Ordering::Release);
+        Ok(())
+    }
+
+    /// Tells the reader that no more frames will be written.
+    pub fn close(&mut self) {
+        self.ring.closed().store(1, Ordering::Release);
+    }
+}
+
+impl Drop for ShmFrameWriter {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
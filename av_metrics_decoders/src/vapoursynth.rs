@@ -1,9 +1,10 @@
-use anyhow::{ensure, Result};
+use crate::DecoderError;
 use av_metrics::video::{
-    decode::{Decoder, Rational, VideoDetails},
+    decode::{Decoder, DecoderCapabilities, DetailsOverride, FrameMeta, Rational, VideoDetails},
     ChromaSampling,
 };
 use std::{
+    fmt::Display,
     mem::{size_of, transmute},
     path::Path,
 };
@@ -16,12 +17,26 @@ use vapoursynth::{
 /// A video decoder implementation using Vaopursynth
 pub struct VapoursynthDecoder {
     env: Environment,
+    details: VideoDetails,
     cur_frame: usize,
+    overrides: DetailsOverride,
+}
+
+fn backend_error<E: Display>(err: E) -> DecoderError {
+    DecoderError::Backend {
+        reason: err.to_string(),
+    }
+}
+
+fn unsupported(reason: &str) -> DecoderError {
+    DecoderError::UnsupportedInput {
+        reason: reason.to_owned(),
+    }
 }
 
 impl VapoursynthDecoder {
     /// Loads a video file using the default decoder plugin (currently `LSmashSource`)
-    pub fn new_from_video(filename: &Path) -> Result<Self> {
+    pub fn new_from_video(filename: &Path) -> Result<Self, DecoderError> {
         Self::new_from_video_with_decoder(filename, VapoursynthDecoderPlugin::default())
     }
 
@@ -29,11 +44,15 @@ impl VapoursynthDecoder {
     pub fn new_from_video_with_decoder(
         filename: &Path,
         plugin: VapoursynthDecoderPlugin,
-    ) -> Result<Self> {
+    ) -> Result<Self, DecoderError> {
+        let filename = filename.canonicalize()?;
+        // The path is embedded in a Python script, so it has to be valid Unicode.
+        // Converting it lossily would silently open the wrong file.
         let escaped_filename = filename
-            .canonicalize()
-            .unwrap()
-            .to_string_lossy()
+            .to_str()
+            .ok_or_else(|| DecoderError::Io {
+                reason: format!("Path is not valid Unicode: {}", filename.display()),
+            })?
             .trim_start_matches(r"\\?\")
             .replace('\\', "\\\\")
             .replace('"', "\\\"");
@@ -57,59 +76,57 @@ clip.set_output(0)
                 ""
             }
         );
-        let env = Environment::from_script(&script)?;
-        let this = Self { env, cur_frame: 0 };
-        this.get_node()?;
-        ensure!(
-            this.get_format()?.sample_type() == SampleType::Integer,
-            "Currently only integer input is supported"
-        );
-        Ok(this)
+        let env = Environment::from_script(&script).map_err(backend_error)?;
+        Self::from_environment(env)
     }
 
     /// Loads a `.vpy` script
-    pub fn new_from_script(filename: &Path) -> Result<Self> {
-        let env = Environment::from_file(filename, EvalFlags::SetWorkingDir)?;
-        let this = Self { env, cur_frame: 0 };
-        this.get_node()?;
-        ensure!(
-            this.get_format()?.sample_type() == SampleType::Integer,
-            "Currently only integer input is supported"
-        );
-        Ok(this)
-    }
-
-    fn get_node(&self) -> Result<Node<'_>> {
-        Ok(self.env.get_output(0)?.0)
+    pub fn new_from_script(filename: &Path) -> Result<Self, DecoderError> {
+        let env =
+            Environment::from_file(filename, EvalFlags::SetWorkingDir).map_err(backend_error)?;
+        Self::from_environment(env)
     }
 
-    fn get_resolution(&self) -> Result<Resolution> {
-        match self.get_node()?.info().resolution {
-            Property::Constant(res) => Ok(res),
-            Property::Variable => Err(anyhow::anyhow!(
-                "Variable resolution videos are not supported"
-            )),
+    /// Checks that the output of `env` is supported, and reads its details
+    /// once, so that decoding doesn't have to handle a changing format.
+    fn from_environment(env: Environment) -> Result<Self, DecoderError> {
+        let info = env.get_output(0).map_err(backend_error)?.0.info();
+        let Property::Constant(format) = info.format else {
+            return Err(unsupported("Variable format videos are not supported"));
+        };
+        let Property::Constant(res) = info.resolution else {
+            return Err(unsupported("Variable resolution videos are not supported"));
+        };
+        let Property::Constant(fps) = info.framerate else {
+            return Err(unsupported("Variable frame rate videos are not supported"));
+        };
+        if format.sample_type() != SampleType::Integer {
+            return Err(unsupported("Currently only integer input is supported"));
+        }
+        let bit_depth = format.bits_per_sample() as usize;
+        if bit_depth > 16 {
+            return Err(unsupported("Bit depths above 16 are not supported"));
         }
+        let details = video_details(format, res, fps);
+        Ok(Self {
+            env,
+            details,
+            cur_frame: 0,
+            overrides: DetailsOverride::default(),
+        })
     }
 
-    fn get_format(&self) -> Result<Format<'_>> {
-        match self.get_node()?.info().format {
-            Property::Constant(format) => Ok(format),
-            Property::Variable => Err(anyhow::anyhow!("Variable format videos are not supported")),
-        }
+    fn get_node(&self) -> Result<Node<'_>, DecoderError> {
+        Ok(self.env.get_output(0).map_err(backend_error)?.0)
     }
 
-    fn get_frame_rate(&self) -> Result<Framerate> {
-        match self.get_node()?.info().framerate {
-            Property::Constant(fps) => Ok(fps),
-            Property::Variable => Err(anyhow::anyhow!(
-                "Variable frameratevideos are not supported"
-            )),
-        }
+    /// Replaces details of the video reported by Vapoursynth.
+    pub fn set_overrides(&mut self, overrides: DetailsOverride) {
+        self.overrides = overrides;
     }
 
     /// Returns the number of frames in this video
-    pub fn get_frame_count(&self) -> Result<usize> {
+    pub fn get_frame_count(&self) -> Result<usize, DecoderError> {
         Ok(self.get_node()?.info().num_frames)
     }
 }
@@ -119,12 +136,8 @@ impl Decoder for VapoursynthDecoder {
         &mut self,
     ) -> Option<av_metrics::video::Frame<T>> {
         let details = self.get_video_details();
-        if details.bit_depth <= 8 {
-            assert!(size_of::<T>() == 1);
-        } else if details.bit_depth <= 16 {
-            assert!(size_of::<T>() == 2);
-        } else {
-            panic!("Unsupported bit depth");
+        if (details.bit_depth <= 8) != (size_of::<T>() == 1) {
+            return None;
         }
 
         let mut f: av_metrics::video::Frame<T> = av_metrics::video::Frame::new_with_padding(
@@ -135,11 +148,7 @@ impl Decoder for VapoursynthDecoder {
         );
 
         {
-            let frame = self.get_node().unwrap().get_frame(self.cur_frame);
-            if frame.is_err() {
-                return None;
-            }
-            let frame = frame.unwrap();
+            let frame = self.get_node().ok()?.get_frame(self.cur_frame).ok()?;
             match size_of::<T>() {
                 1 => {
                     for (out_row, in_row) in f.planes[0]
@@ -152,7 +161,7 @@ impl Decoder for VapoursynthDecoder {
                     if details.chroma_sampling != ChromaSampling::Cs400 {
                         for (out_row, in_row) in f.planes[1].rows_iter_mut().zip(
                             (0..(details.height
-                                >> details.chroma_sampling.get_decimation().unwrap().1))
+                                >> details.chroma_sampling.get_decimation().unwrap_or((0, 0)).1))
                                 .map(|y| frame.plane_row::<u8>(1, y)),
                         ) {
                             // SAFETY: We know that `T` is `u8` here.
@@ -162,7 +171,7 @@ impl Decoder for VapoursynthDecoder {
                     if details.chroma_sampling != ChromaSampling::Cs400 {
                         for (out_row, in_row) in f.planes[2].rows_iter_mut().zip(
                             (0..(details.height
-                                >> details.chroma_sampling.get_decimation().unwrap().1))
+                                >> details.chroma_sampling.get_decimation().unwrap_or((0, 0)).1))
                                 .map(|y| frame.plane_row::<u8>(2, y)),
                         ) {
                             // SAFETY: We know that `T` is `u8` here.
@@ -181,7 +190,7 @@ impl Decoder for VapoursynthDecoder {
                     if details.chroma_sampling != ChromaSampling::Cs400 {
                         for (out_row, in_row) in f.planes[1].rows_iter_mut().zip(
                             (0..(details.height
-                                >> details.chroma_sampling.get_decimation().unwrap().1))
+                                >> details.chroma_sampling.get_decimation().unwrap_or((0, 0)).1))
                                 .map(|y| frame.plane_row::<u16>(1, y)),
                         ) {
                             // SAFETY: We know that `T` is `u16` here.
@@ -191,7 +200,7 @@ impl Decoder for VapoursynthDecoder {
                     if details.chroma_sampling != ChromaSampling::Cs400 {
                         for (out_row, in_row) in f.planes[2].rows_iter_mut().zip(
                             (0..(details.height
-                                >> details.chroma_sampling.get_decimation().unwrap().1))
+                                >> details.chroma_sampling.get_decimation().unwrap_or((0, 0)).1))
                                 .map(|y| frame.plane_row::<u16>(2, y)),
                         ) {
                             // SAFETY: We know that `T` is `u16` here.
@@ -208,33 +217,70 @@ impl Decoder for VapoursynthDecoder {
     }
 
     fn get_bit_depth(&self) -> usize {
-        let format = self.get_format().unwrap();
-        format.bits_per_sample() as usize
+        self.details.bit_depth
     }
 
-    fn get_video_details(&self) -> VideoDetails {
-        let format = self.get_format().unwrap();
-        let res = self.get_resolution().unwrap();
-        let fps = self.get_frame_rate().unwrap();
-        let chroma = match (
-            format.color_family(),
-            format.sub_sampling_w() + format.sub_sampling_h(),
-        ) {
-            (ColorFamily::Gray, _) => ChromaSampling::Cs400,
-            (_, 0) => ChromaSampling::Cs444,
-            (_, 1) => ChromaSampling::Cs422,
-            _ => ChromaSampling::Cs420,
-        };
-        VideoDetails {
-            width: res.width,
-            height: res.height,
-            bit_depth: format.bits_per_sample() as usize,
-            chroma_sampling: chroma,
-            chroma_sample_position: av_metrics::video::ChromaSamplePosition::Unknown,
-            time_base: Rational::new(fps.denominator, fps.numerator),
-            luma_padding: 0,
+    fn read_specific_frame<T: av_metrics::video::Pixel>(
+        &mut self,
+        frame_number: usize,
+    ) -> Option<av_metrics::video::Frame<T>> {
+        self.cur_frame = frame_number;
+        self.read_video_frame()
+    }
+
+    fn last_frame_meta(&self) -> Option<FrameMeta> {
+        let number = self.cur_frame.checked_sub(1)?;
+        Some(FrameMeta::from_number(
+            number,
+            self.get_video_details().time_base,
+        ))
+    }
+
+    fn capabilities(&self) -> DecoderCapabilities {
+        DecoderCapabilities {
+            seek: true,
+            frame_count: true,
+            ..Default::default()
         }
     }
+
+    fn frame_count(&self) -> Option<usize> {
+        self.get_frame_count().ok()
+    }
+
+    // Vapoursynth already knows the number of frames and can seek to any of
+    // them.
+    fn build_index(&mut self) -> Option<usize> {
+        self.frame_count()
+    }
+
+    fn get_video_details(&self) -> VideoDetails {
+        let mut details = self.details;
+        self.overrides.apply(&mut details);
+        details
+    }
+}
+
+fn video_details(format: Format<'_>, res: Resolution, fps: Framerate) -> VideoDetails {
+    let chroma = match (
+        format.color_family(),
+        format.sub_sampling_w() + format.sub_sampling_h(),
+    ) {
+        (ColorFamily::Gray, _) => ChromaSampling::Cs400,
+        (_, 0) => ChromaSampling::Cs444,
+        (_, 1) => ChromaSampling::Cs422,
+        _ => ChromaSampling::Cs420,
+    };
+    VideoDetails {
+        width: res.width,
+        height: res.height,
+        bit_depth: format.bits_per_sample() as usize,
+        chroma_sampling: chroma,
+        chroma_sample_position: av_metrics::video::ChromaSamplePosition::Unknown,
+        time_base: Rational::new(fps.denominator, fps.numerator),
+        luma_padding: 0,
+        ..Default::default()
+    }
 }
 
 #[derive(Clone, Copy, Debug, Default, PartialEq)]
@@ -0,0 +1,97 @@
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+use crate::DecoderError;
+use av_metrics::audio::decode::*;
+
+impl From<hound::Error> for DecoderError {
+    fn from(err: hound::Error) -> Self {
+        match err {
+            hound::Error::IoError(err) => err.into(),
+            hound::Error::Unsupported => DecoderError::UnsupportedInput {
+                reason: err.to_string(),
+            },
+            _ => DecoderError::MalformedInput {
+                reason: err.to_string(),
+            },
+        }
+    }
+}
+
+/// The number of samples of each channel returned per frame.
+const FRAME_LEN: usize = 4096;
+
+/// An interface that is used for decoding WAV audio using `hound`
+pub struct WavDecoder<R: Read + Send> {
+    reader: hound::WavReader<R>,
+    details: AudioDetails,
+}
+
+impl WavDecoder<BufReader<File>> {
+    /// Initialize a new WAV decoder for a given input file
+    pub fn open<P: AsRef<Path>>(input: P) -> Result<Self, DecoderError> {
+        let reader = hound::WavReader::open(input)?;
+        Self::from_reader(reader)
+    }
+}
+
+impl<R: Read + Send> WavDecoder<R> {
+    /// Initialize a new WAV decoder for a given reader
+    pub fn new(input: R) -> Result<Self, DecoderError> {
+        let reader = hound::WavReader::new(input)?;
+        Self::from_reader(reader)
+    }
+
+    fn from_reader(reader: hound::WavReader<R>) -> Result<Self, DecoderError> {
+        let spec = reader.spec();
+        let sample_format = match (spec.sample_format, spec.bits_per_sample) {
+            (hound::SampleFormat::Int, 8) => SampleFormat::U8,
+            (hound::SampleFormat::Int, 16) => SampleFormat::I16,
+            (hound::SampleFormat::Int, 24) => SampleFormat::I24,
+            (hound::SampleFormat::Int, 32) => SampleFormat::I32,
+            (hound::SampleFormat::Float, 32) => SampleFormat::F32,
+            (format, bits) => {
+                return Err(DecoderError::UnsupportedInput {
+                    reason: format!("Sample format {:?} {}", format, bits),
+                });
+            }
+        };
+        Ok(Self {
+            details: AudioDetails {
+                sample_rate: spec.sample_rate,
+                channels: spec.channels as usize,
+                sample_format,
+            },
+            reader,
+        })
+    }
+}
+
+impl<R: Read + Send> AudioDecoder for WavDecoder<R> {
+    fn read_audio_frame(&mut self) -> Option<AudioFrame> {
+        let count = FRAME_LEN * self.details.channels;
+        let samples: Vec<f32> = if self.details.sample_format.is_float() {
+            self.reader
+                .samples::<f32>()
+                .take(count)
+                .map_while(Result::ok)
+                .collect()
+        } else {
+            // hound centers 8-bit samples on 0 like the wider formats.
+            let scale = (1u64 << (self.details.sample_format.bits() - 1)) as f32;
+            self.reader
+                .samples::<i32>()
+                .take(count)
+                .map_while(Result::ok)
+                .map(|sample| sample as f32 / scale)
+                .collect()
+        };
+        let frame = AudioFrame::from_interleaved(self.details.channels, samples);
+        (!frame.is_empty()).then_some(frame)
+    }
+
+    fn get_audio_details(&self) -> AudioDetails {
+        self.details
+    }
+}
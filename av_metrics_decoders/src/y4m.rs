@@ -1,20 +1,31 @@
+use crate::DecoderError;
 use av_metrics::video::decode::*;
 use av_metrics::video::*;
 use std::fs::File;
 use std::io::{stdin, BufReader, Read, Stdin};
-use std::path::Path;
+use std::mem::size_of;
+use std::path::{Path, PathBuf};
 
 /// A decoder for a y4m input stream
 pub struct Y4MDecoder<R: Read + Send> {
     inner: y4m::Decoder<R>,
+    chroma: (ChromaSampling, ChromaSamplePosition),
+    overrides: DetailsOverride,
+    frames_read: usize,
+    /// The file the input was opened from, which can be scanned again by
+    /// `build_index`
+    source: Option<PathBuf>,
+    frame_count: Option<usize>,
 }
 
 /// Function to map y4m color space
-fn map_y4m_color_space(color_space: y4m::Colorspace) -> (ChromaSampling, ChromaSamplePosition) {
+fn map_y4m_color_space(
+    color_space: y4m::Colorspace,
+) -> Option<(ChromaSampling, ChromaSamplePosition)> {
     use av_metrics::video::ChromaSamplePosition::*;
     use av_metrics::video::ChromaSampling::*;
     use y4m::Colorspace::*;
-    match color_space {
+    Some(match color_space {
         Cmono | Cmono12 => (Cs400, Unknown),
         C420jpeg => (Cs420, Bilateral),
         C420paldv => (Cs420, Interpolated),
@@ -22,24 +33,85 @@ fn map_y4m_color_space(color_space: y4m::Colorspace) -> (ChromaSampling, ChromaS
         C420 | C420p10 | C420p12 => (Cs420, Colocated),
         C422 | C422p10 | C422p12 => (Cs422, Vertical),
         C444 | C444p10 | C444p12 => (Cs444, Colocated),
-        _ => unimplemented!(),
+        _ => return None,
+    })
+}
+
+impl From<y4m::Error> for DecoderError {
+    fn from(err: y4m::Error) -> Self {
+        match err {
+            y4m::Error::IoError(err) => err.into(),
+            y4m::Error::UnknownColorspace => DecoderError::UnsupportedInput {
+                reason: err.to_string(),
+            },
+            _ => DecoderError::MalformedInput {
+                reason: err.to_string(),
+            },
+        }
+    }
+}
+
+/// Parses the interlacing (`I`) and vendor extension (`X`) parameters of a
+/// y4m header into `details`. Unknown values are ignored.
+fn parse_header_params(params: &[u8], details: &mut VideoDetails) {
+    for param in params.split(|&b| b == b' ') {
+        match param {
+            b"Ip" => details.interlacing = Interlacing::Progressive,
+            b"It" => details.interlacing = Interlacing::TopFieldFirst,
+            b"Ib" => details.interlacing = Interlacing::BottomFieldFirst,
+            b"Im" => details.interlacing = Interlacing::Mixed,
+            b"XCOLORRANGE=FULL" => details.color_range = ColorRange::Full,
+            b"XCOLORRANGE=LIMITED" => details.color_range = ColorRange::Limited,
+            _ => (),
+        }
     }
 }
 
 /// Initialize a new Y4M decoder for a given input file
 pub fn new_decoder_from_file<P: AsRef<Path>>(
     input: P,
-) -> Result<Y4MDecoder<BufReader<File>>, String> {
-    let file = File::open(input).map_err(|e| e.to_string())?;
-    let inner = y4m::Decoder::new(BufReader::new(file)).map_err(|e| e.to_string())?;
-    Ok(Y4MDecoder { inner })
+) -> Result<Y4MDecoder<BufReader<File>>, DecoderError> {
+    let file = File::open(&input)?;
+    let mut decoder = Y4MDecoder::new(BufReader::new(file))?;
+    decoder.source = Some(input.as_ref().to_path_buf());
+    Ok(decoder)
 }
 
 /// Initialize a new Y4M decoder from stdin
-pub fn new_decoder_from_stdin() -> Result<Y4MDecoder<BufReader<Stdin>>, String> {
-    Ok(Y4MDecoder {
-        inner: y4m::Decoder::new(BufReader::new(stdin())).map_err(|e| e.to_string())?,
-    })
+pub fn new_decoder_from_stdin() -> Result<Y4MDecoder<BufReader<Stdin>>, DecoderError> {
+    Y4MDecoder::new(BufReader::new(stdin()))
+}
+
+impl<R: Read + Send> Y4MDecoder<R> {
+    /// Initialize a new Y4M decoder reading from `input`
+    pub fn new(input: R) -> Result<Self, DecoderError> {
+        let inner = y4m::Decoder::new(input)?;
+        let color_space = inner.get_colorspace();
+        let chroma =
+            map_y4m_color_space(color_space).ok_or_else(|| DecoderError::UnsupportedInput {
+                reason: format!("y4m color space {:?}", color_space),
+            })?;
+        Ok(Y4MDecoder {
+            inner,
+            chroma,
+            overrides: DetailsOverride::default(),
+            frames_read: 0,
+            source: None,
+            frame_count: None,
+        })
+    }
+
+    /// Replaces details of the video reported by the y4m header, such as a
+    /// wrong chroma sample position.
+    pub fn set_overrides(&mut self, overrides: DetailsOverride) {
+        self.overrides = overrides;
+    }
+
+    /// The number of bytes of each sample, if they fit in a `T`.
+    fn bytes_per_sample<T: Pixel>(&self) -> Option<usize> {
+        let bytes = self.inner.get_bytes_per_sample();
+        (bytes <= size_of::<T>()).then_some(bytes)
+    }
 }
 
 impl<R> Decoder for Y4MDecoder<R>
@@ -51,12 +123,19 @@ where
         let height = self.inner.get_height();
         let color_space = self.inner.get_colorspace();
         let bit_depth = color_space.get_bit_depth();
-        let (chroma_sampling, chroma_sample_position) = map_y4m_color_space(color_space);
+        let (chroma_sampling, chroma_sample_position) = self.chroma;
         let framerate = self.inner.get_framerate();
         let time_base = Rational::new(framerate.den as u64, framerate.num as u64);
         let luma_padding = 0;
+        let pixel_aspect = self.inner.get_pixel_aspect();
+        // 0:0 means that the aspect ratio is unknown
+        let sample_aspect_ratio = if pixel_aspect.num > 0 && pixel_aspect.den > 0 {
+            Rational::new(pixel_aspect.num as u64, pixel_aspect.den as u64)
+        } else {
+            Rational::new(1, 1)
+        };
 
-        VideoDetails {
+        let mut details = VideoDetails {
             width,
             height,
             bit_depth,
@@ -64,43 +143,158 @@ where
             chroma_sample_position,
             time_base,
             luma_padding,
-        }
+            sample_aspect_ratio,
+            ..Default::default()
+        };
+        parse_header_params(self.inner.get_raw_params(), &mut details);
+        self.overrides.apply(&mut details);
+        details
     }
 
     fn read_video_frame<T: Pixel>(&mut self) -> Option<Frame<T>> {
-        let bit_depth = self.inner.get_bit_depth();
-        let color_space = self.inner.get_colorspace();
-        let (chroma_sampling, chroma_sample_pos) = map_y4m_color_space(color_space);
-        let width = self.inner.get_width();
-        let height = self.inner.get_height();
-        let bytes = self.inner.get_bytes_per_sample();
-        self.inner.read_frame().ok().map(|frame| {
-            let mut f: Frame<T> = Frame::new_with_padding(width, height, chroma_sampling, 0);
-
-            let (chroma_width, _) = chroma_sampling.get_chroma_dimensions(width, height);
-            f.planes[0].copy_from_raw_u8(frame.get_y_plane(), width * bytes, bytes);
-            convert_chroma_data(
-                &mut f.planes[1],
-                chroma_sample_pos,
-                bit_depth,
-                frame.get_u_plane(),
-                chroma_width * bytes,
-                bytes,
-            );
-            convert_chroma_data(
-                &mut f.planes[2],
-                chroma_sample_pos,
-                bit_depth,
-                frame.get_v_plane(),
-                chroma_width * bytes,
-                bytes,
-            );
+        let details = self.get_video_details();
+        let bytes = self.bytes_per_sample::<T>()?;
+        let frame = self.inner.read_frame().ok()?;
+        self.frames_read += 1;
+        Some(copy_frame(&frame, &details, bytes))
+    }
 
-            f
-        })
+    fn read_video_frame_into<T: Pixel>(&mut self, frame: &mut Frame<T>) -> bool {
+        let details = self.get_video_details();
+        let Some(bytes) = self.bytes_per_sample::<T>() else {
+            return false;
+        };
+        match self.inner.read_frame() {
+            Ok(data) => {
+                self.frames_read += 1;
+                if is_frame_reusable(frame, &details) {
+                    fill_frame(frame, &data, &details, bytes);
+                } else {
+                    *frame = copy_frame(&data, &details, bytes);
+                }
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    fn read_video_frame_ref<T: Pixel, U, F: FnOnce(FrameRef<'_, T>) -> U>(
+        &mut self,
+        f: F,
+    ) -> Option<U> {
+        let details = self.get_video_details();
+        let bytes = self.bytes_per_sample::<T>()?;
+        let frame = self.inner.read_frame().ok()?;
+        self.frames_read += 1;
+        if details.chroma_sample_position != ChromaSamplePosition::Vertical {
+            let (width, height) = (details.width, details.height);
+            let (chroma_width, chroma_height) =
+                details.chroma_sampling.get_chroma_dimensions(width, height);
+            let planes = (
+                PlaneRef::from_raw_u8(frame.get_y_plane(), width * bytes, bytes, width, height),
+                PlaneRef::from_raw_u8(
+                    frame.get_u_plane(),
+                    chroma_width * bytes,
+                    bytes,
+                    chroma_width,
+                    chroma_height,
+                ),
+                PlaneRef::from_raw_u8(
+                    frame.get_v_plane(),
+                    chroma_width * bytes,
+                    bytes,
+                    chroma_width,
+                    chroma_height,
+                ),
+            );
+            if let (Some(y), Some(u), Some(v)) = planes {
+                return Some(f(FrameRef { planes: [y, u, v] }));
+            }
+        }
+        // The chroma needs to be repositioned, or the samples can't be borrowed as `T`.
+        let frame: Frame<T> = copy_frame(&frame, &details, bytes);
+        Some(f(FrameRef::from(&frame)))
     }
 
     fn get_bit_depth(&self) -> usize {
         self.inner.get_bit_depth()
     }
+
+    fn get_stream_info(&self) -> StreamInfo {
+        StreamInfo {
+            codec: Some("rawvideo".to_owned()),
+            container: Some("yuv4mpegpipe".to_owned()),
+            ..StreamInfo::from(&self.get_video_details())
+        }
+    }
+
+    fn last_frame_meta(&self) -> Option<FrameMeta> {
+        let number = self.frames_read.checked_sub(1)?;
+        Some(FrameMeta::from_number(
+            number,
+            self.get_video_details().time_base,
+        ))
+    }
+
+    fn capabilities(&self) -> DecoderCapabilities {
+        DecoderCapabilities {
+            metadata: true,
+            frame_count: self.frame_count.is_some(),
+            ..Default::default()
+        }
+    }
+
+    fn frame_count(&self) -> Option<usize> {
+        self.frame_count
+    }
+
+    // Only inputs opened from a file can be read a second time. The frames
+    // are read without converting them, since y4m stores raw samples.
+    fn build_index(&mut self) -> Option<usize> {
+        if self.frame_count.is_none() {
+            let file = File::open(self.source.as_ref()?).ok()?;
+            let mut scan = y4m::Decoder::new(BufReader::new(file)).ok()?;
+            let mut frames = 0;
+            while scan.read_frame().is_ok() {
+                frames += 1;
+            }
+            self.frame_count = Some(frames);
+        }
+        self.frame_count
+    }
+}
+
+fn copy_frame<T: Pixel>(frame: &y4m::Frame, details: &VideoDetails, bytes: usize) -> Frame<T> {
+    let mut f: Frame<T> =
+        Frame::new_with_padding(details.width, details.height, details.chroma_sampling, 0);
+    fill_frame(&mut f, frame, details, bytes);
+    f
+}
+
+fn fill_frame<T: Pixel>(
+    f: &mut Frame<T>,
+    frame: &y4m::Frame,
+    details: &VideoDetails,
+    bytes: usize,
+) {
+    let (chroma_width, _) = details
+        .chroma_sampling
+        .get_chroma_dimensions(details.width, details.height);
+    f.planes[0].copy_from_raw_u8(frame.get_y_plane(), details.width * bytes, bytes);
+    convert_chroma_data(
+        &mut f.planes[1],
+        details.chroma_sample_position,
+        details.bit_depth,
+        frame.get_u_plane(),
+        chroma_width * bytes,
+        bytes,
+    );
+    convert_chroma_data(
+        &mut f.planes[2],
+        details.chroma_sample_position,
+        details.bit_depth,
+        frame.get_v_plane(),
+        chroma_width * bytes,
+        bytes,
+    );
 }
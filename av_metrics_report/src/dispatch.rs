@@ -0,0 +1,141 @@
+//! Computes the video metrics of a comparison by their command line name, so
+//! that every frontend computes them the same way and with the same settings.
+
+use crate::MetricsResults;
+use av_metrics::video::decode::Decoder;
+use av_metrics::video::vmaf::VmafModel;
+use av_metrics::video::*;
+use std::error::Error;
+
+/// The full-reference video metrics, by command line name, in the order the
+/// frontends compute them.
+pub const VIDEO_METRICS: [&str; 7] = [
+    "psnr",
+    "apsnr",
+    "psnrhvs",
+    "ssim",
+    "msssim",
+    "ciede2000",
+    "vmaf",
+];
+
+/// The settings of the video metrics that the frontends let users choose.
+#[derive(Debug, Clone, Default)]
+pub struct MetricSettings {
+    /// The weights of the planes in the averages of PSNR, APSNR, SSIM and
+    /// MS-SSIM
+    pub plane_weights: PlaneWeights,
+    /// Whether CIEDE2000 adds up the rows of each frame in row order, so that
+    /// its results are bit-identical across runs and thread counts
+    pub deterministic: bool,
+    /// The model VMAF is computed with. VMAF cannot be computed without one
+    pub vmaf_model: Option<VmafModel>,
+}
+
+/// The name of a metric of [`VIDEO_METRICS`] as shown to users, such as
+/// `PSNR-HVS` for `psnrhvs`.
+pub fn display_name(metric: &str) -> &'static str {
+    match metric {
+        "psnr" => "PSNR",
+        "apsnr" => "APSNR",
+        "psnrhvs" => "PSNR-HVS",
+        "ssim" => "SSIM",
+        "msssim" => "MSSSIM",
+        "ciede2000" => "CIEDE2000",
+        "vmaf" => "VMAF",
+        _ => "",
+    }
+}
+
+/// Computes `metric`, one of [`VIDEO_METRICS`], comparing the frames of
+/// `decoder2` with those of `decoder1`.
+pub fn compute_video_metric<D: Decoder, F: Fn(usize) + Send>(
+    metric: &str,
+    decoder1: &mut D,
+    decoder2: &mut D,
+    settings: &MetricSettings,
+    video: VideoOptions<F>,
+) -> Result<Score, Box<dyn Error>> {
+    let weights = settings.plane_weights;
+    Ok(match metric {
+        "psnr" => Score::Planar(psnr::calculate_video_psnr_with(
+            decoder1,
+            decoder2,
+            psnr::PsnrOptions {
+                video,
+                config: Default::default(),
+            }
+            .weights(weights),
+        )?),
+        "apsnr" => Score::Planar(psnr::calculate_video_apsnr_with(
+            decoder1,
+            decoder2,
+            psnr::PsnrOptions {
+                video,
+                config: Default::default(),
+            }
+            .weights(weights),
+        )?),
+        "psnrhvs" => Score::Planar(psnr_hvs::calculate_video_psnr_hvs_with(
+            decoder1, decoder2, video,
+        )?),
+        "ssim" => Score::Planar(ssim::calculate_video_ssim_with(
+            decoder1,
+            decoder2,
+            ssim::SsimOptions {
+                video,
+                config: Default::default(),
+            }
+            .weights(weights),
+        )?),
+        "msssim" => Score::Planar(ssim::calculate_video_msssim_with(
+            decoder1,
+            decoder2,
+            ssim::MsSsimOptions {
+                video,
+                config: Default::default(),
+            }
+            .weights(weights),
+        )?),
+        "ciede2000" => Score::Value(ciede::calculate_video_ciede_with(
+            decoder1,
+            decoder2,
+            ciede::CiedeOptions {
+                video,
+                config: Default::default(),
+            }
+            .deterministic(settings.deterministic),
+        )?),
+        "vmaf" => {
+            let model = settings
+                .vmaf_model
+                .as_ref()
+                .ok_or("No VMAF model was given")?;
+            Score::Value(
+                vmaf::calculate_video_vmaf_with_model(decoder1, decoder2, model, video)?.mean,
+            )
+        }
+        _ => return Err(format!("Unknown metric {}", metric).into()),
+    })
+}
+
+impl MetricsResults {
+    /// Stores `score`, the score of `metric` as returned by
+    /// [`compute_video_metric`], in its field.
+    pub fn set_score(&mut self, metric: &str, score: Score) {
+        let planar = match score {
+            Score::Planar(planar) => Some(planar),
+            Score::Value(_) => None,
+        };
+        match metric {
+            "psnr" => self.psnr = planar,
+            "apsnr" => self.apsnr = planar,
+            "psnrhvs" => self.psnr_hvs = planar,
+            "ssim" => self.ssim = planar,
+            "msssim" => self.msssim = planar,
+            "ciede2000" => self.ciede2000 = Some(score.value()),
+            "vmaf" => self.vmaf = Some(score.value()),
+            _ => {}
+        }
+    }
+}
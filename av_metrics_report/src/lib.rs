@@ -0,0 +1,596 @@
+//! Result reports shared by the av-metrics frontends.
+//!
+//! A [`Report`] holds the scores of one or more comparisons against the same
+//! base input, and can be written as JSON, XML, YAML, CSV or Markdown.
+
+#![deny(missing_docs)]
+
+use av_metrics::audio::peaq::PeaqResult;
+use av_metrics::audio::spectral::SpectralMetrics;
+use av_metrics::video::align::Shift;
+use av_metrics::video::banding::BandingResults;
+use av_metrics::video::bootstrap::ConfidenceInterval;
+use av_metrics::video::decode::{Rational, StreamInfo};
+use av_metrics::video::gop::GopScores;
+use av_metrics::video::histogram::Histogram;
+use av_metrics::video::rate_control::LinearFit;
+use av_metrics::video::tiles::TileScores;
+use av_metrics::video::worst::FrameScore;
+use av_metrics::video::{ChromaSampling, PlanarMetrics};
+use round::Rounded;
+use serde::Serialize;
+use std::io::Write;
+use std::time::Duration;
+
+pub mod dispatch;
+mod round;
+
+/// The scores of one input compared to the base input, along with the
+/// format of the inputs.
+///
+/// For audio inputs, the video format fields are left at 0, the sample rate
+/// and channel count are set instead, and `frames` counts analysis windows.
+#[derive(Debug, Clone, Serialize, Default)]
+pub struct MetricsResults {
+    /// The name of the compared input
+    pub filename: String,
+    /// The name of the base input this input was compared to, if it is not
+    /// the base input of the report, as in batches listing several base inputs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub base: Option<String>,
+    /// The label given to the comparison
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    /// The width of the input, in pixels
+    pub width: usize,
+    /// The height of the input, in pixels
+    pub height: usize,
+    /// The bit depth of the input
+    pub bit_depth: usize,
+    /// The chroma sampling of the input, as returned by [`chroma_sampling_name`]
+    pub chroma_sampling: &'static str,
+    /// The number of frames compared
+    pub frames: usize,
+    /// The codec, container and other metadata of the input
+    pub stream_info: StreamInfo,
+    /// The width of a pixel of the input relative to its height, for
+    /// anamorphic inputs whose pixels are not square
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sample_aspect_ratio: Option<Rational>,
+    /// The sample rate of the input, in Hz, for audio inputs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sample_rate: Option<u32>,
+    /// The number of channels of the input, for audio inputs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub channels: Option<usize>,
+    /// The number of frames the input was found to be delayed by relative to
+    /// the base input, if alignment was requested. Negative if the base input
+    /// is delayed instead.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frame_offset: Option<isize>,
+    /// The global translation of the input relative to the base input, in
+    /// pixels, if it was detected. The input was moved back before scoring.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shift: Option<Shift>,
+    /// The factor the inputs were downscaled by, if they were
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub downscale: Option<usize>,
+    /// The trailing frames that were not compared because one input has more
+    /// frames than the other
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ignored_frames: Option<IgnoredFrames>,
+    /// PSNR, if it was computed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub psnr: Option<PlanarMetrics>,
+    /// APSNR, if it was computed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub apsnr: Option<PlanarMetrics>,
+    /// PSNR-HVS, if it was computed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub psnr_hvs: Option<PlanarMetrics>,
+    /// SSIM, if it was computed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ssim: Option<PlanarMetrics>,
+    /// MS-SSIM, if it was computed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub msssim: Option<PlanarMetrics>,
+    /// CIEDE2000, if it was computed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ciede2000: Option<f64>,
+    /// The mean VMAF score, if it was computed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vmaf: Option<f64>,
+    /// The mean BRISQUE score of the input on its own, if it was computed.
+    /// Lower is better
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub brisque: Option<f64>,
+    /// PEAQ, if it was computed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub peaq: Option<PeaqResult>,
+    /// The spectral audio metrics, if they were computed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spectral: Option<SpectralMetrics>,
+    /// How long each metric took to compute, in the order they were computed
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub timings: Vec<MetricTiming>,
+    /// The scores of each metric aggregated by GOP, if requested
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub gops: Vec<MetricGops>,
+    /// The frames with the lowest score of each metric, if requested
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub worst_frames: Vec<MetricWorstFrames>,
+    /// The distribution of the per-frame scores of each metric, if requested
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub histograms: Vec<MetricHistogram>,
+    /// The confidence interval of the mean per-frame score of each metric, if
+    /// requested
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub confidence_intervals: Vec<MetricConfidence>,
+    /// How the per-frame scores of each metric follow the quantizers and
+    /// frame sizes of the encoder, if requested
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub rate_control: Vec<MetricRateControl>,
+    /// The PSNR of each tile of the frames over the whole video, row by row,
+    /// if requested
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tiles: Vec<TileScores>,
+    /// The share of each frame prone to banding and the PSNR in those
+    /// regions, if requested
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub banding: Option<BandingResults>,
+}
+
+/// The scores of one metric aggregated by GOP.
+#[derive(Debug, Clone, Serialize, Default, PartialEq)]
+pub struct MetricGops {
+    /// The command line name of the metric
+    pub metric: String,
+    /// The scores of each GOP, in order. Planar metrics are scored by their
+    /// weighted average.
+    pub gops: Vec<GopScores>,
+}
+
+/// The frames with the lowest score of one metric.
+#[derive(Debug, Clone, Serialize, Default, PartialEq)]
+pub struct MetricWorstFrames {
+    /// The command line name of the metric
+    pub metric: String,
+    /// The worst frames, from the lowest score up. Planar metrics are scored
+    /// by their weighted average.
+    pub frames: Vec<FrameScore>,
+}
+
+/// The distribution of the per-frame scores of one metric.
+#[derive(Debug, Clone, Serialize, Default, PartialEq)]
+pub struct MetricHistogram {
+    /// The command line name of the metric
+    pub metric: String,
+    /// The histogram of the scores. Planar metrics are scored by their
+    /// weighted average.
+    pub histogram: Histogram,
+}
+
+/// The confidence interval of the mean per-frame score of one metric.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct MetricConfidence {
+    /// The command line name of the metric
+    pub metric: String,
+    /// The mean of the per-frame scores and its interval. Planar metrics are
+    /// scored by their weighted average.
+    pub interval: ConfidenceInterval,
+}
+
+/// How the per-frame scores of one metric follow the encoder statistics.
+#[derive(Debug, Clone, Serialize, Default, PartialEq)]
+pub struct MetricRateControl {
+    /// The command line name of the metric
+    pub metric: String,
+    /// The line fitted through the scores against the average quantizer of
+    /// each frame. Planar metrics are scored by their weighted average.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub qp: Option<LinearFit>,
+    /// The line fitted through the scores against the base 2 logarithm of the
+    /// size of each frame.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bits: Option<LinearFit>,
+}
+
+/// The trailing frames of the longer of two inputs, which were not compared.
+#[derive(Debug, Clone, Copy, Serialize, Default, PartialEq, Eq)]
+pub struct IgnoredFrames {
+    /// The number of ignored frames of the base input
+    pub base: usize,
+    /// The number of ignored frames of the compared input
+    pub input: usize,
+}
+
+/// The time taken to compute one metric.
+#[derive(Debug, Clone, Serialize, Default, PartialEq)]
+pub struct MetricTiming {
+    /// The command line name of the metric
+    pub metric: String,
+    /// The wall time taken, in seconds, including decoding
+    pub seconds: f64,
+    /// The number of frames processed, or of windows for audio metrics
+    pub frames: usize,
+    /// The number of frames processed per second
+    pub fps: f64,
+}
+
+impl MetricTiming {
+    /// The timing of `metric`, which processed `frames` frames in `elapsed`.
+    pub fn new(metric: &str, elapsed: Duration, frames: usize) -> Self {
+        let seconds = elapsed.as_secs_f64();
+        MetricTiming {
+            metric: metric.to_owned(),
+            seconds,
+            frames,
+            fps: if seconds > 0. {
+                frames as f64 / seconds
+            } else {
+                0.
+            },
+        }
+    }
+}
+
+impl MetricsResults {
+    /// The average score of a metric, by its command line name.
+    ///
+    /// PEAQ is scored by its Objective Difference Grade and the spectral
+    /// metrics by the segmental SNR, so that higher is better for every metric.
+    pub fn average(&self, metric: &str) -> Option<f64> {
+        match metric {
+            "psnr" => self.psnr.map(|v| v.avg),
+            "apsnr" => self.apsnr.map(|v| v.avg),
+            "psnrhvs" => self.psnr_hvs.map(|v| v.avg),
+            "ssim" => self.ssim.map(|v| v.avg),
+            "msssim" => self.msssim.map(|v| v.avg),
+            "ciede2000" => self.ciede2000,
+            "vmaf" => self.vmaf,
+            "peaq" => self.peaq.map(|v| v.odg),
+            "spectral" => self.spectral.map(|v| v.segmental_snr),
+            _ => None,
+        }
+    }
+
+    /// The average score of every metric that was computed, by display name.
+    pub fn averages(&self) -> Vec<(&'static str, f64)> {
+        [
+            ("PSNR", self.psnr.map(|v| v.avg)),
+            ("APSNR", self.apsnr.map(|v| v.avg)),
+            ("PSNR HVS", self.psnr_hvs.map(|v| v.avg)),
+            ("SSIM", self.ssim.map(|v| v.avg)),
+            ("MSSSIM", self.msssim.map(|v| v.avg)),
+            ("CIEDE2000", self.ciede2000),
+            ("VMAF", self.vmaf),
+            ("BRISQUE", self.brisque),
+            ("PEAQ", self.peaq.map(|v| v.odg)),
+            ("SegSNR", self.spectral.map(|v| v.segmental_snr)),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| value.map(|value| (name, value)))
+        .collect()
+    }
+
+    /// Describes how the metadata of the input differs from that of the base
+    /// input, in ways that suggest the two do not line up frame by frame.
+    pub fn stream_mismatches(&self, base: &StreamInfo) -> Vec<String> {
+        let mut mismatches = Vec::new();
+        if let (Some(rate), Some(base_rate)) = (self.stream_info.frame_rate, base.frame_rate) {
+            if (rate - base_rate).abs() > 0.001 {
+                mismatches.push(format!(
+                    "frame rate {:.3} differs from the base {:.3}",
+                    rate, base_rate
+                ));
+            }
+        }
+        if let (Some(duration), Some(base_duration), Some(rate)) =
+            (self.stream_info.duration, base.duration, base.frame_rate)
+        {
+            if (duration - base_duration).abs() * rate >= 1. {
+                mismatches.push(format!(
+                    "duration {:.2} s differs from the base {:.2} s",
+                    duration, base_duration
+                ));
+            }
+        }
+        mismatches
+    }
+
+    /// Describes the trailing frames of the longer input that were not
+    /// compared, if the inputs have different lengths.
+    pub fn length_mismatch(&self) -> Option<String> {
+        let ignored = self.ignored_frames?;
+        let (frames, input) = if ignored.base > 0 {
+            (ignored.base, "the base input")
+        } else {
+            (ignored.input, "this input")
+        };
+        Some(format!(
+            "the last {} frame(s) of {} were not compared, the other input is shorter",
+            frames, input
+        ))
+    }
+
+    /// The resolution of a video input, followed by the resolution it is
+    /// displayed at if it is anamorphic, or the sample rate of an audio input.
+    pub fn resolution(&self) -> String {
+        match (self.sample_rate, self.display_resolution()) {
+            (Some(rate), _) => format!("{} Hz", rate),
+            (None, Some((width, height))) => format!(
+                "{}x{} displayed as {}x{}",
+                self.width, self.height, width, height
+            ),
+            (None, None) => format!("{}x{}", self.width, self.height),
+        }
+    }
+
+    /// The resolution an anamorphic input is displayed at, with the width
+    /// scaled by the sample aspect ratio.
+    pub fn display_resolution(&self) -> Option<(usize, usize)> {
+        let sar = self
+            .sample_aspect_ratio
+            .filter(|sar| sar.num > 0 && sar.den > 0 && sar.num != sar.den)?;
+        let width = (self.width as u64 * sar.num + sar.den / 2) / sar.den;
+        Some((width as usize, self.height))
+    }
+
+    /// The bit depth and chroma sampling of a video input, or the channel
+    /// count of an audio input.
+    pub fn format(&self) -> String {
+        match self.channels {
+            Some(channels) => format!("{} channels", channels),
+            None => format!("{}-bit {}", self.bit_depth, self.chroma_sampling),
+        }
+    }
+
+    /// The label of the comparison followed by the name of the input, or the
+    /// name alone if the comparison has no label.
+    pub fn title(&self) -> String {
+        match &self.label {
+            Some(label) => format!("{}: {}", label, self.filename),
+            None => self.filename.clone(),
+        }
+    }
+
+    /// A note marking the scores as approximate when the inputs were downscaled.
+    pub fn approximate_label(&self) -> String {
+        self.downscale
+            .map(|factor| format!(" (approximate, downscaled 1/{})", factor))
+            .unwrap_or_default()
+    }
+}
+
+/// The results of comparing one or more inputs to a base input.
+#[derive(Debug, Serialize, Default)]
+pub struct Report<'s> {
+    /// The name of the base input, or of the batch file listing the
+    /// comparisons with their own base inputs
+    pub base: &'s str,
+    /// The codec, container and other metadata of the base input
+    pub base_stream_info: StreamInfo,
+    /// The version of av-metrics the scores were computed with
+    pub av_metrics_version: &'static str,
+    /// The results for each compared input
+    pub comparisons: Vec<MetricsResults>,
+    /// The number of decimal places of the values written, or `None` to
+    /// write them at full precision
+    #[serde(skip)]
+    pub precision: Option<usize>,
+}
+
+impl<'s> Report<'s> {
+    /// Creates an empty report for comparisons against `base`, computed with
+    /// this version of av-metrics.
+    pub fn new(base: &'s str) -> Self {
+        Report {
+            base,
+            base_stream_info: StreamInfo::default(),
+            av_metrics_version: av_metrics::VERSION,
+            comparisons: Vec::new(),
+            precision: None,
+        }
+    }
+
+    /// The name of the base input `cmp` was compared to.
+    pub fn base_of<'a>(&'a self, cmp: &'a MetricsResults) -> &'a str {
+        cmp.base.as_deref().unwrap_or(self.base)
+    }
+
+    /// The report with its values rounded to its precision.
+    fn rounded(&self) -> Rounded<'_, Self> {
+        Rounded {
+            value: self,
+            decimals: self.precision,
+        }
+    }
+
+    /// Formats a value of the report with its precision.
+    fn number(&self, value: f64) -> String {
+        match self.precision {
+            Some(decimals) => format!("{:.*}", decimals, value),
+            None => value.to_string(),
+        }
+    }
+
+    /// Writes the report as a single line of JSON.
+    pub fn write_json<W: Write>(&self, w: &mut W) -> Result<(), String> {
+        writeln!(
+            w,
+            "{}",
+            serde_json::to_string(&self.rounded()).map_err(|err| err.to_string())?
+        )
+        .map_err(|err| err.to_string())
+    }
+
+    /// Writes the report as XML, with a `report` root element.
+    pub fn write_xml<W: Write>(&self, w: &mut W) -> Result<(), String> {
+        let xml = quick_xml::se::to_string_with_root("report", &self.rounded())
+            .map_err(|err| err.to_string())?;
+        writeln!(w, "{}", xml).map_err(|err| err.to_string())
+    }
+
+    /// Writes the report as YAML.
+    pub fn write_yaml<W: Write>(&self, w: &mut W) -> Result<(), String> {
+        serde_yaml::to_writer(w, &self.rounded()).map_err(|err| err.to_string())
+    }
+
+    /// Writes the average scores of every comparison as CSV, one row per
+    /// comparison, after its label and base input. Planar metrics are
+    /// followed by the scores of each plane. Metrics that were not computed
+    /// are written as `-0`.
+    pub fn write_csv<W: Write>(&self, w: &mut W) -> Result<(), String> {
+        let header: Vec<String> = ["filename", "label", "base"]
+            .map(str::to_owned)
+            .into_iter()
+            .chain(planar_headers())
+            .chain(
+                [
+                    "ciede2000",
+                    "vmaf",
+                    "brisque",
+                    "peaq",
+                    "log_spectral_distance",
+                    "spectral_convergence",
+                    "segmental_snr",
+                    "width",
+                    "height",
+                    "bit_depth",
+                    "chroma_sampling",
+                    "frames",
+                    "downscale",
+                    "av_metrics_version",
+                ]
+                .map(str::to_owned),
+            )
+            .collect();
+        writeln!(w, "{}", header.join(",")).map_err(|err| err.to_string())?;
+        for cmp in self.comparisons.iter() {
+            writeln!(
+                w,
+                "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+                cmp.filename,
+                cmp.label.as_deref().unwrap_or_default(),
+                self.base_of(cmp),
+                self.planar_values(cmp).join(","),
+                self.number(cmp.ciede2000.unwrap_or(-0.0)),
+                self.number(cmp.vmaf.unwrap_or(-0.0)),
+                self.number(cmp.brisque.unwrap_or(-0.0)),
+                self.number(cmp.peaq.map(|v| v.odg).unwrap_or(-0.0)),
+                self.number(
+                    cmp.spectral
+                        .map(|v| v.log_spectral_distance)
+                        .unwrap_or(-0.0)
+                ),
+                self.number(cmp.spectral.map(|v| v.spectral_convergence).unwrap_or(-0.0)),
+                self.number(cmp.spectral.map(|v| v.segmental_snr).unwrap_or(-0.0)),
+                cmp.width,
+                cmp.height,
+                cmp.bit_depth,
+                cmp.chroma_sampling,
+                cmp.frames,
+                cmp.downscale.unwrap_or(1),
+                self.av_metrics_version
+            )
+            .map_err(|err| err.to_string())?;
+        }
+        Ok(())
+    }
+
+    /// Writes the average scores of every comparison as a Markdown table.
+    /// Planar metrics are followed by the scores of each plane.
+    pub fn write_markdown<W: Write>(&self, w: &mut W) -> Result<(), String> {
+        let header: Vec<String> = ["filename".to_owned()]
+            .into_iter()
+            .chain(planar_headers())
+            .chain(
+                [
+                    "ciede2000",
+                    "vmaf",
+                    "brisque",
+                    "peaq",
+                    "lsd",
+                    "sc",
+                    "segsnr",
+                    "resolution",
+                    "format",
+                    "frames",
+                ]
+                .map(str::to_owned),
+            )
+            .collect();
+        writeln!(
+            w,
+            "Computed with av-metrics {}\n\n|{}|\n|{}|",
+            self.av_metrics_version,
+            header.join("|"),
+            vec!["-"; header.len()].join("|")
+        )
+        .map_err(|err| err.to_string())?;
+        for cmp in self.comparisons.iter() {
+            writeln!(
+                w,
+                "|{}{}|{}|{}|{}|{}|{}|{}|{}|{}|{}|{}|{}|",
+                cmp.title(),
+                cmp.approximate_label(),
+                self.planar_values(cmp).join("|"),
+                self.number(cmp.ciede2000.unwrap_or(-0.0)),
+                self.number(cmp.vmaf.unwrap_or(-0.0)),
+                self.number(cmp.brisque.unwrap_or(-0.0)),
+                self.number(cmp.peaq.map(|v| v.odg).unwrap_or(-0.0)),
+                self.number(
+                    cmp.spectral
+                        .map(|v| v.log_spectral_distance)
+                        .unwrap_or(-0.0)
+                ),
+                self.number(cmp.spectral.map(|v| v.spectral_convergence).unwrap_or(-0.0)),
+                self.number(cmp.spectral.map(|v| v.segmental_snr).unwrap_or(-0.0)),
+                cmp.resolution(),
+                cmp.format(),
+                cmp.frames
+            )
+            .map_err(|err| err.to_string())?;
+        }
+        Ok(())
+    }
+
+    /// The average and per-plane scores of each planar metric of a
+    /// comparison, in the order of [`PLANAR_METRICS`].
+    fn planar_values(&self, cmp: &MetricsResults) -> Vec<String> {
+        [cmp.psnr, cmp.apsnr, cmp.psnr_hvs, cmp.ssim, cmp.msssim]
+            .into_iter()
+            .flat_map(|metrics| match metrics {
+                Some(v) => [v.avg, v.y, v.u, v.v],
+                None => [-0.0; 4],
+            })
+            .map(|value| self.number(value))
+            .collect()
+    }
+}
+
+/// The names of the planar metrics in the CSV and Markdown columns.
+const PLANAR_METRICS: [&str; 5] = ["psnr", "apsnr", "psnr_hvs", "ssim", "msssim"];
+
+/// The CSV and Markdown headers of the planar metrics: the name of each for
+/// its average, followed by the name with the suffix of each plane.
+fn planar_headers() -> impl Iterator<Item = String> {
+    PLANAR_METRICS.into_iter().flat_map(|name| {
+        [
+            name.to_owned(),
+            format!("{}_y", name),
+            format!("{}_u", name),
+            format!("{}_v", name),
+        ]
+    })
+}
+
+/// The conventional name of a chroma sampling, such as `4:2:0`.
+pub fn chroma_sampling_name(chroma_sampling: ChromaSampling) -> &'static str {
+    match chroma_sampling {
+        ChromaSampling::Cs420 => "4:2:0",
+        ChromaSampling::Cs422 => "4:2:2",
+        ChromaSampling::Cs444 => "4:4:4",
+        ChromaSampling::Cs400 => "4:0:0",
+    }
+}
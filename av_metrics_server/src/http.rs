@@ -0,0 +1,173 @@
+//! Just enough HTTP/1.1 to serve the API: one request per connection, with a
+//! body delimited by `Content-Length`.
+
+use serde::Serialize;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+
+/// The longest request line or header accepted.
+const MAX_LINE: u64 = 8192;
+/// The most headers accepted in a request.
+const MAX_HEADERS: usize = 100;
+/// The largest body read into memory. Uploads are streamed instead.
+const MAX_BODY: usize = 1 << 20;
+
+/// A request whose line and headers were read, and whose body is left on the
+/// connection.
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub content_length: usize,
+    reader: BufReader<TcpStream>,
+}
+
+impl Request {
+    /// Reads the request line and headers from `stream`.
+    pub fn read(stream: TcpStream) -> Result<Self, String> {
+        let mut reader = BufReader::new(stream);
+        let request_line = read_line(&mut reader)?;
+        let mut parts = request_line.split_whitespace();
+        let (Some(method), Some(target), Some(_version)) =
+            (parts.next(), parts.next(), parts.next())
+        else {
+            return Err("Malformed request line".to_owned());
+        };
+        // The query string is not used
+        let path = target.split('?').next().unwrap_or_default().to_owned();
+        let method = method.to_owned();
+
+        let mut content_length = 0;
+        for _ in 0..MAX_HEADERS {
+            let line = read_line(&mut reader)?;
+            if line.is_empty() {
+                return Ok(Request {
+                    method,
+                    path,
+                    content_length,
+                    reader,
+                });
+            }
+            let Some((name, value)) = line.split_once(':') else {
+                return Err("Malformed header".to_owned());
+            };
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_length = value
+                    .trim()
+                    .parse()
+                    .map_err(|_| "Malformed Content-Length".to_owned())?;
+            }
+        }
+        Err("Too many headers".to_owned())
+    }
+
+    /// Reads the whole body, which must be small.
+    pub fn body(&mut self) -> Result<Vec<u8>, String> {
+        if self.content_length > MAX_BODY {
+            return Err("Request body is too large".to_owned());
+        }
+        let mut body = vec![0; self.content_length];
+        self.reader
+            .read_exact(&mut body)
+            .map_err(|err| format!("Failed to read the request body: {}", err))?;
+        Ok(body)
+    }
+
+    /// The body, to be read as a stream.
+    pub fn body_reader(&mut self) -> impl Read + '_ {
+        (&mut self.reader).take(self.content_length as u64)
+    }
+
+    /// Sends `response` and closes the connection.
+    pub fn respond(self, response: Response) {
+        let mut stream = self.reader.into_inner();
+        // The client may be gone, and there is nobody else to tell
+        let _ = response.write(&mut stream);
+    }
+}
+
+/// Answers a connection that is not served with `response`, without waiting
+/// for its request. What the client already sent is discarded first, so that
+/// closing the connection does not reset it before the response is read.
+pub fn refuse(mut stream: TcpStream, response: Response) {
+    if stream.set_nonblocking(true).is_err() {
+        return;
+    }
+    let mut discarded = [0; 4096];
+    for _ in 0..16 {
+        if !matches!(stream.read(&mut discarded), Ok(len) if len > 0) {
+            break;
+        }
+    }
+    let _ = response.write(&mut stream);
+}
+
+fn read_line(reader: &mut BufReader<TcpStream>) -> Result<String, String> {
+    let mut line = String::new();
+    reader
+        .take(MAX_LINE)
+        .read_line(&mut line)
+        .map_err(|err| format!("Failed to read the request: {}", err))?;
+    if !line.ends_with('\n') {
+        return Err("Request line or header is too long".to_owned());
+    }
+    Ok(line.trim_end().to_owned())
+}
+
+#[derive(Serialize)]
+struct ErrorBody<'a> {
+    error: &'a str,
+}
+
+/// A response with a JSON body.
+pub struct Response {
+    status: u16,
+    body: String,
+}
+
+impl Response {
+    /// A response with `value` serialized as its body.
+    pub fn json<T: Serialize + ?Sized>(status: u16, value: &T) -> Self {
+        match serde_json::to_string(value) {
+            Ok(body) => Response { status, body },
+            Err(err) => Response::error(500, &format!("Failed to serialize the response: {}", err)),
+        }
+    }
+
+    /// A response with a body already serialized as JSON.
+    pub fn raw(status: u16, body: String) -> Self {
+        Response { status, body }
+    }
+
+    /// An error response, with the message in the `error` field of the body.
+    pub fn error(status: u16, message: &str) -> Self {
+        Response::json(status, &ErrorBody { error: message })
+    }
+
+    fn write<W: Write>(&self, w: &mut W) -> std::io::Result<()> {
+        let reason = match self.status {
+            200 => "OK",
+            201 => "Created",
+            202 => "Accepted",
+            400 => "Bad Request",
+            403 => "Forbidden",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            409 => "Conflict",
+            411 => "Length Required",
+            413 => "Payload Too Large",
+            422 => "Unprocessable Entity",
+            503 => "Service Unavailable",
+            507 => "Insufficient Storage",
+            _ => "Internal Server Error",
+        };
+        write!(
+            w,
+            "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            self.status,
+            reason,
+            self.body.len()
+        )?;
+        w.write_all(self.body.as_bytes())?;
+        w.flush()
+    }
+}
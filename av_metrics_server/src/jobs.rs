@@ -0,0 +1,286 @@
+//! The queue of comparisons, run in the background by a fixed number of
+//! workers.
+
+use av_metrics::video::decode::Decoder;
+use av_metrics::video::VideoOptions;
+use av_metrics::PROGRESS_DONE;
+use av_metrics_decoders::{open_decoder, AnyDecoder, Backend};
+use av_metrics_report::dispatch::{compute_video_metric, MetricSettings, VIDEO_METRICS};
+use av_metrics_report::{chroma_sampling_name, MetricTiming, MetricsResults};
+use serde::Serialize;
+use std::collections::{BTreeMap, VecDeque};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// Where a job is in its lifetime.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum State {
+    Queued,
+    Running,
+    Done,
+    Failed,
+}
+
+/// A comparison of a distorted input with a reference input.
+pub struct Job {
+    id: usize,
+    reference: PathBuf,
+    distorted: PathBuf,
+    metric: Option<&'static str>,
+    /// The number of frames the current metric has scored
+    frame: AtomicUsize,
+    progress: Mutex<Progress>,
+}
+
+struct Progress {
+    state: State,
+    metric: Option<&'static str>,
+    results: Option<MetricsResults>,
+    error: Option<String>,
+    /// When the job was done or failed
+    finished: Option<Instant>,
+}
+
+/// The status of a job, as reported by the API.
+#[derive(Serialize)]
+pub struct JobStatus<'a> {
+    id: usize,
+    reference: &'a Path,
+    distorted: &'a Path,
+    state: State,
+    /// The metric being computed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    metric: Option<&'static str>,
+    /// The number of frames the metric being computed has scored
+    frame: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl Job {
+    /// The reference input, against which the report is made.
+    pub fn reference(&self) -> &Path {
+        &self.reference
+    }
+
+    pub fn status(&self) -> JobStatus<'_> {
+        let progress = self.progress.lock().unwrap();
+        JobStatus {
+            id: self.id,
+            reference: &self.reference,
+            distorted: &self.distorted,
+            state: progress.state,
+            metric: progress.metric,
+            frame: self.frame.load(Ordering::Relaxed),
+            error: progress.error.clone(),
+        }
+    }
+
+    /// The results of a finished job, or why it failed. `None` if it is not
+    /// finished yet.
+    pub fn outcome(&self) -> Option<Result<MetricsResults, String>> {
+        let progress = self.progress.lock().unwrap();
+        match progress.state {
+            State::Done => progress.results.clone().map(Ok),
+            State::Failed => progress.error.clone().map(Err),
+            State::Queued | State::Running => None,
+        }
+    }
+
+    fn update<F: FnOnce(&mut Progress)>(&self, f: F) {
+        f(&mut self.progress.lock().unwrap());
+    }
+
+    fn run(&self, settings: &MetricSettings) -> Result<MetricsResults, String> {
+        let open = || -> Result<(AnyDecoder, AnyDecoder), String> {
+            Ok((open_input(&self.reference)?, open_input(&self.distorted)?))
+        };
+        let (_, decoder) = open()?;
+        let details = decoder.get_video_details();
+        let mut results = MetricsResults {
+            filename: self.distorted.to_string_lossy().into_owned(),
+            width: details.width,
+            height: details.height,
+            bit_depth: details.bit_depth,
+            chroma_sampling: chroma_sampling_name(details.chroma_sampling),
+            sample_aspect_ratio: Some(details.sample_aspect_ratio).filter(|sar| sar.num != sar.den),
+            stream_info: decoder.get_stream_info(),
+            ..Default::default()
+        };
+
+        for metric in VIDEO_METRICS {
+            if self.metric.is_some_and(|selected| selected != metric)
+                || (metric == "vmaf" && settings.vmaf_model.is_none())
+            {
+                continue;
+            }
+            self.update(|progress| progress.metric = Some(metric));
+            self.frame.store(0, Ordering::Relaxed);
+            let progress = |frameno: usize| {
+                if frameno != PROGRESS_DONE {
+                    self.frame.fetch_max(frameno, Ordering::Relaxed);
+                }
+            };
+
+            let start = Instant::now();
+            let (mut dec1, mut dec2) = open()?;
+            let video = VideoOptions::new().progress(progress);
+            let score = compute_video_metric(metric, &mut dec1, &mut dec2, settings, video)
+                .map_err(|err| format!("Failed to compute {}: {}", metric, err))?;
+            results.set_score(metric, score);
+            let frames = self.frame.load(Ordering::Relaxed);
+            results
+                .timings
+                .push(MetricTiming::new(metric, start.elapsed(), frames));
+            results.frames = results.frames.max(frames);
+        }
+        Ok(results)
+    }
+}
+
+fn open_input(path: &Path) -> Result<AnyDecoder, String> {
+    let backend = Backend::detect(path)
+        .ok_or_else(|| format!("No decoder is enabled for {}", path.display()))?;
+    open_decoder(backend, path).map_err(|err| format!("Failed to open {}: {}", path.display(), err))
+}
+
+/// The jobs submitted and not expired yet, by id, and those waiting for a
+/// worker.
+pub struct Jobs {
+    jobs: Mutex<BTreeMap<usize, Arc<Job>>>,
+    next_id: AtomicUsize,
+    queue: Mutex<VecDeque<Arc<Job>>>,
+    available: Condvar,
+    settings: MetricSettings,
+    /// The number of jobs that can wait for a worker
+    max_queued: usize,
+}
+
+impl Jobs {
+    /// No jobs yet, whose metrics will be computed with `settings`, and of
+    /// which at most `max_queued` can wait for a worker.
+    pub fn new(settings: MetricSettings, max_queued: usize) -> Self {
+        Jobs {
+            jobs: Mutex::default(),
+            next_id: AtomicUsize::new(0),
+            queue: Mutex::default(),
+            available: Condvar::new(),
+            settings,
+            max_queued,
+        }
+    }
+
+    /// Queues a comparison, and returns the id of its job.
+    ///
+    /// `metric` selects a single metric of [`VIDEO_METRICS`], instead of all
+    /// of them. VMAF can only be selected when the settings have a model.
+    ///
+    /// Fails with the HTTP status and message to answer with: 400 for a metric
+    /// that cannot be computed, and 503 when the queue is full.
+    pub fn submit(
+        &self,
+        reference: PathBuf,
+        distorted: PathBuf,
+        metric: Option<&str>,
+    ) -> Result<usize, (u16, String)> {
+        let metric = match metric {
+            Some("vmaf") if self.settings.vmaf_model.is_none() => {
+                return Err((400, "VMAF cannot be computed without a model".to_owned()))
+            }
+            Some(metric) => Some(
+                VIDEO_METRICS
+                    .into_iter()
+                    .find(|&name| name == metric)
+                    .ok_or_else(|| (400, format!("Unknown metric {}", metric)))?,
+            ),
+            None => None,
+        };
+        let mut queue = self.queue.lock().unwrap();
+        if queue.len() >= self.max_queued {
+            return Err((503, "Too many jobs are queued".to_owned()));
+        }
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let job = Arc::new(Job {
+            id,
+            reference,
+            distorted,
+            metric,
+            frame: AtomicUsize::new(0),
+            progress: Mutex::new(Progress {
+                state: State::Queued,
+                metric: None,
+                results: None,
+                error: None,
+                finished: None,
+            }),
+        });
+        self.jobs.lock().unwrap().insert(id, Arc::clone(&job));
+        queue.push_back(job);
+        self.available.notify_one();
+        Ok(id)
+    }
+
+    pub fn get(&self, id: usize) -> Option<Arc<Job>> {
+        self.jobs.lock().unwrap().get(&id).cloned()
+    }
+
+    pub fn all(&self) -> Vec<Arc<Job>> {
+        self.jobs.lock().unwrap().values().cloned().collect()
+    }
+
+    /// Forgets the jobs that finished at least `retention` ago.
+    pub fn expire(&self, retention: Duration) {
+        self.jobs.lock().unwrap().retain(|_, job| {
+            let finished = job.progress.lock().unwrap().finished;
+            finished.is_none_or(|finished| finished.elapsed() < retention)
+        });
+    }
+
+    /// The inputs of the jobs that are queued or running.
+    pub fn inputs_in_use(&self) -> Vec<PathBuf> {
+        let jobs = self.jobs.lock().unwrap();
+        let unfinished = jobs
+            .values()
+            .filter(|job| job.progress.lock().unwrap().finished.is_none());
+        unfinished
+            .flat_map(|job| [job.reference.clone(), job.distorted.clone()])
+            .collect()
+    }
+
+    /// Runs queued jobs one after another, forever.
+    pub fn work(&self) {
+        loop {
+            let job = {
+                let mut queue = self.queue.lock().unwrap();
+                loop {
+                    match queue.pop_front() {
+                        Some(job) => break job,
+                        None => queue = self.available.wait(queue).unwrap(),
+                    }
+                }
+            };
+            job.update(|progress| progress.state = State::Running);
+            // A panic while decoding a bad input must not take the worker down
+            let outcome = panic::catch_unwind(AssertUnwindSafe(|| job.run(&self.settings)))
+                .unwrap_or_else(|_| Err("The comparison panicked".to_owned()));
+            job.update(|progress| {
+                progress.metric = None;
+                progress.finished = Some(Instant::now());
+                match outcome {
+                    Ok(results) => {
+                        progress.state = State::Done;
+                        progress.results = Some(results);
+                    }
+                    Err(err) => {
+                        progress.state = State::Failed;
+                        progress.error = Some(err);
+                    }
+                }
+            });
+        }
+    }
+}
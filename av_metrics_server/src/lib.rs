@@ -0,0 +1,415 @@
+//! An HTTP service measuring video metrics, for encoding farms that want a
+//! central place to score their encodes.
+//!
+//! Comparisons are queued as jobs, run in the background by a fixed number of
+//! workers, and polled until their report can be fetched. All bodies are JSON:
+//!
+//! - `POST /jobs` with `{"reference": PATH, "distorted": PATH, "metric": NAME}`
+//!   queues a comparison and returns its `id`. `metric` is optional and
+//!   selects a single metric instead of all of them.
+//! - `GET /jobs` returns the status of every job.
+//! - `GET /jobs/ID` returns the status of a job: its state, the metric being
+//!   computed and the number of frames it scored so far.
+//! - `GET /jobs/ID/report` returns the report of a finished job, in the same
+//!   format as `--export-json` of av-metrics-tool.
+//! - `POST /uploads` stores a y4m body in the upload directory and returns its
+//!   `path`, for clients that do not share a file system with the server.
+//!
+//! The metrics are computed as av-metrics-tool computes them, with the plane
+//! weights and VMAF model given in the [`Config`].
+//!
+//! Inputs must be uploaded, or be inside the root directory given in the
+//! [`Config`]. Paths that do not exist and paths that are not allowed get the
+//! same 403, so that clients cannot probe the file system of the server.
+//!
+//! Every connection is closed when its client stops sending or reading for
+//! longer than the timeout, and at most `max_connections` are served at once.
+//! Connections over the limit are answered with 503, as are jobs submitted
+//! while `max_queued` jobs wait for a worker. Uploads without a body are
+//! answered with 411, uploads larger than `max_upload` with 413, and uploads
+//! that would take the stored uploads over `max_stored` with 507.
+//!
+//! Nothing is kept forever. Finished jobs are forgotten, and their reports
+//! lost, once they finished longer than the retention period ago. Uploads are
+//! deleted once they are older than the retention period, unless a queued or
+//! running job reads them.
+
+use av_metrics_report::dispatch::MetricSettings;
+use av_metrics_report::Report;
+use http::{Request, Response};
+use jobs::Jobs;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, ErrorKind};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+pub mod http;
+pub mod jobs;
+
+/// The longest time between two sweeps of the expired jobs and uploads.
+const MAX_SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How a [`Server`] stores inputs, limits its clients and computes metrics.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// The directory that inputs must be in, besides the upload directory.
+    /// Only uploaded inputs are accepted without one
+    pub root: Option<PathBuf>,
+    /// The directory to store uploaded inputs in, created if missing
+    pub uploads: PathBuf,
+    /// The number of comparisons to run at the same time
+    pub workers: usize,
+    /// The number of connections served at the same time
+    pub max_connections: usize,
+    /// The number of jobs that can wait for a worker
+    pub max_queued: usize,
+    /// The size of the largest upload accepted, in bytes
+    pub max_upload: u64,
+    /// The total size of the uploads stored at the same time, in bytes
+    pub max_stored: u64,
+    /// How long a client may stop sending or reading before its connection is
+    /// closed
+    pub timeout: Duration,
+    /// How long finished jobs and uploads are kept
+    pub retention: Duration,
+    /// The settings the metrics are computed with
+    pub settings: MetricSettings,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            root: None,
+            uploads: std::env::temp_dir().join("av-metrics-server"),
+            workers: 1,
+            max_connections: 64,
+            max_queued: 64,
+            max_upload: 8 << 30,
+            max_stored: 64 << 30,
+            timeout: Duration::from_secs(30),
+            retention: Duration::from_secs(24 * 60 * 60),
+            settings: MetricSettings::default(),
+        }
+    }
+}
+
+/// The body of `POST /jobs`.
+#[derive(Deserialize)]
+struct JobRequest {
+    reference: PathBuf,
+    distorted: PathBuf,
+    #[serde(default)]
+    metric: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Created {
+    id: usize,
+}
+
+#[derive(Serialize)]
+struct Uploaded {
+    path: PathBuf,
+}
+
+/// The state of the service, shared by the connections and the workers.
+pub struct Server {
+    jobs: Jobs,
+    /// The directory that inputs must be in, besides the upload directory
+    root: Option<PathBuf>,
+    uploads: PathBuf,
+    next_upload: AtomicUsize,
+    /// The sizes of the uploads being received, which are not all stored yet
+    uploading: Mutex<u64>,
+    workers: usize,
+    max_connections: usize,
+    max_upload: u64,
+    max_stored: u64,
+    timeout: Duration,
+    retention: Duration,
+    /// The number of connections being served
+    connections: AtomicUsize,
+}
+
+/// A connection being served, counted until it is dropped.
+struct Active(Arc<Server>);
+
+impl Drop for Active {
+    fn drop(&mut self) {
+        self.0.connections.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl Server {
+    /// Checks `config` and creates the upload directory.
+    pub fn new(config: Config) -> Result<Self, String> {
+        if config.workers == 0 {
+            return Err("At least one worker is needed".to_owned());
+        }
+        if config.max_connections == 0 {
+            return Err("At least one connection must be allowed".to_owned());
+        }
+        if config.max_queued == 0 {
+            return Err("At least one job must be allowed to wait".to_owned());
+        }
+        if config.timeout.is_zero() {
+            return Err("The timeout must be longer than zero".to_owned());
+        }
+        fs::create_dir_all(&config.uploads)
+            .map_err(|err| format!("Failed to create {}: {}", config.uploads.display(), err))?;
+        let canonical = |path: &Path| {
+            path.canonicalize()
+                .map_err(|err| format!("Failed to open {}: {}", path.display(), err))
+        };
+        Ok(Server {
+            jobs: Jobs::new(config.settings, config.max_queued),
+            root: config.root.as_deref().map(canonical).transpose()?,
+            uploads: canonical(&config.uploads)?,
+            next_upload: AtomicUsize::new(0),
+            uploading: Mutex::new(0),
+            workers: config.workers,
+            max_connections: config.max_connections,
+            max_upload: config.max_upload,
+            max_stored: config.max_stored,
+            timeout: config.timeout,
+            retention: config.retention,
+            connections: AtomicUsize::new(0),
+        })
+    }
+
+    /// Starts the workers, and serves the connections of `listener` forever.
+    pub fn run(self: Arc<Self>, listener: TcpListener) {
+        for _ in 0..self.workers {
+            let server = Arc::clone(&self);
+            thread::spawn(move || server.jobs.work());
+        }
+        let server = Arc::clone(&self);
+        thread::spawn(move || {
+            let interval = server
+                .retention
+                .clamp(Duration::from_secs(1), MAX_SWEEP_INTERVAL);
+            loop {
+                thread::sleep(interval);
+                server.expire();
+            }
+        });
+
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else {
+                continue;
+            };
+            // Only this thread adds connections, so the count cannot go over
+            // the limit between the check and the increment
+            if self.connections.load(Ordering::Relaxed) >= self.max_connections {
+                http::refuse(stream, Response::error(503, "Too many connections"));
+                continue;
+            }
+            self.connections.fetch_add(1, Ordering::Relaxed);
+            let active = Active(Arc::clone(&self));
+            thread::spawn(move || active.0.handle(stream));
+        }
+    }
+
+    /// Forgets the jobs that finished longer than the retention period ago,
+    /// and deletes the uploads older than it that no queued or running job
+    /// reads.
+    pub fn expire(&self) {
+        self.jobs.expire(self.retention);
+        let in_use = self.jobs.inputs_in_use();
+        for (path, metadata) in self.stored_uploads() {
+            let expired = metadata
+                .modified()
+                .ok()
+                .and_then(|modified| modified.elapsed().ok())
+                .is_some_and(|age| age >= self.retention);
+            if expired && !in_use.contains(&path) {
+                let _ = fs::remove_file(&path);
+            }
+        }
+    }
+
+    /// The files in the upload directory that were uploaded.
+    fn stored_uploads(&self) -> Vec<(PathBuf, fs::Metadata)> {
+        let Ok(entries) = fs::read_dir(&self.uploads) else {
+            return Vec::new();
+        };
+        entries
+            .flatten()
+            .filter(|entry| entry.file_name().to_string_lossy().starts_with("upload-"))
+            .filter_map(|entry| Some((entry.path(), entry.metadata().ok()?)))
+            .collect()
+    }
+
+    fn handle(&self, stream: TcpStream) {
+        // A client that stops sending or reading must not hold its connection
+        // forever
+        let timeouts = stream
+            .set_read_timeout(Some(self.timeout))
+            .and_then(|_| stream.set_write_timeout(Some(self.timeout)));
+        if timeouts.is_err() {
+            return;
+        }
+        let mut request = match Request::read(stream) {
+            Ok(request) => request,
+            // Not HTTP, or the client went away
+            Err(_) => return,
+        };
+        let response = self.route(&mut request);
+        request.respond(response);
+    }
+
+    fn route(&self, request: &mut Request) -> Response {
+        let segments: Vec<&str> = request.path.split('/').filter(|s| !s.is_empty()).collect();
+        match (request.method.as_str(), segments.as_slice()) {
+            ("POST", ["jobs"]) => self.submit(request),
+            ("GET", ["jobs"]) => {
+                let jobs = self.jobs.all();
+                let statuses: Vec<_> = jobs.iter().map(|job| job.status()).collect();
+                Response::json(200, &statuses)
+            }
+            ("GET", ["jobs", id]) => match self.job(id) {
+                Some(job) => Response::json(200, &job.status()),
+                None => Response::error(404, "No such job"),
+            },
+            ("GET", ["jobs", id, "report"]) => match self.job(id) {
+                Some(job) => match job.outcome() {
+                    Some(Ok(results)) => {
+                        let base = job.reference().to_string_lossy();
+                        let mut report = Report::new(&base);
+                        report.comparisons.push(results);
+                        let mut body = Vec::new();
+                        match report.write_json(&mut body) {
+                            Ok(()) => Response::raw(200, String::from_utf8_lossy(&body).into()),
+                            Err(err) => Response::error(500, &err),
+                        }
+                    }
+                    Some(Err(err)) => Response::error(422, &err),
+                    None => Response::error(409, "The job is not finished"),
+                },
+                None => Response::error(404, "No such job"),
+            },
+            ("POST", ["uploads"]) => self.upload(request),
+            (_, ["jobs"] | ["jobs", _] | ["jobs", _, "report"] | ["uploads"]) => {
+                Response::error(405, "Method not allowed")
+            }
+            _ => Response::error(404, "Not found"),
+        }
+    }
+
+    fn job(&self, id: &str) -> Option<Arc<jobs::Job>> {
+        self.jobs.get(id.parse().ok()?)
+    }
+
+    fn submit(&self, request: &mut Request) -> Response {
+        let body = match request.body() {
+            Ok(body) => body,
+            Err(err) => return Response::error(413, &err),
+        };
+        let job: JobRequest = match serde_json::from_slice(&body) {
+            Ok(job) => job,
+            Err(err) => return Response::error(400, &format!("Invalid job: {}", err)),
+        };
+        let (reference, distorted) =
+            match (self.resolve(&job.reference), self.resolve(&job.distorted)) {
+                (Ok(reference), Ok(distorted)) => (reference, distorted),
+                (Err((status, err)), _) | (_, Err((status, err))) => {
+                    return Response::error(status, &err)
+                }
+            };
+        match self
+            .jobs
+            .submit(reference, distorted, job.metric.as_deref())
+        {
+            Ok(id) => Response::json(202, &Created { id }),
+            Err((status, err)) => Response::error(status, &err),
+        }
+    }
+
+    /// Checks that an input exists and is allowed, and returns its canonical
+    /// path. Both failures get the same error, which does not tell which.
+    fn resolve(&self, path: &Path) -> Result<PathBuf, (u16, String)> {
+        let allowed = |canonical: &PathBuf| {
+            canonical.starts_with(&self.uploads)
+                || self
+                    .root
+                    .as_ref()
+                    .is_some_and(|root| canonical.starts_with(root))
+        };
+        path.canonicalize()
+            .ok()
+            .filter(allowed)
+            .ok_or_else(|| (403, format!("{} is not an allowed input", path.display())))
+    }
+
+    fn upload(&self, request: &mut Request) -> Response {
+        // Refused before anything is stored, so that a client cannot fill the
+        // disk
+        let length = request.content_length as u64;
+        if length == 0 {
+            return Response::error(411, "Uploads need a Content-Length and a body");
+        }
+        if length > self.max_upload {
+            return Response::error(
+                413,
+                &format!("Uploads are limited to {} bytes", self.max_upload),
+            );
+        }
+        {
+            let mut uploading = self.uploading.lock().unwrap();
+            let stored: u64 = self
+                .stored_uploads()
+                .iter()
+                .map(|(_, metadata)| metadata.len())
+                .sum();
+            if stored + *uploading + length > self.max_stored {
+                return Response::error(
+                    507,
+                    &format!(
+                        "The uploads stored are limited to {} bytes",
+                        self.max_stored
+                    ),
+                );
+            }
+            *uploading += length;
+        }
+        let response = self.store_upload(request);
+        *self.uploading.lock().unwrap() -= length;
+        response
+    }
+
+    /// Stores the body of `request` in a new file of the upload directory.
+    fn store_upload(&self, request: &mut Request) -> Response {
+        let (path, mut file) = match self.create_upload() {
+            Ok(upload) => upload,
+            Err(err) => {
+                return Response::error(500, &format!("Failed to store the upload: {}", err))
+            }
+        };
+        let copied = io::copy(&mut request.body_reader(), &mut file);
+        if !matches!(copied, Ok(len) if len == request.content_length as u64) {
+            let _ = fs::remove_file(&path);
+            return Response::error(400, "The upload is incomplete");
+        }
+        Response::json(201, &Uploaded { path })
+    }
+
+    /// Creates a new file in the upload directory.
+    fn create_upload(&self) -> io::Result<(PathBuf, File)> {
+        loop {
+            let n = self.next_upload.fetch_add(1, Ordering::Relaxed);
+            let path = self
+                .uploads
+                .join(format!("upload-{}-{}.y4m", std::process::id(), n));
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(file) => return Ok((path, file)),
+                Err(err) if err.kind() == ErrorKind::AlreadyExists => continue,
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
@@ -0,0 +1,179 @@
+//! The command line of the server, whose API is documented in the library.
+
+use av_metrics::video::vmaf::VmafModel;
+use av_metrics::video::PlaneWeights;
+use av_metrics_report::dispatch::MetricSettings;
+use av_metrics_server::{Config, Server};
+use clap::builder::RangedU64ValueParser;
+use clap::{value_parser, Arg, Command};
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+fn main() -> Result<(), String> {
+    let cli = Command::new("AV Metrics Server")
+        .version(env!("CARGO_PKG_VERSION"))
+        .author(env!("CARGO_PKG_AUTHORS"))
+        .about(env!("CARGO_PKG_DESCRIPTION"))
+        .arg(
+            Arg::new("LISTEN")
+                .help("The address to listen on")
+                .long("listen")
+                .num_args(1)
+                .default_value("127.0.0.1:8080"),
+        )
+        .arg(
+            Arg::new("WORKERS")
+                .help("The number of comparisons to run at the same time")
+                .long("workers")
+                .num_args(1)
+                .value_parser(value_parser!(usize))
+                .default_value("1"),
+        )
+        .arg(
+            Arg::new("ROOT")
+                .help(
+                    "Also accept inputs inside this directory. Only uploaded inputs are \
+                     accepted without it",
+                )
+                .long("root")
+                .num_args(1)
+                .value_parser(value_parser!(PathBuf)),
+        )
+        .arg(
+            Arg::new("UPLOADS")
+                .help("The directory to store uploaded inputs in")
+                .long("upload-dir")
+                .num_args(1)
+                .value_parser(value_parser!(PathBuf)),
+        )
+        .arg(
+            Arg::new("MAX_CONNECTIONS")
+                .help("The number of connections served at the same time. Others get a 503")
+                .long("max-connections")
+                .num_args(1)
+                .value_parser(value_parser!(usize))
+                .default_value("64"),
+        )
+        .arg(
+            Arg::new("MAX_QUEUED")
+                .help("The number of jobs that can wait for a worker. Others get a 503")
+                .long("max-queued")
+                .num_args(1)
+                .value_parser(value_parser!(usize))
+                .default_value("64"),
+        )
+        .arg(
+            Arg::new("MAX_UPLOAD")
+                .help("The size of the largest upload accepted, in MiB. Larger ones get a 413")
+                .long("max-upload")
+                .num_args(1)
+                .value_parser(value_parser!(u64))
+                .default_value("8192"),
+        )
+        .arg(
+            Arg::new("MAX_STORED")
+                .help(
+                    "The total size of the uploads stored at the same time, in MiB. Uploads \
+                     that would go over it get a 507",
+                )
+                .long("max-stored")
+                .num_args(1)
+                .value_parser(value_parser!(u64))
+                .default_value("65536"),
+        )
+        .arg(
+            Arg::new("TIMEOUT")
+                .help(
+                    "The number of seconds a client may stop sending or reading before its \
+                     connection is closed",
+                )
+                .long("timeout")
+                .num_args(1)
+                .value_parser(RangedU64ValueParser::<u64>::new().range(1..))
+                .default_value("30"),
+        )
+        .arg(
+            Arg::new("RETENTION")
+                .help(
+                    "The number of seconds finished jobs and uploads are kept. Uploads read by \
+                     queued or running jobs are kept until the jobs finish",
+                )
+                .long("retention")
+                .num_args(1)
+                .value_parser(value_parser!(u64))
+                .default_value("86400"),
+        )
+        .arg(
+            Arg::new("WEIGHTS")
+                .help(
+                    "How the planes are weighted in the averages of PSNR, APSNR, SSIM and \
+                     MSSSIM: by their number of samples, luma only, equally, or 6:1:1 as in the \
+                     common test conditions of JVET and AOM",
+                )
+                .long("weights")
+                .num_args(1)
+                .value_parser(["default", "luma", "equal", "ctc"]),
+        )
+        .arg(
+            Arg::new("VMAF_MODEL")
+                .help(
+                    "Also compute VMAF, scoring the features with this libvmaf model file, \
+                     such as vmaf_v0.6.1.json",
+                )
+                .long("vmaf-model")
+                .num_args(1)
+                .value_parser(value_parser!(PathBuf)),
+        )
+        .get_matches();
+
+    let vmaf_model = cli
+        .get_one::<PathBuf>("VMAF_MODEL")
+        .map(|path| {
+            VmafModel::load(path)
+                .map_err(|err| format!("Failed to load {}: {}", path.display(), err))
+        })
+        .transpose()?;
+    let config = Config {
+        root: cli.get_one::<PathBuf>("ROOT").cloned(),
+        uploads: cli
+            .get_one::<PathBuf>("UPLOADS")
+            .cloned()
+            .unwrap_or_else(|| Config::default().uploads),
+        workers: *cli.get_one::<usize>("WORKERS").unwrap(),
+        max_connections: *cli.get_one::<usize>("MAX_CONNECTIONS").unwrap(),
+        max_queued: *cli.get_one::<usize>("MAX_QUEUED").unwrap(),
+        max_upload: cli
+            .get_one::<u64>("MAX_UPLOAD")
+            .unwrap()
+            .saturating_mul(1 << 20),
+        max_stored: cli
+            .get_one::<u64>("MAX_STORED")
+            .unwrap()
+            .saturating_mul(1 << 20),
+        timeout: Duration::from_secs(*cli.get_one::<u64>("TIMEOUT").unwrap()),
+        retention: Duration::from_secs(*cli.get_one::<u64>("RETENTION").unwrap()),
+        settings: MetricSettings {
+            plane_weights: match cli.get_one::<String>("WEIGHTS").map(String::as_str) {
+                Some("luma") => PlaneWeights::Luma,
+                Some("equal") => PlaneWeights::Equal,
+                Some("ctc") => PlaneWeights::Ctc,
+                _ => PlaneWeights::Default,
+            },
+            vmaf_model,
+            ..Default::default()
+        },
+    };
+    let server = Arc::new(Server::new(config)?);
+
+    let addr = cli.get_one::<String>("LISTEN").unwrap();
+    let listener =
+        TcpListener::bind(addr).map_err(|err| format!("Failed to listen on {}: {}", addr, err))?;
+    eprintln!(
+        "Listening on {}",
+        listener.local_addr().map_err(|err| err.to_string())?
+    );
+    server.run(listener);
+    Ok(())
+}
@@ -2,25 +2,114 @@
 mod tests {
     #![allow(unused_imports)]
 
-    use av_metrics::video::ciede::{calculate_video_ciede, calculate_video_ciede_nosimd};
-    use av_metrics::video::psnr::{calculate_video_apsnr, calculate_video_psnr};
-    use av_metrics::video::psnr_hvs::calculate_video_psnr_hvs;
-    use av_metrics::video::ssim::{calculate_video_msssim, calculate_video_ssim};
-    #[cfg(feature = "ffmpeg")]
-    use av_metrics_decoders::FfmpegDecoder;
+    use av_metrics::audio::decode::{AudioDecoder, AudioDetails, AudioFrame, SampleFormat};
+    use av_metrics::audio::peaq::{calculate_audio_peaq, PeaqMovs, PeaqResult};
+    use av_metrics::audio::spectral::{
+        calculate_audio_spectral, calculate_audio_spectral_with_config, SpectralConfig,
+        SpectralMetrics,
+    };
+    use av_metrics::audio::window::WindowFunction;
+    use av_metrics::video::align::{
+        detect_frame_offset, detect_shift, shift_frame, Shift, Skipped,
+    };
+    use av_metrics::video::all::calculate_all_frame_metrics;
+    use av_metrics::video::alpha::AlphaPlane;
+    use av_metrics::video::artifacts::{calculate_frame_artifacts, calculate_video_artifacts};
+    use av_metrics::video::banding::{
+        calculate_frame_banding, calculate_video_banding, calculate_video_banding_with,
+        BandingAggregator, BandingConfig, BandingOptions,
+    };
+    use av_metrics::video::bootstrap::{bootstrap_mean, BootstrapConfig};
+    use av_metrics::video::ciede::{
+        calculate_frame_ciede, calculate_frame_ciede_nosimd, calculate_video_ciede,
+        calculate_video_ciede_nosimd, calculate_video_ciede_with, CiedeConfig, CiedeOptions,
+    };
+    use av_metrics::video::color::{ColorConfig, ColorConverter, TransferFunction};
+    use av_metrics::video::decode::{
+        copy_plane_from_raw_u8, copy_plane_from_slice, Decoder, DecoderCapabilities,
+        DetailsOverride, DynDecoder, FrameMeta, Rational, StreamInfo, VideoDetails,
+    };
+    use av_metrics::video::gop::aggregate_by_gop;
+    use av_metrics::video::histogram::Histogram;
+    use av_metrics::video::lossless::{verify_video_lossless, Mismatch};
+    use av_metrics::video::noref::brisque::{
+        calculate_frame_brisque, calculate_frame_brisque_features, calculate_video_brisque,
+        BrisqueModel,
+    };
+    use av_metrics::video::pairing::Paired;
+    use av_metrics::video::psnr::{
+        calculate_frame_mse, calculate_frame_mse_with_config, calculate_frame_psnr,
+        calculate_frame_psnr_ref, calculate_frame_psnr_with_config, calculate_video_apsnr,
+        calculate_video_apsnr_with_config, calculate_video_mse, calculate_video_psnr,
+        calculate_video_psnr_from_frames, calculate_video_psnr_with,
+        calculate_video_psnr_with_config, Prefilter, PsnrConfig, PsnrOptions,
+    };
+    use av_metrics::video::psnr_b::{calculate_frame_psnr_b, calculate_video_psnr_b};
+    use av_metrics::video::psnr_hvs::{
+        calculate_frame_psnr_hvs, calculate_frame_psnr_hvs_nosimd, calculate_video_psnr_hvs,
+    };
+    use av_metrics::video::rate_control::fit_rate_control;
+    use av_metrics::video::scale::{downscale_frame, Downscaled};
+    use av_metrics::video::side_data::{QpMap, SideData, WithSideData};
+    use av_metrics::video::ssim::{
+        calculate_frame_msssim, calculate_frame_msssim_with_config, calculate_frame_ssim,
+        calculate_frame_ssim_map, calculate_video_msssim, calculate_video_msssim_with,
+        calculate_video_msssim_with_config, calculate_video_ssim, calculate_video_ssim_from_frames,
+        calculate_video_ssim_with, calculate_video_ssim_with_config, MsSsimConfig, MsSsimOptions,
+        MsSsimWindow, SsimConfig, SsimOptions, SsimScale, SsimWindow,
+    };
+    use av_metrics::video::synth;
+    use av_metrics::video::temporal::{
+        calculate_frame_si, calculate_frame_ti, calculate_video_temporal,
+    };
+    use av_metrics::video::tiles::{
+        calculate_frame_psnr_tiles, calculate_video_psnr_tiles, calculate_video_psnr_tiles_with,
+        TileAggregator, TileGrid, TilesOptions,
+    };
+    use av_metrics::video::vmaf::{
+        calculate_video_vmaf, calculate_video_vmaf_with, VmafConfig, VmafModel, VmafOptions,
+    };
+    use av_metrics::video::vmaf_features::{
+        calculate_frame_vmaf_features, calculate_video_vmaf_features, VmafFeatures,
+    };
+    use av_metrics::video::worst::{worst_frames, FrameScore, WorstFrames};
+    use av_metrics::video::{
+        frame_from_planes, ChromaSamplePosition, ChromaSampling, ColorRange, CompatMode, Frame,
+        FrameContext, FrameSink, Interlacing, LengthMismatch, MatrixCoefficients, PipelineControl,
+        PipelineMonitor, PipelineStats, PlanarMetrics, Plane, PlaneSelect, PlaneWeights, Sampling,
+        Score, ScoredFrame, VideoOptions,
+    };
+    use av_metrics::{MetricsError, PROGRESS_DONE};
+    #[cfg(feature = "shm")]
+    use av_metrics_decoders::shm::{ShmFormat, HEADER_SIZE};
+    #[cfg(feature = "isolation")]
+    use av_metrics_decoders::IsolatedDecoder;
     #[cfg(not(feature = "ffmpeg"))]
     use av_metrics_decoders::Y4MDecoder;
+    use av_metrics_decoders::{open_decoder, Backend, CachingDecoder, DecoderError};
+    #[cfg(feature = "ffmpeg")]
+    use av_metrics_decoders::{FfmpegDecoder, FfmpegOptions};
+    #[cfg(feature = "shm")]
+    use av_metrics_decoders::{ShmDecoder, ShmFrameWriter};
+    use av_metrics_report::dispatch::{compute_video_metric, MetricSettings, VIDEO_METRICS};
+    use av_metrics_report::{chroma_sampling_name, MetricTiming, MetricsResults, Report};
+    use av_metrics_server::{Config as ServerConfig, Server};
+    use serde_json::Value;
     use std::fs::File;
-    use std::io::BufReader;
+    use std::io::{BufReader, Read, Write};
+    use std::net::{SocketAddr, TcpListener, TcpStream};
     use std::path::Path;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::{Duration, Instant};
 
     #[cfg(not(feature = "ffmpeg"))]
-    fn get_decoder<P: AsRef<Path>>(input: P) -> Result<Y4MDecoder<BufReader<File>>, String> {
+    fn get_decoder<P: AsRef<Path>>(input: P) -> Result<Y4MDecoder<BufReader<File>>, DecoderError> {
         av_metrics_decoders::y4m::new_decoder_from_file(input)
     }
 
     #[cfg(feature = "ffmpeg")]
-    fn get_decoder<P: AsRef<Path>>(input: P) -> Result<FfmpegDecoder, String> {
+    fn get_decoder<P: AsRef<Path>>(input: P) -> Result<FfmpegDecoder, DecoderError> {
         FfmpegDecoder::new(input)
     }
 
@@ -43,6 +132,608 @@ mod tests {
         assert_metric_eq(33.6861, result.avg);
     }
 
+    #[test]
+    fn psnr_peak_value_yuv420p8() {
+        let mut dec1 = get_decoder(format!(
+            "{}/../testfiles/yuv420p8_input.y4m",
+            env!("CARGO_MANIFEST_DIR")
+        ))
+        .unwrap();
+        let mut dec2 = get_decoder(format!(
+            "{}/../testfiles/yuv420p8_output.y4m",
+            env!("CARGO_MANIFEST_DIR")
+        ))
+        .unwrap();
+        let options = PsnrOptions::new().peak_value(235);
+        let result = calculate_video_psnr_with(&mut dec1, &mut dec2, options).unwrap();
+        assert_metric_eq(31.8187, result.y);
+        assert_metric_eq(35.6989, result.u);
+        assert_metric_eq(39.1144, result.v);
+        assert_metric_eq(32.9767, result.avg);
+
+        // Peaks whose square does not fit in an integer only raise the scores
+        let mut dec1 = get_decoder(format!(
+            "{}/../testfiles/yuv420p8_input.y4m",
+            env!("CARGO_MANIFEST_DIR")
+        ))
+        .unwrap();
+        let mut dec2 = get_decoder(format!(
+            "{}/../testfiles/yuv420p8_output.y4m",
+            env!("CARGO_MANIFEST_DIR")
+        ))
+        .unwrap();
+        let options = PsnrOptions::new().peak_value(usize::MAX);
+        let result = calculate_video_psnr_with(&mut dec1, &mut dec2, options).unwrap();
+        let gain = 20.0 * (usize::MAX as f64 / 255.0).log10();
+        assert_metric_eq(32.5281 + gain, result.y);
+        assert_metric_eq(33.6861 + gain, result.avg);
+
+        let frame = synth::gradient::<u8>(16, 16, 8, ChromaSampling::Cs420).unwrap();
+        let config = PsnrConfig {
+            peak_value: Some(0),
+            ..Default::default()
+        };
+        assert!(
+            calculate_frame_psnr_with_config(&frame, &frame, 8, ChromaSampling::Cs420, config)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn psnr_factory_decoder_yuv420p8() {
+        let input1 = format!(
+            "{}/../testfiles/yuv420p8_input.y4m",
+            env!("CARGO_MANIFEST_DIR")
+        );
+        let input2 = format!(
+            "{}/../testfiles/yuv420p8_output.y4m",
+            env!("CARGO_MANIFEST_DIR")
+        );
+        let backend = Backend::detect(&input1).unwrap();
+        assert_eq!(backend, backend.name().parse().unwrap());
+        let mut dec1 = open_decoder(backend, input1).unwrap();
+        let mut dec2 = open_decoder(backend, input2).unwrap();
+        let result = calculate_video_psnr(&mut dec1, &mut dec2, None, |_| ()).unwrap();
+        assert_metric_eq(33.6861, result.avg);
+        assert!("nonexistent".parse::<Backend>().is_err());
+    }
+
+    #[test]
+    fn align_yuv420p8() {
+        let input1 = format!(
+            "{}/../testfiles/yuv420p8_input.y4m",
+            env!("CARGO_MANIFEST_DIR")
+        );
+        let input2 = format!(
+            "{}/../testfiles/yuv420p8_output.y4m",
+            env!("CARGO_MANIFEST_DIR")
+        );
+        let mut dec1 = Skipped::new(get_decoder(&input1).unwrap(), 0);
+        let mut dec2 = Skipped::new(get_decoder(&input2).unwrap(), 0);
+        assert_eq!(detect_frame_offset(&mut dec1, &mut dec2, 4, 3).unwrap(), 0);
+
+        let mut dec1 = Skipped::new(get_decoder(&input1).unwrap(), 0);
+        let mut dec2 = Skipped::new(get_decoder(&input2).unwrap(), 2);
+        assert_eq!(detect_frame_offset(&mut dec1, &mut dec2, 4, 3).unwrap(), -2);
+
+        let mut dec1 = Skipped::new(get_decoder(&input1).unwrap(), 1);
+        let mut dec2 = Skipped::new(get_decoder(&input2).unwrap(), 0);
+        let offset = detect_frame_offset(&mut dec1, &mut dec2, 4, 3).unwrap();
+        assert_eq!(offset, 1);
+
+        let (mut dec1, mut dec2) = Skipped::pair(
+            Skipped::new(get_decoder(&input1).unwrap(), 1),
+            Skipped::new(get_decoder(&input2).unwrap(), 0),
+            offset,
+        );
+        let aligned = calculate_video_psnr(&mut dec1, &mut dec2, None, |_| ()).unwrap();
+        let mut dec1 = Skipped::new(get_decoder(&input1).unwrap(), 1);
+        let mut dec2 = Skipped::new(get_decoder(&input2).unwrap(), 1);
+        let expected = calculate_video_psnr(&mut dec1, &mut dec2, None, |_| ()).unwrap();
+        assert_metric_eq(expected.avg, aligned.avg);
+    }
+
+    #[test]
+    fn detect_shift_yuv420p8() {
+        let mut dec = get_decoder(format!(
+            "{}/../testfiles/yuv420p8_input.y4m",
+            env!("CARGO_MANIFEST_DIR")
+        ))
+        .unwrap();
+        let frame = dec.read_video_frame::<u8>().unwrap();
+        // Moves the picture 2 pixels to the right and 1 pixel up.
+        let moved = shift_frame(&frame, Shift { x: -2, y: 1 });
+        let shift = detect_shift(&frame, &moved, 4).unwrap();
+        assert_eq!(shift, Shift { x: 2, y: -1 });
+        assert_eq!(shift.to_string(), "+2x-1");
+        assert_eq!(detect_shift(&frame, &frame, 4).unwrap(), Shift::default());
+
+        let before = calculate_frame_ssim(&frame, &moved, 8, ChromaSampling::Cs420).unwrap();
+        let restored = shift_frame(&moved, shift);
+        let after = calculate_frame_ssim(&frame, &restored, 8, ChromaSampling::Cs420).unwrap();
+        assert!(after.avg > before.avg + 5.0);
+    }
+
+    /// A decoder for frames with an alpha plane, held in memory as 8-bit samples.
+    struct AlphaFrames {
+        frames: Vec<(Frame<u8>, Frame<u8>)>,
+        details: VideoDetails,
+    }
+
+    impl Decoder for AlphaFrames {
+        fn read_video_frame<T: av_metrics::video::Pixel>(&mut self) -> Option<Frame<T>> {
+            self.read_video_frame_with_alpha().map(|(frame, _)| frame)
+        }
+
+        fn read_video_frame_with_alpha<T: av_metrics::video::Pixel>(
+            &mut self,
+        ) -> Option<(Frame<T>, Option<Plane<T>>)> {
+            if self.frames.is_empty() {
+                return None;
+            }
+            let (color, alpha) = self.frames.remove(0);
+            let mut frame: Frame<T> = Frame::new_with_padding(
+                self.details.width,
+                self.details.height,
+                self.details.chroma_sampling,
+                0,
+            );
+            for (dst, src) in frame.planes.iter_mut().zip(color.planes.iter()) {
+                dst.copy_from_raw_u8(src.data_origin(), src.cfg.stride, 1);
+            }
+            let mut plane = frame.planes[0].clone();
+            plane.copy_from_raw_u8(alpha.planes[0].data_origin(), alpha.planes[0].cfg.stride, 1);
+            Some((frame, Some(plane)))
+        }
+
+        fn get_bit_depth(&self) -> usize {
+            self.details.bit_depth
+        }
+
+        fn get_video_details(&self) -> VideoDetails {
+            self.details
+        }
+    }
+
+    /// A decoder for 8-bit frames held in memory, each filled with one value
+    /// and shown at the given time in seconds.
+    struct TimedFrames {
+        frames: Vec<(f64, u8)>,
+        read: usize,
+        details: VideoDetails,
+    }
+
+    impl Decoder for TimedFrames {
+        fn read_video_frame<T: av_metrics::video::Pixel>(&mut self) -> Option<Frame<T>> {
+            let &(_, value) = self.frames.get(self.read)?;
+            self.read += 1;
+            let mut frame: Frame<T> = Frame::new_with_padding(
+                self.details.width,
+                self.details.height,
+                self.details.chroma_sampling,
+                0,
+            );
+            for plane in frame.planes.iter_mut() {
+                plane.data.fill(T::cast_from(value));
+            }
+            Some(frame)
+        }
+
+        fn get_bit_depth(&self) -> usize {
+            self.details.bit_depth
+        }
+
+        fn get_video_details(&self) -> VideoDetails {
+            self.details
+        }
+
+        fn last_frame_meta(&self) -> Option<FrameMeta> {
+            let number = self.read.checked_sub(1)?;
+            Some(FrameMeta {
+                number,
+                pts: None,
+                time: Some(self.frames[number].0),
+                keyframe: None,
+            })
+        }
+    }
+
+    #[test]
+    fn pair_frames_by_timestamp() {
+        let details = VideoDetails {
+            width: 16,
+            height: 16,
+            ..Default::default()
+        };
+        let dec1 = TimedFrames {
+            frames: vec![(0.0, 0), (1.0, 1), (2.0, 2), (2.1, 3), (4.0, 4)],
+            read: 0,
+            details,
+        };
+        // The second input has no frame near 1 s, a single frame for the two
+        // frames near 2 s, and ends early.
+        let dec2 = TimedFrames {
+            frames: vec![(0.0, 10), (2.05, 12), (3.0, 13)],
+            read: 0,
+            details,
+        };
+        let (mut dec1, mut dec2) = Paired::by_timestamp(dec1, dec2, 0.2);
+        let mut pairs = Vec::new();
+        while let (Some(f1), Some(f2)) =
+            (dec1.read_video_frame::<u8>(), dec2.read_video_frame::<u8>())
+        {
+            let (meta1, meta2) = (
+                dec1.last_frame_meta().unwrap(),
+                dec2.last_frame_meta().unwrap(),
+            );
+            pairs.push((
+                f1.planes[0].data_origin()[0],
+                f2.planes[0].data_origin()[0],
+                meta1.number,
+                meta2.number,
+            ));
+        }
+        assert_eq!(pairs, vec![(0, 10, 0, 0), (2, 12, 2, 1), (3, 12, 3, 1)]);
+
+        let (mut dec1, mut dec2) = Paired::lockstep(
+            get_decoder(format!(
+                "{}/../testfiles/yuv420p8_input.y4m",
+                env!("CARGO_MANIFEST_DIR")
+            ))
+            .unwrap(),
+            get_decoder(format!(
+                "{}/../testfiles/yuv420p8_output.y4m",
+                env!("CARGO_MANIFEST_DIR")
+            ))
+            .unwrap(),
+        );
+        let result = calculate_video_psnr(&mut dec1, &mut dec2, None, |_| ()).unwrap();
+        assert_metric_eq(32.5281, result.y);
+    }
+
+    #[test]
+    fn audio_frame_channels() {
+        let frame = AudioFrame::from_planar(&[vec![0.0, 0.5, 1.0], vec![-0.5, -1.0]]);
+        assert_eq!(frame.channels, 2);
+        assert_eq!(frame.len(), 2);
+        assert_eq!(frame.samples, vec![0.0, -0.5, 0.5, -1.0]);
+        assert_eq!(frame.channel(1).collect::<Vec<_>>(), vec![-0.5, -1.0]);
+        assert_eq!(frame.channel(2).count(), 0);
+
+        let frame = AudioFrame::from_interleaved(2, vec![0.1, 0.2, 0.3]);
+        assert_eq!(frame.len(), 1);
+        assert_eq!(frame.channel(0).collect::<Vec<_>>(), vec![0.1]);
+        assert!(AudioFrame::from_interleaved(2, Vec::new()).is_empty());
+    }
+
+    /// Interleaved samples returned in blocks of 1000 per channel.
+    struct MemoryAudio {
+        samples: Vec<f32>,
+        read: usize,
+        details: AudioDetails,
+    }
+
+    impl MemoryAudio {
+        fn new(samples: Vec<f32>, sample_rate: u32) -> Self {
+            MemoryAudio {
+                samples,
+                read: 0,
+                details: AudioDetails {
+                    sample_rate,
+                    channels: 1,
+                    sample_format: SampleFormat::F32,
+                },
+            }
+        }
+    }
+
+    impl AudioDecoder for MemoryAudio {
+        fn read_audio_frame(&mut self) -> Option<AudioFrame> {
+            if self.read >= self.samples.len() {
+                return None;
+            }
+            let end = (self.read + 1000 * self.details.channels).min(self.samples.len());
+            let frame = AudioFrame::from_interleaved(
+                self.details.channels,
+                self.samples[self.read..end].to_vec(),
+            );
+            self.read = end;
+            Some(frame)
+        }
+
+        fn get_audio_details(&self) -> AudioDetails {
+            self.details
+        }
+    }
+
+    #[test]
+    fn peaq_quantization() {
+        let reference: Vec<f32> = (0..48000)
+            .map(|i| {
+                let t = i as f32 / 48000.;
+                0.3 * (2. * std::f32::consts::PI * 440. * t).sin()
+                    + 0.2 * (2. * std::f32::consts::PI * 1320. * t).sin()
+                    + 0.1 * (2. * std::f32::consts::PI * 5000. * t).sin()
+            })
+            .collect();
+        let quantized = |steps: f32| -> Vec<f32> {
+            reference
+                .iter()
+                .map(|s| (s * steps).round() / steps)
+                .collect()
+        };
+        let peaq = |test: Vec<f32>| {
+            calculate_audio_peaq(
+                &mut MemoryAudio::new(reference.clone(), 48000),
+                &mut MemoryAudio::new(test, 48000),
+                |_| (),
+            )
+            .unwrap()
+        };
+
+        let same = peaq(reference.clone());
+        let fine = peaq(quantized(2048.));
+        let coarse = peaq(quantized(16.));
+        assert!(same.odg > -0.5 && same.odg <= 0.22);
+        assert_eq!(same.movs.rel_dist_frames, 0.);
+        assert!(fine.odg < same.odg);
+        assert!(coarse.odg < fine.odg);
+        assert!(coarse.odg >= -3.98);
+        assert!(coarse.movs.total_nmr > fine.movs.total_nmr);
+
+        assert!(calculate_audio_peaq(
+            &mut MemoryAudio::new(reference.clone(), 44100),
+            &mut MemoryAudio::new(reference.clone(), 44100),
+            |_| (),
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn spectral_scaled_noise() {
+        let mut seed = 1u32;
+        let reference: Vec<f32> = (0..20000)
+            .map(|_| {
+                seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+                (seed >> 8) as f32 / (1 << 24) as f32 - 0.5
+            })
+            .collect();
+        let halved: Vec<f32> = reference.iter().map(|s| s / 2.).collect();
+
+        let same = calculate_audio_spectral(
+            &mut MemoryAudio::new(reference.clone(), 48000),
+            &mut MemoryAudio::new(reference.clone(), 48000),
+            |_| (),
+        )
+        .unwrap();
+        assert_metric_eq(0., same.log_spectral_distance);
+        assert_metric_eq(0., same.spectral_convergence);
+        assert_metric_eq(35., same.segmental_snr);
+
+        for window in [WindowFunction::Hann, WindowFunction::Rectangular] {
+            let result = calculate_audio_spectral_with_config(
+                &mut MemoryAudio::new(reference.clone(), 48000),
+                &mut MemoryAudio::new(halved.clone(), 48000),
+                |_| (),
+                SpectralConfig {
+                    window,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+            assert_metric_eq(6.0206, result.log_spectral_distance);
+            assert_metric_eq(0.5, result.spectral_convergence);
+            assert_metric_eq(6.0206, result.segmental_snr);
+        }
+
+        assert!(calculate_audio_spectral_with_config(
+            &mut MemoryAudio::new(reference.clone(), 48000),
+            &mut MemoryAudio::new(halved, 48000),
+            |_| (),
+            SpectralConfig {
+                frame_len: 1000,
+                ..Default::default()
+            },
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn psnr_alpha_plane() {
+        let details = VideoDetails {
+            width: 64,
+            height: 64,
+            ..Default::default()
+        };
+        let color = synth::zone_plate::<u8>(64, 64, 8, ChromaSampling::Cs420).unwrap();
+        let alpha = synth::gradient::<u8>(64, 64, 8, ChromaSampling::Cs420).unwrap();
+        let noisy = synth::add_noise(&alpha, 8, 30.0, 3).unwrap();
+        let mut dec1 = AlphaPlane::new(AlphaFrames {
+            frames: vec![(color.clone(), alpha.clone()), (color.clone(), alpha)],
+            details,
+        });
+        let mut dec2 = AlphaPlane::new(AlphaFrames {
+            frames: vec![(color.clone(), noisy.clone()), (color, noisy)],
+            details,
+        });
+        let result = calculate_video_psnr(&mut dec1, &mut dec2, Some(2), |_| ()).unwrap();
+        assert_metric_eq(30.0, result.y);
+        assert_eq!(result.alpha, None);
+    }
+
+    #[test]
+    fn pipeline_stats() {
+        let mut dec1 = get_decoder(format!(
+            "{}/../testfiles/yuv420p8_input.y4m",
+            env!("CARGO_MANIFEST_DIR")
+        ))
+        .unwrap();
+        let mut dec2 = get_decoder(format!(
+            "{}/../testfiles/yuv420p8_output.y4m",
+            env!("CARGO_MANIFEST_DIR")
+        ))
+        .unwrap();
+        let monitor = PipelineMonitor::new();
+        let reader = monitor.clone();
+        let result = calculate_video_psnr_with(
+            &mut dec1,
+            &mut dec2,
+            PsnrOptions::new().monitor(&monitor).progress(|_| {
+                let stats = reader.stats();
+                assert!(stats.queue_depth <= stats.queue_capacity);
+            }),
+        )
+        .unwrap();
+        assert_metric_eq(33.6861, result.avg);
+        let stats = monitor.stats();
+        assert_eq!(stats.frames_decoded, 3);
+        assert_eq!(stats.frames_scored, 3);
+        assert_eq!(stats.queue_depth, 0);
+        assert!(stats.threads >= 1);
+        assert!(stats.decode_fps() > 0.0);
+        assert!(stats.score_fps() > 0.0);
+    }
+
+    #[test]
+    fn pipeline_control() {
+        let path = |kind: &str| {
+            format!(
+                "{}/../testfiles/yuv420p8_{}.y4m",
+                env!("CARGO_MANIFEST_DIR"),
+                kind
+            )
+        };
+        let psnr = |options: PsnrOptions| {
+            let mut dec1 = get_decoder(path("input")).unwrap();
+            let mut dec2 = get_decoder(path("output")).unwrap();
+            calculate_video_psnr_with(&mut dec1, &mut dec2, options).unwrap()
+        };
+
+        let control = PipelineControl::new();
+        control.set_max_fps(Some(-1.0));
+        assert_eq!(control.max_fps(), None);
+        control.set_max_fps(Some(20.0));
+        assert_eq!(control.max_fps(), Some(20.0));
+        let start = std::time::Instant::now();
+        let result = psnr(PsnrOptions::new().control(&control));
+        // The second and third frames each wait for 50 ms
+        assert!(start.elapsed() >= Duration::from_millis(90));
+        assert_metric_eq(33.6861, result.avg);
+
+        let control = PipelineControl::new();
+        let monitor = PipelineMonitor::new();
+        control.pause();
+        assert!(control.is_paused());
+        let result = std::thread::scope(|s| {
+            let handle = s.spawn(|| psnr(PsnrOptions::new().control(&control).monitor(&monitor)));
+            std::thread::sleep(Duration::from_millis(100));
+            assert_eq!(monitor.stats().frames_decoded, 0);
+            control.resume();
+            handle.join().unwrap()
+        });
+        assert!(!control.is_paused());
+        assert_metric_eq(33.6861, result.avg);
+        assert_eq!(monitor.stats().frames_decoded, 3);
+    }
+
+    #[test]
+    fn frame_sink() {
+        let path = |kind: &str| {
+            format!(
+                "{}/../testfiles/yuv420p8_{}.y4m",
+                env!("CARGO_MANIFEST_DIR"),
+                kind
+            )
+        };
+        let frames = Arc::new(Mutex::new(Vec::new()));
+        let sink = FrameSink::new({
+            let frames = frames.clone();
+            move |frame: &ScoredFrame| {
+                assert!(frame.frames::<u16>().is_none());
+                let [frame1, frame2] = frame.frames::<u8>().unwrap();
+                let expected =
+                    calculate_frame_psnr(frame1, frame2, 8, ChromaSampling::Cs420).unwrap();
+                assert_eq!(frame.score, Some(Score::Planar(expected)));
+                assert_eq!(frame.context.bit_depth(), 8);
+                frames.lock().unwrap().push(frame.frame);
+            }
+        });
+        let mut dec1 = get_decoder(path("input")).unwrap();
+        let mut dec2 = get_decoder(path("output")).unwrap();
+        let result =
+            calculate_video_psnr_with(&mut dec1, &mut dec2, PsnrOptions::new().frame_sink(&sink))
+                .unwrap();
+        assert_metric_eq(33.6861, result.avg);
+        // The frames are scored on several threads, so they may come in any order
+        let mut frames = frames.lock().unwrap().clone();
+        frames.sort();
+        assert_eq!(frames, [0, 1, 2]);
+
+        let scores = Arc::new(Mutex::new(Vec::new()));
+        let sink = FrameSink::new({
+            let scores = scores.clone();
+            move |frame: &ScoredFrame| {
+                let [frame1, frame2] = frame.frames::<u8>().unwrap();
+                let expected =
+                    calculate_frame_ciede(frame1, frame2, 8, ChromaSampling::Cs420).unwrap();
+                assert_eq!(frame.score, Some(Score::Value(expected)));
+                scores.lock().unwrap().push(expected);
+            }
+        });
+        let mut dec1 = get_decoder(path("input")).unwrap();
+        let mut dec2 = get_decoder(path("output")).unwrap();
+        let result =
+            calculate_video_ciede_with(&mut dec1, &mut dec2, CiedeOptions::new().frame_sink(&sink))
+                .unwrap();
+        let scores = scores.lock().unwrap();
+        assert_eq!(scores.len(), 3);
+        assert_metric_eq(result, scores.iter().sum::<f64>() / 3.0);
+    }
+
+    #[test]
+    fn aggregators_from_frame_sink() {
+        let path = |kind: &str| {
+            format!(
+                "{}/../testfiles/yuv420p8_{}.y4m",
+                env!("CARGO_MANIFEST_DIR"),
+                kind
+            )
+        };
+        let tiles = Arc::new(TileAggregator::new(TileGrid::new(2, 2)));
+        let banding = Arc::new(BandingAggregator::new(BandingConfig::default()));
+        let sink = FrameSink::new({
+            let tiles = tiles.clone();
+            let banding = banding.clone();
+            move |frame: &ScoredFrame| {
+                let [frame1, frame2] = frame.frames::<u8>().unwrap();
+                tiles
+                    .push(frame.frame, frame1, frame2, frame.context)
+                    .unwrap();
+                banding
+                    .push(frame.frame, frame1, frame2, frame.context)
+                    .unwrap();
+            }
+        });
+        let mut dec1 = get_decoder(path("input")).unwrap();
+        let mut dec2 = get_decoder(path("output")).unwrap();
+        calculate_video_psnr_with(&mut dec1, &mut dec2, PsnrOptions::new().frame_sink(&sink))
+            .unwrap();
+        drop(sink);
+        let tiles = Arc::into_inner(tiles).unwrap().finish().unwrap();
+        let banding = Arc::into_inner(banding).unwrap().finish().unwrap();
+
+        let mut dec1 = get_decoder(path("input")).unwrap();
+        let mut dec2 = get_decoder(path("output")).unwrap();
+        let expected =
+            calculate_video_psnr_tiles(&mut dec1, &mut dec2, TileGrid::new(2, 2), None, |_| ())
+                .unwrap();
+        assert_eq!(tiles, expected);
+        let mut dec1 = get_decoder(path("input")).unwrap();
+        let mut dec2 = get_decoder(path("output")).unwrap();
+        let expected = calculate_video_banding(&mut dec1, &mut dec2, None, |_| ()).unwrap();
+        assert_eq!(banding, expected);
+    }
+
     #[test]
     fn psnr_yuv422p8() {
         let mut dec1 = get_decoder(format!(
@@ -100,6 +791,381 @@ mod tests {
         assert_metric_eq(33.7071, result.avg);
     }
 
+    #[test]
+    fn psnr_yuv420p12() {
+        let mut dec1 = get_decoder(format!(
+            "{}/../testfiles/yuv420p12_input.y4m",
+            env!("CARGO_MANIFEST_DIR")
+        ))
+        .unwrap();
+        let mut dec2 = get_decoder(format!(
+            "{}/../testfiles/yuv420p12_output.y4m",
+            env!("CARGO_MANIFEST_DIR")
+        ))
+        .unwrap();
+        let result = calculate_video_psnr(&mut dec1, &mut dec2, None, |_| ()).unwrap();
+        assert_metric_eq(32.5485, result.y);
+        assert_metric_eq(36.4985, result.u);
+        assert_metric_eq(39.8621, result.v);
+        assert_metric_eq(33.7134, result.avg);
+    }
+
+    #[test]
+    fn psnr_frame_ref_yuv420p10() {
+        let path = |kind: &str| {
+            format!(
+                "{}/../testfiles/yuv420p10_{}.y4m",
+                env!("CARGO_MANIFEST_DIR"),
+                kind
+            )
+        };
+        let mut dec1 = get_decoder(path("input")).unwrap();
+        let mut dec2 = get_decoder(path("output")).unwrap();
+        let mut ref1 = get_decoder(path("input")).unwrap();
+        let mut ref2 = get_decoder(path("output")).unwrap();
+        let mut frames = 0;
+        while let (Some(frame1), Some(frame2)) = (
+            dec1.read_video_frame::<u16>(),
+            dec2.read_video_frame::<u16>(),
+        ) {
+            let expected =
+                calculate_frame_psnr(&frame1, &frame2, 10, ChromaSampling::Cs420).unwrap();
+            let result = ref1
+                .read_video_frame_ref::<u16, _, _>(|frame1| {
+                    ref2.read_video_frame_ref::<u16, _, _>(|frame2| {
+                        calculate_frame_psnr_ref(&frame1, &frame2, 10, ChromaSampling::Cs420)
+                            .unwrap()
+                    })
+                })
+                .flatten()
+                .unwrap();
+            assert_metric_eq(expected.y, result.y);
+            assert_metric_eq(expected.u, result.u);
+            assert_metric_eq(expected.v, result.v);
+            assert_metric_eq(expected.avg, result.avg);
+            frames += 1;
+        }
+        assert!(frames > 0);
+    }
+
+    #[test]
+    fn psnr_frame_ref_yuv422p8() {
+        let path = |kind: &str| {
+            format!(
+                "{}/../testfiles/yuv422p8_{}.y4m",
+                env!("CARGO_MANIFEST_DIR"),
+                kind
+            )
+        };
+        let mut dec1 = get_decoder(path("input")).unwrap();
+        let mut dec2 = get_decoder(path("output")).unwrap();
+        let mut ref1 = get_decoder(path("input")).unwrap();
+        let mut ref2 = get_decoder(path("output")).unwrap();
+        let mut frames = 0;
+        while let (Some(frame1), Some(frame2)) =
+            (dec1.read_video_frame::<u8>(), dec2.read_video_frame::<u8>())
+        {
+            let expected =
+                calculate_frame_psnr(&frame1, &frame2, 8, ChromaSampling::Cs422).unwrap();
+            let result = ref1
+                .read_video_frame_ref::<u8, _, _>(|frame1| {
+                    ref2.read_video_frame_ref::<u8, _, _>(|frame2| {
+                        calculate_frame_psnr_ref(&frame1, &frame2, 8, ChromaSampling::Cs422)
+                            .unwrap()
+                    })
+                })
+                .flatten()
+                .unwrap();
+            assert_metric_eq(expected.y, result.y);
+            assert_metric_eq(expected.u, result.u);
+            assert_metric_eq(expected.v, result.v);
+            assert_metric_eq(expected.avg, result.avg);
+            frames += 1;
+        }
+        assert!(frames > 0);
+    }
+
+    #[test]
+    fn read_video_frame_into_reuses_frame() {
+        let path = format!(
+            "{}/../testfiles/yuv420p8_input.y4m",
+            env!("CARGO_MANIFEST_DIR")
+        );
+        let mut dec = get_decoder(&path).unwrap();
+        let mut expected = get_decoder(&path).unwrap();
+        let mut frame = dec.read_video_frame::<u8>().unwrap();
+        expected.read_video_frame::<u8>().unwrap();
+        let luma = frame.planes[0].data.as_ptr();
+        while dec.read_video_frame_into(&mut frame) {
+            let expected = expected.read_video_frame::<u8>().unwrap();
+            assert_eq!(frame.planes[0].data.as_ptr(), luma);
+            for (plane, expected) in frame.planes.iter().zip(expected.planes.iter()) {
+                assert_eq!(&plane.data[..], &expected.data[..]);
+            }
+        }
+        assert!(expected.read_video_frame::<u8>().is_none());
+    }
+
+    #[test]
+    fn psnr_downscaled_yuv420p8() {
+        let path = |kind: &str| {
+            format!(
+                "{}/../testfiles/yuv420p8_{}.y4m",
+                env!("CARGO_MANIFEST_DIR"),
+                kind
+            )
+        };
+        let mut dec1 = Downscaled::new(get_decoder(path("input")).unwrap(), 1).unwrap();
+        let mut dec2 = Downscaled::new(get_decoder(path("output")).unwrap(), 1).unwrap();
+        let result = calculate_video_psnr(&mut dec1, &mut dec2, None, |_| ()).unwrap();
+        assert_metric_eq(32.5281, result.y);
+        assert_metric_eq(33.6861, result.avg);
+
+        let mut dec1 = Downscaled::new(get_decoder(path("input")).unwrap(), 2).unwrap();
+        let mut dec2 = Downscaled::new(get_decoder(path("output")).unwrap(), 2).unwrap();
+        let details = dec1.get_video_details();
+        let full = get_decoder(path("input")).unwrap().get_video_details();
+        assert_eq!(details.width, full.width.div_ceil(2));
+        assert_eq!(details.height, full.height.div_ceil(2));
+        let result = calculate_video_psnr(&mut dec1, &mut dec2, None, |_| ()).unwrap();
+        assert_metric_eq(35.8130, result.y);
+        assert_metric_eq(39.8966, result.u);
+        assert_metric_eq(43.2865, result.v);
+        assert_metric_eq(36.9959, result.avg);
+    }
+
+    #[test]
+    fn downscale_frame_averages_blocks() {
+        let details = VideoDetails {
+            width: 16,
+            height: 16,
+            ..Default::default()
+        };
+        let mut frame = synth::gradient::<u8>(16, 16, 8, ChromaSampling::Cs420).unwrap();
+        for (y, row) in frame.planes[0].rows_iter_mut().enumerate().take(16) {
+            for (x, pixel) in row[..16].iter_mut().enumerate() {
+                *pixel = ((x / 4) * 40 + (y / 4) * 10 + (x + y) % 2) as u8;
+            }
+        }
+        let scaled = downscale_frame(&frame, &details, 4);
+        for (y, row) in scaled.planes[0].rows_iter().enumerate().take(4) {
+            for (x, &pixel) in row[..4].iter().enumerate() {
+                assert_eq!(pixel as usize, x * 40 + y * 10 + 1);
+            }
+        }
+        assert!(Downscaled::new(
+            get_decoder(format!(
+                "{}/../testfiles/yuv420p8_input.y4m",
+                env!("CARGO_MANIFEST_DIR")
+            ))
+            .unwrap(),
+            0
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn psnr_prefilter_yuv420p8() {
+        let path = |kind: &str| {
+            format!(
+                "{}/../testfiles/yuv420p8_{}.y4m",
+                env!("CARGO_MANIFEST_DIR"),
+                kind
+            )
+        };
+        let config = PsnrConfig {
+            prefilter: Some(Prefilter::Binomial3x3),
+            ..Default::default()
+        };
+        let mut dec1 = get_decoder(path("input")).unwrap();
+        let mut dec2 = get_decoder(path("output")).unwrap();
+        let result =
+            calculate_video_psnr_with_config(&mut dec1, &mut dec2, None, |_| (), config).unwrap();
+        assert_metric_eq(37.7839, result.y);
+        assert_metric_eq(41.6652, result.u);
+        assert_metric_eq(45.1353, result.v);
+        assert_metric_eq(38.9443, result.avg);
+
+        let mut dec1 = get_decoder(path("input")).unwrap();
+        let mut dec2 = get_decoder(path("output")).unwrap();
+        let result =
+            calculate_video_apsnr_with_config(&mut dec1, &mut dec2, None, |_| (), config).unwrap();
+        assert_metric_eq(37.8341, result.y);
+        assert_metric_eq(41.6667, result.u);
+        assert_metric_eq(45.1370, result.v);
+        assert_metric_eq(38.9839, result.avg);
+    }
+
+    #[test]
+    fn psnr_prefilter_removes_dither() {
+        let mut flat = synth::gradient::<u8>(64, 64, 8, ChromaSampling::Cs444).unwrap();
+        for plane in flat.planes.iter_mut() {
+            for row in plane.rows_iter_mut() {
+                row.fill(128);
+            }
+        }
+        let mut dithered = flat.clone();
+        for plane in dithered.planes.iter_mut() {
+            for (y, row) in plane.rows_iter_mut().enumerate() {
+                for (x, pixel) in row.iter_mut().enumerate() {
+                    *pixel = if (x + y) % 2 == 0 { 127 } else { 129 };
+                }
+            }
+        }
+        let plain = calculate_frame_psnr(&flat, &dithered, 8, ChromaSampling::Cs444).unwrap();
+        assert_metric_eq(48.1308, plain.avg);
+        for prefilter in [Prefilter::Binomial3x3, Prefilter::Box3x3] {
+            let config = PsnrConfig {
+                prefilter: Some(prefilter),
+                ..Default::default()
+            };
+            let filtered = calculate_frame_psnr_with_config(
+                &flat,
+                &dithered,
+                8,
+                ChromaSampling::Cs444,
+                config,
+            )
+            .unwrap();
+            assert_metric_eq(100.0, filtered.avg);
+        }
+    }
+
+    #[test]
+    fn psnr_b_yuv420p8() {
+        let mut dec1 = get_decoder(format!(
+            "{}/../testfiles/yuv420p8_input.y4m",
+            env!("CARGO_MANIFEST_DIR")
+        ))
+        .unwrap();
+        let mut dec2 = get_decoder(format!(
+            "{}/../testfiles/yuv420p8_output.y4m",
+            env!("CARGO_MANIFEST_DIR")
+        ))
+        .unwrap();
+        let result = calculate_video_psnr_b(&mut dec1, &mut dec2, None, |_| ()).unwrap();
+        assert_metric_eq(32.3336, result.y);
+        assert_metric_eq(36.3527, result.u);
+        assert_metric_eq(39.7903, result.v);
+        assert_metric_eq(33.5103, result.avg);
+    }
+
+    #[test]
+    fn psnr_b_penalizes_blocking() {
+        let mut flat = synth::gradient::<u8>(64, 64, 8, ChromaSampling::Cs444).unwrap();
+        for plane in flat.planes.iter_mut() {
+            for row in plane.rows_iter_mut() {
+                row.fill(128);
+            }
+        }
+        let distort = |offset: fn(usize, usize) -> bool| {
+            let mut frame = flat.clone();
+            for plane in frame.planes.iter_mut() {
+                for (y, row) in plane.rows_iter_mut().enumerate() {
+                    for (x, pixel) in row.iter_mut().enumerate() {
+                        *pixel = if offset(x, y) { 130 } else { 126 };
+                    }
+                }
+            }
+            frame
+        };
+        let blocky = distort(|x, y| (x / 8 + y / 8) % 2 == 0);
+        let noisy = distort(|x, y| (x + y) % 2 == 0);
+
+        let psnr = calculate_frame_psnr(&flat, &noisy, 8, ChromaSampling::Cs444).unwrap();
+        let psnr_b = calculate_frame_psnr_b(&flat, &noisy, 8, ChromaSampling::Cs444).unwrap();
+        assert_metric_eq(psnr.avg, psnr_b.avg);
+
+        let psnr = calculate_frame_psnr(&flat, &blocky, 8, ChromaSampling::Cs444).unwrap();
+        let psnr_b = calculate_frame_psnr_b(&flat, &blocky, 8, ChromaSampling::Cs444).unwrap();
+        assert_metric_eq(42.1102, psnr.avg);
+        assert!(psnr_b.avg < psnr.avg - 1.0);
+    }
+
+    #[test]
+    fn temporal_yuv420p8() {
+        let path = |kind: &str| {
+            format!(
+                "{}/../testfiles/yuv420p8_{}.y4m",
+                env!("CARGO_MANIFEST_DIR"),
+                kind
+            )
+        };
+        let mut dec1 = get_decoder(path("input")).unwrap();
+        let mut dec2 = get_decoder(path("output")).unwrap();
+        let result = calculate_video_temporal(&mut dec1, &mut dec2, None, |_| ()).unwrap();
+        assert_metric_eq(106.0863, result.reference.si);
+        assert_metric_eq(26.4825, result.reference.ti);
+        assert_metric_eq(96.1961, result.distorted.si);
+        assert_metric_eq(23.7642, result.distorted.ti);
+        assert_metric_eq(2.7160, result.flicker);
+
+        let mut dec1 = get_decoder(path("input")).unwrap();
+        let mut dec2 = get_decoder(path("input")).unwrap();
+        let result = calculate_video_temporal(&mut dec1, &mut dec2, None, |_| ()).unwrap();
+        assert_eq!(result.reference, result.distorted);
+        assert_metric_eq(0.0, result.flicker);
+
+        let mut dec1 = get_decoder(path("input")).unwrap();
+        let mut dec2 = get_decoder(path("output")).unwrap();
+        assert!(calculate_video_temporal(&mut dec1, &mut dec2, Some(1), |_| ()).is_err());
+    }
+
+    #[test]
+    fn lossless_yuv420p8() {
+        let path = |kind: &str| {
+            format!(
+                "{}/../testfiles/yuv420p8_{}.y4m",
+                env!("CARGO_MANIFEST_DIR"),
+                kind
+            )
+        };
+        let mut dec1 = get_decoder(path("input")).unwrap();
+        let mut dec2 = get_decoder(path("input")).unwrap();
+        let result = verify_video_lossless(&mut dec1, &mut dec2, None, |_| ()).unwrap();
+        assert!(result.is_lossless());
+        assert_eq!(result.frames, 3);
+
+        let mut dec1 = get_decoder(path("input")).unwrap();
+        let mut dec2 = get_decoder(path("output")).unwrap();
+        let result = verify_video_lossless(&mut dec1, &mut dec2, None, |_| ()).unwrap();
+        assert_eq!(result.frames, 0);
+        assert_eq!(
+            result.mismatch,
+            Some(Mismatch::Sample {
+                frame: 0,
+                plane: 0,
+                x: 0,
+                y: 0,
+                value1: 34,
+                value2: 23,
+            })
+        );
+
+        let mut dec1 = get_decoder(path("input")).unwrap();
+        let mut dec2 = get_decoder(path("input")).unwrap();
+        let result = verify_video_lossless(&mut dec1, &mut dec2, Some(2), |_| ()).unwrap();
+        assert_eq!(result.frames, 2);
+    }
+
+    #[test]
+    fn temporal_frame_si_ti() {
+        let frame = synth::zone_plate::<u16>(64, 64, 10, ChromaSampling::Cs420).unwrap();
+        let mut flat = frame.clone();
+        for plane in flat.planes.iter_mut() {
+            for row in plane.rows_iter_mut() {
+                row.fill(512);
+            }
+        }
+        assert_metric_eq(0.0, calculate_frame_si(&flat, 10));
+        assert!(calculate_frame_si(&frame, 10) > 0.0);
+        assert_metric_eq(0.0, calculate_frame_ti(&frame, &frame, 10).unwrap());
+        assert_metric_eq(
+            calculate_frame_ti(&flat, &frame, 10).unwrap(),
+            calculate_frame_ti(&frame, &flat, 10).unwrap(),
+        );
+    }
+
     #[test]
     fn apsnr_yuv420p8() {
         let mut dec1 = get_decoder(format!(
@@ -176,6 +1242,25 @@ mod tests {
         assert_metric_eq(33.7200, result.avg);
     }
 
+    #[test]
+    fn apsnr_yuv420p12() {
+        let mut dec1 = get_decoder(format!(
+            "{}/../testfiles/yuv420p12_input.y4m",
+            env!("CARGO_MANIFEST_DIR")
+        ))
+        .unwrap();
+        let mut dec2 = get_decoder(format!(
+            "{}/../testfiles/yuv420p12_output.y4m",
+            env!("CARGO_MANIFEST_DIR")
+        ))
+        .unwrap();
+        let result = calculate_video_apsnr(&mut dec1, &mut dec2, None, |_| ()).unwrap();
+        assert_metric_eq(32.5650, result.y);
+        assert_metric_eq(36.4987, result.u);
+        assert_metric_eq(39.8627, result.v);
+        assert_metric_eq(33.7264, result.avg);
+    }
+
     #[test]
     fn psnr_hvs_yuv420p8() {
         let mut dec1 = get_decoder(format!(
@@ -209,9 +1294,9 @@ mod tests {
         .unwrap();
         let result = calculate_video_psnr_hvs(&mut dec1, &mut dec2, None, |_| ()).unwrap();
         assert_metric_eq(45.3473, result.y);
-        assert_metric_eq(46.3951, result.u);
-        assert_metric_eq(45.1177, result.v);
-        assert_metric_eq(39.5041, result.avg);
+        assert_metric_eq(46.7731, result.u);
+        assert_metric_eq(45.5214, result.v);
+        assert_metric_eq(39.6878, result.avg);
     }
 
     #[test]
@@ -228,9 +1313,37 @@ mod tests {
         .unwrap();
         let result = calculate_video_psnr_hvs(&mut dec1, &mut dec2, None, |_| ()).unwrap();
         assert_metric_eq(34.1887, result.y);
-        assert_metric_eq(38.0190, result.u);
-        assert_metric_eq(40.4087, result.v);
-        assert_metric_eq(27.2354, result.avg);
+        assert_metric_eq(41.4274, result.u);
+        assert_metric_eq(43.8427, result.v);
+        assert_metric_eq(28.2874, result.avg);
+    }
+
+    #[test]
+    fn psnr_hvs_chroma_csf_by_subsampling() {
+        // The same noisy color bars in each subsampling. The luma table does
+        // not depend on the subsampling, the chroma tables match the
+        // resolution of the chroma planes.
+        let scores = [
+            ChromaSampling::Cs420,
+            ChromaSampling::Cs422,
+            ChromaSampling::Cs444,
+        ]
+        .map(|cs| {
+            let frame1 = synth::color_bars::<u8>(128, 64, 8, cs).unwrap();
+            let frame2 = synth::add_noise(&frame1, 8, 30.0, 7).unwrap();
+            calculate_frame_psnr_hvs(&frame1, &frame2, 8, cs).unwrap()
+        });
+        let expected = [
+            (36.7094, 34.2482, 29.4687),
+            (38.0471, 36.4316, 27.9686),
+            (38.8287, 38.1233, 25.7295),
+        ];
+        for (result, (u, v, avg)) in scores.iter().zip(expected) {
+            assert_metric_eq(32.1746, result.y);
+            assert_metric_eq(u, result.u);
+            assert_metric_eq(v, result.v);
+            assert_metric_eq(avg, result.avg);
+        }
     }
 
     #[test]
@@ -253,26 +1366,26 @@ mod tests {
     }
 
     #[test]
-    fn ssim_yuv420p8() {
+    fn psnr_hvs_yuv420p12() {
         let mut dec1 = get_decoder(format!(
-            "{}/../testfiles/yuv420p8_input.y4m",
+            "{}/../testfiles/yuv420p12_input.y4m",
             env!("CARGO_MANIFEST_DIR")
         ))
         .unwrap();
         let mut dec2 = get_decoder(format!(
-            "{}/../testfiles/yuv420p8_output.y4m",
+            "{}/../testfiles/yuv420p12_output.y4m",
             env!("CARGO_MANIFEST_DIR")
         ))
         .unwrap();
-        let result = calculate_video_ssim(&mut dec1, &mut dec2, None, |_| ()).unwrap();
-        assert_metric_eq(13.2572, result.y);
-        assert_metric_eq(10.8624, result.u);
-        assert_metric_eq(12.8369, result.v);
-        assert_metric_eq(12.6899, result.avg);
+        let result = calculate_video_psnr_hvs(&mut dec1, &mut dec2, None, |_| ()).unwrap();
+        assert_metric_eq(34.5045, result.y);
+        assert_metric_eq(38.1928, result.u);
+        assert_metric_eq(41.1156, result.v);
+        assert_metric_eq(32.0934, result.avg);
     }
 
     #[test]
-    fn msssim_yuv420p8() {
+    fn psnr_hvs_yuv420p8_nosimd() {
         let mut dec1 = get_decoder(format!(
             "{}/../testfiles/yuv420p8_input.y4m",
             env!("CARGO_MANIFEST_DIR")
@@ -283,36 +1396,465 @@ mod tests {
             env!("CARGO_MANIFEST_DIR")
         ))
         .unwrap();
-        let result = calculate_video_msssim(&mut dec1, &mut dec2, None, |_| ()).unwrap();
-        assert_metric_eq(18.8343, result.y);
-        assert_metric_eq(16.6943, result.u);
-        assert_metric_eq(18.7662, result.v);
-        assert_metric_eq(18.3859, result.avg);
+        while let (Some(frame1), Some(frame2)) =
+            (dec1.read_video_frame::<u8>(), dec2.read_video_frame::<u8>())
+        {
+            let simd =
+                calculate_frame_psnr_hvs(&frame1, &frame2, 8, ChromaSampling::Cs420).unwrap();
+            let scalar =
+                calculate_frame_psnr_hvs_nosimd(&frame1, &frame2, 8, ChromaSampling::Cs420)
+                    .unwrap();
+            assert_metric_eq(scalar.y, simd.y);
+            assert_metric_eq(scalar.u, simd.u);
+            assert_metric_eq(scalar.v, simd.v);
+            assert_metric_eq(scalar.avg, simd.avg);
+        }
     }
 
     #[test]
-    fn ssim_yuv422p8() {
+    fn psnr_hvs_yuv420p12_nosimd() {
         let mut dec1 = get_decoder(format!(
-            "{}/../testfiles/yuv422p8_input.y4m",
+            "{}/../testfiles/yuv420p12_input.y4m",
             env!("CARGO_MANIFEST_DIR")
         ))
         .unwrap();
         let mut dec2 = get_decoder(format!(
-            "{}/../testfiles/yuv422p8_output.y4m",
+            "{}/../testfiles/yuv420p12_output.y4m",
             env!("CARGO_MANIFEST_DIR")
         ))
         .unwrap();
-        let result = calculate_video_ssim(&mut dec1, &mut dec2, None, |_| ()).unwrap();
-        assert_metric_eq(21.1130, result.y);
-        assert_metric_eq(21.9978, result.u);
-        assert_metric_eq(22.7898, result.v);
-        assert_metric_eq(21.6987, result.avg);
+        while let (Some(frame1), Some(frame2)) = (
+            dec1.read_video_frame::<u16>(),
+            dec2.read_video_frame::<u16>(),
+        ) {
+            let simd =
+                calculate_frame_psnr_hvs(&frame1, &frame2, 12, ChromaSampling::Cs420).unwrap();
+            let scalar =
+                calculate_frame_psnr_hvs_nosimd(&frame1, &frame2, 12, ChromaSampling::Cs420)
+                    .unwrap();
+            assert_metric_eq(scalar.y, simd.y);
+            assert_metric_eq(scalar.u, simd.u);
+            assert_metric_eq(scalar.v, simd.v);
+            assert_metric_eq(scalar.avg, simd.avg);
+        }
     }
 
     #[test]
-    fn msssim_yuv422p8() {
+    fn ssim_yuv420p8() {
         let mut dec1 = get_decoder(format!(
-            "{}/../testfiles/yuv422p8_input.y4m",
+            "{}/../testfiles/yuv420p8_input.y4m",
+            env!("CARGO_MANIFEST_DIR")
+        ))
+        .unwrap();
+        let mut dec2 = get_decoder(format!(
+            "{}/../testfiles/yuv420p8_output.y4m",
+            env!("CARGO_MANIFEST_DIR")
+        ))
+        .unwrap();
+        let result = calculate_video_ssim(&mut dec1, &mut dec2, None, |_| ()).unwrap();
+        assert_metric_eq(13.2572, result.y);
+        assert_metric_eq(10.8624, result.u);
+        assert_metric_eq(12.8369, result.v);
+        assert_metric_eq(12.6899, result.avg);
+    }
+
+    #[test]
+    fn ssim_dssim_yuv420p8() {
+        let mut dec1 = get_decoder(format!(
+            "{}/../testfiles/yuv420p8_input.y4m",
+            env!("CARGO_MANIFEST_DIR")
+        ))
+        .unwrap();
+        let mut dec2 = get_decoder(format!(
+            "{}/../testfiles/yuv420p8_output.y4m",
+            env!("CARGO_MANIFEST_DIR")
+        ))
+        .unwrap();
+        let result = calculate_video_ssim(&mut dec1, &mut dec2, None, |_| ()).unwrap();
+        let dssim = result.dssim.unwrap();
+        let ssim = 1.0 - 10f64.powf(-result.avg / 10.0);
+        assert!((dssim - 0.0569).abs() < 0.0001, "got {}", dssim);
+        assert!((dssim - (1.0 / ssim - 1.0)).abs() < 1e-9);
+
+        let frame = synth::zone_plate::<u8>(64, 64, 8, ChromaSampling::Cs420).unwrap();
+        let result = calculate_frame_ssim(&frame, &frame, 8, ChromaSampling::Cs420).unwrap();
+        assert!(result.dssim.unwrap().abs() < 1e-9);
+
+        let psnr = calculate_frame_psnr(&frame, &frame, 8, ChromaSampling::Cs420).unwrap();
+        assert_eq!(None, psnr.dssim);
+    }
+
+    #[test]
+    fn ssim_raw_scale_yuv420p8() {
+        let path = |kind: &str| {
+            format!(
+                "{}/../testfiles/yuv420p8_{}.y4m",
+                env!("CARGO_MANIFEST_DIR"),
+                kind
+            )
+        };
+        let assert_raw_eq = |expected: f64, value: f64| {
+            assert!(
+                (expected - value).abs() < 0.0001,
+                "Expected {}, got {}",
+                expected,
+                value
+            );
+        };
+
+        let mut dec1 = get_decoder(path("input")).unwrap();
+        let mut dec2 = get_decoder(path("output")).unwrap();
+        let config = SsimConfig {
+            scale: SsimScale::Raw,
+            ..Default::default()
+        };
+        let result =
+            calculate_video_ssim_with_config(&mut dec1, &mut dec2, None, |_| (), config).unwrap();
+        assert_raw_eq(0.9528, result.y);
+        assert_raw_eq(0.9180, result.u);
+        assert_raw_eq(0.9480, result.v);
+        assert_raw_eq(0.9462, result.avg);
+        assert_raw_eq(1.0 / result.avg - 1.0, result.dssim.unwrap());
+
+        let mut dec1 = get_decoder(path("input")).unwrap();
+        let mut dec2 = get_decoder(path("output")).unwrap();
+        let options = MsSsimOptions::new().scale(SsimScale::Raw);
+        let result = calculate_video_msssim_with(&mut dec1, &mut dec2, options).unwrap();
+        assert_raw_eq(0.9855, result.avg);
+    }
+
+    #[test]
+    fn msssim_yuv420p8() {
+        let mut dec1 = get_decoder(format!(
+            "{}/../testfiles/yuv420p8_input.y4m",
+            env!("CARGO_MANIFEST_DIR")
+        ))
+        .unwrap();
+        let mut dec2 = get_decoder(format!(
+            "{}/../testfiles/yuv420p8_output.y4m",
+            env!("CARGO_MANIFEST_DIR")
+        ))
+        .unwrap();
+        let result = calculate_video_msssim(&mut dec1, &mut dec2, None, |_| ()).unwrap();
+        assert_metric_eq(18.8343, result.y);
+        assert_metric_eq(16.6943, result.u);
+        assert_metric_eq(18.7662, result.v);
+        assert_metric_eq(18.3859, result.avg);
+    }
+
+    #[test]
+    fn msssim_box_window_yuv420p8() {
+        let mut dec1 = get_decoder(format!(
+            "{}/../testfiles/yuv420p8_input.y4m",
+            env!("CARGO_MANIFEST_DIR")
+        ))
+        .unwrap();
+        let mut dec2 = get_decoder(format!(
+            "{}/../testfiles/yuv420p8_output.y4m",
+            env!("CARGO_MANIFEST_DIR")
+        ))
+        .unwrap();
+        let config = MsSsimConfig {
+            window: MsSsimWindow::Box,
+            luma_only: false,
+            ..Default::default()
+        };
+        let result =
+            calculate_video_msssim_with_config(&mut dec1, &mut dec2, None, |_| (), config).unwrap();
+        // The box window is an approximation, so it is checked against
+        // the Gaussian scores with a looser tolerance.
+        assert!((18.8343 - result.y).abs() < 0.3, "y: {}", result.y);
+        assert!((16.6943 - result.u).abs() < 0.3, "u: {}", result.u);
+        assert!((18.7662 - result.v).abs() < 0.3, "v: {}", result.v);
+        assert!((18.3859 - result.avg).abs() < 0.3, "avg: {}", result.avg);
+    }
+
+    #[test]
+    fn msssim_box_window_frame_yuv420p10() {
+        let mut dec1 = get_decoder(format!(
+            "{}/../testfiles/yuv420p10_input.y4m",
+            env!("CARGO_MANIFEST_DIR")
+        ))
+        .unwrap();
+        let mut dec2 = get_decoder(format!(
+            "{}/../testfiles/yuv420p10_output.y4m",
+            env!("CARGO_MANIFEST_DIR")
+        ))
+        .unwrap();
+        let frame1 = dec1.read_video_frame::<u16>().unwrap();
+        let frame2 = dec2.read_video_frame::<u16>().unwrap();
+        let gaussian = calculate_frame_msssim(&frame1, &frame2, 10, ChromaSampling::Cs420).unwrap();
+        let config = MsSsimConfig {
+            window: MsSsimWindow::Box,
+            luma_only: false,
+            ..Default::default()
+        };
+        let boxed =
+            calculate_frame_msssim_with_config(&frame1, &frame2, 10, ChromaSampling::Cs420, config)
+                .unwrap();
+        assert!((gaussian.y - boxed.y).abs() < 0.3);
+        assert!((gaussian.u - boxed.u).abs() < 0.3);
+        assert!((gaussian.v - boxed.v).abs() < 0.3);
+        assert!((gaussian.avg - boxed.avg).abs() < 0.3);
+    }
+
+    #[test]
+    fn msssim_luma_only_yuv420p8() {
+        let mut dec1 = get_decoder(format!(
+            "{}/../testfiles/yuv420p8_input.y4m",
+            env!("CARGO_MANIFEST_DIR")
+        ))
+        .unwrap();
+        let mut dec2 = get_decoder(format!(
+            "{}/../testfiles/yuv420p8_output.y4m",
+            env!("CARGO_MANIFEST_DIR")
+        ))
+        .unwrap();
+        let config = MsSsimConfig {
+            window: MsSsimWindow::Gaussian,
+            luma_only: true,
+            ..Default::default()
+        };
+        let result =
+            calculate_video_msssim_with_config(&mut dec1, &mut dec2, None, |_| (), config).unwrap();
+        assert_metric_eq(18.8343, result.y);
+        assert_metric_eq(0.0, result.u);
+        assert_metric_eq(0.0, result.v);
+        assert_metric_eq(18.8343, result.avg);
+    }
+
+    #[test]
+    fn psnr_selected_planes_yuv420p8() {
+        let path = |name: &str| {
+            format!(
+                "{}/../testfiles/yuv420p8_{}.y4m",
+                env!("CARGO_MANIFEST_DIR"),
+                name
+            )
+        };
+        let mut dec1 = get_decoder(path("input")).unwrap();
+        let mut dec2 = get_decoder(path("output")).unwrap();
+        let full = calculate_video_psnr(&mut dec1, &mut dec2, None, |_| ()).unwrap();
+
+        let config = PsnrConfig {
+            planes: PlaneSelect::V,
+            ..Default::default()
+        };
+        let mut dec1 = get_decoder(path("input")).unwrap();
+        let mut dec2 = get_decoder(path("output")).unwrap();
+        let result =
+            calculate_video_psnr_with_config(&mut dec1, &mut dec2, None, |_| (), config).unwrap();
+        assert_metric_eq(0.0, result.y);
+        assert_metric_eq(0.0, result.u);
+        assert_metric_eq(full.v, result.v);
+        assert_metric_eq(full.v, result.avg);
+
+        let config = PsnrConfig {
+            planes: PlaneSelect::NONE,
+            ..Default::default()
+        };
+        let mut dec1 = get_decoder(path("input")).unwrap();
+        let mut dec2 = get_decoder(path("output")).unwrap();
+        assert!(
+            calculate_video_psnr_with_config(&mut dec1, &mut dec2, None, |_| (), config).is_err()
+        );
+    }
+
+    #[test]
+    fn ssim_selected_planes_yuv420p8() {
+        let path = |name: &str| {
+            format!(
+                "{}/../testfiles/yuv420p8_{}.y4m",
+                env!("CARGO_MANIFEST_DIR"),
+                name
+            )
+        };
+        let mut dec1 = get_decoder(path("input")).unwrap();
+        let mut dec2 = get_decoder(path("output")).unwrap();
+        let full = calculate_video_ssim(&mut dec1, &mut dec2, None, |_| ()).unwrap();
+
+        let config = SsimConfig {
+            planes: PlaneSelect::CHROMA,
+            ..Default::default()
+        };
+        let mut dec1 = get_decoder(path("input")).unwrap();
+        let mut dec2 = get_decoder(path("output")).unwrap();
+        let result =
+            calculate_video_ssim_with_config(&mut dec1, &mut dec2, None, |_| (), config).unwrap();
+        assert_metric_eq(0.0, result.y);
+        assert_metric_eq(full.u, result.u);
+        assert_metric_eq(full.v, result.v);
+        assert!(result.avg > full.u.min(full.v) && result.avg < full.u.max(full.v));
+
+        let config = MsSsimConfig {
+            planes: PlaneSelect::Y,
+            ..Default::default()
+        };
+        let mut dec1 = get_decoder(path("input")).unwrap();
+        let mut dec2 = get_decoder(path("output")).unwrap();
+        let result =
+            calculate_video_msssim_with_config(&mut dec1, &mut dec2, None, |_| (), config).unwrap();
+        assert_metric_eq(18.8343, result.y);
+        assert_metric_eq(0.0, result.u);
+        assert_metric_eq(0.0, result.v);
+        assert_metric_eq(18.8343, result.avg);
+    }
+
+    #[test]
+    fn mse_yuv420p8() {
+        let path = |name: &str| {
+            format!(
+                "{}/../testfiles/yuv420p8_{}.y4m",
+                env!("CARGO_MANIFEST_DIR"),
+                name
+            )
+        };
+        let to_db = |mse: f64| 10.0 * (255.0 * 255.0 / mse).log10();
+        let mut dec1 = get_decoder(path("input")).unwrap();
+        let mut dec2 = get_decoder(path("output")).unwrap();
+        let psnr = calculate_video_psnr(&mut dec1, &mut dec2, None, |_| ()).unwrap();
+        let mut dec1 = get_decoder(path("input")).unwrap();
+        let mut dec2 = get_decoder(path("output")).unwrap();
+        let result = calculate_video_mse(&mut dec1, &mut dec2, None, |_| ()).unwrap();
+        assert_metric_eq(psnr.y, to_db(result.mse.y));
+        assert_metric_eq(psnr.u, to_db(result.mse.u));
+        assert_metric_eq(psnr.v, to_db(result.mse.v));
+        assert_metric_eq(psnr.avg, to_db(result.mse.avg));
+        assert_metric_eq(result.mse.y.sqrt(), result.rmse.y);
+        assert_metric_eq(result.mse.avg.sqrt(), result.rmse.avg);
+        assert_eq!(result.frames.len(), 3);
+        let mean = result.frames.iter().map(|frame| frame.y).sum::<f64>() / 3.0;
+        assert_metric_eq(result.mse.y, mean);
+
+        let frame1 = get_decoder(path("input")).unwrap().read_video_frame::<u8>();
+        let frame2 = get_decoder(path("output"))
+            .unwrap()
+            .read_video_frame::<u8>();
+        let (frame1, frame2) = (frame1.unwrap(), frame2.unwrap());
+        let frame = calculate_frame_mse(&frame1, &frame2, 8, ChromaSampling::Cs420).unwrap();
+        assert_eq!(frame, result.frames[0]);
+        let config = PsnrConfig {
+            planes: PlaneSelect::Y,
+            ..Default::default()
+        };
+        let luma =
+            calculate_frame_mse_with_config(&frame1, &frame2, 8, ChromaSampling::Cs420, config)
+                .unwrap();
+        assert_eq!(
+            (frame.y, 0.0, 0.0, frame.y),
+            (luma.y, luma.u, luma.v, luma.avg)
+        );
+    }
+
+    #[test]
+    fn mse_yuv420p16_8k_max_error() {
+        let (width, height) = (7680, 4320);
+        let mut frame1: Frame<u16> =
+            Frame::new_with_padding(width, height, ChromaSampling::Cs420, 0);
+        let mut frame2 = frame1.clone();
+        for (plane1, plane2) in frame1.planes.iter_mut().zip(frame2.planes.iter_mut()) {
+            plane1.data.iter_mut().for_each(|pix| *pix = 0);
+            plane2.data.iter_mut().for_each(|pix| *pix = u16::MAX);
+        }
+        let max_err = f64::from(u16::MAX).powi(2);
+        let mse = calculate_frame_mse(&frame1, &frame2, 16, ChromaSampling::Cs420).unwrap();
+        assert_eq!((max_err, max_err, max_err), (mse.y, mse.u, mse.v));
+        let psnr = calculate_frame_psnr(&frame1, &frame2, 16, ChromaSampling::Cs420).unwrap();
+        assert_metric_eq(0.0, psnr.y);
+        assert_metric_eq(0.0, psnr.u);
+        assert_metric_eq(0.0, psnr.v);
+        assert_metric_eq(0.0, psnr.avg);
+    }
+
+    #[test]
+    fn plane_weight_presets_yuv420p8() {
+        let path = |name: &str| {
+            format!(
+                "{}/../testfiles/yuv420p8_{}.y4m",
+                env!("CARGO_MANIFEST_DIR"),
+                name
+            )
+        };
+        // The average of scores in decibels of the errors of each plane
+        let mean_db = |scores: [f64; 3], weights: [f64; 3]| {
+            let sum = scores
+                .iter()
+                .zip(weights.iter())
+                .map(|(score, weight)| weight * 10f64.powf(-score / 10.0))
+                .sum::<f64>();
+            -10.0 * (sum / weights.iter().sum::<f64>()).log10()
+        };
+        let psnr = |weights: PlaneWeights| {
+            let mut dec1 = get_decoder(path("input")).unwrap();
+            let mut dec2 = get_decoder(path("output")).unwrap();
+            calculate_video_psnr_with(&mut dec1, &mut dec2, PsnrOptions::new().weights(weights))
+                .unwrap()
+        };
+        let default = psnr(PlaneWeights::Default);
+        let mut dec1 = get_decoder(path("input")).unwrap();
+        let mut dec2 = get_decoder(path("output")).unwrap();
+        let expected = calculate_video_psnr(&mut dec1, &mut dec2, None, |_| ()).unwrap();
+        assert_eq!(expected, default);
+        let scores = [default.y, default.u, default.v];
+        assert_metric_eq(mean_db(scores, [4.0, 1.0, 1.0]), default.avg);
+        assert_metric_eq(default.y, psnr(PlaneWeights::Luma).avg);
+        assert_metric_eq(mean_db(scores, [1.0; 3]), psnr(PlaneWeights::Equal).avg);
+        let ctc = psnr(PlaneWeights::Ctc);
+        assert_eq!((default.y, default.u, default.v), (ctc.y, ctc.u, ctc.v));
+        assert_metric_eq(mean_db(scores, [6.0, 1.0, 1.0]), ctc.avg);
+
+        let mut dec1 = get_decoder(path("input")).unwrap();
+        let mut dec2 = get_decoder(path("output")).unwrap();
+        let ssim = calculate_video_ssim_with(
+            &mut dec1,
+            &mut dec2,
+            SsimOptions::new().weights(PlaneWeights::Equal),
+        )
+        .unwrap();
+        assert_metric_eq(mean_db([ssim.y, ssim.u, ssim.v], [1.0; 3]), ssim.avg);
+
+        let frame1 = get_decoder(path("input")).unwrap().read_video_frame::<u8>();
+        let frame2 = get_decoder(path("output"))
+            .unwrap()
+            .read_video_frame::<u8>();
+        let config = MsSsimConfig {
+            weights: PlaneWeights::Luma,
+            ..Default::default()
+        };
+        let msssim = calculate_frame_msssim_with_config(
+            &frame1.unwrap(),
+            &frame2.unwrap(),
+            8,
+            ChromaSampling::Cs420,
+            config,
+        )
+        .unwrap();
+        assert_metric_eq(msssim.y, msssim.avg);
+    }
+
+    #[test]
+    fn ssim_yuv422p8() {
+        let mut dec1 = get_decoder(format!(
+            "{}/../testfiles/yuv422p8_input.y4m",
+            env!("CARGO_MANIFEST_DIR")
+        ))
+        .unwrap();
+        let mut dec2 = get_decoder(format!(
+            "{}/../testfiles/yuv422p8_output.y4m",
+            env!("CARGO_MANIFEST_DIR")
+        ))
+        .unwrap();
+        let result = calculate_video_ssim(&mut dec1, &mut dec2, None, |_| ()).unwrap();
+        assert_metric_eq(21.1130, result.y);
+        assert_metric_eq(21.9978, result.u);
+        assert_metric_eq(22.7898, result.v);
+        assert_metric_eq(21.6987, result.avg);
+    }
+
+    #[test]
+    fn msssim_yuv422p8() {
+        let mut dec1 = get_decoder(format!(
+            "{}/../testfiles/yuv422p8_input.y4m",
             env!("CARGO_MANIFEST_DIR")
         ))
         .unwrap();
@@ -385,6 +1927,25 @@ mod tests {
         assert_metric_eq(12.7729, result.avg);
     }
 
+    #[test]
+    fn ssim_yuv420p12() {
+        let mut dec1 = get_decoder(format!(
+            "{}/../testfiles/yuv420p12_input.y4m",
+            env!("CARGO_MANIFEST_DIR")
+        ))
+        .unwrap();
+        let mut dec2 = get_decoder(format!(
+            "{}/../testfiles/yuv420p12_output.y4m",
+            env!("CARGO_MANIFEST_DIR")
+        ))
+        .unwrap();
+        let result = calculate_video_ssim(&mut dec1, &mut dec2, None, |_| ()).unwrap();
+        assert_metric_eq(13.3624, result.y);
+        assert_metric_eq(10.9352, result.u);
+        assert_metric_eq(12.8724, result.v);
+        assert_metric_eq(12.7755, result.avg);
+    }
+
     #[test]
     fn msssim_yuv420p10() {
         let mut dec1 = get_decoder(format!(
@@ -404,6 +1965,25 @@ mod tests {
         assert_metric_eq(18.5631, result.avg);
     }
 
+    #[test]
+    fn msssim_yuv420p12() {
+        let mut dec1 = get_decoder(format!(
+            "{}/../testfiles/yuv420p12_input.y4m",
+            env!("CARGO_MANIFEST_DIR")
+        ))
+        .unwrap();
+        let mut dec2 = get_decoder(format!(
+            "{}/../testfiles/yuv420p12_output.y4m",
+            env!("CARGO_MANIFEST_DIR")
+        ))
+        .unwrap();
+        let result = calculate_video_msssim(&mut dec1, &mut dec2, None, |_| ()).unwrap();
+        assert_metric_eq(19.0415, result.y);
+        assert_metric_eq(16.8571, result.u);
+        assert_metric_eq(18.8688, result.v);
+        assert_metric_eq(18.5660, result.avg);
+    }
+
     #[test]
     fn ciede2000_yuv420p8_nosimd() {
         let mut dec1 = get_decoder(format!(
@@ -436,6 +2016,91 @@ mod tests {
         assert_metric_eq(36.2821, result);
     }
 
+    #[test]
+    fn ciede2000_yuv420p8_deterministic() {
+        let ciede = |threads: usize, sampling: Sampling| {
+            let path = |name: &str| {
+                format!(
+                    "{}/../testfiles/yuv420p8_{}.y4m",
+                    env!("CARGO_MANIFEST_DIR"),
+                    name
+                )
+            };
+            let mut dec1 = get_decoder(path("input")).unwrap();
+            let mut dec2 = get_decoder(path("output")).unwrap();
+            let options = CiedeOptions::new()
+                .threads(threads)
+                .sampling(sampling)
+                .deterministic(true);
+            calculate_video_ciede_with(&mut dec1, &mut dec2, options).unwrap()
+        };
+        for sampling in [Sampling::Full, Sampling::Quincunx] {
+            let single = ciede(1, sampling);
+            assert_eq!(single.to_bits(), ciede(3, sampling).to_bits());
+            assert_eq!(single.to_bits(), ciede(8, sampling).to_bits());
+        }
+        assert_metric_eq(36.2821, ciede(4, Sampling::Full));
+    }
+
+    #[test]
+    fn ciede2000_yuv420p8_range_and_matrix() {
+        let overrides = DetailsOverride {
+            color_range: Some(ColorRange::Full),
+            matrix_coefficients: Some(MatrixCoefficients::Bt601),
+            ..Default::default()
+        };
+        let open = || {
+            let mut dec1 = get_decoder(format!(
+                "{}/../testfiles/yuv420p8_input.y4m",
+                env!("CARGO_MANIFEST_DIR")
+            ))
+            .unwrap();
+            let mut dec2 = get_decoder(format!(
+                "{}/../testfiles/yuv420p8_output.y4m",
+                env!("CARGO_MANIFEST_DIR")
+            ))
+            .unwrap();
+            dec1.set_overrides(overrides);
+            dec2.set_overrides(overrides);
+            (dec1, dec2)
+        };
+        let (mut dec1, mut dec2) = open();
+        let details = dec1.get_video_details();
+        assert_eq!(details.color_range, ColorRange::Full);
+        assert_eq!(details.matrix_coefficients, MatrixCoefficients::Bt601);
+        let result = calculate_video_ciede(&mut dec1, &mut dec2, None, |_| ()).unwrap();
+        assert_metric_eq(37.0726, result);
+        let (mut dec1, mut dec2) = open();
+        let nosimd = calculate_video_ciede_nosimd(&mut dec1, &mut dec2, None, |_| ()).unwrap();
+        assert_metric_eq(result, nosimd);
+    }
+
+    #[test]
+    fn psnr_yuv422p8_chroma_loc_override() {
+        let mut dec1 = get_decoder(format!(
+            "{}/../testfiles/yuv422p8_input.y4m",
+            env!("CARGO_MANIFEST_DIR")
+        ))
+        .unwrap();
+        let mut dec2 = get_decoder(format!(
+            "{}/../testfiles/yuv422p8_output.y4m",
+            env!("CARGO_MANIFEST_DIR")
+        ))
+        .unwrap();
+        let overrides = DetailsOverride {
+            chroma_sample_position: Some(ChromaSamplePosition::Colocated),
+            ..Default::default()
+        };
+        dec1.set_overrides(overrides);
+        dec2.set_overrides(overrides);
+        let result = calculate_video_psnr(&mut dec1, &mut dec2, None, |_| ()).unwrap();
+        // The chroma is no longer repositioned, so it differs from `psnr_yuv422p8`.
+        assert_metric_eq(38.6740, result.y);
+        assert_metric_eq(47.5465, result.u);
+        assert_metric_eq(48.9752, result.v);
+        assert_metric_eq(41.2253, result.avg);
+    }
+
     #[test]
     fn ciede2000_yuv422p8_nosimd() {
         let mut dec1 = get_decoder(format!(
@@ -516,6 +2181,22 @@ mod tests {
         assert_metric_eq(36.3691, result);
     }
 
+    #[test]
+    fn ciede2000_yuv420p12_nosimd() {
+        let mut dec1 = get_decoder(format!(
+            "{}/../testfiles/yuv420p12_input.y4m",
+            env!("CARGO_MANIFEST_DIR")
+        ))
+        .unwrap();
+        let mut dec2 = get_decoder(format!(
+            "{}/../testfiles/yuv420p12_output.y4m",
+            env!("CARGO_MANIFEST_DIR")
+        ))
+        .unwrap();
+        let result = calculate_video_ciede_nosimd(&mut dec1, &mut dec2, None, |_| ()).unwrap();
+        assert_metric_eq(36.3690, result);
+    }
+
     #[test]
     fn ciede2000_yuv420p10() {
         let mut dec1 = get_decoder(format!(
@@ -532,12 +2213,2368 @@ mod tests {
         assert_metric_eq(36.3691, result);
     }
 
-    fn assert_metric_eq(expected: f64, value: f64) {
-        assert!(
-            (expected - value).abs() < 0.01,
-            "Expected {}, got {}",
-            expected,
-            value
-        );
+    #[test]
+    fn ciede2000_yuv420p12() {
+        let mut dec1 = get_decoder(format!(
+            "{}/../testfiles/yuv420p12_input.y4m",
+            env!("CARGO_MANIFEST_DIR")
+        ))
+        .unwrap();
+        let mut dec2 = get_decoder(format!(
+            "{}/../testfiles/yuv420p12_output.y4m",
+            env!("CARGO_MANIFEST_DIR")
+        ))
+        .unwrap();
+        let result = calculate_video_ciede(&mut dec1, &mut dec2, None, |_| ()).unwrap();
+        assert_metric_eq(36.3690, result);
+    }
+
+    #[test]
+    fn psnr_yuv420p16() {
+        let (frame1, frame2) = get_yuv420p16_frames();
+        let result = calculate_frame_psnr(&frame1, &frame2, 16, ChromaSampling::Cs420).unwrap();
+        assert_metric_eq(33.0969, result.y);
+        assert_metric_eq(36.5417, result.u);
+        assert_metric_eq(39.9593, result.v);
+        assert_metric_eq(34.1961, result.avg);
+    }
+
+    #[test]
+    fn psnr_hvs_yuv420p16() {
+        let (frame1, frame2) = get_yuv420p16_frames();
+        let result = calculate_frame_psnr_hvs(&frame1, &frame2, 16, ChromaSampling::Cs420).unwrap();
+        assert_metric_eq(35.7473, result.y);
+        assert_metric_eq(38.3947, result.u);
+        assert_metric_eq(41.2377, result.v);
+        assert_metric_eq(33.1711, result.avg);
+    }
+
+    #[test]
+    fn ssim_yuv420p16() {
+        let (frame1, frame2) = get_yuv420p16_frames();
+        let result = calculate_frame_ssim(&frame1, &frame2, 16, ChromaSampling::Cs420).unwrap();
+        assert_metric_eq(13.6051, result.y);
+        assert_metric_eq(10.9631, result.u);
+        assert_metric_eq(12.9848, result.v);
+        assert_metric_eq(12.9413, result.avg);
+    }
+
+    #[test]
+    fn msssim_yuv420p16() {
+        let (frame1, frame2) = get_yuv420p16_frames();
+        let result = calculate_frame_msssim(&frame1, &frame2, 16, ChromaSampling::Cs420).unwrap();
+        assert_metric_eq(19.4935, result.y);
+        assert_metric_eq(16.8469, result.u);
+        assert_metric_eq(18.9228, result.v);
+        assert_metric_eq(18.8366, result.avg);
+    }
+
+    #[test]
+    fn ciede2000_yuv420p16_nosimd() {
+        let (frame1, frame2) = get_yuv420p16_frames();
+        let result =
+            calculate_frame_ciede_nosimd(&frame1, &frame2, 16, ChromaSampling::Cs420).unwrap();
+        assert_metric_eq(36.5962, result);
+    }
+
+    #[test]
+    fn ciede2000_yuv420p16() {
+        let (frame1, frame2) = get_yuv420p16_frames();
+        let result = calculate_frame_ciede(&frame1, &frame2, 16, ChromaSampling::Cs420).unwrap();
+        assert_metric_eq(36.5962, result);
+    }
+
+    #[test]
+    fn synth_noise_psnr_yuv420p8() {
+        let frame = synth::zone_plate::<u8>(640, 360, 8, ChromaSampling::Cs420).unwrap();
+        let noisy = synth::add_noise(&frame, 8, 30.0, 1).unwrap();
+        let result = calculate_frame_psnr(&frame, &noisy, 8, ChromaSampling::Cs420).unwrap();
+        assert_metric_eq(30.0, result.y);
+        assert_metric_eq(30.0, result.u);
+        assert_metric_eq(30.0, result.v);
+        assert_metric_eq(30.0, result.avg);
+    }
+
+    #[test]
+    fn synth_noise_psnr_yuv444p10() {
+        let frame = synth::color_bars::<u16>(640, 360, 10, ChromaSampling::Cs444).unwrap();
+        let noisy = synth::add_noise(&frame, 10, 42.0, 7).unwrap();
+        let result = calculate_frame_psnr(&frame, &noisy, 10, ChromaSampling::Cs444).unwrap();
+        assert_metric_eq(42.0, result.y);
+        assert_metric_eq(42.0, result.u);
+        assert_metric_eq(42.0, result.v);
+        assert_metric_eq(42.0, result.avg);
+    }
+
+    #[test]
+    fn synth_gradient_ciede_identical() {
+        let frame = synth::gradient::<u8>(640, 360, 8, ChromaSampling::Cs420).unwrap();
+        let result = calculate_frame_ciede(&frame, &frame, 8, ChromaSampling::Cs420).unwrap();
+        assert_metric_eq(100.0, result);
+    }
+
+    #[test]
+    fn psnr_from_frames_yuv420p8() {
+        let mut dec1 = get_decoder(format!(
+            "{}/../testfiles/yuv420p8_input.y4m",
+            env!("CARGO_MANIFEST_DIR")
+        ))
+        .unwrap();
+        let mut dec2 = get_decoder(format!(
+            "{}/../testfiles/yuv420p8_output.y4m",
+            env!("CARGO_MANIFEST_DIR")
+        ))
+        .unwrap();
+        let mut frames = Vec::new();
+        while let (Some(frame1), Some(frame2)) =
+            (dec1.read_video_frame::<u8>(), dec2.read_video_frame::<u8>())
+        {
+            frames.push((frame1, frame2));
+        }
+        let result =
+            calculate_video_psnr_from_frames(frames, 8, ChromaSampling::Cs420, |_| ()).unwrap();
+        assert_metric_eq(32.5281, result.y);
+        assert_metric_eq(36.4083, result.u);
+        assert_metric_eq(39.8238, result.v);
+        assert_metric_eq(33.6861, result.avg);
+    }
+
+    #[test]
+    fn psnr_from_frames_synth() {
+        let frames = (0..4).map(|seed| {
+            let frame = synth::zone_plate::<u16>(320, 180, 10, ChromaSampling::Cs422).unwrap();
+            let noisy = synth::add_noise(&frame, 10, 36.0, seed).unwrap();
+            (frame, noisy)
+        });
+        let result =
+            calculate_video_psnr_from_frames(frames, 10, ChromaSampling::Cs422, |_| ()).unwrap();
+        assert_metric_eq(36.0, result.y);
+        assert_metric_eq(36.0, result.avg);
+
+        let frame = synth::gradient::<u8>(64, 64, 8, ChromaSampling::Cs420).unwrap();
+        let frames = vec![(frame.clone(), frame)];
+        assert!(
+            calculate_video_ssim_from_frames(frames, 8, ChromaSampling::Cs444, |_| ()).is_err()
+        );
+    }
+
+    #[test]
+    fn frame_chroma_sampling_mismatch() {
+        let frame = synth::gradient::<u8>(64, 64, 8, ChromaSampling::Cs420).unwrap();
+        let err = calculate_frame_psnr(&frame, &frame, 8, ChromaSampling::Cs444).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<MetricsError>(),
+            Some(MetricsError::PlaneMismatch { plane: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn frame_context_mismatch() {
+        let frame1 = synth::gradient::<u16>(64, 64, 10, ChromaSampling::Cs422).unwrap();
+        let frame2 = synth::gradient::<u16>(64, 32, 10, ChromaSampling::Cs422).unwrap();
+        let ctx = FrameContext::new(10, ChromaSampling::Cs422).unwrap();
+        assert!(ctx.validate(&frame1, &frame1).is_ok());
+        assert!(matches!(
+            ctx.validate(&frame1, &frame2),
+            Err(MetricsError::PlaneMismatch { plane: 0, .. })
+        ));
+        let ctx = FrameContext::new(8, ChromaSampling::Cs422).unwrap();
+        assert!(matches!(
+            ctx.validate(&frame1, &frame1),
+            Err(MetricsError::InputMismatch { .. })
+        ));
+        assert!(FrameContext::new(17, ChromaSampling::Cs422).is_err());
+    }
+
+    #[test]
+    fn frame_from_planes_yuv420p10() {
+        let frame = synth::zone_plate::<u16>(32, 24, 10, ChromaSampling::Cs420).unwrap();
+        // Copy the visible samples into rows padded to 40 and 16 samples.
+        let padded = |plane: &Plane<u16>, stride: usize| {
+            let mut data = vec![0; stride * plane.cfg.height];
+            for (row, src) in data.chunks_mut(stride).zip(plane.rows_iter()) {
+                row[..plane.cfg.width].copy_from_slice(&src[..plane.cfg.width]);
+            }
+            data
+        };
+        let y = padded(&frame.planes[0], 40);
+        let u = padded(&frame.planes[1], 16);
+        let v = padded(&frame.planes[2], 16);
+        let strides = [40, 16, 16];
+
+        let rebuilt =
+            frame_from_planes(32, 24, ChromaSampling::Cs420, [&y, &u, &v], strides).unwrap();
+        let result = calculate_frame_psnr(&frame, &rebuilt, 10, ChromaSampling::Cs420).unwrap();
+        assert_metric_eq(100.0, result.avg);
+
+        let err = frame_from_planes(32, 24, ChromaSampling::Cs420, [&y, &u, &v[..100]], strides)
+            .unwrap_err();
+        assert!(matches!(err, MetricsError::PlaneMismatch { plane: 2, .. }));
+        let err = frame_from_planes(32, 24, ChromaSampling::Cs420, [&y, &u, &v], [20, 16, 16])
+            .unwrap_err();
+        assert!(matches!(err, MetricsError::PlaneMismatch { plane: 0, .. }));
+    }
+
+    #[test]
+    fn copy_plane_checks_geometry() {
+        let mut frame: Frame<u16> = Frame::new_with_padding(8, 8, ChromaSampling::Cs420, 0);
+        let plane = &mut frame.planes[1];
+        let (width, height) = (plane.cfg.width, plane.cfg.height);
+
+        // Little-endian 10-bit samples in rows padded to 12 bytes.
+        let stride = width * 2 + 4;
+        let mut raw = vec![0u8; stride * height];
+        for row in raw.chunks_mut(stride) {
+            for sample in row[..width * 2].chunks_mut(2) {
+                sample.copy_from_slice(&513u16.to_le_bytes());
+            }
+        }
+        copy_plane_from_raw_u8(plane, &raw, stride, 2).unwrap();
+        assert!(plane
+            .rows_iter()
+            .all(|row| row[..width].iter().all(|&p| p == 513)));
+
+        let short = &raw[..stride * (height - 1)];
+        assert!(matches!(
+            copy_plane_from_raw_u8(plane, short, stride, 2),
+            Err(MetricsError::MalformedInput { .. })
+        ));
+        assert!(matches!(
+            copy_plane_from_raw_u8(plane, &raw, width, 2),
+            Err(MetricsError::MalformedInput { .. })
+        ));
+        let mut frame8: Frame<u8> = Frame::new_with_padding(8, 8, ChromaSampling::Cs420, 0);
+        assert!(matches!(
+            copy_plane_from_raw_u8(&mut frame8.planes[0], &raw, stride, 2),
+            Err(MetricsError::MalformedInput { .. })
+        ));
+
+        let samples = vec![64u16; width * height];
+        copy_plane_from_slice(plane, &samples, width).unwrap();
+        assert!(plane
+            .rows_iter()
+            .all(|row| row[..width].iter().all(|&p| p == 64)));
+        assert!(matches!(
+            copy_plane_from_slice(plane, &samples[1..], width),
+            Err(MetricsError::MalformedInput { .. })
+        ));
+    }
+
+    #[test]
+    fn ssim_standard_window_yuv420p8() {
+        let path = |name: &str| {
+            format!(
+                "{}/../testfiles/yuv420p8_{}.y4m",
+                env!("CARGO_MANIFEST_DIR"),
+                name
+            )
+        };
+        let config = SsimConfig {
+            window: SsimWindow::Standard,
+            ..Default::default()
+        };
+        let mut dec1 = get_decoder(path("input")).unwrap();
+        let mut dec2 = get_decoder(path("output")).unwrap();
+        let result =
+            calculate_video_ssim_with_config(&mut dec1, &mut dec2, None, |_| (), config).unwrap();
+        assert_metric_eq(12.7297, result.y);
+        assert_metric_eq(10.9382, result.u);
+        assert_metric_eq(12.7618, result.v);
+        assert_metric_eq(12.3799, result.avg);
+    }
+
+    #[test]
+    fn ffmpeg_compat_yuv420p8() {
+        let path = |name: &str| {
+            format!(
+                "{}/../testfiles/yuv420p8_{}.y4m",
+                env!("CARGO_MANIFEST_DIR"),
+                name
+            )
+        };
+        let config = SsimConfig {
+            compat: CompatMode::Ffmpeg,
+            ..Default::default()
+        };
+        let mut dec1 = get_decoder(path("input")).unwrap();
+        let mut dec2 = get_decoder(path("output")).unwrap();
+        let result =
+            calculate_video_ssim_with_config(&mut dec1, &mut dec2, None, |_| (), config).unwrap();
+        assert_metric_eq(13.1743, result.y);
+        assert_metric_eq(11.1793, result.u);
+        assert_metric_eq(12.8223, result.v);
+        assert_metric_eq(12.7162, result.avg);
+
+        // PSNR is aggregated the same way, but not capped.
+        let config = PsnrConfig {
+            compat: CompatMode::Ffmpeg,
+            ..Default::default()
+        };
+        let mut dec1 = get_decoder(path("input")).unwrap();
+        let mut dec2 = get_decoder(path("output")).unwrap();
+        let native = calculate_video_psnr(&mut dec1, &mut dec2, None, |_| ()).unwrap();
+        let mut dec1 = get_decoder(path("input")).unwrap();
+        let mut dec2 = get_decoder(path("output")).unwrap();
+        let result =
+            calculate_video_psnr_with_config(&mut dec1, &mut dec2, None, |_| (), config).unwrap();
+        assert_eq!(native, result);
+        let mut dec1 = get_decoder(path("input")).unwrap();
+        let frame = dec1.read_video_frame::<u8>().unwrap();
+        let result =
+            calculate_frame_psnr_with_config(&frame, &frame, 8, ChromaSampling::Cs420, config)
+                .unwrap();
+        assert_eq!(f64::INFINITY, result.avg);
+    }
+
+    #[test]
+    fn quincunx_sampling() {
+        for format in ["yuv420p8", "yuv422p8", "yuv444p8"] {
+            let path = |kind: &str| {
+                format!(
+                    "{}/../testfiles/{}_{}.y4m",
+                    env!("CARGO_MANIFEST_DIR"),
+                    format,
+                    kind
+                )
+            };
+            let mut dec1 = get_decoder(path("input")).unwrap();
+            let mut dec2 = get_decoder(path("output")).unwrap();
+            let full = calculate_video_psnr(&mut dec1, &mut dec2, None, |_| ()).unwrap();
+            let mut dec1 = get_decoder(path("input")).unwrap();
+            let mut dec2 = get_decoder(path("output")).unwrap();
+            let options = PsnrOptions::new().sampling(Sampling::Quincunx);
+            let sampled = calculate_video_psnr_with(&mut dec1, &mut dec2, options).unwrap();
+            assert!((full.avg - sampled.avg).abs() < 0.05);
+            assert!((full.y - sampled.y).abs() < 0.05);
+
+            let mut dec1 = get_decoder(path("input")).unwrap();
+            let mut dec2 = get_decoder(path("output")).unwrap();
+            let full = calculate_video_ciede(&mut dec1, &mut dec2, None, |_| ()).unwrap();
+            let mut dec1 = get_decoder(path("input")).unwrap();
+            let mut dec2 = get_decoder(path("output")).unwrap();
+            let options = CiedeOptions::new().sampling(Sampling::Quincunx);
+            let sampled = calculate_video_ciede_with(&mut dec1, &mut dec2, options).unwrap();
+            assert!((full - sampled).abs() < 0.1);
+        }
+
+        // An error on every other sample is entirely missed, or doubled.
+        let mut flat = synth::gradient::<u8>(64, 64, 8, ChromaSampling::Cs444).unwrap();
+        for plane in flat.planes.iter_mut() {
+            for row in plane.rows_iter_mut() {
+                row.fill(128);
+            }
+        }
+        let mut checkerboard = flat.clone();
+        for plane in checkerboard.planes.iter_mut() {
+            for (y, row) in plane.rows_iter_mut().enumerate() {
+                for (x, pixel) in row.iter_mut().enumerate() {
+                    if (x + y) % 2 == 1 {
+                        *pixel = 138;
+                    }
+                }
+            }
+        }
+        let config = PsnrConfig {
+            sampling: Sampling::Quincunx,
+            ..Default::default()
+        };
+        let sampled = calculate_frame_psnr_with_config(
+            &flat,
+            &checkerboard,
+            8,
+            ChromaSampling::Cs444,
+            config,
+        )
+        .unwrap();
+        assert_metric_eq(100.0, sampled.avg);
+    }
+
+    #[test]
+    fn ssim_map_yuv420p8() {
+        let mut dec1 = get_decoder(format!(
+            "{}/../testfiles/yuv420p8_input.y4m",
+            env!("CARGO_MANIFEST_DIR")
+        ))
+        .unwrap();
+        let mut dec2 = get_decoder(format!(
+            "{}/../testfiles/yuv420p8_output.y4m",
+            env!("CARGO_MANIFEST_DIR")
+        ))
+        .unwrap();
+        let frame1 = dec1.read_video_frame::<u8>().unwrap();
+        let frame2 = dec2.read_video_frame::<u8>().unwrap();
+        let cs = ChromaSampling::Cs420;
+
+        let maps = calculate_frame_ssim_map(&frame1, &frame2, 8, cs).unwrap();
+        for (map, plane) in maps.iter().zip(frame1.planes.iter()) {
+            assert_eq!(map.width, plane.cfg.width);
+            assert_eq!(map.height, plane.cfg.height);
+            assert_eq!(map.rows().count(), plane.cfg.height);
+            assert!(map.data.iter().all(|&v| (-1.0..=1.0).contains(&v)));
+        }
+        // Away from the edges, every window has the same weight, so the mean
+        // of the map is close to the pooled score.
+        let ssim = calculate_frame_ssim(&frame1, &frame2, 8, cs).unwrap();
+        let mean = maps[0].data.iter().map(|&v| v as f64).sum::<f64>() / maps[0].data.len() as f64;
+        let pooled = 1.0 - 10f64.powf(-ssim.y / 10.0);
+        assert!((pooled - mean).abs() < 0.001, "{} {}", pooled, mean);
+
+        let maps = calculate_frame_ssim_map(&frame1, &frame1, 8, cs).unwrap();
+        assert!(maps[1].data.iter().all(|&v| (v - 1.0).abs() < 1e-6));
+        assert!((maps[2].get(3, 5) - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn all_frame_metrics_yuv420p8() {
+        let mut dec1 = get_decoder(format!(
+            "{}/../testfiles/yuv420p8_input.y4m",
+            env!("CARGO_MANIFEST_DIR")
+        ))
+        .unwrap();
+        let mut dec2 = get_decoder(format!(
+            "{}/../testfiles/yuv420p8_output.y4m",
+            env!("CARGO_MANIFEST_DIR")
+        ))
+        .unwrap();
+        let frame1 = dec1.read_video_frame::<u8>().unwrap();
+        let frame2 = dec2.read_video_frame::<u8>().unwrap();
+        let cs = ChromaSampling::Cs420;
+        let ctx = FrameContext::new(8, cs).unwrap();
+        let all = calculate_all_frame_metrics(&frame1, &frame2, ctx).unwrap();
+        assert_eq!(
+            all.psnr,
+            calculate_frame_psnr(&frame1, &frame2, 8, cs).unwrap()
+        );
+        assert_eq!(
+            all.psnr_hvs,
+            calculate_frame_psnr_hvs(&frame1, &frame2, 8, cs).unwrap()
+        );
+        assert_eq!(
+            all.ssim,
+            calculate_frame_ssim(&frame1, &frame2, 8, cs).unwrap()
+        );
+        assert_eq!(
+            all.msssim,
+            calculate_frame_msssim(&frame1, &frame2, 8, cs).unwrap()
+        );
+        assert_metric_eq(
+            calculate_frame_ciede(&frame1, &frame2, 8, cs).unwrap(),
+            all.ciede,
+        );
+
+        let ctx = FrameContext::new(8, ChromaSampling::Cs444).unwrap();
+        assert!(calculate_all_frame_metrics(&frame1, &frame2, ctx).is_err());
+    }
+
+    #[test]
+    fn serde_round_trip() {
+        fn round_trip<T>(value: &T) -> T
+        where
+            T: serde::Serialize + serde::de::DeserializeOwned,
+        {
+            serde_json::from_str(&serde_json::to_string(value).unwrap()).unwrap()
+        }
+        fn assert_round_trip<T>(value: T)
+        where
+            T: serde::Serialize + serde::de::DeserializeOwned + PartialEq + std::fmt::Debug,
+        {
+            assert_eq!(value, round_trip(&value));
+        }
+
+        assert_round_trip(PlanarMetrics {
+            y: 40.5,
+            u: 44.25,
+            v: f64::MAX,
+            avg: 41.125,
+            alpha: Some(38.0),
+            dssim: Some(0.015625),
+        });
+        assert_round_trip(PsnrConfig {
+            prefilter: Some(Prefilter::Box3x3),
+            planes: PlaneSelect::Y | PlaneSelect::V,
+            compat: CompatMode::Ffmpeg,
+            sampling: Sampling::Quincunx,
+            peak_value: Some(940),
+            weights: PlaneWeights::Ctc,
+        });
+        assert_round_trip(SsimConfig {
+            window: SsimWindow::Standard,
+            planes: PlaneSelect::CHROMA,
+            compat: CompatMode::Ffmpeg,
+            scale: SsimScale::Raw,
+            weights: PlaneWeights::Equal,
+        });
+        assert_round_trip(MsSsimConfig {
+            window: MsSsimWindow::Box,
+            luma_only: true,
+            planes: PlaneSelect::U,
+            scale: SsimScale::Raw,
+            weights: PlaneWeights::Luma,
+        });
+        assert_round_trip(CiedeConfig {
+            sampling: Sampling::Quincunx,
+        });
+        assert_round_trip(VmafConfig {
+            model_path: Some("vmaf_v0.6.1.json".into()),
+        });
+        assert_round_trip(PipelineStats {
+            frames_decoded: 12,
+            frames_scored: 10,
+            queue_depth: 2,
+            queue_capacity: 4,
+            threads: 3,
+            elapsed: Duration::from_millis(1500),
+            decoder_wait: Duration::from_micros(250),
+            scorer_wait: Duration::from_nanos(7),
+            ignored_frames: [0, 5],
+        });
+        assert_round_trip(
+            calculate_frame_ssim_map(
+                &synth::zone_plate::<u8>(16, 16, 8, ChromaSampling::Cs420).unwrap(),
+                &synth::gradient::<u8>(16, 16, 8, ChromaSampling::Cs420).unwrap(),
+                8,
+                ChromaSampling::Cs420,
+            )
+            .unwrap(),
+        );
+        assert_round_trip(FrameContext::new(12, ChromaSampling::Cs444).unwrap());
+        // Deserialized contexts are checked like new ones
+        assert!(serde_json::from_str::<FrameContext>(
+            r#"{"bit_depth": 7, "chroma_sampling": "Cs420"}"#
+        )
+        .is_err());
+
+        let mut worst = WorstFrames::new(2);
+        for score in [40.0, 35.5, 42.0, 31.25] {
+            worst.push(score);
+        }
+        let mut restored = round_trip(&worst);
+        worst.push(30.0);
+        restored.push(30.0);
+        assert_eq!(worst.finish(), restored.finish());
+    }
+
+    #[test]
+    fn stream_info_yuv420p10() {
+        let dec = get_decoder(format!(
+            "{}/../testfiles/yuv420p10_input.y4m",
+            env!("CARGO_MANIFEST_DIR")
+        ))
+        .unwrap();
+        let info = dec.get_stream_info();
+        assert_eq!(info.pixel_format.as_deref(), Some("yuv420p10le"));
+        assert!(info.frame_rate.unwrap() > 0.);
+        println!("{info} {info:?}");
+
+        let base = StreamInfo {
+            frame_rate: Some(30.),
+            duration: Some(10.),
+            ..Default::default()
+        };
+        let mut results = MetricsResults {
+            stream_info: base.clone(),
+            ..Default::default()
+        };
+        assert!(results.stream_mismatches(&base).is_empty());
+        results.stream_info.frame_rate = Some(25.);
+        results.stream_info.duration = Some(9.5);
+        assert_eq!(results.stream_mismatches(&base).len(), 2);
+    }
+
+    #[test]
+    fn frame_meta_yuv420p8() {
+        let path = format!(
+            "{}/../testfiles/yuv420p8_input.y4m",
+            env!("CARGO_MANIFEST_DIR")
+        );
+        let mut dec = get_decoder(&path).unwrap();
+        assert_eq!(dec.last_frame_meta(), None);
+        let frame_rate = dec.get_stream_info().frame_rate.unwrap();
+        let mut frame = dec.read_video_frame::<u8>().unwrap();
+        assert_eq!(dec.last_frame_meta().unwrap().number, 0);
+        assert!(dec.read_video_frame_into(&mut frame));
+        let meta = dec.last_frame_meta().unwrap();
+        assert_eq!(meta.number, 1);
+        assert_eq!(meta.pts, Some(1));
+        assert!((meta.time.unwrap() - 1. / frame_rate).abs() < 1e-6);
+
+        let mut skipped = Skipped::new(get_decoder(&path).unwrap(), 2);
+        skipped.read_video_frame::<u8>().unwrap();
+        assert_eq!(skipped.last_frame_meta().unwrap().number, 2);
+    }
+
+    #[test]
+    fn y4m_extended_header() {
+        let mut data =
+            b"YUV4MPEG2 W4 H2 F25:1 It A16:15 C420jpeg XCOLORRANGE=FULL\nFRAME\n".to_vec();
+        data.extend_from_slice(&[128; 12]);
+        let dec = av_metrics_decoders::Y4MDecoder::new(std::io::Cursor::new(data)).unwrap();
+        let details = dec.get_video_details();
+        assert_eq!(details.interlacing, Interlacing::TopFieldFirst);
+        assert_eq!(details.sample_aspect_ratio.num, 16);
+        assert_eq!(details.sample_aspect_ratio.den, 15);
+        assert_eq!(details.color_range, ColorRange::Full);
+
+        let dec = get_decoder(format!(
+            "{}/../testfiles/yuv420p8_input.y4m",
+            env!("CARGO_MANIFEST_DIR")
+        ))
+        .unwrap();
+        let details = dec.get_video_details();
+        assert_eq!(details.interlacing, Interlacing::Progressive);
+        assert_eq!(
+            details.sample_aspect_ratio.num,
+            details.sample_aspect_ratio.den
+        );
+        assert_eq!(details.color_range, ColorRange::Limited);
+    }
+
+    #[test]
+    fn y4m_malformed_inputs() {
+        let decoder = |header: &[u8], samples: usize| {
+            let mut data = header.to_vec();
+            data.extend(std::iter::repeat_n(128, samples));
+            av_metrics_decoders::Y4MDecoder::new(std::io::Cursor::new(data)).unwrap()
+        };
+
+        // Vertical chroma planes narrower than the repositioning filter.
+        let mut dec = decoder(b"YUV4MPEG2 W4 H2 C420mpeg2\nFRAME\n", 12);
+        let frame = dec.read_video_frame::<u8>().unwrap();
+        assert_eq!(128, frame.planes[1].p(1, 0));
+
+        // 10-bit samples do not fit in `u8`.
+        let mut dec = decoder(b"YUV4MPEG2 W4 H2 C420p10\nFRAME\n", 24);
+        assert!(dec.read_video_frame::<u8>().is_none());
+        assert!(dec.read_video_frame_ref::<u8, _, _>(|_| ()).is_none());
+        assert!(dec.read_video_frame::<u16>().is_some());
+
+        // A truncated frame ends the video.
+        let mut dec = decoder(b"YUV4MPEG2 W4 H2 C420\nFRAME\n", 11);
+        assert!(dec.read_video_frame::<u8>().is_none());
+    }
+
+    #[test]
+    fn decoder_errors() {
+        let open = |data: &[u8]| {
+            av_metrics_decoders::Y4MDecoder::new(std::io::Cursor::new(data.to_vec())).err()
+        };
+        assert!(matches!(
+            av_metrics_decoders::y4m::new_decoder_from_file("./testfiles/missing.y4m").err(),
+            Some(DecoderError::Io { .. })
+        ));
+        assert!(matches!(
+            open(b"YUV4MPEG2 W4"),
+            Some(DecoderError::Io { .. } | DecoderError::MalformedInput { .. })
+        ));
+        assert!(matches!(
+            open(b"YUV4MPEG2 W4 H2 C411\n"),
+            Some(DecoderError::UnsupportedInput { .. })
+        ));
+        assert!(matches!(
+            open(b"RIFF").map(MetricsError::from),
+            Some(MetricsError::DecodeError { .. })
+        ));
+    }
+
+    #[test]
+    fn build_index() {
+        let path = format!(
+            "{}/../testfiles/yuv420p8_input.y4m",
+            env!("CARGO_MANIFEST_DIR")
+        );
+        let mut dec = get_decoder(&path).unwrap();
+        assert!(!dec.capabilities().frame_count);
+        assert_eq!(Some(3), dec.build_index());
+        assert!(dec.capabilities().frame_count);
+        assert_eq!(Some(3), dec.frame_count());
+        // Indexing does not move the position of the next frame
+        for _ in 0..3 {
+            assert!(dec.read_video_frame::<u8>().is_some());
+        }
+        assert!(dec.read_video_frame::<u8>().is_none());
+
+        // Frames of a stream can only be read once
+        let file = BufReader::new(File::open(&path).unwrap());
+        let mut dec = av_metrics_decoders::Y4MDecoder::new(file).unwrap();
+        assert_eq!(None, dec.build_index());
+        assert_eq!(None, dec.frame_count());
+    }
+
+    #[test]
+    fn caching_decoder() {
+        let path = format!(
+            "{}/../testfiles/yuv420p8_input.y4m",
+            env!("CARGO_MANIFEST_DIR")
+        );
+        let luma = |frame: Option<Frame<u8>>| frame.map(|frame| frame.planes[0].data.to_vec());
+        let mut expected = get_decoder(&path).unwrap();
+        let frames: Vec<_> = (0..3).map(|_| luma(expected.read_video_frame())).collect();
+        let frame_bytes = get_decoder(&path)
+            .unwrap()
+            .read_video_frame::<u8>()
+            .unwrap()
+            .planes
+            .iter()
+            .map(|plane| plane.data.len())
+            .sum::<usize>();
+
+        let mut dec = CachingDecoder::new(get_decoder(&path).unwrap(), 2 * frame_bytes);
+        assert_eq!(frames[2], luma(dec.read_specific_frame(2)));
+        assert_eq!(vec![1, 2], dec.cached_frames().collect::<Vec<_>>());
+        assert_eq!(2 * frame_bytes, dec.cached_bytes());
+        assert_eq!(frames[1], luma(dec.read_specific_frame(1)));
+        assert_eq!(vec![2, 1], dec.cached_frames().collect::<Vec<_>>());
+        // Reading in order continues after the last frame read
+        assert_eq!(frames[2], luma(dec.read_video_frame()));
+        assert!(dec.read_video_frame::<u8>().is_none());
+        // The first frame was evicted, and the decoder cannot seek
+        assert!(dec.read_specific_frame::<u8>(0).is_none());
+
+        let mut dec = CachingDecoder::new(get_decoder(&path).unwrap(), 0);
+        assert_eq!(frames[1], luma(dec.read_specific_frame(1)));
+        assert_eq!(0, dec.cached_frames().count());
+    }
+
+    #[test]
+    fn color_conversions() {
+        let limited = ColorConverter::new(8, ColorConfig::default());
+        assert_metric_eq(1.0, limited.rgb(235, 128, 128)[1] as f64);
+        assert_metric_eq(0.0, limited.rgb(16, 128, 128)[0] as f64);
+        assert_metric_eq(100.0, limited.lab(235, 128, 128)[0] as f64);
+        assert_metric_eq(0.0, limited.lab(16, 128, 128)[0] as f64);
+
+        let full = ColorConverter::new(
+            10,
+            ColorConfig {
+                color_range: ColorRange::Full,
+                matrix_coefficients: MatrixCoefficients::Bt2020,
+                transfer_function: TransferFunction::Linear,
+            },
+        );
+        let rgb = full.rgb(1023, 512, 512);
+        assert_metric_eq(1.0, rgb[0] as f64);
+        assert_eq!(full.linear_rgb(1023, 512, 512), rgb);
+
+        // BT.709 linearizes mid-grey lighter than sRGB
+        assert_metric_eq(0.2140, TransferFunction::Srgb.to_linear(0.5) as f64);
+        assert_metric_eq(0.2596, TransferFunction::Bt709.to_linear(0.5) as f64);
+    }
+
+    #[test]
+    fn metric_options_builders() {
+        let path = |name: &str| {
+            format!(
+                "{}/../testfiles/yuv420p8_{}.y4m",
+                env!("CARGO_MANIFEST_DIR"),
+                name
+            )
+        };
+        let decoders = || {
+            (
+                get_decoder(path("input")).unwrap(),
+                get_decoder(path("output")).unwrap(),
+            )
+        };
+
+        let (mut dec1, mut dec2) = decoders();
+        let expected = calculate_video_psnr_with_config(
+            &mut dec1,
+            &mut dec2,
+            Some(2),
+            |_| (),
+            PsnrConfig {
+                prefilter: Some(Prefilter::Box3x3),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let frames = std::sync::atomic::AtomicUsize::new(0);
+        let (mut dec1, mut dec2) = decoders();
+        let result = calculate_video_psnr_with(
+            &mut dec1,
+            &mut dec2,
+            PsnrOptions::new()
+                .frame_limit(2)
+                .threads(2)
+                .prefilter(Prefilter::Box3x3)
+                .progress(|frame| {
+                    if frame != PROGRESS_DONE {
+                        frames.fetch_max(frame, std::sync::atomic::Ordering::Relaxed);
+                    }
+                }),
+        )
+        .unwrap();
+        assert_metric_eq(expected.avg, result.avg);
+        assert_eq!(frames.into_inner(), 2);
+
+        let (mut dec1, mut dec2) = decoders();
+        let expected = calculate_video_msssim_with_config(
+            &mut dec1,
+            &mut dec2,
+            None,
+            |_| (),
+            MsSsimConfig {
+                luma_only: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let (mut dec1, mut dec2) = decoders();
+        let result = calculate_video_msssim_with(
+            &mut dec1,
+            &mut dec2,
+            MsSsimOptions::new().threads(1).luma_only(true),
+        )
+        .unwrap();
+        assert_metric_eq(expected.y, result.y);
+        assert_eq!(result.u, 0.);
+    }
+
+    #[test]
+    fn dyn_decoder() {
+        for format in ["yuv420p8", "yuv420p10"] {
+            let path = |name: &str| {
+                format!(
+                    "{}/../testfiles/{}_{}.y4m",
+                    env!("CARGO_MANIFEST_DIR"),
+                    format,
+                    name
+                )
+            };
+            let mut dec1 = get_decoder(path("input")).unwrap();
+            let mut dec2 = get_decoder(path("output")).unwrap();
+            let expected = calculate_video_psnr(&mut dec1, &mut dec2, None, |_| ()).unwrap();
+
+            let mut dec1: Box<dyn DynDecoder> = Box::new(get_decoder(path("input")).unwrap());
+            let mut dec2: Box<dyn DynDecoder> = Box::new(get_decoder(path("output")).unwrap());
+            assert_eq!(
+                dec1.get_bit_depth(),
+                get_decoder(path("input")).unwrap().get_bit_depth()
+            );
+            let result = calculate_video_psnr(&mut dec1, &mut dec2, None, |_| ()).unwrap();
+            assert_metric_eq(expected.y, result.y);
+            assert_metric_eq(expected.u, result.u);
+            assert_metric_eq(expected.v, result.v);
+            assert_metric_eq(expected.avg, result.avg);
+        }
+    }
+
+    #[test]
+    fn decoder_capabilities() {
+        let path = format!(
+            "{}/../testfiles/yuv420p8_input.y4m",
+            env!("CARGO_MANIFEST_DIR")
+        );
+        let decoder = get_decoder(&path).unwrap();
+        let capabilities = decoder.capabilities();
+        assert_eq!(
+            capabilities,
+            DecoderCapabilities {
+                metadata: true,
+                ..Default::default()
+            }
+        );
+        assert_eq!(decoder.frame_count(), None);
+
+        let boxed: Box<dyn DynDecoder> = Box::new(get_decoder(&path).unwrap());
+        assert_eq!(boxed.capabilities(), capabilities);
+        let skipped = Skipped::new(get_decoder(&path).unwrap(), 2);
+        assert_eq!(skipped.capabilities(), capabilities);
+        let downscaled = Downscaled::new(get_decoder(&path).unwrap(), 2).unwrap();
+        assert_eq!(downscaled.capabilities(), capabilities);
+    }
+
+    #[test]
+    fn frame_limit_boundaries() {
+        let details = VideoDetails {
+            width: 16,
+            height: 16,
+            ..Default::default()
+        };
+        let decoder = |values: &[u8]| TimedFrames {
+            frames: values.iter().map(|&value| (0.0, value)).collect(),
+            read: 0,
+            details,
+        };
+        let reference = [0, 0, 0, 0, 0];
+        let distorted = [1, 2, 3, 4, 5];
+        for limit in [1, 2, 4, 5, 6] {
+            let compared = limit.min(distorted.len());
+            for threads in [1, 4] {
+                let mut dec1 = decoder(&reference);
+                let mut dec2 = decoder(&distorted);
+                let calls = std::sync::Mutex::new(Vec::new());
+                let result = calculate_video_psnr_with(
+                    &mut dec1,
+                    &mut dec2,
+                    PsnrOptions::new()
+                        .frame_limit(limit)
+                        .threads(threads)
+                        .progress(|frames| calls.lock().unwrap().push(frames)),
+                )
+                .unwrap();
+
+                // No frame is decoded past the limit
+                assert_eq!(dec1.read, compared);
+                assert_eq!(dec2.read, compared);
+
+                let mut dec1 = decoder(&reference[..compared]);
+                let mut dec2 = decoder(&distorted[..compared]);
+                let expected = calculate_video_psnr(&mut dec1, &mut dec2, None, |_| ()).unwrap();
+                assert_metric_eq(expected.avg, result.avg);
+
+                let calls = calls.into_inner().unwrap();
+                let (&done, counts) = calls.split_last().unwrap();
+                assert_eq!(done, PROGRESS_DONE);
+                assert!(counts.windows(2).all(|pair| pair[0] < pair[1]));
+                assert_eq!(counts.last(), Some(&compared));
+            }
+        }
+
+        let mut dec1 = decoder(&reference);
+        let mut dec2 = decoder(&distorted);
+        assert!(calculate_video_psnr(&mut dec1, &mut dec2, Some(0), |_| ()).is_err());
+    }
+
+    #[test]
+    fn length_mismatch() {
+        let details = VideoDetails {
+            width: 16,
+            height: 16,
+            ..Default::default()
+        };
+        let decoder = |frames: usize| TimedFrames {
+            frames: (0..frames).map(|i| (i as f64, i as u8)).collect(),
+            read: 0,
+            details,
+        };
+
+        // The trailing frames are ignored by default, and counted when there
+        // is a monitor
+        let monitor = PipelineMonitor::new();
+        let (mut dec1, mut dec2) = (decoder(3), decoder(5));
+        let result =
+            calculate_video_psnr_with(&mut dec1, &mut dec2, PsnrOptions::new().monitor(&monitor))
+                .unwrap();
+        assert_eq!(result.y, 100.0);
+        assert_eq!(monitor.stats().ignored_frames, [0, 2]);
+
+        let (mut dec1, mut dec2) = (decoder(5), decoder(3));
+        let err = calculate_video_psnr_with(
+            &mut dec1,
+            &mut dec2,
+            PsnrOptions::new().length_mismatch(LengthMismatch::Error),
+        )
+        .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<MetricsError>(),
+            Some(MetricsError::LengthMismatch {
+                frames1: 5,
+                frames2: 3
+            })
+        ));
+
+        // Reaching the frame limit first is not a mismatch
+        let (mut dec1, mut dec2) = (decoder(5), decoder(3));
+        calculate_video_psnr_with(
+            &mut dec1,
+            &mut dec2,
+            PsnrOptions::new()
+                .frame_limit(3)
+                .length_mismatch(LengthMismatch::Error),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn gop_aggregation() {
+        let scores = [
+            (true, 40.0),
+            (false, 38.0),
+            (false, 36.0),
+            (true, 42.0),
+            (false, 30.0),
+        ];
+        let gops = aggregate_by_gop(scores);
+        assert_eq!(gops.len(), 2);
+        assert_eq!(gops[0].start_frame, 0);
+        assert_eq!(gops[0].frames, 3);
+        assert_metric_eq(38.0, gops[0].mean);
+        assert_metric_eq(36.0, gops[0].min);
+        assert_eq!(gops[0].min_frame, 2);
+        assert_eq!(gops[1].start_frame, 3);
+        assert_eq!(gops[1].frames, 2);
+        assert_metric_eq(36.0, gops[1].mean);
+        assert_eq!(gops[1].min_frame, 4);
+
+        // A video cut after a keyframe still starts with a GOP
+        let gops = aggregate_by_gop([(false, 20.0), (false, 22.0)]);
+        assert_eq!(gops.len(), 1);
+        assert_metric_eq(21.0, gops[0].mean);
+    }
+
+    #[test]
+    fn score_histogram() {
+        let histogram = Histogram::new([30.0, 31.0, 34.0, 39.5, 40.0, f64::INFINITY], 4);
+        assert_metric_eq(30.0, histogram.min);
+        assert_metric_eq(40.0, histogram.max);
+        assert_metric_eq(2.5, histogram.bin_width());
+        assert_eq!(histogram.counts, vec![2, 1, 0, 2]);
+        assert_eq!(histogram.frames(), 5);
+
+        let flat = Histogram::new([35.0; 3], 2);
+        assert_eq!(flat.counts, vec![3, 0]);
+        assert_metric_eq(0.0, flat.bin_width());
+        let empty = Histogram::new([], 3);
+        assert_eq!(empty.counts, vec![0; 3]);
+        assert_eq!(empty.frames(), 0);
+    }
+
+    #[test]
+    fn bootstrap_confidence() {
+        let scores: Vec<f64> = (0..50).map(|i| 30.0 + (i % 5) as f64).collect();
+        let interval = bootstrap_mean(scores.iter().copied(), BootstrapConfig::default()).unwrap();
+        assert_metric_eq(32.0, interval.mean);
+        assert_metric_eq(0.95, interval.confidence);
+        assert!(interval.lower < interval.mean && interval.mean < interval.upper);
+        // The standard error of the mean is 0.2, so the interval is about 0.8 wide
+        assert!(interval.upper - interval.lower > 0.5 && interval.upper - interval.lower < 1.1);
+        assert_eq!(
+            bootstrap_mean(scores.iter().copied(), BootstrapConfig::default()),
+            Some(interval)
+        );
+
+        let wider = bootstrap_mean(
+            scores.iter().copied(),
+            BootstrapConfig {
+                confidence: 0.99,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert!(wider.lower <= interval.lower && wider.upper >= interval.upper);
+
+        let steady = bootstrap_mean([35.0; 10], BootstrapConfig::default()).unwrap();
+        assert_eq!((steady.lower, steady.upper), (35.0, 35.0));
+        assert_eq!(bootstrap_mean([], BootstrapConfig::default()), None);
+    }
+
+    #[test]
+    fn rate_control_fit() {
+        let side_data: Vec<SideData> = [(22.0, 80_000), (26.0, 40_000), (30.0, 20_000)]
+            .into_iter()
+            .map(|(qp, bits)| SideData {
+                qp: Some(qp),
+                bits: Some(bits),
+                ..Default::default()
+            })
+            .collect();
+        let scores = [44.0, 42.0, 40.0];
+        let fits = fit_rate_control(side_data.iter().map(Some).zip(scores));
+        let qp = fits.qp.unwrap();
+        assert_metric_eq(-0.5, qp.slope);
+        assert_metric_eq(55.0, qp.intercept);
+        assert_metric_eq(-1.0, qp.correlation);
+        assert_metric_eq(40.0, qp.predict(30.0));
+        assert_eq!(qp.frames, 3);
+        // Each doubling of the frame size gains 2 dB
+        let bits = fits.bits.unwrap();
+        assert_metric_eq(2.0, bits.slope);
+        assert_metric_eq(1.0, bits.r_squared());
+
+        // Frames without side data and infinite scores are left out
+        let fits = fit_rate_control([
+            (Some(&side_data[0]), 44.0),
+            (None, 10.0),
+            (Some(&side_data[1]), f64::INFINITY),
+        ]);
+        assert_eq!(fits.qp, None);
+        assert_eq!(fits.bits, None);
+    }
+
+    #[test]
+    fn worst_frame_scores() {
+        let worst = worst_frames([40.0, 31.0, 38.0, 29.5, 31.0, 45.0], 3);
+        assert_eq!(
+            worst,
+            vec![
+                FrameScore {
+                    frame: 3,
+                    score: 29.5
+                },
+                FrameScore {
+                    frame: 1,
+                    score: 31.0
+                },
+                FrameScore {
+                    frame: 4,
+                    score: 31.0
+                },
+            ]
+        );
+        assert_eq!(worst_frames([40.0, 30.0], 5).len(), 2);
+        assert!(worst_frames([40.0], 0).is_empty());
+
+        let mut aggregator = WorstFrames::new(1);
+        for score in [42.0, f64::INFINITY, 12.5, 13.0] {
+            aggregator.push(score);
+        }
+        assert_eq!(
+            aggregator.finish(),
+            vec![FrameScore {
+                frame: 2,
+                score: 12.5
+            }]
+        );
+    }
+
+    #[test]
+    fn report_csv_and_markdown() {
+        let mut report = Report::new("base.y4m");
+        report.comparisons.push(MetricsResults {
+            filename: "alt.y4m".to_owned(),
+            width: 64,
+            height: 32,
+            bit_depth: 10,
+            chroma_sampling: chroma_sampling_name(ChromaSampling::Cs422),
+            frames: 3,
+            downscale: Some(2),
+            psnr: Some(PlanarMetrics {
+                y: 40.5,
+                u: 44.,
+                v: 45.25,
+                avg: 41.75,
+                ..Default::default()
+            }),
+            ciede2000: Some(41.5),
+            vmaf: Some(87.25),
+            ..Default::default()
+        });
+        assert_eq!(report.comparisons[0].average("ciede2000"), Some(41.5));
+        assert_eq!(report.comparisons[0].average("vmaf"), Some(87.25));
+        let timing = MetricTiming::new("ciede2000", Duration::from_millis(1500), 3);
+        assert_metric_eq(2.0, timing.fps);
+        assert_metric_eq(1.5, timing.seconds);
+        assert_eq!(report.comparisons[0].average("psnr"), Some(41.75));
+        assert_eq!(report.comparisons[0].average("ssim"), None);
+
+        let mut csv = Vec::new();
+        report.write_csv(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let mut lines = csv.lines();
+        assert!(lines
+            .next()
+            .unwrap()
+            .starts_with("filename,label,base,psnr,psnr_y,psnr_u,psnr_v,apsnr,apsnr_y,"));
+        assert_eq!(
+            lines.next().unwrap(),
+            format!(
+                "alt.y4m,,base.y4m,41.75,40.5,44,45.25,{}\
+                 41.5,87.25,-0,-0,-0,-0,-0,64,32,10,4:2:2,3,2,{}",
+                "-0,".repeat(16),
+                av_metrics::VERSION
+            )
+        );
+
+        let mut markdown = Vec::new();
+        report.write_markdown(&mut markdown).unwrap();
+        let markdown = String::from_utf8(markdown).unwrap();
+        let lines: Vec<_> = markdown.lines().collect();
+        assert_eq!(lines[2].split('|').count(), lines[3].split('|').count());
+        assert_eq!(lines[3].split('|').count(), lines[4].split('|').count());
+        assert_eq!(
+            lines[4],
+            format!(
+                "|alt.y4m (approximate, downscaled 1/2)|41.75|40.5|44|45.25|{}\
+                 41.5|87.25|-0|-0|-0|-0|-0|64x32|10-bit 4:2:2|3|",
+                "-0|".repeat(16)
+            )
+        );
+
+        // Comparisons of a batch have their own base input and a label
+        report.comparisons[0].base = Some("other.y4m".to_owned());
+        report.comparisons[0].label = Some("crf 20".to_owned());
+        assert_eq!(report.base_of(&report.comparisons[0]), "other.y4m");
+        let mut csv = Vec::new();
+        report.write_csv(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert!(csv
+            .lines()
+            .nth(1)
+            .unwrap()
+            .starts_with("alt.y4m,crf 20,other.y4m,41.75,"));
+        let mut markdown = Vec::new();
+        report.write_markdown(&mut markdown).unwrap();
+        let markdown = String::from_utf8(markdown).unwrap();
+        assert!(markdown
+            .lines()
+            .last()
+            .unwrap()
+            .starts_with("|crf 20: alt.y4m (approximate, downscaled 1/2)|41.75|"));
+    }
+
+    #[test]
+    fn report_precision() {
+        let mut report = Report::new("base.y4m");
+        report.comparisons.push(MetricsResults {
+            filename: "alt.y4m".to_owned(),
+            ciede2000: Some(41.123456789),
+            vmaf: Some(87.256),
+            ..Default::default()
+        });
+        let csv_row = |report: &Report| {
+            let mut csv = Vec::new();
+            report.write_csv(&mut csv).unwrap();
+            let csv = String::from_utf8(csv).unwrap();
+            csv.lines().nth(1).unwrap().to_owned()
+        };
+        assert!(csv_row(&report).contains(",-0,41.123456789,87.256,"));
+
+        report.precision = Some(2);
+        assert!(csv_row(&report).starts_with("alt.y4m,,base.y4m,-0.00,-0.00,"));
+        assert!(csv_row(&report).contains(",-0.00,41.12,87.26,"));
+        let mut json = Vec::new();
+        report.write_json(&mut json).unwrap();
+        let json = String::from_utf8(json).unwrap();
+        assert!(json.contains(r#""ciede2000":41.12,"vmaf":87.26"#));
+        assert!(!json.contains("precision"));
+        let mut yaml = Vec::new();
+        report.write_yaml(&mut yaml).unwrap();
+        let yaml = String::from_utf8(yaml).unwrap();
+        assert!(yaml.contains("vmaf: 87.26\n"));
+
+        report.precision = Some(0);
+        assert!(csv_row(&report).contains(",41,87,"));
+    }
+
+    #[test]
+    fn report_display_resolution() {
+        let mut results = MetricsResults {
+            width: 720,
+            height: 576,
+            ..Default::default()
+        };
+        assert_eq!(results.display_resolution(), None);
+        assert_eq!(results.resolution(), "720x576");
+        results.sample_aspect_ratio = Some(Rational::new(64, 45));
+        assert_eq!(results.display_resolution(), Some((1024, 576)));
+        assert_eq!(results.resolution(), "720x576 displayed as 1024x576");
+        results.sample_aspect_ratio = Some(Rational::new(1, 1));
+        assert_eq!(results.display_resolution(), None);
+    }
+
+    #[test]
+    fn report_audio_results() {
+        let mut report = Report::new("base.wav");
+        report.comparisons.push(MetricsResults {
+            filename: "alt.wav".to_owned(),
+            sample_rate: Some(48000),
+            channels: Some(2),
+            frames: 10,
+            peaq: Some(PeaqResult {
+                odg: -1.5,
+                distortion_index: 0.,
+                movs: PeaqMovs::default(),
+            }),
+            spectral: Some(SpectralMetrics {
+                log_spectral_distance: 2.,
+                spectral_convergence: 0.25,
+                segmental_snr: 20.,
+            }),
+            ..Default::default()
+        });
+        assert_eq!(report.comparisons[0].average("peaq"), Some(-1.5));
+        assert_eq!(report.comparisons[0].average("spectral"), Some(20.));
+
+        let mut markdown = Vec::new();
+        report.write_markdown(&mut markdown).unwrap();
+        let markdown = String::from_utf8(markdown).unwrap();
+        assert_eq!(
+            markdown.lines().last().unwrap(),
+            format!(
+                "|alt.wav|{}-1.5|2|0.25|20|48000 Hz|2 channels|10|",
+                "-0|".repeat(23)
+            )
+        );
+    }
+
+    /// y4m has no 16-bit colorspaces, so the 16-bit tests use the first frame
+    /// of the 10-bit clips scaled up to 16 bits.
+    fn get_yuv420p16_frames() -> (Frame<u16>, Frame<u16>) {
+        let mut dec1 = get_decoder(format!(
+            "{}/../testfiles/yuv420p10_input.y4m",
+            env!("CARGO_MANIFEST_DIR")
+        ))
+        .unwrap();
+        let mut dec2 = get_decoder(format!(
+            "{}/../testfiles/yuv420p10_output.y4m",
+            env!("CARGO_MANIFEST_DIR")
+        ))
+        .unwrap();
+        let scale = |mut frame: Frame<u16>| {
+            for plane in frame.planes.iter_mut() {
+                plane.data.iter_mut().for_each(|pix| *pix <<= 6);
+            }
+            frame
+        };
+        (
+            scale(dec1.read_video_frame::<u16>().unwrap()),
+            scale(dec2.read_video_frame::<u16>().unwrap()),
+        )
+    }
+
+    fn assert_metric_eq(expected: f64, value: f64) {
+        assert!(
+            (expected - value).abs() < 0.01,
+            "Expected {}, got {}",
+            expected,
+            value
+        );
+    }
+
+    #[cfg(feature = "ffmpeg")]
+    #[test]
+    fn ffmpeg_unknown_hwaccel() {
+        let options = FfmpegOptions {
+            hwaccel: Some("not-a-device".to_owned()),
+            ..Default::default()
+        };
+        let decoder = FfmpegDecoder::new_with_options(
+            format!(
+                "{}/../testfiles/yuv420p8_input.y4m",
+                env!("CARGO_MANIFEST_DIR")
+            ),
+            &options,
+        );
+        assert!(decoder.is_err());
+    }
+
+    #[cfg(feature = "ffmpeg")]
+    #[test]
+    fn ffmpeg_stream_selection() {
+        let input = format!(
+            "{}/../testfiles/yuv420p8_input.y4m",
+            env!("CARGO_MANIFEST_DIR")
+        );
+        let open = |stream_index, program| {
+            FfmpegDecoder::new_with_options(
+                &input,
+                &FfmpegOptions {
+                    stream_index,
+                    program,
+                    ..Default::default()
+                },
+            )
+        };
+        assert!(open(Some(0), None).is_ok());
+        assert!(open(Some(1), None).is_err());
+        assert!(open(None, Some(1)).is_err());
+    }
+
+    #[cfg(feature = "ffmpeg")]
+    #[test]
+    fn ffmpeg_presentation_order() {
+        let path = format!(
+            "{}/../testfiles/yuv420p8_output.y4m",
+            env!("CARGO_MANIFEST_DIR")
+        );
+        let mut ffmpeg = FfmpegDecoder::new(&path).unwrap();
+        let mut y4m = av_metrics_decoders::y4m::new_decoder_from_file(&path).unwrap();
+        let chroma_sampling = y4m.get_video_details().chroma_sampling;
+        let mut last_pts = None;
+        let mut frames = 0;
+        while let Some(frame) = ffmpeg.read_video_frame::<u8>() {
+            let expected = y4m.read_video_frame::<u8>().unwrap();
+            assert_eq!(
+                av_metrics::video::lossless::find_frame_mismatch(
+                    &frame,
+                    &expected,
+                    chroma_sampling
+                ),
+                None
+            );
+            let meta = ffmpeg.last_frame_meta().unwrap();
+            assert_eq!(meta.number, frames);
+            assert!(meta.pts > last_pts);
+            last_pts = meta.pts;
+            frames += 1;
+        }
+        assert!(y4m.read_video_frame::<u8>().is_none());
+        assert_eq!(frames, 3);
+    }
+
+    #[cfg(all(unix, feature = "isolation"))]
+    #[test]
+    fn isolated_decoder() {
+        let open = |kind: &str| {
+            let mut command = std::process::Command::new("cat");
+            command.arg(format!(
+                "{}/../testfiles/yuv420p8_{}.y4m",
+                env!("CARGO_MANIFEST_DIR"),
+                kind
+            ));
+            IsolatedDecoder::new(command).unwrap()
+        };
+        let mut dec1 = open("input");
+        let mut dec2 = open("output");
+        assert_eq!(dec1.get_video_details().width, 640);
+        let result = calculate_video_psnr(&mut dec1, &mut dec2, None, |_| ()).unwrap();
+        assert_metric_eq(33.6861, result.avg);
+
+        assert!(IsolatedDecoder::new(std::process::Command::new("false")).is_err());
+        assert!(IsolatedDecoder::new(std::process::Command::new("/nonexistent/decoder")).is_err());
+        let command = IsolatedDecoder::ffmpeg_command("input.mkv");
+        assert_eq!(command.get_program(), "ffmpeg");
+        assert!(command.get_args().any(|arg| arg == "yuv4mpegpipe"));
+    }
+
+    #[cfg(feature = "shm")]
+    #[test]
+    fn shm_decoder() {
+        let path = |kind: &str| {
+            format!(
+                "{}/../testfiles/yuv420p8_{}.y4m",
+                env!("CARGO_MANIFEST_DIR"),
+                kind
+            )
+        };
+        let slots = 2;
+        let format =
+            ShmFormat::from_details(&get_decoder(path("input")).unwrap().get_video_details());
+        assert_eq!(format.frame_size(), Some(640 * 360 * 3 / 2));
+        let len = format.ring_size(slots).unwrap();
+        let mut memory1 = vec![0u64; len.div_ceil(8)];
+        let mut memory2 = vec![0u64; len.div_ceil(8)];
+        let base1 = memory1.as_mut_ptr() as *mut u8;
+        let base2 = memory2.as_mut_ptr() as *mut u8;
+
+        assert!(unsafe { ShmDecoder::open(base1, len) }.is_err());
+        assert!(unsafe { ShmFrameWriter::create(base1, HEADER_SIZE, format, slots) }.is_err());
+        assert!(
+            unsafe { ShmFrameWriter::create(base1.wrapping_add(1), len - 1, format, slots) }
+                .is_err()
+        );
+
+        let writer1 = unsafe { ShmFrameWriter::create(base1, len, format, slots) }.unwrap();
+        let writer2 = unsafe { ShmFrameWriter::create(base2, len, format, slots) }.unwrap();
+        let mut dec1 = unsafe { ShmDecoder::open(base1, len) }.unwrap();
+        let mut dec2 = unsafe { ShmDecoder::open(base2, len) }.unwrap();
+        assert_eq!(dec1.get_video_details().width, 640);
+        assert_eq!(dec1.get_bit_depth(), 8);
+
+        let result = std::thread::scope(|scope| {
+            for (mut writer, kind) in [(writer1, "input"), (writer2, "output")] {
+                let mut source = get_decoder(path(kind)).unwrap();
+                scope.spawn(move || {
+                    while let Some(frame) = source.read_video_frame::<u8>() {
+                        writer.write_frame(&frame).unwrap();
+                    }
+                });
+            }
+            calculate_video_psnr(&mut dec1, &mut dec2, None, |_| ()).unwrap()
+        });
+        assert_metric_eq(33.6861, result.avg);
+        assert!(dec1.read_video_frame::<u8>().is_none());
+    }
+
+    #[cfg(feature = "shm")]
+    #[test]
+    fn shm_decoder_overflowing_header() {
+        // A header written by hand, as an untrusted producer could
+        let header = |width: u32, height: u32, slots: u32, slot_size: u64| {
+            let mut memory = vec![0u64; HEADER_SIZE / 8 + 8];
+            let bytes = memory.as_mut_ptr() as *mut u8;
+            let fields = [
+                u32::from_ne_bytes(*b"AVMF"),
+                1,
+                width,
+                height,
+                16,
+                2,
+                slots,
+                0,
+            ];
+            for (i, field) in fields.iter().enumerate() {
+                // SAFETY: The fields are within the memory.
+                unsafe { (bytes.add(i * 4) as *mut u32).write(*field) };
+            }
+            // SAFETY: As above.
+            unsafe { (bytes.add(32) as *mut u64).write(slot_size) };
+            memory
+        };
+        let open = |mut memory: Vec<u64>| {
+            let len = memory.len() * 8;
+            // SAFETY: The memory is mapped for the lifetime of the decoder.
+            unsafe { ShmDecoder::open(memory.as_mut_ptr() as *mut u8, len) }.map(|_| ())
+        };
+
+        let huge = ShmFormat {
+            width: u32::MAX as usize,
+            height: u32::MAX as usize,
+            bit_depth: 16,
+            chroma_sampling: ChromaSampling::Cs444,
+        };
+        assert_eq!(huge.frame_size(), None);
+        assert_eq!(huge.ring_size(1), None);
+        // The frame size overflows, so no slot size matches it
+        for slot_size in [0, 64, u64::MAX] {
+            let memory = header(u32::MAX, u32::MAX, 1, slot_size);
+            assert!(matches!(
+                open(memory),
+                Err(DecoderError::MalformedInput { .. })
+            ));
+        }
+
+        // The slot size is consistent, but the size of the ring overflows
+        let wide = ShmFormat {
+            width: 1 << 16,
+            height: 1 << 16,
+            ..huge
+        };
+        let slot_size = wide.frame_size().unwrap() as u64;
+        assert_eq!(wide.ring_size(u32::MAX as usize), None);
+        let memory = header(1 << 16, 1 << 16, u32::MAX, slot_size);
+        assert!(matches!(
+            open(memory),
+            Err(DecoderError::MalformedInput { .. })
+        ));
+    }
+
+    #[test]
+    fn vmaf_features() {
+        let path = |kind: &str| {
+            format!(
+                "{}/../testfiles/yuv420p8_{}.y4m",
+                env!("CARGO_MANIFEST_DIR"),
+                kind
+            )
+        };
+        let mut dec1 = get_decoder(path("input")).unwrap();
+        let mut dec2 = get_decoder(path("input")).unwrap();
+        let identical = calculate_video_vmaf_features(&mut dec1, &mut dec2, None, |_| ()).unwrap();
+        let mut dec1 = get_decoder(path("input")).unwrap();
+        let mut dec2 = get_decoder(path("output")).unwrap();
+        let distorted = calculate_video_vmaf_features(&mut dec1, &mut dec2, None, |_| ()).unwrap();
+        assert_eq!(identical.len(), distorted.len());
+        assert_metric_eq(0.9418, distorted[0].adm2);
+        assert_metric_eq(0.4778, distorted[0].vif[0]);
+        assert_metric_eq(0.9518, distorted[0].vif[3]);
+        assert_metric_eq(7.7687, distorted[1].motion);
+        assert_metric_eq(7.7687, distorted[1].motion2);
+        assert_metric_eq(0.0, identical[0].motion);
+        for (identical, distorted) in identical.iter().zip(distorted.iter()) {
+            assert_metric_eq(1.0, identical.adm2);
+            for vif in identical.vif {
+                assert_metric_eq(1.0, vif);
+            }
+            assert!(distorted.adm2 < 1.0);
+            assert!(distorted.vif.iter().all(|&vif| vif < 1.0));
+            // Motion only depends on the reference
+            assert_eq!(identical.motion, distorted.motion);
+            assert!(identical.motion2 <= identical.motion);
+        }
+
+        let frame = synth::zone_plate::<u16>(64, 64, 10, ChromaSampling::Cs420).unwrap();
+        let features =
+            calculate_frame_vmaf_features(&frame, &frame, 10, ChromaSampling::Cs420).unwrap();
+        assert_metric_eq(0.0, features.motion);
+        assert_metric_eq(1.0, features.adm2);
+    }
+
+    /// A libvmaf model with two support vectors over ADM2 and motion2.
+    const VMAF_TEST_MODEL: &str = r#"{
+        "param_dict": {"C": 4.0, "nu": 0.9, "gamma": 0.5},
+        "model_dict": {
+            "model_type": "LIBSVMNU",
+            "norm_type": "linear_rescale",
+            "feature_names": ["VMAF_feature_adm2_score", "VMAF_feature_motion2_score"],
+            "slopes": [0.5, 2.0, 1.0],
+            "intercepts": [-1.0, 0.0, 0.5],
+            "score_clip": [0.0, 100.0],
+            "score_transform": {"enabled": "false", "p0": 1.0, "p1": 1.0, "p2": 0.0},
+            "model": "svm_type nu_svr\nkernel_type rbf\ngamma 0.5\nnr_class 2\ntotal_sv 2\nrho -1\nSV\n2 1:1 2:0.5 \n-1 1:0 \n"
+        }
+    }"#;
+
+    #[test]
+    fn vmaf_model() {
+        let model = VmafModel::from_json(VMAF_TEST_MODEL).unwrap();
+        let features = VmafFeatures {
+            adm2: 0.5,
+            motion2: 0.25,
+            ..Default::default()
+        };
+        // The rescaled features are (1, 0.75), so the regression gives
+        // 2 * exp(-0.5 * 0.0625) - exp(-0.5 * 1.5625) + 1, rescaled back
+        assert_metric_eq(6.9613, model.predict(&features));
+        let far = VmafFeatures {
+            adm2: 100.0,
+            ..features
+        };
+        // Far from every support vector, only rho is left
+        assert_metric_eq(4.0, model.predict(&far));
+
+        let clipped =
+            VmafModel::from_json(&VMAF_TEST_MODEL.replace("[0.0, 100.0]", "[0.0, 5.0]")).unwrap();
+        assert_metric_eq(5.0, clipped.predict(&features));
+        let transformed = VmafModel::from_json(
+            &VMAF_TEST_MODEL.replace(r#""enabled": "false""#, r#""enabled": true"#),
+        )
+        .unwrap();
+        assert_metric_eq(7.9613, transformed.predict(&features));
+
+        assert!(VmafModel::from_json("{\"model_dict\": ").is_err());
+        assert!(
+            VmafModel::from_json(&VMAF_TEST_MODEL.replace("LIBSVMNU", "BOOTSTRAP_LIBSVMNU"))
+                .is_err()
+        );
+        assert!(
+            VmafModel::from_json(&VMAF_TEST_MODEL.replace("motion2_score", "ansnr_score")).is_err()
+        );
+
+        let path = |kind: &str| {
+            format!(
+                "{}/../testfiles/yuv420p8_{}.y4m",
+                env!("CARGO_MANIFEST_DIR"),
+                kind
+            )
+        };
+        let mut dec1 = get_decoder(path("input")).unwrap();
+        let mut dec2 = get_decoder(path("output")).unwrap();
+        let features = calculate_video_vmaf_features(&mut dec1, &mut dec2, None, |_| ()).unwrap();
+        let mut dec1 = get_decoder(path("input")).unwrap();
+        let mut dec2 = get_decoder(path("output")).unwrap();
+        let result = calculate_video_vmaf(&mut dec1, &mut dec2, &model, None, |_| ()).unwrap();
+        assert_eq!(result.frames.len(), features.len());
+        for (score, features) in result.frames.iter().zip(features.iter()) {
+            assert_metric_eq(model.predict(features), *score);
+        }
+        assert_metric_eq(result.frames.iter().sum::<f64>() / 3.0, result.mean);
+        assert!(result.min <= result.harmonic_mean && result.harmonic_mean <= result.mean);
+
+        let mut dec1 = get_decoder(path("input")).unwrap();
+        let mut dec2 = get_decoder(path("output")).unwrap();
+        assert!(calculate_video_vmaf_with(&mut dec1, &mut dec2, VmafOptions::new()).is_err());
+    }
+
+    #[test]
+    fn brisque() {
+        let range = std::iter::once("x\n-1 1".to_owned())
+            .chain((1..=36).map(|i| format!("{} 0 1", i)))
+            .collect::<Vec<_>>()
+            .join("\n");
+        // With a gamma of 0, the only support vector counts fully for any frame
+        let model = BrisqueModel::from_libsvm(
+            "svm_type epsilon_svr\nkernel_type rbf\ngamma 0\ntotal_sv 1\nrho -5\nSV\n10 1:0.5\n",
+            &range,
+        )
+        .unwrap();
+        assert!(BrisqueModel::from_libsvm("SV\n", &range).is_err());
+        assert!(BrisqueModel::from_libsvm("gamma 0\nrho 0\nSV\n", "x\n-1 1\n1 0 1").is_err());
+
+        let mut decoder = get_decoder(format!(
+            "{}/../testfiles/yuv420p8_input.y4m",
+            env!("CARGO_MANIFEST_DIR")
+        ))
+        .unwrap();
+        let frame = decoder.read_video_frame::<u8>().unwrap();
+        let features = calculate_frame_brisque_features(&frame, 8).unwrap();
+        assert!(features.values.iter().all(|value| value.is_finite()));
+        // Shapes are fitted between 0.2 and 10
+        for shape in [0, 2, 6, 10, 14, 18, 20, 24, 28, 32] {
+            assert!((0.2..=10.0).contains(&features.values[shape]));
+        }
+        assert_metric_eq(15.0, calculate_frame_brisque(&frame, 8, &model).unwrap());
+
+        let mut decoder = get_decoder(format!(
+            "{}/../testfiles/yuv420p8_input.y4m",
+            env!("CARGO_MANIFEST_DIR")
+        ))
+        .unwrap();
+        let result = calculate_video_brisque(&mut decoder, &model, Some(2), |_| ()).unwrap();
+        assert_eq!(result.frames.len(), 2);
+        assert_metric_eq(15.0, result.mean);
+        assert_metric_eq(15.0, result.max);
+
+        let tiny = synth::gradient::<u8>(8, 8, 8, ChromaSampling::Cs420).unwrap();
+        assert!(calculate_frame_brisque_features(&tiny, 8).is_err());
+    }
+
+    #[test]
+    fn artifact_indicators() {
+        let (width, height) = (64, 32);
+        let chroma = vec![128u8; width * height / 4];
+        let frame = |luma: &dyn Fn(usize, usize) -> u8| {
+            let y: Vec<u8> = (0..width * height)
+                .map(|i| luma(i % width, i / width))
+                .collect();
+            frame_from_planes(
+                width,
+                height,
+                ChromaSampling::Cs420,
+                [&y, &chroma, &chroma],
+                [width, width / 2, width / 2],
+            )
+            .unwrap()
+        };
+
+        let flat = calculate_frame_artifacts(&frame(&|_, _| 100), 8).unwrap();
+        assert_metric_eq(1.0, flat.blockiness);
+        assert_metric_eq(0.0, flat.blurriness);
+
+        // Constant 8x8 blocks have sharp edges only along their boundaries
+        let blocks = frame(&|x, y| if (x / 8 + y / 8) % 2 == 0 { 100 } else { 140 });
+        let blocky = calculate_frame_artifacts(&blocks, 8).unwrap();
+        assert!(blocky.blockiness > 20.0);
+        assert_metric_eq(1.0, blocky.blurriness);
+
+        // Steps spread over 4 pixels are measured from one plateau to the other
+        let ramps = frame(&|x, _| match x % 32 {
+            0..=5 | 26..=31 => 100,
+            6..=9 => 100 + 8 * (x % 32 - 5) as u8,
+            10..=21 => 140,
+            _ => 140 - 8 * (x % 32 - 21) as u8,
+        });
+        let blurry = calculate_frame_artifacts(&ramps, 8).unwrap();
+        assert_metric_eq(5.0, blurry.blurriness);
+        assert!(blurry.blockiness < blocky.blockiness / 10.0);
+
+        let mut decoder = get_decoder(format!(
+            "{}/../testfiles/yuv420p8_output.y4m",
+            env!("CARGO_MANIFEST_DIR")
+        ))
+        .unwrap();
+        let result = calculate_video_artifacts(&mut decoder, None, |_| ()).unwrap();
+        assert_eq!(result.frames.len(), 3);
+        let mean = result.frames.iter().map(|f| f.blurriness).sum::<f64>() / 3.0;
+        assert_metric_eq(mean, result.mean.blurriness);
+        assert!(result
+            .frames
+            .iter()
+            .all(|f| f.blockiness <= result.max.blockiness));
+    }
+
+    #[test]
+    fn side_data() {
+        assert!(QpMap::new(0, 2, 1, vec![10, 20]).is_none());
+        assert!(QpMap::new(16, 2, 2, vec![10, 20]).is_none());
+        let map = QpMap::new(16, 2, 2, vec![10, 20, 30, 40]).unwrap();
+        assert_eq!(map.at_pixel(17, 3), Some(20));
+        assert_eq!(map.at_pixel(5, 31), Some(30));
+        assert_eq!(map.at_pixel(32, 0), None);
+        assert_metric_eq(25.0, map.mean());
+        let mut side_data = SideData {
+            qp_map: Some(map),
+            ..Default::default()
+        };
+        assert_eq!(side_data.mean_qp(), Some(25.0));
+        side_data.qp = Some(22.0);
+        assert_eq!(side_data.mean_qp(), Some(22.0));
+        assert_eq!(SideData::default().mean_qp(), None);
+
+        let path = |kind: &str| {
+            format!(
+                "{}/../testfiles/yuv420p8_{}.y4m",
+                env!("CARGO_MANIFEST_DIR"),
+                kind
+            )
+        };
+        let frame_sizes = |sizes: &[u64]| {
+            sizes
+                .iter()
+                .map(|&bits| SideData {
+                    bits: Some(bits),
+                    ..Default::default()
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let mut decoder = WithSideData::new(
+            get_decoder(path("output")).unwrap(),
+            frame_sizes(&[800, 200]),
+        );
+        assert_eq!(decoder.last_side_data(), None);
+        assert!(!decoder.capabilities().seek);
+        let mut bits = Vec::new();
+        while decoder.read_video_frame::<u8>().is_some() {
+            bits.push(
+                decoder
+                    .last_side_data()
+                    .and_then(|side_data| side_data.bits),
+            );
+        }
+        assert_eq!(bits, vec![Some(800), Some(200), None]);
+
+        let (mut dec1, mut dec2) = Paired::lockstep(
+            WithSideData::new(get_decoder(path("input")).unwrap(), Vec::new()),
+            WithSideData::new(get_decoder(path("output")).unwrap(), frame_sizes(&[800])),
+        );
+        assert!(dec1.read_video_frame::<u8>().is_some());
+        assert!(dec2.read_video_frame::<u8>().is_some());
+        assert_eq!(dec1.last_side_data(), None);
+        let boxed: Box<dyn DynDecoder> = Box::new(dec2);
+        assert_eq!(
+            boxed.last_side_data().and_then(|side_data| side_data.bits),
+            Some(800)
+        );
+
+        let mut dec1 = get_decoder(path("input")).unwrap();
+        let mut dec2 = get_decoder(path("output")).unwrap();
+        let expected = calculate_video_psnr(&mut dec1, &mut dec2, None, |_| ()).unwrap();
+        let mut dec1 = WithSideData::new(get_decoder(path("input")).unwrap(), Vec::new());
+        let mut dec2 = WithSideData::new(
+            get_decoder(path("output")).unwrap(),
+            frame_sizes(&[1, 2, 3]),
+        );
+        let result = calculate_video_psnr(&mut dec1, &mut dec2, None, |_| ()).unwrap();
+        assert_metric_eq(expected.avg, result.avg);
+    }
+
+    #[test]
+    fn psnr_tiles() {
+        let frame = synth::gradient::<u8>(64, 64, 8, ChromaSampling::Cs420).unwrap();
+        let mut corrupted = frame.clone();
+        for row in corrupted.planes[0].rows_iter_mut().skip(32) {
+            for pixel in row[32..].iter_mut() {
+                *pixel ^= 4;
+            }
+        }
+        let tiles = calculate_frame_psnr_tiles(
+            &frame,
+            &corrupted,
+            8,
+            ChromaSampling::Cs420,
+            TileGrid::new(2, 2),
+        )
+        .unwrap();
+        assert_eq!(tiles.len(), 4);
+        for tile in tiles[..3].iter() {
+            assert_metric_eq(100.0, tile.psnr.avg);
+        }
+        let corner = tiles[3];
+        assert_eq!(
+            (
+                corner.column,
+                corner.row,
+                corner.x,
+                corner.y,
+                corner.width,
+                corner.height
+            ),
+            (1, 1, 32, 32, 32, 32)
+        );
+        assert_metric_eq(36.0914, corner.psnr.y);
+        assert_metric_eq(100.0, corner.psnr.u);
+        assert_metric_eq(37.8523, corner.psnr.avg);
+        assert_metric_eq(corner.psnr.avg, corner.min);
+        assert_eq!(corner.min_frame, 0);
+
+        for grid in [TileGrid::new(0, 2), TileGrid::new(65, 1)] {
+            assert!(
+                calculate_frame_psnr_tiles(&frame, &frame, 8, ChromaSampling::Cs420, grid).is_err()
+            );
+        }
+
+        let path = |kind: &str| {
+            format!(
+                "{}/../testfiles/yuv420p8_{}.y4m",
+                env!("CARGO_MANIFEST_DIR"),
+                kind
+            )
+        };
+        let mut dec1 = get_decoder(path("input")).unwrap();
+        let mut dec2 = get_decoder(path("output")).unwrap();
+        let expected = calculate_video_psnr(&mut dec1, &mut dec2, None, |_| ()).unwrap();
+        let mut dec1 = get_decoder(path("input")).unwrap();
+        let mut dec2 = get_decoder(path("output")).unwrap();
+        let whole =
+            calculate_video_psnr_tiles(&mut dec1, &mut dec2, TileGrid::new(1, 1), None, |_| ())
+                .unwrap();
+        assert_eq!(whole.len(), 1);
+        assert_metric_eq(expected.y, whole[0].psnr.y);
+        assert_metric_eq(expected.u, whole[0].psnr.u);
+        assert_metric_eq(expected.v, whole[0].psnr.v);
+        assert_metric_eq(expected.avg, whole[0].psnr.avg);
+        assert!(whole[0].min <= whole[0].psnr.avg);
+
+        let mut dec1 = get_decoder(path("input")).unwrap();
+        let mut dec2 = get_decoder(path("output")).unwrap();
+        let tiles = calculate_video_psnr_tiles_with(
+            &mut dec1,
+            &mut dec2,
+            TilesOptions::new().frame_limit(2).grid(4, 3),
+        )
+        .unwrap();
+        assert_eq!(tiles.len(), 12);
+        assert_eq!(
+            tiles.iter().map(|tile| tile.width).sum::<usize>(),
+            3 * whole[0].width
+        );
+        assert!(tiles.iter().all(|tile| tile.min_frame < 2));
+    }
+
+    #[test]
+    fn banding_coverage() {
+        let (width, height) = (64, 32);
+        let frame = |offset: u8| {
+            // A slow ramp on the left half and a checkerboard on the right
+            let y: Vec<u8> = (0..width * height)
+                .map(|i| {
+                    let (x, y) = (i % width, i / width);
+                    if x < 32 {
+                        100 + (x / 4) as u8 + offset
+                    } else {
+                        100 + 40 * ((x + y) % 2) as u8 + offset
+                    }
+                })
+                .collect();
+            let chroma = vec![128u8; width * height / 4];
+            frame_from_planes(
+                width,
+                height,
+                ChromaSampling::Cs420,
+                [&y, &chroma, &chroma],
+                [width, width / 2, width / 2],
+            )
+            .unwrap()
+        };
+        let (base, brighter) = (frame(0), frame(2));
+
+        let result = calculate_frame_banding(
+            &base,
+            &brighter,
+            8,
+            ChromaSampling::Cs420,
+            BandingConfig::default(),
+        )
+        .unwrap();
+        assert_metric_eq(50.0, result.coverage);
+        let psnr = result.psnr.unwrap();
+        assert_metric_eq(42.1102, psnr.y);
+        assert_metric_eq(100.0, psnr.u);
+        assert_metric_eq(43.8711, psnr.avg);
+
+        let config = BandingConfig {
+            max_gradient: 0.0,
+            ..Default::default()
+        };
+        let result =
+            calculate_frame_banding(&base, &brighter, 8, ChromaSampling::Cs420, config).unwrap();
+        assert_metric_eq(0.0, result.coverage);
+        assert!(result.psnr.is_none());
+
+        let config = BandingConfig {
+            block_size: 1,
+            ..Default::default()
+        };
+        assert!(calculate_frame_banding(&base, &base, 8, ChromaSampling::Cs420, config).is_err());
+
+        let path = |kind: &str| {
+            format!(
+                "{}/../testfiles/yuv420p8_{}.y4m",
+                env!("CARGO_MANIFEST_DIR"),
+                kind
+            )
+        };
+        let mut dec1 = get_decoder(path("input")).unwrap();
+        let mut dec2 = get_decoder(path("output")).unwrap();
+        let result = calculate_video_banding_with(
+            &mut dec1,
+            &mut dec2,
+            BandingOptions::new().max_gradient(255.0),
+        )
+        .unwrap();
+        let mut dec1 = get_decoder(path("input")).unwrap();
+        let mut dec2 = get_decoder(path("output")).unwrap();
+        let expected = calculate_video_psnr(&mut dec1, &mut dec2, None, |_| ()).unwrap();
+        assert_eq!(result.frames.len(), 3);
+        assert!(result.frames.iter().all(|frame| frame.coverage == 100.0));
+        assert_metric_eq(expected.avg, result.psnr.unwrap().avg);
+    }
+
+    #[test]
+    fn video_metric_dispatch() {
+        let path = |kind: &str| {
+            format!(
+                "{}/../testfiles/yuv420p8_{}.y4m",
+                env!("CARGO_MANIFEST_DIR"),
+                kind
+            )
+        };
+        let compute = |metric: &str, settings: &MetricSettings| {
+            let mut dec1 = get_decoder(path("input")).unwrap();
+            let mut dec2 = get_decoder(path("output")).unwrap();
+            compute_video_metric(
+                metric,
+                &mut dec1,
+                &mut dec2,
+                settings,
+                VideoOptions::new().progress(|_| ()),
+            )
+        };
+        let settings = MetricSettings {
+            vmaf_model: Some(VmafModel::from_json(VMAF_TEST_MODEL).unwrap()),
+            ..Default::default()
+        };
+        for metric in VIDEO_METRICS {
+            let score = compute(metric, &settings).unwrap();
+            let mut results = MetricsResults::default();
+            results.set_score(metric, score);
+            assert_eq!(results.average(metric), Some(score.value()), "{}", metric);
+        }
+
+        let mut dec1 = get_decoder(path("input")).unwrap();
+        let mut dec2 = get_decoder(path("output")).unwrap();
+        let expected = calculate_video_ssim(&mut dec1, &mut dec2, None, |_| ()).unwrap();
+        let Score::Planar(ssim) = compute("ssim", &settings).unwrap() else {
+            panic!("SSIM is planar");
+        };
+        assert_metric_eq(expected.avg, ssim.avg);
+        let luma = MetricSettings {
+            plane_weights: PlaneWeights::Luma,
+            ..Default::default()
+        };
+        let Score::Planar(psnr) = compute("psnr", &luma).unwrap() else {
+            panic!("PSNR is planar");
+        };
+        assert_eq!(psnr.avg, psnr.y);
+
+        assert!(compute("vmaf", &MetricSettings::default()).is_err());
+        assert!(compute("brisque", &settings).is_err());
+    }
+
+    /// Serves `config` on a free port of the loopback interface, with its
+    /// uploads in a directory named after `name`.
+    fn start_server(name: &str, config: ServerConfig) -> (Arc<Server>, SocketAddr) {
+        let uploads =
+            std::env::temp_dir().join(format!("av-metrics-server-{}-{}", std::process::id(), name));
+        let server = Arc::new(Server::new(ServerConfig { uploads, ..config }).unwrap());
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let running = Arc::clone(&server);
+        thread::spawn(move || running.run(listener));
+        (server, addr)
+    }
+
+    /// Sends `request` as is, and returns the status and body of the
+    /// response, or `None` if the connection was closed without one.
+    fn send_raw(addr: SocketAddr, request: &[u8]) -> Option<(u16, Value)> {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(request).ok()?;
+        let mut response = String::new();
+        stream.read_to_string(&mut response).ok()?;
+        let (head, body) = response.split_once("\r\n\r\n")?;
+        let status = head.split_whitespace().nth(1)?.parse().ok()?;
+        Some((status, serde_json::from_str(body).unwrap()))
+    }
+
+    fn send(addr: SocketAddr, method: &str, path: &str, body: &[u8]) -> (u16, Value) {
+        let mut request = format!(
+            "{} {} HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n\r\n",
+            method,
+            path,
+            body.len()
+        )
+        .into_bytes();
+        request.extend_from_slice(body);
+        send_raw(addr, &request).expect("a response")
+    }
+
+    fn testfile(name: &str) -> String {
+        format!("{}/../testfiles/{}", env!("CARGO_MANIFEST_DIR"), name)
+    }
+
+    /// A server configuration accepting the test files as inputs.
+    fn testfiles_config() -> ServerConfig {
+        ServerConfig {
+            root: Some(testfile("").into()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn server_routes() {
+        let (_, addr) = start_server("routes", testfiles_config());
+        let job = |metric: &str| {
+            serde_json::json!({
+                "reference": testfile("yuv420p8_input.y4m"),
+                "distorted": testfile("yuv420p8_output.y4m"),
+                "metric": metric,
+            })
+            .to_string()
+        };
+
+        assert_eq!(send(addr, "GET", "/", b"").0, 404);
+        assert_eq!(send(addr, "GET", "/jobs/0/report/more", b"").0, 404);
+        assert_eq!(send(addr, "DELETE", "/jobs", b"").0, 405);
+        assert_eq!(send(addr, "GET", "/uploads", b"").0, 405);
+        assert_eq!(
+            send(addr, "GET", "/jobs?state=done", b""),
+            (200, serde_json::json!([]))
+        );
+        assert_eq!(send(addr, "GET", "/jobs/0", b"").0, 404);
+        assert_eq!(send(addr, "GET", "/jobs/first", b"").0, 404);
+        assert_eq!(send(addr, "GET", "/jobs/0/report", b"").0, 404);
+
+        let (status, body) = send(addr, "POST", "/jobs", b"{\"reference\": 1}");
+        assert_eq!(status, 400);
+        assert!(body["error"].as_str().unwrap().starts_with("Invalid job"));
+        // Missing inputs and inputs outside the root cannot be told apart
+        let missing = serde_json::json!({
+            "reference": testfile("missing.y4m"),
+            "distorted": testfile("yuv420p8_output.y4m"),
+        });
+        let (status, body) = send(addr, "POST", "/jobs", missing.to_string().as_bytes());
+        assert_eq!(status, 403);
+        assert_eq!(
+            body["error"],
+            format!("{} is not an allowed input", testfile("missing.y4m"))
+        );
+        let outside = format!("{}/Cargo.toml", env!("CARGO_MANIFEST_DIR"));
+        let forbidden = serde_json::json!({
+            "reference": testfile("yuv420p8_input.y4m"),
+            "distorted": outside,
+        });
+        let (status, body) = send(addr, "POST", "/jobs", forbidden.to_string().as_bytes());
+        assert_eq!(status, 403);
+        assert_eq!(
+            body["error"],
+            format!("{} is not an allowed input", outside)
+        );
+        assert_eq!(
+            send(addr, "POST", "/jobs", job("brisque").as_bytes()).0,
+            400
+        );
+        let (status, body) = send(addr, "POST", "/jobs", job("vmaf").as_bytes());
+        assert_eq!(status, 400);
+        assert_eq!(body["error"], "VMAF cannot be computed without a model");
+        // Nothing was queued
+        assert_eq!(send(addr, "GET", "/jobs", b"").1, serde_json::json!([]));
+
+        // Without a root, only uploads are accepted
+        let (_, addr) = start_server("root", ServerConfig::default());
+        assert_eq!(send(addr, "POST", "/jobs", job("psnr").as_bytes()).0, 403);
+    }
+
+    #[test]
+    fn server_request_parsing() {
+        let (_, addr) = start_server("parsing", ServerConfig::default());
+
+        assert!(send_raw(addr, b"GARBAGE\r\n\r\n").is_none());
+        assert!(send_raw(addr, b"GET /jobs HTTP/1.1\r\nNo colon\r\n\r\n").is_none());
+        assert!(send_raw(addr, b"GET /jobs HTTP/1.1\r\nContent-Length: many\r\n\r\n").is_none());
+        let long = format!("GET /{} HTTP/1.1\r\n\r\n", "a".repeat(10000));
+        assert!(send_raw(addr, long.as_bytes()).is_none());
+        let many = format!(
+            "GET /jobs HTTP/1.1\r\n{}\r\n",
+            "X-Header: 1\r\n".repeat(101)
+        );
+        assert!(send_raw(addr, many.as_bytes()).is_none());
+
+        // Header names are case-insensitive, and the body is read
+        let (status, body) =
+            send_raw(addr, b"POST /jobs HTTP/1.1\r\ncontent-length: 2\r\n\r\n{}").unwrap();
+        assert_eq!(status, 400);
+        assert!(body["error"].as_str().unwrap().contains("missing field"));
+        // Job bodies must be small, and are refused before being read
+        let (status, _) = send_raw(
+            addr,
+            b"POST /jobs HTTP/1.1\r\nContent-Length: 2000000\r\n\r\n",
+        )
+        .unwrap();
+        assert_eq!(status, 413);
+    }
+
+    /// Polls the job `id` until it is finished, and returns its status.
+    fn wait_for_job(addr: SocketAddr, id: u64) -> Value {
+        let start = Instant::now();
+        loop {
+            let (status, job) = send(addr, "GET", &format!("/jobs/{}", id), b"");
+            assert_eq!(status, 200);
+            assert_eq!(job["id"], id);
+            if job["state"] == "done" || job["state"] == "failed" {
+                return job;
+            }
+            assert!(start.elapsed() < Duration::from_secs(60), "{}", job);
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    #[test]
+    fn server_job_lifecycle() {
+        let retention = Duration::from_secs(2);
+        let (server, addr) = start_server(
+            "lifecycle",
+            ServerConfig {
+                retention,
+                settings: MetricSettings {
+                    plane_weights: PlaneWeights::Luma,
+                    ..Default::default()
+                },
+                ..testfiles_config()
+            },
+        );
+
+        let distorted = std::fs::read(testfile("yuv420p8_output.y4m")).unwrap();
+        let (status, upload) = send(addr, "POST", "/uploads", &distorted);
+        assert_eq!(status, 201);
+        let upload = upload["path"].as_str().unwrap().to_owned();
+        assert_eq!(std::fs::read(&upload).unwrap(), distorted);
+
+        let job = serde_json::json!({
+            "reference": testfile("yuv420p8_input.y4m"),
+            "distorted": upload,
+            "metric": "psnr",
+        });
+        assert_eq!(
+            send(addr, "POST", "/jobs", job.to_string().as_bytes()),
+            (202, serde_json::json!({ "id": 0 }))
+        );
+        let (status, garbage) = send(addr, "POST", "/uploads", b"not a video");
+        assert_eq!(status, 201);
+        let failing = serde_json::json!({
+            "reference": garbage["path"],
+            "distorted": upload,
+        });
+        assert_eq!(
+            send(addr, "POST", "/jobs", failing.to_string().as_bytes()),
+            (202, serde_json::json!({ "id": 1 }))
+        );
+
+        let done = wait_for_job(addr, 0);
+        assert_eq!(done["state"], "done");
+        assert_eq!(done["distorted"], upload);
+        let (status, report) = send(addr, "GET", "/jobs/0/report", b"");
+        assert_eq!(status, 200);
+        let psnr = &report["comparisons"][0]["psnr"];
+        let mut dec1 = get_decoder(testfile("yuv420p8_input.y4m")).unwrap();
+        let mut dec2 = get_decoder(testfile("yuv420p8_output.y4m")).unwrap();
+        let expected = calculate_video_psnr(&mut dec1, &mut dec2, None, |_| ()).unwrap();
+        assert_metric_eq(expected.y, psnr["y"].as_f64().unwrap());
+        // The plane weights of the server are used
+        assert_eq!(psnr["avg"], psnr["y"]);
+        assert!(report["comparisons"][0]["ssim"].is_null());
+
+        let failed = wait_for_job(addr, 1);
+        assert_eq!(failed["state"], "failed");
+        let (status, body) = send(addr, "GET", "/jobs/1/report", b"");
+        assert_eq!(status, 422);
+        assert_eq!(body["error"], failed["error"]);
+        let (status, jobs) = send(addr, "GET", "/jobs", b"");
+        assert_eq!(status, 200);
+        assert_eq!(jobs.as_array().unwrap().len(), 2);
+
+        // Finished jobs and their uploads are kept for the retention period
+        server.expire();
+        assert_eq!(send(addr, "GET", "/jobs/0/report", b"").0, 200);
+        assert!(Path::new(&upload).exists());
+        thread::sleep(retention);
+        server.expire();
+        assert_eq!(send(addr, "GET", "/jobs/0", b"").0, 404);
+        assert_eq!(send(addr, "GET", "/jobs", b"").1, serde_json::json!([]));
+        assert!(!Path::new(&upload).exists());
+        assert_eq!(
+            send(addr, "POST", "/jobs", failing.to_string().as_bytes()).0,
+            403
+        );
+        // The ids of expired jobs are not reused
+        let job = serde_json::json!({
+            "reference": testfile("yuv420p8_input.y4m"),
+            "distorted": testfile("yuv420p8_output.y4m"),
+            "metric": "psnr",
+        });
+        assert_eq!(
+            send(addr, "POST", "/jobs", job.to_string().as_bytes()),
+            (202, serde_json::json!({ "id": 2 }))
+        );
+    }
+
+    #[test]
+    fn server_limits() {
+        let timeout = Duration::from_secs(2);
+        let (_, addr) = start_server(
+            "limits",
+            ServerConfig {
+                max_connections: 1,
+                max_queued: 1,
+                max_upload: 1000,
+                max_stored: 1500,
+                timeout,
+                ..testfiles_config()
+            },
+        );
+
+        // One job runs and one waits, and the others are refused
+        let job = serde_json::json!({
+            "reference": testfile("yuv420p8_input.y4m"),
+            "distorted": testfile("yuv420p8_output.y4m"),
+        })
+        .to_string();
+        let mut queued = 0;
+        for _ in 0..4 {
+            match send(addr, "POST", "/jobs", job.as_bytes()) {
+                (202, _) => queued += 1,
+                (status, body) => {
+                    assert_eq!(status, 503);
+                    assert_eq!(body["error"], "Too many jobs are queued");
+                }
+            }
+        }
+        assert!((1..=2).contains(&queued));
+
+        let (status, body) = send(addr, "POST", "/uploads", b"");
+        assert_eq!(status, 411);
+        assert_eq!(body["error"], "Uploads need a Content-Length and a body");
+        let (status, _) = send_raw(addr, b"POST /uploads HTTP/1.1\r\n\r\n").unwrap();
+        assert_eq!(status, 411);
+
+        let (status, body) = send_raw(
+            addr,
+            b"POST /uploads HTTP/1.1\r\nContent-Length: 1001\r\n\r\n",
+        )
+        .unwrap();
+        assert_eq!(status, 413);
+        assert_eq!(body["error"], "Uploads are limited to 1000 bytes");
+        assert_eq!(send(addr, "POST", "/uploads", &[0; 1000]).0, 201);
+        let (status, body) = send(addr, "POST", "/uploads", &[0; 1000]);
+        assert_eq!(status, 507);
+        assert_eq!(
+            body["error"],
+            "The uploads stored are limited to 1500 bytes"
+        );
+
+        // A client that stops sending holds the only connection until the
+        // timeout, and the others are refused meanwhile. The connection of the
+        // upload may not be released yet, so the client retries until it is
+        // served rather than refused.
+        let start = Instant::now();
+        let mut idle = loop {
+            let mut idle = TcpStream::connect(addr).unwrap();
+            idle.write_all(b"GET /jobs HTTP/1.1\r\n").unwrap();
+            idle.set_read_timeout(Some(timeout / 10)).unwrap();
+            if idle.read(&mut [0; 1]).is_err() {
+                idle.set_read_timeout(None).unwrap();
+                break idle;
+            }
+            thread::sleep(Duration::from_millis(20));
+        };
+        let (status, body) = send_raw(addr, b"").unwrap();
+        assert_eq!(status, 503);
+        assert_eq!(body["error"], "Too many connections");
+        let mut response = Vec::new();
+        idle.read_to_end(&mut response).unwrap();
+        assert!(response.is_empty());
+        assert!(start.elapsed() < timeout * 4);
+
+        let start = Instant::now();
+        loop {
+            let (status, _) = send(addr, "GET", "/jobs", b"");
+            if status == 200 {
+                break;
+            }
+            assert_eq!(status, 503);
+            assert!(start.elapsed() < Duration::from_secs(5));
+            thread::sleep(Duration::from_millis(20));
+        }
     }
 }
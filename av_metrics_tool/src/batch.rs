@@ -0,0 +1,144 @@
+//! Reads the comparisons listed with `--batch-file`.
+//!
+//! Each line lists the base input and the alternate input of a comparison,
+//! and optionally a label for it, separated by commas:
+//!
+//! ```text
+//! source.y4m,crf20.y4m,crf 20
+//! source.y4m,crf30.y4m,crf 30
+//! other.y4m,other-crf20.y4m
+//! ```
+//!
+//! Blank lines and lines starting with `#` are skipped. The label may contain
+//! commas, the filenames cannot. Relative filenames are relative to the working
+//! directory, not to the batch file.
+
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+/// One comparison of a batch.
+#[derive(Debug, Clone)]
+pub struct BatchEntry {
+    /// The base input
+    pub reference: PathBuf,
+    /// The alternate input compared with the base input
+    pub distorted: PathBuf,
+    /// The label reported with the scores
+    pub label: Option<String>,
+}
+
+/// Reads the comparisons listed in the file at `path`, or on stdin if the
+/// path is `-`.
+pub fn read_batch_file(path: &Path) -> Result<Vec<BatchEntry>, String> {
+    let text = if path == Path::new("-") {
+        let mut text = String::new();
+        io::stdin()
+            .read_to_string(&mut text)
+            .map_err(|err| format!("Failed to read the batch from stdin: {}", err))?;
+        text
+    } else {
+        fs::read_to_string(path)
+            .map_err(|err| format!("Failed to read {}: {}", path.display(), err))?
+    };
+    parse_batch(&text).map_err(|err| format!("{}: {}", path.display(), err))
+}
+
+fn parse_batch(text: &str) -> Result<Vec<BatchEntry>, String> {
+    let entries = text
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.starts_with('#'))
+        .map(|(number, line)| {
+            let mut fields = line.splitn(3, ',').map(str::trim);
+            let reference = fields.next().filter(|field| !field.is_empty());
+            let distorted = fields.next().filter(|field| !field.is_empty());
+            let (Some(reference), Some(distorted)) = (reference, distorted) else {
+                return Err(format!(
+                    "Expected reference,distorted[,label] on line {}",
+                    number + 1
+                ));
+            };
+            Ok(BatchEntry {
+                reference: PathBuf::from(reference),
+                distorted: PathBuf::from(distorted),
+                label: fields
+                    .next()
+                    .filter(|label| !label.is_empty())
+                    .map(str::to_owned),
+            })
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    if entries.is_empty() {
+        return Err("The batch lists no comparisons".to_owned());
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_batch(name: &str, text: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "av-metrics-batch-{}-{}.csv",
+            std::process::id(),
+            name
+        ));
+        fs::write(&path, text).unwrap();
+        path
+    }
+
+    #[test]
+    fn comments_and_blank_lines() {
+        let path = write_batch(
+            "comments",
+            "# reference,distorted,label\n\nsource.y4m, crf20.y4m, crf 20, slow\n   \n\
+             other.y4m,other-crf20.y4m,\n",
+        );
+        let entries = read_batch_file(&path).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].reference, Path::new("source.y4m"));
+        assert_eq!(entries[0].distorted, Path::new("crf20.y4m"));
+        assert_eq!(entries[0].label.as_deref(), Some("crf 20, slow"));
+        assert_eq!(entries[1].label, None);
+
+        let path = write_batch("only-comments", "# nothing\n\n");
+        assert_eq!(
+            read_batch_file(&path).unwrap_err(),
+            format!("{}: The batch lists no comparisons", path.display())
+        );
+    }
+
+    #[test]
+    fn relative_paths() {
+        let path = write_batch("relative", "../source.y4m,encodes/crf20.y4m\n");
+        let entries = read_batch_file(&path).unwrap();
+        // Kept as given, to be opened from the working directory
+        assert_eq!(entries[0].reference, Path::new("../source.y4m"));
+        assert_eq!(entries[0].distorted, Path::new("encodes/crf20.y4m"));
+    }
+
+    #[test]
+    fn malformed_lines() {
+        let path = write_batch(
+            "malformed",
+            "# header\nsource.y4m,crf20.y4m\n\nsource.y4m\n",
+        );
+        assert_eq!(
+            read_batch_file(&path).unwrap_err(),
+            format!(
+                "{}: Expected reference,distorted[,label] on line 4",
+                path.display()
+            )
+        );
+        let path = write_batch("empty-field", "source.y4m, ,label\n");
+        assert!(read_batch_file(&path)
+            .unwrap_err()
+            .ends_with("Expected reference,distorted[,label] on line 1"));
+        let missing = Path::new("/nonexistent/batch.csv");
+        assert!(read_batch_file(missing)
+            .unwrap_err()
+            .starts_with("Failed to read /nonexistent/batch.csv"));
+    }
+}
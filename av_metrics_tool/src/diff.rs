@@ -0,0 +1,219 @@
+//! Compares two reports exported with `--export-json`.
+//!
+//! Comparisons are matched by filename, and the average score of every metric
+//! computed in both reports is printed with its change. A score is considered
+//! unchanged when it moved by no more than the tolerance, and regressed when it
+//! dropped by more, since higher is better for every average score.
+
+use crate::failure::{CliError, ErrorKind};
+use crate::METRICS;
+use console::style;
+use serde_json::Value;
+use std::path::Path;
+
+/// Prints the changes from the report at `old` to the report at `new`, and
+/// returns an error if any score dropped by more than `tolerance`.
+pub fn run(old: &Path, new: &Path, tolerance: f64) -> Result<(), CliError> {
+    let old_report = read_report(old)?;
+    let new_report = read_report(new)?;
+    let old_comparisons = comparisons(&old_report, old)?;
+    let new_comparisons = comparisons(&new_report, new)?;
+
+    let mut regressions = 0;
+    for new_cmp in new_comparisons.iter() {
+        let filename = new_cmp["filename"].as_str().unwrap_or_default();
+        let Some(old_cmp) = old_comparisons
+            .iter()
+            .find(|cmp| cmp["filename"].as_str() == Some(filename))
+        else {
+            println!(
+                "\n    {} {}",
+                style(filename).italic().cyan(),
+                style(format!("is not in {}", old.display())).dim()
+            );
+            continue;
+        };
+        println!("\n    {}:", style(filename).italic().cyan());
+        for metric in METRICS {
+            let (Some(old_value), Some(new_value)) =
+                (average(old_cmp, metric), average(new_cmp, metric))
+            else {
+                continue;
+            };
+            let delta = new_value - old_value;
+            let change = format!("{:+.4}", delta);
+            let change = if delta.abs() <= tolerance {
+                style(change).dim()
+            } else if delta > 0. {
+                style(change).green()
+            } else {
+                regressions += 1;
+                style(change).red()
+            };
+            println!(
+                "     {:<10} {:>10.4} →  {:<10.4} ({})",
+                style(metric).cyan(),
+                old_value,
+                new_value,
+                change
+            );
+        }
+    }
+    for old_cmp in old_comparisons.iter() {
+        let filename = old_cmp["filename"].as_str().unwrap_or_default();
+        if !new_comparisons
+            .iter()
+            .any(|cmp| cmp["filename"].as_str() == Some(filename))
+        {
+            println!(
+                "\n    {} {}",
+                style(filename).italic().cyan(),
+                style(format!("is not in {}", new.display())).dim()
+            );
+        }
+    }
+
+    if regressions > 0 {
+        return Err(CliError::new(
+            ErrorKind::Threshold,
+            format!(
+                "{} score(s) dropped by more than {}",
+                regressions, tolerance
+            ),
+        ));
+    }
+    Ok(())
+}
+
+fn read_report(path: &Path) -> Result<Value, CliError> {
+    let file = std::fs::File::open(path)
+        .map_err(|err| CliError::input(format!("Failed to open {}: {}", path.display(), err)))?;
+    serde_json::from_reader(std::io::BufReader::new(file))
+        .map_err(|err| CliError::input(format!("Failed to parse {}: {}", path.display(), err)))
+}
+
+fn comparisons<'a>(report: &'a Value, path: &Path) -> Result<&'a Vec<Value>, CliError> {
+    report["comparisons"].as_array().ok_or_else(|| {
+        CliError::input(format!(
+            "{} is not a report exported as JSON",
+            path.display()
+        ))
+    })
+}
+
+/// The average score of a metric in an exported comparison, by its command
+/// line name, matching `MetricsResults::average`.
+fn average(cmp: &Value, metric: &str) -> Option<f64> {
+    let value = match metric {
+        "psnr" => &cmp["psnr"]["avg"],
+        "apsnr" => &cmp["apsnr"]["avg"],
+        "psnrhvs" => &cmp["psnr_hvs"]["avg"],
+        "ssim" => &cmp["ssim"]["avg"],
+        "msssim" => &cmp["msssim"]["avg"],
+        "ciede2000" => &cmp["ciede2000"],
+        "vmaf" => &cmp["vmaf"],
+        "peaq" => &cmp["peaq"]["odg"],
+        "spectral" => &cmp["spectral"]["segmental_snr"],
+        _ => return None,
+    };
+    value.as_f64()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::path::PathBuf;
+
+    /// Writes a report with the given comparisons to a file named after
+    /// `name`, and returns its path.
+    fn write_report(name: &str, comparisons: Value) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "av-metrics-diff-{}-{}.json",
+            std::process::id(),
+            name
+        ));
+        let report = json!({ "base": "source.y4m", "comparisons": comparisons });
+        std::fs::write(&path, report.to_string()).unwrap();
+        path
+    }
+
+    fn comparison(filename: &str, psnr: f64, ciede2000: f64) -> Value {
+        json!({
+            "filename": filename,
+            "psnr": { "y": psnr, "u": psnr, "v": psnr, "avg": psnr },
+            "ciede2000": ciede2000,
+        })
+    }
+
+    #[test]
+    fn matching_reports() {
+        let old = write_report("matching-old", json!([comparison("a.y4m", 40.0, 30.0)]));
+        let new = write_report("matching-new", json!([comparison("a.y4m", 40.0, 30.0)]));
+        assert!(run(&old, &new, 0.0).is_ok());
+        // Improvements are not regressions
+        let better = write_report("matching-better", json!([comparison("a.y4m", 41.0, 31.0)]));
+        assert!(run(&old, &better, 0.0).is_ok());
+    }
+
+    #[test]
+    fn mismatched_reports() {
+        let old = write_report(
+            "mismatched-old",
+            json!([
+                comparison("a.y4m", 40.0, 30.0),
+                comparison("b.y4m", 40.0, 30.0)
+            ]),
+        );
+        let new = write_report(
+            "mismatched-new",
+            json!([
+                comparison("a.y4m", 39.0, 29.0),
+                comparison("c.y4m", 20.0, 10.0)
+            ]),
+        );
+        let err = run(&old, &new, 0.0).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Threshold);
+        assert_eq!(err.message, "2 score(s) dropped by more than 0");
+        // Comparisons in only one of the reports are not compared
+        let only_new = write_report("mismatched-only", json!([comparison("c.y4m", 20.0, 10.0)]));
+        assert!(run(&old, &only_new, 0.0).is_ok());
+    }
+
+    #[test]
+    fn missing_metrics() {
+        let old = write_report("missing-old", json!([comparison("a.y4m", 40.0, 30.0)]));
+        let new = write_report(
+            "missing-new",
+            json!([{ "filename": "a.y4m", "ciede2000": 30.0 }]),
+        );
+        // PSNR is only in the old report, so its drop is not a regression
+        assert!(run(&old, &new, 0.0).is_ok());
+        let dropped = write_report(
+            "missing-dropped",
+            json!([{ "filename": "a.y4m", "ciede2000": 20.0 }]),
+        );
+        assert_eq!(
+            run(&old, &dropped, 0.0).unwrap_err().message,
+            "1 score(s) dropped by more than 0"
+        );
+
+        let not_report = write_report("missing-comparisons", json!(null));
+        let err = run(&old, &not_report, 0.0).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Input);
+        assert!(err.message.ends_with("is not a report exported as JSON"));
+        let err = run(&old, Path::new("/nonexistent/report.json"), 0.0).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Input);
+    }
+
+    #[test]
+    fn tolerance() {
+        let old = write_report("tolerance-old", json!([comparison("a.y4m", 40.0, 30.0)]));
+        let new = write_report("tolerance-new", json!([comparison("a.y4m", 39.5, 30.0)]));
+        assert!(run(&old, &new, 0.5).is_ok());
+        assert!(run(&old, &new, 1.0).is_ok());
+        let err = run(&old, &new, 0.25).unwrap_err();
+        assert_eq!(err.message, "1 score(s) dropped by more than 0.25");
+        assert_eq!(err.code, 6);
+    }
+}
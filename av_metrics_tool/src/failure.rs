@@ -0,0 +1,140 @@
+//! Errors of the tool, grouped into categories that each exit with their own
+//! code, so that scripts and container pipelines can tell them apart.
+
+use av_metrics_decoders::DecoderError;
+use serde::Serialize;
+use std::fmt;
+
+/// The category of an error, which decides the exit code of the tool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    /// An unexpected failure, such as a crash while computing a metric
+    Internal,
+    /// The command line is invalid
+    Usage,
+    /// An input or the VMAF model cannot be opened, read or decoded
+    Input,
+    /// The inputs cannot be compared, because their formats or lengths differ
+    Mismatch,
+    /// A report, database or stream cannot be written
+    Output,
+    /// A score is below a `--fail-below` threshold, or dropped by more than
+    /// the tolerance of `diff`
+    Threshold,
+    /// `--verify-lossless` found a difference between the inputs
+    NotLossless,
+}
+
+impl ErrorKind {
+    /// The exit code of the tool for errors of this kind.
+    pub fn exit_code(self) -> u8 {
+        match self {
+            ErrorKind::Internal => 1,
+            ErrorKind::Usage => 2,
+            ErrorKind::Input => 3,
+            ErrorKind::Mismatch => 4,
+            ErrorKind::Output => 5,
+            ErrorKind::Threshold => 6,
+            ErrorKind::NotLossless => 7,
+        }
+    }
+}
+
+/// An error of the tool, with the category it is reported under.
+#[derive(Serialize)]
+pub struct CliError {
+    pub kind: ErrorKind,
+    pub code: u8,
+    pub message: String,
+    /// Whether the error was already printed in the JSON document of
+    /// `--json-only`, along with the report
+    #[serde(skip)]
+    pub printed: bool,
+}
+
+impl CliError {
+    pub fn new<M: fmt::Display>(kind: ErrorKind, message: M) -> Self {
+        CliError {
+            kind,
+            code: kind.exit_code(),
+            message: message.to_string(),
+            printed: false,
+        }
+    }
+
+    pub fn usage<M: fmt::Display>(message: M) -> Self {
+        CliError::new(ErrorKind::Usage, message)
+    }
+
+    pub fn input<M: fmt::Display>(message: M) -> Self {
+        CliError::new(ErrorKind::Input, message)
+    }
+
+    pub fn mismatch<M: fmt::Display>(message: M) -> Self {
+        CliError::new(ErrorKind::Mismatch, message)
+    }
+
+    pub fn output<M: fmt::Display>(message: M) -> Self {
+        CliError::new(ErrorKind::Output, message)
+    }
+}
+
+/// Errors that were not given a category are unexpected.
+impl From<String> for CliError {
+    fn from(message: String) -> Self {
+        CliError::new(ErrorKind::Internal, message)
+    }
+}
+
+/// Inputs that cannot be opened or decoded are input errors.
+impl From<DecoderError> for CliError {
+    fn from(err: DecoderError) -> Self {
+        CliError::input(err)
+    }
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The exit codes are documented in the README and relied on by scripts,
+    /// so they must not change.
+    #[test]
+    fn exit_codes() {
+        let codes = [
+            (ErrorKind::Internal, 1, "internal"),
+            (ErrorKind::Usage, 2, "usage"),
+            (ErrorKind::Input, 3, "input"),
+            (ErrorKind::Mismatch, 4, "mismatch"),
+            (ErrorKind::Output, 5, "output"),
+            (ErrorKind::Threshold, 6, "threshold"),
+            (ErrorKind::NotLossless, 7, "not_lossless"),
+        ];
+        for (kind, code, name) in codes {
+            assert_eq!(kind.exit_code(), code, "{:?}", kind);
+            let err = CliError::new(kind, "failed");
+            assert_eq!(err.code, code);
+            let json = serde_json::to_value(&err).unwrap();
+            assert_eq!(json["kind"], name);
+            assert_eq!(json["code"], code);
+        }
+
+        assert_eq!(CliError::usage("").code, 2);
+        assert_eq!(CliError::input("").code, 3);
+        assert_eq!(CliError::mismatch("").code, 4);
+        assert_eq!(CliError::output("").code, 5);
+        assert_eq!(CliError::from("crash".to_owned()).code, 1);
+        let decoder: CliError = DecoderError::Io {
+            reason: "broken".to_owned(),
+        }
+        .into();
+        assert_eq!(decoder.code, 3);
+    }
+}
@@ -1,38 +1,178 @@
 #![allow(clippy::upper_case_acronyms)]
 
-use av_metrics::video::decode::Decoder;
+#[cfg(any(feature = "wav", feature = "ffmpeg"))]
+use av_metrics::audio::decode::AudioDecoder;
+use av_metrics::audio::{peaq, spectral};
+use av_metrics::video::align::{self, Shift, Shifted, Skipped};
+use av_metrics::video::alpha::AlphaPlane;
+use av_metrics::video::decode::{self, Decoder, DetailsOverride, VideoDetails};
+use av_metrics::video::pairing::Paired;
+use av_metrics::video::scale::Downscaled;
 use av_metrics::video::*;
-#[cfg(feature = "ffmpeg")]
-use av_metrics_decoders::FfmpegDecoder;
+use av_metrics::PROGRESS_DONE;
+use av_metrics_decoders::DecoderError;
+#[cfg(all(feature = "wav", not(feature = "ffmpeg")))]
+use av_metrics_decoders::WavDecoder;
 #[cfg(not(feature = "ffmpeg"))]
 use av_metrics_decoders::Y4MDecoder;
-use clap::{Arg, Command};
+#[cfg(feature = "ffmpeg")]
+use av_metrics_decoders::{FfmpegAudioDecoder, FfmpegDecoder, FfmpegOptions};
+use av_metrics_report::dispatch::{
+    compute_video_metric, display_name, MetricSettings, VIDEO_METRICS,
+};
+use av_metrics_report::{
+    chroma_sampling_name, IgnoredFrames, MetricConfidence, MetricGops, MetricHistogram,
+    MetricRateControl, MetricTiming, MetricWorstFrames, MetricsResults, Report,
+};
+use clap::builder::RangedU64ValueParser;
+use clap::{value_parser, Arg, ArgAction, Command};
 use console::style;
-use indicatif::{ProgressBar, ProgressStyle};
+use indicatif::MultiProgress;
 use serde::Serialize;
 use std::error::Error;
 use std::fs::File;
 #[cfg(not(feature = "ffmpeg"))]
 use std::io::BufReader;
 use std::io::{BufWriter, Stdout, Write};
-use std::path::Path;
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+mod batch;
+mod diff;
+mod failure;
+mod per_frame;
+mod progress;
+mod qp_log;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+mod stream;
+mod tui;
+
+use batch::BatchEntry;
+use failure::{CliError, ErrorKind};
+use per_frame::FrameObserver;
+use progress::{Progress, ProgressMode};
+use stream::StreamSink;
+
+/// The metrics that can be selected on the command line.
+const METRICS: [&str; 10] = [
+    "psnr",
+    "apsnr",
+    "psnrhvs",
+    "ssim",
+    "msssim",
+    "ciede2000",
+    "vmaf",
+    "brisque",
+    "peaq",
+    "spectral",
+];
+
+/// The metrics that also score the alpha planes with `--alpha`.
+const ALPHA_METRICS: [&str; 4] = ["psnr", "apsnr", "ssim", "msssim"];
+
+fn main() -> ExitCode {
+    // Checked before parsing, so that invalid command lines are reported as
+    // JSON too
+    let json_only = std::env::args_os().any(|arg| arg == "--json-only");
+    if json_only {
+        // Panics are reported in the JSON document instead
+        panic::set_hook(Box::new(|_| ()));
+    }
+    let result = panic::catch_unwind(|| run(json_only)).unwrap_or_else(|payload| {
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|msg| msg.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "The tool panicked".to_owned());
+        Err(CliError::new(ErrorKind::Internal, message))
+    });
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            if !json_only {
+                eprintln!("Error: {}", err);
+            } else if !err.printed {
+                let _ = print_json_document(None, Some(&err));
+            }
+            ExitCode::from(err.code)
+        }
+    }
+}
+
+/// The single document printed to stdout with `--json-only`: the report, in
+/// the format of `--export-json`, and the error if the tool failed.
+#[derive(Serialize)]
+struct JsonDocument<'a> {
+    #[serde(flatten)]
+    report: Option<&'a Report<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<&'a CliError>,
+}
+
+fn print_json_document(report: Option<&Report>, error: Option<&CliError>) -> Result<(), CliError> {
+    let mut stdout = std::io::stdout().lock();
+    serde_json::to_writer(&mut stdout, &JsonDocument { report, error })
+        .map_err(CliError::output)?;
+    writeln!(stdout).map_err(CliError::output)
+}
 
-fn main() -> Result<(), String> {
+fn run(json_only: bool) -> Result<(), CliError> {
     let cli = Command::new("AV Metrics")
         .version(env!("CARGO_PKG_VERSION"))
         .author(env!("CARGO_PKG_AUTHORS"))
         .about(env!("CARGO_PKG_DESCRIPTION"))
+        .args_conflicts_with_subcommands(true)
+        .subcommand_negates_reqs(true)
+        .subcommand(
+            Command::new("diff")
+                .about("Compare the scores of two reports exported with --export-json")
+                .arg(
+                    Arg::new("OLD")
+                        .help("The earlier report")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf))
+                        .index(1),
+                )
+                .arg(
+                    Arg::new("NEW")
+                        .help("The later report")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf))
+                        .index(2),
+                )
+                .arg(
+                    Arg::new("TOLERANCE")
+                        .help(
+                            "Changes of at most this much are shown as unchanged; \
+                             exit with an error if a score drops by more",
+                        )
+                        .long("tolerance")
+                        .num_args(1)
+                        .value_parser(value_parser!(f64))
+                        .default_value("0"),
+                ),
+        )
         .arg(
             Arg::new("BASE")
                 .help("The base input file to compare--currently supports Y4M files")
-                .required(true)
+                .required_unless_present("BATCH")
+                .value_parser(value_parser!(PathBuf))
                 .index(1),
         )
         .arg(
             Arg::new("FILES")
-                .help("The alternate input files to compare with the base file")
-                .required(true)
+                .help(
+                    "The alternate input files to compare with the base file. \
+                     Not needed with --metric brisque, which scores every file on its own",
+                )
                 .num_args(1..)
+                .value_parser(value_parser!(PathBuf))
                 .index(2),
         )
         .arg(
@@ -40,13 +180,14 @@ fn main() -> Result<(), String> {
                 .help("Run only one metric, instead of the entire suite")
                 .long("metric")
                 .num_args(1)
-                .value_parser(["psnr", "apsnr", "psnrhvs", "ssim", "msssim", "ciede2000"]),
+                .value_parser(METRICS),
         )
         .arg(
             Arg::new("JSON")
                 .help("Output results as JSON--useful for piping to other programs")
                 .long("export-json")
                 .num_args(1)
+                .value_parser(value_parser!(PathBuf))
                 .value_name("FILE"),
         )
         .arg(
@@ -54,6 +195,7 @@ fn main() -> Result<(), String> {
                 .help("Output results as CSV")
                 .long("export-csv")
                 .num_args(1)
+                .value_parser(value_parser!(PathBuf))
                 .value_name("FILE"),
         )
         .arg(
@@ -61,6 +203,23 @@ fn main() -> Result<(), String> {
                 .help("Output results as Markdown")
                 .long("export-markdown")
                 .num_args(1)
+                .value_parser(value_parser!(PathBuf))
+                .value_name("FILE"),
+        )
+        .arg(
+            Arg::new("XML")
+                .help("Output results as XML")
+                .long("export-xml")
+                .num_args(1)
+                .value_parser(value_parser!(PathBuf))
+                .value_name("FILE"),
+        )
+        .arg(
+            Arg::new("YAML")
+                .help("Output results as YAML")
+                .long("export-yaml")
+                .num_args(1)
+                .value_parser(value_parser!(PathBuf))
                 .value_name("FILE"),
         )
         .arg(
@@ -68,214 +227,1441 @@ fn main() -> Result<(), String> {
                 .help("Output results to a file")
                 .long("export-file")
                 .num_args(1)
+                .value_parser(value_parser!(PathBuf))
                 .value_name("FILE"),
         )
+        .arg(
+            Arg::new("STREAM")
+                .help(
+                    "Stream per-frame results as newline-delimited JSON to a listener \
+                     at HOST:PORT or unix:PATH, as the frames are scored",
+                )
+                .long("stream")
+                .num_args(1)
+                .value_name("ADDR"),
+        )
+        .arg(
+            Arg::new("TUI")
+                .help(
+                    "Show a live sparkline of the per-frame scores of one metric, \
+                     with the speed and remaining time",
+                )
+                .long("tui")
+                .num_args(0)
+                .conflicts_with_all(["QUIET", "JSON_ONLY", "PROGRESS", "WATCH", "VERIFY_LOSSLESS"]),
+        )
         .arg(
             Arg::new("QUIET")
                 .help("Do not output to stdout")
                 .long("quiet")
                 .num_args(0),
         )
+        .arg(
+            Arg::new("JSON_ONLY")
+                .help(
+                    "Print nothing but a single JSON document with the report, and the \
+                     error if any, to stdout. The exit code tells the kind of error",
+                )
+                .long("json-only")
+                .num_args(0)
+                .conflicts_with_all(["QUIET", "WATCH", "VERIFY_LOSSLESS"]),
+        )
+        .arg(
+            Arg::new("PROGRESS")
+                .help(
+                    "How to report progress: a progress bar, or JSON records on \
+                     stderr for programs running this tool",
+                )
+                .long("progress")
+                .num_args(1)
+                .value_parser(["bar", "json"]),
+        )
         .arg(
             Arg::new("FRAMES")
                 .help("Count the number of frames in a file")
                 .long("frames")
                 .num_args(0),
         )
-        .get_matches();
-    let base = cli.get_one::<String>("BASE").unwrap();
-    let inputs = cli.get_many::<String>("FILES").unwrap();
+        .arg(
+            Arg::new("FAIL_BELOW")
+                .help(
+                    "Exit with an error if the average score of a metric is below a threshold, \
+                     e.g. psnr=35. May be given multiple times",
+                )
+                .long("fail-below")
+                .num_args(1)
+                .value_name("METRIC=VALUE")
+                .value_parser(Threshold::parse)
+                .action(ArgAction::Append),
+        )
+        .arg(
+            Arg::new("DOWNSCALE")
+                .help(
+                    "Compute approximate metrics on inputs downscaled by an integer factor, \
+                     for a quick preview",
+                )
+                .long("downscale")
+                .num_args(1)
+                .value_name("FACTOR")
+                .value_parser(RangedU64ValueParser::<usize>::new().range(1..)),
+        )
+        .arg(
+            Arg::new("ALIGN")
+                .help(
+                    "Detect a constant frame offset between the inputs by comparing up to N \
+                     frames at offsets of up to N frames, and skip the leading frames of the \
+                     input that is ahead",
+                )
+                .long("align")
+                .num_args(1)
+                .value_name("N")
+                .value_parser(RangedU64ValueParser::<usize>::new().range(1..)),
+        )
+        .arg(
+            Arg::new("MATCH_TIMESTAMPS")
+                .help(
+                    "Pair each frame of the base input with the frame of the alternate input \
+                     whose timestamp is the nearest, if they are at most MS milliseconds apart, \
+                     instead of pairing frames in order. Use for inputs with a variable or \
+                     different frame rate",
+                )
+                .long("match-timestamps")
+                .num_args(1)
+                .value_name("MS")
+                .value_parser(value_parser!(f64))
+                .conflicts_with("ALIGN"),
+        )
+        .arg(
+            Arg::new("SHIFT")
+                .help(
+                    "Detect a global translation of up to MAX pixels between the first frames \
+                     of the inputs, and move the alternate input back before scoring",
+                )
+                .long("detect-shift")
+                .num_args(1)
+                .value_name("MAX")
+                .value_parser(RangedU64ValueParser::<usize>::new().range(1..)),
+        )
+        .arg(
+            Arg::new("GOP")
+                .help(
+                    "Also report the mean and minimum score of each GOP of the alternate \
+                     inputs, which needs a decoder that reports keyframes, such as FFmpeg",
+                )
+                .long("gop")
+                .num_args(0),
+        )
+        .arg(
+            Arg::new("WORST")
+                .help("Also report the N frames with the lowest score of each metric")
+                .long("worst")
+                .num_args(1)
+                .value_name("N")
+                .value_parser(RangedU64ValueParser::<usize>::new().range(1..)),
+        )
+        .arg(
+            Arg::new("HISTOGRAM")
+                .help(
+                    "Also count the per-frame scores of each metric into BINS bins, \
+                     shown as a sparkline",
+                )
+                .long("histogram")
+                .num_args(1)
+                .value_name("BINS")
+                .value_parser(RangedU64ValueParser::<usize>::new().range(1..)),
+        )
+        .arg(
+            Arg::new("CONFIDENCE")
+                .help(
+                    "Also report a 95% confidence interval of the mean per-frame score of \
+                     each metric, by bootstrap resampling of the frames",
+                )
+                .long("confidence")
+                .num_args(0),
+        )
+        .arg(
+            Arg::new("QP_LOG")
+                .help(
+                    "Also fit the per-frame scores of each metric against the quantizers \
+                     and frame sizes in FILE, a CSV file with a qp, bits or bytes column \
+                     and a line per frame. Given once for each alternate input, in order",
+                )
+                .long("qp-log")
+                .num_args(1)
+                .value_name("FILE")
+                .value_parser(value_parser!(PathBuf))
+                .action(ArgAction::Append),
+        )
+        .arg(
+            Arg::new("TILES")
+                .help(
+                    "Also report the PSNR of each tile of a grid over the whole video, \
+                     to locate quality problems that stay in the same place",
+                )
+                .long("tiles")
+                .num_args(1)
+                .value_name("COLUMNSxROWS")
+                .value_parser(parse_tile_grid),
+        )
+        .arg(
+            Arg::new("BANDING")
+                .help(
+                    "Also report the share of each frame prone to banding, in blocks with low \
+                     gradients in the base input, and the PSNR in those blocks",
+                )
+                .long("banding")
+                .num_args(0),
+        )
+        .arg(
+            Arg::new("STRICT_LENGTH")
+                .help(
+                    "Exit with an error if the inputs have a different number of frames, \
+                     instead of comparing the frames they both have",
+                )
+                .long("strict-length")
+                .num_args(0),
+        )
+        .arg(
+            Arg::new("VMAF_MODEL")
+                .help(
+                    "Also compute VMAF, scoring the features with this libvmaf model file, \
+                     such as vmaf_v0.6.1.json",
+                )
+                .long("vmaf-model")
+                .num_args(1)
+                .value_parser(value_parser!(PathBuf)),
+        )
+        .arg(
+            Arg::new("BRISQUE_MODEL")
+                .help(
+                    "Also compute BRISQUE on each alternate input on its own, scoring \
+                     the features with this libsvm model file, such as allmodel from the \
+                     reference implementation",
+                )
+                .long("brisque-model")
+                .num_args(1)
+                .value_parser(value_parser!(PathBuf))
+                .requires("BRISQUE_RANGE"),
+        )
+        .arg(
+            Arg::new("BRISQUE_RANGE")
+                .help(
+                    "The feature ranges of the BRISQUE model, such as allrange from the \
+                     reference implementation",
+                )
+                .long("brisque-range")
+                .num_args(1)
+                .value_parser(value_parser!(PathBuf))
+                .requires("BRISQUE_MODEL"),
+        )
+        .arg(
+            Arg::new("ALPHA")
+                .help(
+                    "Also compare the alpha planes of inputs with transparency when computing \
+                     PSNR, APSNR, SSIM and MSSSIM",
+                )
+                .long("alpha")
+                .num_args(0),
+        )
+        .arg(
+            Arg::new("WEIGHTS")
+                .help(
+                    "How the planes are weighted in the averages of PSNR, APSNR, SSIM and \
+                     MSSSIM: by their number of samples, luma only, equally, or 6:1:1 as in the \
+                     common test conditions of JVET and AOM",
+                )
+                .long("weights")
+                .num_args(1)
+                .value_parser(["default", "luma", "equal", "ctc"]),
+        )
+        .arg(
+            Arg::new("PRECISION")
+                .help(
+                    "The number of decimal places of the scores in every output format. Text \
+                     output has 4 by default, the other formats full precision",
+                )
+                .long("precision")
+                .num_args(1)
+                .value_parser(value_parser!(usize)),
+        )
+        .arg(
+            Arg::new("DETERMINISTIC")
+                .help(
+                    "Make the results bit-identical across runs and thread counts, at a small \
+                     cost to the speed of CIEDE2000",
+                )
+                .long("deterministic")
+                .num_args(0),
+        )
+        .arg(
+            Arg::new("RANGE")
+                .help("Override the color range reported by the decoder for all inputs")
+                .long("range")
+                .num_args(1)
+                .value_parser(["limited", "full"]),
+        )
+        .arg(
+            Arg::new("MATRIX")
+                .help("Override the matrix coefficients reported by the decoder for all inputs")
+                .long("matrix")
+                .num_args(1)
+                .value_parser(["bt709", "bt601", "bt2020"]),
+        )
+        .arg(
+            Arg::new("CHROMA_LOC")
+                .help("Override the chroma sample position reported by the decoder for all inputs")
+                .long("chroma-loc")
+                .num_args(1)
+                .value_parser(["vertical", "colocated", "bilateral", "interpolated"]),
+        )
+        .arg(
+            Arg::new("WATCH")
+                .help("Recompute the metrics whenever an alternate input file changes")
+                .long("watch")
+                .num_args(0),
+        )
+        .arg(
+            Arg::new("VERIFY_LOSSLESS")
+                .help(
+                    "Only check that the inputs are bit-exact, reporting the first \
+                     differing sample, and exit with an error if they are not",
+                )
+                .long("verify-lossless")
+                .num_args(0)
+                .conflicts_with_all(["METRIC", "WATCH"]),
+        )
+        .arg(
+            Arg::new("JOBS")
+                .help(
+                    "Compare up to N alternate inputs at the same time, splitting \
+                     the threads between them",
+                )
+                .long("jobs")
+                .short('j')
+                .num_args(1)
+                .value_name("N")
+                .value_parser(value_parser!(usize))
+                .conflicts_with_all(["STREAM", "TUI", "WATCH", "VERIFY_LOSSLESS"]),
+        )
+        .arg(
+            Arg::new("BATCH")
+                .help(
+                    "Run the comparisons listed in FILE, or on stdin if FILE is -, one per line \
+                     as reference,distorted[,label], and report them together",
+                )
+                .long("batch-file")
+                .num_args(1)
+                .value_name("FILE")
+                .value_parser(value_parser!(PathBuf))
+                .conflicts_with_all(["BASE", "FILES", "WATCH", "VERIFY_LOSSLESS", "QP_LOG"]),
+        );
+    #[cfg(feature = "sqlite")]
+    let cli = cli.arg(
+        Arg::new("SQLITE")
+            .help("Append results to a SQLite database, creating it if needed")
+            .long("export-sqlite")
+            .num_args(1)
+            .value_parser(value_parser!(PathBuf))
+            .value_name("FILE"),
+    );
+    #[cfg(feature = "ffmpeg")]
+    let cli = cli
+        .arg(
+            Arg::new("HWACCEL")
+                .help(
+                    "Decode the inputs with a hardware device, such as vaapi, cuda or videotoolbox",
+                )
+                .long("hwaccel")
+                .num_args(1)
+                .value_name("DEVICE"),
+        )
+        .arg(
+            Arg::new("VIDEO_STREAM")
+                .help(
+                    "Decode the video stream at this index in every input, instead of the best one",
+                )
+                .long("video-stream")
+                .num_args(1)
+                .value_name("INDEX")
+                .value_parser(value_parser!(usize)),
+        )
+        .arg(
+            Arg::new("PROGRAM")
+                .help("Decode a video stream of the program with this id, in MPEG-TS inputs")
+                .long("program")
+                .num_args(1)
+                .value_name("ID")
+                .value_parser(value_parser!(i32)),
+        );
+    let cli = cli.try_get_matches().or_else(|err| {
+        if json_only && err.use_stderr() {
+            Err(CliError::usage(err))
+        } else {
+            // Help, version, or an error printed by clap itself
+            err.exit()
+        }
+    })?;
+    if let Some(("diff", args)) = cli.subcommand() {
+        return diff::run(
+            args.get_one::<PathBuf>("OLD").unwrap(),
+            args.get_one::<PathBuf>("NEW").unwrap(),
+            args.get_one::<f64>("TOLERANCE")
+                .copied()
+                .unwrap_or(0.)
+                .abs(),
+        );
+    }
+    let batch_file = cli.get_one::<PathBuf>("BATCH");
+    let batch = batch_file
+        .map(|path| batch::read_batch_file(path))
+        .transpose()
+        .map_err(CliError::input)?;
+    // The base input of the first comparison stands in for the base input of
+    // a batch wherever a single one is needed
+    let base = match batch.as_deref() {
+        Some([first, ..]) => &first.reference,
+        _ => cli.get_one::<PathBuf>("BASE").unwrap(),
+    };
+    let inputs = cli.get_many::<PathBuf>("FILES").unwrap_or_default();
     let mut writers = vec![];
-    if let Some(filename) = cli.get_one::<String>("FILE") {
+    if let Some(filename) = cli.get_one::<PathBuf>("FILE") {
         writers.push(OutputType::TEXT(BufWriter::new(
-            File::create(filename).map_err(|err| err.to_string())?,
+            File::create(filename).map_err(CliError::output)?,
         )));
     };
-    if let Some(filename) = cli.get_one::<String>("JSON") {
+    if let Some(filename) = cli.get_one::<PathBuf>("JSON") {
         writers.push(OutputType::JSON(BufWriter::new(
-            File::create(filename).map_err(|err| err.to_string())?,
+            File::create(filename).map_err(CliError::output)?,
         )));
     };
-    if let Some(filename) = cli.get_one::<String>("CSV") {
+    if let Some(filename) = cli.get_one::<PathBuf>("CSV") {
         writers.push(OutputType::CSV(BufWriter::new(
-            File::create(filename).map_err(|err| err.to_string())?,
+            File::create(filename).map_err(CliError::output)?,
         )));
     };
-    if let Some(filename) = cli.get_one::<String>("MARKDOWN") {
+    if let Some(filename) = cli.get_one::<PathBuf>("MARKDOWN") {
         writers.push(OutputType::Markdown(BufWriter::new(
-            File::create(filename).map_err(|err| err.to_string())?,
+            File::create(filename).map_err(CliError::output)?,
         )));
     };
-    if !cli.contains_id("QUIET") {
+    if let Some(filename) = cli.get_one::<PathBuf>("XML") {
+        writers.push(OutputType::XML(BufWriter::new(
+            File::create(filename).map_err(CliError::output)?,
+        )));
+    };
+    if let Some(filename) = cli.get_one::<PathBuf>("YAML") {
+        writers.push(OutputType::YAML(BufWriter::new(
+            File::create(filename).map_err(CliError::output)?,
+        )));
+    };
+    if !cli.get_flag("QUIET") && !json_only {
         writers.push(OutputType::Stdout(BufWriter::new(std::io::stdout())));
     }
 
+    let mut run_options = RunOptions {
+        metric: cli.get_one::<String>("METRIC").cloned(),
+        all_frames: cli.get_flag("FRAMES"),
+        overrides: DetailsOverride {
+            color_range: cli
+                .get_one::<String>("RANGE")
+                .map(|range| match range.as_str() {
+                    "full" => ColorRange::Full,
+                    _ => ColorRange::Limited,
+                }),
+            matrix_coefficients: cli.get_one::<String>("MATRIX").map(|matrix| {
+                match matrix.as_str() {
+                    "bt601" => MatrixCoefficients::Bt601,
+                    "bt2020" => MatrixCoefficients::Bt2020,
+                    _ => MatrixCoefficients::Bt709,
+                }
+            }),
+            chroma_sample_position: cli.get_one::<String>("CHROMA_LOC").map(|loc| {
+                match loc.as_str() {
+                    "vertical" => ChromaSamplePosition::Vertical,
+                    "bilateral" => ChromaSamplePosition::Bilateral,
+                    "interpolated" => ChromaSamplePosition::Interpolated,
+                    _ => ChromaSamplePosition::Colocated,
+                }
+            }),
+        },
+        #[cfg(feature = "ffmpeg")]
+        ffmpeg: FfmpegOptions {
+            hwaccel: cli.get_one::<String>("HWACCEL").cloned(),
+            stream_index: cli.get_one::<usize>("VIDEO_STREAM").copied(),
+            program: cli.get_one::<i32>("PROGRAM").copied(),
+        },
+        settings: MetricSettings {
+            plane_weights: match cli.get_one::<String>("WEIGHTS").map(String::as_str) {
+                Some("luma") => PlaneWeights::Luma,
+                Some("equal") => PlaneWeights::Equal,
+                Some("ctc") => PlaneWeights::Ctc,
+                _ => PlaneWeights::Default,
+            },
+            deterministic: cli.get_flag("DETERMINISTIC"),
+            vmaf_model: None,
+        },
+        precision: cli.get_one::<usize>("PRECISION").copied(),
+        brisque_model: None,
+    };
+
     let base_type = InputType::detect(base);
+    let progress = ProgressMode::new(
+        // The live view shows the speed in place of the progress bars
+        cli.get_flag("QUIET") || json_only || cli.get_flag("TUI"),
+        cli.get_one::<String>("PROGRESS").map(String::as_str),
+    );
 
     let metrics = cli.get_one::<String>("METRIC").map(String::as_str);
+    if let Some(path) = cli.get_one::<PathBuf>("VMAF_MODEL") {
+        let model = vmaf::VmafModel::load(path).map_err(|err| {
+            CliError::input(format!("Failed to load {}: {}", path.display(), err))
+        })?;
+        run_options.settings.vmaf_model = Some(model);
+    } else if metrics == Some("vmaf") {
+        return Err(CliError::usage(
+            "--metric vmaf needs a model given with --vmaf-model.",
+        ));
+    }
+    if let (Some(model), Some(range)) = (
+        cli.get_one::<PathBuf>("BRISQUE_MODEL"),
+        cli.get_one::<PathBuf>("BRISQUE_RANGE"),
+    ) {
+        let model = noref::brisque::BrisqueModel::load(model, range).map_err(|err| {
+            CliError::input(format!("Failed to load {}: {}", model.display(), err))
+        })?;
+        run_options.brisque_model = Some(model);
+    } else if metrics == Some("brisque") {
+        return Err(CliError::usage(
+            "--metric brisque needs a model given with --brisque-model and --brisque-range.",
+        ));
+    }
+    let run = &run_options;
+    if batch.is_none() && inputs.clone().next().is_none() && metrics != Some("brisque") {
+        return Err(CliError::usage(
+            "At least one alternate input is needed, except with --metric brisque.",
+        ));
+    }
 
-    let mut report = Report {
-        base,
-        ..Default::default()
+    let options = InputOptions {
+        downscale: cli.get_one::<usize>("DOWNSCALE").copied().unwrap_or(1),
+        alpha: cli.get_flag("ALPHA"),
+        alignment: Alignment::default(),
+        timestamp_tolerance: cli
+            .get_one::<f64>("MATCH_TIMESTAMPS")
+            .map(|ms| ms.abs() / 1000.),
     };
+    let align = cli.get_one::<usize>("ALIGN").copied();
+    let max_shift = cli.get_one::<usize>("SHIFT").copied();
 
-    for input in inputs {
-        let input_type = InputType::detect(input);
+    let base_name = batch_file.unwrap_or(base).to_string_lossy();
+    if cli.get_flag("VERIFY_LOSSLESS") {
+        if let InputType::Audio = base_type {
+            return Err(CliError::usage(
+                "--verify-lossless only supports video inputs.",
+            ));
+        }
+        return verify_lossless(base, inputs, progress, run);
+    }
+    if cli.get_flag("WATCH") {
+        if let InputType::Audio = base_type {
+            return Err(CliError::usage("--watch only supports video inputs."));
+        }
+        // Counting the frames would read every file again on each change
+        let run = &RunOptions {
+            all_frames: false,
+            ..run_options
+        };
+        return Ok(watch_video_metrics(
+            base,
+            &inputs.collect::<Vec<_>>(),
+            options,
+            align,
+            max_shift,
+            run,
+        )?);
+    }
 
-        match (base_type, input_type) {
-            (InputType::Video, InputType::Video) => {
-                report.comparisons.push(run_video_metrics(
-                    base,
-                    input,
-                    metrics,
-                    cli.contains_id("QUIET"),
-                    cli.contains_id("FRAMES"),
+    let stream = cli
+        .get_one::<String>("STREAM")
+        .map(|addr| StreamSink::connect(addr))
+        .transpose()
+        .map_err(CliError::output)?;
+
+    let tui = cli
+        .get_flag("TUI")
+        .then(|| tui::shown_metric(metrics))
+        .transpose()
+        .map_err(CliError::usage)?;
+    if tui.is_some() && !console::user_attended_stderr() {
+        return Err(CliError::usage("--tui needs a terminal."));
+    }
+
+    let mut report = Report::new(&base_name);
+    report.precision = run.precision;
+    if let (None, InputType::Video) = (&batch, base_type) {
+        report.base_stream_info = get_decoder(base, run)?.get_stream_info();
+    }
+
+    let aggregations = per_frame::Aggregations {
+        gops: cli.get_flag("GOP"),
+        worst: cli.get_one::<usize>("WORST").copied(),
+        histogram: cli.get_one::<usize>("HISTOGRAM").copied(),
+        confidence: cli.get_flag("CONFIDENCE"),
+        rate_control: cli.contains_id("QP_LOG"),
+        tiles: cli.get_one::<tiles::TileGrid>("TILES").copied(),
+        banding: cli.get_flag("BANDING"),
+    };
+    let qp_logs = match cli.get_many::<PathBuf>("QP_LOG") {
+        Some(logs) if logs.len() != inputs.len() => {
+            return Err(CliError::usage(
+                "--qp-log must be given once for each alternate input.",
+            ));
+        }
+        Some(logs) => inputs
+            .clone()
+            .zip(logs)
+            .map(|(input, log)| Ok((input.as_path(), qp_log::read_qp_log(log)?)))
+            .collect::<Result<Vec<_>, String>>()
+            .map_err(CliError::input)?,
+        None => Vec::new(),
+    };
+    let strict_length = cli.get_flag("STRICT_LENGTH");
+    let multi = MultiProgress::new();
+    let compare = |base: &Path,
+                   input: &Path,
+                   stream: Option<&StreamSink>|
+     -> Result<MetricsResults, CliError> {
+        if metrics == Some("brisque") {
+            // Every input is scored on its own, including the base
+            let InputType::Video = InputType::detect(input) else {
+                return Err(CliError::usage(
+                    "--metric brisque only supports video inputs.",
                 ));
+            };
+            return run_video_metrics(input, input, progress, options, run, &[], &multi);
+        }
+        match (InputType::detect(base), InputType::detect(input)) {
+            (InputType::Video, InputType::Video) => {
+                check_video_inputs(base, input, run)?;
+                let options = InputOptions {
+                    alignment: detect_alignment(base, input, align, max_shift, run)
+                        .map_err(CliError::input)?,
+                    ..options
+                };
+                let view = tui
+                    .map(|metric| {
+                        let total = total_frames(base, input, run).ok();
+                        tui::FrameView::new(input, metric, total).map(Arc::new)
+                    })
+                    .transpose()
+                    .map_err(CliError::output)?;
+                let frame_lines =
+                    stream.map(|stream| Arc::new(stream::FrameLines::new(stream.clone(), input)));
+                let collector = aggregations
+                    .any()
+                    .then(|| Arc::new(per_frame::FrameCollector::new(aggregations)));
+                let mut observers: Vec<Arc<dyn FrameObserver>> = Vec::new();
+                if let Some(frame_lines) = &frame_lines {
+                    observers.push(frame_lines.clone());
+                }
+                if let Some(collector) = &collector {
+                    observers.push(collector.clone());
+                }
+                if let Some(view) = &view {
+                    observers.push(view.clone());
+                }
+                let mut results =
+                    run_video_metrics(base, input, progress, options, run, &observers, &multi)?;
+                drop(observers);
+                if let Some(frame_lines) = frame_lines {
+                    frame_lines.finish().map_err(CliError::output)?;
+                }
+                if let Some(view) = view {
+                    view.finish().map_err(CliError::output)?;
+                }
+                if strict_length {
+                    if let Some(mismatch) = results.length_mismatch() {
+                        return Err(CliError::mismatch(format!(
+                            "{}: {}",
+                            input.to_string_lossy(),
+                            mismatch
+                        )));
+                    }
+                }
+                if let Some(collector) = collector {
+                    let side_data = qp_logs
+                        .iter()
+                        .find(|(path, _)| *path == input)
+                        .map(|(_, side_data)| side_data.as_slice())
+                        .unwrap_or_default();
+                    // The frame sinks holding the other references are gone
+                    // once the metrics are computed
+                    let aggregates = Arc::into_inner(collector)
+                        .expect("the frame sinks outlived the metrics")
+                        .finish(side_data)
+                        .map_err(|err| CliError::input(format!("{}: {}", input.display(), err)))?;
+                    results.gops = aggregates.gops;
+                    results.worst_frames = aggregates.worst_frames;
+                    results.histograms = aggregates.histograms;
+                    results.confidence_intervals = aggregates.confidence_intervals;
+                    results.rate_control = aggregates.rate_control;
+                    results.tiles = aggregates.tiles;
+                    results.banding = aggregates.banding;
+                }
+                if let Some(stream) = stream {
+                    stream.send_summary(&results).map_err(CliError::output)?;
+                }
+                Ok(results)
             }
             (InputType::Audio, InputType::Audio) => {
-                return Err("No audio metrics currently implemented, exiting.".to_owned());
+                if stream.is_some() {
+                    return Err(CliError::usage("--stream only supports video inputs."));
+                }
+                if tui.is_some() {
+                    return Err(CliError::usage("--tui only supports video inputs."));
+                }
+                run_audio_metrics(base, input, metrics, progress, &multi).map_err(CliError::input)
             }
             (InputType::Video, InputType::Audio) | (InputType::Audio, InputType::Video) => {
-                return Err("Incompatible input files.".to_owned());
+                Err(CliError::mismatch("Incompatible input files."))
             }
             (InputType::Unknown, _) | (_, InputType::Unknown) => {
-                return Err("Unsupported input format.".to_owned());
+                Err(CliError::input("Unsupported input format."))
             }
+        }
+    };
+
+    let entries = match batch.clone() {
+        Some(entries) => entries,
+        None => {
+            let mut inputs: Vec<&PathBuf> = inputs.collect();
+            if metrics == Some("brisque") {
+                inputs.insert(0, base);
+            }
+            inputs
+                .into_iter()
+                .map(|input| BatchEntry {
+                    reference: base.clone(),
+                    distorted: input.clone(),
+                    label: None,
+                })
+                .collect()
+        }
+    };
+    let compare_entry =
+        |entry: &BatchEntry, stream: Option<&StreamSink>| -> Result<MetricsResults, CliError> {
+            let mut results = compare(&entry.reference, &entry.distorted, stream)?;
+            if batch.is_some() {
+                results.base = Some(entry.reference.to_string_lossy().into_owned());
+            }
+            results.label = entry.label.clone();
+            Ok(results)
         };
+    let jobs = cli.get_one::<usize>("JOBS").copied().unwrap_or(1);
+    if jobs > 1 && entries.len() > 1 {
+        report.comparisons = run_jobs(&entries, jobs, |entry| compare_entry(entry, None))?;
+    } else {
+        for entry in entries.iter() {
+            let results = compare_entry(entry, stream.as_ref())?;
+            report.comparisons.push(results);
+        }
     }
 
     for writer in writers.iter_mut() {
-        report.print(writer)?;
+        print_report(&report, writer, run).map_err(CliError::output)?;
     }
 
-    Ok(())
+    #[cfg(feature = "sqlite")]
+    if let Some(filename) = cli.get_one::<PathBuf>("SQLITE") {
+        sqlite::append(filename, &report).map_err(CliError::output)?;
+    }
+
+    let mut outcome = Ok(());
+    if let Some(thresholds) = cli.get_many::<Threshold>("FAIL_BELOW") {
+        let failures = check_thresholds(&report, thresholds, run).map_err(CliError::usage)?;
+        if !failures.is_empty() {
+            if !json_only {
+                for failure in failures.iter() {
+                    eprintln!("{}", failure);
+                }
+            }
+            outcome = Err(threshold_error(&failures, json_only));
+        }
+    }
+
+    if json_only {
+        print_json_document(Some(&report), outcome.as_ref().err())?;
+        if let Err(err) = outcome.as_mut() {
+            err.printed = true;
+        }
+    }
+    outcome
+}
+
+/// A minimum average score for a metric, as given to `--fail-below`.
+#[derive(Debug, Clone)]
+struct Threshold {
+    metric: String,
+    value: f64,
+}
+
+impl Threshold {
+    fn parse(arg: &str) -> Result<Self, String> {
+        let (metric, value) = arg
+            .split_once('=')
+            .ok_or_else(|| format!("Expected METRIC=VALUE, got '{}'", arg))?;
+        let metric = metric.trim().to_lowercase();
+        if !METRICS.contains(&metric.as_str()) {
+            return Err(format!(
+                "Unknown metric '{}', expected one of {}",
+                metric,
+                METRICS.join(", ")
+            ));
+        }
+        let value = value
+            .trim()
+            .parse()
+            .map_err(|_| format!("Invalid threshold '{}'", value))?;
+        Ok(Threshold { metric, value })
+    }
+}
+
+/// Parses the `COLUMNSxROWS` grid of `--tiles`.
+fn parse_tile_grid(arg: &str) -> Result<tiles::TileGrid, String> {
+    let invalid = || format!("Expected COLUMNSxROWS, such as 8x8, got '{}'", arg);
+    let (columns, rows) = arg.to_lowercase().split_once('x').map_or_else(
+        || Err(invalid()),
+        |(columns, rows)| {
+            Ok((
+                columns.trim().parse::<usize>().map_err(|_| invalid())?,
+                rows.trim().parse::<usize>().map_err(|_| invalid())?,
+            ))
+        },
+    )?;
+    if columns == 0 || rows == 0 {
+        return Err(invalid());
+    }
+    Ok(tiles::TileGrid::new(columns, rows))
 }
 
 #[derive(Debug, Clone, Copy)]
 enum InputType {
     Video,
-    #[allow(dead_code)]
     Audio,
     #[allow(dead_code)]
     Unknown,
 }
 
 impl InputType {
-    pub fn detect<P: AsRef<Path>>(_filename: P) -> Self {
-        // FIXME: For now, just assume anything is a video, since that's all we currently support.
-        InputType::Video
+    /// Detects audio inputs by their extension. Anything else is assumed to
+    /// be a video, and left to the video decoder to reject.
+    pub fn detect<P: AsRef<Path>>(filename: P) -> Self {
+        const AUDIO_EXTENSIONS: [&str; 8] =
+            ["wav", "flac", "mp3", "ogg", "opus", "m4a", "aac", "wv"];
+
+        let extension = filename
+            .as_ref()
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase());
+        match extension {
+            Some(ext) if AUDIO_EXTENSIONS.contains(&ext.as_str()) => InputType::Audio,
+            _ => InputType::Video,
+        }
     }
 }
 
+/// The options given on the command line that apply to every input and
+/// comparison. They are read once in `run` and passed to everything that
+/// opens, scores or prints an input.
+#[derive(Debug, Default)]
+pub struct RunOptions {
+    /// The metric to compute, or every metric when `None`.
+    pub metric: Option<String>,
+    /// Whether every frame is counted before computing the metrics, to show
+    /// the remaining time.
+    pub all_frames: bool,
+    /// Video details replacing the values reported by the decoder of every
+    /// input.
+    pub overrides: DetailsOverride,
+    /// Options of the decoder.
+    #[cfg(feature = "ffmpeg")]
+    pub ffmpeg: FfmpegOptions,
+    /// The plane weights, determinism and VMAF model of the metrics.
+    pub settings: MetricSettings,
+    /// The number of decimal places of the scores, if given.
+    pub precision: Option<usize>,
+    /// The BRISQUE model, if given.
+    pub brisque_model: Option<noref::brisque::BrisqueModel>,
+}
+
+impl RunOptions {
+    /// The metric to compute, or every metric when `None`.
+    pub fn metric(&self) -> Option<&str> {
+        self.metric.as_deref()
+    }
+
+    /// The number of decimal places of the scores printed as text.
+    pub fn precision(&self) -> usize {
+        self.precision.unwrap_or(4)
+    }
+}
+
+/// The decoder used for every input.
 #[cfg(not(feature = "ffmpeg"))]
-pub fn get_decoder<P: AsRef<Path>>(input: P) -> Result<Y4MDecoder<BufReader<File>>, String> {
-    av_metrics_decoders::y4m::new_decoder_from_file(input)
+pub type InputDecoder = Y4MDecoder<BufReader<File>>;
+/// The decoder used for every input.
+#[cfg(feature = "ffmpeg")]
+pub type InputDecoder = FfmpegDecoder;
+
+#[cfg(not(feature = "ffmpeg"))]
+pub fn get_decoder<P: AsRef<Path>>(
+    input: P,
+    run: &RunOptions,
+) -> Result<InputDecoder, DecoderError> {
+    let mut decoder = av_metrics_decoders::y4m::new_decoder_from_file(input)?;
+    decoder.set_overrides(run.overrides);
+    Ok(decoder)
 }
 
 #[cfg(feature = "ffmpeg")]
-pub fn get_decoder<P: AsRef<Path>>(input: P) -> Result<FfmpegDecoder, String> {
-    FfmpegDecoder::new(input)
+pub fn get_decoder<P: AsRef<Path>>(
+    input: P,
+    run: &RunOptions,
+) -> Result<InputDecoder, DecoderError> {
+    let mut decoder = FfmpegDecoder::new_with_options(input, &run.ffmpeg)?;
+    decoder.set_overrides(run.overrides);
+    Ok(decoder)
 }
 
-#[derive(Debug, Clone, Serialize, Default)]
-struct MetricsResults {
-    filename: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    psnr: Option<PlanarMetrics>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    apsnr: Option<PlanarMetrics>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    psnr_hvs: Option<PlanarMetrics>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    ssim: Option<PlanarMetrics>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    msssim: Option<PlanarMetrics>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    ciede2000: Option<f64>,
+/// The decoder used for every audio input.
+#[cfg(feature = "ffmpeg")]
+pub type AudioInputDecoder = FfmpegAudioDecoder;
+/// The decoder used for every audio input.
+#[cfg(all(feature = "wav", not(feature = "ffmpeg")))]
+pub type AudioInputDecoder = WavDecoder<BufReader<File>>;
+
+#[cfg(feature = "ffmpeg")]
+pub fn get_audio_decoder<P: AsRef<Path>>(input: P) -> Result<AudioInputDecoder, DecoderError> {
+    FfmpegAudioDecoder::new(input)
 }
 
-fn run_video_metrics(
-    input1: &str,
-    input2: &str,
-    metric: Option<&str>,
-    quiet: bool,
-    all_frames: bool,
-) -> MetricsResults {
-    let mut results = MetricsResults {
-        filename: input2.to_owned(),
-        ..Default::default()
+#[cfg(all(feature = "wav", not(feature = "ffmpeg")))]
+pub fn get_audio_decoder<P: AsRef<Path>>(input: P) -> Result<AudioInputDecoder, DecoderError> {
+    WavDecoder::open(input)
+}
+
+/// How an alternate input is aligned with the base input before scoring, as
+/// detected with `--align` and `--detect-shift`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Alignment {
+    frame_offset: Option<isize>,
+    shift: Option<Shift>,
+}
+
+/// How the inputs of a comparison are opened and which planes are scored.
+#[derive(Debug, Clone, Copy)]
+pub struct InputOptions {
+    /// The factor both inputs are downscaled by, as given to `--downscale`
+    downscale: usize,
+    /// Whether the alpha planes are compared as well, as requested with `--alpha`
+    alpha: bool,
+    /// How the alternate input is aligned with the base input
+    alignment: Alignment,
+    /// The tolerance in seconds for pairing frames by timestamp, as given to
+    /// `--match-timestamps`
+    timestamp_tolerance: Option<f64>,
+}
+
+/// An input opened for comparison, after alignment and downscaling.
+pub type ComparedDecoder = Downscaled<Shifted<Paired<Skipped<InputDecoder>>>>;
+
+/// Opens both inputs, aligning the alternate input with the base input and
+/// downscaling both.
+pub fn open_inputs<P: AsRef<Path>>(
+    input1: P,
+    input2: P,
+    options: InputOptions,
+    run: &RunOptions,
+) -> Result<(ComparedDecoder, ComparedDecoder), String> {
+    let (dec1, dec2) = Skipped::pair(
+        get_decoder(input1, run).map_err(|err| err.to_string())?,
+        get_decoder(input2, run).map_err(|err| err.to_string())?,
+        options.alignment.frame_offset.unwrap_or(0),
+    );
+    let (dec1, dec2) = match options.timestamp_tolerance {
+        Some(tolerance) => Paired::by_timestamp(dec1, dec2, tolerance),
+        None => Paired::lockstep(dec1, dec2),
     };
+    let dec1 = Shifted::new(dec1, Shift::default());
+    let dec2 = Shifted::new(dec2, options.alignment.shift.unwrap_or_default());
+    Ok((
+        Downscaled::new(dec1, options.downscale).map_err(|err| err.to_string())?,
+        Downscaled::new(dec2, options.downscale).map_err(|err| err.to_string())?,
+    ))
+}
 
-    let (progress, total_frames) = if quiet || !console::user_attended() {
-        (ProgressBar::hidden(), 0)
-    } else if all_frames {
-        let total_frames = total_frames(&input1, &input2);
-        (
-            ProgressBar::new(total_frames).with_style(
-                ProgressStyle::default_spinner()
-                    .template("{prefix} - Frame {pos}/{msg}")
-                    .unwrap(),
-            ),
-            total_frames,
+/// Checks that both inputs can be opened and have the same format, so that
+/// they can be compared.
+fn check_video_inputs(input1: &Path, input2: &Path, run: &RunOptions) -> Result<(), CliError> {
+    let details1 = get_decoder(input1, run)?.get_video_details();
+    let details2 = get_decoder(input2, run)?.get_video_details();
+    let format = |details: &VideoDetails| {
+        format!(
+            "{}x{} {}",
+            details.width,
+            details.height,
+            decode::pixel_format_name(details.chroma_sampling, details.bit_depth)
         )
-    } else {
-        (
-            ProgressBar::new_spinner().with_style(
-                ProgressStyle::default_spinner()
-                    .template("{prefix} - Frame {pos}")
-                    .unwrap(),
-            ),
-            0,
+    };
+    if format(&details1) != format(&details2) {
+        return Err(CliError::mismatch(format!(
+            "{} is {}, but {} is {}",
+            input1.display(),
+            format(&details1),
+            input2.display(),
+            format(&details2)
+        )));
+    }
+    Ok(())
+}
+
+/// Detects the frame offset over the first `frames` frames and then the
+/// global shift of up to `max_shift` pixels between two inputs, when requested.
+fn detect_alignment(
+    input1: &Path,
+    input2: &Path,
+    frames: Option<usize>,
+    max_shift: Option<usize>,
+    run: &RunOptions,
+) -> Result<Alignment, String> {
+    let error = |err: Box<dyn Error>| {
+        format!(
+            "Failed to align {} with {}: {}",
+            input2.display(),
+            input1.display(),
+            err
         )
     };
+    let mut alignment = Alignment::default();
+    if let Some(frames) = frames {
+        let mut dec1 = get_decoder(input1, run).map_err(|err| err.to_string())?;
+        let mut dec2 = get_decoder(input2, run).map_err(|err| err.to_string())?;
+        alignment.frame_offset =
+            Some(align::detect_frame_offset(&mut dec1, &mut dec2, frames, frames).map_err(error)?);
+    }
+    if let Some(max_shift) = max_shift {
+        let (mut dec1, mut dec2) = Skipped::pair(
+            get_decoder(input1, run).map_err(|err| err.to_string())?,
+            get_decoder(input2, run).map_err(|err| err.to_string())?,
+            alignment.frame_offset.unwrap_or(0),
+        );
+        alignment.shift =
+            Some(align::detect_video_shift(&mut dec1, &mut dec2, max_shift).map_err(error)?);
+    }
+    Ok(alignment)
+}
 
-    if all_frames {
-        progress.set_message(total_frames.to_string());
+/// Formats the average scores of `results` on a single line, along with the
+/// change from `previous` when there is one. Higher is better for every metric.
+fn summary_line(
+    results: &MetricsResults,
+    previous: Option<&MetricsResults>,
+    run: &RunOptions,
+) -> String {
+    let prec = run.precision();
+    let previous = previous.map(MetricsResults::averages).unwrap_or_default();
+    let mut line = format!("{}{}:", results.filename, results.approximate_label());
+    for (name, value) in results.averages() {
+        line.push_str(&format!("  {} {:.prec$}", name, value));
+        if let Some((_, old)) = previous.iter().find(|(old_name, _)| *old_name == name) {
+            let delta = value - old;
+            let delta = format!("{:+.prec$}", delta);
+            let delta = if value > *old {
+                style(delta).green()
+            } else if value < *old {
+                style(delta).red()
+            } else {
+                style(delta).dim()
+            };
+            line.push_str(&format!(" ({})", delta));
+        }
     }
+    line
+}
+
+fn run_video_metrics(
+    input1: &Path,
+    input2: &Path,
+    progress: ProgressMode,
+    options: InputOptions,
+    run: &RunOptions,
+    observers: &[Arc<dyn FrameObserver>],
+    multi: &MultiProgress,
+) -> Result<MetricsResults, CliError> {
+    let metric = run.metric();
+    let decoder = get_decoder(input2, run)?;
+    let details = decoder.get_video_details();
+    let mut results = MetricsResults {
+        filename: input2.to_string_lossy().into_owned(),
+        width: details.width,
+        height: details.height,
+        bit_depth: details.bit_depth,
+        chroma_sampling: chroma_sampling_name(details.chroma_sampling),
+        sample_aspect_ratio: Some(details.sample_aspect_ratio).filter(|sar| sar.num != sar.den),
+        downscale: Some(options.downscale).filter(|&factor| factor > 1),
+        stream_info: decoder.get_stream_info(),
+        frame_offset: options.alignment.frame_offset,
+        shift: options.alignment.shift,
+        ..Default::default()
+    };
 
+    let total = run
+        .all_frames
+        .then(|| total_frames(input1, input2, run))
+        .transpose()?;
+    let progress = Progress::new(progress, multi, &results.filename, "Frame", total);
+    // Records the trailing frames of the longer input that were not compared
+    let monitor = PipelineMonitor::new();
+    let frames = AtomicUsize::new(0);
     let progress_fn = |frameno: usize| {
-        if frameno != usize::MAX {
-            progress.set_position(frameno as u64);
+        if frameno != PROGRESS_DONE {
+            frames.fetch_max(frameno, Ordering::Relaxed);
         }
+        progress.update(frameno);
+    };
+
+    let video = VideoOptions::new().monitor(&monitor).progress(progress_fn);
+    // Gives the frames scored by the pass of `name` to every observer
+    let observed = |name: &'static str| {
+        if observers.is_empty() {
+            return video.clone();
+        }
+        let observers = observers.to_vec();
+        video.clone().frame_sink(&FrameSink::new(move |frame| {
+            for observer in observers.iter() {
+                observer.frame(name, frame);
+            }
+        }))
     };
 
-    if metric.is_none() || metric == Some("psnr") {
-        progress.set_prefix("Computing PSNR");
-        progress.reset();
-        results.psnr = Psnr::run(input1, input2, progress_fn);
+    for name in VIDEO_METRICS {
+        if metric.is_some_and(|metric| metric != name)
+            || (name == "vmaf" && run.settings.vmaf_model.is_none())
+        {
+            continue;
+        }
+        progress.start(name, &format!("Computing {}", display_name(name)));
+        let video = match name {
+            // The frames are the same as those of PSNR, so they are only
+            // observed when PSNR is not computed
+            "apsnr" if metric == Some("apsnr") => observed("psnr"),
+            "apsnr" => video.clone(),
+            _ => observed(name),
+        };
+        let score = open_inputs(input1, input2, options, run)
+            .ok()
+            .and_then(|(mut dec1, mut dec2)| {
+                compute_video_metric(name, &mut dec1, &mut dec2, &run.settings, video).ok()
+            })
+            .map(|score| with_alpha(name, score, input1, input2, options, run, progress_fn));
+        if let Some(score) = score {
+            results.set_score(name, score);
+        }
     }
 
-    if metric.is_none() || metric == Some("apsnr") {
-        progress.set_prefix("Computing APSNR");
-        progress.reset();
-        results.apsnr = APsnr::run(input1, input2, progress_fn);
+    if let Some(model) = &run.brisque_model {
+        if metric.is_none() || metric == Some("brisque") {
+            progress.start("brisque", "Computing BRISQUE");
+            let mut decoder = get_decoder(input2, run)?;
+            let brisque =
+                noref::brisque::calculate_video_brisque(&mut decoder, model, None, progress_fn)
+                    .map_err(|err| {
+                        CliError::input(format!(
+                            "Failed to compute BRISQUE for {}: {}",
+                            input2.display(),
+                            err
+                        ))
+                    })?;
+            results.brisque = Some(brisque.mean);
+        }
     }
 
-    if metric.is_none() || metric == Some("psnrhvs") {
-        progress.set_prefix("Computing PSNR-HVS");
-        progress.reset();
-        results.psnr_hvs = PsnrHvs::run(input1, input2, progress_fn);
+    results.timings = progress.finish();
+    results.frames = frames.into_inner();
+    let [base, input] = monitor.stats().ignored_frames;
+    if base > 0 || input > 0 {
+        results.ignored_frames = Some(IgnoredFrames { base, input });
     }
+    Ok(results)
+}
 
-    if metric.is_none() || metric == Some("ssim") {
-        progress.set_prefix("Computing SSIM");
-        progress.reset();
-        results.ssim = Ssim::run(input1, input2, progress_fn);
+/// Adds the score of the alpha planes to `score` when `--alpha` is given, both
+/// inputs have an alpha channel and `metric` is PSNR, APSNR, SSIM or MS-SSIM.
+fn with_alpha<F: Fn(usize) + Send>(
+    metric: &str,
+    score: Score,
+    input1: &Path,
+    input2: &Path,
+    options: InputOptions,
+    run: &RunOptions,
+    progress_callback: F,
+) -> Score {
+    let Score::Planar(result) = score else {
+        return score;
+    };
+    if !options.alpha || !ALPHA_METRICS.contains(&metric) {
+        return score;
     }
+    let alpha = open_inputs(input1, input2, options, run)
+        .ok()
+        .and_then(|(dec1, dec2)| {
+            compute_video_metric(
+                metric,
+                &mut AlphaPlane::new(dec1),
+                &mut AlphaPlane::new(dec2),
+                &run.settings,
+                VideoOptions::new().progress(progress_callback),
+            )
+            .ok()
+        });
+    Score::Planar(PlanarMetrics {
+        alpha: alpha.and_then(|alpha| match alpha {
+            Score::Planar(alpha) => Some(alpha.y),
+            Score::Value(_) => None,
+        }),
+        ..result
+    })
+}
 
-    if metric.is_none() || metric == Some("msssim") {
-        progress.set_prefix("Computing MSSSIM");
-        progress.reset();
-        results.msssim = MsSsim::run(input1, input2, progress_fn);
+#[cfg(not(any(feature = "wav", feature = "ffmpeg")))]
+fn run_audio_metrics(
+    _input1: &Path,
+    _input2: &Path,
+    _metric: Option<&str>,
+    _progress: ProgressMode,
+    _multi: &MultiProgress,
+) -> Result<MetricsResults, String> {
+    Err("This build has no audio decoder, enable the wav or ffmpeg feature.".to_owned())
+}
+
+#[cfg(any(feature = "wav", feature = "ffmpeg"))]
+fn run_audio_metrics(
+    input1: &Path,
+    input2: &Path,
+    metric: Option<&str>,
+    progress: ProgressMode,
+    multi: &MultiProgress,
+) -> Result<MetricsResults, String> {
+    let details = get_audio_decoder(input2)
+        .map_err(|err| err.to_string())?
+        .get_audio_details();
+    let mut results = MetricsResults {
+        filename: input2.to_string_lossy().into_owned(),
+        sample_rate: Some(details.sample_rate),
+        channels: Some(details.channels),
+        ..Default::default()
+    };
+
+    let progress = Progress::new(progress, multi, &results.filename, "Window", None);
+    let windows = AtomicUsize::new(0);
+    let progress_fn = |windowno: usize| {
+        if windowno != PROGRESS_DONE {
+            windows.fetch_max(windowno, Ordering::Relaxed);
+        }
+        progress.update(windowno);
+    };
+    let open_audio = || -> Result<_, DecoderError> {
+        Ok((get_audio_decoder(input1)?, get_audio_decoder(input2)?))
+    };
+
+    if metric.is_none() || metric == Some("peaq") {
+        progress.start("peaq", "Computing PEAQ");
+        let (mut dec1, mut dec2) = open_audio().map_err(|err| err.to_string())?;
+        results.peaq = peaq::calculate_audio_peaq(&mut dec1, &mut dec2, progress_fn).ok();
     }
 
-    if metric.is_none() || metric == Some("ciede2000") {
-        progress.set_prefix("Computing CIEDE2000");
-        progress.reset();
-        results.ciede2000 = Ciede2000::run(input1, input2, progress_fn);
+    if metric.is_none() || metric == Some("spectral") {
+        progress.start("spectral", "Computing spectral metrics");
+        let (mut dec1, mut dec2) = open_audio().map_err(|err| err.to_string())?;
+        results.spectral =
+            spectral::calculate_audio_spectral(&mut dec1, &mut dec2, progress_fn).ok();
     }
 
-    results
+    results.timings = progress.finish();
+    results.frames = windows.into_inner();
+    Ok(results)
+}
+
+/// Runs `compare` on every input, with up to `jobs` comparisons at the same
+/// time. Each comparison gets its own thread pool with an equal share of the
+/// threads, since the metrics are already parallel. The results are returned
+/// in the order of `inputs`.
+fn run_jobs<T, F, E>(inputs: &[T], jobs: usize, compare: F) -> Result<Vec<MetricsResults>, E>
+where
+    T: Sync,
+    F: Fn(&T) -> Result<MetricsResults, E> + Sync,
+    E: From<String> + Send,
+{
+    let jobs = jobs.min(inputs.len());
+    let threads = (rayon::current_num_threads() / jobs).max(1);
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(vec![None; inputs.len()]);
+    thread::scope(|s| {
+        let workers: Vec<_> = (0..jobs)
+            .map(|_| {
+                s.spawn(|| -> Result<(), E> {
+                    let pool = rayon::ThreadPoolBuilder::new()
+                        .num_threads(threads)
+                        .build()
+                        .map_err(|err| E::from(err.to_string()))?;
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(input) = inputs.get(index) else {
+                            return Ok(());
+                        };
+                        let result = pool.install(|| compare(input))?;
+                        results.lock().unwrap()[index] = Some(result);
+                    }
+                })
+            })
+            .collect();
+        workers.into_iter().try_for_each(|worker| {
+            worker
+                .join()
+                .unwrap_or_else(|_| Err(E::from("A comparison thread panicked".to_owned())))
+        })
+    })?;
+    Ok(results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .flatten()
+        .collect())
+}
+
+/// Checks that every alternate input is bit-exact with the base input, and
+/// prints the first difference of those that are not.
+fn verify_lossless<'a>(
+    base: &Path,
+    inputs: impl Iterator<Item = &'a PathBuf>,
+    progress: ProgressMode,
+    run: &RunOptions,
+) -> Result<(), CliError> {
+    let mut failures = 0;
+    for input in inputs {
+        let progress = Progress::new(
+            progress,
+            &MultiProgress::new(),
+            &input.to_string_lossy(),
+            "Frame",
+            None,
+        );
+        progress.start("lossless", "Verifying");
+        let mut dec1 = get_decoder(base, run)?;
+        let mut dec2 = get_decoder(input, run)?;
+        let result = lossless::verify_video_lossless(&mut dec1, &mut dec2, None, |frameno| {
+            progress.update(frameno)
+        })
+        .map_err(CliError::mismatch)?;
+        progress.finish();
+
+        let name = style(input.display()).italic().cyan();
+        match result.mismatch {
+            None => println!(
+                "{}: {} ({} frames)",
+                name,
+                style("lossless").green(),
+                result.frames
+            ),
+            Some(mismatch) => {
+                failures += 1;
+                let detail = match mismatch {
+                    lossless::Mismatch::Sample {
+                        frame,
+                        plane,
+                        x,
+                        y,
+                        value1,
+                        value2,
+                    } => format!(
+                        "frame {}, plane {}, x {}, y {}: {} != {}",
+                        frame, plane, x, y, value1, value2
+                    ),
+                    lossless::Mismatch::Length { frame } => {
+                        format!("one input ends at frame {}", frame)
+                    }
+                };
+                println!("{}: {} at {}", name, style("differs").red(), detail);
+            }
+        }
+    }
+    if failures > 0 {
+        return Err(CliError::new(
+            ErrorKind::NotLossless,
+            format!("{} input(s) are not lossless", failures),
+        ));
+    }
+    Ok(())
+}
+
+/// Polls the alternate inputs and prints a one line summary, with the change
+/// from the previous run, every time one of them has been modified. A file is
+/// only measured once it has stopped changing between two polls, so that
+/// files still being written by an encoder are not read.
+fn watch_video_metrics(
+    base: &Path,
+    inputs: &[&PathBuf],
+    options: InputOptions,
+    align: Option<usize>,
+    max_shift: Option<usize>,
+    run: &RunOptions,
+) -> Result<(), String> {
+    const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+    let stamp = |path: &Path| -> Option<(SystemTime, u64)> {
+        let metadata = std::fs::metadata(path).ok()?;
+        Some((metadata.modified().ok()?, metadata.len()))
+    };
+
+    let mut measured = vec![None; inputs.len()];
+    let mut pending = vec![None; inputs.len()];
+    let mut previous: Vec<Option<MetricsResults>> = vec![None; inputs.len()];
+    println!(
+        "Watching {} file(s) against {}, press Ctrl-C to stop",
+        inputs.len(),
+        style(base.display()).italic().cyan()
+    );
+    loop {
+        for (i, input) in inputs.iter().enumerate() {
+            let current = stamp(input);
+            if current.is_none() || current == measured[i] {
+                continue;
+            }
+            if current != pending[i] {
+                pending[i] = current;
+                continue;
+            }
+            measured[i] = current;
+
+            let options = InputOptions {
+                alignment: detect_alignment(base, input, align, max_shift, run)?,
+                ..options
+            };
+            let results = run_video_metrics(
+                base,
+                input,
+                ProgressMode::Hidden,
+                options,
+                run,
+                &[],
+                &MultiProgress::new(),
+            )
+            .map_err(|err| err.to_string())?;
+            println!("{}", summary_line(&results, previous[i].as_ref(), run));
+            previous[i] = Some(results);
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
 }
 
 #[inline(always)]
@@ -288,233 +1674,465 @@ fn count_frames<D: Decoder, P: Pixel>(dec1: &mut D, dec2: &mut D) -> u64 {
     frame_number
 }
 
-fn total_frames<P: AsRef<Path>>(input1: P, input2: P) -> u64 {
-    let mut decoder1 =
-        get_decoder(input1).expect("Failed to open input file 1 for counting frames");
-    let mut decoder2 =
-        get_decoder(input2).expect("Failed to open input file 2 for counting frames");
-    if decoder1.get_bit_depth() > 8 {
+fn total_frames<P: AsRef<Path>>(
+    input1: P,
+    input2: P,
+    run: &RunOptions,
+) -> Result<u64, DecoderError> {
+    let mut decoder1 = get_decoder(input1, run)?;
+    let mut decoder2 = get_decoder(input2, run)?;
+    // Scanning the inputs is cheaper than decoding them twice
+    let count =
+        |decoder: &mut InputDecoder| decoder.frame_count().or_else(|| decoder.build_index());
+    if let (Some(frames1), Some(frames2)) = (count(&mut decoder1), count(&mut decoder2)) {
+        return Ok(frames1.min(frames2) as u64);
+    }
+    Ok(if decoder1.get_bit_depth() > 8 {
         count_frames::<_, u16>(&mut decoder1, &mut decoder2)
     } else {
         count_frames::<_, u8>(&mut decoder1, &mut decoder2)
+    })
+}
+
+/// Returns a message for every comparison in `report` scoring below one of
+/// the thresholds.
+fn check_thresholds<'a, I: IntoIterator<Item = &'a Threshold>>(
+    report: &Report,
+    thresholds: I,
+    run: &RunOptions,
+) -> Result<Vec<String>, String> {
+    let prec = run.precision();
+    let mut failures = vec![];
+    for threshold in thresholds {
+        for cmp in report.comparisons.iter() {
+            let value = cmp.average(&threshold.metric).ok_or_else(|| {
+                format!(
+                    "Cannot check --fail-below for {}, it was not computed for {}",
+                    threshold.metric, cmp.filename
+                )
+            })?;
+            if value < threshold.value {
+                failures.push(format!(
+                    "{}: {} {:.prec$} is below {}",
+                    cmp.filename, threshold.metric, value, threshold.value
+                ));
+            }
+        }
     }
+    Ok(failures)
 }
 
-#[derive(Debug, Serialize, Default)]
-struct Report<'s> {
-    base: &'s str,
-    comparisons: Vec<MetricsResults>,
+/// The error that `--fail-below` exits with for `failures`. With `--json-only`
+/// the failures are not printed on their own, so the message lists them.
+fn threshold_error(failures: &[String], json_only: bool) -> CliError {
+    let mut message = format!("{} quality threshold(s) not met", failures.len());
+    if json_only {
+        message = format!("{}: {}", message, failures.join("; "));
+    }
+    CliError::new(ErrorKind::Threshold, message)
 }
 
-impl Report<'_> {
-    fn print(&self, writer: &mut OutputType) -> Result<(), String> {
-        match writer {
-            OutputType::JSON(w) => {
-                writeln!(w, "{}", serde_json::to_string(&self).unwrap())
-                    .map_err(|err| err.to_string())?;
-            }
-            OutputType::CSV(w) => {
-                writeln!(w, "filename,psnr,apsnr,psnr_hvs,ssim,msssim,ciede2000")
-                    .map_err(|err| err.to_string())?;
-                for cmp in self.comparisons.iter() {
+fn print_report(report: &Report, writer: &mut OutputType, run: &RunOptions) -> Result<(), String> {
+    let prec = run.precision();
+    match writer {
+        OutputType::JSON(w) => report.write_json(w),
+        OutputType::XML(w) => report.write_xml(w),
+        OutputType::YAML(w) => report.write_yaml(w),
+        OutputType::CSV(w) => report.write_csv(w),
+        OutputType::Markdown(w) => report.write_markdown(w),
+        OutputType::Stdout(_) | OutputType::TEXT(_) => {
+            writeln!(
+                writer,
+                "Comparing {} ({}) using av-metrics {}\n",
+                style(report.base).italic().cyan(),
+                report.base_stream_info,
+                report.av_metrics_version
+            )
+            .map_err(|err| err.to_string())?;
+            for cmp in report.comparisons.iter() {
+                if cmp.sample_rate.is_some() {
                     writeln!(
-                        w,
-                        "{},{},{},{},{},{},{}",
-                        cmp.filename,
-                        cmp.psnr.map(|v| v.avg).unwrap_or(-0.0),
-                        cmp.apsnr.map(|v| v.avg).unwrap_or(-0.0),
-                        cmp.psnr_hvs.map(|v| v.avg).unwrap_or(-0.0),
-                        cmp.ssim.map(|v| v.avg).unwrap_or(-0.0),
-                        cmp.msssim.map(|v| v.avg).unwrap_or(-0.0),
-                        cmp.ciede2000.unwrap_or(-0.0)
+                        writer,
+                        "\n    {} for {} ({}, {}):\n",
+                        style("Results").yellow(),
+                        style(cmp.title()).italic().cyan(),
+                        cmp.resolution(),
+                        cmp.format(),
                     )
                     .map_err(|err| err.to_string())?;
+                    print_base(writer, cmp)?;
+                    Text::print_result(writer, "PEAQ", cmp.peaq, run)?;
+                    Text::print_result(writer, "Spectral", cmp.spectral, run)?;
+                    print_timings(writer, &cmp.timings)?;
+                    continue;
                 }
-            }
-            OutputType::Markdown(w) => {
                 writeln!(
-                    w,
-                    "|filename|psnr|apsnr|psnr_hvs|ssim|msssim|ciede2000|\n\
-                     |-|-|-|-|-|-|-|"
+                    writer,
+                    "\n    {} for {} ({}, {}, {} frames){}:",
+                    style("Results").yellow(),
+                    style(cmp.title()).italic().cyan(),
+                    cmp.resolution(),
+                    cmp.format(),
+                    cmp.frames,
+                    style(cmp.approximate_label()).yellow()
                 )
                 .map_err(|err| err.to_string())?;
-                for cmp in self.comparisons.iter() {
+                writeln!(writer, "     {}", style(&cmp.stream_info).dim())
+                    .map_err(|err| err.to_string())?;
+                print_base(writer, cmp)?;
+                if let Some(offset) = cmp.frame_offset {
                     writeln!(
-                        w,
-                        "|{}|{}|{}|{}|{}|{}|{}|",
-                        cmp.filename,
-                        cmp.psnr.map(|v| v.avg).unwrap_or(-0.0),
-                        cmp.apsnr.map(|v| v.avg).unwrap_or(-0.0),
-                        cmp.psnr_hvs.map(|v| v.avg).unwrap_or(-0.0),
-                        cmp.ssim.map(|v| v.avg).unwrap_or(-0.0),
-                        cmp.msssim.map(|v| v.avg).unwrap_or(-0.0),
-                        cmp.ciede2000.unwrap_or(-0.0)
+                        writer,
+                        "     {}",
+                        style(format!("Aligned with a frame offset of {}", offset)).dim()
                     )
                     .map_err(|err| err.to_string())?;
                 }
-            }
-            OutputType::Stdout(_) | OutputType::TEXT(_) => {
-                writeln!(writer, "Comparing {}\n", style(self.base).italic().cyan())
+                if let Some(shift) = cmp.shift {
+                    writeln!(
+                        writer,
+                        "     {}",
+                        style(format!("Aligned with a shift of {} pixels", shift)).dim()
+                    )
                     .map_err(|err| err.to_string())?;
-                for cmp in self.comparisons.iter() {
+                }
+                for mismatch in cmp
+                    .stream_mismatches(&report.base_stream_info)
+                    .into_iter()
+                    .chain(cmp.length_mismatch())
+                {
                     writeln!(
                         writer,
-                        "\n    {} for {}: \n",
-                        style("Results").yellow(),
-                        style(&cmp.filename).italic().cyan()
+                        "     {}",
+                        style(format!("Warning: {}", mismatch)).yellow()
+                    )
+                    .map_err(|err| err.to_string())?;
+                }
+                writeln!(writer).map_err(|err| err.to_string())?;
+                Text::print_result(writer, "PSNR", cmp.psnr, run)?;
+                Text::print_result(writer, "APSNR", cmp.apsnr, run)?;
+                Text::print_result(writer, "PSNR HVS", cmp.psnr_hvs, run)?;
+                Text::print_result(writer, "SSIM", cmp.ssim, run)?;
+                Text::print_result(writer, "MSSSIM", cmp.msssim, run)?;
+                Text::print_result(writer, "CIEDE2000", cmp.ciede2000, run)?;
+                if let Some(brisque) = cmp.brisque {
+                    writeln!(
+                        writer,
+                        "     {:<10} →  Score: {:.prec$} (lower is better)",
+                        style("BRISQUE").cyan(),
+                        brisque
                     )
                     .map_err(|err| err.to_string())?;
-                    Text::print_result(writer, "PSNR", cmp.psnr)?;
-                    Text::print_result(writer, "APSNR", cmp.apsnr)?;
-                    Text::print_result(writer, "PSNR HVS", cmp.psnr_hvs)?;
-                    Text::print_result(writer, "SSIM", cmp.ssim)?;
-                    Text::print_result(writer, "MSSSIM", cmp.msssim)?;
-                    Text::print_result(writer, "CIEDE2000", cmp.ciede2000)?;
                 }
+                if let Some(vmaf) = cmp.vmaf {
+                    writeln!(
+                        writer,
+                        "     {:<10} →  Score: {:<8.prec$}",
+                        style("VMAF").cyan(),
+                        vmaf
+                    )
+                    .map_err(|err| err.to_string())?;
+                }
+                print_gops(writer, &cmp.gops, run)?;
+                print_worst_frames(writer, &cmp.worst_frames, run)?;
+                print_histograms(writer, &cmp.histograms, run)?;
+                print_confidence_intervals(writer, &cmp.confidence_intervals, run)?;
+                print_rate_control(writer, &cmp.rate_control, run)?;
+                print_tiles(writer, &cmp.tiles, run)?;
+                print_banding(writer, cmp.banding.as_ref(), run)?;
+                print_timings(writer, &cmp.timings)?;
             }
+            Ok(())
         }
-
-        Ok(())
     }
 }
 
-enum OutputType {
-    JSON(BufWriter<File>),
-    CSV(BufWriter<File>),
-    Markdown(BufWriter<File>),
-    TEXT(BufWriter<File>),
-    Stdout(BufWriter<Stdout>),
+/// Prints the base input of a comparison of a batch, which has its own.
+fn print_base(writer: &mut OutputType, cmp: &MetricsResults) -> Result<(), String> {
+    let Some(base) = &cmp.base else {
+        return Ok(());
+    };
+    writeln!(
+        writer,
+        "     {}",
+        style(format!("Compared with {}", base)).dim()
+    )
+    .map_err(|err| err.to_string())
 }
 
-impl Write for OutputType {
-    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
-        match self {
-            OutputType::JSON(f)
-            | OutputType::CSV(f)
-            | OutputType::Markdown(f)
-            | OutputType::TEXT(f) => f.write(buf),
-            OutputType::Stdout(s) => s.write(buf),
+fn print_gops(
+    writer: &mut OutputType,
+    gops: &[MetricGops],
+    run: &RunOptions,
+) -> Result<(), String> {
+    let prec = run.precision();
+    for metric in gops {
+        writeln!(writer, "\n     {} by GOP:", style(&metric.metric).cyan())
+            .map_err(|err| err.to_string())?;
+        for gop in metric.gops.iter() {
+            writeln!(
+                writer,
+                "       Frame {:<6} ({:>4} frames) →  Mean: {:<8.prec$} \
+                 Min: {:<8.prec$} at frame {}",
+                gop.start_frame, gop.frames, gop.mean, gop.min, gop.min_frame
+            )
+            .map_err(|err| err.to_string())?;
         }
     }
+    Ok(())
+}
 
-    fn flush(&mut self) -> std::io::Result<()> {
-        match self {
-            OutputType::JSON(f)
-            | OutputType::CSV(f)
-            | OutputType::Markdown(f)
-            | OutputType::TEXT(f) => f.flush(),
-            OutputType::Stdout(s) => s.flush(),
+fn print_worst_frames(
+    writer: &mut OutputType,
+    worst: &[MetricWorstFrames],
+    run: &RunOptions,
+) -> Result<(), String> {
+    let prec = run.precision();
+    for metric in worst {
+        writeln!(
+            writer,
+            "\n     {} worst frames:",
+            style(&metric.metric).cyan()
+        )
+        .map_err(|err| err.to_string())?;
+        for frame in metric.frames.iter() {
+            writeln!(
+                writer,
+                "       Frame {:<6} →  Score: {:<8.prec$}",
+                frame.frame, frame.score
+            )
+            .map_err(|err| err.to_string())?;
         }
     }
+    Ok(())
 }
 
-trait CliMetric {
-    type VideoResult: Serialize;
+/// Prints each histogram as a sparkline between the lowest and highest score.
+fn print_histograms(
+    writer: &mut OutputType,
+    histograms: &[MetricHistogram],
+    run: &RunOptions,
+) -> Result<(), String> {
+    let prec = run.precision();
+    const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
 
-    fn run<P: AsRef<Path>, F: Fn(usize) + Send>(
-        input1: P,
-        input2: P,
-        progress_callback: F,
-    ) -> Option<Self::VideoResult> {
-        let mut dec1 = get_decoder(input1).expect("Failed to open input file 1");
-        let mut dec2 = get_decoder(input2).expect("Failed to open input file 2");
-        Self::calculate_video_metric(&mut dec1, &mut dec2, progress_callback).ok()
+    if !histograms.is_empty() {
+        writeln!(writer).map_err(|err| err.to_string())?;
     }
-
-    fn calculate_video_metric<D: Decoder, F: Fn(usize) + Send>(
-        dec1: &mut D,
-        dec2: &mut D,
-        progress_callback: F,
-    ) -> Result<Self::VideoResult, Box<dyn Error>>;
+    for metric in histograms {
+        let histogram = &metric.histogram;
+        let highest = histogram.counts.iter().copied().max().unwrap_or(0);
+        let sparkline: String = histogram
+            .counts
+            .iter()
+            .map(|&count| match count {
+                0 => ' ',
+                // Any bin with frames shows at least the lowest bar
+                _ => BARS[(count * BARS.len()).div_ceil(highest) - 1],
+            })
+            .collect();
+        writeln!(
+            writer,
+            "     {:<10} →  {:<8.prec$} {} {:.prec$}",
+            style(&metric.metric).cyan(),
+            histogram.min,
+            sparkline,
+            histogram.max
+        )
+        .map_err(|err| err.to_string())?;
+    }
+    Ok(())
 }
 
-struct Psnr;
-
-impl CliMetric for Psnr {
-    type VideoResult = PlanarMetrics;
-
-    fn calculate_video_metric<D: Decoder, F: Fn(usize) + Send>(
-        dec1: &mut D,
-        dec2: &mut D,
-        progress_callback: F,
-    ) -> Result<Self::VideoResult, Box<dyn Error>> {
-        psnr::calculate_video_psnr(dec1, dec2, None, progress_callback)
+fn print_confidence_intervals(
+    writer: &mut OutputType,
+    intervals: &[MetricConfidence],
+    run: &RunOptions,
+) -> Result<(), String> {
+    let prec = run.precision();
+    if !intervals.is_empty() {
+        writeln!(writer).map_err(|err| err.to_string())?;
+    }
+    for metric in intervals {
+        let interval = &metric.interval;
+        writeln!(
+            writer,
+            "     {:<10} →  Mean: {:<8.prec$} {:.0}% interval: {:.prec$} to {:.prec$}",
+            style(&metric.metric).cyan(),
+            interval.mean,
+            interval.confidence * 100.0,
+            interval.lower,
+            interval.upper
+        )
+        .map_err(|err| err.to_string())?;
     }
+    Ok(())
 }
 
-struct APsnr;
-
-impl CliMetric for APsnr {
-    type VideoResult = PlanarMetrics;
-
-    fn calculate_video_metric<D: Decoder, F: Fn(usize) + Send>(
-        dec1: &mut D,
-        dec2: &mut D,
-        progress_callback: F,
-    ) -> Result<Self::VideoResult, Box<dyn Error>> {
-        psnr::calculate_video_apsnr(dec1, dec2, None, progress_callback)
+fn print_rate_control(
+    writer: &mut OutputType,
+    rate_control: &[MetricRateControl],
+    run: &RunOptions,
+) -> Result<(), String> {
+    let prec = run.precision();
+    for metric in rate_control {
+        writeln!(
+            writer,
+            "\n     {} against the encoder statistics:",
+            style(&metric.metric).cyan()
+        )
+        .map_err(|err| err.to_string())?;
+        for (statistic, unit, fit) in [
+            ("QP", "per QP", metric.qp),
+            ("Size", "per doubling", metric.bits),
+        ] {
+            let Some(fit) = fit else {
+                continue;
+            };
+            writeln!(
+                writer,
+                "       {:<4} →  Slope: {:<8.prec$} {:<13} Correlation: {:<7.prec$} ({} frames)",
+                statistic, fit.slope, unit, fit.correlation, fit.frames
+            )
+            .map_err(|err| err.to_string())?;
+        }
     }
+    Ok(())
 }
 
-struct PsnrHvs;
-
-impl CliMetric for PsnrHvs {
-    type VideoResult = PlanarMetrics;
-
-    fn calculate_video_metric<D: Decoder, F: Fn(usize) + Send>(
-        dec1: &mut D,
-        dec2: &mut D,
-        progress_callback: F,
-    ) -> Result<Self::VideoResult, Box<dyn Error>> {
-        psnr_hvs::calculate_video_psnr_hvs(dec1, dec2, None, progress_callback)
+/// Prints the average PSNR of the tiles as a grid, followed by the worst tile.
+fn print_tiles(
+    writer: &mut OutputType,
+    tiles: &[tiles::TileScores],
+    run: &RunOptions,
+) -> Result<(), String> {
+    let prec = run.precision();
+    let Some(worst) = tiles
+        .iter()
+        .min_by(|a, b| a.psnr.avg.total_cmp(&b.psnr.avg))
+    else {
+        return Ok(());
+    };
+    writeln!(writer, "\n     {} by tile:", style("psnr").cyan()).map_err(|err| err.to_string())?;
+    for row in tiles.chunk_by(|a, b| a.row == b.row) {
+        let scores = row
+            .iter()
+            .map(|tile| format!("{:>7.2}", tile.psnr.avg))
+            .collect::<String>();
+        writeln!(writer, "      {}", scores).map_err(|err| err.to_string())?;
     }
+    writeln!(
+        writer,
+        "       Worst tile at {}x{} ({}x{} pixels) →  Mean: {:<8.prec$} \
+         Min: {:<8.prec$} at frame {}",
+        worst.x, worst.y, worst.width, worst.height, worst.psnr.avg, worst.min, worst.min_frame
+    )
+    .map_err(|err| err.to_string())
 }
 
-struct Ssim;
-
-impl CliMetric for Ssim {
-    type VideoResult = PlanarMetrics;
-
-    fn calculate_video_metric<D: Decoder, F: Fn(usize) + Send>(
-        dec1: &mut D,
-        dec2: &mut D,
-        progress_callback: F,
-    ) -> Result<Self::VideoResult, Box<dyn Error>> {
-        ssim::calculate_video_ssim(dec1, dec2, None, progress_callback)
+/// Prints the share of the frames prone to banding and the PSNR in those
+/// regions.
+fn print_banding(
+    writer: &mut OutputType,
+    banding: Option<&banding::BandingResults>,
+    run: &RunOptions,
+) -> Result<(), String> {
+    let prec = run.precision();
+    let Some(banding) = banding.filter(|banding| !banding.frames.is_empty()) else {
+        return Ok(());
+    };
+    let (least, most) = banding
+        .frames
+        .iter()
+        .fold((f64::INFINITY, 0.0f64), |(least, most), frame| {
+            (least.min(frame.coverage), most.max(frame.coverage))
+        });
+    writeln!(
+        writer,
+        "\n     {} →  Coverage: {:.2}% (min {:.2}%, max {:.2}%)",
+        style("Banding").cyan(),
+        banding.coverage,
+        least,
+        most
+    )
+    .map_err(|err| err.to_string())?;
+    if let Some(psnr) = banding.psnr {
+        writeln!(
+            writer,
+            "       PSNR in those regions →  Y: {:<8.prec$} U: {:<8.prec$} \
+             V: {:<8.prec$} Avg: {:<8.prec$}",
+            psnr.y, psnr.u, psnr.v, psnr.avg
+        )
+        .map_err(|err| err.to_string())?;
     }
+    Ok(())
 }
 
-struct MsSsim;
-
-impl CliMetric for MsSsim {
-    type VideoResult = PlanarMetrics;
-
-    fn calculate_video_metric<D: Decoder, F: Fn(usize) + Send>(
-        dec1: &mut D,
-        dec2: &mut D,
-        progress_callback: F,
-    ) -> Result<Self::VideoResult, Box<dyn Error>> {
-        ssim::calculate_video_msssim(dec1, dec2, None, progress_callback)
+fn print_timings(writer: &mut OutputType, timings: &[MetricTiming]) -> Result<(), String> {
+    if timings.is_empty() {
+        return Ok(());
     }
+    let timings = timings
+        .iter()
+        .map(|timing| {
+            format!(
+                "{} {:.2} s ({:.1} fps)",
+                timing.metric, timing.seconds, timing.fps
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+    writeln!(
+        writer,
+        "\n     {}",
+        style(format!("Time: {}", timings)).dim()
+    )
+    .map_err(|err| err.to_string())
 }
 
-struct Ciede2000;
+enum OutputType {
+    JSON(BufWriter<File>),
+    CSV(BufWriter<File>),
+    Markdown(BufWriter<File>),
+    XML(BufWriter<File>),
+    YAML(BufWriter<File>),
+    TEXT(BufWriter<File>),
+    Stdout(BufWriter<Stdout>),
+}
 
-impl CliMetric for Ciede2000 {
-    type VideoResult = f64;
+impl Write for OutputType {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            OutputType::JSON(f)
+            | OutputType::CSV(f)
+            | OutputType::Markdown(f)
+            | OutputType::XML(f)
+            | OutputType::YAML(f)
+            | OutputType::TEXT(f) => f.write(buf),
+            OutputType::Stdout(s) => s.write(buf),
+        }
+    }
 
-    fn calculate_video_metric<D: Decoder, F: Fn(usize) + Send>(
-        dec1: &mut D,
-        dec2: &mut D,
-        progress_callback: F,
-    ) -> Result<Self::VideoResult, Box<dyn Error>> {
-        ciede::calculate_video_ciede(dec1, dec2, None, progress_callback)
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            OutputType::JSON(f)
+            | OutputType::CSV(f)
+            | OutputType::Markdown(f)
+            | OutputType::XML(f)
+            | OutputType::YAML(f)
+            | OutputType::TEXT(f) => f.flush(),
+            OutputType::Stdout(s) => s.flush(),
+        }
     }
 }
 
 trait PrintResult<T> {
-    fn print_result(writer: &mut OutputType, header: &str, result: Option<T>)
-        -> Result<(), String>;
+    fn print_result(
+        writer: &mut OutputType,
+        header: &str,
+        result: Option<T>,
+        run: &RunOptions,
+    ) -> Result<(), String>;
 }
 
 struct Text;
@@ -524,11 +2142,14 @@ impl PrintResult<PlanarMetrics> for Text {
         writer: &mut OutputType,
         header: &str,
         result: Option<PlanarMetrics>,
+        run: &RunOptions,
     ) -> Result<(), String> {
+        let prec = run.precision();
         if let Some(result) = result {
             writeln!(
                 writer,
-                "     {:<10} →  Y: {:<8.4} U/Cb: {:<8.4} V/Cr: {:<8.4} Avg value: {:<8.4}",
+                "     {:<10} →  Y: {:<8.prec$} U/Cb: {:<8.prec$} \
+                 V/Cr: {:<8.prec$} Avg value: {:<8.prec$}",
                 style(header).cyan(),
                 result.y,
                 result.u,
@@ -536,6 +2157,16 @@ impl PrintResult<PlanarMetrics> for Text {
                 result.avg
             )
             .map_err(|err| err.to_string())?;
+            if let Some(alpha) = result.alpha {
+                writeln!(writer, "     {:<10}    Alpha: {:<8.prec$}", "", alpha)
+                    .map_err(|err| err.to_string())?;
+            }
+            if let Some(dssim) = result.dssim {
+                // DSSIM is much smaller than SSIM, so it gets two more decimals
+                let dssim_prec = prec + 2;
+                writeln!(writer, "     {:<10}    DSSIM: {:<8.dssim_prec$}", "", dssim)
+                    .map_err(|err| err.to_string())?;
+            }
         }
         Ok(())
     }
@@ -546,11 +2177,13 @@ impl PrintResult<f64> for Text {
         writer: &mut OutputType,
         header: &str,
         result: Option<f64>,
+        run: &RunOptions,
     ) -> Result<(), String> {
+        let prec = run.precision();
         if let Some(result) = result {
             writeln!(
                 writer,
-                "     {:<10} →  Delta: {:<8.4}",
+                "     {:<10} →  Delta: {:<8.prec$}",
                 style(header).cyan(),
                 result
             )
@@ -559,3 +2192,112 @@ impl PrintResult<f64> for Text {
         Ok(())
     }
 }
+
+impl PrintResult<peaq::PeaqResult> for Text {
+    fn print_result(
+        writer: &mut OutputType,
+        header: &str,
+        result: Option<peaq::PeaqResult>,
+        run: &RunOptions,
+    ) -> Result<(), String> {
+        let prec = run.precision();
+        if let Some(result) = result {
+            writeln!(
+                writer,
+                "     {:<10} →  ODG: {:<8.prec$} DI: {:<8.prec$}",
+                style(header).cyan(),
+                result.odg,
+                result.distortion_index
+            )
+            .map_err(|err| err.to_string())?;
+        }
+        Ok(())
+    }
+}
+
+impl PrintResult<spectral::SpectralMetrics> for Text {
+    fn print_result(
+        writer: &mut OutputType,
+        header: &str,
+        result: Option<spectral::SpectralMetrics>,
+        run: &RunOptions,
+    ) -> Result<(), String> {
+        let prec = run.precision();
+        if let Some(result) = result {
+            writeln!(
+                writer,
+                "     {:<10} →  LSD: {:<8.prec$} SC: {:<8.prec$} SegSNR: {:<8.prec$}",
+                style(header).cyan(),
+                result.log_spectral_distance,
+                result.spectral_convergence,
+                result.segmental_snr
+            )
+            .map_err(|err| err.to_string())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn threshold_parse() {
+        let threshold = Threshold::parse(" PSNR = 35.5 ").unwrap();
+        assert_eq!(threshold.metric, "psnr");
+        assert_eq!(threshold.value, 35.5);
+        assert_eq!(Threshold::parse("vmaf=-1").unwrap().value, -1.0);
+
+        assert_eq!(
+            Threshold::parse("psnr").unwrap_err(),
+            "Expected METRIC=VALUE, got 'psnr'"
+        );
+        assert!(Threshold::parse("butteraugli=1")
+            .unwrap_err()
+            .starts_with("Unknown metric 'butteraugli'"));
+        assert_eq!(
+            Threshold::parse("ssim=high").unwrap_err(),
+            "Invalid threshold 'high'"
+        );
+    }
+
+    #[test]
+    fn thresholds_fail_below() {
+        let psnr = |filename: &str, avg: f64| MetricsResults {
+            filename: filename.to_owned(),
+            psnr: Some(PlanarMetrics {
+                avg,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let mut report = Report::new("source.y4m");
+        report.comparisons.push(psnr("good.y4m", 40.0));
+        report.comparisons.push(psnr("bad.y4m", 30.0));
+        let run = RunOptions::default();
+        let check =
+            |spec: &str| check_thresholds(&report, &[Threshold::parse(spec).unwrap()], &run);
+
+        assert_eq!(check("psnr=30").unwrap(), Vec::<String>::new());
+        assert_eq!(
+            check("psnr=35").unwrap(),
+            ["bad.y4m: psnr 30.0000 is below 35"]
+        );
+        assert_eq!(check("psnr=50").unwrap().len(), 2);
+        assert_eq!(
+            check("ssim=10").unwrap_err(),
+            "Cannot check --fail-below for ssim, it was not computed for good.y4m"
+        );
+
+        let failures = check("psnr=35").unwrap();
+        let err = threshold_error(&failures, false);
+        assert_eq!(err.kind, ErrorKind::Threshold);
+        assert_eq!(err.code, 6);
+        assert_eq!(err.message, "1 quality threshold(s) not met");
+        assert_eq!(
+            threshold_error(&failures, true).message,
+            "1 quality threshold(s) not met: bad.y4m: psnr 30.0000 is below 35"
+        );
+    }
+}
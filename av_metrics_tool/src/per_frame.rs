@@ -0,0 +1,243 @@
+//! Collects the per-frame scores of the metrics and aggregates them by GOP,
+//! for `--gop`, keeps the worst frames, for `--worst`, counts them in a
+//! histogram, for `--histogram`, resamples them for confidence intervals, for
+//! `--confidence`, and fits them against the encoder statistics, for
+//! `--qp-log`. The tiles, for `--tiles`, and the regions prone to banding, for
+//! `--banding`, are scored from the same frames.
+//!
+//! The frames come from the passes computing the averages, so the inputs are
+//! only decoded once per metric.
+
+use av_metrics::video::banding::{BandingAggregator, BandingConfig, BandingResults};
+use av_metrics::video::bootstrap::{bootstrap_mean, BootstrapConfig};
+use av_metrics::video::gop::GopAggregator;
+use av_metrics::video::histogram::Histogram;
+use av_metrics::video::rate_control::RateControlAggregator;
+use av_metrics::video::side_data::SideData;
+use av_metrics::video::tiles::{TileAggregator, TileGrid, TileScores};
+use av_metrics::video::worst::WorstFrames;
+use av_metrics::video::*;
+use av_metrics_report::{
+    MetricConfidence, MetricGops, MetricHistogram, MetricRateControl, MetricWorstFrames,
+};
+use std::error::Error;
+use std::sync::{Mutex, OnceLock};
+
+/// The metrics that have a per-frame score, by command line name. APSNR is
+/// the same as PSNR for a single frame, so it is grouped as PSNR.
+const FRAME_METRICS: [&str; 5] = ["psnr", "psnrhvs", "ssim", "msssim", "ciede2000"];
+
+/// Receives the frame pairs scored by the metrics of a comparison, from the
+/// pass computing their averages.
+pub trait FrameObserver: Send + Sync {
+    /// Called with each frame pair scored by `metric`, by its command line
+    /// name. It is called from the scoring threads, possibly out of order.
+    fn frame(&self, metric: &'static str, frame: &ScoredFrame);
+}
+
+/// The per-frame aggregations to compute.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Aggregations {
+    /// Group the scores by GOP.
+    pub gops: bool,
+    /// Keep this many of the worst frames.
+    pub worst: Option<usize>,
+    /// Count the scores into this many bins.
+    pub histogram: Option<usize>,
+    /// Compute the confidence interval of the mean score.
+    pub confidence: bool,
+    /// Fit the scores against the encoder statistics of each frame.
+    pub rate_control: bool,
+    /// Score each tile of this grid.
+    pub tiles: Option<TileGrid>,
+    /// Find the regions prone to banding.
+    pub banding: bool,
+}
+
+impl Aggregations {
+    pub fn any(&self) -> bool {
+        self.gops
+            || self.worst.is_some()
+            || self.histogram.is_some()
+            || self.confidence
+            || self.rate_control
+            || self.tiles.is_some()
+            || self.banding
+    }
+}
+
+/// The aggregated per-frame scores of each metric.
+#[derive(Debug, Default)]
+pub struct FrameAggregates {
+    pub gops: Vec<MetricGops>,
+    pub worst_frames: Vec<MetricWorstFrames>,
+    pub histograms: Vec<MetricHistogram>,
+    pub confidence_intervals: Vec<MetricConfidence>,
+    pub rate_control: Vec<MetricRateControl>,
+    pub tiles: Vec<TileScores>,
+    pub banding: Option<BandingResults>,
+}
+
+/// The score of one frame by one metric.
+struct FrameRecord {
+    metric: &'static str,
+    frame: usize,
+    score: f64,
+    keyframe: Option<bool>,
+    side_data: Option<SideData>,
+}
+
+/// Collects the scores of every frame of a comparison and aggregates them
+/// once the metrics are computed.
+pub struct FrameCollector {
+    aggregations: Aggregations,
+    records: Mutex<Vec<FrameRecord>>,
+    tiles: Option<TileAggregator>,
+    banding: Option<BandingAggregator>,
+    /// The metric whose pass scores the tiles and banding, which is the first
+    /// one to give a frame.
+    regions_metric: OnceLock<&'static str>,
+    error: Mutex<Option<String>>,
+}
+
+impl FrameCollector {
+    pub fn new(aggregations: Aggregations) -> Self {
+        FrameCollector {
+            aggregations,
+            records: Mutex::new(Vec::new()),
+            tiles: aggregations.tiles.map(TileAggregator::new),
+            banding: aggregations
+                .banding
+                .then(|| BandingAggregator::new(BandingConfig::default())),
+            regions_metric: OnceLock::new(),
+            error: Mutex::new(None),
+        }
+    }
+
+    /// Scores the tiles and the regions prone to banding of one frame pair.
+    fn score_regions<T: Pixel>(
+        &self,
+        frame: &ScoredFrame,
+        [frame1, frame2]: [&Frame<T>; 2],
+    ) -> Result<(), Box<dyn Error>> {
+        if let Some(tiles) = &self.tiles {
+            tiles.push(frame.frame, frame1, frame2, frame.context)?;
+        }
+        if let Some(banding) = &self.banding {
+            banding.push(frame.frame, frame1, frame2, frame.context)?;
+        }
+        Ok(())
+    }
+
+    /// Aggregates the scores of the frames, fitting them against the
+    /// statistics of each frame in `side_data`, or else against those reported
+    /// by the decoder of the alternate input.
+    pub fn finish(self, side_data: &[SideData]) -> Result<FrameAggregates, String> {
+        if let Some(err) = self.error.into_inner().unwrap() {
+            return Err(err);
+        }
+        let aggregations = self.aggregations;
+        let mut records = self.records.into_inner().unwrap();
+        records.sort_by_key(|record| record.frame);
+
+        let mut aggregates = FrameAggregates::default();
+        for name in FRAME_METRICS {
+            let records: Vec<&FrameRecord> = records
+                .iter()
+                .filter(|record| record.metric == name)
+                .collect();
+            if records.is_empty() {
+                continue;
+            }
+            let scores: Vec<f64> = records.iter().map(|record| record.score).collect();
+            if aggregations.gops {
+                let mut gops = GopAggregator::new();
+                for record in records.iter() {
+                    let keyframe = record
+                        .keyframe
+                        .ok_or("--gop needs a decoder that reports keyframes, such as FFmpeg")?;
+                    gops.push(keyframe, record.score);
+                }
+                aggregates.gops.push(MetricGops {
+                    metric: name.to_owned(),
+                    gops: gops.finish(),
+                });
+            }
+            if let Some(n) = aggregations.worst {
+                let mut worst = WorstFrames::new(n);
+                scores.iter().for_each(|&score| worst.push(score));
+                aggregates.worst_frames.push(MetricWorstFrames {
+                    metric: name.to_owned(),
+                    frames: worst.finish(),
+                });
+            }
+            if let Some(bins) = aggregations.histogram {
+                aggregates.histograms.push(MetricHistogram {
+                    metric: name.to_owned(),
+                    histogram: Histogram::new(scores.iter().copied(), bins),
+                });
+            }
+            if aggregations.confidence {
+                if let Some(interval) = bootstrap_mean(scores, BootstrapConfig::default()) {
+                    aggregates.confidence_intervals.push(MetricConfidence {
+                        metric: name.to_owned(),
+                        interval,
+                    });
+                }
+            }
+            if aggregations.rate_control {
+                let mut rate_control = RateControlAggregator::new();
+                for record in records.iter() {
+                    let side_data = side_data.get(record.frame).or(record.side_data.as_ref());
+                    rate_control.push(side_data, record.score);
+                }
+                let fits = rate_control.finish();
+                aggregates.rate_control.push(MetricRateControl {
+                    metric: name.to_owned(),
+                    qp: fits.qp,
+                    bits: fits.bits,
+                });
+            }
+        }
+        if let Some(tiles) = self.tiles {
+            aggregates.tiles = tiles.finish().map_err(|err| err.to_string())?;
+        }
+        if let Some(banding) = self.banding {
+            aggregates.banding = Some(banding.finish().map_err(|err| err.to_string())?);
+        }
+        Ok(aggregates)
+    }
+}
+
+impl FrameObserver for FrameCollector {
+    fn frame(&self, metric: &'static str, frame: &ScoredFrame) {
+        if *self.regions_metric.get_or_init(|| metric) == metric {
+            let result = match (frame.frames::<u8>(), frame.frames::<u16>()) {
+                (Some(frames), _) => self.score_regions(frame, frames),
+                (_, Some(frames)) => self.score_regions(frame, frames),
+                _ => Ok(()),
+            };
+            if let Err(err) = result {
+                self.error.lock().unwrap().get_or_insert(err.to_string());
+            }
+        }
+        let Some(score) = frame.score else {
+            return;
+        };
+        if !FRAME_METRICS.contains(&metric) {
+            return;
+        }
+        let side_data = if self.aggregations.rate_control {
+            frame.side_data[1].clone()
+        } else {
+            None
+        };
+        self.records.lock().unwrap().push(FrameRecord {
+            metric,
+            frame: frame.frame,
+            score: score.value(),
+            keyframe: frame.meta[1].and_then(|meta| meta.keyframe),
+            side_data,
+        });
+    }
+}
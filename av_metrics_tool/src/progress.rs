@@ -0,0 +1,206 @@
+//! Reports the progress of a comparison.
+//!
+//! Progress is shown with a progress bar on the terminal, or printed to stderr
+//! as newline-delimited JSON with `--progress json`, so that wrappers do not
+//! have to parse the progress bar. Each JSON record looks like:
+//!
+//! ```json
+//! {"type":"progress","file":"encode.y4m","metric":"psnr","frame":120,"total":240,"fps":58.2,"eta":2.1}
+//! ```
+//!
+//! `total` and `eta` are only known when the frames are counted with
+//! `--frames`. A record is printed at most twice per second, and once more
+//! when a metric is done.
+//!
+//! The time taken by each metric is recorded along the way, for the results.
+
+use av_metrics::PROGRESS_DONE;
+use av_metrics_report::MetricTiming;
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use serde::Serialize;
+use std::io::Write;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// The time between two JSON progress records.
+const JSON_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How progress is reported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProgressMode {
+    /// Nothing is reported.
+    Hidden,
+    /// A progress bar is drawn on the terminal, if there is one.
+    Bar,
+    /// JSON records are printed to stderr.
+    Json,
+}
+
+impl ProgressMode {
+    /// The mode selected by `--quiet` and `--progress`.
+    pub fn new(quiet: bool, progress: Option<&str>) -> Self {
+        match progress {
+            Some("json") => ProgressMode::Json,
+            _ if quiet || !console::user_attended() => ProgressMode::Hidden,
+            _ => ProgressMode::Bar,
+        }
+    }
+}
+
+/// The progress of the metrics computed on one input.
+pub struct Progress {
+    bar: ProgressBar,
+    json: bool,
+    file: String,
+    total: Option<u64>,
+    state: Mutex<State>,
+}
+
+/// The progress of the current metric.
+struct State {
+    metric: &'static str,
+    position: u64,
+    start: Instant,
+    last_record: Option<Instant>,
+    timings: Vec<MetricTiming>,
+}
+
+#[derive(Serialize)]
+struct Record<'a> {
+    r#type: &'static str,
+    file: &'a str,
+    metric: &'static str,
+    frame: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    total: Option<u64>,
+    fps: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    eta: Option<f64>,
+}
+
+impl Progress {
+    /// Creates the progress of `file`, counting frames or another `unit`, out
+    /// of `total` if it is known.
+    pub fn new(
+        mode: ProgressMode,
+        multi: &MultiProgress,
+        file: &str,
+        unit: &str,
+        total: Option<u64>,
+    ) -> Self {
+        let bar = match (mode, total) {
+            (ProgressMode::Bar, Some(total)) => ProgressBar::new(total).with_style(
+                ProgressStyle::default_spinner()
+                    .template(&format!("{{prefix}} - {} {{pos}}/{{msg}}", unit))
+                    .unwrap(),
+            ),
+            (ProgressMode::Bar, None) => ProgressBar::new_spinner().with_style(
+                ProgressStyle::default_spinner()
+                    .template(&format!("{{prefix}} - {} {{pos}}", unit))
+                    .unwrap(),
+            ),
+            // Not added to `multi`, which would draw it on the terminal
+            _ => ProgressBar::hidden(),
+        };
+        let bar = match mode {
+            ProgressMode::Bar => multi.add(bar),
+            _ => bar,
+        };
+        if let Some(total) = total {
+            bar.set_message(total.to_string());
+        }
+        Progress {
+            bar,
+            json: mode == ProgressMode::Json,
+            file: file.to_owned(),
+            total,
+            state: Mutex::new(State {
+                metric: "",
+                position: 0,
+                start: Instant::now(),
+                last_record: None,
+                timings: Vec::new(),
+            }),
+        }
+    }
+
+    /// Starts over for the next metric, named `metric` in JSON records and
+    /// timings and shown with `prefix` on the progress bar.
+    pub fn start(&self, metric: &'static str, prefix: &str) {
+        self.bar.set_prefix(prefix.to_owned());
+        self.bar.reset();
+        let mut state = self.state.lock().unwrap();
+        state.end_metric();
+        state.metric = metric;
+        state.position = 0;
+        state.start = Instant::now();
+        state.last_record = None;
+    }
+
+    /// Updates the position from a progress callback of the library, which
+    /// passes [`PROGRESS_DONE`] once every frame is processed.
+    pub fn update(&self, position: usize) {
+        let done = position == PROGRESS_DONE;
+        let mut state = self.state.lock().unwrap();
+        if !done {
+            self.bar.set_position(position as u64);
+            state.position = state.position.max(position as u64);
+        }
+        if self.json {
+            let now = Instant::now();
+            if done
+                || state
+                    .last_record
+                    .is_none_or(|last| now - last >= JSON_INTERVAL)
+            {
+                state.last_record = Some(now);
+                self.print_record(&state, now);
+            }
+        }
+    }
+
+    /// Removes the progress bar, and returns the time taken by each metric.
+    pub fn finish(&self) -> Vec<MetricTiming> {
+        self.bar.finish_and_clear();
+        let mut state = self.state.lock().unwrap();
+        state.end_metric();
+        std::mem::take(&mut state.timings)
+    }
+
+    fn print_record(&self, state: &State, now: Instant) {
+        let elapsed = (now - state.start).as_secs_f64();
+        let fps = if elapsed > 0. {
+            state.position as f64 / elapsed
+        } else {
+            0.
+        };
+        let record = Record {
+            r#type: "progress",
+            file: &self.file,
+            metric: state.metric,
+            frame: state.position,
+            total: self.total,
+            fps,
+            eta: self
+                .total
+                .filter(|_| fps > 0.)
+                .map(|total| total.saturating_sub(state.position) as f64 / fps),
+        };
+        if let Ok(line) = serde_json::to_string(&record) {
+            // Progress is best effort, a closed stderr must not stop the metrics
+            let _ = writeln!(std::io::stderr().lock(), "{}", line);
+        }
+    }
+}
+
+impl State {
+    /// Records the time taken by the current metric, if there is one.
+    fn end_metric(&mut self) {
+        if !self.metric.is_empty() {
+            let timing =
+                MetricTiming::new(self.metric, self.start.elapsed(), self.position as usize);
+            self.timings.push(timing);
+            self.metric = "";
+        }
+    }
+}
@@ -0,0 +1,83 @@
+//! Reads the per-frame encoder statistics given with `--qp-log`.
+//!
+//! The log is a CSV file with a header line and one line per frame, in
+//! decoding order of the alternate input. The columns `qp` and `bits` or
+//! `bytes` are read, and any other column is ignored, so that the output of
+//! most encoders and of `ffprobe` only needs a header:
+//!
+//! ```text
+//! qp,bytes
+//! 22.5,48211
+//! 27.0,9120
+//! ```
+//!
+//! Empty fields leave the statistic of that frame unknown. Lines starting
+//! with `#` are skipped.
+
+use av_metrics::video::side_data::SideData;
+use std::fs;
+use std::path::Path;
+
+/// The columns of the log that are read.
+#[derive(Debug, Clone, Copy, Default)]
+struct Columns {
+    qp: Option<usize>,
+    bits: Option<usize>,
+    bytes: Option<usize>,
+}
+
+/// Reads the side data of each frame from the log at `path`.
+pub fn read_qp_log(path: &Path) -> Result<Vec<SideData>, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|err| format!("Failed to read {}: {}", path.display(), err))?;
+    parse_qp_log(&text).map_err(|err| format!("{}: {}", path.display(), err))
+}
+
+fn parse_qp_log(text: &str) -> Result<Vec<SideData>, String> {
+    let mut lines = text
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.starts_with('#'));
+    let (_, header) = lines.next().ok_or("The log is empty")?;
+    let mut columns = Columns::default();
+    for (i, name) in header.split(',').enumerate() {
+        match name.trim().to_lowercase().as_str() {
+            "qp" => columns.qp = Some(i),
+            "bits" => columns.bits = Some(i),
+            "bytes" => columns.bytes = Some(i),
+            _ => {}
+        }
+    }
+    if columns.qp.is_none() && columns.bits.is_none() && columns.bytes.is_none() {
+        return Err("The header has no qp, bits or bytes column".to_owned());
+    }
+
+    lines
+        .map(|(number, line)| {
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            let field = |column: Option<usize>| {
+                column
+                    .and_then(|i| fields.get(i))
+                    .filter(|field| !field.is_empty())
+            };
+            let invalid = |field: &str| format!("Invalid value '{}' on line {}", field, number + 1);
+            let qp = field(columns.qp)
+                .map(|field| field.parse::<f64>().map_err(|_| invalid(field)))
+                .transpose()?;
+            let count = |column: Option<usize>| {
+                field(column)
+                    .map(|field| field.parse::<u64>().map_err(|_| invalid(field)))
+                    .transpose()
+            };
+            let bits = match count(columns.bits)? {
+                Some(bits) => Some(bits),
+                None => count(columns.bytes)?.map(|bytes| bytes * 8),
+            };
+            Ok(SideData {
+                bits,
+                qp,
+                qp_map: None,
+            })
+        })
+        .collect()
+}
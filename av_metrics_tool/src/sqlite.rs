@@ -0,0 +1,128 @@
+//! Appends reports to a SQLite database.
+//!
+//! Every run adds one row to `comparisons` for each alternate input, and one
+//! row to `scores` for each plane of each metric. Scores for the whole video
+//! have a `NULL` frame number, so per-frame scores can share the same table.
+//! Audio metrics store each of their scores as a plane.
+
+use av_metrics::video::PlanarMetrics;
+use av_metrics_report::{MetricsResults, Report};
+use rusqlite::{params, Connection, Transaction};
+use std::path::Path;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS comparisons (
+    id INTEGER PRIMARY KEY,
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    av_metrics_version TEXT NOT NULL,
+    base TEXT NOT NULL,
+    filename TEXT NOT NULL,
+    width INTEGER NOT NULL,
+    height INTEGER NOT NULL,
+    bit_depth INTEGER NOT NULL,
+    chroma_sampling TEXT NOT NULL,
+    frames INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS scores (
+    comparison_id INTEGER NOT NULL REFERENCES comparisons(id),
+    metric TEXT NOT NULL,
+    plane TEXT NOT NULL,
+    frame INTEGER,
+    value REAL NOT NULL
+);
+CREATE INDEX IF NOT EXISTS scores_comparison_id ON scores(comparison_id);
+";
+
+/// Appends every comparison in `report` to the database at `path`,
+/// creating the database and its tables if they do not exist yet.
+pub fn append(path: &Path, report: &Report) -> Result<(), String> {
+    let mut conn = Connection::open(path).map_err(|err| err.to_string())?;
+    conn.execute_batch(SCHEMA).map_err(|err| err.to_string())?;
+    let tx = conn.transaction().map_err(|err| err.to_string())?;
+    for cmp in report.comparisons.iter() {
+        insert_comparison(&tx, report, cmp).map_err(|err| err.to_string())?;
+    }
+    tx.commit().map_err(|err| err.to_string())
+}
+
+fn insert_comparison(
+    tx: &Transaction,
+    report: &Report,
+    cmp: &MetricsResults,
+) -> rusqlite::Result<()> {
+    tx.execute(
+        "INSERT INTO comparisons (av_metrics_version, base, filename, width, height, \
+         bit_depth, chroma_sampling, frames) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        params![
+            report.av_metrics_version,
+            report.base_of(cmp),
+            cmp.filename,
+            cmp.width as i64,
+            cmp.height as i64,
+            cmp.bit_depth as i64,
+            cmp.chroma_sampling,
+            cmp.frames as i64
+        ],
+    )?;
+    let id = tx.last_insert_rowid();
+
+    let planar = [
+        ("psnr", cmp.psnr),
+        ("apsnr", cmp.apsnr),
+        ("psnrhvs", cmp.psnr_hvs),
+        ("ssim", cmp.ssim),
+        ("msssim", cmp.msssim),
+    ];
+    for (metric, result) in planar {
+        if let Some(PlanarMetrics {
+            y,
+            u,
+            v,
+            avg,
+            alpha,
+            dssim,
+        }) = result
+        {
+            for (plane, value) in [("y", y), ("u", u), ("v", v), ("avg", avg)] {
+                insert_score(tx, id, metric, plane, value)?;
+            }
+            if let Some(value) = alpha {
+                insert_score(tx, id, metric, "alpha", value)?;
+            }
+            if let Some(value) = dssim {
+                insert_score(tx, id, metric, "dssim", value)?;
+            }
+        }
+    }
+    if let Some(value) = cmp.ciede2000 {
+        insert_score(tx, id, "ciede2000", "avg", value)?;
+    }
+    if let Some(value) = cmp.vmaf {
+        insert_score(tx, id, "vmaf", "avg", value)?;
+    }
+    if let Some(peaq) = cmp.peaq {
+        insert_score(tx, id, "peaq", "odg", peaq.odg)?;
+        insert_score(tx, id, "peaq", "di", peaq.distortion_index)?;
+    }
+    if let Some(spectral) = cmp.spectral {
+        insert_score(tx, id, "spectral", "lsd", spectral.log_spectral_distance)?;
+        insert_score(tx, id, "spectral", "sc", spectral.spectral_convergence)?;
+        insert_score(tx, id, "spectral", "segsnr", spectral.segmental_snr)?;
+    }
+    Ok(())
+}
+
+fn insert_score(
+    tx: &Transaction,
+    comparison_id: i64,
+    metric: &str,
+    plane: &str,
+    value: f64,
+) -> rusqlite::Result<()> {
+    tx.execute(
+        "INSERT INTO scores (comparison_id, metric, plane, frame, value) \
+         VALUES (?1, ?2, ?3, NULL, ?4)",
+        params![comparison_id, metric, plane, value],
+    )?;
+    Ok(())
+}
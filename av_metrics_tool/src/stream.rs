@@ -0,0 +1,186 @@
+//! Streams per-frame results as newline-delimited JSON.
+//!
+//! Each line is a JSON object with a `type` field. A `frame` line is sent as
+//! soon as a metric has scored a frame, with the score of that metric, and a
+//! `summary` line with the same fields as the other export formats is sent
+//! once a comparison is finished. The frames are scored on several threads, so
+//! the `frame` lines of a metric may arrive out of order.
+
+use crate::per_frame::FrameObserver;
+use av_metrics::video::decode::FrameMeta;
+use av_metrics::video::*;
+use av_metrics_report::MetricsResults;
+use serde::Serialize;
+use std::io::{BufWriter, Write};
+use std::net::TcpStream;
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// A connection to a listener for streamed results. Clones share the
+/// connection, and every message is sent as a whole.
+#[derive(Clone)]
+pub struct StreamSink(Arc<Mutex<Connection>>);
+
+enum Connection {
+    Tcp(BufWriter<TcpStream>),
+    #[cfg(unix)]
+    Unix(BufWriter<UnixStream>),
+}
+
+impl StreamSink {
+    /// Connects to `addr`, which is either `HOST:PORT` or `unix:PATH`.
+    pub fn connect(addr: &str) -> Result<Self, String> {
+        let connection = match addr.strip_prefix("unix:") {
+            #[cfg(unix)]
+            Some(path) => {
+                UnixStream::connect(path).map(|stream| Connection::Unix(BufWriter::new(stream)))
+            }
+            #[cfg(not(unix))]
+            Some(path) => {
+                return Err(format!(
+                    "Unix sockets are not supported on this platform: {}",
+                    path
+                ))
+            }
+            None => TcpStream::connect(addr).map(|stream| Connection::Tcp(BufWriter::new(stream))),
+        };
+        connection
+            .map(|connection| StreamSink(Arc::new(Mutex::new(connection))))
+            .map_err(|err| format!("Failed to connect to {}: {}", addr, err))
+    }
+
+    /// Sends one message and flushes it, so the listener sees it immediately.
+    fn send(&self, message: &Message) -> Result<(), String> {
+        let mut connection = self.0.lock().unwrap();
+        serde_json::to_writer(&mut *connection, message).map_err(|err| err.to_string())?;
+        connection
+            .write_all(b"\n")
+            .and_then(|_| connection.flush())
+            .map_err(|err| err.to_string())
+    }
+
+    /// Sends the aggregate results of a comparison.
+    pub fn send_summary(&self, results: &MetricsResults) -> Result<(), String> {
+        self.send(&Message::Summary(results))
+    }
+}
+
+impl Write for Connection {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Connection::Tcp(s) => s.write(buf),
+            #[cfg(unix)]
+            Connection::Unix(s) => s.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Connection::Tcp(s) => s.flush(),
+            #[cfg(unix)]
+            Connection::Unix(s) => s.flush(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Message<'a> {
+    Frame(&'a FrameResults<'a>),
+    Summary(&'a MetricsResults),
+}
+
+#[derive(Serialize)]
+struct FrameResults<'a> {
+    filename: &'a str,
+    frame: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    base_frame: Option<FrameMeta>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    input_frame: Option<FrameMeta>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    side_data: Option<side_data::SideData>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    psnr: Option<PlanarMetrics>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    psnr_hvs: Option<PlanarMetrics>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ssim: Option<PlanarMetrics>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    msssim: Option<PlanarMetrics>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ciede2000: Option<f64>,
+}
+
+/// Sends a `frame` line for each frame pair scored by the metrics of one
+/// comparison.
+///
+/// The lines come from the pass computing the averages, through a
+/// [`FrameObserver`]. Each line has the score of a single metric, and the
+/// lines may arrive out of order, since the frames are scored on several
+/// threads.
+pub struct FrameLines {
+    sink: StreamSink,
+    filename: String,
+    error: Mutex<Option<String>>,
+}
+
+impl FrameLines {
+    /// Sends the frame lines of the comparison of `input` through `sink`.
+    pub fn new(sink: StreamSink, input: &Path) -> Self {
+        FrameLines {
+            sink,
+            filename: input.to_string_lossy().into_owned(),
+            error: Mutex::new(None),
+        }
+    }
+
+    /// Returns the first error that occurred while sending the lines.
+    pub fn finish(&self) -> Result<(), String> {
+        match self.error.lock().unwrap().take() {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+}
+
+impl FrameObserver for FrameLines {
+    fn frame(&self, metric: &'static str, frame: &ScoredFrame) {
+        let Some(score) = frame.score else {
+            return;
+        };
+        let planar = match score {
+            Score::Planar(metrics) => Some(metrics),
+            Score::Value(_) => None,
+        };
+        let mut results = FrameResults {
+            filename: &self.filename,
+            frame: frame.frame,
+            base_frame: frame.meta[0],
+            input_frame: frame.meta[1],
+            side_data: frame.side_data[1].clone(),
+            psnr: None,
+            psnr_hvs: None,
+            ssim: None,
+            msssim: None,
+            ciede2000: None,
+        };
+        match metric {
+            "psnr" => results.psnr = planar,
+            "psnrhvs" => results.psnr_hvs = planar,
+            "ssim" => results.ssim = planar,
+            "msssim" => results.msssim = planar,
+            "ciede2000" => results.ciede2000 = Some(score.value()),
+            _ => return,
+        }
+        let mut error = self.error.lock().unwrap();
+        // Once the listener is gone, there is no point in trying again
+        if error.is_none() {
+            if let Err(err) = self.sink.send(&Message::Frame(&results)) {
+                *error = Some(err);
+            }
+        }
+    }
+}
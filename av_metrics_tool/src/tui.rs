@@ -0,0 +1,268 @@
+//! A live view of a comparison on the terminal, for `--tui`.
+//!
+//! While the frames are scored, a few lines at the bottom of the terminal are
+//! redrawn with a sparkline of the most recent per-frame scores, the frame rate
+//! and, when the number of frames is known, the remaining time. The view is
+//! drawn inline with ratatui rather than on the alternate screen, so it works
+//! over SSH on servers without a display and stays above the results.
+//!
+//! The scores come from the pass computing the metric, so the frames are not
+//! scored a second time.
+
+use crate::per_frame::FrameObserver;
+use av_metrics::video::ScoredFrame;
+use ratatui::backend::CrosstermBackend;
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Style, Stylize};
+use ratatui::text::Line;
+use ratatui::widgets::{Paragraph, Sparkline};
+use ratatui::{Terminal, TerminalOptions, Viewport};
+use std::io::{Stderr, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// The metrics that can be shown, which are those with a per-frame score.
+/// APSNR has the same per-frame score as PSNR, so it is not shown.
+pub const TUI_METRICS: [&str; 5] = ["psnr", "psnrhvs", "ssim", "msssim", "ciede2000"];
+
+/// The time between two redraws.
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The number of lines drawn.
+const LINES: u16 = 3;
+
+/// The height of the highest bar of the sparkline, to which the scores are
+/// scaled.
+const BAR_MAX: u64 = 100;
+
+/// The metric shown for `metric`, the metric selected on the command line,
+/// which is PSNR when every metric is computed. Metrics without a per-frame
+/// score cannot be shown.
+pub fn shown_metric(metric: Option<&str>) -> Result<&'static str, String> {
+    match metric {
+        None => Ok("psnr"),
+        Some(metric) => TUI_METRICS
+            .into_iter()
+            .find(|&name| name == metric)
+            .ok_or_else(|| {
+                format!(
+                    "--tui can only show {}, not {}.",
+                    TUI_METRICS.join(", "),
+                    metric
+                )
+            }),
+    }
+}
+
+/// Shows the per-frame scores of one metric as they come.
+pub struct FrameView {
+    metric: &'static str,
+    view: Mutex<View>,
+}
+
+impl FrameView {
+    /// Opens a view below the cursor for the scores of `input` by `metric`.
+    /// `total` is the number of frames, if known.
+    pub fn new(input: &Path, metric: &'static str, total: Option<u64>) -> Result<Self, String> {
+        let terminal = Terminal::with_options(
+            CrosstermBackend::new(std::io::stderr()),
+            TerminalOptions {
+                viewport: Viewport::Inline(LINES),
+            },
+        )
+        .map_err(|err| err.to_string())?;
+        Ok(FrameView {
+            metric,
+            view: Mutex::new(View {
+                terminal,
+                filename: input.to_string_lossy().into_owned(),
+                metric,
+                total,
+                scores: Vec::new(),
+                scored: 0,
+                start: Instant::now(),
+                last_frame: None,
+                last_draw: None,
+                error: None,
+            }),
+        })
+    }
+
+    /// Draws the final state and moves the cursor below the view, returning
+    /// the first error met while drawing.
+    pub fn finish(&self) -> Result<(), String> {
+        let mut view = self.view.lock().unwrap();
+        if let Some(err) = view.error.take() {
+            return Err(err);
+        }
+        view.draw()
+            .and_then(|_| view.close())
+            .map_err(|err| err.to_string())
+    }
+}
+
+impl FrameObserver for FrameView {
+    fn frame(&self, metric: &'static str, frame: &ScoredFrame) {
+        if metric != self.metric {
+            return;
+        }
+        let Some(score) = frame.score else {
+            return;
+        };
+        let mut view = self.view.lock().unwrap();
+        if view.error.is_some() {
+            return;
+        }
+        if let Err(err) = view.push(frame.frame, score.value()) {
+            view.error = Some(err.to_string());
+        }
+    }
+}
+
+/// The state of the live view.
+struct View {
+    terminal: Terminal<CrosstermBackend<Stderr>>,
+    filename: String,
+    metric: &'static str,
+    total: Option<u64>,
+    /// The score of each frame, by frame number. The frames are scored on
+    /// several threads, so a frame may be missing until its thread is done.
+    scores: Vec<Option<f64>>,
+    scored: u64,
+    start: Instant,
+    last_frame: Option<Instant>,
+    last_draw: Option<Instant>,
+    error: Option<String>,
+}
+
+impl View {
+    /// Adds the score of frame number `frame`, redrawing if it is time to.
+    fn push(&mut self, frame: usize, score: f64) -> std::io::Result<()> {
+        if self.scores.len() <= frame {
+            self.scores.resize(frame + 1, None);
+        }
+        self.scores[frame] = Some(score);
+        self.scored += 1;
+        self.last_frame = Some(Instant::now());
+        let due = self
+            .last_draw
+            .map(|last| last.elapsed() >= REDRAW_INTERVAL)
+            .unwrap_or(true);
+        if due {
+            self.draw()?;
+        }
+        Ok(())
+    }
+
+    /// Replaces the lines drawn last with the current state.
+    fn draw(&mut self) -> std::io::Result<()> {
+        let title = Line::from(vec![
+            self.filename.as_str().bold(),
+            format!(" - {}", self.metric.to_uppercase()).into(),
+        ]);
+        let status = self.status();
+        let scores = &self.scores;
+        self.terminal.draw(|frame| {
+            let [title_area, sparkline_area, status_area] =
+                Layout::vertical([Constraint::Length(1); LINES as usize]).areas(frame.area());
+            let recent = &scores[scores.len().saturating_sub(sparkline_area.width as usize)..];
+            frame.render_widget(Paragraph::new(title), title_area);
+            frame.render_widget(
+                Sparkline::default()
+                    .data(bars(recent))
+                    .max(BAR_MAX)
+                    .style(Style::new().cyan()),
+                sparkline_area,
+            );
+            frame.render_widget(Paragraph::new(status), status_area);
+        })?;
+        self.last_draw = Some(Instant::now());
+        Ok(())
+    }
+
+    /// Leaves the view as drawn last and moves the cursor to the line below
+    /// it.
+    fn close(&mut self) -> std::io::Result<()> {
+        let area = self.terminal.get_frame().area();
+        self.terminal
+            .set_cursor_position((0, area.bottom().saturating_sub(1)))?;
+        self.terminal.show_cursor()?;
+        writeln!(self.terminal.backend_mut())
+    }
+
+    /// The frame count, speed, remaining time and statistics of the scores.
+    fn status(&self) -> String {
+        let frames = self.scored;
+        let elapsed = self
+            .last_frame
+            .map(|last| last.duration_since(self.start).as_secs_f64())
+            .unwrap_or_default();
+        let fps = if elapsed > 0. {
+            frames as f64 / elapsed
+        } else {
+            0.
+        };
+        let mut status = match self.total {
+            Some(total) => format!("frame {}/{}", frames, total),
+            None => format!("frame {}", frames),
+        };
+        status.push_str(&format!("  {:.1} fps", fps));
+        if let Some(total) = self.total {
+            if fps > 0. {
+                let eta = total.saturating_sub(frames) as f64 / fps;
+                status.push_str(&format!("  ETA {}", format_time(eta)));
+            }
+        }
+        let scores: Vec<f64> = self.scores.iter().flatten().copied().collect();
+        if let Some(&last) = self.scores.last().and_then(Option::as_ref) {
+            let (min, max) = bounds(&scores);
+            let mean = scores.iter().sum::<f64>() / scores.len() as f64;
+            status.push_str(&format!(
+                "  last {:.4}  mean {:.4}  min {:.4}  max {:.4}",
+                last, mean, min, max
+            ));
+        }
+        status
+    }
+}
+
+/// Scales the scores between their minimum and maximum to the heights of the
+/// bars, keeping the lowest score visible. Infinite scores, from identical
+/// frames, are drawn as the highest bar, and frames not scored yet as no bar.
+fn bars(scores: &[Option<f64>]) -> Vec<Option<u64>> {
+    let finite: Vec<f64> = scores.iter().flatten().copied().collect();
+    let (min, max) = bounds(&finite);
+    scores
+        .iter()
+        .map(|score| {
+            score.map(|score| {
+                if !score.is_finite() || max <= min {
+                    return BAR_MAX;
+                }
+                1 + ((score - min) / (max - min) * (BAR_MAX - 1) as f64).round() as u64
+            })
+        })
+        .collect()
+}
+
+/// The smallest and largest finite scores.
+fn bounds(scores: &[f64]) -> (f64, f64) {
+    scores
+        .iter()
+        .filter(|score| score.is_finite())
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), &score| {
+            (min.min(score), max.max(score))
+        })
+}
+
+/// Formats a number of seconds as `h:mm:ss`, or `m:ss` under an hour.
+fn format_time(seconds: f64) -> String {
+    let seconds = seconds.round() as u64;
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    if hours > 0 {
+        format!("{}:{:02}:{:02}", hours, minutes, seconds)
+    } else {
+        format!("{}:{:02}", minutes, seconds)
+    }
+}
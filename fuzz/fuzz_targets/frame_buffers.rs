@@ -0,0 +1,32 @@
+#![no_main]
+
+use av_metrics::video::decode::copy_plane_from_raw_u8;
+use av_metrics::video::{frame_from_planes, ChromaSampling, Frame};
+use libfuzzer_sys::fuzz_target;
+
+const CHROMA_SAMPLINGS: [ChromaSampling; 4] = [
+    ChromaSampling::Cs420,
+    ChromaSampling::Cs422,
+    ChromaSampling::Cs444,
+    ChromaSampling::Cs400,
+];
+
+// Builds frames from caller-provided buffers with arbitrary dimensions,
+// strides and byte widths, which must be rejected instead of read past.
+fuzz_target!(|input: (u8, u8, u8, u8, [u16; 3], &[u8])| {
+    let (width, height, sampling, bytewidth, strides, data) = input;
+    let (width, height) = (width as usize, height as usize);
+    let chroma_sampling = CHROMA_SAMPLINGS[sampling as usize % CHROMA_SAMPLINGS.len()];
+    let strides = strides.map(usize::from);
+
+    let third = data.len() / 3;
+    let planes = [&data[..third], &data[third..2 * third], &data[2 * third..]];
+    let _ = frame_from_planes(width, height, chroma_sampling, planes, strides);
+
+    if width > 0 && height > 0 {
+        let mut frame: Frame<u16> = Frame::new_with_padding(width, height, chroma_sampling, 0);
+        for (plane, (source, stride)) in frame.planes.iter_mut().zip(planes.iter().zip(strides)) {
+            let _ = copy_plane_from_raw_u8(plane, source, stride, bytewidth as usize);
+        }
+    }
+});
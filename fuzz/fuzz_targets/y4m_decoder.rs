@@ -0,0 +1,41 @@
+#![no_main]
+
+use av_metrics::video::decode::Decoder;
+use av_metrics::video::psnr::calculate_frame_psnr;
+use av_metrics::video::Pixel;
+use av_metrics_decoders::Y4MDecoder;
+use libfuzzer_sys::fuzz_target;
+
+/// Frames larger than this are skipped, so that the fuzzer looks for
+/// malformed headers and data instead of running out of memory.
+const MAX_PIXELS: usize = 1 << 20;
+
+/// Reads a few frames, both as owned frames and as borrowed views, and
+/// scores each one against itself.
+fn read_frames<T: Pixel>(decoder: &mut Y4MDecoder<&[u8]>) {
+    let details = decoder.get_video_details();
+    for _ in 0..4 {
+        let Some(frame) = decoder.read_video_frame::<T>() else {
+            return;
+        };
+        let _ = calculate_frame_psnr(&frame, &frame, details.bit_depth, details.chroma_sampling);
+        if decoder.read_video_frame_ref::<T, _, _>(|_| ()).is_none() {
+            return;
+        }
+    }
+}
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(mut decoder) = Y4MDecoder::new(data) else {
+        return;
+    };
+    let details = decoder.get_video_details();
+    if details.width.saturating_mul(details.height) > MAX_PIXELS {
+        return;
+    }
+    if details.bit_depth == 8 {
+        read_frames::<u8>(&mut decoder);
+    } else {
+        read_frames::<u16>(&mut decoder);
+    }
+});